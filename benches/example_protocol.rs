@@ -5,6 +5,7 @@ pub enum CommandsExample {
     Start, // = [48, b'0'],
     Funny, // = [b'0', b'0'],
 }
+#[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BusyStatesExample {
     Idle,
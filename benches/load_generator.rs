@@ -0,0 +1,54 @@
+mod example_protocol;
+use criterion::*;
+
+// this measures a short echo load profile over a loopback connection, driven by the load generator of the crate
+fn load_generator_echo(c: &mut criterion::Criterion) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::bench::*;
+    use rust_tcp_ipc::*;
+
+    let config = TcpIpcConfig {
+        after_connect_wait_time: Some(std::time::Duration::from_micros(5_000)),
+        read_iteration_wait_time: None,
+        shutdown_wait_time: Some(std::time::Duration::from_micros(5_000_000)),
+        check_count: 10_000,
+        control_check_interval: Some(std::time::Duration::from_millis(1)),
+        verify_frames: Some(false),
+        read_error_limit: None,
+        ..TcpIpcConfig::default()
+    };
+
+    let (server, mut client) = rust_tcp_ipc::testing::loopback(config.clone(), config)
+        .expect("Failed to connect loopback pair");
+    let responder = EchoResponder::attach(server).expect("Failed to start echo responder");
+    let spec = LoadSpec::<ProtocolExample> {
+        command_mix: vec![(CommandsExample::Start, 3), (CommandsExample::Funny, 1)],
+        // the example protocol only supports payloads up to 9 bytes
+        payload_sizes: vec![(ECHO_ID_LENGTH, 1)],
+        rate: None,
+        duration: std::time::Duration::from_secs(10),
+        max_messages: Some(100),
+        echo: Some(std::time::Duration::from_secs(1)),
+        seed: 42,
+    };
+    c.bench_function("load_generator_echo_100_messages", |b| {
+        b.iter(|| {
+            let report = LoadGenerator::new(spec.clone()).run(&mut client);
+            assert!(report.is_clean(), "{:?}", report);
+        })
+    });
+    let (server, client) = close_both(
+        responder.detach(),
+        client,
+        std::time::Duration::from_secs(1),
+    );
+    assert!(
+        server.is_ok() && client.is_ok(),
+        "{:?} {:?}",
+        server,
+        client
+    );
+}
+
+criterion_group!(benches, load_generator_echo);
+criterion_main!(benches);
@@ -0,0 +1,144 @@
+mod example_protocol;
+use criterion::*;
+
+const FRAMES: usize = 1_000;
+const READ_SIZE: usize = 128;
+
+// this measures how fast incoming frames are parsed, fed in chunks as the read thread does
+// frames per second = FRAMES / (time per iteration)
+fn bench_parse_frames(c: &mut criterion::Criterion, id: &str, payload_size: usize) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::*;
+
+    let mut stream = Vec::new();
+    for i in 0..FRAMES {
+        let payload = vec![i as u8; payload_size];
+        stream.extend(
+            ProtocolExample::construct_message(CommandsExample::Start, &payload)
+                .expect("Failed to construct message"),
+        );
+    }
+    c.bench_function(id, |b| {
+        b.iter(|| {
+            let mut protocol_buffer = ProtocolBuffer::<ProtocolExample>::new();
+            let mut count = 0;
+            for chunk in stream.chunks(READ_SIZE) {
+                let mut buffer = chunk;
+                while let Some((_, message)) = protocol_buffer.process_new_buffer(buffer) {
+                    buffer = &[];
+                    assert_eq!(message.len(), payload_size);
+                    count += 1;
+                }
+            }
+            assert_eq!(count, FRAMES);
+        })
+    });
+}
+
+fn parse_frames_16_byte_payload(c: &mut criterion::Criterion) {
+    bench_parse_frames(c, "parse_1000_frames_16_byte_payload", 16);
+}
+
+// bare commands without payload
+fn parse_frames_empty_payload(c: &mut criterion::Criterion) {
+    bench_parse_frames(c, "parse_1000_frames_empty_payload", 0);
+}
+
+const LARGE_STREAM_SIZE: usize = 10 * 1024 * 1024;
+const LARGE_READ_SIZE: usize = 64 * 1024;
+
+// a parser which removes every frame from the front of its buffer, like the parser before the read position was introduced
+// each removal moves all bytes buffered behind the frame, so parsing a large read of small frames is quadratic in the read size
+struct FrontRemovingParser {
+    buffer: Vec<u8>,
+}
+impl FrontRemovingParser {
+    fn process_new_buffer<P: rust_tcp_ipc::Protocol>(
+        &mut self,
+        incoming_buffer: &[u8],
+    ) -> Option<(P::Commands, Vec<u8>)> {
+        self.buffer.extend_from_slice(incoming_buffer);
+        let (header, message) = P::message_slice_to_header_array(&self.buffer)?;
+        let header_length = self.buffer.len() - message.len();
+        let (command, length) = P::parse_header(header).expect("Failed to parse header");
+        if message.len() < length {
+            return None;
+        }
+        let payload = message[..length].to_vec();
+        self.buffer.drain(..header_length + length);
+        Some((command, payload))
+    }
+}
+
+// this compares both parsers on a 10 MB stream of frames with 16-byte payloads, fed in 64 KB reads (like a recorded stream)
+// frames per second = (10 MB / 21 bytes per frame) / (time per iteration)
+fn parse_large_stream(c: &mut criterion::Criterion) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::*;
+
+    let frame = ProtocolExample::construct_message(CommandsExample::Start, &[42; 16])
+        .expect("Failed to construct message");
+    let stream: Vec<u8> = frame
+        .iter()
+        .copied()
+        .cycle()
+        .take(LARGE_STREAM_SIZE / frame.len() * frame.len())
+        .collect();
+    let frames = stream.len() / frame.len();
+    let stream_front_removing = stream.clone();
+    let front_removing = Fun::new("front_removing", move |b, read_size: &usize| {
+        b.iter(|| {
+            let mut parser = FrontRemovingParser { buffer: Vec::new() };
+            let mut count = 0;
+            for chunk in stream_front_removing.chunks(*read_size) {
+                let mut buffer = chunk;
+                while let Some((_, message)) = parser.process_new_buffer::<ProtocolExample>(buffer)
+                {
+                    buffer = &[];
+                    assert_eq!(message.len(), 16);
+                    count += 1;
+                }
+            }
+            assert_eq!(count, frames);
+        })
+    });
+    let protocol_buffer = Fun::new("protocol_buffer", move |b, read_size: &usize| {
+        b.iter(|| {
+            let mut protocol_buffer = ProtocolBuffer::<ProtocolExample>::new();
+            let mut count = 0;
+            for chunk in stream.chunks(*read_size) {
+                let mut buffer = chunk;
+                while let Some((_, message)) = protocol_buffer.process_new_buffer(buffer) {
+                    buffer = &[];
+                    assert_eq!(message.len(), 16);
+                    count += 1;
+                }
+            }
+            assert_eq!(count, frames);
+        })
+    });
+    // an iteration takes tens of milliseconds (hundreds for the front removing parser), so fewer samples are taken
+    c.sample_size(10).bench_functions(
+        "parse_10_mb_stream_16_byte_payload",
+        vec![front_removing, protocol_buffer],
+        &LARGE_READ_SIZE,
+    );
+}
+
+// compared to "parse_1000_frames_16_byte_payload", this shows the overhead of the disabled log statements
+// criterion installs a logger which rejects everything below "Warn"
+// raising the maximal level lets every log statement pass the cheap level check, so only the guards prevent formatting the payloads
+fn parse_frames_16_byte_payload_logging_disabled(c: &mut criterion::Criterion) {
+    log::set_max_level(log::LevelFilter::Trace);
+    bench_parse_frames(c, "parse_1000_frames_16_byte_payload_logging_disabled", 16);
+}
+
+criterion_group!(
+    benches,
+    parse_frames_16_byte_payload,
+    parse_frames_empty_payload,
+    parse_large_stream,
+    // this has to be last, since it changes the global log level
+    parse_frames_16_byte_payload_logging_disabled
+);
+criterion_main!(benches);
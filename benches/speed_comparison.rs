@@ -4,6 +4,7 @@ use criterion::*;
 
 // this function is a minimial speed test for the tcp protocol
 // this is the source for the definition of constant "TIME_LIMIT_IN_US"
+#[allow(dead_code)]
 fn speed_check_tcp_standard(c: &mut criterion::Criterion) {
     use std::io::{Read, Write};
     std::thread::spawn(move || {
@@ -23,7 +24,7 @@ fn speed_check_tcp_standard(c: &mut criterion::Criterion) {
                 panic!("Server Message receiving: no message");
             }
             server
-                .write(&buffer[0..n])
+                .write_all(&buffer[0..n])
                 .expect("Server failed to write message");
         }
     });
@@ -36,7 +37,7 @@ fn speed_check_tcp_standard(c: &mut criterion::Criterion) {
 
     // send one message to ensure that everything is online
     client
-        .write(&[1, 2, 3])
+        .write_all(&[1, 2, 3])
         .expect("Client failed to write message");
     let mut buffer = [0; 128];
     let n = client
@@ -49,7 +50,7 @@ fn speed_check_tcp_standard(c: &mut criterion::Criterion) {
     c.bench_function("speed_check_tcp_standard", |b| {
         b.iter(|| {
             client
-                .write(&[1, 2, 3])
+                .write_all(&[1, 2, 3])
                 .expect("Client failed to write message");
             let mut buffer = [0; 128];
             let n = client
@@ -103,7 +104,7 @@ fn speed_check_tcp_mio(c: &mut criterion::Criterion) {
                 panic!("Server Message receiving: no message");
             }
             server
-                .write(&buffer[0..n])
+                .write_all(&buffer[0..n])
                 .expect("Server failed to write message");
         }
     });
@@ -175,19 +176,58 @@ fn speed_check_tcp_mio(c: &mut criterion::Criterion) {
 }
 
 // this is a speed check of an examplary implementation
+#[allow(dead_code)]
 fn speed_check_rust_tcp_ipc(c: &mut criterion::Criterion) {
-    use crate::example_protocol::*;
+    speed_check_rust_tcp_ipc_with_tap(c, "speed_check_rust_tcp_ipc", None);
+}
+
+// this compares to the above, to show the overhead of a frame tap which does nothing
+#[allow(dead_code)]
+fn speed_check_rust_tcp_ipc_noop_tap(c: &mut criterion::Criterion) {
+    speed_check_rust_tcp_ipc_with_tap(
+        c,
+        "speed_check_rust_tcp_ipc_noop_tap",
+        Some(std::sync::Arc::new(|_, _, _| {})),
+    );
+}
+
+fn example_config(
+    frame_tap: Option<rust_tcp_ipc::FrameTap<example_protocol::ProtocolExample>>,
+) -> rust_tcp_ipc::TcpIpcConfig<example_protocol::ProtocolExample> {
     use rust_tcp_ipc::*;
 
-    let config = TcpIpcConfig {
+    TcpIpcConfig {
         after_connect_wait_time: Some(std::time::Duration::from_micros(5_000)),
         read_iteration_wait_time: None, //Some(std::time::Duration::from_nanos(500)), //None,
         shutdown_wait_time: Some(std::time::Duration::from_micros(5_000_000)),
         check_count: 10_000,
-    };
+        control_check_interval: Some(std::time::Duration::from_millis(1)),
+        verify_frames: Some(false),
+        read_error_limit: None,
+        frame_tap,
+        ..TcpIpcConfig::default()
+    }
+}
+
+#[allow(dead_code)]
+fn speed_check_rust_tcp_ipc_with_tap(
+    c: &mut criterion::Criterion,
+    name: &str,
+    frame_tap: Option<rust_tcp_ipc::FrameTap<example_protocol::ProtocolExample>>,
+) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::*;
 
+    let config = example_config(frame_tap);
+    // the port is chosen by the operating system & known before the client connects
+    let listener = TcpIpc::<ProtocolExample>::listen("127.0.0.1:0").expect("Unable to listen");
+    let address = listener
+        .local_addr()
+        .expect("Unable to get the bound address");
+    let server_config = config.clone();
     std::thread::spawn(move || {
-        let mut server = TcpIpc::<ProtocolExample>::server("127.0.0.1:42457", config)
+        let mut server = listener
+            .accept(server_config)
             .expect("Unable to start server");
         loop {
             let (command, message) = server
@@ -199,9 +239,8 @@ fn speed_check_rust_tcp_ipc(c: &mut criterion::Criterion) {
                 .expect("Server failed to write message");
         }
     });
-    std::thread::sleep(std::time::Duration::from_millis(100));
     let mut client = TcpIpc::<ProtocolExample>::client(
-        "127.0.0.1:42457",
+        address,
         config,
         Some(std::time::Duration::from_millis(1)),
     )
@@ -219,7 +258,7 @@ fn speed_check_rust_tcp_ipc(c: &mut criterion::Criterion) {
         .expect("Await time exceeded");
 
     // starting iterations
-    c.bench_function("speed_check_rust_tcp_ipc", |b| {
+    c.bench_function(name, |b| {
         b.iter(|| {
             client
                 .write_message(CommandsExample::Start, &[0, 2, 3])
@@ -232,9 +271,81 @@ fn speed_check_rust_tcp_ipc(c: &mut criterion::Criterion) {
     });
 }
 
+// this measures the transfer of a 1 MB payload (answered by an empty message), with the socket buffers left at the defaults of the operating system
+fn throughput_1mb_os_default_buffers(c: &mut criterion::Criterion) {
+    throughput_1mb(c, "throughput_1mb_os_default_buffers", None);
+}
+
+// this compares to the above, with socket buffers as small as the header (which the connection set up formerly)
+fn throughput_1mb_header_size_buffers(c: &mut criterion::Criterion) {
+    throughput_1mb(
+        c,
+        "throughput_1mb_header_size_buffers",
+        Some(std::mem::size_of::<
+            <example_protocol::ProtocolExample as rust_tcp_ipc::Protocol>::HeaderAsArray,
+        >()),
+    );
+}
+
+fn throughput_1mb(c: &mut criterion::Criterion, name: &str, buffer_size: Option<usize>) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::*;
+
+    let mut config = example_config(None);
+    config.send_buffer_size = buffer_size;
+    config.recv_buffer_size = buffer_size;
+    // the payload does not fit into the socket buffers, so writing waits until the server read enough
+    config.write_retry = Some(RetrySpec {
+        max_duration: std::time::Duration::from_secs(10),
+        backoff: std::time::Duration::from_micros(10),
+    });
+
+    // the port is chosen by the operating system & known before the client connects
+    let listener = TcpIpc::<ProtocolExample>::listen("127.0.0.1:0").expect("Unable to listen");
+    let address = listener
+        .local_addr()
+        .expect("Unable to get the bound address");
+    let server_config = config.clone();
+    std::thread::spawn(move || {
+        let mut server = listener
+            .accept(server_config)
+            .expect("Unable to start server");
+        loop {
+            let (command, _) = server
+                .await_message(std::time::Duration::from_secs(10), None)
+                .expect("Server failed to receive message")
+                .expect("Await time exceeded");
+            server
+                .write_message(command, &[])
+                .expect("Server failed to write message");
+        }
+    });
+    let mut client = TcpIpc::<ProtocolExample>::client(
+        address,
+        config,
+        Some(std::time::Duration::from_millis(1)),
+    )
+    .expect("Unable to connect to server");
+
+    let payload = vec![42; 1_000_000];
+    c.sample_size(10).bench_function(name, |b| {
+        b.iter(|| {
+            client
+                .write_message(CommandsExample::Start, &payload)
+                .expect("Client failed to write message");
+            let (_, _) = client
+                .await_message(std::time::Duration::from_secs(10), None)
+                .expect("Client failed to receive message")
+                .expect("Await time exceeded");
+        });
+    });
+}
+
 criterion_group!(
     benches,
     //speed_check_tcp_standard,
-    speed_check_tcp_mio //speed_check_rust_tcp_ipc
+    speed_check_tcp_mio, //speed_check_rust_tcp_ipc, speed_check_rust_tcp_ipc_noop_tap
+    throughput_1mb_os_default_buffers,
+    throughput_1mb_header_size_buffers
 );
 criterion_main!(benches);
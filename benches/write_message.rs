@@ -0,0 +1,53 @@
+mod example_protocol;
+use criterion::*;
+
+const PAYLOAD_SIZE: usize = 1_000_000;
+
+// this measures the cost of writing a single message with a large payload, excluding the TCP-stream
+// the sink accepts vectored writes, so the difference is the copy of the payload into the concatenated message
+fn write_1mb_payload_concatenated(c: &mut criterion::Criterion) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::*;
+    use std::io::Write;
+
+    let payload = vec![42; PAYLOAD_SIZE];
+    c.bench_function("write_1mb_payload_concatenated", |b| {
+        b.iter(|| {
+            let message = ProtocolExample::construct_message(CommandsExample::Start, &payload)
+                .expect("Failed to construct message");
+            std::io::sink()
+                .write_all(&message)
+                .expect("Failed to write message");
+        })
+    });
+}
+
+fn write_1mb_payload_vectored(c: &mut criterion::Criterion) {
+    use crate::example_protocol::*;
+    use rust_tcp_ipc::*;
+    use std::io::Write;
+
+    let payload = vec![42; PAYLOAD_SIZE];
+    c.bench_function("write_1mb_payload_vectored", |b| {
+        b.iter(|| {
+            let header =
+                ProtocolExample::construct_message_header(CommandsExample::Start, &payload)
+                    .expect("Failed to construct header");
+            let slices = [
+                std::io::IoSlice::new(&header),
+                std::io::IoSlice::new(&payload),
+            ];
+            let written = std::io::sink()
+                .write_vectored(&slices)
+                .expect("Failed to write message");
+            assert_eq!(written, header.len() + payload.len());
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    write_1mb_payload_concatenated,
+    write_1mb_payload_vectored
+);
+criterion_main!(benches);
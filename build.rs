@@ -0,0 +1,27 @@
+use std::path::Path;
+use std::process::Command;
+
+// passes the commit the crate is built from to 'build_info', if it is built from a git checkout of this crate
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let git = Path::new(".git");
+    // a packaged crate has no '.git', but might be placed within an unrelated repository
+    if !git.exists() {
+        return;
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Ok(head) = std::fs::read_to_string(git.join("HEAD")) {
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=.git/{}", reference);
+        }
+    }
+    let output = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output();
+    if let Ok(output) = output {
+        if output.status.success() {
+            let hash = String::from_utf8_lossy(&output.stdout);
+            println!("cargo:rustc-env=RUST_TCP_IPC_GIT_HASH={}", hash.trim());
+        }
+    }
+}
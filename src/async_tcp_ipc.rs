@@ -0,0 +1,405 @@
+use super::protocol_buffer::{Message, Protocol, ProtocolBuffer};
+use super::read_thread::is_fatal_stream_error;
+use super::registry::ConnectionId;
+use super::response_table::ImmediateResponseTable;
+use super::tcp_ipc::{
+    ConnectErrors, ImmediateFailurePolicy, ReadThreadErrors, RetainedPayload, Strictness,
+    TcpIpcConfig, TruncatedFramePolicy, WriteMessageErrors,
+};
+use log::*;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// The number of bytes the read task reads at once.
+const READ_CHUNK_SIZE: usize = 4096;
+
+type Received<P> = Result<Message<P>, ReadThreadErrors<P>>;
+type SharedBusyState<P> = Arc<Mutex<<P as Protocol>::BusyStates>>;
+
+/// A request to the write task. All frames pass this queue, so frames of the consumer & of the read task never interleave.
+enum Outgoing {
+    /// A frame, together with the sender awaiting the result (None for frames of the read task).
+    Frame(Vec<u8>, Option<oneshot::Sender<std::io::Result<()>>>),
+    /// Shuts the writing side of the stream down, after all frames queued before (the sender is None if the read task terminates the connection).
+    Shutdown(Option<oneshot::Sender<std::io::Result<()>>>),
+}
+
+/// The settings of the config which apply to the read task.
+struct ReadSettings<P: Protocol> {
+    id: ConnectionId,
+    immediate_responses: Option<Arc<ImmediateResponseTable<P>>>,
+    on_immediate_construct_failure: ImmediateFailurePolicy<P>,
+    strictness: Strictness,
+    on_truncated_frame: TruncatedFramePolicy,
+    error_payload_retention: usize,
+}
+
+/// A connection for applications running a tokio runtime, with awaitable operations instead of blocking & polling ones.
+///
+/// Frames are parsed by the same 'ProtocolBuffer' as for 'TcpIpc', on a task spawned on the runtime instead of a read thread.
+/// This task also answers via the immediate route (see 'Protocol::message_is_answered_via_immediate_route' & 'TcpIpcConfig::immediate_responses'),
+/// using the busy state set via 'update_busy_state'. Written frames & immediate responses pass one queue (drained by a second task),
+/// so they never interleave & a consumer awaiting a write does not stop the reading.
+///
+/// Of the config, 'nodelay', 'initial_busy_state', 'immediate_responses', 'on_immediate_construct_failure', 'strictness',
+/// 'on_truncated_frame', 'error_payload_retention', 'max_header_size' & 'shutdown_wait_time' are used. The other settings are not.
+///
+/// This is only available with the 'tokio' feature. All functions have to be called within a tokio runtime.
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # fn handle<C, P>(_: C, _: P) {}
+/// # async fn example() -> Result<(), ExampleError> {
+/// # let config = config();
+/// let mut connection = AsyncTcpIpc::<ProtocolExample>::connect("127.0.0.1:6666", config).await?;
+/// connection.write_message(CommandsExample::Start, &[]).await?;
+/// loop {
+///     match connection.next_message().await {
+///         Ok((CommandsExample::Done, _)) => break,
+///         Ok((command, payload)) => handle(command, payload),
+///         Err(err) => return Err(err.into()),
+///     }
+/// }
+/// connection.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncTcpIpc<P: Protocol> {
+    id: ConnectionId,
+    incoming: mpsc::UnboundedReceiver<Received<P>>,
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    busy_state: SharedBusyState<P>,
+    // None once the connection is shut down
+    read_task: Option<JoinHandle<()>>,
+    shutdown_wait_time: Option<std::time::Duration>,
+}
+impl<P: Protocol> std::fmt::Debug for AsyncTcpIpc<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AsyncTcpIpc")
+            .field("id", &self.id)
+            .field("busy_state", &self.busy_state())
+            .field("reading", &self.read_task.is_some())
+            .finish()
+    }
+}
+impl<P: Protocol> Drop for AsyncTcpIpc<P> {
+    fn drop(&mut self) {
+        // the write task ends once the read task (holding the other sender) is gone
+        if let Some(read_task) = self.read_task.take() {
+            read_task.abort();
+        }
+    }
+}
+impl<P: Protocol> AsyncTcpIpc<P> {
+    /// This connects to a server. The first address which accepts the connection is used.
+    pub async fn connect<T: ToSocketAddrs>(
+        socket_addresses: T,
+        config: TcpIpcConfig<P>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        let stream = TcpStream::connect(socket_addresses)
+            .await
+            .map_err(ConnectErrors::ConnectionError)?;
+        Self::from_transport(stream, config)
+    }
+    /// This sets up a connection on an already connected stream (for example accepted by a 'tokio::net::TcpListener') & starts its tasks.
+    pub fn from_transport(
+        stream: TcpStream,
+        config: TcpIpcConfig<P>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        if let Some(nodelay) = config.nodelay {
+            stream
+                .set_nodelay(nodelay)
+                .map_err(ConnectErrors::SetNodelayError)?;
+        }
+        let id = ConnectionId::next();
+        info!("{}: Connected to {:?}", id, stream.peer_addr().ok());
+        let (reader, writer) = stream.into_split();
+        let busy_state = Arc::new(Mutex::new(
+            config.initial_busy_state.unwrap_or_else(P::idle),
+        ));
+        let (incoming_sender, incoming) = mpsc::unbounded_channel();
+        let (outgoing, outgoing_receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_task(
+            id,
+            writer,
+            outgoing_receiver,
+            // a weak sender, so the consumer sees the connection closed once the read task ended
+            incoming_sender.downgrade(),
+        ));
+        let settings = ReadSettings {
+            id,
+            immediate_responses: config.immediate_responses.clone(),
+            on_immediate_construct_failure: config.on_immediate_construct_failure.clone(),
+            strictness: config.strictness,
+            on_truncated_frame: config.on_truncated_frame,
+            error_payload_retention: config.error_payload_retention,
+        };
+        let read_task = tokio::spawn(read_task(
+            reader,
+            settings,
+            busy_state.clone(),
+            incoming_sender,
+            outgoing.clone(),
+        ));
+        Ok(Self {
+            id,
+            incoming,
+            outgoing,
+            busy_state,
+            read_task: Some(read_task),
+            shutdown_wait_time: config.shutdown_wait_time,
+        })
+    }
+    /// Returns the process-unique id of this connection, which is part of its log lines.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+    /// Waits for the next message, in the order the messages were received. Errors are reported in order as well.
+    /// Once the connection is closed and all messages are returned, ConnectionClosed is returned.
+    pub async fn next_message(&mut self) -> Result<Message<P>, ReadThreadErrors<P>> {
+        match self.incoming.recv().await {
+            Some(received) => received,
+            None => Err(ReadThreadErrors::ConnectionClosed),
+        }
+    }
+    /// Takes the next message if one was received already, without waiting.
+    /// Errors are reported like by 'next_message'.
+    pub fn try_next_message(&mut self) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+        match self.incoming.try_recv() {
+            Ok(received) => received.map(Some),
+            Err(mpsc::error::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::error::TryRecvError::Disconnected) => Err(ReadThreadErrors::ConnectionClosed),
+        }
+    }
+    /// This writes a message & waits until it was handed to the operating system.
+    /// Immediate responses queued before are written first.
+    pub async fn write_message(
+        &self,
+        command: P::Commands,
+        message: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        let frame = P::construct_message(command, message)
+            .ok_or(WriteMessageErrors::MessageConstructionFailed)?;
+        let (result_sender, result) = oneshot::channel();
+        self.outgoing
+            .send(Outgoing::Frame(frame, Some(result_sender)))
+            .map_err(|_| WriteMessageErrors::ConnectionClosed)?;
+        match result.await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(WriteMessageErrors::MessageSendFailed(err)),
+            Err(_) => Err(WriteMessageErrors::ConnectionClosed),
+        }
+    }
+    /// This updates the busy state, which applies to all messages parsed afterwards.
+    pub fn update_busy_state(&self, busy_state: P::BusyStates) {
+        *self.busy_state.lock().unwrap_or_else(|e| e.into_inner()) = busy_state;
+    }
+    /// This returns the current busy state.
+    pub fn busy_state(&self) -> P::BusyStates {
+        *self.busy_state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// This shuts down the connection: the goodbye is sent (if the protocol defines one, see 'Protocol::shutdown_command'),
+    /// the queued frames are written & the writing side of the stream is shut down.
+    /// Afterwards, the read task is given the 'shutdown_wait_time' to see the peer closing the connection, then it is stopped.
+    ///
+    /// The error of shutting down the stream is returned (a failed goodbye is only logged).
+    pub async fn shutdown(mut self) -> Result<(), std::io::Error> {
+        if let Some(goodbye) = P::shutdown_command() {
+            if let Err(err) = self.write_message(goodbye, &[]).await {
+                debug!("{}: Goodbye could not be sent: {:?}", self.id, err);
+            }
+        }
+        let (result_sender, result) = oneshot::channel();
+        let socket = match self.outgoing.send(Outgoing::Shutdown(Some(result_sender))) {
+            Ok(()) => result.await.unwrap_or(Ok(())),
+            // the write task ended after a failed write, so the stream is unusable anyway
+            Err(_) => Ok(()),
+        };
+        if let Some(mut read_task) = self.read_task.take() {
+            let wait_time = self.shutdown_wait_time.unwrap_or_default();
+            if tokio::time::timeout(wait_time, &mut read_task)
+                .await
+                .is_err()
+            {
+                debug!("{}: Peer did not close the connection in time", self.id);
+                read_task.abort();
+            }
+        }
+        info!("{}: Connection shut down", self.id);
+        socket
+    }
+}
+
+/// Writes the queued frames, until the stream is shut down or a write fails.
+async fn write_task<P: Protocol>(
+    id: ConnectionId,
+    mut writer: OwnedWriteHalf,
+    mut outgoing: mpsc::UnboundedReceiver<Outgoing>,
+    incoming: mpsc::WeakUnboundedSender<Received<P>>,
+) {
+    while let Some(request) = outgoing.recv().await {
+        match request {
+            Outgoing::Frame(frame, result_sender) => {
+                let result = writer.write_all(&frame).await;
+                let failed = match &result {
+                    Ok(()) => false,
+                    Err(err) => is_fatal_stream_error(err.kind()),
+                };
+                match (result, result_sender) {
+                    (result, Some(result_sender)) => {
+                        // the consumer may have stopped waiting
+                        let _ = result_sender.send(result);
+                    }
+                    (Ok(()), None) => {}
+                    (Err(err), None) => {
+                        warn!("{}: Immediate response could not be written: {:?}", id, err);
+                        if let Some(incoming) = incoming.upgrade() {
+                            let _ = incoming.send(Err(ReadThreadErrors::WriteError(err)));
+                        }
+                    }
+                }
+                if failed {
+                    warn!("{}: Stream is unusable, writing stopped", id);
+                    return;
+                }
+            }
+            Outgoing::Shutdown(result_sender) => {
+                let result = writer.shutdown().await;
+                if let Some(result_sender) = result_sender {
+                    let _ = result_sender.send(result);
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Reads & parses incoming frames, answers via the immediate route & forwards all other messages, until the connection is closed.
+async fn read_task<P: Protocol>(
+    mut reader: OwnedReadHalf,
+    settings: ReadSettings<P>,
+    busy_state: SharedBusyState<P>,
+    incoming: mpsc::UnboundedSender<Received<P>>,
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+) {
+    let id = settings.id;
+    let mut protocol = ProtocolBuffer::<P>::new();
+    let mut chunk = vec![0; READ_CHUNK_SIZE];
+    loop {
+        let read = match reader.read(&mut chunk).await {
+            Ok(0) => {
+                info!("{}: Peer closed the connection", id);
+                let mid_frame = match settings.on_truncated_frame {
+                    TruncatedFramePolicy::Report => protocol.parser_state().truncated_frame(),
+                    TruncatedFramePolicy::Drop => None,
+                };
+                let _ = incoming.send(Err(ReadThreadErrors::PeerClosed { mid_frame }));
+                return;
+            }
+            Ok(read) => read,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                warn!("{}: Reading failed: {:?}", id, err);
+                let _ = incoming.send(Err(ReadThreadErrors::ReadError(err)));
+                return;
+            }
+        };
+        let mut new_bytes = &chunk[..read];
+        loop {
+            let parsed = protocol.try_process_new_buffer(new_bytes);
+            new_bytes = &[];
+            let keep_reading = match parsed {
+                Ok(None) => break,
+                Ok(Some((command, message))) => {
+                    let busy_state = *busy_state.lock().unwrap_or_else(|e| e.into_inner());
+                    handle_message(
+                        &settings, &incoming, &outgoing, command, message, busy_state,
+                    )
+                }
+                Err(violation) => {
+                    let strict = settings.strictness == Strictness::Strict;
+                    warn!(
+                        "{}: Protocol violation: {:?}, incoming header: {:?}.",
+                        id, violation.error, violation.header
+                    );
+                    if strict {
+                        if let Some(fault) = P::fault_frame(&violation)
+                            .and_then(|(command, message)| P::construct_message(command, &message))
+                        {
+                            let _ = outgoing.send(Outgoing::Frame(fault, None));
+                        }
+                        let _ = outgoing.send(Outgoing::Shutdown(None));
+                    }
+                    incoming
+                        .send(Err(ReadThreadErrors::ProtocolViolation(violation)))
+                        .is_ok()
+                        && !strict
+                }
+            };
+            if !keep_reading {
+                debug!("{}: Reading stopped", id);
+                return;
+            }
+        }
+    }
+}
+
+/// Answers a message via the immediate route or forwards it. Returns false if the reading has to stop.
+fn handle_message<P: Protocol>(
+    settings: &ReadSettings<P>,
+    incoming: &mpsc::UnboundedSender<Received<P>>,
+    outgoing: &mpsc::UnboundedSender<Outgoing>,
+    command: P::Commands,
+    message: Vec<u8>,
+    busy_state: P::BusyStates,
+) -> bool {
+    // the table decides first, the protocol only if no rule matched
+    let immediate = match settings
+        .immediate_responses
+        .as_ref()
+        .and_then(|table| table.decide(&command, &message, &busy_state))
+    {
+        Some(decision) => decision,
+        None => P::message_is_answered_via_immediate_route(&command, &message, &busy_state),
+    };
+    let (response_command, response) = match immediate {
+        Some(immediate) => immediate,
+        None => return incoming.send(Ok((command, message))).is_ok(),
+    };
+    if let Some(frame) = P::construct_message(response_command, &response) {
+        return outgoing.send(Outgoing::Frame(frame, None)).is_ok();
+    }
+    let fallback = match &settings.on_immediate_construct_failure {
+        ImmediateFailurePolicy::ReportOnly => Ok(None),
+        ImmediateFailurePolicy::SendFallbackFrame(command, message) => {
+            P::construct_message(*command, message).map(Some).ok_or(())
+        }
+        ImmediateFailurePolicy::CloseConnection => Err(()),
+    };
+    let error = ReadThreadErrors::ImmediateMessageConstructError((
+        response_command,
+        RetainedPayload::new(response, settings.error_payload_retention),
+    ));
+    if incoming.send(Err(error)).is_err() {
+        return false;
+    }
+    match fallback {
+        Ok(Some(fallback)) => outgoing.send(Outgoing::Frame(fallback, None)).is_ok(),
+        Ok(None) => true,
+        Err(()) => {
+            warn!(
+                "{}: Immediate response could not be constructed. Connection will be closed.",
+                settings.id
+            );
+            let _ = outgoing.send(Outgoing::Shutdown(None));
+            false
+        }
+    }
+}
@@ -0,0 +1,384 @@
+//! A synthetic load generator, to compare protocol implementations & config settings (like buffer sizes or nodelay) under load.
+//!
+//! This module is only available with the `bench` feature.
+//! 'LoadGenerator::run' writes messages to a connection as described by a 'LoadSpec' (command mix, payload sizes, rate & duration)
+//! and reports throughput & errors. In echo mode, the peer answers every message via an 'EchoResponder', so latency percentiles are reported as well.
+//! Only the public API of the crate is used, so the generator doubles as a check that this API suffices for such a harness.
+//! # Example
+//! ```
+//! # use rust_tcp_ipc::*;
+//! # use rust_tcp_ipc::doc_example::*;
+//! # run(|| {
+//! # let config = config();
+//! use rust_tcp_ipc::bench::*;
+//! let (server, mut client) = rust_tcp_ipc::testing::loopback::<ProtocolExample>(config.clone(), config)?;
+//! let responder = EchoResponder::attach(server)?;
+//! let spec = LoadSpec {
+//!     command_mix: vec![(CommandsExample::Start, 1)],
+//!     payload_sizes: vec![(16, 9), (4096, 1)],
+//!     rate: Some(10_000),
+//!     duration: std::time::Duration::from_secs(1),
+//!     max_messages: None,
+//!     echo: Some(std::time::Duration::from_millis(500)),
+//!     seed: 1,
+//! };
+//! let report = LoadGenerator::new(spec).run(&mut client);
+//! println!("{:?}", report.latency);
+//! let server = responder.detach();
+//! # Ok(())
+//! # });
+//! ```
+use super::tcp_ipc::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The time the generator & the responder sleep while nothing is to be done.
+const POLL_INTERVAL: Duration = Duration::from_micros(50);
+/// The number of bytes at the start of each payload in echo mode, holding the number of the message.
+pub const ECHO_ID_LENGTH: usize = 8;
+
+/// This describes the load of a 'LoadGenerator'.
+pub struct LoadSpec<P: Protocol> {
+    /// The commands to send, each with its weight (the relative frequency). Commands with weight 0 are never sent.
+    pub command_mix: Vec<(P::Commands, u32)>,
+    /// The payload sizes to send, each with its weight (the relative frequency).
+    /// In echo mode, payloads shorter than 'ECHO_ID_LENGTH' are extended to this length.
+    pub payload_sizes: Vec<(usize, u32)>,
+    /// The number of messages per second. If None, messages are written as fast as possible.
+    pub rate: Option<u32>,
+    /// The time during which messages are written.
+    pub duration: Duration,
+    /// If given, writing stops after this number of messages, even before the duration passed.
+    pub max_messages: Option<u64>,
+    /// If given, every message is expected to be echoed by the peer (see 'EchoResponder'), so its latency is measured.
+    /// After the last message, the generator waits up to this time for the outstanding echoes.
+    pub echo: Option<Duration>,
+    /// The seed of the (deterministic) random choice of commands & payload sizes.
+    pub seed: u64,
+}
+impl<P: Protocol> Clone for LoadSpec<P> {
+    fn clone(&self) -> Self {
+        Self {
+            command_mix: self.command_mix.clone(),
+            payload_sizes: self.payload_sizes.clone(),
+            rate: self.rate,
+            duration: self.duration,
+            max_messages: self.max_messages,
+            echo: self.echo,
+            seed: self.seed,
+        }
+    }
+}
+impl<P: Protocol> std::fmt::Debug for LoadSpec<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LoadSpec")
+            .field("command_mix", &self.command_mix)
+            .field("payload_sizes", &self.payload_sizes)
+            .field("rate", &self.rate)
+            .field("duration", &self.duration)
+            .field("max_messages", &self.max_messages)
+            .field("echo", &self.echo)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+/// The latency percentiles of the echoed messages, see 'LoadReport::latency'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyPercentiles {
+    /// The median.
+    pub p50: Duration,
+    /// The 90th percentile.
+    pub p90: Duration,
+    /// The 99th percentile.
+    pub p99: Duration,
+    /// The maximal latency.
+    pub max: Duration,
+}
+
+/// The result of 'LoadGenerator::run'.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoadReport {
+    /// The number of messages written successfully.
+    pub messages_sent: u64,
+    /// The number of payload bytes written successfully.
+    pub payload_bytes_sent: u64,
+    /// The time from the first write until the last echo (or the last write, without echo mode).
+    pub elapsed: Duration,
+    /// The messages written per second.
+    pub messages_per_second: f64,
+    /// The payload bytes written per second.
+    pub payload_bytes_per_second: f64,
+    /// The number of writes which failed.
+    /// Writing stops once the connection is closed (for example, since a write failed after 'TcpIpcConfig::write_retry' was exhausted).
+    pub write_errors: u64,
+    /// The number of errors reported while taking messages.
+    pub read_errors: u64,
+    /// The number of echoes received (only in echo mode).
+    pub echoes_received: u64,
+    /// The number of messages which were not echoed in time (only in echo mode).
+    pub echoes_missing: u64,
+    /// The number of received messages which are no echo of a message written by the generator.
+    pub unexpected_messages: u64,
+    /// The round-trip latency of the echoed messages. This is None without echo mode, or if nothing was echoed.
+    pub latency: Option<LatencyPercentiles>,
+    /// Indicates that the connection was closed at the end of the run.
+    pub connection_closed: bool,
+}
+impl LoadReport {
+    /// Checks that no error occurred, the connection is still open & every message was echoed (in echo mode).
+    pub fn is_clean(&self) -> bool {
+        !self.connection_closed
+            && self.write_errors == 0
+            && self.read_errors == 0
+            && self.echoes_missing == 0
+            && self.unexpected_messages == 0
+    }
+}
+
+/// Writes a synthetic load to a connection, see the module documentation.
+pub struct LoadGenerator<P: Protocol> {
+    spec: LoadSpec<P>,
+    random: u64,
+}
+impl<P: Protocol> std::fmt::Debug for LoadGenerator<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LoadGenerator")
+            .field("spec", &self.spec)
+            .finish()
+    }
+}
+impl<P: Protocol> LoadGenerator<P> {
+    /// Creates a generator for the given load.
+    pub fn new(spec: LoadSpec<P>) -> Self {
+        Self {
+            // xorshift must not start at 0
+            random: spec.seed.max(1),
+            spec,
+        }
+    }
+    /// Writes the load to the given connection & returns the report.
+    /// In echo mode, messages taken from the connection are expected to be echoes; they are consumed.
+    ///
+    /// # Panics
+    /// If the command mix or the payload sizes are empty (or all their weights are 0).
+    pub fn run(&mut self, client: &mut TcpIpc<P>) -> LoadReport {
+        let mut report = LoadReport::default();
+        let mut outstanding = HashMap::new();
+        let mut latencies = Vec::new();
+        let started = Instant::now();
+        let rate = self.spec.rate.filter(|&rate| rate > 0);
+        let mut next_id: u64 = 0;
+        while started.elapsed() < self.spec.duration
+            && self.spec.max_messages.is_none_or(|max| next_id < max)
+        {
+            if let Some(rate) = rate {
+                let due = started + Duration::from_secs_f64(next_id as f64 / f64::from(rate));
+                while Instant::now() < due {
+                    let taken = self.spec.echo.is_some()
+                        && self.take_echoes(client, &mut outstanding, &mut latencies, &mut report);
+                    if !taken {
+                        std::thread::sleep(
+                            POLL_INTERVAL.min(due.saturating_duration_since(Instant::now())),
+                        );
+                    }
+                }
+            }
+            let command = pick(&mut self.random, &self.spec.command_mix);
+            let mut payload = vec![0; pick(&mut self.random, &self.spec.payload_sizes)];
+            if self.spec.echo.is_some() {
+                if payload.len() < ECHO_ID_LENGTH {
+                    payload.resize(ECHO_ID_LENGTH, 0);
+                }
+                payload[..ECHO_ID_LENGTH].copy_from_slice(&next_id.to_be_bytes());
+            }
+            let sent_at = Instant::now();
+            match client.write_message(command, &payload) {
+                Ok(()) => {
+                    report.messages_sent += 1;
+                    report.payload_bytes_sent += payload.len() as u64;
+                    if self.spec.echo.is_some() {
+                        outstanding.insert(next_id, sent_at);
+                    }
+                }
+                Err(_) => {
+                    report.write_errors += 1;
+                    if client.is_connection_closed() {
+                        break;
+                    }
+                }
+            }
+            next_id += 1;
+            if self.spec.echo.is_some() {
+                self.take_echoes(client, &mut outstanding, &mut latencies, &mut report);
+            }
+        }
+        if let Some(echo_wait_time) = self.spec.echo {
+            let writing_finished = Instant::now();
+            while !outstanding.is_empty() && writing_finished.elapsed() < echo_wait_time {
+                if !self.take_echoes(client, &mut outstanding, &mut latencies, &mut report) {
+                    if client.is_connection_closed() {
+                        // all echoes received before the closing were taken
+                        break;
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }
+            report.echoes_missing = outstanding.len() as u64;
+        }
+        report.connection_closed = client.is_connection_closed();
+        report.elapsed = started.elapsed();
+        let seconds = report.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            report.messages_per_second = report.messages_sent as f64 / seconds;
+            report.payload_bytes_per_second = report.payload_bytes_sent as f64 / seconds;
+        }
+        report.latency = percentiles(latencies);
+        report
+    }
+    // takes the available echoes, returns false if none was available
+    fn take_echoes(
+        &self,
+        client: &mut TcpIpc<P>,
+        outstanding: &mut HashMap<u64, Instant>,
+        latencies: &mut Vec<Duration>,
+        report: &mut LoadReport,
+    ) -> bool {
+        let mut taken = false;
+        loop {
+            match client.get_message() {
+                Ok(Some((_, payload))) => {
+                    taken = true;
+                    let sent_at = payload.get(..ECHO_ID_LENGTH).and_then(|id| {
+                        let mut bytes = [0; ECHO_ID_LENGTH];
+                        bytes.copy_from_slice(id);
+                        outstanding.remove(&u64::from_be_bytes(bytes))
+                    });
+                    match sent_at {
+                        Some(sent_at) => {
+                            report.echoes_received += 1;
+                            latencies.push(sent_at.elapsed());
+                        }
+                        None => report.unexpected_messages += 1,
+                    }
+                }
+                // reported via 'LoadReport::connection_closed'
+                Ok(None) | Err(ReadThreadErrors::ConnectionClosed) => return taken,
+                Err(_) => {
+                    report.read_errors += 1;
+                    return taken;
+                }
+            }
+        }
+    }
+}
+
+// chooses an entry according to the weights (xorshift64)
+fn pick<T: Copy>(random: &mut u64, weighted: &[(T, u32)]) -> T {
+    let total: u64 = weighted.iter().map(|(_, weight)| u64::from(*weight)).sum();
+    assert!(
+        total > 0,
+        "the load spec needs an entry with a positive weight"
+    );
+    *random ^= *random << 13;
+    *random ^= *random >> 7;
+    *random ^= *random << 17;
+    let mut chosen = *random % total;
+    for (entry, weight) in weighted {
+        if chosen < u64::from(*weight) {
+            return *entry;
+        }
+        chosen -= u64::from(*weight);
+    }
+    unreachable!("the weights sum up to the total")
+}
+
+fn percentiles(mut latencies: Vec<Duration>) -> Option<LatencyPercentiles> {
+    if latencies.is_empty() {
+        return None;
+    }
+    latencies.sort_unstable();
+    let at = |percent: usize| latencies[(latencies.len() - 1) * percent / 100];
+    Some(LatencyPercentiles {
+        p50: at(50),
+        p90: at(90),
+        p99: at(99),
+        max: latencies[latencies.len() - 1],
+    })
+}
+
+/// Echoes every message received on a connection back to the peer (with the same command & payload), on a thread of its own.
+/// This is the peer side of a 'LoadGenerator' in echo mode.
+pub struct EchoResponder<P: Protocol> {
+    stop: Arc<AtomicBool>,
+    echoed: Arc<AtomicU64>,
+    errors: Arc<AtomicU64>,
+    thread: std::thread::JoinHandle<TcpIpc<P>>,
+}
+impl<P: Protocol> std::fmt::Debug for EchoResponder<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("EchoResponder")
+            .field("echoed", &self.echoed())
+            .field("errors", &self.errors())
+            .finish()
+    }
+}
+impl<P: Protocol> EchoResponder<P> {
+    /// Starts echoing on the given connection, until 'detach' is called or the connection is closed.
+    pub fn attach(mut peer: TcpIpc<P>) -> std::io::Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let echoed = Arc::new(AtomicU64::new(0));
+        let errors = Arc::new(AtomicU64::new(0));
+        let thread = {
+            let (stop, echoed, errors) = (stop.clone(), echoed.clone(), errors.clone());
+            std::thread::Builder::new()
+                .name("tcp-ipc/echo".to_string())
+                .spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        match peer.get_message() {
+                            Ok(Some((command, payload))) => {
+                                match peer.write_message(command, &payload) {
+                                    Ok(()) => echoed.fetch_add(1, Ordering::Relaxed),
+                                    Err(_) => errors.fetch_add(1, Ordering::Relaxed),
+                                };
+                            }
+                            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+                            Err(ReadThreadErrors::ConnectionClosed)
+                            | Err(ReadThreadErrors::Disconnected) => break,
+                            Err(_) => {
+                                errors.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                    peer
+                })?
+        };
+        Ok(Self {
+            stop,
+            echoed,
+            errors,
+            thread,
+        })
+    }
+    /// The number of messages echoed so far.
+    pub fn echoed(&self) -> u64 {
+        self.echoed.load(Ordering::Relaxed)
+    }
+    /// The number of errors (while taking or echoing messages) so far.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+    /// Stops echoing & returns the connection.
+    ///
+    /// # Panics
+    /// If the echo thread panicked.
+    pub fn detach(self) -> TcpIpc<P> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.thread.join() {
+            Ok(peer) => peer,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
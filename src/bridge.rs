@@ -0,0 +1,236 @@
+use super::protocol_buffer::Protocol;
+use super::tcp_ipc::{ConnectErrors, ReadThreadErrors, ShutdownReport, TcpIpc};
+use log::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The direction a message is forwarded by a bridge (see 'bridge').
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// From the first connection to the second.
+    AToB,
+    /// From the second connection to the first.
+    BToA,
+}
+
+/// This is returned by the filter of a bridge, to decide about a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BridgeAction<P: Protocol> {
+    /// The message is forwarded unchanged.
+    Forward,
+    /// The message is dropped.
+    Drop,
+    /// The given command & payload are forwarded instead of the message.
+    Rewrite(P::Commands, Vec<u8>),
+}
+
+/// The counters of one direction of a bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DirectionStats {
+    /// The number of messages forwarded unchanged.
+    pub forwarded: u64,
+    /// The number of messages dropped by the filter.
+    pub dropped: u64,
+    /// The number of messages forwarded after being rewritten by the filter.
+    pub rewritten: u64,
+    /// The number of messages which could not be written to the other side.
+    pub failed: u64,
+}
+
+/// The counters of a bridge, see 'BridgeHandle::stats'.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BridgeStats {
+    /// The messages from the first connection to the second.
+    pub a_to_b: DirectionStats,
+    /// The messages from the second connection to the first.
+    pub b_to_a: DirectionStats,
+}
+
+/// How a bridge ended, see 'BridgeHandle::join'.
+#[derive(Debug)]
+pub enum BridgeEnd<P: Protocol> {
+    /// Both connections were shut down, by 'BridgeHandle::shutdown' or since a connection was closed & disconnects are propagated.
+    ShutDown(Result<BridgeShutdown, BridgeShutdown>),
+    /// A connection was closed & disconnects are not propagated, so both connections are handed back.
+    Closed(Box<(TcpIpc<P>, TcpIpc<P>)>),
+}
+
+/// The shutdown reports of both connections of a bridge, see 'BridgeHandle::shutdown'.
+#[derive(Debug)]
+pub struct BridgeShutdown {
+    /// The report of the first connection.
+    pub a: ShutdownReport,
+    /// The report of the second connection.
+    pub b: ShutdownReport,
+}
+impl BridgeShutdown {
+    /// Checks that both connections were shut down cleanly, see 'ShutdownReport::is_clean'.
+    pub fn is_clean(&self) -> bool {
+        self.a.is_clean() && self.b.is_clean()
+    }
+}
+
+/// This is returned by 'bridge', to observe & stop it.
+pub struct BridgeHandle<P: Protocol> {
+    stop: Arc<AtomicBool>,
+    stats: Arc<Mutex<BridgeStats>>,
+    thread: std::thread::JoinHandle<BridgeEnd<P>>,
+}
+impl<P: Protocol> std::fmt::Debug for BridgeHandle<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BridgeHandle")
+            .field("stats", &self.stats())
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+impl<P: Protocol> BridgeHandle<P> {
+    /// Returns a snapshot of the counters of the bridge.
+    pub fn stats(&self) -> BridgeStats {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Checks if the bridge stopped forwarding, since a connection was closed.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+    /// Waits until the bridge stopped forwarding, since a connection was closed.
+    pub fn join(self) -> BridgeEnd<P> {
+        self.thread
+            .join()
+            .unwrap_or_else(|err| std::panic::resume_unwind(err))
+    }
+    /// Stops the bridge & shuts down both connections.
+    /// The reports of both connections are returned as error if any of them is degraded.
+    pub fn shutdown(self) -> Result<BridgeShutdown, BridgeShutdown> {
+        self.stop.store(true, Ordering::SeqCst);
+        match self.join() {
+            BridgeEnd::ShutDown(result) => result,
+            BridgeEnd::Closed(connections) => shutdown_both(connections.0, connections.1),
+        }
+    }
+}
+fn shutdown_both<P: Protocol>(
+    a: TcpIpc<P>,
+    b: TcpIpc<P>,
+) -> Result<BridgeShutdown, BridgeShutdown> {
+    let reports = BridgeShutdown {
+        a: a.shutdown().unwrap_or_else(|report| report),
+        b: b.shutdown().unwrap_or_else(|report| report),
+    };
+    if reports.is_clean() {
+        Ok(reports)
+    } else {
+        Err(reports)
+    }
+}
+
+/// This forwards messages between two connections in both directions, until one of them is closed or the bridge is shut down.
+///
+/// Each received message is passed to the filter, which decides to forward, drop or rewrite it.
+/// Messages answered via the immediate route are not forwarded, since they never reach the consumer.
+/// If 'propagate_disconnect' is true, the other connection is shut down once a connection is closed.
+/// Otherwise, both connections are handed back by 'BridgeHandle::join'.
+///
+/// The bridge runs on its own thread, which sleeps the read iteration wait time of the first connection's config if no message was forwarded.
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # run(|| {
+/// # let (device, controller) = (client(), client());
+/// let handle = bridge(device, controller, true, |direction, command, _payload| match command {
+///     CommandsExample::Debug => BridgeAction::Drop,
+///     _ => BridgeAction::Forward,
+/// })?;
+/// # Ok(())
+/// # });
+/// ```
+pub fn bridge<P, F>(
+    mut a: TcpIpc<P>,
+    mut b: TcpIpc<P>,
+    propagate_disconnect: bool,
+    mut filter: F,
+) -> Result<BridgeHandle<P>, ConnectErrors>
+where
+    P: Protocol,
+    F: FnMut(Direction, &P::Commands, &[u8]) -> BridgeAction<P> + Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let stats = Arc::new(Mutex::new(BridgeStats::default()));
+    let stop_bridge = stop.clone();
+    let stats_bridge = stats.clone();
+    let iteration_wait_time = a.read_iteration_wait_time();
+    let thread = std::thread::Builder::new()
+        .name("tcp-ipc/bridge".to_string())
+        .spawn(move || {
+            info!("Bridge thread started");
+            let mut closed = false;
+            while !closed && !stop_bridge.load(Ordering::SeqCst) {
+                let mut idle = true;
+                for &direction in &[Direction::AToB, Direction::BToA] {
+                    let (from, to) = match direction {
+                        Direction::AToB => (&mut a, &mut b),
+                        Direction::BToA => (&mut b, &mut a),
+                    };
+                    let (command, payload) = match from.get_message() {
+                        Ok(Some(message)) => message,
+                        Ok(None) => continue,
+                        Err(ReadThreadErrors::ConnectionClosed)
+                        | Err(ReadThreadErrors::PeerClosed { .. })
+                        | Err(ReadThreadErrors::Disconnected) => {
+                            closed = true;
+                            continue;
+                        }
+                        Err(_) => {
+                            // the error is kept as the connection's last error (see 'TcpIpc::diagnostics')
+                            warn!("Bridge ignored a read thread error ({:?})", direction);
+                            continue;
+                        }
+                    };
+                    idle = false;
+                    let action = filter(direction, &command, &payload);
+                    let result = match &action {
+                        BridgeAction::Forward => Some(to.write_message(command, &payload)),
+                        BridgeAction::Drop => None,
+                        BridgeAction::Rewrite(command, payload) => {
+                            Some(to.write_message(*command, payload))
+                        }
+                    };
+                    let mut stats = stats_bridge.lock().unwrap_or_else(|e| e.into_inner());
+                    let stats = match direction {
+                        Direction::AToB => &mut stats.a_to_b,
+                        Direction::BToA => &mut stats.b_to_a,
+                    };
+                    match action {
+                        BridgeAction::Forward => stats.forwarded += 1,
+                        BridgeAction::Drop => stats.dropped += 1,
+                        BridgeAction::Rewrite(..) => stats.rewritten += 1,
+                    }
+                    if let Some(Err(err)) = result {
+                        warn!("Bridge failed to forward ({:?}): {:?}", direction, err);
+                        stats.failed += 1;
+                        closed |= to.is_connection_closed();
+                    }
+                }
+                if idle {
+                    if let Some(iteration_wait_time) = iteration_wait_time {
+                        std::thread::sleep(iteration_wait_time);
+                    }
+                }
+            }
+            info!("Bridge thread finished");
+            if closed && !propagate_disconnect {
+                BridgeEnd::Closed(Box::new((a, b)))
+            } else {
+                BridgeEnd::ShutDown(shutdown_both(a, b))
+            }
+        })
+        .map_err(ConnectErrors::ThreadSpawnError)?;
+    Ok(BridgeHandle {
+        stop,
+        stats,
+        thread,
+    })
+}
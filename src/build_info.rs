@@ -0,0 +1,139 @@
+use super::diagnostics::ConfiguredLimits;
+use super::protocol_buffer::Protocol;
+
+/// How this crate was built, see 'build_info'. This is intended to be attached to bug reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BuildInfo {
+    /// The version of the crate.
+    pub version: &'static str,
+    /// The commit the crate was built from, if it was built from a git checkout (for example as a path or git dependency).
+    pub git_hash: Option<&'static str>,
+    /// The enabled cargo features (the implicit feature `mio` excluded).
+    pub features: &'static [&'static str],
+    /// The engine providing the sockets: "mio" or "std".
+    pub engine: &'static str,
+    /// Indicates if the crate was built with debug assertions (which, for example, enable 'TcpIpcConfig::verify_frames' by default).
+    pub debug_assertions: bool,
+}
+
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "bench")]
+    "bench",
+    #[cfg(feature = "engine-mio")]
+    "engine-mio",
+    #[cfg(feature = "engine-std")]
+    "engine-std",
+    #[cfg(feature = "registry")]
+    "registry",
+    #[cfg(feature = "serde")]
+    "serde",
+    #[cfg(feature = "std")]
+    "std",
+    #[cfg(feature = "test-util")]
+    "test-util",
+    #[cfg(feature = "tokio")]
+    "tokio",
+];
+
+/// Returns how this crate was built: its version, the commit (if known), the enabled features & the engine.
+/// # Example
+/// ```
+/// log::info!("rust_tcp_ipc {:?}", rust_tcp_ipc::build_info());
+/// ```
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        // set by the build script
+        git_hash: option_env!("RUST_TCP_IPC_GIT_HASH"),
+        features: FEATURES,
+        engine: if cfg!(feature = "engine-mio") {
+            "mio"
+        } else {
+            "std"
+        },
+        debug_assertions: cfg!(debug_assertions),
+    }
+}
+
+/// What a protocol declares about its frames, as reported by 'TcpIpc::capability_report'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProtocolCapabilities {
+    /// The type name of the protocol.
+    pub protocol: &'static str,
+    /// The size of a header in bytes.
+    pub header_size: usize,
+    /// The size of the command within the header in bytes.
+    pub command_size: usize,
+    /// The size of the length within the header in bytes.
+    pub length_size: usize,
+    /// Indicates if the payload is written after the header as is, see 'Protocol::payload_follows_header'.
+    pub payload_follows_header: bool,
+    /// Indicates if the protocol defines an acknowledgement, as needed by the reliability layer (see 'Protocol::ack_command').
+    pub ack_command: bool,
+    /// Indicates if the protocol defines a goodbye message, see 'Protocol::shutdown_command'.
+    pub shutdown_command: bool,
+    /// Indicates if the protocol defines a probe, see 'Protocol::probe_frame'.
+    pub probe_frame: bool,
+}
+impl ProtocolCapabilities {
+    pub(crate) fn of<P: Protocol>() -> Self {
+        Self {
+            protocol: std::any::type_name::<P>(),
+            header_size: std::mem::size_of::<P::HeaderAsArray>(),
+            command_size: std::mem::size_of::<P::CommandAsArray>(),
+            length_size: std::mem::size_of::<P::LengthAsArray>(),
+            payload_follows_header: P::payload_follows_header(),
+            ack_command: P::ack_command().is_some(),
+            shutdown_command: P::shutdown_command().is_some(),
+            probe_frame: P::probe_frame().is_some(),
+        }
+    }
+}
+
+/// The settings of a connection as they are in effect, i.e. with the defaults of the config resolved.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ResolvedConfig {
+    /// The name of the connection, see 'TcpIpcConfig::name'.
+    pub name: Option<String>,
+    /// The TCP_NODELAY option set after connecting (None if the default of the operating system is kept).
+    pub nodelay: Option<bool>,
+    /// The size of the send buffer of the operating system set after connecting (None if the default is kept).
+    pub send_buffer_size: Option<usize>,
+    /// The size of the receive buffer of the operating system set after connecting (None if the default is kept).
+    pub recv_buffer_size: Option<usize>,
+    /// Indicates if constructed frames are parsed back before writing, see 'TcpIpcConfig::verify_frames'.
+    pub verify_frames: bool,
+    /// Indicates if a protocol violation terminates the connection, see 'TcpIpcConfig::strictness'.
+    pub strict: bool,
+    /// The maximal number of payload bytes kept in an error, see 'TcpIpcConfig::error_payload_retention'.
+    pub error_payload_retention: usize,
+    /// The maximal number of unacknowledged reliable messages, if the reliability layer is enabled.
+    pub max_unacknowledged: Option<usize>,
+    /// The window in which duplicate frames are dropped, see 'TcpIpcConfig::dedup_window'.
+    pub dedup_window: Option<std::time::Duration>,
+    /// The limit of outgoing frames per second, see 'TcpIpcConfig::outgoing_rate_limit'.
+    pub outgoing_frames_per_second: Option<u32>,
+    /// The time 'write_message' retries while the stream would block, see 'TcpIpcConfig::write_retry'.
+    pub write_retry: Option<std::time::Duration>,
+    /// The number of reads failing in a row which close the connection, see 'TcpIpcConfig::read_error_limit'.
+    pub read_error_limit: Option<usize>,
+    /// Indicates if frames can be journaled, see 'TcpIpcConfig::journal'.
+    pub journal: bool,
+}
+
+/// How a connection was built: the crate, the protocol & the effective config, see 'TcpIpc::capability_report'.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CapabilityReport {
+    /// How this crate was built.
+    pub build: BuildInfo,
+    /// What the protocol declares about its frames.
+    pub protocol: ProtocolCapabilities,
+    /// The limits the connection is configured with.
+    pub limits: ConfiguredLimits,
+    /// The effective settings of the connection.
+    pub config: ResolvedConfig,
+}
@@ -0,0 +1,292 @@
+//! A conformance check for implementations of the 'Protocol' trait.
+//!
+//! This module is only available with the `test-util` feature.
+//! Calling 'check_protocol' is the recommended first test for a new protocol: it finds inconsistencies between
+//! constructing & parsing frames before they desynchronize a real stream.
+//! # Example
+//! ```
+//! # use rust_tcp_ipc::doc_example::*;
+//! use rust_tcp_ipc::conformance::*;
+//! fn protocol_conforms() {
+//!     let failures = check_protocol::<ProtocolExample>(
+//!         &[CommandsExample::Start, CommandsExample::Stop],
+//!         &[vec![], vec![1, 2, 3], vec![0; 1000]],
+//!     );
+//!     assert!(failures.is_empty(), "{:#?}", failures);
+//! }
+//! # protocol_conforms();
+//! ```
+use super::tcp_ipc::*;
+
+/// The payload lengths probed for every command, around the limits of common length encodings.
+const BOUNDARY_LENGTHS: [usize; 12] = [
+    0, 1, 127, 128, 255, 256, 257, 65_535, 65_536, 65_537, 16_777_215, 16_777_216,
+];
+/// The number of random headers passed to 'Protocol::parse_header'.
+const RANDOM_HEADERS: usize = 4096;
+/// The chunk sizes used to feed the parser with fragmented frames.
+const CHUNK_SIZES: [usize; 3] = [1, 2, 7];
+
+/// The part of the conformance check which failed, see 'ConformanceFailure'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConformanceCheck {
+    /// A frame could not be constructed for a sample command & payload.
+    Construct,
+    /// A constructed frame is inconsistent with its header: it does not consist of header & payload (see 'Protocol::payload_follows_header'),
+    /// or its header does not declare the payload length.
+    HeaderConsistency,
+    /// A constructed frame was not parsed back into its command & payload.
+    RoundTrip,
+    /// Frames fed to the parser in small pieces were not parsed back into their commands & payloads.
+    Fragmented,
+    /// A frame with a payload of a boundary length (like 256 or 65536 bytes) was constructed, but not parsed back.
+    /// Refusing to construct such a frame is fine.
+    BoundaryLength,
+    /// Parsing a random header panicked, instead of returning an error.
+    RandomHeader,
+}
+
+/// A failed part of the conformance check, see 'check_protocol'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceFailure {
+    /// The part of the check which failed.
+    pub check: ConformanceCheck,
+    /// A description of the failure, including the command, the payload (or its length) & the bytes involved.
+    pub description: String,
+}
+impl std::fmt::Display for ConformanceFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.check, self.description)
+    }
+}
+
+/// Checks a protocol implementation against the expectations of the parser and returns all failures (empty if it conforms).
+///
+/// Every combination of the sample commands & payloads (and an empty payload) is constructed and parsed back, as a whole and fed in small pieces.
+/// Additionally, payloads of boundary lengths are probed for every sample command, and random headers are parsed to check that this never panics.
+/// The check is deterministic, so a failure can be reproduced.
+/// Payloads of up to 16 MiB are constructed, so this takes some memory.
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::conformance::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// for failure in check_protocol::<ProtocolExample>(&[CommandsExample::Start], &[vec![1, 2, 3]]) {
+///     println!("{}", failure);
+/// }
+/// ```
+pub fn check_protocol<P: Protocol>(
+    sample_commands: &[P::Commands],
+    sample_payloads: &[Vec<u8>],
+) -> Vec<ConformanceFailure> {
+    let mut failures = Vec::new();
+    let mut payloads = vec![Vec::new()];
+    payloads.extend(sample_payloads.iter().filter(|p| !p.is_empty()).cloned());
+    let mut messages = Vec::new();
+    let mut stream = Vec::new();
+    for &command in sample_commands {
+        for payload in &payloads {
+            if let Some(frame) = check_frame::<P>(command, payload, false, &mut failures) {
+                messages.push((command, payload.clone()));
+                stream.extend_from_slice(&frame);
+            }
+        }
+    }
+    for &chunk_size in &CHUNK_SIZES {
+        check_fragmented::<P>(&stream, &messages, chunk_size, &mut failures);
+    }
+    for &command in sample_commands {
+        for &length in &BOUNDARY_LENGTHS {
+            // a deterministic pattern, so misplaced bytes are detected
+            let payload: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+            check_frame::<P>(command, &payload, true, &mut failures);
+        }
+    }
+    check_random_headers::<P>(&mut failures);
+    failures
+}
+
+/// Constructs a frame and parses it back. Returns the frame, if no check failed.
+fn check_frame<P: Protocol>(
+    command: P::Commands,
+    payload: &[u8],
+    boundary: bool,
+    failures: &mut Vec<ConformanceFailure>,
+) -> Option<Vec<u8>> {
+    let fail = |failures: &mut Vec<ConformanceFailure>, check, description: String| {
+        let check = if boundary {
+            ConformanceCheck::BoundaryLength
+        } else {
+            check
+        };
+        failures.push(ConformanceFailure { check, description })
+    };
+    let frame = match P::construct_message(command, payload) {
+        Some(frame) => frame,
+        // refusing a boundary length is fine
+        None if boundary => return None,
+        None => {
+            fail(
+                failures,
+                ConformanceCheck::Construct,
+                format!(
+                    "'construct_message' returned None for {:?} with a payload of {} bytes",
+                    command,
+                    payload.len()
+                ),
+            );
+            return None;
+        }
+    };
+    if P::payload_follows_header() {
+        match P::construct_message_header(command, payload) {
+            Some(header)
+                if frame.len() == header.len() + payload.len()
+                    && frame.starts_with(&header)
+                    && frame.ends_with(payload) => {}
+            header => {
+                fail(
+                    failures,
+                    ConformanceCheck::HeaderConsistency,
+                    format!(
+                        "the frame for {:?} with a payload of {} bytes is not header & payload, although 'payload_follows_header' is true (header: {:?}, frame starts with {:?})",
+                        command,
+                        payload.len(),
+                        header,
+                        &frame[..frame.len().min(32)]
+                    ),
+                );
+                return None;
+            }
+        }
+    }
+    match P::message_slice_to_header_array(&frame).map(|(header, _)| P::parse_header(header)) {
+        Some(Ok((parsed, length)))
+            if parsed == command && length == frame.len() - header_length::<P>(&frame) => {}
+        parsed => {
+            fail(
+                failures,
+                ConformanceCheck::HeaderConsistency,
+                format!(
+                    "the header of the frame for {:?} with a payload of {} bytes parses to {:?} (frame starts with {:?})",
+                    command,
+                    payload.len(),
+                    parsed.map(|parsed| parsed.map_err(|(error, _)| error)),
+                    &frame[..frame.len().min(32)]
+                ),
+            );
+            return None;
+        }
+    }
+    let mut buffer = ProtocolBuffer::<P>::new();
+    let parsed = buffer.try_process_new_buffer(&frame);
+    let rest = buffer.try_process_new_buffer(&[]);
+    match (&parsed, &rest) {
+        (Ok(Some((parsed_command, parsed_payload))), Ok(None))
+            if *parsed_command == command
+                && parsed_payload.as_slice() == payload
+                && !buffer.parser_state().is_mid_frame() => {}
+        _ => {
+            fail(
+                failures,
+                ConformanceCheck::RoundTrip,
+                format!(
+                    "the frame for {:?} with a payload of {} bytes is parsed to {:?}, followed by {:?} (frame starts with {:?})",
+                    command,
+                    payload.len(),
+                    parsed.map(|message| message.map(|(command, payload)| (command, payload.len()))),
+                    rest.map(|message| message.map(|(command, payload)| (command, payload.len()))),
+                    &frame[..frame.len().min(32)]
+                ),
+            );
+            return None;
+        }
+    }
+    Some(frame)
+}
+
+/// The number of bytes in front of the payload, as split off by 'Protocol::message_slice_to_header_array'.
+fn header_length<P: Protocol>(frame: &[u8]) -> usize {
+    match P::message_slice_to_header_array(frame) {
+        Some((_, rest)) => frame.len() - rest.len(),
+        None => frame.len(),
+    }
+}
+
+/// Feeds all frames in pieces of the given size to a single parser and compares the parsed messages.
+fn check_fragmented<P: Protocol>(
+    stream: &[u8],
+    messages: &[Message<P>],
+    chunk_size: usize,
+    failures: &mut Vec<ConformanceFailure>,
+) {
+    let mut buffer = ProtocolBuffer::<P>::new();
+    let mut parsed = Vec::new();
+    for chunk in stream.chunks(chunk_size) {
+        let mut chunk = chunk;
+        loop {
+            match buffer.try_process_new_buffer(chunk) {
+                Ok(Some(message)) => parsed.push(message),
+                Ok(None) => break,
+                Err(violation) => {
+                    failures.push(ConformanceFailure {
+                        check: ConformanceCheck::Fragmented,
+                        description: format!(
+                            "fed in pieces of {} bytes, parsing failed after {} messages: {:?}",
+                            chunk_size,
+                            parsed.len(),
+                            violation
+                        ),
+                    });
+                    return;
+                }
+            }
+            chunk = &[];
+        }
+    }
+    if let Some(index) = (0..messages.len().max(parsed.len()))
+        .find(|&index| messages.get(index) != parsed.get(index))
+    {
+        failures.push(ConformanceFailure {
+            check: ConformanceCheck::Fragmented,
+            description: format!(
+                "fed in pieces of {} bytes, message {} is parsed to {:?} instead of {:?}",
+                chunk_size,
+                index,
+                parsed
+                    .get(index)
+                    .map(|(command, payload)| (command, payload.len())),
+                messages
+                    .get(index)
+                    .map(|(command, payload)| (command, payload.len()))
+            ),
+        });
+    }
+}
+
+/// Parses random headers, which may return errors, but must not panic.
+fn check_random_headers<P: Protocol>(failures: &mut Vec<ConformanceFailure>) {
+    let size = std::mem::size_of::<P::HeaderAsArray>() / std::mem::size_of::<u8>();
+    // xorshift, so the headers are the same for every run
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..RANDOM_HEADERS {
+        let header: Vec<u8> = (0..size)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let result = std::panic::catch_unwind(|| {
+            if let Some((header, _)) = P::message_slice_to_header_array(&header) {
+                let _ = P::parse_header(header);
+            }
+        });
+        if result.is_err() {
+            failures.push(ConformanceFailure {
+                check: ConformanceCheck::RandomHeader,
+                description: format!("parsing the header {:?} panicked", header),
+            });
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::engine::TcpStream;
+use super::protocol_buffer::Protocol;
+use super::read_thread::ReadThread;
+use super::tcp_ipc::{ConnectErrors, TcpIpc, TcpIpcConfig};
+use log::*;
+use std::net::ToSocketAddrs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+/// This services many connections with a single thread, instead of one read thread per connection.
+///
+/// Connections added to the group are returned as usual 'TcpIpc' values, so messages are received, written & awaited as for a single connection.
+/// Immediate responses are still answered by the group thread.
+/// Shutting down or dropping a connection does not disturb the other connections of the group.
+///
+/// The group thread runs until the group is dropped and all its connections are finished.
+/// The 'name' & 'thread_priority' settings of the connections' configs are not used, since the connections share the group thread.
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # run(|| {
+/// # let config = config();
+/// let group = ConnectionGroup::<ProtocolExample>::new(Some(std::time::Duration::from_micros(10)))?;
+/// let mut clients = Vec::new();
+/// for port in 6000..6200 {
+///     clients.push(group.add_client(("127.0.0.1", port), config.clone(), None)?);
+/// }
+/// # Ok(())
+/// # });
+/// ```
+pub struct ConnectionGroup<P: Protocol> {
+    new_connections: Sender<ReadThread<P>>,
+    connection_count: Arc<AtomicUsize>,
+}
+impl<P: Protocol> std::fmt::Debug for ConnectionGroup<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ConnectionGroup")
+            .field("connection_count", &self.connection_count())
+            .finish()
+    }
+}
+impl<P: Protocol> ConnectionGroup<P> {
+    /// This starts the group thread.
+    /// The input variable 'iteration_wait_time' is the time the group thread sleeps if none of its connections received data.
+    /// A 'None' value means that there will no time spend waiting.
+    pub fn new(iteration_wait_time: Option<std::time::Duration>) -> Result<Self, ConnectErrors> {
+        let (new_connections, new_connections_receiver) = std::sync::mpsc::channel();
+        let connection_count = Arc::new(AtomicUsize::new(0));
+        let connection_count_group = connection_count.clone();
+        std::thread::Builder::new()
+            .name("tcp-ipc/group".to_string())
+            .spawn(move || {
+                info!("Group thread started");
+                let mut connections: Vec<ReadThread<P>> = Vec::new();
+                'group_loop: loop {
+                    // without connections, there is nothing to do but waiting for new ones
+                    if connections.is_empty() {
+                        match new_connections_receiver.recv() {
+                            Ok(connection) => connections.push(connection),
+                            Err(_) => break 'group_loop,
+                        }
+                    }
+                    while let Ok(connection) = new_connections_receiver.try_recv() {
+                        connections.push(connection);
+                    }
+                    connections.retain_mut(|connection| connection.step());
+                    connection_count_group.store(connections.len(), Ordering::SeqCst);
+                    if connections.iter().all(|connection| connection.is_idle()) {
+                        if let Some(iteration_wait_time) = iteration_wait_time {
+                            std::thread::sleep(iteration_wait_time);
+                        }
+                    }
+                }
+                info!("Group thread finished");
+            })
+            .map_err(ConnectErrors::ThreadSpawnError)?;
+        Ok(Self {
+            new_connections,
+            connection_count,
+        })
+    }
+    /// This connects a client to a server (see 'TcpIpc::client') and adds the connection to the group.
+    pub fn add_client<T: ToSocketAddrs>(
+        &self,
+        socket_addresses: T,
+        config: TcpIpcConfig<P>,
+        connect_wait_time: Option<std::time::Duration>,
+    ) -> Result<TcpIpc<P>, ConnectErrors> {
+        config.check_protocol()?;
+        let stream = TcpIpc::<P>::connect(socket_addresses, connect_wait_time)?;
+        self.add(stream, config)
+    }
+    /// This waits for a client to connect (see 'TcpIpc::server') and adds the connection to the group.
+    pub fn add_server<T: ToSocketAddrs>(
+        &self,
+        socket_addresses: T,
+        config: TcpIpcConfig<P>,
+    ) -> Result<TcpIpc<P>, ConnectErrors> {
+        config.check_protocol()?;
+        let stream = TcpIpc::<P>::accept(socket_addresses)?;
+        self.add(stream, config)
+    }
+    /// This returns the number of connections currently serviced by the group thread.
+    pub fn connection_count(&self) -> usize {
+        self.connection_count.load(Ordering::SeqCst)
+    }
+    fn add(&self, stream: TcpStream, config: TcpIpcConfig<P>) -> Result<TcpIpc<P>, ConnectErrors> {
+        let (mut tcp_ipc, read_thread) = TcpIpc::prepare_connection(stream, config)?;
+        let started = std::time::Instant::now();
+        self.new_connections
+            .send(read_thread)
+            .map_err(|_| ConnectErrors::GroupThreadStopped)?;
+        tcp_ipc.settle(started)?;
+        Ok(tcp_ipc)
+    }
+}
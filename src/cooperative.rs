@@ -0,0 +1,184 @@
+use super::engine::{self, TcpStream};
+use super::protocol_buffer::{Message, Protocol};
+use super::read_thread::ReadThread;
+use super::tcp_ipc::{
+    BusyStateUpdateResult, ConnectErrors, ConnectionStats, ReadThreadErrors, ShutdownReport,
+    TcpIpc, TcpIpcConfig, WriteMessageErrors,
+};
+use std::net::ToSocketAddrs;
+
+/// The result of a single 'TcpIpcCooperative::tick'.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TickReport {
+    /// The number of frames parsed during the tick (including frames answered via the immediate route).
+    pub frames_parsed: u64,
+    /// Indicates that the budget was used up while data was still available, so the next tick should follow soon.
+    pub more_pending: bool,
+    /// Indicates that the reading side is finished (the connection is closed), so further ticks do nothing.
+    pub finished: bool,
+}
+
+/// A connection which is driven by periodic work items of the application, without any thread of this crate.
+///
+/// Each call of 'tick' reads, parses, answers via the immediate route & handles control requests (like busy state updates),
+/// using the same code as the read thread of 'TcpIpc', but only for the given time budget.
+/// Between ticks, 'get_message', 'write_message' & 'update_busy_state' can be called on the same thread.
+/// Unlike 'TcpIpcInline', no event loop is needed: a tick simply finds out whether data is available.
+/// The 'after_connect_wait_time', 'ready_when', 'read_iteration_wait_time' & 'thread_priority' settings of the config are not used.
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # fn handle<C, P>(_: C, _: P) {}
+/// # run(|| {
+/// # let config = config();
+/// let mut connection = TcpIpcCooperative::<ProtocolExample>::client("127.0.0.1:6666", config, None)?;
+/// // called periodically by the scheduler of the host application
+/// let report = connection.tick(std::time::Duration::from_micros(200));
+/// while let Some((command, payload)) = connection.get_message()? {
+///     handle(command, payload);
+/// }
+/// # Ok(())
+/// # });
+/// ```
+pub struct TcpIpcCooperative<P: Protocol> {
+    tcp_ipc: TcpIpc<P>,
+    // None once the reading side is finished
+    read_thread: Option<ReadThread<P>>,
+}
+impl<P: Protocol> std::fmt::Debug for TcpIpcCooperative<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TcpIpcCooperative")
+            .field("tcp_ipc", &self.tcp_ipc)
+            .field("finished", &self.read_thread.is_none())
+            .finish()
+    }
+}
+impl<P: Protocol> TcpIpcCooperative<P> {
+    /// This connects to a server, see 'TcpIpc::client'. No thread is spawned & nothing is read before the first tick.
+    pub fn client<T: ToSocketAddrs>(
+        socket_addresses: T,
+        config: TcpIpcConfig<P>,
+        connect_wait_time: Option<std::time::Duration>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        let stream = TcpIpc::<P>::connect(socket_addresses, connect_wait_time)?;
+        Self::from_transport(stream, config)
+    }
+    /// This waits for a client to connect, see 'TcpIpc::server'. No thread is spawned & nothing is read before the first tick.
+    pub fn server<T: ToSocketAddrs>(
+        socket_addresses: T,
+        config: TcpIpcConfig<P>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        let stream = TcpIpc::<P>::accept(socket_addresses)?;
+        Self::from_transport(stream, config)
+    }
+    /// This sets up a connection on an already connected stream.
+    pub fn from_transport(
+        stream: TcpStream,
+        config: TcpIpcConfig<P>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        engine::set_nonblocking(&stream).map_err(ConnectErrors::ConnectionError)?;
+        let (tcp_ipc, read_thread) = TcpIpc::prepare_connection(stream, config)?;
+        Ok(Self {
+            tcp_ipc,
+            read_thread: Some(read_thread),
+        })
+    }
+    /// This does the work of the read thread until no data is available or the budget is used up.
+    /// A single read (and parsing the frames it completed) is not interrupted, so a tick may take slightly longer than the budget.
+    /// At least one read is done, even for a zero budget.
+    pub fn tick(&mut self, budget: std::time::Duration) -> TickReport {
+        let read_thread = match &mut self.read_thread {
+            Some(read_thread) => read_thread,
+            None => {
+                return TickReport {
+                    finished: true,
+                    ..TickReport::default()
+                }
+            }
+        };
+        let started = std::time::Instant::now();
+        let parsed_before = self.tcp_ipc.stats().messages_received;
+        let mut report = TickReport::default();
+        loop {
+            if !read_thread.step() {
+                report.finished = true;
+                break;
+            }
+            if read_thread.is_idle() {
+                break;
+            }
+            if started.elapsed() >= budget {
+                report.more_pending = true;
+                break;
+            }
+        }
+        if report.finished {
+            self.read_thread = None;
+        }
+        report.frames_parsed = self.tcp_ipc.stats().messages_received - parsed_before;
+        report
+    }
+    /// This function checks if a message was received by a previous tick, see 'TcpIpc::get_message'.
+    pub fn get_message(&mut self) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+        self.tcp_ipc.get_message()
+    }
+    /// This writes a message, see 'TcpIpc::write_message'.
+    pub fn write_message(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        self.tcp_ipc.write_message(command, message_)
+    }
+    /// This writes a message without waiting for the outgoing rate limit, see 'TcpIpc::try_write_message'.
+    pub fn try_write_message(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        self.tcp_ipc.try_write_message(command, message_)
+    }
+    /// This writes a message, retrying at most the given time while the stream would block, see 'TcpIpc::write_message_within'.
+    pub fn write_message_within(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+        max_duration: std::time::Duration,
+    ) -> Result<(), WriteMessageErrors> {
+        self.tcp_ipc
+            .write_message_within(command, message_, max_duration)
+    }
+    /// This updates the busy state, which applies to all messages parsed afterwards.
+    pub fn update_busy_state(&mut self, new_busy_state: P::BusyStates) -> BusyStateUpdateResult {
+        let result = self.tcp_ipc.update_busy_state(new_busy_state);
+        if let Some(read_thread) = &mut self.read_thread {
+            if !read_thread.handle_control() {
+                self.read_thread = None;
+            }
+        }
+        result
+    }
+    /// Checks if the connection is known to be closed, see 'TcpIpc::is_connection_closed'.
+    pub fn is_connection_closed(&self) -> bool {
+        self.tcp_ipc.is_connection_closed()
+    }
+    /// Returns a snapshot of the counters of this connection.
+    pub fn stats(&self) -> ConnectionStats {
+        self.tcp_ipc.stats()
+    }
+    /// This shuts down the connection, see 'TcpIpc::shutdown'.
+    /// Queued frames are written until the 'shutdown_wait_time' passed, so this blocks for at most this time.
+    pub fn shutdown(mut self) -> Result<ShutdownReport, ShutdownReport> {
+        self.tcp_ipc.stop_read_thread();
+        if let Some(read_thread) = &mut self.read_thread {
+            while read_thread.step() {
+                std::thread::yield_now();
+            }
+        }
+        self.tcp_ipc.shutdown()
+    }
+}
@@ -0,0 +1,82 @@
+use super::protocol_buffer::{Message, Protocol};
+use super::tcp_ipc::ReadThreadErrors;
+use std::time::Duration;
+
+/// The time left of an overall deadline, which successive awaits draw from (see 'TcpIpc::await_command_budgeted').
+///
+/// The budget only does the arithmetic: the time waited is passed in, so it does not depend on the clock. It never drops below zero.
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # run(|| {
+/// # let mut client = client();
+/// let mut budget = DeadlineBudget::new(std::time::Duration::from_secs(1));
+/// let ready = client.await_command_budgeted(&[CommandsExample::Ready], &mut budget);
+/// let done = client.await_command_budgeted(&[CommandsExample::Done], &mut budget); // gets what the first await left
+/// # Ok(())
+/// # });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadlineBudget {
+    remaining: Duration,
+}
+impl DeadlineBudget {
+    /// Creates a budget of the given time.
+    pub fn new(total: Duration) -> Self {
+        Self { remaining: total }
+    }
+    /// Returns the time left.
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+    /// Checks if no time is left.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == Duration::from_secs(0)
+    }
+    /// Takes the given time from the budget & returns the time left, which is zero once more was drawn than was left.
+    pub fn draw(&mut self, waited: Duration) -> Duration {
+        self.remaining = self.remaining.saturating_sub(waited);
+        self.remaining
+    }
+}
+
+/// The result of a budgeted await together with its accounting, see 'TcpIpc::await_command_budgeted'.
+#[derive(Debug)]
+pub struct AwaitOutcome<P: Protocol> {
+    /// The result, as returned by 'TcpIpc::await_any_command'.
+    pub result: Result<Option<Message<P>>, ReadThreadErrors<P>>,
+    /// The time the await took, which was drawn from the budget.
+    pub waited: Duration,
+    /// The number of queued messages with other commands the await passed over, which stay queued for 'TcpIpc::get_message'.
+    pub messages_skipped: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn successive_draws_share_the_budget() {
+        let mut budget = DeadlineBudget::new(300 * MS);
+        assert_eq!(budget.draw(50 * MS), 250 * MS);
+        assert_eq!(budget.draw(120 * MS), 130 * MS);
+        assert_eq!(budget.remaining(), 130 * MS);
+        assert!(!budget.is_exhausted());
+        assert_eq!(budget.draw(130 * MS), Duration::from_secs(0));
+        assert!(budget.is_exhausted());
+    }
+
+    #[test]
+    fn the_budget_saturates_at_zero() {
+        let mut budget = DeadlineBudget::new(100 * MS);
+        assert_eq!(budget.draw(250 * MS), Duration::from_secs(0));
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.draw(MS), Duration::from_secs(0));
+        let mut empty = DeadlineBudget::new(Duration::from_secs(0));
+        assert!(empty.is_exhausted());
+        assert_eq!(empty.draw(Duration::from_secs(0)), Duration::from_secs(0));
+    }
+}
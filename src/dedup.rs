@@ -0,0 +1,36 @@
+use std::collections::VecDeque;
+
+/// The maximal number of recent keys remembered by the deduplication filter (see 'TcpIpcConfig::dedup_window').
+/// If more frames arrive within the window, the oldest keys are forgotten early.
+pub const DEDUP_CAPACITY: usize = 256;
+
+/// Remembers the keys (see 'Protocol::dedup_key') of recently received frames, to drop duplicates within a time window.
+pub struct DedupFilter {
+    window: std::time::Duration,
+    recent: VecDeque<(std::time::Instant, u64)>,
+}
+impl DedupFilter {
+    pub fn new(window: std::time::Duration) -> Self {
+        Self {
+            window,
+            recent: VecDeque::with_capacity(DEDUP_CAPACITY),
+        }
+    }
+    /// Checks if a frame with the given key was seen within the window. If not, the key is remembered.
+    pub fn is_duplicate(&mut self, key: u64, now: std::time::Instant) -> bool {
+        while let Some((seen, _)) = self.recent.front() {
+            if now.duration_since(*seen) <= self.window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        if self.recent.iter().any(|(_, recent_key)| *recent_key == key) {
+            return true;
+        }
+        if self.recent.len() == DEDUP_CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((now, key));
+        false
+    }
+}
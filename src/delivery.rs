@@ -0,0 +1,147 @@
+use super::protocol_buffer::{Message, Protocol};
+
+/// A report about the messages delivered to the consumer, see 'TcpIpc::take_delivery_report'.
+///
+/// Each message forwarded by the read thread gets a per-connection sequence number (starting at 0), so the consumer can verify that it saw every message, in order.
+/// Messages answered via the immediate route are not forwarded and thus get no sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeliveryReport {
+    /// The number of messages delivered since the last report.
+    pub delivered: u64,
+    /// The number of messages which were dropped (by a policy) before delivery since the last report.
+    pub dropped_by_policy: u64,
+    /// The sequence number of the last delivered message, if any message was delivered at all.
+    pub last_sequence: Option<u64>,
+}
+
+/// The metadata of a delivered message, see 'TcpIpc::get_message_with_metadata'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MessageMetadata {
+    /// The sequence number of the message.
+    pub sequence: u64,
+    /// Indicates that the message was received after the peer announced to close the connection (see 'Protocol::shutdown_command'), i.e. it is part of the peer's final flush.
+    pub received_during_peer_shutdown: bool,
+    /// The number of previous deliveries which were not acknowledged (see 'TcpIpc::get_message_unacked'). This is 0 for other ways of retrieving messages.
+    pub redeliveries: u32,
+}
+
+/// A delivered message together with the context it was parsed & delivered in, see 'TcpIpc::next_with_context'.
+pub struct MessageWithContext<P: Protocol> {
+    /// The message itself.
+    pub message: Message<P>,
+    /// The sequence number of the message.
+    pub sequence: u64,
+    /// The busy state the read thread applied when it parsed the message (the one the immediate route was checked with).
+    pub busy_state: P::BusyStates,
+    /// The number of further messages which were already forwarded by the read thread when the message was delivered.
+    pub queue_depth: usize,
+}
+
+/// A delivered message or a gap, as returned by 'TcpIpc::get_message_or_gap'.
+pub enum MessageOrGap<P: Protocol> {
+    /// A delivered message together with its sequence number.
+    Message {
+        /// The sequence number of the message.
+        sequence: u64,
+        /// The message itself.
+        message: Message<P>,
+    },
+    /// Messages were dropped before delivery. The message following the gap is returned by the next call.
+    Gap {
+        /// The sequence number of the first dropped message.
+        first_missing: u64,
+        /// The number of dropped messages.
+        count: u64,
+    },
+}
+// the impls are written by hand, since deriving them would require the protocol itself to implement them
+impl<P: Protocol> Clone for MessageWithContext<P> {
+    fn clone(&self) -> Self {
+        Self {
+            message: self.message.clone(),
+            sequence: self.sequence,
+            busy_state: self.busy_state,
+            queue_depth: self.queue_depth,
+        }
+    }
+}
+impl<P: Protocol> core::fmt::Debug for MessageWithContext<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("MessageWithContext")
+            .field("message", &self.message)
+            .field("sequence", &self.sequence)
+            .field("busy_state", &self.busy_state)
+            .field("queue_depth", &self.queue_depth)
+            .finish()
+    }
+}
+impl<P: Protocol> PartialEq for MessageWithContext<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+            && self.sequence == other.sequence
+            && self.busy_state == other.busy_state
+            && self.queue_depth == other.queue_depth
+    }
+}
+impl<P: Protocol> Clone for MessageOrGap<P> {
+    fn clone(&self) -> Self {
+        match self {
+            MessageOrGap::Message { sequence, message } => MessageOrGap::Message {
+                sequence: *sequence,
+                message: message.clone(),
+            },
+            MessageOrGap::Gap {
+                first_missing,
+                count,
+            } => MessageOrGap::Gap {
+                first_missing: *first_missing,
+                count: *count,
+            },
+        }
+    }
+}
+impl<P: Protocol> core::fmt::Debug for MessageOrGap<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            MessageOrGap::Message { sequence, message } => f
+                .debug_struct("Message")
+                .field("sequence", sequence)
+                .field("message", message)
+                .finish(),
+            MessageOrGap::Gap {
+                first_missing,
+                count,
+            } => f
+                .debug_struct("Gap")
+                .field("first_missing", first_missing)
+                .field("count", count)
+                .finish(),
+        }
+    }
+}
+impl<P: Protocol> PartialEq for MessageOrGap<P> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                MessageOrGap::Message { sequence, message },
+                MessageOrGap::Message {
+                    sequence: other_sequence,
+                    message: other_message,
+                },
+            ) => sequence == other_sequence && message == other_message,
+            (
+                MessageOrGap::Gap {
+                    first_missing,
+                    count,
+                },
+                MessageOrGap::Gap {
+                    first_missing: other_first_missing,
+                    count: other_count,
+                },
+            ) => first_missing == other_first_missing && count == other_count,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use super::build_info::CapabilityReport;
+use super::protocol_buffer::{ParserState, PeerInfo, Protocol};
+use super::registry::ConnectionId;
+use super::stats::ConnectionStats;
+use super::trace::{IncomingTraceEntry, TraceEntry};
+
+/// The number of queued commands which are listed in a diagnostics snapshot.
+pub const DIAGNOSTICS_PENDING_COMMANDS: usize = 16;
+
+/// A snapshot of the internal state of a connection, intended to be attached to logs or bug reports.
+/// It is created via 'TcpIpc::diagnostics', which does not consume any queued messages.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(bound = "P::Commands: serde::Serialize, P::BusyStates: serde::Serialize")
+)]
+pub struct Diagnostics<P: Protocol> {
+    /// The id of the connection.
+    pub connection_id: ConnectionId,
+    /// The name of the connection, see 'TcpIpcConfig::name'.
+    pub name: Option<String>,
+    /// Indicates if the connection is known to be closed.
+    pub connection_closed: bool,
+    /// The counters of the connection.
+    pub stats: ConnectionStats,
+    /// The number of received messages which are not yet retrieved.
+    pub pending_messages: usize,
+    /// The number of read thread errors which are not yet retrieved.
+    pub pending_errors: usize,
+    /// The commands of the first (up to 'DIAGNOSTICS_PENDING_COMMANDS') messages which are not yet retrieved.
+    pub pending_commands: Vec<P::Commands>,
+    /// The state of the parser. This is None if the read thread is not running anymore.
+    pub parser_state: Option<ParserState<P>>,
+    /// The current busy state. This is None if the read thread is not running anymore.
+    pub busy_state: Option<P::BusyStates>,
+    /// The limits the connection is configured with.
+    pub limits: ConfiguredLimits,
+    /// How the connection was built, see 'TcpIpc::capability_report'.
+    pub capabilities: CapabilityReport,
+    /// A description of the last error seen on this connection, if any.
+    pub last_error: Option<String>,
+    /// What the peer declared about itself, see 'TcpIpc::peer_info'.
+    pub peer_info: Option<PeerInfo>,
+    /// The most recent outgoing frames, see 'TcpIpc::outgoing_trace'.
+    pub outgoing_trace: Vec<TraceEntry<P>>,
+    /// The most recent incoming frames, see 'TcpIpc::incoming_trace'.
+    pub incoming_trace: Vec<IncomingTraceEntry<P>>,
+}
+
+/// The configured limits of a connection, as reported in a diagnostics snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConfiguredLimits {
+    /// The size of the buffer the read thread reads into.
+    pub read_buffer_size: usize,
+    /// The configured (deprecated) number of read iterations, see 'TcpIpcConfig::check_count'.
+    pub check_count: u32,
+    /// The interval after which control requests (shutdown, busy state, ...) are handled.
+    pub control_check_interval: std::time::Duration,
+    /// The time the read thread sleeps between iterations.
+    pub read_iteration_wait_time: Option<std::time::Duration>,
+    /// The time a shutdown waits for the read thread.
+    pub shutdown_wait_time: Option<std::time::Duration>,
+    /// The memory budget of the connection, see 'TcpIpcConfig::memory_budget'.
+    pub memory_budget: Option<usize>,
+}
@@ -0,0 +1,162 @@
+//! The protocol used by the examples of the documentation, so they are compiled (& partly run) as doc tests.
+//! It is not part of the API & may change at any time.
+use super::protocol_buffer::Protocol;
+use super::protocols::LengthPrefixedProtocol;
+use super::tcp_ipc::{TcpIpc, TcpIpcConfig};
+use core::convert::TryFrom;
+use core::fmt::Debug;
+
+/// The example protocol: a 4-byte length followed by a 1-byte command (see 'LengthPrefixedProtocol').
+/// 'QueryIsBusy' is answered via the immediate route with the busy state.
+#[derive(Debug)]
+pub enum ProtocolExample {}
+
+/// The commands of 'ProtocolExample'.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandsExample {
+    Start = 1,
+    Stop,
+    Started,
+    Prepared,
+    Ready,
+    Done,
+    Data,
+    Image,
+    Telemetry,
+    GetStatus,
+    Status,
+    QueryIsBusy,
+    IsBusy,
+    Ping,
+    Pong,
+    Ack,
+    Nack,
+    Welcome,
+    Bye,
+    Closing,
+    OpenValve,
+    Debug,
+}
+const COMMANDS: [CommandsExample; 22] = {
+    use CommandsExample::*;
+    [
+        Start,
+        Stop,
+        Started,
+        Prepared,
+        Ready,
+        Done,
+        Data,
+        Image,
+        Telemetry,
+        GetStatus,
+        Status,
+        QueryIsBusy,
+        IsBusy,
+        Ping,
+        Pong,
+        Ack,
+        Nack,
+        Welcome,
+        Bye,
+        Closing,
+        OpenValve,
+        Debug,
+    ]
+};
+impl From<CommandsExample> for u32 {
+    fn from(command: CommandsExample) -> u32 {
+        command as u32
+    }
+}
+impl TryFrom<u32> for CommandsExample {
+    type Error = ();
+    fn try_from(id: u32) -> Result<Self, ()> {
+        COMMANDS
+            .iter()
+            .copied()
+            .find(|command| *command as u32 == id)
+            .ok_or(())
+    }
+}
+
+/// The busy states of 'ProtocolExample'.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusyStatesExample {
+    Idle,
+    Working,
+    Failure,
+}
+
+type Layout = LengthPrefixedProtocol<CommandsExample, 4, 1>;
+impl Protocol for ProtocolExample {
+    type Commands = CommandsExample;
+    type BusyStates = BusyStatesExample;
+    type CommandAsArray = <Layout as Protocol>::CommandAsArray;
+    type LengthAsArray = <Layout as Protocol>::LengthAsArray;
+    type HeaderAsArray = <Layout as Protocol>::HeaderAsArray;
+    fn idle() -> Self::BusyStates {
+        BusyStatesExample::Idle
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &Self::Commands,
+        _message: &[u8],
+        busy_state: &Self::BusyStates,
+    ) -> Option<(Self::Commands, Vec<u8>)> {
+        match command {
+            CommandsExample::QueryIsBusy => {
+                Some((CommandsExample::IsBusy, vec![*busy_state as u8]))
+            }
+            _ => None,
+        }
+    }
+    fn parse_command(command: &Self::CommandAsArray) -> Option<Self::Commands> {
+        Layout::parse_command(command)
+    }
+    fn parse_length(length: &Self::LengthAsArray) -> Option<usize> {
+        Layout::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&Self::HeaderAsArray, &[u8])> {
+        Layout::message_slice_to_header_array(input)
+    }
+    fn split_header_array(
+        header: &Self::HeaderAsArray,
+    ) -> (&Self::CommandAsArray, &Self::LengthAsArray) {
+        Layout::split_header_array(header)
+    }
+    fn command_to_array(command: Self::Commands) -> Self::CommandAsArray {
+        Layout::command_to_array(command)
+    }
+    fn get_length_as_array(command: Self::Commands, message: &[u8]) -> Option<Self::LengthAsArray> {
+        Layout::get_length_as_array(command, message)
+    }
+    fn construct_header(command: Self::CommandAsArray, length: Self::LengthAsArray) -> Vec<u8> {
+        Layout::construct_header(command, length)
+    }
+}
+
+/// The default config for 'ProtocolExample'.
+pub fn config() -> TcpIpcConfig<ProtocolExample> {
+    TcpIpcConfig::default()
+}
+/// Connects a client to "127.0.0.1:6666" (so the examples using it are not run).
+pub fn client() -> TcpIpc<ProtocolExample> {
+    TcpIpc::client("127.0.0.1:6666", config(), None).expect("connecting failed")
+}
+
+/// The error of the examples, which every error of this crate converts into (by '?').
+/// Most errors of this crate do not implement 'std::error::Error', so 'Box<dyn Error>' cannot be used.
+pub struct ExampleError(pub String);
+impl<E: Debug> From<E> for ExampleError {
+    fn from(err: E) -> Self {
+        ExampleError(format!("{:?}", err))
+    }
+}
+/// Runs an example, which fails by panicking with its error.
+pub fn run<F: FnOnce() -> Result<(), ExampleError>>(example: F) {
+    if let Err(ExampleError(err)) = example() {
+        panic!("The example failed: {}", err);
+    }
+}
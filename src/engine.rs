@@ -0,0 +1,110 @@
+//! The non-blocking sockets of the selected engine (cargo features `engine-mio` & `engine-std`).
+//! Both engines provide the same socket interface, so the rest of the crate does not depend on the engine.
+//! If both features are enabled, mio is used.
+//! Errors of connecting & accepting are normalized (see 'os_errors'), so the connect loops see the same error kinds on all platforms.
+#[cfg(any(feature = "engine-mio", feature = "engine-std"))]
+use super::os_errors::normalize;
+#[cfg(any(feature = "engine-mio", feature = "engine-std"))]
+use std::net::SocketAddr;
+
+#[cfg(not(any(feature = "engine-mio", feature = "engine-std")))]
+compile_error!("At least one of the features `engine-mio` and `engine-std` has to be enabled.");
+
+#[cfg(feature = "engine-mio")]
+pub use mio::net::{TcpListener, TcpStream};
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub use std::net::{TcpListener, TcpStream};
+
+/// Connects to the given address. The returned stream is non-blocking.
+#[cfg(feature = "engine-mio")]
+pub fn connect(address: &SocketAddr) -> std::io::Result<TcpStream> {
+    TcpStream::connect(address).map_err(normalize)
+}
+/// Connects to the given address. The returned stream is non-blocking.
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn connect(address: &SocketAddr) -> std::io::Result<TcpStream> {
+    let stream = TcpStream::connect(address).map_err(normalize)?;
+    stream.set_nonblocking(true)?;
+    Ok(stream)
+}
+
+/// Binds a listener to the given address. Accepting is non-blocking.
+#[cfg(feature = "engine-mio")]
+pub fn bind(address: &SocketAddr) -> std::io::Result<TcpListener> {
+    TcpListener::bind(address)
+}
+/// Binds a listener to the given address. Accepting is non-blocking.
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn bind(address: &SocketAddr) -> std::io::Result<TcpListener> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Accepts a pending connection, if any. The returned stream is non-blocking.
+#[cfg(feature = "engine-mio")]
+pub fn accept(listener: &TcpListener) -> std::io::Result<(TcpStream, SocketAddr)> {
+    listener.accept().map_err(normalize)
+}
+/// Accepts a pending connection, if any. The returned stream is non-blocking.
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn accept(listener: &TcpListener) -> std::io::Result<(TcpStream, SocketAddr)> {
+    let (stream, address) = listener.accept().map_err(normalize)?;
+    stream.set_nonblocking(true)?;
+    Ok((stream, address))
+}
+
+/// Makes sure that the stream is non-blocking. Streams of mio are always non-blocking.
+#[cfg(feature = "engine-mio")]
+pub fn set_nonblocking(_stream: &TcpStream) -> std::io::Result<()> {
+    Ok(())
+}
+/// Makes sure that the stream is non-blocking.
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn set_nonblocking(stream: &TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(true)
+}
+
+/// Sets the size of the send buffer of the operating system.
+#[cfg(feature = "engine-mio")]
+pub fn set_send_buffer_size(stream: &TcpStream, size: usize) -> std::io::Result<()> {
+    stream.set_send_buffer_size(size)
+}
+/// Sets the size of the send buffer of the operating system. The standard library cannot set it, so socket2 is used.
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn set_send_buffer_size(stream: &TcpStream, size: usize) -> std::io::Result<()> {
+    socket2::SockRef::from(stream).set_send_buffer_size(size)
+}
+
+/// Sets the size of the receive buffer of the operating system.
+#[cfg(feature = "engine-mio")]
+pub fn set_recv_buffer_size(stream: &TcpStream, size: usize) -> std::io::Result<()> {
+    stream.set_recv_buffer_size(size)
+}
+/// Sets the size of the receive buffer of the operating system. The standard library cannot set it, so socket2 is used.
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn set_recv_buffer_size(stream: &TcpStream, size: usize) -> std::io::Result<()> {
+    socket2::SockRef::from(stream).set_recv_buffer_size(size)
+}
+
+/// Gets the size of the send buffer of the operating system.
+#[cfg(feature = "engine-mio")]
+pub fn send_buffer_size(stream: &TcpStream) -> std::io::Result<usize> {
+    stream.send_buffer_size()
+}
+/// Gets the size of the send buffer of the operating system (via socket2, like 'set_send_buffer_size').
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn send_buffer_size(stream: &TcpStream) -> std::io::Result<usize> {
+    socket2::SockRef::from(stream).send_buffer_size()
+}
+
+/// Gets the size of the receive buffer of the operating system.
+#[cfg(feature = "engine-mio")]
+pub fn recv_buffer_size(stream: &TcpStream) -> std::io::Result<usize> {
+    stream.recv_buffer_size()
+}
+/// Gets the size of the receive buffer of the operating system (via socket2, like 'set_recv_buffer_size').
+#[cfg(all(feature = "engine-std", not(feature = "engine-mio")))]
+pub fn recv_buffer_size(stream: &TcpStream) -> std::io::Result<usize> {
+    socket2::SockRef::from(stream).recv_buffer_size()
+}
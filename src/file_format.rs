@@ -0,0 +1,33 @@
+/// A file was written in a format version which this version of the crate cannot read (for example, by a newer version).
+/// The readers return it as the inner error of an 'std::io::Error' of kind 'InvalidData' (see 'std::io::Error::get_ref').
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedFormatVersion {
+    /// The version found in the header of the file.
+    pub found: u32,
+    /// The versions which can be read.
+    pub supported: &'static [u32],
+}
+impl std::fmt::Display for UnsupportedFormatVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "unsupported file format version {} (supported: {:?})",
+            self.found, self.supported
+        )
+    }
+}
+impl std::error::Error for UnsupportedFormatVersion {}
+impl UnsupportedFormatVersion {
+    // wraps the error for the readers, which report 'std::io::Error'
+    pub(crate) fn into_io_error(self) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, self)
+    }
+}
+
+/// The error of a reader given no codec for a file whose header says that its bytes are encoded.
+pub(crate) fn codec_required() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "the bytes of the file are encoded, so the codec it was written with is required",
+    )
+}
@@ -0,0 +1,177 @@
+use super::engine::{self, TcpStream};
+use super::protocol_buffer::{Message, Protocol};
+use super::read_thread::ReadThread;
+use super::tcp_ipc::{
+    BusyStateUpdateResult, ConnectErrors, ConnectionStats, ReadThreadErrors, ShutdownReport,
+    TcpIpc, TcpIpcConfig, WriteMessageErrors,
+};
+
+/// A connection which is driven by an external event loop, without any thread of this crate.
+///
+/// Reading, parsing, answering via the immediate route & the busy state are handled by the same code as for 'TcpIpc',
+/// but only when the event loop calls 'handle_readable' (or 'handle_writable'), instead of on a read thread.
+/// The 'after_connect_wait_time', 'ready_when', 'read_iteration_wait_time' & 'thread_priority' settings of the config are not used.
+/// # Example
+/// The example registers the connection at a mio poll, which needs the 'engine-mio' engine.
+#[cfg_attr(feature = "engine-mio", doc = "```no_run")]
+#[cfg_attr(not(feature = "engine-mio"), doc = "```ignore")]
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # use mio::{Events, Poll, Token};
+/// # fn handle<C, P>(_: C, _: P) {}
+/// # run(|| {
+/// # let config = config();
+/// # let poll = Poll::new()?;
+/// # let mut events = Events::with_capacity(16);
+/// # let stream = mio::net::TcpStream::connect(&"127.0.0.1:6666".parse()?)?;
+/// let mut connection = TcpIpcInline::<ProtocolExample>::from_transport(stream, config)?;
+/// connection.register(&poll, Token(0))?;
+/// loop {
+///     poll.poll(&mut events, None)?;
+///     for event in &events {
+///         if event.readiness().is_writable() {
+///             connection.handle_writable();
+///         }
+///         if event.readiness().is_readable() {
+///             for (command, payload) in connection.handle_readable()? {
+///                 handle(command, payload);
+///             }
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # });
+/// ```
+pub struct TcpIpcInline<P: Protocol> {
+    tcp_ipc: TcpIpc<P>,
+    // None once the reading side is finished
+    read_thread: Option<ReadThread<P>>,
+}
+impl<P: Protocol> std::fmt::Debug for TcpIpcInline<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TcpIpcInline")
+            .field("tcp_ipc", &self.tcp_ipc)
+            .field("finished", &self.read_thread.is_none())
+            .finish()
+    }
+}
+impl<P: Protocol> TcpIpcInline<P> {
+    /// This sets up a connection on an already connected stream (for example accepted by the event loop).
+    pub fn from_transport(
+        stream: TcpStream,
+        config: TcpIpcConfig<P>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        engine::set_nonblocking(&stream).map_err(ConnectErrors::ConnectionError)?;
+        let (tcp_ipc, read_thread) = TcpIpc::prepare_connection(stream, config)?;
+        Ok(Self {
+            tcp_ipc,
+            read_thread: Some(read_thread),
+        })
+    }
+    /// This registers the connection for readable & writable events (edge-triggered) at the given poll.
+    #[cfg(feature = "engine-mio")]
+    pub fn register(&self, poll: &mio::Poll, token: mio::Token) -> Result<(), std::io::Error> {
+        poll.register(
+            self.tcp_ipc.stream(),
+            token,
+            mio::Ready::readable() | mio::Ready::writable(),
+            mio::PollOpt::edge(),
+        )
+    }
+    /// This removes the connection from the given poll.
+    #[cfg(feature = "engine-mio")]
+    pub fn deregister(&self, poll: &mio::Poll) -> Result<(), std::io::Error> {
+        poll.deregister(self.tcp_ipc.stream())
+    }
+    /// This reads until no more data is available, answers via the immediate route & returns all received messages.
+    /// An error is returned after the messages received before it (i.e. by the next call).
+    /// Once the connection is closed and all messages are returned, ConnectionClosed is returned.
+    pub fn handle_readable(&mut self) -> Result<Vec<Message<P>>, ReadThreadErrors<P>> {
+        if let Some(read_thread) = &mut self.read_thread {
+            // reading until the stream would block is required, since the readiness is edge-triggered
+            loop {
+                if !read_thread.step() {
+                    self.read_thread = None;
+                    break;
+                }
+                if read_thread.is_idle() {
+                    break;
+                }
+            }
+        }
+        let mut messages = Vec::new();
+        loop {
+            match self.tcp_ipc.get_message() {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => return Ok(messages),
+                Err(err) if messages.is_empty() => return Err(err),
+                Err(err) => {
+                    self.tcp_ipc.defer_error(err);
+                    return Ok(messages);
+                }
+            }
+        }
+    }
+    /// This writes queued frames (like immediate responses or messages which did not fit into the socket), as far as possible without blocking.
+    pub fn handle_writable(&mut self) {
+        if let Some(read_thread) = &mut self.read_thread {
+            read_thread.flush();
+        }
+    }
+    /// This writes a message, see 'TcpIpc::write_message'.
+    pub fn write_message(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        self.tcp_ipc.write_message(command, message_)
+    }
+    /// This writes a message without waiting for the outgoing rate limit, see 'TcpIpc::try_write_message'.
+    pub fn try_write_message(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        self.tcp_ipc.try_write_message(command, message_)
+    }
+    /// This writes a message, retrying at most the given time while the stream would block, see 'TcpIpc::write_message_within'.
+    pub fn write_message_within(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+        max_duration: std::time::Duration,
+    ) -> Result<(), WriteMessageErrors> {
+        self.tcp_ipc
+            .write_message_within(command, message_, max_duration)
+    }
+    /// This updates the busy state, which applies to all messages parsed afterwards.
+    pub fn update_busy_state(&mut self, new_busy_state: P::BusyStates) -> BusyStateUpdateResult {
+        let result = self.tcp_ipc.update_busy_state(new_busy_state);
+        if let Some(read_thread) = &mut self.read_thread {
+            if !read_thread.handle_control() {
+                self.read_thread = None;
+            }
+        }
+        result
+    }
+    /// Checks if the connection is known to be closed, see 'TcpIpc::is_connection_closed'.
+    pub fn is_connection_closed(&self) -> bool {
+        self.tcp_ipc.is_connection_closed()
+    }
+    /// Returns a snapshot of the counters of this connection.
+    pub fn stats(&self) -> ConnectionStats {
+        self.tcp_ipc.stats()
+    }
+    /// This shuts down the connection, see 'TcpIpc::shutdown'.
+    /// Queued frames are written until the 'shutdown_wait_time' passed, so this blocks for at most this time.
+    pub fn shutdown(mut self) -> Result<ShutdownReport, ShutdownReport> {
+        self.tcp_ipc.stop_read_thread();
+        if let Some(read_thread) = &mut self.read_thread {
+            while read_thread.step() {
+                std::thread::yield_now();
+            }
+        }
+        self.tcp_ipc.shutdown()
+    }
+}
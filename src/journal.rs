@@ -0,0 +1,424 @@
+//! A write-ahead journal of outgoing frames, for commands which must not get lost if the process crashes.
+//!
+//! 'TcpIpc::write_message_journaled' appends the frame to the journal before it is written to the socket,
+//! and marks it as completed once the socket accepted all of its bytes.
+//! After a crash, 'recover' returns the frames which were not completed, so the application can inspect them
+//! (and send them again, or 'discard' them). A frame can be reported although it reached the peer (if the crash happened right after writing),
+//! but a frame which may not have reached the peer is never missed.
+//!
+//! A journal file must only be used by one connection at a time.
+//! When a connection opens a journal without pending frames, the file is emptied, so it does not grow across restarts.
+//!
+//! # Format
+//! The file starts with a header, followed by records. All integers are big-endian.
+//!
+//! | field | size | content |
+//! |---|---|---|
+//! | magic | 3 bytes | `TIJ` |
+//! | version | 1 byte | the format version as ASCII digit, currently `2` |
+//! | flags | 1 byte | bit 0: the frames are encoded by a codec (see 'JournalConfig::codec') |
+//!
+//! | field | size | content |
+//! |---|---|---|
+//! | kind | 1 byte | 1: frame appended, 2: frame completed |
+//! | id | 8 bytes | the journal id of the frame, increasing within a file |
+//! | length | 4 bytes | the number of frame bytes (0 for a completion) |
+//! | frame | length bytes | the frame as written to the socket (header & payload) |
+//! | checksum | 4 bytes | FNV-1a (32 bit) over kind, id, length & frame |
+//!
+//! If a codec is configured (see 'JournalConfig::codec'), the frame field holds the encoded frame, so length & checksum refer to the encoded bytes.
+//!
+//! Version 1 has no flags byte, so it does not tell whether the frames are encoded: they are decoded if the reader is given a codec.
+//! Both versions are read. When a connection opens a journal of version 1 (or written with a different codec setting) with pending frames,
+//! they are migrated to the current version: the file is replaced by one holding these frames only.
+//! A newer version is reported as 'UnsupportedFormatVersion'.
+//!
+//! A record which is truncated or whose checksum does not match ends the journal: it and all following bytes are ignored,
+//! since a crash while appending leaves a partial record at the end of the file.
+//! # Example
+//! ```no_run
+//! # use rust_tcp_ipc::*;
+//! # use rust_tcp_ipc::doc_example::*;
+//! # run(|| {
+//! # let codec: Box<dyn StorageCodec> = Box::new(PassThrough);
+//! // on startup, before connecting
+//! let pending = rust_tcp_ipc::journal::recover_with("outgoing.journal", &*codec)?;
+//! for frame in &pending {
+//!     if let Some((command, payload)) = frame.message::<ProtocolExample>() {
+//!         println!("{:?} may not have been sent", command);
+//!     }
+//! }
+//! let ids: Vec<_> = pending.iter().map(|frame| frame.id).collect();
+//! rust_tcp_ipc::journal::discard("outgoing.journal", &ids)?;
+//! # Ok(())
+//! # });
+//! ```
+use super::file_format::{codec_required, UnsupportedFormatVersion};
+use super::protocol_buffer::{Message, Protocol, ProtocolBuffer};
+use super::storage_codec::{PassThrough, SharedStorageCodec, StorageCodec};
+use std::convert::TryFrom;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 3] = b"TIJ";
+// the version written, as ASCII digit after the magic
+const VERSION: u32 = 2;
+const SUPPORTED_VERSIONS: &[u32] = &[1, 2];
+// set in the flags byte (since version 2) if the frames are encoded by a codec
+const ENCODED: u8 = 1;
+const APPENDED: u8 = 1;
+const COMPLETED: u8 = 2;
+// kind, id & length
+const RECORD_HEADER: usize = 1 + 8 + 4;
+const CHECKSUM: usize = 4;
+
+/// This configures the journal of a connection, see 'TcpIpcConfig::journal'.
+#[derive(Clone)]
+pub struct JournalConfig {
+    /// The path of the journal file. It is created if it does not exist.
+    pub path: PathBuf,
+    /// If true, every record is synced to the storage device before writing continues.
+    /// Without this, a crash of the process is survived, but a crash of the operating system may lose records.
+    pub fsync: bool,
+    /// If given, every frame is encoded before it is written to the file (for example, encrypted), see 'StorageCodec'.
+    /// The journal has to be recovered with the same codec (see 'recover_with').
+    pub codec: Option<SharedStorageCodec>,
+}
+impl std::fmt::Debug for JournalConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("JournalConfig")
+            .field("path", &self.path)
+            .field("fsync", &self.fsync)
+            .field("codec", &self.codec.as_ref().map(|_| "<codec>"))
+            .finish()
+    }
+}
+impl PartialEq for JournalConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.fsync == other.fsync
+            && match (&self.codec, &other.codec) {
+                (Some(codec), Some(other_codec)) => std::sync::Arc::ptr_eq(codec, other_codec),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+/// The id of a journaled frame, see 'TcpIpc::write_message_journaled'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JournalId(pub u64);
+
+/// A journaled frame which was not marked as completed, see 'recover'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingFrame {
+    /// The journal id of the frame.
+    pub id: JournalId,
+    /// The frame as it was to be written to the socket (header & payload).
+    pub frame: Vec<u8>,
+}
+impl PendingFrame {
+    /// Parses the frame back into its command & payload. Returns None if the frame does not parse with the given protocol.
+    pub fn message<P: Protocol>(&self) -> Option<Message<P>> {
+        ProtocolBuffer::<P>::new()
+            .try_process_new_buffer(&self.frame)
+            .ok()
+            .flatten()
+    }
+}
+
+/// Returns the frames of the journal which were not marked as completed, in the order they were appended.
+/// A missing file has no pending frames. A truncated or corrupted end of the file is ignored (see the format above).
+/// This reads a journal written without codec, see 'recover_with' otherwise.
+pub fn recover<T: AsRef<Path>>(path: T) -> std::io::Result<Vec<PendingFrame>> {
+    recover_frames(path, None)
+}
+
+/// Like 'recover', for a journal written with the given codec (see 'JournalConfig::codec').
+/// If a pending frame fails to decode, an error of kind 'InvalidData' is returned, with the 'CodecError' as inner error.
+pub fn recover_with<T: AsRef<Path>>(
+    path: T,
+    codec: &dyn StorageCodec,
+) -> std::io::Result<Vec<PendingFrame>> {
+    recover_frames(path, Some(codec))
+}
+
+fn recover_frames<T: AsRef<Path>>(
+    path: T,
+    codec: Option<&dyn StorageCodec>,
+) -> std::io::Result<Vec<PendingFrame>> {
+    match std::fs::read(path) {
+        Ok(content) => decode(scan(&content)?, codec),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Marks the given frames as completed, so they are not reported by 'recover' anymore.
+/// This is typically called after the pending frames were handled, before a connection opens the journal again.
+pub fn discard<T: AsRef<Path>>(path: T, ids: &[JournalId]) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+    let mut records = Vec::new();
+    for id in ids {
+        records.extend_from_slice(&record(COMPLETED, *id, &[]));
+    }
+    file.write_all(&records)?;
+    file.sync_data()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+    version: u32,
+    // None for version 1, which does not tell whether the frames are encoded
+    encoded: Option<bool>,
+    length: usize,
+}
+
+// the header of the current version
+fn header(encoded: bool) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(b'0' + VERSION as u8);
+    header.push(if encoded { ENCODED } else { 0 });
+    header
+}
+
+// returns None for an empty or truncated header, which is a crash right after creating the file
+fn parse_header(content: &[u8]) -> std::io::Result<Option<Header>> {
+    if !content.starts_with(MAGIC) {
+        if MAGIC.starts_with(content) {
+            return Ok(None);
+        }
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the file is not a journal",
+        ));
+    }
+    let version = match content.get(MAGIC.len()) {
+        Some(version) => u32::from(version.wrapping_sub(b'0')),
+        None => return Ok(None),
+    };
+    match version {
+        1 => Ok(Some(Header {
+            version,
+            encoded: None,
+            length: MAGIC.len() + 1,
+        })),
+        2 => Ok(content.get(MAGIC.len() + 1).map(|flags| Header {
+            version,
+            encoded: Some(flags & ENCODED != 0),
+            length: MAGIC.len() + 2,
+        })),
+        found => Err(UnsupportedFormatVersion {
+            found,
+            supported: SUPPORTED_VERSIONS,
+        }
+        .into_io_error()),
+    }
+}
+
+struct Scan {
+    // None if the file has no complete header
+    header: Option<Header>,
+    pending: Vec<PendingFrame>,
+    // the number of bytes up to the end of the last valid record
+    valid_length: usize,
+    next_id: u64,
+}
+
+fn scan(content: &[u8]) -> std::io::Result<Scan> {
+    let header = parse_header(content)?;
+    let mut scan = Scan {
+        header,
+        pending: Vec::new(),
+        valid_length: 0,
+        next_id: 0,
+    };
+    let mut position = match header {
+        Some(header) => header.length,
+        None => return Ok(scan),
+    };
+    scan.valid_length = position;
+    while let Some(header) = content.get(position..position + RECORD_HEADER) {
+        let kind = header[0];
+        let mut id = [0; 8];
+        id.copy_from_slice(&header[1..9]);
+        let id = u64::from_be_bytes(id);
+        let mut length = [0; 4];
+        length.copy_from_slice(&header[9..13]);
+        let length = u32::from_be_bytes(length) as usize;
+        let end = position + RECORD_HEADER + length;
+        let (frame, checksum) = match (
+            content.get(position + RECORD_HEADER..end),
+            content.get(end..end + CHECKSUM),
+        ) {
+            (Some(frame), Some(checksum)) => (frame, checksum),
+            _ => break,
+        };
+        if fnv1a(&content[position..end]).to_be_bytes() != checksum {
+            break;
+        }
+        match kind {
+            APPENDED => scan.pending.push(PendingFrame {
+                id: JournalId(id),
+                frame: frame.to_vec(),
+            }),
+            COMPLETED => scan.pending.retain(|pending| pending.id != JournalId(id)),
+            _ => break,
+        }
+        scan.next_id = scan.next_id.max(id.saturating_add(1));
+        position = end + CHECKSUM;
+        scan.valid_length = position;
+    }
+    Ok(scan)
+}
+
+// restores the pending frames, given the codec of the reader (if any)
+fn decode(scan: Scan, codec: Option<&dyn StorageCodec>) -> std::io::Result<Vec<PendingFrame>> {
+    let codec = match scan.header.and_then(|header| header.encoded) {
+        // the frames are stored as given, whatever the codec of the reader
+        Some(false) => None,
+        Some(true) if codec.is_none() && !scan.pending.is_empty() => return Err(codec_required()),
+        // version 1 does not tell, so the frames are decoded if the reader has a codec
+        _ => codec,
+    };
+    let codec = match codec {
+        Some(codec) => codec,
+        None => return Ok(scan.pending),
+    };
+    scan.pending
+        .into_iter()
+        .map(|pending| {
+            Ok(PendingFrame {
+                id: pending.id,
+                frame: codec
+                    .decode(&pending.frame)
+                    .map_err(|err| err.into_io_error())?,
+            })
+        })
+        .collect()
+}
+
+fn record(kind: u8, id: JournalId, frame: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER + frame.len() + CHECKSUM);
+    record.push(kind);
+    record.extend_from_slice(&id.0.to_be_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+    record.extend_from_slice(frame);
+    let checksum = fnv1a(&record);
+    record.extend_from_slice(&checksum.to_be_bytes());
+    record
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// The open journal of a connection.
+pub(crate) struct Journal {
+    file: std::fs::File,
+    fsync: bool,
+    next_id: u64,
+    codec: SharedStorageCodec,
+}
+impl Journal {
+    /// Opens (or creates) the journal. A corrupted end is cut off, and a journal without pending frames is emptied.
+    /// Pending frames of an older version (or written with a different codec setting) are migrated to the current version.
+    pub fn open(config: &JournalConfig) -> std::io::Result<Self> {
+        let mut file = Self::open_file(&config.path)?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        let scan = scan(&content)?;
+        let encoded = config.codec.is_some();
+        let codec = config
+            .codec
+            .clone()
+            .unwrap_or_else(|| std::sync::Arc::new(PassThrough));
+        let current = scan
+            .header
+            .is_some_and(|header| header.version == VERSION && header.encoded == Some(encoded));
+        let next_id = scan.next_id;
+        let valid_length = scan.valid_length;
+        let pending = decode(scan, config.codec.as_deref())?;
+        if pending.is_empty() {
+            file.set_len(0)?;
+            file.seek(std::io::SeekFrom::Start(0))?;
+            file.write_all(&header(encoded))?;
+        } else if current {
+            file.set_len(valid_length as u64)?;
+            file.seek(std::io::SeekFrom::Start(valid_length as u64))?;
+        } else {
+            // the file is replaced at once, so a crash during the migration leaves either the old or the new file
+            let mut migrated = header(encoded);
+            for pending in &pending {
+                migrated.extend_from_slice(&record(
+                    APPENDED,
+                    pending.id,
+                    &codec.encode(&pending.frame),
+                ));
+            }
+            let mut temporary = config.path.clone().into_os_string();
+            temporary.push(".migrating");
+            let temporary = PathBuf::from(temporary);
+            let mut migrated_file = std::fs::File::create(&temporary)?;
+            migrated_file.write_all(&migrated)?;
+            migrated_file.sync_data()?;
+            drop(migrated_file);
+            drop(file);
+            std::fs::rename(&temporary, &config.path)?;
+            file = Self::open_file(&config.path)?;
+            file.seek(std::io::SeekFrom::End(0))?;
+        }
+        if config.fsync {
+            file.sync_data()?;
+        }
+        Ok(Self {
+            file,
+            fsync: config.fsync,
+            next_id,
+            codec,
+        })
+    }
+    fn open_file(path: &Path) -> std::io::Result<std::fs::File> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    }
+    /// Appends a frame & returns its id. Frames longer than 4 GiB (after encoding) cannot be journaled.
+    pub fn append(&mut self, frame: &[u8]) -> std::io::Result<JournalId> {
+        let frame = self.codec.encode(frame);
+        if u32::try_from(frame.len()).is_err() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the frame is too long for the journal",
+            ));
+        }
+        let id = JournalId(self.next_id);
+        self.write(&record(APPENDED, id, &frame))?;
+        self.next_id += 1;
+        Ok(id)
+    }
+    /// Marks a frame as completed.
+    pub fn complete(&mut self, id: JournalId) -> std::io::Result<()> {
+        self.write(&record(COMPLETED, id, &[]))
+    }
+    fn write(&mut self, record: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(record)?;
+        if self.fsync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+impl std::fmt::Debug for Journal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Journal")
+            .field("fsync", &self.fsync)
+            .field("next_id", &self.next_id)
+            .finish()
+    }
+}
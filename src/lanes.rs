@@ -0,0 +1,291 @@
+use super::memory_budget::{self, SharedMemoryBudget};
+use super::protocol_buffer::{Message, Protocol};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// The lane a received message is routed to, see 'TcpIpc::split_by'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lane {
+    /// Responses to requests, see 'RpcHandle'.
+    Rpc,
+    /// Messages the peer pushes unsolicited (like telemetry), see 'StreamHandle'.
+    Stream,
+}
+
+/// This determines what happens to a message routed to a full lane (see 'LaneConfig').
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LaneOverflow {
+    /// The oldest queued message is dropped, to make room for the new one.
+    DropOldest,
+    /// The new message is dropped.
+    DropNewest,
+}
+
+/// This bundles the settings of a lane, see 'TcpIpc::split_by'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneConfig {
+    /// The maximal number of queued messages.
+    pub capacity: usize,
+    /// What happens to a message routed to the lane while it is full.
+    pub overflow: LaneOverflow,
+}
+
+struct LaneQueue<P: Protocol> {
+    messages: VecDeque<Message<P>>,
+    dropped: u64,
+    closed: bool,
+}
+/// A bounded queue of messages, filled by the read thread & emptied by a handle.
+/// The payloads of queued messages are reserved in the memory budget of the connection (if any) by the read thread, & released once they leave the lane.
+struct LaneState<P: Protocol> {
+    queue: Mutex<LaneQueue<P>>,
+    arrived: Condvar,
+    config: LaneConfig,
+    memory_budget: Option<SharedMemoryBudget>,
+}
+impl<P: Protocol> Drop for LaneState<P> {
+    fn drop(&mut self) {
+        let queue = self.queue.get_mut().unwrap_or_else(|e| e.into_inner());
+        for (_, payload) in &queue.messages {
+            memory_budget::release(&self.memory_budget, payload.len());
+        }
+    }
+}
+impl<P: Protocol> LaneState<P> {
+    fn new(config: LaneConfig, memory_budget: Option<SharedMemoryBudget>) -> Self {
+        Self {
+            queue: Mutex::new(LaneQueue {
+                messages: VecDeque::with_capacity(config.capacity),
+                dropped: 0,
+                closed: false,
+            }),
+            arrived: Condvar::new(),
+            config,
+            memory_budget,
+        }
+    }
+    fn lock(&self) -> MutexGuard<'_, LaneQueue<P>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Queues the message. Returns false if it was dropped, since the lane is full.
+    fn push(&self, message: Message<P>) -> bool {
+        let mut queue = self.lock();
+        if queue.messages.len() >= self.config.capacity {
+            queue.dropped += 1;
+            match self.config.overflow {
+                LaneOverflow::DropNewest => return false,
+                LaneOverflow::DropOldest => {
+                    if let Some((_, payload)) = queue.messages.pop_front() {
+                        self.release(&payload);
+                    }
+                }
+            }
+        }
+        if self.config.capacity == 0 {
+            return false;
+        }
+        queue.messages.push_back(message);
+        drop(queue);
+        self.arrived.notify_all();
+        true
+    }
+    fn release(&self, payload: &[u8]) {
+        memory_budget::release(&self.memory_budget, payload.len());
+    }
+    fn close(&self) {
+        self.lock().closed = true;
+        self.arrived.notify_all();
+    }
+    /// Waits for the first message matching the predicate & takes it out of the queue.
+    /// Returns None if none arrived within the given time, or the lane was closed.
+    fn take_first<F: Fn(&Message<P>) -> bool>(
+        &self,
+        predicate: F,
+        timeout: std::time::Duration,
+    ) -> Option<Message<P>> {
+        let start = std::time::Instant::now();
+        let mut queue = self.lock();
+        loop {
+            if let Some(position) = queue.messages.iter().position(&predicate) {
+                let message = queue.messages.remove(position);
+                if let Some((_, payload)) = &message {
+                    self.release(payload);
+                }
+                return message;
+            }
+            let remaining = timeout.checked_sub(start.elapsed())?;
+            if queue.closed {
+                return None;
+            }
+            queue = self
+                .arrived
+                .wait_timeout(queue, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+/// The classifier of a split, see 'TcpIpc::split_by'.
+pub type LaneClassifier<P> = Box<dyn Fn(&<P as Protocol>::Commands) -> Lane + Send>;
+/// The routing of received messages to the lanes, shared by the read thread & the main thread.
+pub struct LaneRouter<P: Protocol> {
+    classifier: LaneClassifier<P>,
+    rpc: Arc<LaneState<P>>,
+    stream: Arc<LaneState<P>>,
+}
+impl<P: Protocol> Drop for LaneRouter<P> {
+    // the handles of a replaced split stop waiting
+    fn drop(&mut self) {
+        self.rpc.close();
+        self.stream.close();
+    }
+}
+pub type SharedLaneRouter<P> = Arc<Mutex<Option<LaneRouter<P>>>>;
+pub fn new_lane_router<P: Protocol>() -> SharedLaneRouter<P> {
+    Arc::new(Mutex::new(None))
+}
+fn lock_router<P: Protocol>(router: &SharedLaneRouter<P>) -> MutexGuard<'_, Option<LaneRouter<P>>> {
+    router.lock().unwrap_or_else(|e| e.into_inner())
+}
+/// Sets up the lanes & returns their handles. A previous split is torn down, so its handles are closed.
+pub fn split<P: Protocol>(
+    router: &SharedLaneRouter<P>,
+    classifier: LaneClassifier<P>,
+    rpc: LaneConfig,
+    stream: LaneConfig,
+    memory_budget: Option<SharedMemoryBudget>,
+) -> (RpcHandle<P>, StreamHandle<P>) {
+    let rpc = Arc::new(LaneState::new(rpc, memory_budget.clone()));
+    let stream = Arc::new(LaneState::new(stream, memory_budget));
+    *lock_router(router) = Some(LaneRouter {
+        classifier,
+        rpc: rpc.clone(),
+        stream: stream.clone(),
+    });
+    (RpcHandle { lane: rpc }, StreamHandle { lane: stream })
+}
+/// Routes a received message to its lane, if the connection is split.
+/// Returns Ok(false) if the lane was full & the message was dropped, or the message itself if the connection is not split.
+pub fn route<P: Protocol>(
+    router: &SharedLaneRouter<P>,
+    message: Message<P>,
+) -> Result<bool, Message<P>> {
+    match &*lock_router(router) {
+        Some(router) => Ok(match (router.classifier)(&message.0) {
+            Lane::Rpc => router.rpc.push(message),
+            Lane::Stream => router.stream.push(message),
+        }),
+        None => Err(message),
+    }
+}
+/// Closes the lanes, since no more messages arrive.
+pub fn close_lanes<P: Protocol>(router: &SharedLaneRouter<P>) {
+    if let Some(router) = &*lock_router(router) {
+        router.rpc.close();
+        router.stream.close();
+    }
+}
+
+/// The handle of the lane of responses, see 'TcpIpc::split_by'.
+/// It can be moved to another thread than the connection & the stream handle.
+pub struct RpcHandle<P: Protocol> {
+    lane: Arc<LaneState<P>>,
+}
+impl<P: Protocol> std::fmt::Debug for RpcHandle<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("RpcHandle")
+            .field("queued", &self.queued())
+            .field("dropped", &self.dropped())
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+impl<P: Protocol> RpcHandle<P> {
+    /// Waits for the first queued message with the given command & returns its payload. Other messages stay queued in order.
+    /// Returns None if no such message arrived within the given time, or the lane was closed (see 'is_closed').
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// # let (rpc, _stream) = client.split_by(
+    /// #     |_| Lane::Rpc,
+    /// #     LaneConfig { capacity: 16, overflow: LaneOverflow::DropNewest },
+    /// #     LaneConfig { capacity: 16, overflow: LaneOverflow::DropNewest },
+    /// # );
+    /// client.write_message(CommandsExample::GetStatus, &[])?;
+    /// let status = rpc.await_command(CommandsExample::Status, std::time::Duration::from_millis(100));
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn await_command(
+        &self,
+        command: P::Commands,
+        timeout: std::time::Duration,
+    ) -> Option<Vec<u8>> {
+        self.lane
+            .take_first(|message| message.0 == command, timeout)
+            .map(|(_, payload)| payload)
+    }
+    /// Waits for the next message of the lane. Returns None if none arrived within the given time, or the lane was closed.
+    pub fn await_message(&self, timeout: std::time::Duration) -> Option<Message<P>> {
+        self.lane.take_first(|_| true, timeout)
+    }
+    /// Returns the number of queued messages.
+    pub fn queued(&self) -> usize {
+        self.lane.lock().messages.len()
+    }
+    /// Returns the number of messages dropped since the lane was full (see 'LaneOverflow').
+    pub fn dropped(&self) -> u64 {
+        self.lane.lock().dropped
+    }
+    /// Checks if no more messages arrive, since the read thread finished or the connection was split again.
+    /// Messages queued before can still be taken.
+    pub fn is_closed(&self) -> bool {
+        self.lane.lock().closed
+    }
+}
+
+/// The handle of the lane of unsolicited messages, see 'TcpIpc::split_by'.
+/// It can be moved to another thread than the connection & the rpc handle.
+pub struct StreamHandle<P: Protocol> {
+    lane: Arc<LaneState<P>>,
+}
+impl<P: Protocol> std::fmt::Debug for StreamHandle<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("StreamHandle")
+            .field("queued", &self.queued())
+            .field("dropped", &self.dropped())
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+impl<P: Protocol> StreamHandle<P> {
+    /// Takes all queued messages (oldest first), without waiting.
+    pub fn messages(&self) -> Vec<Message<P>> {
+        let messages: Vec<_> = self.lane.lock().messages.drain(..).collect();
+        for (_, payload) in &messages {
+            self.lane.release(payload);
+        }
+        messages
+    }
+    /// Waits for the next message of the lane. Returns None if none arrived within the given time, or the lane was closed.
+    pub fn await_message(&self, timeout: std::time::Duration) -> Option<Message<P>> {
+        self.lane.take_first(|_| true, timeout)
+    }
+    /// Returns the number of queued messages.
+    pub fn queued(&self) -> usize {
+        self.lane.lock().messages.len()
+    }
+    /// Returns the number of messages dropped since the lane was full (see 'LaneOverflow').
+    pub fn dropped(&self) -> u64 {
+        self.lane.lock().dropped
+    }
+    /// Checks if no more messages arrive, since the read thread finished or the connection was split again.
+    /// Messages queued before can still be taken.
+    pub fn is_closed(&self) -> bool {
+        self.lane.lock().closed
+    }
+}
@@ -1,4 +1,5 @@
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 //! This is a crate for Interprocess Communication via TCP.
 //!
 //! It allows for easy, asynchronous sending and receiving messages/commands.
@@ -12,8 +13,175 @@
 //! Then the next length-many bytes which are received are the payload of the message.
 //! Further received bytes form the next message.
 //!
-//! An example is given in the Examples.
+//! An example is given in the Examples. For the common layout of a length followed by a command id, `protocols::LengthPrefixedProtocol` is ready to use.
+//!
+//! # Threads
+//! A `TcpIpc` can be moved to another thread (it is `Send`), but not shared between threads, since all its operations take `&mut self`.
+//! To use a connection from several threads, put it behind a `Mutex`.
+//! The handles `DeliveryHandle`, `PeriodicHandle`, `RpcHandle`, `StreamHandle` and `ConnectionGroup`, as well as `TcpIpcConfig`, are `Send + Sync`.
+//! For this, the protocol's commands and busy states have to be `Send + Sync`, which is required by the `Protocol` trait.
+//! To drive a connection from an own event loop without any thread of this crate, use `TcpIpcInline`.
+//! Where no thread may be spawned and no event loop is available, `TcpIpcCooperative` does the reading in periodic, time-bounded ticks.
+//! A `TcpIpcServer`, which serves any number of clients with one connection (and read thread) per client, is `Send` as well.
+//!
+//! # Immediate responses
+//! Messages answered via the immediate route (see `Protocol::message_is_answered_via_immediate_route`) are answered by the read thread
+//! and not delivered to the consumer. All frames of a connection, written by the consumer or by the read thread, pass one outgoing queue,
+//! so they never interleave on the wire. This holds as well if both peers answer each other via the immediate route, with queries crossing:
+//! - Every answered message gets exactly one answer, in the order the messages were received.
+//!   Only if the memory budget is used up (see `TcpIpcConfig::memory_budget`), answers are dropped (& logged).
+//! - The read thread never waits for a write: answers which cannot be written right away are queued & written by later iterations.
+//!   This includes answers waiting for a token of the rate limit (see 'RateLimit::include_immediate_responses').
+//!   So it keeps reading while the consumer is blocked in `write_message`, and two such peers do not deadlock on full socket buffers.
+//! - A write of the consumer which cannot complete is retried as configured (see `TcpIpcConfig::write_retry`) and then fails with `MessageSendFailed`.
+//!   Without retry, the unwritten rest is queued & written by the read thread, like answers.
+//!
+//! # Cargo features
+//! - `engine-mio` (default): the sockets are provided by mio.
+//! - `engine-std`: the sockets are provided by the standard library, so mio is not needed. Use it with `default-features = false`.
+//!   The socket options which the standard library cannot set (like the buffer sizes) are set via socket2.
+//!   At least one engine has to be enabled. If both are, mio is used. The API is identical for both engines.
+//!   The features `registry`, `tokio` & `bench` enable `engine-std`, so they build on their own. Together with `engine-mio`, mio is used.
+//! - `std` (enabled by both engines): without it, the crate is `no_std` (requiring `alloc`) and only provides the parsing core
+//!   (`Protocol`, `ProtocolBuffer`, `ImmediateResponseTable`, the module `protocols` and their types), for example to parse messages on an embedded target.
+//! - `registry`: provides the function `registry`, listing all live connections of the process (for example for an admin endpoint).
+//! - `tokio`: provides `AsyncTcpIpc`, a connection with `async` operations for applications running a tokio runtime.
+//!   The sync API stays available, so the connection is established by the engine (see above).
+//! - `serde`: implements `serde::Serialize` for the diagnostic types (like `Diagnostics` and `ConnectionStats`).
+//! - `bench`: provides the module `bench` with a synthetic load generator (& an echo responder for the peer side),
+//!   to compare protocol implementations & config settings under load.
+//! - `test-util`: provides the module `testing` with assertion helpers for tests (like `expect_message`),
+//!   and the module `conformance` to check a `Protocol` implementation (the recommended first test for a new protocol).
+//!
+//! # Migrating from 0.3
+//! `TcpIpcConfig` is generic over the protocol (`TcpIpcConfig<P>`), since some settings name commands or busy states
+//! (like `on_immediate_construct_failure`). It holds hooks & names as well, so it is `Clone`, but no longer `Copy`.
+//! - Write the protocol as part of the type (`TcpIpcConfig::<ProtocolExample> { .. }`), or let it be inferred from `TcpIpc::<ProtocolExample>::client`.
+//! - Fill the settings which are not given with `..TcpIpcConfig::default()`, which keeps working when settings are added.
+//! - Pass `config.clone()` where the config was copied before, for example when connecting several times with one config.
+extern crate alloc;
+
+#[cfg(feature = "tokio")]
+mod async_tcp_ipc;
+#[cfg(all(feature = "std", feature = "bench"))]
+pub mod bench;
+#[cfg(feature = "std")]
+mod bridge;
+#[cfg(feature = "std")]
+mod build_info;
+#[cfg(all(feature = "std", feature = "test-util"))]
+pub mod conformance;
+#[cfg(feature = "std")]
+mod connection_group;
+#[cfg(feature = "std")]
+mod cooperative;
+#[cfg(feature = "std")]
+mod deadline;
+#[cfg(feature = "std")]
+mod dedup;
+#[cfg(feature = "std")]
+mod delivery;
+#[cfg(feature = "std")]
+mod diagnostics;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod doc_example;
+#[cfg(feature = "std")]
+mod engine;
+#[cfg(feature = "std")]
+mod file_format;
+#[cfg(feature = "std")]
+mod inline;
+#[cfg(feature = "std")]
+pub mod journal;
+#[cfg(feature = "std")]
+mod lanes;
+#[cfg(feature = "std")]
+mod listener;
+#[cfg(feature = "std")]
+mod memory_budget;
+#[cfg(feature = "std")]
+mod os_errors;
+#[cfg(feature = "std")]
+mod outgoing_queue;
+#[cfg(feature = "std")]
+mod pair;
+#[cfg(feature = "std")]
+pub mod prelude;
+#[cfg(feature = "std")]
+mod probe;
 mod protocol;
 mod protocol_buffer;
+pub mod protocols;
+#[cfg(feature = "std")]
+mod rate_limit;
+#[cfg(feature = "std")]
+mod read_errors;
+#[cfg(feature = "std")]
+mod read_thread;
+#[cfg(feature = "std")]
+mod reconnect;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(feature = "std")]
+mod reliability;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+mod response_budget;
+mod response_table;
+#[cfg(feature = "std")]
+mod schedule;
+#[cfg(feature = "std")]
+mod server;
+#[cfg(feature = "std")]
+mod session;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod storage_codec;
+#[cfg(feature = "std")]
+mod tap;
+#[cfg(feature = "std")]
 mod tcp_ipc;
+#[cfg(feature = "std")]
+mod trace;
+#[cfg(feature = "std")]
+mod transaction;
+#[cfg(feature = "std")]
+mod unacked;
+#[cfg(feature = "std")]
+mod waker;
+#[cfg(feature = "std")]
+mod write_pressure;
+#[cfg(not(feature = "std"))]
+pub use self::protocol_buffer::{
+    Message, ParseHeaderError, ParserState, PeerInfo, Protocol, ProtocolBuffer, ProtocolViolation,
+    Reservation, TruncatedFrame, DEFAULT_MAX_HEADER_SIZE,
+};
+#[cfg(not(feature = "std"))]
+pub use self::response_table::{ImmediateResponseTable, Matcher, ResponseTemplate};
+#[cfg(feature = "std")]
 pub use self::tcp_ipc::*;
+#[cfg(all(feature = "std", feature = "test-util"))]
+pub mod testing;
+
+// compile-time check of the thread-safety guarantees documented above
+#[cfg(feature = "std")]
+#[allow(dead_code)]
+fn assert_thread_safety<P: Protocol>() {
+    fn send<T: Send>() {}
+    fn send_sync<T: Send + Sync>() {}
+    send::<TcpIpc<P>>();
+    send::<TcpIpcCooperative<P>>();
+    send::<TcpIpcServer<P>>();
+    #[cfg(feature = "tokio")]
+    send::<AsyncTcpIpc<P>>();
+    send::<ReadThreadErrors<P>>();
+    send_sync::<TcpIpcConfig<P>>();
+    send_sync::<DeliveryHandle<P>>();
+    send_sync::<PeriodicHandle>();
+    send_sync::<RpcHandle<P>>();
+    send_sync::<StreamHandle<P>>();
+    send_sync::<ConnectionGroup<P>>();
+}
@@ -0,0 +1,92 @@
+use super::engine::{self, TcpListener, TcpStream};
+use super::protocol_buffer::Protocol;
+use super::tcp_ipc::{ConnectErrors, TcpIpc, TcpIpcConfig};
+use log::*;
+use std::marker::PhantomData;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+/// The time 'accept' sleeps between checking for a client.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// A bound listener, waiting for a client to connect (see 'TcpIpc::listen').
+///
+/// Binding & accepting are separate, so the bound address is known before a client connects,
+/// for example the port the operating system chose for port 0, which a client in a test connects to.
+/// The listener stays bound until it is dropped. To serve several clients at once, use 'TcpIpcServer'.
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # run(|| {
+/// # let config = config();
+/// # let client_config = config.clone();
+/// let listener = TcpIpc::<ProtocolExample>::listen("127.0.0.1:0")?;
+/// let address = listener.local_addr()?;
+/// let client = std::thread::spawn(move || TcpIpc::<ProtocolExample>::client(address, client_config, None));
+/// let mut server = listener.accept(config)?;
+/// # Ok(())
+/// # });
+/// ```
+pub struct TcpIpcListener<P: Protocol> {
+    listener: TcpListener,
+    _protocol: PhantomData<fn() -> P>,
+}
+impl<P: Protocol> std::fmt::Debug for TcpIpcListener<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TcpIpcListener")
+            .field("local_addr", &self.local_addr().ok())
+            .finish()
+    }
+}
+impl<P: Protocol> TcpIpcListener<P> {
+    /// Binds to the first of the given addresses which can be bound. If none can, the error of the last one is returned.
+    pub(crate) fn bind<T: ToSocketAddrs>(socket_addresses: T) -> Result<Self, ConnectErrors> {
+        let mut error = ConnectErrors::SocketListIsEmpty;
+        for socket_address in super::tcp_ipc::resolve(socket_addresses)? {
+            debug!("trying to listen on {:?}", socket_address);
+            match engine::bind(&socket_address) {
+                Ok(listener) => {
+                    info!("listening on {:?}", socket_address);
+                    return Ok(Self {
+                        listener,
+                        _protocol: PhantomData,
+                    });
+                }
+                Err(err) => error = ConnectErrors::BindError(err),
+            }
+        }
+        Err(error)
+    }
+    /// Returns the address the listener is bound to, for example to find out the port chosen for port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    /// Waits for a client to connect & sets up the connection like 'TcpIpc::server'.
+    /// So this returns only once the connection is ready (see 'TcpIpcConfig::ready_when').
+    ///
+    /// The listener stays bound, so further clients can be accepted by calling this again.
+    pub fn accept(&self, config: TcpIpcConfig<P>) -> Result<TcpIpc<P>, ConnectErrors> {
+        config.check_protocol()?;
+        let stream = self.accept_stream()?;
+        TcpIpc::start_read_thread(stream, config)
+    }
+    /// Waits for a client to connect, without setting up the connection.
+    pub(crate) fn accept_stream(&self) -> Result<TcpStream, ConnectErrors> {
+        loop {
+            match engine::accept(&self.listener) {
+                Ok((stream, socket_address)) => {
+                    info!("connected to {:?}", socket_address);
+                    return Ok(stream);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(ACCEPT_POLL_INTERVAL)
+                }
+                Err(err) => {
+                    info!("Received error: {:?}", err);
+                    return Err(ConnectErrors::ConnectionError(err));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The memory usage of a connection, see 'TcpIpcConfig::memory_budget' & 'ConnectionStats::memory'.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MemoryUsage {
+    /// The configured budget in bytes.
+    pub budget: usize,
+    /// The bytes currently held by the buffers & queues of the connection.
+    pub used: usize,
+    /// The maximal number of bytes held at once.
+    pub peak: usize,
+    /// The number of times the budget was exceeded, so a frame was dropped or refused, or a write failed.
+    /// Reading pausing until the budget allows it is not counted.
+    pub refused: u64,
+}
+
+/// The bytes held by the buffers & queues of a connection, shared by the read thread & the main thread.
+/// Every buffer reserves bytes before it holds them, so the budget is never exceeded.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    budget: usize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    refused: AtomicU64,
+}
+impl MemoryBudget {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            refused: AtomicU64::new(0),
+        }
+    }
+    /// Reserves the given number of bytes. Returns false (& reserves nothing) if this would exceed the budget.
+    pub fn try_reserve(&self, bytes: usize) -> bool {
+        let mut used = self.used.load(Ordering::SeqCst);
+        loop {
+            let reserved = match used.checked_add(bytes) {
+                Some(reserved) if reserved <= self.budget => reserved,
+                _ => return false,
+            };
+            match self.used.compare_exchange_weak(
+                used,
+                reserved,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    self.peak.fetch_max(reserved, Ordering::Relaxed);
+                    return true;
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+    /// Counts a frame dropped or refused (or a failed write), since the budget was exceeded.
+    pub fn refused(&self) {
+        self.refused.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Releases bytes reserved before.
+    pub fn release(&self, bytes: usize) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+    /// Returns the number of bytes which can currently be reserved.
+    pub fn available(&self) -> usize {
+        self.budget.saturating_sub(self.used.load(Ordering::SeqCst))
+    }
+    pub fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            budget: self.budget,
+            used: self.used.load(Ordering::SeqCst),
+            peak: self.peak.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+        }
+    }
+}
+pub type SharedMemoryBudget = Arc<MemoryBudget>;
+
+/// Reserves bytes, if a budget is configured. Returns false if the budget would be exceeded, which is counted as refused.
+pub fn try_reserve(budget: &Option<SharedMemoryBudget>, bytes: usize) -> bool {
+    budget.as_ref().is_none_or(|budget| {
+        let reserved = budget.try_reserve(bytes);
+        if !reserved {
+            budget.refused();
+        }
+        reserved
+    })
+}
+/// Releases bytes, if a budget is configured.
+pub fn release(budget: &Option<SharedMemoryBudget>, bytes: usize) {
+    if let Some(budget) = budget {
+        budget.release(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_are_limited_by_the_budget() {
+        let budget = MemoryBudget::new(100);
+        assert!(budget.try_reserve(60));
+        assert!(!budget.try_reserve(41));
+        assert!(budget.try_reserve(40));
+        assert_eq!(budget.available(), 0);
+        budget.release(70);
+        assert_eq!(budget.available(), 70);
+        assert_eq!(
+            budget.usage(),
+            MemoryUsage {
+                budget: 100,
+                used: 30,
+                peak: 100,
+                refused: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn refusals_are_counted() {
+        let shared = Arc::new(MemoryBudget::new(10));
+        let budget = Some(shared.clone());
+        assert!(try_reserve(&budget, 10));
+        assert!(!try_reserve(&budget, 1));
+        assert!(!try_reserve(&budget, usize::MAX));
+        release(&budget, 10);
+        let usage = shared.usage();
+        assert_eq!((usage.used, usage.refused), (0, 2));
+        // without a budget, everything can be reserved
+        assert!(try_reserve(&None, usize::MAX));
+    }
+
+    #[test]
+    fn concurrent_reservations_never_exceed_the_budget() {
+        let budget = Arc::new(MemoryBudget::new(1000));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                std::thread::spawn(move || {
+                    for bytes in (1..100).cycle().take(10_000) {
+                        if budget.try_reserve(bytes) {
+                            assert!(budget.usage().used <= 1000);
+                            budget.release(bytes);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let usage = budget.usage();
+        assert_eq!(usage.used, 0);
+        assert!(usage.peak <= 1000);
+    }
+}
@@ -0,0 +1,121 @@
+//! The normalization of platform-specific socket errors.
+//!
+//! Windows reports some socket errors by raw codes, which are not always mapped to the same 'std::io::ErrorKind' as on Linux.
+//! So every socket error of the connect loops & the read thread is normalized before it is inspected or returned,
+//! and the same typed error results on all platforms:
+//!
+//! | Windows code | normalized kind | result |
+//! |---|---|---|
+//! | WSAECONNRESET (10054) | `ConnectionReset` | read thread: 'ReadError', the connection state is 'PeerClosed' |
+//! | WSAECONNABORTED (10053) | `ConnectionAborted` | read thread: 'ReadError', the connection state is 'Closed' |
+//! | WSAEWOULDBLOCK (10035) | `WouldBlock` | connect loop: polling continues, read thread: no data available |
+//! | WSAETIMEDOUT (10060) | `TimedOut` | connect loop: 'ConnectionError' (retryable), read thread: no data available |
+//! | ERROR_SEM_TIMEOUT (121) | `TimedOut` | connect loop: 'ConnectionError' (retryable), read thread: no data available |
+//! | WSAECONNREFUSED (10061) | `ConnectionRefused` | connect loop: 'ConnectionError' (retryable) |
+//!
+//! On other platforms, the raw codes have other meanings, so errors are only normalized on Windows.
+//!
+//! Both engines classify the results of a read the same way: a read of zero bytes means that the peer closed the connection
+//! (reported once as 'PeerClosed', then the read thread finishes), 'ConnectionReset' & 'ConnectionAborted' close the connection,
+//! & 'Interrupted' is retried like a read without data.
+use std::io::{Error, ErrorKind};
+
+/// Returns the error kind of a raw Windows error code, if it is a socket error with a platform-independent meaning.
+pub fn windows_error_kind(code: i32) -> Option<ErrorKind> {
+    match code {
+        10054 => Some(ErrorKind::ConnectionReset),
+        10053 => Some(ErrorKind::ConnectionAborted),
+        10035 => Some(ErrorKind::WouldBlock),
+        10060 | 121 => Some(ErrorKind::TimedOut),
+        10061 => Some(ErrorKind::ConnectionRefused),
+        _ => None,
+    }
+}
+
+/// Normalizes a socket error, see the mapping table of this module.
+/// The message of the original error is kept.
+pub fn normalize(error: Error) -> Error {
+    if !cfg!(windows) {
+        return error;
+    }
+    match error.raw_os_error().and_then(windows_error_kind) {
+        Some(kind) if kind != error.kind() => Error::new(kind, error),
+        _ => error,
+    }
+}
+
+/// Checks if a (normalized) read error means that there is no data available yet, i.e. that reading is to be retried later.
+/// Depending on the platform, a read without data reports 'WouldBlock' or 'TimedOut'.
+/// A read interrupted by a signal ('Interrupted') is retried as well, on all platforms.
+pub fn is_no_data(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+    )
+}
+
+/// Checks if a (normalized) error means that the peer closed the connection abruptly.
+pub fn is_closed_by_peer(kind: ErrorKind) -> bool {
+    kind == ErrorKind::ConnectionReset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [(i32, ErrorKind); 6] = [
+        (10054, ErrorKind::ConnectionReset),
+        (10053, ErrorKind::ConnectionAborted),
+        (10035, ErrorKind::WouldBlock),
+        (10060, ErrorKind::TimedOut),
+        (121, ErrorKind::TimedOut),
+        (10061, ErrorKind::ConnectionRefused),
+    ];
+
+    #[test]
+    fn each_windows_code_has_its_kind() {
+        for (code, kind) in CODES {
+            assert_eq!(windows_error_kind(code), Some(kind), "code {}", code);
+        }
+        assert_eq!(windows_error_kind(0), None);
+        assert_eq!(windows_error_kind(10048), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn raw_windows_errors_are_normalized() {
+        for (code, kind) in CODES {
+            let normalized = normalize(Error::from_raw_os_error(code));
+            assert_eq!(normalized.kind(), kind, "code {}", code);
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn other_platforms_keep_their_errors() {
+        for (code, _) in CODES {
+            let error = Error::from_raw_os_error(code);
+            let kind = error.kind();
+            let normalized = normalize(error);
+            assert_eq!(normalized.kind(), kind, "code {}", code);
+            assert_eq!(normalized.raw_os_error(), Some(code));
+        }
+    }
+
+    #[test]
+    fn typed_errors_are_kept() {
+        let normalized = normalize(Error::new(ErrorKind::ConnectionReset, "reset"));
+        assert_eq!(normalized.kind(), ErrorKind::ConnectionReset);
+        assert_eq!(normalized.to_string(), "reset");
+    }
+
+    #[test]
+    fn the_normalized_kinds_are_classified() {
+        assert!(is_no_data(ErrorKind::WouldBlock));
+        assert!(is_no_data(ErrorKind::TimedOut));
+        assert!(is_no_data(ErrorKind::Interrupted));
+        assert!(!is_no_data(ErrorKind::ConnectionReset));
+        assert!(is_closed_by_peer(ErrorKind::ConnectionReset));
+        assert!(!is_closed_by_peer(ErrorKind::ConnectionAborted));
+    }
+}
@@ -0,0 +1,200 @@
+use super::memory_budget::{self, SharedMemoryBudget};
+use super::tcp_ipc::Priority;
+use super::write_pressure::{WatermarkTracker, WritePressure};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+/// The outgoing queue is shared by the read thread & the main thread, which makes it the single point where frames are written.
+/// Every write to the stream happens while the queue is locked, so frames of both threads are never interleaved on the wire.
+pub type SharedOutgoingQueue = Arc<Mutex<OutgoingQueue>>;
+/// Locks the queue. The queue stays usable, even if a thread panicked while holding the lock.
+pub fn lock_outgoing(queue: &SharedOutgoingQueue) -> MutexGuard<'_, OutgoingQueue> {
+    queue.lock().unwrap_or_else(|err| err.into_inner())
+}
+/// Locks the queue, unless another thread holds it (for example, while it is blocked in a write).
+pub fn try_lock_outgoing(queue: &SharedOutgoingQueue) -> Option<MutexGuard<'_, OutgoingQueue>> {
+    match queue.try_lock() {
+        Ok(outgoing) => Some(outgoing),
+        Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// A queue of fully constructed frames waiting to be written to a non-blocking stream.
+/// Frames are written by priority, in order within a priority, and partially written frames are continued from their unwritten tail.
+/// A frame only overtakes queued frames of lower priority which were not started yet.
+/// The unwritten bytes are reserved in the memory budget of the connection (if any).
+#[derive(Debug)]
+pub struct OutgoingQueue {
+    frames: VecDeque<(Priority, Vec<u8>)>,
+    written: usize,
+    // set if the frame in front is the unwritten tail of a frame, whose head was written directly to the stream
+    continued: bool,
+    // the unwritten bytes of all queued frames
+    queued_bytes: usize,
+    pending: Arc<AtomicUsize>,
+    watermarks: Option<WatermarkTracker>,
+    memory_budget: Option<SharedMemoryBudget>,
+    // the bytes reserved in the memory budget, which follow the queued bytes
+    reserved: usize,
+}
+impl OutgoingQueue {
+    pub fn new(
+        pending: Arc<AtomicUsize>,
+        watermarks: Option<WatermarkTracker>,
+        memory_budget: Option<SharedMemoryBudget>,
+    ) -> Self {
+        pending.store(0, Ordering::SeqCst);
+        Self {
+            frames: VecDeque::new(),
+            written: 0,
+            continued: false,
+            queued_bytes: 0,
+            pending,
+            watermarks,
+            memory_budget,
+            reserved: 0,
+        }
+    }
+    /// Reserves the given number of bytes in the memory budget, for frames which are queued by 'push_reserved' & 'push_reserved_tail' afterwards.
+    /// Returns false if the memory budget does not allow it.
+    #[must_use]
+    pub fn reserve(&self, bytes: usize) -> bool {
+        memory_budget::try_reserve(&self.memory_budget, bytes)
+    }
+    /// Queues the frame behind all queued frames of the same or a higher priority.
+    /// Its bytes were reserved in the memory budget before (see 'reserve'), the reservation is taken over by the queue.
+    pub fn push_reserved(&mut self, frame: Vec<u8>, priority: Priority) {
+        self.insert(self.position_of(priority), priority, frame);
+    }
+    /// Queues the unwritten tail of a frame, whose head was just written directly to the stream. So the queue has to be empty.
+    /// No other frame is written before the tail is complete. Its bytes were reserved in the memory budget before (see 'reserve').
+    pub fn push_reserved_tail(&mut self, tail: Vec<u8>) {
+        debug_assert!(self.frames.is_empty());
+        self.insert(0, Priority::High, tail);
+        self.continued = true;
+    }
+    /// Queues the frame ahead of all queued frames, except a partially written one in front.
+    /// Returns false (& drops the frame) if the memory budget does not allow to queue it.
+    #[must_use]
+    pub fn push_front(&mut self, frame: Vec<u8>) -> bool {
+        if !memory_budget::try_reserve(&self.memory_budget, frame.len()) {
+            return false;
+        }
+        self.insert(self.unstarted(), Priority::High, frame);
+        true
+    }
+    // the index of the first frame, which was not started yet
+    fn unstarted(&self) -> usize {
+        if self.written > 0 || self.continued {
+            1
+        } else {
+            0
+        }
+    }
+    // the index behind all queued frames of the same or a higher priority, ahead of the unstarted frames of lower priority
+    fn position_of(&self, priority: Priority) -> usize {
+        let start = self.unstarted();
+        self.frames
+            .iter()
+            .skip(start)
+            .position(|(queued, _)| *queued > priority)
+            .map_or(self.frames.len(), |position| start + position)
+    }
+    // queues a reserved frame at the given index
+    fn insert(&mut self, index: usize, priority: Priority, frame: Vec<u8>) {
+        self.reserved += frame.len();
+        self.queued_bytes += frame.len();
+        self.frames.insert(index, (priority, frame));
+        self.changed();
+    }
+    /// The unwritten bytes of all queued frames.
+    pub fn queued_bytes(&self) -> usize {
+        self.queued_bytes
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+    /// Discards all queued frames and returns how many were discarded.
+    pub fn abandon(&mut self) -> usize {
+        let abandoned = self.frames.len();
+        self.frames.clear();
+        self.written = 0;
+        self.continued = false;
+        self.queued_bytes = 0;
+        self.changed();
+        abandoned
+    }
+    /// Drops the frame in front if it is partially written, since its tail cannot be completed on another stream.
+    /// Returns true if a frame was dropped.
+    pub fn discard_partial(&mut self) -> bool {
+        if self.unstarted() == 0 {
+            return false;
+        }
+        self.pop_front();
+        self.changed();
+        true
+    }
+    /// Since frames are only queued if the stream did not accept them, a non-empty queue means that a write would block now.
+    pub fn pressure(&self) -> WritePressure {
+        WritePressure {
+            queued_frames: self.frames.len(),
+            queued_bytes: self.queued_bytes,
+            would_block_now: !self.frames.is_empty(),
+        }
+    }
+    fn changed(&mut self) {
+        self.pending.store(self.frames.len(), Ordering::SeqCst);
+        // written & dropped bytes are given back
+        if self.reserved > self.queued_bytes {
+            memory_budget::release(&self.memory_budget, self.reserved - self.queued_bytes);
+            self.reserved = self.queued_bytes;
+        }
+        if let Some(watermarks) = &mut self.watermarks {
+            watermarks.update(self.queued_bytes);
+        }
+    }
+    fn pop_front(&mut self) {
+        if let Some((_, frame)) = self.frames.pop_front() {
+            self.queued_bytes -= frame.len() - self.written;
+        }
+        self.written = 0;
+        self.continued = false;
+    }
+    /// Writes as many queued bytes as the stream accepts without blocking.
+    /// If writing fails, the frame in front is dropped (since it cannot be completed) and the error is returned.
+    pub fn flush<W: Write>(&mut self, stream: &mut W) -> Result<(), std::io::Error> {
+        let result = loop {
+            let frame = match self.frames.front() {
+                Some((_, frame)) => frame,
+                None => break Ok(()),
+            };
+            match stream.write(&frame[self.written..]) {
+                Ok(0) => {
+                    self.pop_front();
+                    break Err(std::io::ErrorKind::WriteZero.into());
+                }
+                Ok(n) => {
+                    let complete = self.written + n == frame.len();
+                    self.written += n;
+                    self.queued_bytes -= n;
+                    if complete {
+                        self.pop_front();
+                    }
+                }
+                Err(err) => match err.kind() {
+                    std::io::ErrorKind::WouldBlock => break Ok(()),
+                    std::io::ErrorKind::Interrupted => continue,
+                    _ => {
+                        self.pop_front();
+                        break Err(err);
+                    }
+                },
+            }
+        };
+        self.changed();
+        result
+    }
+}
@@ -0,0 +1,63 @@
+use super::tcp_ipc::{ConnectionState, Protocol, ShutdownReport, TcpIpc};
+use log::*;
+use std::time::{Duration, Instant};
+
+/// The time 'close_both' sleeps between checking whether the goodbyes arrived.
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Shuts down both ends of a connection pair (like the pair of 'testing::loopback') without racing, for tests & tools.
+///
+/// Shutting down one end after the other lets the second end see the first one closing, so it reports 'PeerClosed'
+/// (or a 'ReadError', if the connection was reset) before its own shutdown runs. Instead, this orchestrates the sequence:
+/// 1. If the protocol defines a goodbye (see 'Protocol::shutdown_command'), both ends send it & wait until the other end received it.
+/// 2. Both read threads are asked to stop & awaited, so neither reads anymore.
+/// 3. Both sockets are shut down.
+///
+/// All waits share the given time. The results are those of 'TcpIpc::shutdown', for 'a' & 'b'.
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # use rust_tcp_ipc::testing;
+/// # run(|| {
+/// # let config = config();
+/// let (server, client) = testing::loopback::<ProtocolExample>(config.clone(), config)?;
+/// // ... the test ...
+/// let (server, client) = close_both(server, client, std::time::Duration::from_secs(1));
+/// assert!(server.is_ok() && client.is_ok());
+/// # Ok(())
+/// # });
+/// ```
+pub fn close_both<P: Protocol>(
+    mut a: TcpIpc<P>,
+    mut b: TcpIpc<P>,
+    wait_time: Duration,
+) -> (
+    Result<ShutdownReport, ShutdownReport>,
+    Result<ShutdownReport, ShutdownReport>,
+) {
+    let deadline = Instant::now() + wait_time;
+    if let Some(goodbye) = P::shutdown_command() {
+        let a_said_goodbye = a.write_message(goodbye, &[]).is_ok();
+        let b_said_goodbye = b.write_message(goodbye, &[]).is_ok();
+        let received = |said_goodbye: bool, peer: &TcpIpc<P>| {
+            !said_goodbye || peer.connection_state() != ConnectionState::Open
+        };
+        while !(received(a_said_goodbye, &b) && received(b_said_goodbye, &a)) {
+            if Instant::now() >= deadline {
+                debug!("{} & {}: Goodbyes did not arrive in time.", a.id(), b.id());
+                break;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+    let a_started = a.begin_shutdown();
+    let b_started = b.begin_shutdown();
+    let remaining = || Some(deadline.saturating_duration_since(Instant::now()));
+    let a_read_thread = a.await_read_thread(remaining());
+    let b_read_thread = b.await_read_thread(remaining());
+    (
+        a.finish_shutdown(a_started, a_read_thread),
+        b.finish_shutdown(b_started, b_read_thread),
+    )
+}
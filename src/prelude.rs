@@ -0,0 +1,14 @@
+//! This re-exports the types needed for typical use of the crate, so a single import is sufficient.
+//! # Example
+//! ```
+//! use rust_tcp_ipc::prelude::*;
+//! fn receive<P: Protocol>(connection: &mut TcpIpc<P>) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+//!     connection.get_message()
+//! }
+//! ```
+#[allow(deprecated)]
+pub use super::tcp_ipc::{
+    BusyStateQueryResult, BusyStateUpdateResult, ConnectErrors, ImmediateFailurePolicy, Message,
+    ParseHeaderError, Protocol, ReadThreadErrors, ShutdownError, ShutdownReport, Strictness,
+    TcpIpc, TcpIpcConfig, TcpIpcListener, WriteMessageErrors,
+};
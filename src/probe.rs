@@ -0,0 +1,67 @@
+use super::protocol_buffer::Protocol;
+use std::sync::{Arc, Mutex};
+
+/// The number of bytes received during a probe which are kept for 'ConnectErrors::PeerNotSpeakingProtocol'.
+pub const PROBE_CAPTURE_LENGTH: usize = 32;
+
+/// This configures the probe of a client, see 'TcpIpcConfig::probe'.
+///
+/// After connecting, the client sends 'Protocol::probe_frame' & waits for an answer which proves that the peer speaks the protocol.
+/// Frames received meanwhile, which are no probe response, are delivered as usual.
+pub struct ProbeConfig<P: Protocol> {
+    /// The maximal time to wait for the answer, counted from sending the probe frame.
+    pub timeout: std::time::Duration,
+    /// If given, only a frame with this command answers the probe. It is taken out of the messages (it is not delivered).
+    /// If None, any frame (including a banner) answers the probe & is delivered as usual.
+    pub response: Option<P::Commands>,
+}
+impl<P: Protocol> Clone for ProbeConfig<P> {
+    fn clone(&self) -> Self {
+        Self {
+            timeout: self.timeout,
+            response: self.response,
+        }
+    }
+}
+impl<P: Protocol> std::fmt::Debug for ProbeConfig<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ProbeConfig")
+            .field("timeout", &self.timeout)
+            .field("response", &self.response)
+            .finish()
+    }
+}
+impl<P: Protocol> PartialEq for ProbeConfig<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.timeout == other.timeout && self.response == other.response
+    }
+}
+
+/// The first bytes received on a connection, captured by the read thread while a probe is configured.
+pub type SharedFirstBytes = Arc<Mutex<Vec<u8>>>;
+pub fn new_first_bytes() -> SharedFirstBytes {
+    Arc::new(Mutex::new(Vec::with_capacity(PROBE_CAPTURE_LENGTH)))
+}
+/// Appends received bytes, up to 'PROBE_CAPTURE_LENGTH' bytes in total. Returns false once nothing more is captured.
+pub fn capture(first_bytes: &SharedFirstBytes, received: &[u8]) -> bool {
+    let mut first_bytes = first_bytes.lock().unwrap_or_else(|e| e.into_inner());
+    let missing = PROBE_CAPTURE_LENGTH - first_bytes.len();
+    first_bytes.extend_from_slice(&received[..missing.min(received.len())]);
+    first_bytes.len() < PROBE_CAPTURE_LENGTH
+}
+/// Formats captured bytes for an error: printable ASCII is kept (so a text protocol like HTTP is recognizable),
+/// all other bytes are replaced by '.', so no binary content is passed on.
+pub fn preview(first_bytes: &SharedFirstBytes) -> String {
+    first_bytes
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|&byte| {
+            if byte == b' ' || byte.is_ascii_graphic() {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
@@ -1,4 +1,7 @@
-use std::fmt::Debug;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Debug;
 
 /// The error type for parsing a header which was transferred via TCP.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -7,6 +10,36 @@ pub enum ParseHeaderError {
     CommandParseFailed,
     /// Parsing of the length failed, possibly because the length is too large (>=2^32)
     LengthParseFailed,
+    /// The declared length cannot be allocated on this platform, or exceeds the memory budget of the connection (see 'TcpIpcConfig::memory_budget').
+    LengthTooLarge,
+    /// The header returned by "message_slice_to_header_array" is inconsistent with the incoming bytes.
+    /// This typically indicates that the protocol implementation has a flaw.
+    HeaderSliceInconsistent,
+}
+/// A header received from the peer which could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolViolation {
+    /// The reason why parsing failed.
+    pub error: ParseHeaderError,
+    /// The raw bytes of the offending header, which were discarded by the parser.
+    /// If the header could not be delimited ('HeaderSliceInconsistent'), these are all bytes which were buffered.
+    pub header: Vec<u8>,
+}
+/// What the peer declared about itself, typically in a hello or handshake message (see 'Protocol::peer_info' & 'TcpIpc::peer_info').
+/// Fields which are specific to a protocol go into 'fields', so the info can be logged & serialized for every protocol.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PeerInfo {
+    /// The version of the protocol the peer speaks.
+    pub protocol_version: Option<u32>,
+    /// The capabilities (or features) the peer declared.
+    pub capabilities: Vec<String>,
+    /// The identity the peer authenticated as.
+    pub identity: Option<String>,
+    /// Indicates that the peer resumed a previous session, instead of starting a new one.
+    pub resumed_session: bool,
+    /// Further fields defined by the protocol.
+    pub fields: BTreeMap<String, String>,
 }
 /// This trait represents the TCP-Protocol to be used.
 ///
@@ -15,13 +48,13 @@ pub enum ParseHeaderError {
 /// The header combines a command (like Start, Stop, Pause, ...) and the lenght of the payload.
 ///
 /// Many of the implementations show as examples should work for all cases, I'm just unable to define them generically (possible due to missing integer generics).
+/// For the common layout of a big-endian length followed by a big-endian command id, use 'protocols::LengthPrefixedProtocol' instead of implementing the trait.
 ///
 /// Since the protocol trait is only used to bundle some functions & types together, a trivial enum is ok:
 /// # Example
 /// ```
 /// enum ProtocolExample {}
 /// ```
-
 pub trait Protocol: 'static {
     /// This type models the possible commands, like Start, Stop, Pause. It typical is represented by an enum.
     /// # Example
@@ -34,7 +67,7 @@ pub trait Protocol: 'static {
     /// ```
     /// enum ExampleBusyStates {Idle, Working, Failure}
     /// ```
-    type BusyStates: Clone + Copy + Debug + PartialEq + Send + 'static;
+    type BusyStates: Clone + Copy + Debug + PartialEq + Send + Sync + 'static;
     /// This type represents the commands' underlying u8-array. (Currently, Rust supports no integer generics.)
     /// # Example
     /// ```
@@ -58,7 +91,20 @@ pub trait Protocol: 'static {
     /// This function returns a default BusyState "Idle".
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn idle() -> Self::BusyStates {ExampleBusyStates::Idle}
+    /// # }
     /// ```
     fn idle() -> Self::BusyStates;
     /// This function checks if a message has to be answered immediately and not be forwarded to the user.
@@ -67,6 +113,18 @@ pub trait Protocol: 'static {
     /// A possible application is for "heartbeat" checks while the user is doing a computation.
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn message_is_answered_via_immediate_route(
     ///      command: &Self::Commands,
     ///      message: &[u8],
@@ -74,6 +132,7 @@ pub trait Protocol: 'static {
     ///  ) -> Option<(Self::Commands, Vec<u8>)> {
     ///     None
     /// }
+    /// # }
     /// ```
     fn message_is_answered_via_immediate_route(
         command: &Self::Commands,
@@ -83,30 +142,69 @@ pub trait Protocol: 'static {
     /// This function parses a command-array into a command (enum-variant). If this fails, None is return.
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn parse_command(command: &Self::CommandAsArray) -> Option<Self::Commands> {
-    ///     use self::ExampleCommands::*;
+    ///     use ExampleCommands::*;
     ///     match command {
-    ///         [0,0,0]=>Start,
-    ///         [1,2,3]=>Stop,
-    ///         [255,255,255]=>Failure,
+    ///         [0,0,0]=>Some(Start),
+    ///         [1,2,3]=>Some(Stop),
+    ///         [255,255,255]=>Some(Failure),
     ///         _ => None,
     ///     }
     /// }
+    /// # }
     /// ```
     fn parse_command(command: &Self::CommandAsArray) -> Option<Self::Commands>;
     /// This function parses a length-array into a payload-length. If this fails, None is return.
+    /// Lengths which may not fit into a usize (for example 4-byte lengths on 16-bit targets) have to be converted via "usize::try_from", returning None on failure.
     /// It is to be used only internally.
     /// # Example
     /// ```
-    /// fn parse_length(length: &Self::LengthAsArray) -> Option<Self::usize> {
-    ///     length[0] as usize +length[1] as usize * 256
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
+    /// fn parse_length(length: &Self::LengthAsArray) -> Option<usize> {
+    ///     Some(length[0] as usize + length[1] as usize * 256)
     /// }
+    /// # }
     /// ```
     fn parse_length(length: &Self::LengthAsArray) -> Option<usize>;
     /// This function splits an incoming message into header-array & payload-slice. If this fails (because the message is too short), None is returned.
     /// It is to be used only internally.
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn message_slice_to_header_array(input: &[u8]) -> Option<(&Self::HeaderAsArray, &[u8])> {
     ///     const HEADER_SIZE_EXAMPLE:usize = 5;
     ///     if input.len() >= HEADER_SIZE_EXAMPLE {
@@ -120,6 +218,7 @@ pub trait Protocol: 'static {
     ///         None
     ///     }
     /// }
+    /// # }
     /// ```
     fn message_slice_to_header_array(input: &[u8]) -> Option<(&Self::HeaderAsArray, &[u8])>;
     /// This function splits header-array into a command-array and a length-array.
@@ -127,8 +226,21 @@ pub trait Protocol: 'static {
     /// # Example
     /// The following example is "length first", so the payload length takes the first (two) bytes from the incoming header. The remaining bytes encode the command.
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn split_header_array(header: &Self::HeaderAsArray) -> (&Self::CommandAsArray, &Self::LengthAsArray) {
     ///     const LENGTH_SIZE_EXAMPLE : usize = 2;
+    ///     const COMMAND_SIZE_EXAMPLE : usize = 3;
     ///     const HEADER_SIZE_EXAMPLE : usize = 5;
     ///     (
     ///         unsafe {
@@ -140,6 +252,7 @@ pub trait Protocol: 'static {
     ///         },
     ///     )
     /// }
+    /// # }
     /// ```
     fn split_header_array(
         header: &Self::HeaderAsArray,
@@ -148,14 +261,27 @@ pub trait Protocol: 'static {
     /// It is to be used only internally.
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn command_to_array(command: Self::Commands) -> Self::CommandAsArray {
-    ///     use self::ExampleCommands::*;
+    ///     use ExampleCommands::*;
     ///     match command {
     ///         Start=>[0,0,0],
     ///         Stop=>[1,2,3],
     ///         Failure=>[255,255,255],
     ///     }
     /// }
+    /// # }
     /// ```
     fn command_to_array(command: Self::Commands) -> Self::CommandAsArray;
     /// This function computes a length (as array-representation) from a command and a message.
@@ -163,34 +289,129 @@ pub trait Protocol: 'static {
     /// It is to be used only internally.
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn get_length_as_array(command: Self::Commands, message: &[u8]) -> Option<Self::LengthAsArray> {
     ///     let length = message.len() as u64;
-    ///     if length >= 256u64.pow(3) {
+    ///     if length >= 256u64.pow(2) {
     ///         return None;
     ///     }
-    ///     let mut length_array = [0; 3];
-    ///     for i in 0u32..3 {
-    ///         length_array[(2 - i) as usize] = (length / 256u64.pow(i) % 256) as u8;
+    ///     let mut length_array = [0; 2];
+    ///     for i in 0u32..2 {
+    ///         length_array[i as usize] = (length / 256u64.pow(i) % 256) as u8;
     ///     }
     ///     Some(length_array)
     /// }
+    /// # }
     /// ```
     fn get_length_as_array(command: Self::Commands, message: &[u8]) -> Option<Self::LengthAsArray>;
     /// This function constructs the message header from a command and a length.
     /// The implementation below should work (I'm just unable to get it to work generically).
     /// # Example
     /// ```
+    /// # use rust_tcp_ipc::Protocol;
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleCommands { Start, Stop, Failure }
+    /// # #[derive(Debug, Clone, Copy, PartialEq)]
+    /// # enum ExampleBusyStates { Idle, Working, Failure }
+    /// # trait Example: Protocol<
+    /// #     Commands = ExampleCommands,
+    /// #     BusyStates = ExampleBusyStates,
+    /// #     CommandAsArray = [u8; 3],
+    /// #     LengthAsArray = [u8; 2],
+    /// #     HeaderAsArray = [u8; 5],
+    /// # > {
     /// fn construct_header(command: Self::CommandAsArray, length: Self::LengthAsArray) -> Vec<u8> {
     ///     let mut header = Vec::new();
     ///     header.extend_from_slice(&length);
     ///     header.extend_from_slice(&command);
     ///     header
     /// }
+    /// # }
     /// ```
     fn construct_header(command: Self::CommandAsArray, length: Self::LengthAsArray) -> Vec<u8>;
 
+    /// This function returns the command used to acknowledge reliable messages (see 'TcpIpc::write_message_reliable').
+    /// The default implementation (None) means that the protocol does not support reliable messages.
+    fn ack_command() -> Option<Self::Commands> {
+        None
+    }
+    /// This function checks if messages with this command are reliable messages, i.e. carry a frame id in front of their payload and are acknowledged.
+    /// The default implementation (no reliable commands) is fine, if the reliability layer is not used.
+    fn is_reliable_command(_command: &Self::Commands) -> bool {
+        false
+    }
+    /// This function returns the frame sent to the peer before a connection in strict mode is closed because of a protocol violation (see 'Strictness').
+    /// The default implementation (None) means that the connection is closed without notice.
+    fn fault_frame(_violation: &ProtocolViolation) -> Option<(Self::Commands, Vec<u8>)> {
+        None
+    }
+    /// This function returns the command the peer sends to announce that it is closing the connection (a goodbye).
+    /// Messages received after it are flagged (see 'TcpIpc::get_message_with_metadata' & 'ConnectionState::PeerClosing').
+    /// The default implementation (None) means that the protocol has no such command.
+    fn shutdown_command() -> Option<Self::Commands> {
+        None
+    }
+    /// This function returns the command carrying session tokens, so that a client re-establishing a lost connection is recognized
+    /// (see 'TcpIpcServer::next_session_event').
+    /// A client (see 'TcpIpc::client') sends it right after connecting & after each reconnect, with the token it was given (or an empty payload).
+    /// A 'TcpIpcServer' answers with the token of the session, which the client keeps (see 'TcpIpc::session_token'). Neither frame is delivered.
+    /// Other servers receive the requests as messages.
+    /// The default implementation (None) means that the protocol has no sessions.
+    fn session_command() -> Option<Self::Commands> {
+        None
+    }
+    /// This function checks if a command identifies the server, like a firmware version sent unsolicited right after accepting.
+    /// The first such frame received while a client connects is kept apart from the other messages (see 'TcpIpc::banner').
+    /// The default implementation (false) means that the protocol has no banner.
+    fn is_banner(_command: &Self::Commands) -> bool {
+        false
+    }
+    /// This function checks if a command completes the handshake of a new connection, so the connection is ready for use
+    /// (see 'ReadyCondition::AfterHandshake').
+    /// The default implementation (false) means that the protocol has no handshake, so this condition never holds.
+    fn is_handshake_complete(_command: &Self::Commands) -> bool {
+        false
+    }
+    /// This function extracts what the peer declared about itself from a received message (like a hello, banner or handshake message).
+    /// The first message for which this returns Some determines 'TcpIpc::peer_info'. The message itself is handled as usual.
+    /// The default implementation (None) means that the peer does not declare anything.
+    fn peer_info(_command: &Self::Commands, _payload: &[u8]) -> Option<PeerInfo> {
+        None
+    }
+    /// This function returns the frame a client sends to find out whether the peer speaks the protocol (see 'TcpIpcConfig::probe'),
+    /// like a ping or a version request.
+    /// The default implementation (None) means that the protocol has no probe, so no probe is sent (even if configured).
+    fn probe_frame() -> Option<(Self::Commands, Vec<u8>)> {
+        None
+    }
+    /// This function returns a key (like a hash) identifying a frame, so that repeated frames can be dropped (see 'TcpIpcConfig::dedup_window').
+    /// Frames with equal keys within the window are treated as duplicates, so the key should cover command & payload.
+    /// The default implementation (None) means that frames are never treated as duplicates.
+    fn dedup_key(_command: &Self::Commands, _payload: &[u8]) -> Option<u64> {
+        None
+    }
+    /// This function checks if a command is delivered ahead of the other queued messages, like an error the consumer has to see
+    /// even while a backlog of regular messages is queued (see 'TcpIpc::get_message').
+    /// High-priority messages keep their order among themselves.
+    /// The default implementation (false) means that all messages are delivered in the order they were received.
+    fn is_high_priority(_command: &Self::Commands) -> bool {
+        false
+    }
+
     /// This function parses a header into a command & a message length.
     /// The default implementation is fine.
+    #[allow(clippy::type_complexity)]
     fn parse_header(
         header: &Self::HeaderAsArray,
     ) -> Result<(Self::Commands, usize), (ParseHeaderError, &Self::HeaderAsArray)> {
@@ -205,19 +426,63 @@ pub trait Protocol: 'static {
             Err((ParseHeaderError::CommandParseFailed, header))
         }
     }
+    /// This function constructs the header of a message from a command & a payload/message.
+    /// The payload itself is not copied.
+    /// The default implementation is fine.
+    fn construct_message_header(command: Self::Commands, message: &[u8]) -> Option<Vec<u8>> {
+        let length = Self::get_length_as_array(command, message)?;
+        let command = Self::command_to_array(command);
+        Some(Self::construct_header(command, length))
+    }
+    /// This function indicates if a message consists of the header (see "construct_message_header") followed by the unchanged payload.
+    /// If so, messages are written as header & payload, without copying the payload into a new buffer.
+    /// If "construct_message" is overwritten to take full control of the final bytes (for example to append a checksum), this has to return false.
+    /// The default implementation is fine.
+    fn payload_follows_header() -> bool {
+        true
+    }
     /// This function construct a message from a command & a payloay/message.
     /// The default implementation is fine.
     fn construct_message(command: Self::Commands, message: &[u8]) -> Option<Vec<u8>> {
-        if let Some(length) = Self::get_length_as_array(command, message) {
-            let command = Self::command_to_array(command);
-            let mut new_message = Self::construct_header(command, length);
-            new_message.extend_from_slice(message);
-            Some(new_message)
-        } else {
-            None
-        }
+        let mut new_message = Self::construct_message_header(command, message)?;
+        new_message.extend_from_slice(message);
+        Some(new_message)
     }
 }
 
 /// A type alias combining a command (as enum-variant) & a message (as byte-vector).
 pub type Message<P> = (<P as Protocol>::Commands, Vec<u8>);
+
+/// The largest header (in bytes) a protocol may declare, unless configured otherwise (see 'TcpIpcConfig::max_header_size').
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 4096;
+
+/// Checks the sizes a protocol declares via its array types, before they are used to size buffers & to split the incoming bytes:
+/// the header has to hold at least one byte (otherwise no progress is made while parsing) & at most the given number of bytes,
+/// and the command & the length have to fit into the header.
+/// Returns a description of the first flaw found.
+pub(crate) fn check_definition<P: Protocol>(max_header_size: usize) -> Result<(), String> {
+    let header_size = core::mem::size_of::<P::HeaderAsArray>();
+    let command_size = core::mem::size_of::<P::CommandAsArray>();
+    let length_size = core::mem::size_of::<P::LengthAsArray>();
+    if header_size == 0 {
+        return Err(String::from(
+            "the header is empty (HeaderAsArray has size 0)",
+        ));
+    }
+    if header_size > max_header_size {
+        return Err(alloc::format!(
+            "the header has {} bytes, more than the maximum of {} bytes",
+            header_size,
+            max_header_size
+        ));
+    }
+    if command_size > header_size || length_size > header_size {
+        return Err(alloc::format!(
+            "command ({} bytes) & length ({} bytes) do not fit into the header ({} bytes)",
+            command_size,
+            length_size,
+            header_size
+        ));
+    }
+    Ok(())
+}
@@ -1,82 +1,569 @@
 pub use super::protocol::*;
+use alloc::vec::Vec;
 use log::*;
 
+/// This is the parser of incoming messages.
+/// It collects incoming bytes and splits them into messages (command & payload) according to the protocol.
+/// It is used internally by the read thread, but can also be used directly (for example to parse a recorded byte stream).
 #[derive(Debug, Clone, PartialEq)]
 pub struct ProtocolBuffer<P: Protocol> {
     current_command: Option<P::Commands>,
     current_target: usize,
     current_message: Vec<u8>,
     incoming_buffer_vec: Vec<u8>,
+    // bytes in front of this position are already consumed
+    incoming_position: usize,
     busy_state: P::BusyStates,
 }
+/// The consumed part of the incoming buffer is only removed if it is at least this large (and at least half of the buffer).
+/// This keeps the number of memmoves low while bounding the memory usage.
+const COMPACTION_THRESHOLD: usize = 4096;
+impl<P: Protocol> Default for ProtocolBuffer<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl<P: Protocol> ProtocolBuffer<P> {
+    /// This creates an empty parser, with busy state "Idle".
     pub fn new() -> Self {
+        Self::with_busy_state(P::idle())
+    }
+    /// This creates an empty parser, with the given busy state.
+    pub fn with_busy_state(busy_state: P::BusyStates) -> Self {
+        // a connection checks the protocol before (see 'ConnectErrors::InvalidProtocolDefinition'), the size limit is up to direct users
+        debug_assert_eq!(check_definition::<P>(usize::MAX), Ok(()));
         Self {
             current_command: None,
             current_target: 0,
             current_message: Vec::new(),
             incoming_buffer_vec: Vec::new(),
-            busy_state: P::idle(),
+            incoming_position: 0,
+            busy_state,
         }
     }
+    /// This appends newly received bytes and returns the next complete message, if any.
+    /// Since several messages may be completed at once, this has to be called again with an empty slice until None is returned.
+    /// # Panics
+    /// This panics if a header cannot be parsed, see 'try_process_new_buffer' for a non-panicking version.
     pub fn process_new_buffer(&mut self, incoming_buffer: &[u8]) -> Option<(P::Commands, Vec<u8>)> {
-        self.incoming_buffer_vec.extend_from_slice(incoming_buffer);
-        if let Some(command) = self.current_command {
-            if self.incoming_buffer_vec.len() + self.current_message.len() < self.current_target {
-                self.current_message.append(&mut self.incoming_buffer_vec);
-                None
-            } else {
-                let mut completed_message = self.current_message.split_off(0);
-                let mut to_append = self
-                    .incoming_buffer_vec
-                    .split_off(self.current_target - completed_message.len());
-                completed_message.append(&mut self.incoming_buffer_vec);
-                info!(
-                    "Message received: {:?}",
-                    (command, completed_message.clone())
+        match self.try_process_new_buffer(incoming_buffer) {
+            Ok(message) => message,
+            Err(violation) => {
+                // this should happen only in two cases:
+                // a) the command is not-known
+                // b) the length of the message is too large
+                // Since both cases should never happen, a panic seems reasonable
+                error!(
+                    "parse error: {:?}, incoming header: {:?}",
+                    violation.error, violation.header
                 );
-                self.incoming_buffer_vec.append(&mut to_append);
+                panic!(
+                    "parse error: {:?}\r\n, incoming header: {:?}",
+                    violation.error, violation.header
+                )
+            }
+        }
+    }
+    /// This works like 'process_new_buffer', but returns an error if a header cannot be parsed.
+    /// The offending header is discarded (it is returned in the error), so the next call continues with the bytes following it.
+    /// If the protocol did not delimit the header ('HeaderSliceInconsistent'), all buffered bytes are discarded & returned instead.
+    /// Whether the following bytes start a valid frame depends on the peer, so a caller resynchronizing this way has to expect further errors.
+    #[allow(clippy::type_complexity)]
+    pub fn try_process_new_buffer(
+        &mut self,
+        incoming_buffer: &[u8],
+    ) -> Result<Option<(P::Commands, Vec<u8>)>, ProtocolViolation> {
+        self.try_process_new_buffer_within(incoming_buffer, &mut |_| Reservation::Granted)
+    }
+    /// This works like 'try_process_new_buffer', but calls 'reserve' with the declared payload length before the payload of a message is allocated,
+    /// for example to account it against a memory budget (see 'Reservation').
+    /// If the allocation fails after 'reserve' granted the length, 'LengthTooLarge' is returned, so the caller has to undo the reservation on an error.
+    #[allow(clippy::type_complexity)]
+    pub fn try_process_new_buffer_within(
+        &mut self,
+        incoming_buffer: &[u8],
+        reserve: &mut dyn FnMut(usize) -> Reservation,
+    ) -> Result<Option<(P::Commands, Vec<u8>)>, ProtocolViolation> {
+        self.incoming_buffer_vec.extend_from_slice(incoming_buffer);
+        loop {
+            let available = &self.incoming_buffer_vec[self.incoming_position..];
+            if let Some(command) = self.current_command {
+                // the payload is copied directly from the incoming buffer into its (pre-allocated) vector
+                // the payload never grows beyond the declared length, so this cannot underflow
+                let missing = self
+                    .current_target
+                    .saturating_sub(self.current_message.len());
+                let taken = missing.min(available.len());
+                self.current_message.extend_from_slice(&available[..taken]);
+                self.incoming_position += taken;
+                self.compact();
+                if taken < missing {
+                    return Ok(None);
+                }
+                let completed_message = core::mem::take(&mut self.current_message);
+                if log_enabled!(Level::Trace) {
+                    trace!("Message received: {:?}", (command, &completed_message));
+                }
                 self.current_target = 0; //not strictly necessary
                 self.current_command = None;
-                Some((command, completed_message))
-            }
-        } else if let Some((header, message)) =
-            P::message_slice_to_header_array(self.incoming_buffer_vec.as_slice())
-        {
-            let (command, length) = match P::parse_header(header) {
-                Ok((command, length)) => (command, length),
-                Err((err, message)) => {
-                    // this should happen only in two cases:
-                    // a) the command is not-known
-                    // b) the length of the message is too large
-                    // Since both cases should never happen, a panic seems reasonable
-                    error!("parse error: {:?}, incoming header: {:?}", err, message);
-                    panic!("parse error: {:?}\r\n, incoming header: {:?}", err, message)
+                return Ok(Some((command, completed_message)));
+            } else if let Some((header, message)) = P::message_slice_to_header_array(available) {
+                let header_length = match available.len().checked_sub(message.len()) {
+                    Some(header_length) => header_length,
+                    None => {
+                        let header = available.to_vec();
+                        self.discard(header.len());
+                        return Err(ProtocolViolation {
+                            error: ParseHeaderError::HeaderSliceInconsistent,
+                            header,
+                        });
+                    }
+                };
+                let (command, length) = match P::parse_header(header) {
+                    Ok((command, length)) => (command, length),
+                    Err((error, _)) => return Err(self.violation(error, header_length)),
+                };
+                // this is the only allocation per message (none for an empty payload)
+                // the length is declared by the peer, so an allocation failure is reported instead of aborting
+                let mut current_message = Vec::new();
+                match reserve(length) {
+                    Reservation::Granted => {}
+                    Reservation::Deferred => {
+                        // the header stays buffered, so it is parsed again by the next call
+                        self.compact();
+                        return Ok(None);
+                    }
+                    Reservation::Refused => {
+                        return Err(self.violation(ParseHeaderError::LengthTooLarge, header_length))
+                    }
+                }
+                if current_message.try_reserve_exact(length).is_err() {
+                    return Err(self.violation(ParseHeaderError::LengthTooLarge, header_length));
+                }
+                self.incoming_position += header_length;
+                if length == 0 {
+                    // a bare command is completed right away, without touching the payload state
+                    self.compact();
+                    trace!("Message received: {:?}", (command, &[] as &[u8]));
+                    return Ok(Some((command, Vec::new())));
                 }
-            };
-            self.current_command = Some(command);
-            self.current_target = length;
-            self.current_message = message.to_vec(); // capacity can also be set already
-            if length > self.current_message.len() {
-                self.incoming_buffer_vec = Vec::new();
+                trace!("New message started: {:?}", (command, length));
+                self.current_command = Some(command);
+                self.current_target = length;
+                self.current_message = current_message;
             } else {
-                self.incoming_buffer_vec = self.current_message.split_off(length);
-                self.current_message
-                    .reserve(length - self.current_message.len());
+                self.compact();
+                return Ok(None);
             }
-            debug!(
-                "New message started: {:?}",
-                (command, self.current_message.clone())
-            );
-            self.process_new_buffer(&[]) // process remaining buffer
-        } else {
-            None
         }
     }
+    /// Discards the offending header in front of the buffered bytes & returns it as violation.
+    fn violation(&mut self, error: ParseHeaderError, header_length: usize) -> ProtocolViolation {
+        let start = self.incoming_position;
+        let header = self.incoming_buffer_vec[start..start + header_length].to_vec();
+        self.discard(header_length);
+        ProtocolViolation { error, header }
+    }
+    fn discard(&mut self, length: usize) {
+        self.incoming_position += length;
+        self.compact();
+    }
+    /// Removes the consumed part of the incoming buffer, if this is cheap or necessary.
+    fn compact(&mut self) {
+        if self.incoming_position == self.incoming_buffer_vec.len() {
+            self.incoming_buffer_vec.clear();
+            self.incoming_position = 0;
+        } else if self.incoming_position >= COMPACTION_THRESHOLD
+            && 2 * self.incoming_position >= self.incoming_buffer_vec.len()
+        {
+            self.incoming_buffer_vec.drain(..self.incoming_position);
+            self.incoming_position = 0;
+        }
+    }
+    /// This updates the busy state, which is used to decide about immediate responses.
     pub fn update_busy_state(&mut self, busy_state: P::BusyStates) {
         self.busy_state = busy_state;
     }
+    /// This returns the current busy state.
     pub fn get_busy_state(&self) -> P::BusyStates {
         self.busy_state
     }
+    /// This returns a snapshot of the parser state, for example to detect a partially received message.
+    pub fn parser_state(&self) -> ParserState<P> {
+        ParserState {
+            command: self.current_command,
+            received: self.current_message.len(),
+            declared: self.current_target,
+            buffered: self.incoming_buffer_vec.len() - self.incoming_position,
+        }
+    }
+}
+
+/// The answer to the reservation of a payload, see 'ProtocolBuffer::try_process_new_buffer_within'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reservation {
+    /// The payload is allocated & parsing continues.
+    Granted,
+    /// The payload does not fit now. Parsing stops before its header, until the next call.
+    Deferred,
+    /// The payload never fits, so the message is refused as 'LengthTooLarge'.
+    Refused,
+}
+
+/// A snapshot of the parser state of a connection.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(bound = "P::Commands: serde::Serialize")
+)]
+pub struct ParserState<P: Protocol> {
+    /// The command of the partially received message, if its header was parsed already.
+    pub command: Option<P::Commands>,
+    /// The number of payload bytes received so far for the partially received message.
+    pub received: usize,
+    /// The payload length declared in the header of the partially received message.
+    pub declared: usize,
+    /// The number of buffered bytes which do not yet form a complete header.
+    pub buffered: usize,
+}
+impl<P: Protocol> ParserState<P> {
+    /// Checks if the parser is waiting for the remaining part of a message (header or payload).
+    pub fn is_mid_frame(&self) -> bool {
+        self.command.is_some() || self.buffered > 0
+    }
+    /// Returns the partially received message, if the parser is waiting for its remaining part.
+    pub fn truncated_frame(&self) -> Option<TruncatedFrame<P>> {
+        match self.command {
+            Some(command) => Some(TruncatedFrame {
+                command: Some(command),
+                received: self.received,
+                declared: Some(self.declared),
+            }),
+            None if self.buffered > 0 => Some(TruncatedFrame {
+                command: None,
+                received: self.buffered,
+                declared: None,
+            }),
+            None => None,
+        }
+    }
+}
+
+/// A message which was only partially received when the connection ended, see 'ReadThreadErrors::PeerClosed'.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(bound = "P::Commands: serde::Serialize")
+)]
+pub struct TruncatedFrame<P: Protocol> {
+    /// The command of the message. This is None if its header was not received completely.
+    pub command: Option<P::Commands>,
+    /// The number of payload bytes received (or header bytes, if the header was not received completely).
+    pub received: usize,
+    /// The payload length declared in the header. This is None if the header was not received completely.
+    pub declared: Option<usize>,
+}
+impl<P: Protocol> Clone for TruncatedFrame<P> {
+    fn clone(&self) -> Self {
+        Self {
+            command: self.command,
+            received: self.received,
+            declared: self.declared,
+        }
+    }
+}
+impl<P: Protocol> core::fmt::Debug for TruncatedFrame<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("TruncatedFrame")
+            .field("command", &self.command)
+            .field("received", &self.received)
+            .field("declared", &self.declared)
+            .finish()
+    }
+}
+impl<P: Protocol> PartialEq for TruncatedFrame<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.received == other.received
+            && self.declared == other.declared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::LengthPrefixedProtocol;
+    use alloc::vec;
+    use core::convert::TryFrom;
+
+    type TestProtocol = LengthPrefixedProtocol<u8, 4, 1>;
+
+    fn frames() -> Vec<(u8, Vec<u8>)> {
+        vec![
+            (1, vec![]),
+            (2, vec![7; 16]),
+            (3, vec![]),
+            (4, (0..=255).collect()),
+            (5, vec![9; 3 * COMPACTION_THRESHOLD]),
+            (6, vec![1]),
+        ]
+    }
+
+    fn wire(frames: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        frames
+            .iter()
+            .flat_map(|(command, payload)| {
+                TestProtocol::construct_message(*command, payload).unwrap()
+            })
+            .collect()
+    }
+
+    fn parse<'a, I: IntoIterator<Item = &'a [u8]>>(chunks: I) -> Vec<(u8, Vec<u8>)> {
+        let mut buffer = ProtocolBuffer::<TestProtocol>::new();
+        let mut messages = Vec::new();
+        for chunk in chunks {
+            let mut next = buffer.process_new_buffer(chunk);
+            while let Some(message) = next {
+                messages.push(message);
+                next = buffer.process_new_buffer(&[]);
+            }
+        }
+        assert!(!buffer.parser_state().is_mid_frame());
+        messages
+    }
+
+    #[test]
+    fn fragmentation_does_not_change_the_messages() {
+        let frames = frames();
+        let wire = wire(&frames);
+        assert_eq!(parse(core::iter::once(&wire[..])), frames);
+        for chunk_size in [1, 2, 3, 5, 8, 13, 128, 4097] {
+            assert_eq!(
+                parse(wire.chunks(chunk_size)),
+                frames,
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn every_split_point_yields_the_same_messages() {
+        let frames = vec![
+            (1, vec![]),
+            (2, vec![7; 16]),
+            (3, vec![]),
+            (4, vec![1, 2, 3]),
+        ];
+        let wire = wire(&frames);
+        for split in 0..=wire.len() {
+            let (first, second) = wire.split_at(split);
+            assert_eq!(parse([first, second]), frames, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn consumed_bytes_are_compacted_away_while_parsing() {
+        let frames: Vec<_> = (0..10_000u32)
+            .map(|i| (i as u8, vec![i as u8; 16]))
+            .collect();
+        let wire = wire(&frames);
+        // all at once: the consumed front is dropped periodically, not per message
+        let mut buffer = ProtocolBuffer::<TestProtocol>::new();
+        let mut next = buffer.process_new_buffer(&wire);
+        for frame in &frames {
+            assert_eq!(next.as_ref(), Some(frame));
+            let remaining = buffer.incoming_buffer_vec.len() - buffer.incoming_position;
+            assert!(buffer.incoming_buffer_vec.len() <= 2 * remaining + COMPACTION_THRESHOLD);
+            next = buffer.process_new_buffer(&[]);
+        }
+        assert_eq!(next, None);
+        assert!(buffer.incoming_buffer_vec.is_empty());
+        // read by read: the buffer never holds more than a read & the unparsed rest
+        let mut buffer = ProtocolBuffer::<TestProtocol>::new();
+        let mut parsed = 0;
+        for chunk in wire.chunks(4096) {
+            let mut next = buffer.process_new_buffer(chunk);
+            while next.is_some() {
+                parsed += 1;
+                next = buffer.process_new_buffer(&[]);
+            }
+            assert!(buffer.incoming_buffer_vec.len() < 4096 + COMPACTION_THRESHOLD);
+        }
+        assert_eq!(parsed, frames.len());
+    }
+
+    #[test]
+    fn payload_is_allocated_once_with_the_declared_length() {
+        let mut buffer = ProtocolBuffer::<TestProtocol>::new();
+        let wire = wire(&[(2, vec![7; 16])]);
+        assert_eq!(buffer.process_new_buffer(&wire[..10]), None);
+        assert_eq!(buffer.current_message.capacity(), 16);
+        let (_, payload) = buffer.process_new_buffer(&wire[10..]).unwrap();
+        assert_eq!(payload.capacity(), 16);
+    }
+
+    #[test]
+    fn parser_state_reports_a_partial_frame() {
+        let mut buffer = ProtocolBuffer::<TestProtocol>::new();
+        let wire = wire(&[(2, vec![7; 16])]);
+        assert_eq!(buffer.process_new_buffer(&wire[..3]), None);
+        assert_eq!(buffer.parser_state().buffered, 3);
+        assert_eq!(buffer.process_new_buffer(&wire[3..10]), None);
+        let state = buffer.parser_state();
+        assert_eq!(
+            (state.command, state.received, state.declared),
+            (Some(2), 5, 16)
+        );
+        assert!(state.is_mid_frame());
+    }
+
+    type WideProtocol = LengthPrefixedProtocol<u8, 8, 1>;
+
+    fn wide_header(length: u64) -> Vec<u8> {
+        let mut header = length.to_be_bytes().to_vec();
+        header.push(1);
+        header
+    }
+
+    // like 'WideProtocol' on a 32-bit target, where 'usize::try_from' fails beyond u32::MAX
+    enum ThirtyTwoBitProtocol {}
+    impl Protocol for ThirtyTwoBitProtocol {
+        type Commands = u8;
+        type BusyStates = ();
+        type CommandAsArray = <WideProtocol as Protocol>::CommandAsArray;
+        type LengthAsArray = <WideProtocol as Protocol>::LengthAsArray;
+        type HeaderAsArray = <WideProtocol as Protocol>::HeaderAsArray;
+        fn idle() {}
+        fn message_is_answered_via_immediate_route(
+            _command: &u8,
+            _message: &[u8],
+            _busy_state: &(),
+        ) -> Option<(u8, Vec<u8>)> {
+            None
+        }
+        fn parse_command(command: &Self::CommandAsArray) -> Option<u8> {
+            WideProtocol::parse_command(command)
+        }
+        fn parse_length(length: &Self::LengthAsArray) -> Option<usize> {
+            let length = u32::try_from(u64::from_be_bytes(*length)).ok()?;
+            usize::try_from(length).ok()
+        }
+        fn message_slice_to_header_array(input: &[u8]) -> Option<(&Self::HeaderAsArray, &[u8])> {
+            WideProtocol::message_slice_to_header_array(input)
+        }
+        fn split_header_array(
+            header: &Self::HeaderAsArray,
+        ) -> (&Self::CommandAsArray, &Self::LengthAsArray) {
+            WideProtocol::split_header_array(header)
+        }
+        fn command_to_array(command: u8) -> Self::CommandAsArray {
+            WideProtocol::command_to_array(command)
+        }
+        fn get_length_as_array(command: u8, message: &[u8]) -> Option<Self::LengthAsArray> {
+            WideProtocol::get_length_as_array(command, message)
+        }
+        fn construct_header(command: Self::CommandAsArray, length: Self::LengthAsArray) -> Vec<u8> {
+            WideProtocol::construct_header(command, length)
+        }
+    }
+
+    #[test]
+    fn unallocatable_lengths_are_reported() {
+        for length in [usize::MAX as u64, u64::MAX, isize::MAX as u64 + 1] {
+            let mut buffer = ProtocolBuffer::<WideProtocol>::new();
+            let header = wide_header(length);
+            let violation = buffer.try_process_new_buffer(&header).unwrap_err();
+            assert_eq!(violation.error, ParseHeaderError::LengthTooLarge);
+            assert_eq!(violation.header, header);
+            // the header is discarded, so the parser continues with the next frame
+            let next = WideProtocol::construct_message(2, &[5]).unwrap();
+            assert_eq!(buffer.try_process_new_buffer(&next), Ok(Some((2, vec![5]))));
+        }
+    }
+
+    #[test]
+    fn lengths_beyond_a_32_bit_usize_are_reported() {
+        let mut buffer = ProtocolBuffer::<ThirtyTwoBitProtocol>::new();
+        let header = wide_header(u64::from(u32::MAX) + 1);
+        let violation = buffer.try_process_new_buffer(&header).unwrap_err();
+        assert_eq!(violation.error, ParseHeaderError::LengthParseFailed);
+        assert_eq!(violation.header, header);
+        // the largest length a 32-bit usize can hold is parsed (its allocation is deferred here)
+        let mut declared = None;
+        let result = buffer.try_process_new_buffer_within(
+            &wide_header(u64::from(u32::MAX)),
+            &mut |length| {
+                declared = Some(length);
+                Reservation::Deferred
+            },
+        );
+        assert_eq!(result, Ok(None));
+        assert_eq!(declared, Some(u32::MAX as usize));
+    }
+
+    #[test]
+    fn a_garbage_header_is_discarded_and_the_frames_behind_it_are_parsed() {
+        let mut buffer = ProtocolBuffer::<ThirtyTwoBitProtocol>::new();
+        let garbage = wide_header(u64::MAX);
+        let mut wire = garbage.clone();
+        wire.extend(ThirtyTwoBitProtocol::construct_message(2, &[5]).unwrap());
+        wire.extend(ThirtyTwoBitProtocol::construct_message(3, &[]).unwrap());
+        // everything arrives at once, the bytes behind the garbage stay buffered
+        let violation = buffer.try_process_new_buffer(&wire).unwrap_err();
+        assert_eq!(violation.error, ParseHeaderError::LengthParseFailed);
+        assert_eq!(violation.header, garbage);
+        assert_eq!(buffer.parser_state().buffered, wire.len() - garbage.len());
+
+        assert_eq!(buffer.try_process_new_buffer(&[]), Ok(Some((2, vec![5]))));
+        assert_eq!(buffer.try_process_new_buffer(&[]), Ok(Some((3, vec![]))));
+        assert_eq!(buffer.try_process_new_buffer(&[]), Ok(None));
+        assert_eq!(buffer.parser_state().buffered, 0);
+        assert!(!buffer.parser_state().is_mid_frame());
+    }
+
+    #[test]
+    #[should_panic(expected = "LengthParseFailed")]
+    fn the_panicking_parser_panics_on_a_garbage_header() {
+        let mut buffer = ProtocolBuffer::<ThirtyTwoBitProtocol>::new();
+        buffer.process_new_buffer(&wide_header(u64::MAX));
+    }
+
+    // the core serves a peer over any byte pipe (here a byte queue each way), without std
+    #[test]
+    fn a_byte_pipe_is_served_without_tcp() {
+        use crate::response_table::{ImmediateResponseTable, Matcher, ResponseTemplate};
+        use alloc::collections::VecDeque;
+        let table = ImmediateResponseTable::<TestProtocol>::new().when(
+            1,
+            Matcher::Any,
+            ResponseTemplate::EchoPayload(2),
+        );
+        let mut requests: VecDeque<u8> = wire(&[(1, vec![1, 2, 3]), (4, vec![]), (1, vec![4])])
+            .into_iter()
+            .collect();
+        let mut responses = VecDeque::new();
+        let mut device = ProtocolBuffer::<TestProtocol>::new();
+        let mut forwarded = Vec::new();
+        // the pipe delivers 2 bytes at a time
+        while !requests.is_empty() {
+            let chunk: Vec<u8> = requests.drain(..requests.len().min(2)).collect();
+            let mut next = device.process_new_buffer(&chunk);
+            while let Some((command, payload)) = next {
+                match table.answer(&command, &payload, &device.get_busy_state()) {
+                    Some((command, payload)) => responses
+                        .extend(TestProtocol::construct_message(command, &payload).unwrap()),
+                    None => forwarded.push((command, payload)),
+                }
+                next = device.process_new_buffer(&[]);
+            }
+        }
+        assert_eq!(forwarded, vec![(4, vec![])]);
+        let responses: Vec<u8> = responses.into_iter().collect();
+        assert_eq!(
+            parse(responses.chunks(3)),
+            vec![(2, vec![1, 2, 3]), (2, vec![4])]
+        );
+    }
 }
@@ -0,0 +1,159 @@
+//! Ready-made implementations of 'Protocol', for the common header layouts, so the header parsing has not to be written by hand.
+use super::protocol::Protocol;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+/// The header of a 'LengthPrefixedProtocol': the payload length followed by the command, both big-endian.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LengthPrefixedHeader<const LEN_BYTES: usize, const CMD_BYTES: usize> {
+    /// The payload length.
+    pub length: [u8; LEN_BYTES],
+    /// The command.
+    pub command: [u8; CMD_BYTES],
+}
+
+/// A protocol whose header is a 'LEN_BYTES'-byte big-endian payload length followed by a 'CMD_BYTES'-byte big-endian command id.
+///
+/// The commands are given by 'C', which converts to & from its id (a u32). Ids which do not convert back are rejected while parsing
+/// ('ParseHeaderError::CommandParseFailed'). A message is not constructed (so 'write_message' fails with 'MessageConstructionFailed')
+/// if its payload is longer than 2^(8*LEN_BYTES)-1 bytes, or if the id of its command does not fit into 'CMD_BYTES' bytes.
+///
+/// The protocol has no busy states (they are '()') & answers no message via the immediate route.
+/// To answer messages immediately anyway, install a table (see 'TcpIpcConfig::immediate_responses').
+/// Since the type is only used to select the protocol, it is never constructed.
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::protocols::LengthPrefixedProtocol;
+/// # use rust_tcp_ipc::doc_example::run;
+/// # use std::convert::TryFrom;
+/// #[derive(Debug, Clone, Copy, PartialEq)]
+/// enum Commands {
+///     Start = 1,
+///     Stop = 2,
+/// }
+/// impl From<Commands> for u32 {
+///     fn from(command: Commands) -> u32 {
+///         command as u32
+///     }
+/// }
+/// impl TryFrom<u32> for Commands {
+///     type Error = ();
+///     fn try_from(id: u32) -> Result<Self, ()> {
+///         match id {
+///             1 => Ok(Commands::Start),
+///             2 => Ok(Commands::Stop),
+///             _ => Err(()),
+///         }
+///     }
+/// }
+/// // a 3-byte length followed by a 2-byte command
+/// type ExampleProtocol = LengthPrefixedProtocol<Commands, 3, 2>;
+///
+/// # run(|| {
+/// # let config = TcpIpcConfig::default();
+/// let mut client = TcpIpc::<ExampleProtocol>::client("127.0.0.1:6666", config, None)?;
+/// client.write_message(Commands::Start, b"ok")?;
+/// # Ok(())
+/// # });
+/// ```
+pub struct LengthPrefixedProtocol<C, const LEN_BYTES: usize, const CMD_BYTES: usize> {
+    _commands: PhantomData<fn() -> C>,
+}
+impl<C, const LEN_BYTES: usize, const CMD_BYTES: usize>
+    LengthPrefixedProtocol<C, LEN_BYTES, CMD_BYTES>
+{
+    const HEADER_SIZE: usize = LEN_BYTES + CMD_BYTES;
+}
+impl<C, const LEN_BYTES: usize, const CMD_BYTES: usize> Debug
+    for LengthPrefixedProtocol<C, LEN_BYTES, CMD_BYTES>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "LengthPrefixedProtocol<{}, {}>", LEN_BYTES, CMD_BYTES)
+    }
+}
+
+/// Checks if the value fits into the given number of bytes.
+fn fits(value: u64, bytes: usize) -> bool {
+    bytes >= 8 || value >> (8 * bytes) == 0
+}
+/// Writes the value big-endian into the array. The value has to fit (see 'fits').
+fn to_big_endian<const BYTES: usize>(value: u64) -> [u8; BYTES] {
+    let mut array = [0; BYTES];
+    for (i, byte) in array.iter_mut().rev().enumerate().take(8) {
+        *byte = (value >> (8 * i)) as u8;
+    }
+    array
+}
+/// Reads a big-endian value from the array. Returns None if it does not fit into a u64.
+fn from_big_endian(array: &[u8]) -> Option<u64> {
+    array.iter().try_fold(0u64, |value, &byte| {
+        if value >> 56 != 0 {
+            None
+        } else {
+            Some(value << 8 | u64::from(byte))
+        }
+    })
+}
+
+impl<C, const LEN_BYTES: usize, const CMD_BYTES: usize> Protocol
+    for LengthPrefixedProtocol<C, LEN_BYTES, CMD_BYTES>
+where
+    C: TryFrom<u32> + Into<u32> + Copy + Debug + PartialEq + Send + Sync + 'static,
+{
+    type Commands = C;
+    type BusyStates = ();
+    type CommandAsArray = [u8; CMD_BYTES];
+    type LengthAsArray = [u8; LEN_BYTES];
+    type HeaderAsArray = LengthPrefixedHeader<LEN_BYTES, CMD_BYTES>;
+    fn idle() -> Self::BusyStates {}
+    fn message_is_answered_via_immediate_route(
+        _command: &Self::Commands,
+        _message: &[u8],
+        _busy_state: &Self::BusyStates,
+    ) -> Option<(Self::Commands, Vec<u8>)> {
+        None
+    }
+    fn parse_command(command: &Self::CommandAsArray) -> Option<Self::Commands> {
+        let id = u32::try_from(from_big_endian(command)?).ok()?;
+        C::try_from(id).ok()
+    }
+    fn parse_length(length: &Self::LengthAsArray) -> Option<usize> {
+        usize::try_from(from_big_endian(length)?).ok()
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&Self::HeaderAsArray, &[u8])> {
+        if input.len() >= Self::HEADER_SIZE {
+            // the header consists of byte arrays only, so it has neither padding nor alignment
+            Some((
+                unsafe { &*(input.as_ptr() as *const LengthPrefixedHeader<LEN_BYTES, CMD_BYTES>) },
+                &input[Self::HEADER_SIZE..],
+            ))
+        } else {
+            None
+        }
+    }
+    fn split_header_array(
+        header: &Self::HeaderAsArray,
+    ) -> (&Self::CommandAsArray, &Self::LengthAsArray) {
+        (&header.command, &header.length)
+    }
+    fn command_to_array(command: Self::Commands) -> Self::CommandAsArray {
+        to_big_endian(u64::from(command.into()))
+    }
+    fn get_length_as_array(command: Self::Commands, message: &[u8]) -> Option<Self::LengthAsArray> {
+        let length = u64::try_from(message.len()).ok()?;
+        if !fits(length, LEN_BYTES) || !fits(u64::from(command.into()), CMD_BYTES) {
+            return None;
+        }
+        Some(to_big_endian(length))
+    }
+    fn construct_header(command: Self::CommandAsArray, length: Self::LengthAsArray) -> Vec<u8> {
+        let mut header = Vec::with_capacity(Self::HEADER_SIZE);
+        header.extend_from_slice(&length);
+        header.extend_from_slice(&command);
+        header
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// This determines what 'TcpIpc::write_message' does while the rate limit is reached (see 'RateLimit').
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitPolicy {
+    /// The calling thread sleeps until the frame may be sent.
+    Wait,
+    /// The frame is not sent & 'WriteMessageErrors::RateLimited' is returned.
+    Reject,
+}
+
+/// This limits the rate of outgoing frames (see 'TcpIpcConfig::outgoing_rate_limit'), for peers which cannot handle bursts.
+///
+/// Frames are sent as long as tokens are available. A token is used per frame, and tokens are refilled at 'frames_per_second',
+/// up to 'burst' tokens. So after an idle period, 'burst' frames are sent at once, and afterwards one frame per 1/'frames_per_second'.
+/// Control frames (pings, acknowledgements & fault frames) are never limited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// The number of frames per second which can be sent on average. Zero is treated as one.
+    pub frames_per_second: u32,
+    /// The maximal number of frames which can be sent at once. Zero is treated as one.
+    pub burst: u32,
+    /// What 'write_message' does while no token is available. 'try_write_message' never waits.
+    pub when_limited: RateLimitPolicy,
+    /// If true, frames written by the read thread (immediate responses & periodic messages, see 'send_periodic') use tokens, too.
+    /// If no token is available, the frame is held back until one is (in order), while the read thread keeps reading.
+    /// If false, these frames are sent without limit & do not use tokens.
+    pub include_immediate_responses: bool,
+}
+
+/// A token bucket, implemented by the time at which the bucket would be full again (like a generic cell rate algorithm).
+/// The current time is passed in, so the arithmetic does not depend on the clock.
+#[derive(Debug)]
+pub struct TokenBucket {
+    // the time needed to refill a single token
+    interval: Duration,
+    burst: u32,
+    // the time at which all previously taken tokens are refilled
+    refilled_at: Instant,
+}
+impl TokenBucket {
+    /// Creates a full bucket.
+    pub fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / limit.frames_per_second.max(1),
+            burst: limit.burst.max(1),
+            refilled_at: now,
+        }
+    }
+    /// Takes the given number of tokens, if they are available at the given time.
+    /// Otherwise nothing is taken & the time until they are available is returned.
+    /// More tokens than the burst are never available.
+    pub fn try_take(&mut self, count: u32, now: Instant) -> Result<(), Duration> {
+        let refilled_at = self.refilled_at.max(now) + self.interval * count;
+        let limit = now + self.interval * self.burst;
+        if refilled_at <= limit {
+            self.refilled_at = refilled_at;
+            Ok(())
+        } else {
+            Err(refilled_at - limit)
+        }
+    }
+}
+
+/// The token bucket of a connection, shared by the main thread & the read thread.
+pub type SharedTokenBucket = Arc<Mutex<TokenBucket>>;
+pub fn new_token_bucket(limit: &RateLimit) -> SharedTokenBucket {
+    Arc::new(Mutex::new(TokenBucket::new(limit, Instant::now())))
+}
+
+/// Takes the given number of tokens. If 'wait' is set, the calling thread sleeps until they are available (token by token, so a count above the burst works).
+/// Otherwise, all tokens are taken at once or the time until they are available is returned.
+pub fn take(bucket: &SharedTokenBucket, count: u32, wait: bool) -> Result<(), Duration> {
+    let lock = || bucket.lock().unwrap_or_else(|e| e.into_inner());
+    if !wait {
+        return lock().try_take(count, Instant::now());
+    }
+    for _ in 0..count {
+        // the lock is not held while sleeping, so other threads can take tokens meanwhile
+        while let Err(wait_time) = lock().try_take(1, Instant::now()) {
+            std::thread::sleep(wait_time);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(frames_per_second: u32, burst: u32, now: Instant) -> TokenBucket {
+        let limit = RateLimit {
+            frames_per_second,
+            burst,
+            when_limited: RateLimitPolicy::Reject,
+            include_immediate_responses: false,
+        };
+        TokenBucket::new(&limit, now)
+    }
+
+    #[test]
+    fn the_burst_is_available_at_once() {
+        let start = Instant::now();
+        let mut bucket = bucket(10, 3, start);
+        for _ in 0..3 {
+            assert_eq!(bucket.try_take(1, start), Ok(()));
+        }
+        // the next token is refilled after 1/10 s
+        assert_eq!(bucket.try_take(1, start), Err(Duration::from_millis(100)));
+        assert_eq!(
+            bucket.try_take(1, start + Duration::from_millis(60)),
+            Err(Duration::from_millis(40))
+        );
+        assert_eq!(
+            bucket.try_take(1, start + Duration::from_millis(100)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn tokens_are_paced_after_the_burst() {
+        let start = Instant::now();
+        let mut bucket = bucket(10, 3, start);
+        assert_eq!(bucket.try_take(3, start), Ok(()));
+        // one frame per 100 ms, never more
+        for i in 1..=5 {
+            let now = start + Duration::from_millis(100) * i;
+            assert_eq!(bucket.try_take(1, now), Ok(()));
+            assert!(bucket.try_take(1, now).is_err());
+        }
+    }
+
+    #[test]
+    fn an_idle_bucket_refills_up_to_the_burst() {
+        let start = Instant::now();
+        let mut bucket = bucket(10, 3, start);
+        assert_eq!(bucket.try_take(3, start), Ok(()));
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.try_take(3, later), Ok(()));
+        assert_eq!(bucket.try_take(1, later), Err(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn more_tokens_than_the_burst_are_never_available() {
+        let start = Instant::now();
+        let mut bucket = bucket(10, 3, start);
+        assert_eq!(bucket.try_take(4, start), Err(Duration::from_millis(100)));
+        // nothing was taken
+        assert_eq!(bucket.try_take(3, start), Ok(()));
+    }
+
+    #[test]
+    fn zero_is_treated_as_one() {
+        let start = Instant::now();
+        let mut bucket = bucket(0, 0, start);
+        assert_eq!(bucket.try_take(1, start), Ok(()));
+        assert_eq!(bucket.try_take(1, start), Err(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,72 @@
+use super::tcp_ipc::RepeatedReadError;
+use std::time::Instant;
+
+/// The reads of a connection failing in a row, whose errors are collapsed (see 'ReadThreadErrors::RepeatedReadError').
+/// The current time is passed in, so the collapsing does not depend on the clock.
+#[derive(Debug, Default)]
+pub struct ReadErrorRun {
+    // the errors of the same kind collapsed since the last report
+    pending: Option<RepeatedReadError>,
+    // the number of reads which failed in a row
+    consecutive: usize,
+}
+impl ReadErrorRun {
+    /// Records a failed read. If its error is of another kind than the collapsed ones, these are returned to be reported now.
+    pub fn failed(&mut self, error: std::io::Error, now: Instant) -> Option<RepeatedReadError> {
+        self.consecutive += 1;
+        match &mut self.pending {
+            Some(repeated) if repeated.error.kind() == error.kind() => {
+                repeated.error = error;
+                repeated.occurrences += 1;
+                repeated.last = now;
+                None
+            }
+            _ => self.pending.replace(RepeatedReadError::new(error, now)),
+        }
+    }
+    /// Checks if the given number of reads failed in a row (see 'TcpIpcConfig::read_error_limit').
+    pub fn limit_reached(&self, limit: Option<usize>) -> bool {
+        limit.is_some_and(|limit| self.consecutive >= limit)
+    }
+    /// The number of reads which failed in a row.
+    pub fn consecutive(&self) -> usize {
+        self.consecutive
+    }
+    /// Ends the run, since a read succeeded (or found no data). Returns the collapsed errors to be reported.
+    pub fn ended(&mut self) -> Option<RepeatedReadError> {
+        self.consecutive = 0;
+        self.pending.take()
+    }
+    /// Takes the collapsed errors to be reported (for example, before the read thread finishes), without ending the run.
+    pub fn take(&mut self) -> Option<RepeatedReadError> {
+        self.pending.take()
+    }
+    /// Forgets the run, since the connection is re-established.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn a_successful_read_ends_the_run() {
+        let now = Instant::now();
+        let mut run = ReadErrorRun::default();
+        assert!(run.failed(ErrorKind::Other.into(), now).is_none());
+        assert!(run.failed(ErrorKind::Other.into(), now).is_none());
+        assert!(run.limit_reached(Some(2)));
+        let repeated = run.ended().unwrap();
+        assert_eq!(repeated.occurrences, 2);
+        assert_eq!(run.consecutive(), 0);
+        assert!(!run.limit_reached(Some(2)));
+        // without a limit, reading never stops
+        assert!(run.failed(ErrorKind::Other.into(), now).is_none());
+        assert!(!run.limit_reached(None));
+        run.reset();
+        assert!(run.take().is_none());
+    }
+}
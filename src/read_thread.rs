@@ -0,0 +1,1640 @@
+use super::dedup::DedupFilter;
+use super::engine::TcpStream;
+use super::lanes::{close_lanes, route, SharedLaneRouter};
+use super::memory_budget::{self, SharedMemoryBudget};
+use super::os_errors::{is_closed_by_peer, is_no_data, normalize};
+use super::outgoing_queue::*;
+use super::probe::{self, SharedFirstBytes};
+use super::protocol_buffer::*;
+use super::rate_limit::{self, SharedTokenBucket};
+use super::read_errors::ReadErrorRun;
+use super::reconnect::{ConnectionEvent, ReconnectStep, Reconnecting, SharedReconnectState};
+use super::registry::ConnectionId;
+use super::reliability::*;
+use super::response_budget::BudgetTracker;
+use super::schedule::ScheduledSend;
+use super::session::{SessionToken, SharedSessionToken};
+use super::stats::{command_received, command_sent, SharedCommandStats, StatsCounters};
+use super::tap::{tap, FrameDirection};
+use super::tcp_ipc::{
+    configure_stream, ConnectErrors, ImmediateFailurePolicy, Priority, RepeatedReadError,
+    RetainedPayload, Strictness, TcpIpcConfig, TruncatedFramePolicy,
+};
+use super::trace::{
+    trace_received, trace_sent, trace_start, FrameDisposition, IncomingTraceEntry, SharedTrace,
+    TraceEntry,
+};
+use log::*;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+/// The size of the buffer the read thread reads into.
+pub const BUFFER_SIZE: usize = 128;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ReadThreadErrorsInternal<P: Protocol> {
+    WriteError(std::io::Error),
+    ReadError(std::io::Error),
+    RepeatedReadError(RepeatedReadError),
+    ImmediateMessageConstructError((P::Commands, RetainedPayload)),
+    ProtocolViolation(ProtocolViolation),
+    PeerClosed(Option<TruncatedFrame<P>>),
+}
+
+/// A message (together with its sequence number) or an error, as sent by the read thread.
+pub type Incoming<P> = Result<(u64, Message<P>), ReadThreadErrorsInternal<P>>;
+
+/// The busy states applied by the read thread, each with the sequence number of the first message parsed with it.
+/// This is shared with the main thread, which removes entries no longer needed for undelivered messages.
+pub type BusyStateTimeline<P> = Arc<Mutex<VecDeque<(u64, <P as Protocol>::BusyStates)>>>;
+pub fn new_busy_state_timeline<P: Protocol>(busy_state: P::BusyStates) -> BusyStateTimeline<P> {
+    Arc::new(Mutex::new(VecDeque::from(vec![(0, busy_state)])))
+}
+
+/// What the peer declared about itself, set by the read thread once (see 'Protocol::peer_info').
+pub type SharedPeerInfo = Arc<Mutex<Option<PeerInfo>>>;
+
+/// The progress of a shutdown initiated by the peer, shared by the read thread & the main thread.
+#[derive(Debug)]
+pub struct PeerShutdown {
+    // the sequence number of the first message forwarded after the peer's goodbye (u64::MAX until a goodbye was received)
+    first_after_goodbye: AtomicU64,
+    end_of_stream: AtomicBool,
+}
+impl Default for PeerShutdown {
+    fn default() -> Self {
+        Self {
+            first_after_goodbye: AtomicU64::new(u64::MAX),
+            end_of_stream: AtomicBool::new(false),
+        }
+    }
+}
+impl PeerShutdown {
+    fn goodbye_received(&self, first_after_goodbye: u64) {
+        // only the first goodbye counts
+        let _ = self.first_after_goodbye.compare_exchange(
+            u64::MAX,
+            first_after_goodbye,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+    pub fn is_closing(&self) -> bool {
+        self.first_after_goodbye.load(Ordering::SeqCst) != u64::MAX
+    }
+    /// Checks if the message with the given sequence number was received after the peer's goodbye.
+    pub fn received_during_shutdown(&self, sequence: u64) -> bool {
+        sequence >= self.first_after_goodbye.load(Ordering::SeqCst)
+    }
+    pub fn is_closed(&self) -> bool {
+        self.end_of_stream.load(Ordering::SeqCst)
+    }
+    /// Forgets a goodbye of the previous connection, once a client reconnected (see 'TcpIpcConfig::reconnect').
+    fn reconnected(&self) {
+        self.first_after_goodbye.store(u64::MAX, Ordering::SeqCst);
+    }
+}
+
+/// Errors of these kinds indicate that the stream cannot be used anymore.
+pub fn is_fatal_stream_error(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        kind,
+        ConnectionReset | ConnectionAborted | BrokenPipe | NotConnected | UnexpectedEof
+    )
+}
+
+/// The read thread's ends of the channels to the main thread.
+pub struct ReadThreadChannels<P: Protocol> {
+    pub message_sender: Sender<Incoming<P>>,
+    pub busy_state_receiver: Receiver<P::BusyStates>,
+    pub busy_state_query_receiver: Receiver<()>,
+    pub busy_state_queried_sender: Sender<P::BusyStates>,
+    pub parser_state_query_receiver: Receiver<()>,
+    pub parser_state_queried_sender: Sender<ParserState<P>>,
+    pub shutdown_receiver: Receiver<()>,
+    pub shutdown_ack_sender: Sender<usize>,
+    pub schedule_receiver: Receiver<ScheduledSend<P>>,
+    pub restart_receiver: Receiver<Sender<RestartHandover<P>>>,
+}
+
+/// The main thread's ends of the channels to the read thread.
+pub struct MainThreadChannels<P: Protocol> {
+    pub message_receiver: Receiver<Incoming<P>>,
+    pub busy_state_sender: Sender<P::BusyStates>,
+    pub busy_state_query_sender: Sender<()>,
+    pub busy_state_queried_receiver: Receiver<P::BusyStates>,
+    pub parser_state_query_sender: Sender<()>,
+    pub parser_state_queried_receiver: Receiver<ParserState<P>>,
+    pub shutdown_sender: Sender<()>,
+    pub shutdown_ack_receiver: Receiver<usize>,
+    pub schedule_sender: Sender<ScheduledSend<P>>,
+    pub restart_sender: Sender<Sender<RestartHandover<P>>>,
+}
+
+/// Creates the channels between the main thread & a read thread.
+pub fn channels<P: Protocol>() -> (ReadThreadChannels<P>, MainThreadChannels<P>) {
+    let (message_sender, message_receiver) = channel();
+    let (busy_state_sender, busy_state_receiver) = channel();
+    let (busy_state_query_sender, busy_state_query_receiver) = channel();
+    let (busy_state_queried_sender, busy_state_queried_receiver) = channel();
+    let (parser_state_query_sender, parser_state_query_receiver) = channel();
+    let (parser_state_queried_sender, parser_state_queried_receiver) = channel();
+    let (shutdown_sender, shutdown_receiver) = channel();
+    let (shutdown_ack_sender, shutdown_ack_receiver) = channel();
+    let (schedule_sender, schedule_receiver) = channel();
+    let (restart_sender, restart_receiver) = channel();
+    (
+        ReadThreadChannels {
+            message_sender,
+            busy_state_receiver,
+            busy_state_query_receiver,
+            busy_state_queried_sender,
+            parser_state_query_receiver,
+            parser_state_queried_sender,
+            shutdown_receiver,
+            shutdown_ack_sender,
+            schedule_receiver,
+            restart_receiver,
+        },
+        MainThreadChannels {
+            message_receiver,
+            busy_state_sender,
+            busy_state_query_sender,
+            busy_state_queried_receiver,
+            parser_state_query_sender,
+            parser_state_queried_receiver,
+            shutdown_sender,
+            shutdown_ack_receiver,
+            schedule_sender,
+            restart_sender,
+        },
+    )
+}
+
+/// What a read thread hands over to its successor when it stops for a restart (see 'TcpIpc::restart_read_thread').
+pub struct RestartHandover<P: Protocol> {
+    protocol: ProtocolBuffer<P>,
+    buffered_reserved: usize,
+    next_sequence: u64,
+    scheduled: Vec<ScheduledSend<P>>,
+    reliable: Option<ReliableReceiver<P>>,
+    dedup: Option<DedupFilter>,
+}
+
+enum ReadThreadState {
+    Running,
+    // the read loop is left, immediate responses are drained since the given instant
+    Draining(std::time::Instant),
+    Finished,
+}
+
+/// The reading side of a single connection.
+/// Each call of 'step' does one read iteration, so a connection can be driven by its own thread or together with others.
+pub struct ReadThread<P: Protocol> {
+    id: ConnectionId,
+    stream: TcpStream,
+    config: TcpIpcConfig<P>,
+    channels: ReadThreadChannels<P>,
+    protocol: ProtocolBuffer<P>,
+    incoming_buffer: [u8; BUFFER_SIZE],
+    outgoing: SharedOutgoingQueue,
+    connection_closed: Arc<AtomicBool>,
+    peer_shutdown: Arc<PeerShutdown>,
+    busy_state_timeline: BusyStateTimeline<P>,
+    stats: Arc<StatsCounters>,
+    command_stats: Option<SharedCommandStats<P>>,
+    outgoing_trace: Option<SharedTrace<TraceEntry<P>>>,
+    incoming_trace: Option<SharedTrace<IncomingTraceEntry<P>>>,
+    lanes: SharedLaneRouter<P>,
+    // taken once the peer info is known
+    peer_info: Option<SharedPeerInfo>,
+    control_check_interval: std::time::Duration,
+    last_control_check: std::time::Instant,
+    // control requests are handled before the first read and whenever no data was available
+    idle: bool,
+    close_stream: bool,
+    abandoned: usize,
+    state: ReadThreadState,
+    // the bytes sent at the last check, to detect when the connection was last written to
+    last_bytes_sent: u64,
+    last_write_activity: std::time::Instant,
+    // the sequence number of the next message forwarded to the main thread
+    next_sequence: u64,
+    reliable: Option<ReliableReceiver<P>>,
+    // the unacknowledged reliable messages, which are sent again after a reconnect
+    retransmit_buffer: SharedRetransmitBuffer<P>,
+    dedup: Option<DedupFilter>,
+    budget: Option<BudgetTracker<P>>,
+    scheduled: Vec<ScheduledSend<P>>,
+    // None if the frames of the read thread are not rate-limited
+    rate_limiter: Option<SharedTokenBucket>,
+    // rate-limited frames of the read thread which wait for a token, in order (reserved in the memory budget)
+    throttled: VecDeque<Vec<u8>>,
+    // None once enough bytes were captured for a probe, or if no probe is configured
+    first_bytes: Option<SharedFirstBytes>,
+    memory_budget: Option<SharedMemoryBudget>,
+    // the bytes of the parse buffer which are reserved in the memory budget
+    buffered_reserved: usize,
+    // a parsed header waits until its payload fits into the memory budget
+    parse_deferred: bool,
+    // set once a restart was requested, to hand the state over instead of finishing
+    restart: Option<Sender<RestartHandover<P>>>,
+    // the reads failing in a row, whose errors are collapsed until another kind or data arrives
+    read_errors: ReadErrorRun,
+    // frames of the read thread which wait for the outgoing queue, since the main thread held it meanwhile (reserved in the memory budget)
+    staged: Vec<Vec<u8>>,
+    // set for a client which re-establishes a lost connection (see 'TcpIpcConfig::reconnect')
+    reconnect: Option<SharedReconnectState>,
+    // Some while the connection is lost & being re-established
+    reconnecting: Option<Reconnecting>,
+    // set for a client whose protocol has sessions (see 'Protocol::session_command')
+    session: Option<SharedSessionToken>,
+    // replaces the stream for reading in the unit tests, to inject read errors
+    #[cfg(test)]
+    transport: Option<Box<dyn Read + Send>>,
+}
+impl<P: Protocol> ReadThread<P> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: ConnectionId,
+        stream: TcpStream,
+        config: TcpIpcConfig<P>,
+        channels: ReadThreadChannels<P>,
+        outgoing: SharedOutgoingQueue,
+        connection_closed: Arc<AtomicBool>,
+        peer_shutdown: Arc<PeerShutdown>,
+        busy_state_timeline: BusyStateTimeline<P>,
+        stats: Arc<StatsCounters>,
+        command_stats: Option<SharedCommandStats<P>>,
+        outgoing_trace: Option<SharedTrace<TraceEntry<P>>>,
+        incoming_trace: Option<SharedTrace<IncomingTraceEntry<P>>>,
+        lanes: SharedLaneRouter<P>,
+        peer_info: SharedPeerInfo,
+        retransmit_buffer: SharedRetransmitBuffer<P>,
+        rate_limiter: Option<SharedTokenBucket>,
+        first_bytes: Option<SharedFirstBytes>,
+    ) -> Self {
+        Self {
+            memory_budget: stats.memory_budget().clone(),
+            buffered_reserved: 0,
+            parse_deferred: false,
+            restart: None,
+            read_errors: ReadErrorRun::default(),
+            staged: Vec::new(),
+            throttled: VecDeque::new(),
+            reconnect: None,
+            reconnecting: None,
+            session: None,
+            #[cfg(test)]
+            transport: None,
+            first_bytes,
+            rate_limiter: rate_limiter.filter(|_| {
+                config
+                    .outgoing_rate_limit
+                    .is_some_and(|limit| limit.include_immediate_responses)
+            }),
+            reliable: config
+                .reliability
+                .map(|reliability| ReliableReceiver::new(retransmit_buffer.clone(), reliability)),
+            retransmit_buffer,
+            id,
+            dedup: config.dedup_window.map(DedupFilter::new),
+            budget: config
+                .immediate_response_budget
+                .clone()
+                .map(BudgetTracker::new),
+            stream,
+            control_check_interval: config.effective_control_check_interval(),
+            protocol: ProtocolBuffer::with_busy_state(
+                config.initial_busy_state.unwrap_or_else(P::idle),
+            ),
+            config,
+            channels,
+            incoming_buffer: [0; BUFFER_SIZE],
+            outgoing,
+            connection_closed,
+            peer_shutdown,
+            busy_state_timeline,
+            stats,
+            command_stats,
+            outgoing_trace,
+            incoming_trace,
+            lanes,
+            peer_info: Some(peer_info),
+            last_control_check: std::time::Instant::now(),
+            idle: true,
+            close_stream: false,
+            abandoned: 0,
+            state: ReadThreadState::Running,
+            last_bytes_sent: 0,
+            last_write_activity: std::time::Instant::now(),
+            next_sequence: 0,
+            scheduled: Vec::new(),
+        }
+    }
+    /// Runs the read thread on the current thread, until it is finished.
+    pub fn run(mut self) {
+        info!("{}: Read thread started", self.id);
+        while self.step() {
+            // wait between loops
+            if let Some(read_iteration_wait_time) = self.config.read_iteration_wait_time {
+                std::thread::sleep(read_iteration_wait_time);
+            }
+        }
+        info!("{}: Read thread finished", self.id);
+    }
+    /// Does one iteration: handling control requests, reading, answering via the immediate route & writing immediate responses.
+    /// After the read loop is left, each call drains immediate responses instead.
+    /// Returns false once the read thread is finished.
+    pub fn step(&mut self) -> bool {
+        match self.state {
+            ReadThreadState::Running => {
+                if !self.read_iteration() {
+                    self.state = ReadThreadState::Draining(std::time::Instant::now());
+                    self.drain_step();
+                }
+            }
+            ReadThreadState::Draining(_) => self.drain_step(),
+            ReadThreadState::Finished => {}
+        }
+        !matches!(self.state, ReadThreadState::Finished)
+    }
+    /// Handles control requests (shutdown, busy state updates & queries) right away, without reading.
+    /// Returns false once the read thread is finished.
+    pub fn handle_control(&mut self) -> bool {
+        if matches!(self.state, ReadThreadState::Running) && !self.handle_control_requests() {
+            self.state = ReadThreadState::Draining(std::time::Instant::now());
+            self.drain_step();
+        }
+        !matches!(self.state, ReadThreadState::Finished)
+    }
+    /// Writes queued frames, as far as possible without blocking.
+    pub fn flush(&mut self) {
+        if matches!(self.state, ReadThreadState::Running) && !self.flush_outgoing() {
+            self.state = ReadThreadState::Draining(std::time::Instant::now());
+            self.drain_step();
+        }
+    }
+    /// Checks if the last read found no data.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+    /// Re-establishes a lost connection instead of finishing, see 'TcpIpcConfig::reconnect'.
+    pub fn enable_reconnect(&mut self, reconnect: Option<SharedReconnectState>) {
+        self.reconnect = reconnect;
+    }
+    /// Keeps the session token the server answers with & presents it after a reconnect, see 'Protocol::session_command'.
+    pub fn enable_session(&mut self, session: Option<SharedSessionToken>) {
+        self.session = session;
+    }
+    // returns false if the read loop is to be left
+    fn read_iteration(&mut self) -> bool {
+        if self.reconnecting.is_some() {
+            // nothing can be read or written meanwhile, but control requests (like a shutdown) are handled
+            return self.handle_control_requests() && self.reconnect_step();
+        }
+        if (self.idle || self.last_control_check.elapsed() >= self.control_check_interval)
+            && !self.handle_control_requests()
+        {
+            return false;
+        }
+        self.handle_incoming() && self.send_scheduled() && self.flush_outgoing()
+    }
+    fn handle_control_requests(&mut self) -> bool {
+        self.last_control_check = std::time::Instant::now();
+        match self.channels.shutdown_receiver.try_recv() {
+            Ok(()) => return false,
+            Err(TryRecvError::Empty) => {
+                // nothing to do
+            }
+            Err(TryRecvError::Disconnected) => return disconnected(self.id),
+        }
+        match self.channels.busy_state_query_receiver.try_recv() {
+            Ok(()) => {
+                if self
+                    .channels
+                    .busy_state_queried_sender
+                    .send(self.protocol.get_busy_state())
+                    .is_err()
+                {
+                    return disconnected(self.id);
+                }
+            }
+            Err(TryRecvError::Empty) => {
+                // nothing to do
+            }
+            Err(TryRecvError::Disconnected) => return disconnected(self.id),
+        }
+        match self.channels.parser_state_query_receiver.try_recv() {
+            Ok(()) => {
+                if self
+                    .channels
+                    .parser_state_queried_sender
+                    .send(self.protocol.parser_state())
+                    .is_err()
+                {
+                    return disconnected(self.id);
+                }
+            }
+            Err(TryRecvError::Empty) => {
+                // nothing to do
+            }
+            Err(TryRecvError::Disconnected) => return disconnected(self.id),
+        }
+        self.check_write_idle();
+        loop {
+            match self.channels.schedule_receiver.try_recv() {
+                Ok(scheduled) => self.scheduled.push(scheduled),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return disconnected(self.id),
+            }
+        }
+        loop {
+            match self.channels.busy_state_receiver.try_recv() {
+                Ok(busy_state) => {
+                    self.protocol.update_busy_state(busy_state);
+                    let mut timeline = self
+                        .busy_state_timeline
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner());
+                    // a state which applied to no message is replaced
+                    if timeline.back().map(|(first, _)| *first) == Some(self.next_sequence) {
+                        timeline.pop_back();
+                    }
+                    timeline.push_back((self.next_sequence, busy_state));
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return disconnected(self.id),
+            }
+        }
+        // a restart is handled last, so the busy states updated before are handed over
+        if let Ok(restart) = self.channels.restart_receiver.try_recv() {
+            self.restart = Some(restart);
+            return false;
+        }
+        true
+    }
+    // queues a ping if nothing was written for the configured time
+    fn check_write_idle(&mut self) {
+        if let Some((write_idle_time, command)) = self.config.write_idle_ping {
+            let bytes_sent = self.stats.bytes_sent();
+            if bytes_sent != self.last_bytes_sent {
+                self.last_bytes_sent = bytes_sent;
+                self.last_write_activity = std::time::Instant::now();
+            } else if self.last_write_activity.elapsed() >= write_idle_time {
+                match P::construct_message(command, &[]) {
+                    Some(ping) => {
+                        self.stats.control_frame_sent(ping.len());
+                        command_sent::<P>(&self.command_stats, command);
+                        trace_sent::<P>(&self.outgoing_trace, command, &[]);
+                        tap::<P>(&self.config.frame_tap, FrameDirection::Sent, &command, &[]);
+                        queue(
+                            self.id,
+                            &self.outgoing,
+                            &self.stats,
+                            &mut self.staged,
+                            &self.memory_budget,
+                            ping,
+                            "Ping",
+                        );
+                    }
+                    None => warn!("{}: Ping {:?} could not be constructed", self.id, command),
+                }
+                self.last_write_activity = std::time::Instant::now();
+            }
+        }
+    }
+    // queues scheduled messages which are due
+    fn send_scheduled(&mut self) -> bool {
+        if self.scheduled.is_empty() {
+            return true;
+        }
+        let now = std::time::Instant::now();
+        let mut due = Vec::new();
+        for scheduled in &mut self.scheduled {
+            if scheduled.is_finished() {
+                continue;
+            }
+            if let Some(payload) = scheduled.poll(now) {
+                if scheduled.interval.is_none() {
+                    scheduled.finish();
+                }
+                match P::construct_message(scheduled.command, &payload) {
+                    Some(message) => {
+                        self.stats.message_sent(message.len());
+                        command_sent::<P>(&self.command_stats, scheduled.command);
+                        trace_sent::<P>(&self.outgoing_trace, scheduled.command, &payload);
+                        tap::<P>(
+                            &self.config.frame_tap,
+                            FrameDirection::Sent,
+                            &scheduled.command,
+                            &payload,
+                        );
+                        due.push(message);
+                    }
+                    None => {
+                        if self
+                            .channels
+                            .message_sender
+                            .send(Err(
+                                ReadThreadErrorsInternal::ImmediateMessageConstructError((
+                                    scheduled.command,
+                                    RetainedPayload::new(
+                                        payload,
+                                        self.config.error_payload_retention,
+                                    ),
+                                )),
+                            ))
+                            .is_err()
+                        {
+                            return disconnected(self.id);
+                        }
+                    }
+                }
+            }
+        }
+        self.scheduled.retain(|scheduled| !scheduled.is_finished());
+        for message in due {
+            self.queue_limited(message, "Scheduled message");
+        }
+        true
+    }
+    fn handle_incoming(&mut self) -> bool {
+        if self.parse_deferred {
+            // a message waits for the memory budget, so nothing more is read before it fits
+            let keep_reading = self.parse(0);
+            self.idle = self.parse_deferred;
+            return keep_reading;
+        }
+        let read_limit = self.read_limit();
+        if read_limit == 0 {
+            // reading pauses until the main thread (or a lane handle) takes messages, which frees the memory budget
+            self.idle = true;
+            return true;
+        }
+        self.stats.read_iteration();
+        match self.read_incoming(read_limit) {
+            Ok(0) => {
+                info!(
+                    "{}: Connection closed by peer. Read thread will be shut down.",
+                    self.id
+                );
+                self.idle = true;
+                let pending = self.read_errors.take();
+                if !self.report_read_error(pending) {
+                    return false;
+                }
+                if self.reconnect.is_none() {
+                    self.peer_shutdown
+                        .end_of_stream
+                        .store(true, Ordering::SeqCst);
+                    self.connection_closed.store(true, Ordering::SeqCst);
+                }
+                // an aborted connection may look like a regular close, except for the pending socket error
+                if let Ok(Some(err)) = self.stream.take_error() {
+                    let err = normalize(err);
+                    info!("{}: Pending socket error: {:?}", self.id, err);
+                    if self
+                        .channels
+                        .message_sender
+                        .send(Err(ReadThreadErrorsInternal::ReadError(err)))
+                        .is_err()
+                    {
+                        return disconnected(self.id);
+                    }
+                }
+                // the partially received message is not delivered, only described (if configured)
+                let mid_frame = self.protocol.parser_state().truncated_frame();
+                let mid_frame = match (mid_frame, self.config.on_truncated_frame) {
+                    (Some(mid_frame), TruncatedFramePolicy::Report) => {
+                        warn!(
+                            "{}: Connection closed by peer in the middle of a message: {:?}",
+                            self.id, mid_frame
+                        );
+                        Some(mid_frame)
+                    }
+                    (Some(mid_frame), TruncatedFramePolicy::Drop) => {
+                        debug!(
+                            "{}: Connection closed by peer in the middle of a message, which is dropped: {:?}",
+                            self.id, mid_frame
+                        );
+                        None
+                    }
+                    (None, _) => None,
+                };
+                if self
+                    .channels
+                    .message_sender
+                    .send(Err(ReadThreadErrorsInternal::PeerClosed(mid_frame)))
+                    .is_err()
+                {
+                    return disconnected(self.id);
+                }
+                self.connection_lost()
+            }
+            Ok(message_length) => {
+                self.idle = false;
+                let pending = self.read_errors.ended();
+                if !self.report_read_error(pending) {
+                    return false;
+                }
+                self.stats.bytes_received(message_length);
+                let buffer = &self.incoming_buffer[0..message_length];
+                if let Some(first_bytes) = &self.first_bytes {
+                    if !probe::capture(first_bytes, buffer) {
+                        self.first_bytes = None;
+                    }
+                }
+                if log_enabled!(Level::Trace) {
+                    trace!("{}: New incoming buffer: {:?}", self.id, buffer);
+                }
+                self.parse(message_length)
+            }
+            Err(err) => {
+                // nothing was read, so the reservation for the read is given back
+                self.buffered_changed();
+                let err = normalize(err);
+                if is_no_data(err.kind()) {
+                    // this is interpreted as "no message available"
+                    self.idle = true;
+                    let pending = self.read_errors.ended();
+                    return self.report_read_error(pending);
+                }
+                let fatal = is_fatal_stream_error(err.kind());
+                if is_closed_by_peer(err.kind()) && self.reconnect.is_none() {
+                    info!("{}: Connection reset by peer.", self.id);
+                    self.peer_shutdown
+                        .end_of_stream
+                        .store(true, Ordering::SeqCst);
+                }
+                let replaced = self.read_errors.failed(err, std::time::Instant::now());
+                if !self.report_read_error(replaced) {
+                    return false;
+                }
+                let limit_reached = self.read_errors.limit_reached(self.config.read_error_limit);
+                if fatal || limit_reached {
+                    let pending = self.read_errors.take();
+                    if !self.report_read_error(pending) {
+                        return false;
+                    }
+                }
+                if limit_reached && !fatal {
+                    warn!(
+                        "{}: {} reads failed in a row. Read thread will be shut down.",
+                        self.id,
+                        self.read_errors.consecutive()
+                    );
+                    self.connection_closed.store(true, Ordering::SeqCst);
+                    return false;
+                }
+                if fatal && self.reconnect.is_some() {
+                    return self.connection_lost();
+                }
+                if fatal {
+                    info!(
+                        "{}: Connection failed. Read thread will be shut down.",
+                        self.id
+                    );
+                    if let Some(mid_frame) = self.protocol.parser_state().truncated_frame() {
+                        warn!(
+                            "{}: Connection failed in the middle of a message: {:?}",
+                            self.id, mid_frame
+                        );
+                    }
+                    self.connection_closed.store(true, Ordering::SeqCst);
+                    return false;
+                }
+                true
+            }
+        }
+    }
+    // reads from the stream into the incoming buffer
+    fn read_incoming(&mut self, read_limit: usize) -> std::io::Result<usize> {
+        let buffer = &mut self.incoming_buffer[..read_limit];
+        #[cfg(test)]
+        if let Some(transport) = &mut self.transport {
+            return transport.read(buffer);
+        }
+        self.stream.read(buffer)
+    }
+    // parses the given number of bytes of the incoming buffer & handles all completed frames
+    // returns false if the read loop is to be left
+    fn parse(&mut self, received: usize) -> bool {
+        let mut received = &self.incoming_buffer[..received];
+        loop {
+            let memory_budget = &self.memory_budget;
+            let mut deferred = false;
+            let mut reserved = 0;
+            let parsed = self
+                .protocol
+                .try_process_new_buffer_within(received, &mut |length| match memory_budget {
+                    // a message larger than the whole budget can never be held
+                    Some(budget) if length > budget.budget() => {
+                        budget.refused();
+                        Reservation::Refused
+                    }
+                    Some(budget) if !budget.try_reserve(length) => {
+                        deferred = true;
+                        Reservation::Deferred
+                    }
+                    Some(_) => {
+                        reserved = length;
+                        Reservation::Granted
+                    }
+                    None => Reservation::Granted,
+                });
+            self.parse_deferred = deferred;
+            self.buffered_changed();
+            let (command, message) = match parsed {
+                Ok(Some(message)) => message,
+                Ok(None) => return true,
+                Err(violation) => {
+                    // a granted payload which could not be allocated is not held
+                    memory_budget::release(&self.memory_budget, reserved);
+                    if !self.protocol_violation(violation) {
+                        return false;
+                    }
+                    // the offending header was discarded, so parsing continues with the bytes following it
+                    received = &[];
+                    continue;
+                }
+            };
+            received = &[];
+            tap::<P>(
+                &self.config.frame_tap,
+                FrameDirection::Received,
+                &command,
+                &message,
+            );
+            let length = message.len();
+            let traced = trace_start(&self.incoming_trace, &message);
+            let (disposition, keep_reading) = self.handle_frame(command, message);
+            if disposition != FrameDisposition::Delivered {
+                // only delivered messages keep their payload
+                memory_budget::release(&self.memory_budget, length);
+            }
+            // every frame is recorded here, whatever happened to it
+            trace_received::<P>(&self.incoming_trace, command, length, traced, disposition);
+            if !keep_reading {
+                return false;
+            }
+        }
+    }
+    // the number of bytes which can be read now, given the memory budget (if any)
+    // bytes completing a payload are already reserved, further bytes are held by the parse buffer until they form a message
+    fn read_limit(&mut self) -> usize {
+        let budget = match &self.memory_budget {
+            Some(budget) => budget,
+            None => return BUFFER_SIZE,
+        };
+        let state = self.protocol.parser_state();
+        let missing_payload = if state.command.is_some() {
+            state.declared - state.received
+        } else {
+            0
+        };
+        let limit = BUFFER_SIZE.min(missing_payload.saturating_add(budget.available()));
+        let buffered = limit.saturating_sub(missing_payload);
+        if !budget.try_reserve(buffered) {
+            // the budget was used meanwhile
+            return missing_payload.min(BUFFER_SIZE);
+        }
+        self.buffered_reserved += buffered;
+        limit
+    }
+    // adjusts the reservation of the parse buffer to the bytes it holds
+    fn buffered_changed(&mut self) {
+        let buffered = self.protocol.parser_state().buffered;
+        if buffered < self.buffered_reserved {
+            memory_budget::release(&self.memory_budget, self.buffered_reserved - buffered);
+            self.buffered_reserved = buffered;
+        }
+    }
+    // handles a parsed frame: the reliability layer, deduplication, the immediate route or the delivery to the main thread
+    // returns what happened to the frame & false if the read loop is to be left
+    fn handle_frame(
+        &mut self,
+        command: P::Commands,
+        mut message: Vec<u8>,
+    ) -> (FrameDisposition, bool) {
+        let reserved = message.len();
+        self.stats
+            .message_received(std::mem::size_of::<P::HeaderAsArray>() + message.len());
+        command_received::<P>(&self.command_stats, command);
+        if let Some(session) = &self.session {
+            if P::session_command() == Some(command) {
+                match SessionToken::from_payload(&message) {
+                    Some(token) => {
+                        info!("{}: Session {}", self.id, token);
+                        *session.lock().unwrap_or_else(|e| e.into_inner()) = Some(token);
+                    }
+                    None => warn!(
+                        "{}: Session answer of {} bytes is no session token",
+                        self.id,
+                        message.len()
+                    ),
+                }
+                return (FrameDisposition::SessionToken, true);
+            }
+        }
+        if let Some(reliable) = &mut self.reliable {
+            match reliable.receive(&command, &mut message) {
+                Received::Unreliable => {}
+                Received::Acknowledgment => return (FrameDisposition::Acknowledgment, true),
+                Received::Reliable { id, duplicate } => {
+                    let ack = P::ack_command().and_then(|ack_command| {
+                        P::construct_message(ack_command, &id.to_be_bytes())
+                    });
+                    match ack {
+                        Some(ack) => {
+                            self.stats.control_frame_sent(ack.len());
+                            if let Some(ack_command) = P::ack_command() {
+                                command_sent::<P>(&self.command_stats, ack_command);
+                                trace_sent::<P>(
+                                    &self.outgoing_trace,
+                                    ack_command,
+                                    &id.to_be_bytes(),
+                                );
+                                tap::<P>(
+                                    &self.config.frame_tap,
+                                    FrameDirection::Sent,
+                                    &ack_command,
+                                    &id.to_be_bytes(),
+                                );
+                            }
+                            queue(
+                                self.id,
+                                &self.outgoing,
+                                &self.stats,
+                                &mut self.staged,
+                                &self.memory_budget,
+                                ack,
+                                "Acknowledgment",
+                            );
+                        }
+                        None => warn!(
+                            "{}: Acknowledgment for frame {} could not be constructed",
+                            self.id, id
+                        ),
+                    }
+                    if duplicate {
+                        debug!("{}: Duplicate of frame {} suppressed", self.id, id);
+                        return (FrameDisposition::Dropped, true);
+                    }
+                }
+            }
+        }
+        let duplicate = match &mut self.dedup {
+            Some(dedup) => P::dedup_key(&command, &message)
+                .is_some_and(|key| dedup.is_duplicate(key, std::time::Instant::now())),
+            None => false,
+        };
+        if duplicate {
+            debug!("{}: Duplicate of {:?} dropped", self.id, command);
+            self.stats.duplicate_dropped();
+            return (FrameDisposition::Dropped, true);
+        }
+        if self.peer_info.is_some() {
+            if let Some(peer_info) = P::peer_info(&command, &message) {
+                debug!("{}: Peer info: {:?}", self.id, peer_info);
+                if let Some(shared) = self.peer_info.take() {
+                    *shared.lock().unwrap_or_else(|e| e.into_inner()) = Some(peer_info);
+                }
+            }
+        }
+        let received_command = command;
+        let goodbye = Some(command) == P::shutdown_command();
+        // a goodbye is recorded once: before it is delivered, or otherwise once the frame is handled
+        let mut goodbye_recorded = false;
+        let demoted = self
+            .budget
+            .as_ref()
+            .is_some_and(|budget| budget.is_demoted(&command));
+        let started = self.budget.as_ref().map(|_| std::time::Instant::now());
+        let immediate = if demoted {
+            None
+        } else {
+            let busy_state = self.protocol.get_busy_state();
+            // the table decides first, the protocol only if no rule matched
+            match self
+                .config
+                .immediate_responses
+                .as_ref()
+                .and_then(|table| table.decide(&command, &message, &busy_state))
+            {
+                Some(decision) => decision,
+                None => P::message_is_answered_via_immediate_route(&command, &message, &busy_state),
+            }
+        };
+        let disposition = if let Some((command, message)) = immediate {
+            let disposition = if let Some(frame) = P::construct_message(command, &message) {
+                self.stats.immediate_response_sent(frame.len());
+                command_sent::<P>(&self.command_stats, command);
+                trace_sent::<P>(&self.outgoing_trace, command, &message);
+                tap::<P>(
+                    &self.config.frame_tap,
+                    FrameDirection::Sent,
+                    &command,
+                    &message,
+                );
+                self.queue_limited(frame, "Immediate response");
+                FrameDisposition::AnsweredImmediately
+            } else {
+                let fallback = match &self.config.on_immediate_construct_failure {
+                    ImmediateFailurePolicy::ReportOnly => Ok(None),
+                    ImmediateFailurePolicy::SendFallbackFrame(command, message) => {
+                        P::construct_message(*command, message).map(Some).ok_or(())
+                    }
+                    ImmediateFailurePolicy::CloseConnection => Err(()),
+                };
+                if self
+                    .channels
+                    .message_sender
+                    .send(Err(
+                        ReadThreadErrorsInternal::ImmediateMessageConstructError((
+                            command,
+                            RetainedPayload::new(message, self.config.error_payload_retention),
+                        )),
+                    ))
+                    .is_err()
+                {
+                    return (FrameDisposition::Failed, disconnected(self.id));
+                }
+                match fallback {
+                    Ok(Some(fallback)) => {
+                        self.stats.immediate_response_sent(fallback.len());
+                        if let ImmediateFailurePolicy::SendFallbackFrame(command, message) =
+                            &self.config.on_immediate_construct_failure
+                        {
+                            command_sent::<P>(&self.command_stats, *command);
+                            trace_sent::<P>(&self.outgoing_trace, *command, message);
+                            tap::<P>(
+                                &self.config.frame_tap,
+                                FrameDirection::Sent,
+                                command,
+                                message,
+                            );
+                        }
+                        self.queue_limited(fallback, "Fallback frame")
+                    }
+                    Ok(None) => {}
+                    Err(()) => {
+                        warn!("{}: Immediate response could not be constructed. Connection will be closed.", self.id);
+                        self.connection_closed.store(true, Ordering::SeqCst);
+                        self.close_stream = true;
+                        return (FrameDisposition::Failed, false);
+                    }
+                }
+                FrameDisposition::Failed
+            };
+            self.immediate_route_finished(&received_command, started);
+            disposition
+        } else {
+            if !demoted {
+                self.immediate_route_finished(&command, started);
+            }
+            // the reservation of a delivered message is released once it is taken, so it has to match its payload
+            // (without the frame id removed by the reliability layer)
+            let unheld = reserved - message.len();
+            match route::<P>(&self.lanes, (command, message)) {
+                Ok(true) => {
+                    memory_budget::release(&self.memory_budget, unheld);
+                    FrameDisposition::Delivered
+                }
+                Ok(false) => {
+                    debug!("{}: Lane of {:?} is full", self.id, command);
+                    FrameDisposition::Dropped
+                }
+                // the connection is not split
+                Err(message) => {
+                    let sequence = self.next_sequence;
+                    self.next_sequence += 1;
+                    // a delivered goodbye is recorded before, so the state is 'PeerClosing' once the goodbye can be taken
+                    if goodbye {
+                        self.peer_shutdown.goodbye_received(self.next_sequence);
+                        goodbye_recorded = true;
+                    }
+                    if self
+                        .channels
+                        .message_sender
+                        .send(Ok((sequence, message)))
+                        .is_err()
+                    {
+                        return (FrameDisposition::Dropped, disconnected(self.id));
+                    }
+                    memory_budget::release(&self.memory_budget, unheld);
+                    FrameDisposition::Delivered
+                }
+            }
+        };
+        if goodbye && !goodbye_recorded {
+            self.peer_shutdown.goodbye_received(self.next_sequence);
+        }
+        (disposition, true)
+    }
+    // checks the time spent on the immediate route of a frame against the budget, if any
+    fn immediate_route_finished(
+        &mut self,
+        command: &P::Commands,
+        started: Option<std::time::Instant>,
+    ) {
+        if let (Some(budget), Some(started)) = (&mut self.budget, started) {
+            let now = std::time::Instant::now();
+            let elapsed = now.duration_since(started);
+            let over_budget = budget.check(self.id, command, elapsed, now);
+            self.stats.immediate_route_timed(elapsed, over_budget);
+        }
+    }
+    fn protocol_violation(&mut self, violation: ProtocolViolation) -> bool {
+        if self.config.strictness == Strictness::Lenient {
+            warn!(
+                "{}: Protocol violation: {:?}, incoming header: {:?}. The header is discarded.",
+                self.id, violation.error, violation.header
+            );
+            return self
+                .channels
+                .message_sender
+                .send(Err(ReadThreadErrorsInternal::ProtocolViolation(violation)))
+                .is_ok()
+                || disconnected(self.id);
+        }
+        warn!(
+            "{}: Protocol violation: {:?}, incoming header: {:?}. Connection will be closed.",
+            self.id, violation.error, violation.header
+        );
+        if let Some((command, message)) = P::fault_frame(&violation) {
+            match P::construct_message(command, &message) {
+                Some(fault) => {
+                    self.stats.control_frame_sent(fault.len());
+                    command_sent::<P>(&self.command_stats, command);
+                    trace_sent::<P>(&self.outgoing_trace, command, &message);
+                    tap::<P>(
+                        &self.config.frame_tap,
+                        FrameDirection::Sent,
+                        &command,
+                        &message,
+                    );
+                    queue(
+                        self.id,
+                        &self.outgoing,
+                        &self.stats,
+                        &mut self.staged,
+                        &self.memory_budget,
+                        fault,
+                        "Fault frame",
+                    );
+                }
+                None => warn!("{}: Fault frame could not be constructed", self.id),
+            }
+        }
+        self.connection_closed.store(true, Ordering::SeqCst);
+        self.close_stream = true;
+        if self
+            .channels
+            .message_sender
+            .send(Err(ReadThreadErrorsInternal::ProtocolViolation(violation)))
+            .is_err()
+        {
+            return disconnected(self.id);
+        }
+        false
+    }
+    // queues a frame of the read thread which uses a token, if these frames are rate-limited (see 'RateLimit::include_immediate_responses')
+    // the read thread never waits for a token: without one, the frame is held back (behind the frames held back before) & queued by a later iteration
+    fn queue_limited(&mut self, frame: Vec<u8>, what: &str) {
+        if self.rate_limiter.is_none() {
+            queue(
+                self.id,
+                &self.outgoing,
+                &self.stats,
+                &mut self.staged,
+                &self.memory_budget,
+                frame,
+                what,
+            );
+            return;
+        }
+        if !reserve(self.id, &self.stats, &self.memory_budget, frame.len(), what) {
+            return;
+        }
+        self.throttled.push_back(frame);
+        self.release_throttled();
+        if let Some(mut outgoing) = try_lock_outgoing(&self.outgoing) {
+            unstage(&mut outgoing, &mut self.staged);
+        }
+    }
+    // stages the held back frames, as long as tokens are available
+    fn release_throttled(&mut self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            while !self.throttled.is_empty() && rate_limit::take(rate_limiter, 1, false).is_ok() {
+                self.staged.extend(self.throttled.pop_front());
+            }
+        }
+    }
+    // write immediate responses, as far as possible without blocking
+    fn flush_outgoing(&mut self) -> bool {
+        self.release_throttled();
+        let result = match try_lock_outgoing(&self.outgoing) {
+            Some(mut outgoing) => {
+                unstage(&mut outgoing, &mut self.staged);
+                outgoing.flush(&mut self.stream)
+            }
+            // the main thread is writing, so the queued frames are written by a later iteration
+            None => Ok(()),
+        };
+        if let Err(err) = result {
+            let err = normalize(err);
+            let fatal = is_fatal_stream_error(err.kind());
+            if is_closed_by_peer(err.kind()) && self.reconnect.is_none() {
+                self.peer_shutdown
+                    .end_of_stream
+                    .store(true, Ordering::SeqCst);
+            }
+            if self
+                .channels
+                .message_sender
+                .send(Err(ReadThreadErrorsInternal::WriteError(err)))
+                .is_err()
+            {
+                return disconnected(self.id);
+            }
+            if fatal {
+                if self.reconnect.is_some() {
+                    return self.connection_lost();
+                }
+                info!(
+                    "{}: Connection failed. Read thread will be shut down.",
+                    self.id
+                );
+                self.connection_closed.store(true, Ordering::SeqCst);
+                return false;
+            }
+        }
+        true
+    }
+    // starts to re-establish the connection (if configured), returns false otherwise
+    fn connection_lost(&mut self) -> bool {
+        let reconnect = match &self.reconnect {
+            Some(reconnect) => reconnect.clone(),
+            None => return false,
+        };
+        info!("{}: Connection lost. Reconnecting.", self.id);
+        reconnect.lost();
+        self.idle = true;
+        self.read_errors.reset();
+        // the bytes of the lost connection are not continued by the new one
+        let state = self.protocol.parser_state();
+        if let Some(truncated) = state.truncated_frame() {
+            info!(
+                "{}: Partial frame discarded by the reconnect: {:?}",
+                self.id, truncated
+            );
+        }
+        // the payload of a parsed header was reserved in full
+        let payload_reserved = if state.command.is_some() {
+            state.declared
+        } else {
+            0
+        };
+        memory_budget::release(
+            &self.memory_budget,
+            std::mem::take(&mut self.buffered_reserved) + payload_reserved,
+        );
+        self.parse_deferred = false;
+        self.protocol = ProtocolBuffer::with_busy_state(self.protocol.get_busy_state());
+        self.reconnecting = Some(Reconnecting::new(reconnect.policy()));
+        true
+    }
+    // makes the next reconnect attempt if it is due, returns false once reconnecting was given up
+    fn reconnect_step(&mut self) -> bool {
+        let (reconnect, mut reconnecting) = match (&self.reconnect, self.reconnecting.take()) {
+            (Some(reconnect), Some(reconnecting)) => (reconnect.clone(), reconnecting),
+            _ => return true,
+        };
+        let step = match reconnecting.poll(&reconnect) {
+            ReconnectStep::Connected(stream) => {
+                match configure_stream(&stream, &self.config)
+                    .and_then(|()| stream.try_clone().map_err(ConnectErrors::TryCloneError))
+                {
+                    Ok(stream_main) => {
+                        // the tail of a partially written frame would corrupt the new stream
+                        if lock_outgoing(&self.outgoing).discard_partial() {
+                            warn!(
+                                "{}: Partially written frame dropped by the reconnect",
+                                self.id
+                            );
+                        }
+                        self.resend_unacknowledged();
+                        self.present_session();
+                        self.stream = stream;
+                        self.peer_shutdown.reconnected();
+                        reconnect.reconnected(stream_main, reconnecting.attempts());
+                        info!(
+                            "{}: Reconnected after {} attempt(s)",
+                            self.id,
+                            reconnecting.attempts()
+                        );
+                        return true;
+                    }
+                    Err(err) => {
+                        warn!("{}: Reconnected stream unusable: {:?}", self.id, err);
+                        reconnecting.failed(reconnect.policy())
+                    }
+                }
+            }
+            step => step,
+        };
+        match step {
+            ReconnectStep::GaveUp => {
+                warn!(
+                    "{}: Reconnecting given up after {} attempt(s). Read thread will be shut down.",
+                    self.id,
+                    reconnecting.attempts()
+                );
+                reconnect.push_event(ConnectionEvent::ReconnectFailed {
+                    attempts: reconnecting.attempts(),
+                });
+                self.connection_closed.store(true, Ordering::SeqCst);
+                false
+            }
+            _ => {
+                self.reconnecting = Some(reconnecting);
+                true
+            }
+        }
+    }
+    // queues the unacknowledged reliable messages ahead of all other queued frames, since the lost connection may have dropped them
+    fn resend_unacknowledged(&mut self) {
+        if self.config.reliability.is_none() {
+            return;
+        }
+        let frames: Vec<_> = {
+            let (buffer, _) = &*self.retransmit_buffer;
+            let buffer = match buffer.lock() {
+                Ok(buffer) => buffer,
+                Err(_) => return,
+            };
+            buffer
+                .frames
+                .iter()
+                .filter_map(|(id, (command, message))| {
+                    P::construct_message(*command, &frame_with_id(*id, message))
+                })
+                .collect()
+        };
+        if frames.is_empty() {
+            return;
+        }
+        let count = frames.len();
+        let mut outgoing = lock_outgoing(&self.outgoing);
+        // pushed in reverse, so the messages are sent in the order of their frame ids
+        for frame in frames.into_iter().rev() {
+            if !outgoing.push_front(frame) {
+                warn!(
+                    "{}: Unacknowledged reliable message not resent, since the memory budget is exhausted",
+                    self.id
+                );
+            }
+        }
+        info!(
+            "{}: {} unacknowledged reliable message(s) queued for resending",
+            self.id, count
+        );
+    }
+    // queues the session request ahead of all other queued frames (including the resent ones), so the server recognizes the client first
+    fn present_session(&mut self) {
+        let (session, command) = match (&self.session, P::session_command()) {
+            (Some(session), Some(command)) => (session, command),
+            _ => return,
+        };
+        let token = *session.lock().unwrap_or_else(|e| e.into_inner());
+        let payload = token.map_or_else(Vec::new, |token| token.0.to_vec());
+        let frame = match P::construct_message(command, &payload) {
+            Some(frame) => frame,
+            None => {
+                warn!("{}: Session request could not be constructed", self.id);
+                return;
+            }
+        };
+        self.stats.control_frame_sent(frame.len());
+        command_sent::<P>(&self.command_stats, command);
+        trace_sent::<P>(&self.outgoing_trace, command, &payload);
+        tap::<P>(
+            &self.config.frame_tap,
+            FrameDirection::Sent,
+            &command,
+            &payload,
+        );
+        if !lock_outgoing(&self.outgoing).push_front(frame) {
+            warn!(
+                "{}: Session not presented, since the memory budget is exhausted",
+                self.id
+            );
+        }
+    }
+    // drain immediate responses which are not yet completely written
+    fn drain_step(&mut self) {
+        let drain_start = match self.state {
+            ReadThreadState::Draining(drain_start) => drain_start,
+            _ => return,
+        };
+        if self.restart.is_some() {
+            // the queued frames are written by the new read thread
+            self.finish();
+            return;
+        }
+        self.release_throttled();
+        let mut outgoing = lock_outgoing(&self.outgoing);
+        unstage(&mut outgoing, &mut self.staged);
+        if !outgoing.is_empty() || !self.throttled.is_empty() {
+            if let Err(err) = outgoing.flush(&mut self.stream) {
+                warn!("{}: Failed to drain immediate response: {:?}", self.id, err);
+                self.abandoned += 1;
+            } else if !outgoing.is_empty() || !self.throttled.is_empty() {
+                match self.config.shutdown_wait_time {
+                    Some(shutdown_wait_time) if drain_start.elapsed() < shutdown_wait_time => {
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        drop(outgoing);
+        self.finish();
+    }
+    fn finish(&mut self) {
+        // the main thread might be gone, which does not matter anymore
+        let pending = self.read_errors.take();
+        self.report_read_error(pending);
+        // staged frames are written by the new read thread, or abandoned below
+        // (frames waiting for a token of the rate limit as well, the new read thread writes them without one)
+        self.staged.extend(self.throttled.drain(..));
+        unstage(&mut lock_outgoing(&self.outgoing), &mut self.staged);
+        if let Some(restart) = self.restart.take() {
+            self.hand_over(restart);
+            return;
+        }
+        self.abandoned += lock_outgoing(&self.outgoing).abandon();
+        if self.abandoned > 0 {
+            warn!(
+                "{}: {} immediate response(s) abandoned during shutdown",
+                self.id, self.abandoned
+            );
+        }
+        if self.close_stream {
+            if let Err(err) = self.stream.shutdown(std::net::Shutdown::Both) {
+                warn!("{}: Failed to close connection: {:?}", self.id, err);
+            }
+        }
+        let _ = self.channels.shutdown_ack_sender.send(self.abandoned);
+        close_lanes(&self.lanes);
+        self.state = ReadThreadState::Finished;
+    }
+    // stops without closing anything, so the new read thread continues on the same stream
+    fn hand_over(&mut self, restart: Sender<RestartHandover<P>>) {
+        let busy_state = self.protocol.get_busy_state();
+        let handover = RestartHandover {
+            protocol: std::mem::replace(
+                &mut self.protocol,
+                ProtocolBuffer::with_busy_state(busy_state),
+            ),
+            buffered_reserved: std::mem::take(&mut self.buffered_reserved),
+            next_sequence: self.next_sequence,
+            scheduled: std::mem::take(&mut self.scheduled),
+            reliable: self.reliable.take(),
+            dedup: self.dedup.take(),
+        };
+        match restart.send(handover) {
+            Ok(()) => {
+                info!("{}: Read thread stopped for a restart", self.id);
+                self.state = ReadThreadState::Finished;
+            }
+            Err(std::sync::mpsc::SendError(handover)) => {
+                // the main thread gave up waiting, so this read thread simply continues
+                warn!("{}: Restart was given up, read thread continues", self.id);
+                self.protocol = handover.protocol;
+                self.buffered_reserved = handover.buffered_reserved;
+                self.scheduled = handover.scheduled;
+                self.reliable = handover.reliable;
+                self.dedup = handover.dedup;
+                self.state = ReadThreadState::Running;
+            }
+        }
+    }
+    /// Continues the sequence numbers & the busy state of a previous read thread which is gone, see 'TcpIpc::restart_read_thread'.
+    pub fn continue_after(&mut self, next_sequence: u64, busy_state: P::BusyStates) {
+        self.next_sequence = next_sequence;
+        self.protocol = ProtocolBuffer::with_busy_state(busy_state);
+    }
+    /// Takes over the state of a previous read thread which stopped for a restart, see 'TcpIpc::restart_read_thread'.
+    /// Reliability & deduplication continue, so no message is delivered twice.
+    pub fn take_over(&mut self, handover: RestartHandover<P>, keep_partial_frame: bool) {
+        self.next_sequence = handover.next_sequence;
+        self.scheduled = handover.scheduled;
+        self.reliable = handover.reliable;
+        self.dedup = handover.dedup;
+        if keep_partial_frame {
+            self.protocol = handover.protocol;
+            self.buffered_reserved = handover.buffered_reserved;
+            return;
+        }
+        let state = handover.protocol.parser_state();
+        if let Some(truncated) = state.truncated_frame() {
+            info!(
+                "{}: Partial frame discarded by the restart: {:?}",
+                self.id, truncated
+            );
+        }
+        // the payload of a parsed header was reserved in full
+        let payload_reserved = if state.command.is_some() {
+            state.declared
+        } else {
+            0
+        };
+        memory_budget::release(
+            &self.memory_budget,
+            handover.buffered_reserved + payload_reserved,
+        );
+        self.protocol = ProtocolBuffer::with_busy_state(handover.protocol.get_busy_state());
+    }
+}
+
+impl<P: Protocol> ReadThread<P> {
+    // sends the collapsed read errors (if any), since another kind of error occurred or the run of failed reads ended
+    // returns false if the main thread is gone
+    fn report_read_error(&self, repeated: Option<RepeatedReadError>) -> bool {
+        let error = match repeated {
+            Some(repeated) if repeated.occurrences == 1 => {
+                ReadThreadErrorsInternal::ReadError(repeated.error)
+            }
+            Some(repeated) => ReadThreadErrorsInternal::RepeatedReadError(repeated),
+            None => return true,
+        };
+        if self.channels.message_sender.send(Err(error)).is_err() {
+            return disconnected(self.id);
+        }
+        true
+    }
+}
+fn disconnected(id: ConnectionId) -> bool {
+    debug!(
+        "{}: Read thread seems to be disconnected from main thread. Will be shut down.",
+        id
+    );
+    false
+}
+
+// queues a frame of the read thread, unless the memory budget is used up
+// the read thread never waits for the outgoing queue: while the main thread holds it, the frame is staged instead.
+// Otherwise, a write of the main thread waiting for the peer to read would stop the reading here, and if the peer does the same
+// (like two peers answering each other's queries via the immediate route), neither side would read anymore.
+fn queue(
+    id: ConnectionId,
+    outgoing: &SharedOutgoingQueue,
+    stats: &StatsCounters,
+    staged: &mut Vec<Vec<u8>>,
+    memory_budget: &Option<SharedMemoryBudget>,
+    frame: Vec<u8>,
+    what: &str,
+) {
+    if !reserve(id, stats, memory_budget, frame.len(), what) {
+        return;
+    }
+    staged.push(frame);
+    if let Some(mut outgoing) = try_lock_outgoing(outgoing) {
+        unstage(&mut outgoing, staged);
+    }
+}
+// moves the staged frames into the outgoing queue, behind the frames queued before
+fn unstage(outgoing: &mut OutgoingQueue, staged: &mut Vec<Vec<u8>>) {
+    for frame in staged.drain(..) {
+        outgoing.push_reserved(frame, Priority::Normal);
+    }
+}
+// reserves a frame of the read thread in the memory budget, or drops it if the budget is used up
+fn reserve(
+    id: ConnectionId,
+    stats: &StatsCounters,
+    memory_budget: &Option<SharedMemoryBudget>,
+    length: usize,
+    what: &str,
+) -> bool {
+    if memory_budget::try_reserve(memory_budget, length) {
+        return true;
+    }
+    warn!(
+        "{}: {} dropped, since the memory budget is used up",
+        id, what
+    );
+    stats.frame_dropped_by_budget();
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::engine;
+    use super::super::protocols::LengthPrefixedProtocol;
+    use super::super::tcp_ipc::{ReadThreadErrors, TcpIpc, TcpIpcConfig};
+    use super::*;
+    use std::io::ErrorKind;
+
+    type TestProtocol = LengthPrefixedProtocol<u8, 4, 1>;
+
+    /// A transport whose reads fail with the given errors (the last one forever).
+    struct FailingTransport(Vec<ErrorKind>);
+    impl Read for FailingTransport {
+        fn read(&mut self, _buffer: &mut [u8]) -> std::io::Result<usize> {
+            let kind = if self.0.len() > 1 {
+                self.0.remove(0)
+            } else {
+                self.0[0]
+            };
+            Err(kind.into())
+        }
+    }
+
+    // sets up a connection whose read thread reads from the given transport, runs the read thread until it finishes
+    // & returns the errors delivered to the main thread, with the number of reads
+    fn read_until_finished(
+        transport: FailingTransport,
+        limit: usize,
+    ) -> (Vec<ReadThreadErrors<TestProtocol>>, u64) {
+        let listener = engine::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let _peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stream = loop {
+            match engine::accept(&listener) {
+                Ok((stream, _)) => break stream,
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(1)),
+            }
+        };
+        let config = TcpIpcConfig {
+            read_error_limit: Some(limit),
+            ..TcpIpcConfig::default()
+        };
+        let (mut connection, mut read_thread) =
+            TcpIpc::<TestProtocol>::prepare_connection(stream, config).unwrap();
+        read_thread.transport = Some(Box::new(transport));
+        let mut steps = 0;
+        while read_thread.step() {
+            steps += 1;
+            assert!(steps <= 2 * limit, "the read thread did not stop");
+        }
+        drop(read_thread);
+        let mut errors = Vec::new();
+        loop {
+            match connection.get_message() {
+                Ok(None) => {}
+                Ok(Some(message)) => panic!("unexpected message {:?}", message),
+                Err(ReadThreadErrors::ConnectionClosed) | Err(ReadThreadErrors::Disconnected) => {
+                    return (errors, connection.stats().read_iterations)
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+    // the kinds & occurrences of the reported read errors (a single error is reported as it is)
+    fn collapsed(errors: &[ReadThreadErrors<TestProtocol>]) -> Vec<(ErrorKind, usize)> {
+        errors
+            .iter()
+            .map(|err| match err {
+                ReadThreadErrors::RepeatedReadError(repeated) => {
+                    (repeated.error.kind(), repeated.occurrences)
+                }
+                ReadThreadErrors::ReadError(error) => (error.kind(), 1),
+                other => panic!("expected a read error, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn failing_reads_are_reported_once_with_their_count_until_the_limit() {
+        let (errors, reads) = read_until_finished(FailingTransport(vec![ErrorKind::Other]), 50);
+        assert_eq!(reads, 50);
+        assert_eq!(collapsed(&errors), vec![(ErrorKind::Other, 50)]);
+    }
+
+    #[test]
+    fn another_kind_reports_the_collapsed_errors() {
+        let transport = FailingTransport(vec![
+            ErrorKind::Other,
+            ErrorKind::Other,
+            ErrorKind::PermissionDenied,
+            ErrorKind::Other,
+        ]);
+        let (errors, reads) = read_until_finished(transport, 10);
+        assert_eq!(reads, 10);
+        assert_eq!(
+            collapsed(&errors),
+            vec![
+                (ErrorKind::Other, 2),
+                (ErrorKind::PermissionDenied, 1),
+                (ErrorKind::Other, 7)
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use super::engine::{self, TcpStream};
+use log::*;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The least time a connect in progress is given, before the attempt counts as failed.
+const MIN_CONNECT_TIME: Duration = Duration::from_millis(100);
+
+/// This determines how a client re-establishes a lost connection, see 'TcpIpcConfig::reconnect'.
+///
+/// The waits between the attempts grow exponentially: the first attempt is made after 'initial_delay',
+/// each further one after the previous wait times 'backoff_multiplier', but at most 'max_delay'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// The number of attempts before reconnecting is given up & the connection is closed.
+    pub max_attempts: u32,
+    /// The wait before the first attempt.
+    pub initial_delay: Duration,
+    /// The factor by which the wait grows after each failed attempt.
+    pub backoff_multiplier: f64,
+    /// The longest wait between two attempts.
+    pub max_delay: Duration,
+    /// The number of bytes 'write_message' (& 'write_messages') may queue while the connection is lost. The queued frames are written once it is re-established.
+    /// Beyond, 'WriteMessageErrors::NotConnected' is returned. With 0, every write fails while the connection is lost.
+    pub queue_limit: usize,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_delay: Duration::from_millis(100),
+            backoff_multiplier: 2.,
+            max_delay: Duration::from_secs(10),
+            queue_limit: 0,
+        }
+    }
+}
+
+/// A change of the connection itself, as reported by 'TcpIpc::next_event'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionEvent {
+    /// The connection was lost & reconnecting started. The loss itself is reported by 'get_message' as usual (like 'PeerClosed' or 'ReadError').
+    Lost,
+    /// A new connection was established. Parsing starts afresh, so a message which was partially received before was discarded.
+    Reconnected {
+        /// The number of attempts it took.
+        attempts: u32,
+    },
+    /// No connection could be established within 'ReconnectPolicy::max_attempts', so the connection is closed.
+    ReconnectFailed {
+        /// The number of attempts made.
+        attempts: u32,
+    },
+}
+
+/// The reconnecting of a client, shared by the read thread (which reconnects) & the main thread (which takes the new stream over for writing).
+#[derive(Debug)]
+pub struct ReconnectState {
+    policy: ReconnectPolicy,
+    addresses: Vec<SocketAddr>,
+    connected: AtomicBool,
+    // the stream of the new connection, until the main thread took it over
+    stream: Mutex<Option<TcpStream>>,
+    events: Mutex<VecDeque<ConnectionEvent>>,
+}
+pub type SharedReconnectState = Arc<ReconnectState>;
+impl ReconnectState {
+    pub fn new(policy: ReconnectPolicy, addresses: Vec<SocketAddr>) -> Self {
+        Self {
+            policy,
+            addresses,
+            connected: AtomicBool::new(true),
+            stream: Mutex::new(None),
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+    pub fn policy(&self) -> &ReconnectPolicy {
+        &self.policy
+    }
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+    pub fn lost(&self) {
+        self.connected.store(false, Ordering::SeqCst);
+        self.push_event(ConnectionEvent::Lost);
+    }
+    /// Publishes the stream of the new connection. It is handed over before the connection counts as re-established,
+    /// so a writer seeing the connection re-established finds the stream.
+    pub fn reconnected(&self, stream: TcpStream, attempts: u32) {
+        *self.stream.lock().unwrap_or_else(|e| e.into_inner()) = Some(stream);
+        self.connected.store(true, Ordering::SeqCst);
+        self.push_event(ConnectionEvent::Reconnected { attempts });
+    }
+    pub fn take_stream(&self) -> Option<TcpStream> {
+        self.stream.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+    pub fn push_event(&self, event: ConnectionEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(event);
+    }
+    pub fn next_event(&self) -> Option<ConnectionEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+    }
+}
+
+/// The result of 'Reconnecting::poll'.
+pub enum ReconnectStep {
+    /// The next attempt is not due yet, or a connect is still in progress.
+    Waiting,
+    /// A connection was established.
+    Connected(TcpStream),
+    /// The last attempt failed.
+    GaveUp,
+}
+
+/// The progress of reconnecting, kept by the read thread. Nothing here blocks, so control requests are handled in between.
+pub struct Reconnecting {
+    attempts: u32,
+    delay: Duration,
+    next_attempt: Instant,
+    // a non-blocking connect which did not complete yet, with its start
+    pending: Option<(TcpStream, Instant)>,
+}
+impl Reconnecting {
+    pub fn new(policy: &ReconnectPolicy) -> Self {
+        Self {
+            attempts: 0,
+            delay: policy.initial_delay,
+            next_attempt: Instant::now() + policy.initial_delay,
+            pending: None,
+        }
+    }
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+    /// Makes the next attempt if it is due, or checks the connect in progress.
+    pub fn poll(&mut self, state: &ReconnectState) -> ReconnectStep {
+        if let Some((stream, started)) = &self.pending {
+            // mio connects in the background, so the connection is established once the peer address is known
+            let failure = match stream.take_error() {
+                Ok(Some(err)) | Err(err) => Some(err),
+                Ok(None) => match stream.peer_addr() {
+                    Ok(_) => None,
+                    Err(_) if started.elapsed() < self.delay.max(MIN_CONNECT_TIME) => {
+                        return ReconnectStep::Waiting
+                    }
+                    Err(err) => Some(err),
+                },
+            };
+            return match failure {
+                None => match self.pending.take() {
+                    Some((stream, _)) => ReconnectStep::Connected(stream),
+                    None => ReconnectStep::Waiting,
+                },
+                Some(err) => {
+                    debug!("Reconnect attempt {} failed: {:?}", self.attempts, err);
+                    self.failed(&state.policy)
+                }
+            };
+        }
+        if Instant::now() < self.next_attempt {
+            return ReconnectStep::Waiting;
+        }
+        self.attempts += 1;
+        for address in &state.addresses {
+            debug!(
+                "Reconnect attempt {}: connecting to {:?}",
+                self.attempts, address
+            );
+            match engine::connect(address) {
+                Ok(stream) => {
+                    self.pending = Some((stream, Instant::now()));
+                    return ReconnectStep::Waiting;
+                }
+                Err(err) => debug!("Reconnect attempt {} failed: {:?}", self.attempts, err),
+            }
+        }
+        self.failed(&state.policy)
+    }
+    /// Counts the current attempt as failed & schedules the next one, unless all attempts are used up.
+    pub fn failed(&mut self, policy: &ReconnectPolicy) -> ReconnectStep {
+        self.pending = None;
+        if self.attempts >= policy.max_attempts {
+            return ReconnectStep::GaveUp;
+        }
+        // a multiplier which is not a number (or overflows) is treated as reaching the longest wait
+        self.delay =
+            Duration::try_from_secs_f64(self.delay.as_secs_f64() * policy.backoff_multiplier)
+                .unwrap_or(policy.max_delay)
+                .min(policy.max_delay);
+        self.next_attempt = Instant::now() + self.delay;
+        ReconnectStep::Waiting
+    }
+}
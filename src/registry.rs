@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A process-unique id of a connection, assigned when it is created.
+/// It is part of the log lines, the stats & the diagnostics of the connection, to correlate them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConnectionId(pub u64);
+impl std::fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "conn-{}", self.0)
+    }
+}
+impl ConnectionId {
+    // ids start at 1, so the default id is never assigned
+    pub(crate) fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        ConnectionId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A description of a live connection, as returned by 'registry'.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConnectionDescriptor {
+    /// The id of the connection.
+    pub id: ConnectionId,
+    /// The name of the connection, see 'TcpIpcConfig::name'.
+    pub name: Option<String>,
+    /// The address of the peer, if it could be determined when the connection was created.
+    pub peer_addr: Option<std::net::SocketAddr>,
+    /// Indicates if the connection is known to be closed (but not yet dropped).
+    pub connection_closed: bool,
+    /// The time the connection was created.
+    pub created_at: std::time::SystemTime,
+}
+
+#[cfg(feature = "registry")]
+struct RegistryEntry {
+    name: Option<String>,
+    peer_addr: Option<std::net::SocketAddr>,
+    connection_closed: Arc<AtomicBool>,
+    created_at: std::time::SystemTime,
+}
+#[cfg(feature = "registry")]
+static REGISTRY: std::sync::Mutex<std::collections::BTreeMap<ConnectionId, RegistryEntry>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// Returns descriptions of all live connections of this process, ordered by their id.
+/// A connection is listed from its creation until it is dropped (or shut down), regardless of the state of its read thread.
+/// # Example
+/// ```
+/// for connection in rust_tcp_ipc::registry() {
+///     println!("{} {:?} closed: {}", connection.id, connection.peer_addr, connection.connection_closed);
+/// }
+/// ```
+#[cfg(feature = "registry")]
+pub fn registry() -> Vec<ConnectionDescriptor> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(id, entry)| ConnectionDescriptor {
+            id: *id,
+            name: entry.name.clone(),
+            peer_addr: entry.peer_addr,
+            connection_closed: entry.connection_closed.load(Ordering::SeqCst),
+            created_at: entry.created_at,
+        })
+        .collect()
+}
+
+/// The registration of a connection in the registry, which is removed when this is dropped.
+/// Without the feature 'registry', this only carries the id.
+pub struct Registration {
+    id: ConnectionId,
+}
+impl Registration {
+    #[cfg_attr(not(feature = "registry"), allow(unused_variables))]
+    pub fn new(
+        name: Option<String>,
+        peer_addr: Option<std::net::SocketAddr>,
+        connection_closed: Arc<AtomicBool>,
+    ) -> Self {
+        let id = ConnectionId::next();
+        #[cfg(feature = "registry")]
+        REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).insert(
+            id,
+            RegistryEntry {
+                name,
+                peer_addr,
+                connection_closed,
+                created_at: std::time::SystemTime::now(),
+            },
+        );
+        Self { id }
+    }
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+}
+#[cfg(feature = "registry")]
+impl Drop for Registration {
+    fn drop(&mut self) {
+        REGISTRY
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
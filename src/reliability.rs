@@ -0,0 +1,179 @@
+use super::memory_budget::{self, SharedMemoryBudget};
+use super::protocol_buffer::Protocol;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The number of bytes of the frame id, which is put in front of the payload of reliable messages.
+pub const FRAME_ID_SIZE: usize = 8;
+
+/// This bundles the settings of the optional reliability layer (see 'TcpIpc::write_message_reliable').
+///
+/// Reliable messages carry a frame id in front of their payload, which the receiving side acknowledges with the protocol's 'ack_command'.
+/// Both sides have to enable the reliability layer, otherwise the frame ids are not removed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReliabilityConfig {
+    /// The maximal number of sent, but not yet acknowledged messages.
+    pub max_unacknowledged: usize,
+    /// The number of recently received frame ids, which are remembered to suppress duplicates.
+    pub dedup_window: usize,
+}
+
+/// The sent, but not yet acknowledged reliable messages of a connection.
+/// Their payloads are reserved in the memory budget of the connection (if any).
+pub struct RetransmitBuffer<P: Protocol> {
+    pub frames: BTreeMap<u64, (P::Commands, Vec<u8>)>,
+    pub next_id: u64,
+    memory_budget: Option<SharedMemoryBudget>,
+}
+impl<P: Protocol> RetransmitBuffer<P> {
+    /// Keeps the message under the given id. Returns false (& keeps nothing) if the memory budget does not allow it.
+    pub fn insert(&mut self, id: u64, command: P::Commands, message: &[u8]) -> bool {
+        if !memory_budget::try_reserve(&self.memory_budget, message.len()) {
+            return false;
+        }
+        self.frames.insert(id, (command, message.to_vec()));
+        true
+    }
+    pub fn remove(&mut self, id: u64) {
+        if let Some((_, message)) = self.frames.remove(&id) {
+            memory_budget::release(&self.memory_budget, message.len());
+        }
+    }
+}
+pub type SharedRetransmitBuffer<P> = Arc<(Mutex<RetransmitBuffer<P>>, Condvar)>;
+pub fn new_retransmit_buffer<P: Protocol>(
+    memory_budget: Option<SharedMemoryBudget>,
+) -> SharedRetransmitBuffer<P> {
+    Arc::new((
+        Mutex::new(RetransmitBuffer {
+            frames: BTreeMap::new(),
+            next_id: 0,
+            memory_budget,
+        }),
+        Condvar::new(),
+    ))
+}
+
+/// Puts the frame id in front of the payload.
+pub fn frame_with_id(id: u64, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_ID_SIZE + payload.len());
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+/// Reads the frame id in front of the payload.
+pub fn frame_id(payload: &[u8]) -> Option<u64> {
+    if payload.len() < FRAME_ID_SIZE {
+        return None;
+    }
+    let mut id = [0; FRAME_ID_SIZE];
+    id.copy_from_slice(&payload[..FRAME_ID_SIZE]);
+    Some(u64::from_be_bytes(id))
+}
+
+/// The receiving side of the reliability layer, used by the read thread.
+pub struct ReliableReceiver<P: Protocol> {
+    retransmit_buffer: SharedRetransmitBuffer<P>,
+    recent_ids: VecDeque<u64>,
+    dedup_window: usize,
+}
+/// What the read thread has to do with a received frame.
+pub enum Received {
+    /// The frame is no reliable message, it is handled as usual.
+    Unreliable,
+    /// The frame was an acknowledgment, nothing is left to do.
+    Acknowledgment,
+    /// The frame is a reliable message, which has to be acknowledged. The frame id is already removed from the payload.
+    /// Duplicates have to be acknowledged (again), but are not to be handled.
+    Reliable {
+        /// The frame id.
+        id: u64,
+        /// Indicates that this frame id was received recently.
+        duplicate: bool,
+    },
+}
+impl<P: Protocol> ReliableReceiver<P> {
+    pub fn new(retransmit_buffer: SharedRetransmitBuffer<P>, config: ReliabilityConfig) -> Self {
+        Self {
+            retransmit_buffer,
+            recent_ids: VecDeque::with_capacity(config.dedup_window),
+            dedup_window: config.dedup_window,
+        }
+    }
+    pub fn receive(&mut self, command: &P::Commands, payload: &mut Vec<u8>) -> Received {
+        if Some(*command) == P::ack_command() {
+            if let Some(id) = frame_id(payload) {
+                let (buffer, acknowledged) = &*self.retransmit_buffer;
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.remove(id);
+                }
+                acknowledged.notify_all();
+            }
+            return Received::Acknowledgment;
+        }
+        if !P::is_reliable_command(command) {
+            return Received::Unreliable;
+        }
+        let id = match frame_id(payload) {
+            Some(id) => id,
+            None => return Received::Unreliable,
+        };
+        payload.drain(..FRAME_ID_SIZE);
+        let duplicate = self.recent_ids.contains(&id);
+        if !duplicate && self.dedup_window > 0 {
+            if self.recent_ids.len() == self.dedup_window {
+                self.recent_ids.pop_front();
+            }
+            self.recent_ids.push_back(id);
+        }
+        Received::Reliable { id, duplicate }
+    }
+}
+
+/// This is returned for each reliable message, to check if the peer acknowledged it.
+pub struct DeliveryHandle<P: Protocol> {
+    id: u64,
+    retransmit_buffer: SharedRetransmitBuffer<P>,
+}
+impl<P: Protocol> std::fmt::Debug for DeliveryHandle<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DeliveryHandle")
+            .field("id", &self.id)
+            .field("acknowledged", &self.is_acknowledged())
+            .finish()
+    }
+}
+impl<P: Protocol> DeliveryHandle<P> {
+    pub(crate) fn new(id: u64, retransmit_buffer: SharedRetransmitBuffer<P>) -> Self {
+        Self {
+            id,
+            retransmit_buffer,
+        }
+    }
+    /// This returns the frame id of the message.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// Checks if the peer acknowledged the message.
+    pub fn is_acknowledged(&self) -> bool {
+        let (buffer, _) = &*self.retransmit_buffer;
+        match buffer.lock() {
+            Ok(buffer) => !buffer.frames.contains_key(&self.id),
+            Err(_) => false,
+        }
+    }
+    /// Waits until the peer acknowledged the message. Returns false if this did not happen within the given time.
+    pub fn wait(&self, timeout: std::time::Duration) -> bool {
+        let (buffer, acknowledged) = &*self.retransmit_buffer;
+        let buffer = match buffer.lock() {
+            Ok(buffer) => buffer,
+            Err(_) => return false,
+        };
+        match acknowledged.wait_timeout_while(buffer, timeout, |buffer| {
+            buffer.frames.contains_key(&self.id)
+        }) {
+            Ok((_, result)) => !result.timed_out(),
+            Err(_) => false,
+        }
+    }
+}
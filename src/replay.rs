@@ -0,0 +1,310 @@
+//! A deterministic replay of captured bytes through the parser, to reproduce a parse failure seen in the field.
+//!
+//! The received chunks of a connection (as returned by the reads of the socket) are fed through a fresh 'ProtocolBuffer' in their original sizes & order,
+//! without any socket or thread. The report pinpoints the record & byte offset of the first failure.
+//! A capture which broke parsing can be checked in as a regression test: the report is clean once the protocol is fixed.
+//! # Example
+//! ```
+//! # use rust_tcp_ipc::doc_example::*;
+//! use rust_tcp_ipc::replay::*;
+//! fn field_capture_parses() {
+//!     let records = load_capture("tests/captures/bad_header.txt").unwrap();
+//!     let report = parse_capture::<ProtocolExample>(&records);
+//!     assert!(report.is_clean(), "{:?}", report.failure);
+//! }
+//! ```
+use super::file_format::codec_required;
+use super::tcp_ipc::*;
+
+/// The start of the header line of a capture file, followed by the format version & the encoding ('plain' or 'encoded').
+pub const CAPTURE_HEADER: &str = "#!tcp-ipc-capture";
+/// The format version of the capture files written by 'save_capture'.
+pub const CAPTURE_VERSION: u32 = 2;
+const SUPPORTED_CAPTURE_VERSIONS: &[u32] = &[1, 2];
+
+/// A chunk of bytes as transferred by a single read (or write) of a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureRecord {
+    /// The direction of the chunk. Only received chunks are replayed.
+    pub direction: FrameDirection,
+    /// The bytes of the chunk.
+    pub bytes: Vec<u8>,
+}
+impl CaptureRecord {
+    /// A chunk received from the peer.
+    pub fn received(bytes: Vec<u8>) -> Self {
+        Self {
+            direction: FrameDirection::Received,
+            bytes,
+        }
+    }
+}
+
+/// Loads a capture from a text file: one record per line, starting with `<` for a received & `>` for a sent chunk,
+/// followed by its bytes in hex (separated by whitespace). Empty lines & lines starting with `#` are ignored.
+/// This reads a capture written without codec, see 'load_capture_with' otherwise.
+///
+/// Since version 2, the first line is a header with the format version & whether the bytes are encoded by a codec (see 'save_capture').
+/// A file without header is read as version 1, which is the same format (its bytes are decoded if the reader is given a codec).
+/// A newer version is reported as 'UnsupportedFormatVersion', as inner error of an error of kind 'InvalidData'.
+/// # Example of a capture file
+/// ```text
+/// #!tcp-ipc-capture 2 plain
+/// # a header announcing 3 bytes, split across two reads
+/// < 01 00 00
+/// < 03 61 62 63
+/// ```
+pub fn load_capture<T: AsRef<std::path::Path>>(path: T) -> std::io::Result<Vec<CaptureRecord>> {
+    read_capture(path, None)
+}
+
+/// Like 'load_capture', for a capture whose records hold the bytes encoded with the given codec (see 'save_capture').
+/// If a record fails to decode, an error of kind 'InvalidData' is returned, with the 'CodecError' as inner error.
+pub fn load_capture_with<T: AsRef<std::path::Path>>(
+    path: T,
+    codec: &dyn StorageCodec,
+) -> std::io::Result<Vec<CaptureRecord>> {
+    read_capture(path, Some(codec))
+}
+
+fn read_capture<T: AsRef<std::path::Path>>(
+    path: T,
+    codec: Option<&dyn StorageCodec>,
+) -> std::io::Result<Vec<CaptureRecord>> {
+    let content = std::fs::read_to_string(path)?;
+    let invalid = |line: usize, reason: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("capture line {}: {}", line + 1, reason),
+        )
+    };
+    let mut codec = codec;
+    let mut records = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if number == 0 && line.starts_with(CAPTURE_HEADER) {
+            let mut fields = line[CAPTURE_HEADER.len()..].split_whitespace();
+            let found = fields
+                .next()
+                .and_then(|version| version.parse().ok())
+                .ok_or_else(|| invalid(number, "invalid format version"))?;
+            if found != CAPTURE_VERSION {
+                return Err(UnsupportedFormatVersion {
+                    found,
+                    supported: SUPPORTED_CAPTURE_VERSIONS,
+                }
+                .into_io_error());
+            }
+            match fields.next() {
+                // the bytes are stored as given, whatever the codec of the reader
+                Some("plain") => codec = None,
+                Some("encoded") if codec.is_none() => return Err(codec_required()),
+                Some("encoded") => {}
+                _ => return Err(invalid(number, "invalid encoding")),
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let direction = match line.as_bytes()[0] {
+            b'<' => FrameDirection::Received,
+            b'>' => FrameDirection::Sent,
+            _ => return Err(invalid(number, "expected '<' or '>'")),
+        };
+        let bytes = line[1..]
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid(number, "invalid hex byte"))?;
+        let bytes = match codec {
+            Some(codec) => codec.decode(&bytes).map_err(|err| err.into_io_error())?,
+            None => bytes,
+        };
+        records.push(CaptureRecord { direction, bytes });
+    }
+    Ok(records)
+}
+
+/// Writes a capture in the current format version, encoding the bytes of every record with the given codec (if any).
+/// A capture written with a codec has to be read via 'load_capture_with'.
+pub fn save_capture<T: AsRef<std::path::Path>>(
+    path: T,
+    records: &[CaptureRecord],
+    codec: Option<&dyn StorageCodec>,
+) -> std::io::Result<()> {
+    use std::fmt::Write;
+    let encoded = codec.is_some();
+    let codec = codec.unwrap_or(&PassThrough);
+    let mut content = format!(
+        "{} {} {}\n",
+        CAPTURE_HEADER,
+        CAPTURE_VERSION,
+        if encoded { "encoded" } else { "plain" }
+    );
+    for record in records {
+        content.push(match record.direction {
+            FrameDirection::Received => '<',
+            FrameDirection::Sent => '>',
+        });
+        for byte in codec.encode(&record.bytes) {
+            // writing to a string cannot fail
+            let _ = write!(content, " {:02x}", byte);
+        }
+        content.push('\n');
+    }
+    std::fs::write(path, content)
+}
+
+/// The reason replaying a capture failed, see 'ReplayFailure'.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayFailureKind {
+    /// A header could not be parsed (see 'ProtocolBuffer::try_process_new_buffer').
+    /// In a connection, this is the protocol violation which closes it (or which is reported & discarded with 'Strictness::Lenient').
+    ProtocolViolation(ProtocolViolation),
+    /// The protocol implementation panicked while parsing, with the given message.
+    Panic(String),
+}
+
+/// The first failure while replaying a capture, see 'ReplayReport'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFailure {
+    /// What failed.
+    pub kind: ReplayFailureKind,
+    /// The index of the record (counting all records, including sent ones) which contains the first byte of the offending header.
+    pub record: usize,
+    /// The offset of the first byte of the offending header within this record.
+    pub offset: usize,
+    /// The offset of the first byte of the offending header within all received bytes.
+    pub stream_offset: usize,
+}
+
+/// A message parsed while replaying a capture.
+pub struct ReplayedMessage<P: Protocol> {
+    /// The index of the record which completed the message.
+    pub record: usize,
+    /// The message itself.
+    pub message: Message<P>,
+}
+impl<P: Protocol> Clone for ReplayedMessage<P> {
+    fn clone(&self) -> Self {
+        Self {
+            record: self.record,
+            message: self.message.clone(),
+        }
+    }
+}
+impl<P: Protocol> std::fmt::Debug for ReplayedMessage<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ReplayedMessage")
+            .field("record", &self.record)
+            .field("message", &self.message)
+            .finish()
+    }
+}
+impl<P: Protocol> PartialEq for ReplayedMessage<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.record == other.record && self.message == other.message
+    }
+}
+
+/// The result of replaying a capture, see 'parse_capture'.
+pub struct ReplayReport<P: Protocol> {
+    /// The messages parsed before the first failure, in order.
+    pub messages: Vec<ReplayedMessage<P>>,
+    /// The first failure, if any. Since the bytes following a failure are not known to start a frame, later records are not replayed.
+    pub failure: Option<ReplayFailure>,
+    /// The state of the parser after the last replayed record, for example to detect a message truncated by the end of the capture.
+    pub parser_state: ParserState<P>,
+    /// The number of received records which were replayed.
+    pub replayed_records: usize,
+}
+impl<P: Protocol> std::fmt::Debug for ReplayReport<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ReplayReport")
+            .field("messages", &self.messages)
+            .field("failure", &self.failure)
+            // the parser state is shown as the message it ends in the middle of, if any
+            .field("truncated_frame", &self.parser_state.truncated_frame())
+            .field("replayed_records", &self.replayed_records)
+            .finish()
+    }
+}
+impl<P: Protocol> ReplayReport<P> {
+    /// Checks that the capture was parsed without failure & does not end in the middle of a message.
+    pub fn is_clean(&self) -> bool {
+        self.failure.is_none() && !self.parser_state.is_mid_frame()
+    }
+}
+
+/// Replays the received records of a capture through a fresh parser, in their recorded chunk sizes & order.
+/// Parsing stops at the first failure (a protocol violation or a panic of the protocol implementation).
+/// The parser starts with the idle busy state, which does not influence parsing.
+pub fn parse_capture<P: Protocol>(records: &[CaptureRecord]) -> ReplayReport<P> {
+    let mut parser = ProtocolBuffer::<P>::with_busy_state(P::idle());
+    let mut messages = Vec::new();
+    let mut replayed_records = 0;
+    // the received records with their index & the stream offset of their first byte
+    let mut received = Vec::new();
+    let mut stream_offset = 0;
+    for (index, record) in records.iter().enumerate() {
+        if record.direction != FrameDirection::Received {
+            continue;
+        }
+        received.push((index, stream_offset));
+        stream_offset += record.bytes.len();
+        replayed_records += 1;
+        let mut chunk = &record.bytes[..];
+        // the bytes not yet parsed before the call, which start with the offending header if the call fails
+        let mut unparsed;
+        let kind = loop {
+            unparsed = parser.parser_state().buffered + chunk.len();
+            let parsed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                parser.try_process_new_buffer(chunk)
+            }));
+            chunk = &[];
+            match parsed {
+                Ok(Ok(Some(message))) => messages.push(ReplayedMessage {
+                    record: index,
+                    message,
+                }),
+                Ok(Ok(None)) => break None,
+                Ok(Err(violation)) => break Some(ReplayFailureKind::ProtocolViolation(violation)),
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    break Some(ReplayFailureKind::Panic(message));
+                }
+            }
+        };
+        if let Some(kind) = kind {
+            let failed_at = stream_offset - unparsed;
+            let (record, record_offset) = received
+                .iter()
+                .rev()
+                .find(|(_, offset)| *offset <= failed_at)
+                .copied()
+                .unwrap_or((index, failed_at));
+            return ReplayReport {
+                messages,
+                failure: Some(ReplayFailure {
+                    kind,
+                    record,
+                    offset: failed_at - record_offset,
+                    stream_offset: failed_at,
+                }),
+                parser_state: parser.parser_state(),
+                replayed_records,
+            };
+        }
+    }
+    ReplayReport {
+        messages,
+        failure: None,
+        parser_state: parser.parser_state(),
+        replayed_records,
+    }
+}
@@ -0,0 +1,182 @@
+use super::protocol_buffer::Protocol;
+use super::registry::ConnectionId;
+use log::*;
+use std::sync::Arc;
+
+/// The minimal time between two warnings about an exceeded immediate response budget (per connection).
+const WARNING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A callback called with the command & the time spent, whenever the immediate route exceeded its budget (see 'ImmediateResponseBudget').
+pub type BudgetExceededHook<P> =
+    Arc<dyn Fn(&<P as Protocol>::Commands, std::time::Duration) + Send + Sync>;
+
+/// This limits the time the read thread spends on a single immediate response (see 'TcpIpcConfig::immediate_response_budget').
+///
+/// 'Protocol::message_is_answered_via_immediate_route' & 'Protocol::construct_message' run on the read thread,
+/// so a slow implementation delays every following frame of the connection.
+/// The time spent on the decision, the construction & queueing the response is measured for every received frame (see 'ConnectionStats::max_immediate_route_time').
+/// If it exceeds the budget, a warning is logged (at most once per second) & the hook is called.
+pub struct ImmediateResponseBudget<P: Protocol> {
+    /// The maximal time the immediate route should take for a frame.
+    pub budget: std::time::Duration,
+    /// If true, a command which exceeded the budget once is not passed to the immediate route anymore.
+    /// Instead, such frames are delivered like any other message (see 'TcpIpc::get_message'), so the application answers them.
+    pub demote: bool,
+    /// This is called (on the read thread) whenever the budget was exceeded. It has to return quickly.
+    pub on_exceeded: Option<BudgetExceededHook<P>>,
+}
+impl<P: Protocol> Clone for ImmediateResponseBudget<P> {
+    fn clone(&self) -> Self {
+        Self {
+            budget: self.budget,
+            demote: self.demote,
+            on_exceeded: self.on_exceeded.clone(),
+        }
+    }
+}
+impl<P: Protocol> std::fmt::Debug for ImmediateResponseBudget<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ImmediateResponseBudget")
+            .field("budget", &self.budget)
+            .field("demote", &self.demote)
+            .field("on_exceeded", &self.on_exceeded.as_ref().map(|_| "<hook>"))
+            .finish()
+    }
+}
+impl<P: Protocol> PartialEq for ImmediateResponseBudget<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.budget == other.budget
+            && self.demote == other.demote
+            && match (&self.on_exceeded, &other.on_exceeded) {
+                (Some(hook), Some(other_hook)) => Arc::ptr_eq(hook, other_hook),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+/// Checks the time of the immediate route against the budget, rate-limiting the warnings & remembering demoted commands.
+pub struct BudgetTracker<P: Protocol> {
+    budget: ImmediateResponseBudget<P>,
+    demoted: Vec<P::Commands>,
+    last_warning: Option<std::time::Instant>,
+    suppressed_warnings: u64,
+}
+impl<P: Protocol> BudgetTracker<P> {
+    pub fn new(budget: ImmediateResponseBudget<P>) -> Self {
+        Self {
+            budget,
+            demoted: Vec::new(),
+            last_warning: None,
+            suppressed_warnings: 0,
+        }
+    }
+    /// Checks if frames of this command skip the immediate route, since it exceeded the budget before.
+    pub fn is_demoted(&self, command: &P::Commands) -> bool {
+        self.demoted.contains(command)
+    }
+    /// Checks the time spent on a frame of the given command. Returns true if the budget was exceeded.
+    pub fn check(
+        &mut self,
+        id: ConnectionId,
+        command: &P::Commands,
+        elapsed: std::time::Duration,
+        now: std::time::Instant,
+    ) -> bool {
+        if elapsed <= self.budget.budget {
+            return false;
+        }
+        if self
+            .last_warning
+            .is_none_or(|last_warning| now.duration_since(last_warning) >= WARNING_INTERVAL)
+        {
+            warn!(
+                "{}: Immediate route of {:?} took {:?}, exceeding the budget of {:?} ({} further warnings suppressed)",
+                id, command, elapsed, self.budget.budget, self.suppressed_warnings
+            );
+            self.last_warning = Some(now);
+            self.suppressed_warnings = 0;
+        } else {
+            self.suppressed_warnings += 1;
+        }
+        if let Some(on_exceeded) = &self.budget.on_exceeded {
+            on_exceeded(command, elapsed);
+        }
+        if self.budget.demote && !self.is_demoted(command) {
+            info!(
+                "{}: {:?} is not answered via the immediate route anymore",
+                id, command
+            );
+            self.demoted.push(*command);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::LengthPrefixedProtocol;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    type TestProtocol = LengthPrefixedProtocol<u8, 4, 1>;
+    const BUDGET: Duration = Duration::from_millis(1);
+    const SLOW: Duration = Duration::from_millis(5);
+
+    fn tracker(
+        demote: bool,
+        hooked: Arc<Mutex<Vec<(u8, Duration)>>>,
+    ) -> BudgetTracker<TestProtocol> {
+        BudgetTracker::new(ImmediateResponseBudget {
+            budget: BUDGET,
+            demote,
+            on_exceeded: Some(Arc::new(move |command: &u8, elapsed| {
+                hooked.lock().unwrap().push((*command, elapsed))
+            })),
+        })
+    }
+
+    #[test]
+    fn warnings_are_rate_limited() {
+        let hooked = Arc::default();
+        let mut tracker = tracker(false, Arc::clone(&hooked));
+        let start = Instant::now();
+        assert!(!tracker.check(ConnectionId(1), &7, BUDGET, start));
+        assert_eq!(tracker.last_warning, None);
+
+        assert!(tracker.check(ConnectionId(1), &7, SLOW, start));
+        assert_eq!(tracker.last_warning, Some(start));
+        // within the interval, the warnings are only counted
+        let later = start + WARNING_INTERVAL / 2;
+        assert!(tracker.check(ConnectionId(1), &7, SLOW, later));
+        assert!(tracker.check(ConnectionId(1), &8, SLOW, later));
+        assert_eq!(tracker.last_warning, Some(start));
+        assert_eq!(tracker.suppressed_warnings, 2);
+        // afterwards, the next warning is logged (reporting the suppressed ones)
+        let next = start + WARNING_INTERVAL;
+        assert!(tracker.check(ConnectionId(1), &7, SLOW, next));
+        assert_eq!(tracker.last_warning, Some(next));
+        assert_eq!(tracker.suppressed_warnings, 0);
+
+        // the hook is called for every exceeded budget, whether warned or not
+        assert_eq!(
+            *hooked.lock().unwrap(),
+            vec![(7, SLOW), (7, SLOW), (8, SLOW), (7, SLOW)]
+        );
+        assert!(!tracker.is_demoted(&7));
+    }
+
+    #[test]
+    fn exceeding_commands_are_demoted() {
+        let mut tracker = tracker(true, Arc::default());
+        let now = Instant::now();
+        assert!(!tracker.check(ConnectionId(1), &7, BUDGET, now));
+        assert!(!tracker.is_demoted(&7));
+        assert!(tracker.check(ConnectionId(1), &7, SLOW, now));
+        assert!(tracker.check(ConnectionId(1), &7, SLOW, now));
+        assert!(tracker.is_demoted(&7));
+        assert_eq!(tracker.demoted, vec![7]);
+        assert!(!tracker.is_demoted(&8));
+    }
+}
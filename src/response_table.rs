@@ -0,0 +1,208 @@
+//! A declarative table of immediate responses, instead of a hand-written 'Protocol::message_is_answered_via_immediate_route'.
+use super::protocol::{Message, Protocol};
+use alloc::vec::Vec;
+
+/// This selects commands or busy states for a rule of an 'ImmediateResponseTable'.
+pub enum Matcher<T> {
+    /// Every value matches.
+    Any,
+    /// Only the given value matches.
+    Is(T),
+    /// Any of the given values matches.
+    AnyOf(Vec<T>),
+    /// The values for which the function returns true match.
+    Where(fn(&T) -> bool),
+}
+impl<T: PartialEq> Matcher<T> {
+    /// Checks if the value matches.
+    pub fn matches(&self, value: &T) -> bool {
+        match self {
+            Matcher::Any => true,
+            Matcher::Is(expected) => expected == value,
+            Matcher::AnyOf(expected) => expected.contains(value),
+            Matcher::Where(predicate) => predicate(value),
+        }
+    }
+}
+impl<T: Clone> Clone for Matcher<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Matcher::Any => Matcher::Any,
+            Matcher::Is(value) => Matcher::Is(value.clone()),
+            Matcher::AnyOf(values) => Matcher::AnyOf(values.clone()),
+            Matcher::Where(predicate) => Matcher::Where(*predicate),
+        }
+    }
+}
+impl<T: core::fmt::Debug> core::fmt::Debug for Matcher<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Matcher::Any => write!(f, "Any"),
+            Matcher::Is(value) => write!(f, "Is({:?})", value),
+            Matcher::AnyOf(values) => write!(f, "AnyOf({:?})", values),
+            Matcher::Where(_) => write!(f, "Where(..)"),
+        }
+    }
+}
+impl<T> From<T> for Matcher<T> {
+    fn from(value: T) -> Self {
+        Matcher::Is(value)
+    }
+}
+
+/// The response of a rule of an 'ImmediateResponseTable'.
+pub enum ResponseTemplate<P: Protocol> {
+    /// Answers with the given command & payload.
+    Static(P::Commands, Vec<u8>),
+    /// Answers with the given command & the payload of the received message.
+    EchoPayload(P::Commands),
+    /// Answers with the command & payload returned by the function, which is given the busy state at the time of the decision.
+    FromBusyState(fn(P::BusyStates) -> Message<P>),
+    /// The message is not answered, but forwarded to the consumer. Later rules are not checked.
+    Forward,
+}
+impl<P: Protocol> Clone for ResponseTemplate<P> {
+    fn clone(&self) -> Self {
+        match self {
+            ResponseTemplate::Static(command, payload) => {
+                ResponseTemplate::Static(*command, payload.clone())
+            }
+            ResponseTemplate::EchoPayload(command) => ResponseTemplate::EchoPayload(*command),
+            ResponseTemplate::FromBusyState(response) => ResponseTemplate::FromBusyState(*response),
+            ResponseTemplate::Forward => ResponseTemplate::Forward,
+        }
+    }
+}
+impl<P: Protocol> core::fmt::Debug for ResponseTemplate<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ResponseTemplate::Static(command, payload) => {
+                write!(f, "Static({:?}, {:?})", command, payload)
+            }
+            ResponseTemplate::EchoPayload(command) => write!(f, "EchoPayload({:?})", command),
+            ResponseTemplate::FromBusyState(_) => write!(f, "FromBusyState(..)"),
+            ResponseTemplate::Forward => write!(f, "Forward"),
+        }
+    }
+}
+
+struct Rule<P: Protocol> {
+    command: Matcher<P::Commands>,
+    busy_state: Matcher<P::BusyStates>,
+    response: ResponseTemplate<P>,
+}
+impl<P: Protocol> Clone for Rule<P> {
+    fn clone(&self) -> Self {
+        Self {
+            command: self.command.clone(),
+            busy_state: self.busy_state.clone(),
+            response: self.response.clone(),
+        }
+    }
+}
+impl<P: Protocol> core::fmt::Debug for Rule<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Rule")
+            .field("command", &self.command)
+            .field("busy_state", &self.busy_state)
+            .field("response", &self.response)
+            .finish()
+    }
+}
+
+/// A table of immediate responses, keyed by command & busy state.
+///
+/// The rules are checked in the order they were added & the first matching rule decides (first match wins),
+/// so specific rules have to be added before general ones.
+/// The table can be installed on a connection (see 'TcpIpcConfig::immediate_responses'),
+/// or used within an implementation of 'Protocol::message_is_answered_via_immediate_route' via 'answer'.
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// // answers QueryIsBusy with the busy state, unless the connection is idle
+/// let table = ImmediateResponseTable::<ProtocolExample>::new()
+///     .when(
+///         CommandsExample::QueryIsBusy,
+///         BusyStatesExample::Idle,
+///         ResponseTemplate::Forward,
+///     )
+///     .when(
+///         CommandsExample::QueryIsBusy,
+///         Matcher::Any,
+///         ResponseTemplate::FromBusyState(|busy_state| {
+///             (CommandsExample::IsBusy, vec![busy_state as u8])
+///         }),
+///     )
+///     .when(CommandsExample::Ping, Matcher::Any, ResponseTemplate::EchoPayload(CommandsExample::Pong));
+/// ```
+pub struct ImmediateResponseTable<P: Protocol> {
+    rules: Vec<Rule<P>>,
+}
+impl<P: Protocol> Clone for ImmediateResponseTable<P> {
+    fn clone(&self) -> Self {
+        Self {
+            rules: self.rules.clone(),
+        }
+    }
+}
+impl<P: Protocol> core::fmt::Debug for ImmediateResponseTable<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("ImmediateResponseTable")
+            .field("rules", &self.rules)
+            .finish()
+    }
+}
+impl<P: Protocol> Default for ImmediateResponseTable<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<P: Protocol> ImmediateResponseTable<P> {
+    /// Creates an empty table, which answers no message.
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+    /// Adds a rule, which is checked after all rules added before.
+    /// A command or busy state can be given directly, which matches only this value.
+    pub fn when<C, B>(mut self, command: C, busy_state: B, response: ResponseTemplate<P>) -> Self
+    where
+        C: Into<Matcher<P::Commands>>,
+        B: Into<Matcher<P::BusyStates>>,
+    {
+        self.rules.push(Rule {
+            command: command.into(),
+            busy_state: busy_state.into(),
+            response,
+        });
+        self
+    }
+    /// Returns the response of the first matching rule, like 'Protocol::message_is_answered_via_immediate_route'.
+    /// None is returned if no rule matches or the first matching rule forwards the message.
+    pub fn answer(
+        &self,
+        command: &P::Commands,
+        payload: &[u8],
+        busy_state: &P::BusyStates,
+    ) -> Option<(P::Commands, Vec<u8>)> {
+        self.decide(command, payload, busy_state).flatten()
+    }
+    // returns None if no rule matches, and Some(None) if the first matching rule forwards the message
+    pub(crate) fn decide(
+        &self,
+        command: &P::Commands,
+        payload: &[u8],
+        busy_state: &P::BusyStates,
+    ) -> Option<Option<(P::Commands, Vec<u8>)>> {
+        let rule = self
+            .rules
+            .iter()
+            .find(|rule| rule.command.matches(command) && rule.busy_state.matches(busy_state))?;
+        Some(match &rule.response {
+            ResponseTemplate::Static(command, response) => Some((*command, response.clone())),
+            ResponseTemplate::EchoPayload(command) => Some((*command, payload.to_vec())),
+            ResponseTemplate::FromBusyState(response) => Some(response(*busy_state)),
+            ResponseTemplate::Forward => None,
+        })
+    }
+}
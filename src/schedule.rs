@@ -0,0 +1,114 @@
+use super::protocol_buffer::Protocol;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A message which the read thread sends at a given time (and possibly periodically).
+pub struct ScheduledSend<P: Protocol> {
+    pub command: P::Commands,
+    pub payload_fn: Box<dyn FnMut() -> Vec<u8> + Send>,
+    pub due: std::time::Instant,
+    // None for a one-shot message
+    pub interval: Option<std::time::Duration>,
+    pub cancelled: Arc<AtomicBool>,
+}
+impl<P: Protocol> ScheduledSend<P> {
+    /// Checks if the message is due. If so, the payload is created and the next due time is computed.
+    /// Returns None if the message is not due.
+    pub fn poll(&mut self, now: std::time::Instant) -> Option<Vec<u8>> {
+        if now < self.due {
+            return None;
+        }
+        if let Some(interval) = self.interval {
+            self.due += interval;
+            // after a long pause, ticks are skipped instead of being sent in a burst
+            if self.due <= now {
+                self.due = now + interval;
+            }
+        }
+        Some((self.payload_fn)())
+    }
+    /// Checks if the message will be sent again.
+    pub fn is_finished(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+    pub fn finish(&self) {
+        self.cancelled.store(true, Ordering::SeqCst)
+    }
+}
+
+/// This is returned for scheduled messages (see 'TcpIpc::send_periodic' & 'TcpIpc::send_after'), to cancel them.
+/// Dropping the handle does not cancel the message.
+#[derive(Debug, Clone)]
+pub struct PeriodicHandle {
+    cancelled: Arc<AtomicBool>,
+}
+impl PeriodicHandle {
+    pub(crate) fn new(cancelled: Arc<AtomicBool>) -> Self {
+        Self { cancelled }
+    }
+    /// Stops sending the message. A message which is currently written is completed.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+    /// Checks if the message is cancelled, or if a one-shot message was sent.
+    pub fn is_finished(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::LengthPrefixedProtocol;
+    use std::time::{Duration, Instant};
+
+    type TestProtocol = LengthPrefixedProtocol<u8, 4, 1>;
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    fn periodic(start: Instant, interval: Option<Duration>) -> ScheduledSend<TestProtocol> {
+        let mut tick = 0u8;
+        ScheduledSend {
+            command: 1,
+            payload_fn: Box::new(move || {
+                tick += 1;
+                vec![tick]
+            }),
+            due: start + INTERVAL,
+            interval,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[test]
+    fn periodic_messages_tick_once_per_interval() {
+        let start = Instant::now();
+        let mut scheduled = periodic(start, Some(INTERVAL));
+        assert_eq!(scheduled.poll(start), None);
+        assert_eq!(scheduled.poll(start + INTERVAL / 2), None);
+        // fast-forward 10 intervals, polling twice per interval
+        let ticks: Vec<_> = (1..=20)
+            .filter_map(|half| scheduled.poll(start + INTERVAL * half / 2))
+            .collect();
+        assert_eq!(ticks, (1..=10).map(|tick| vec![tick]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn ticks_missed_during_a_pause_are_skipped() {
+        let start = Instant::now();
+        let mut scheduled = periodic(start, Some(INTERVAL));
+        assert_eq!(scheduled.poll(start + INTERVAL * 10), Some(vec![1]));
+        assert_eq!(scheduled.poll(start + INTERVAL * 10), None);
+        assert_eq!(scheduled.poll(start + INTERVAL * 11), Some(vec![2]));
+    }
+
+    #[test]
+    fn cancelling_finishes_the_message() {
+        let start = Instant::now();
+        let scheduled = periodic(start, Some(INTERVAL));
+        let handle = PeriodicHandle::new(scheduled.cancelled.clone());
+        assert!(!scheduled.is_finished());
+        handle.cancel();
+        assert!(scheduled.is_finished());
+        assert!(handle.is_finished());
+    }
+}
@@ -0,0 +1,360 @@
+use super::engine::{self, TcpListener};
+use super::protocol_buffer::{Message, Protocol};
+use super::registry::ConnectionId;
+use super::session::{SessionEvent, SessionTable};
+use super::tcp_ipc::{
+    ConnectErrors, ConnectionState, ReadThreadErrors, ShutdownReport, TcpIpc, TcpIpcConfig,
+    WriteMessageErrors,
+};
+use log::*;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// The time 'TcpIpcServer::shutdown_graceful' waits between checks of the connections while draining.
+const DRAIN_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// A server which keeps listening & serves any number of clients, each via a connection of its own.
+///
+/// Unlike 'TcpIpc::server', which accepts a single client, the listener stays bound until the server is dropped.
+/// Each accepted client gets its own 'TcpIpc' (with its own read thread, parser & queues, all set up with the config of the server),
+/// identified by its 'ConnectionId'. So clients connecting & disconnecting do not affect each other.
+/// Messages are taken per connection (see 'connection') or from all connections at once (see 'get_message').
+/// # Example
+/// ```no_run
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # use log::warn;
+/// # fn handle<C, P>(_: C, _: P) {}
+/// # run(|| {
+/// # let config = config();
+/// let mut server = TcpIpcServer::<ProtocolExample>::bind("127.0.0.1:6666", config)?;
+/// loop {
+///     for id in server.accept_pending()? {
+///         server.write_message(id, CommandsExample::Welcome, &[])?;
+///     }
+///     while let Some((id, message)) = server.get_message() {
+///         match message {
+///             Ok((CommandsExample::Bye, _)) => {
+///                 server.disconnect(id);
+///             }
+///             Ok(message) => handle(id, message),
+///             Err(err) => warn!("{}: {:?}", id, err),
+///         }
+///     }
+///     std::thread::sleep(std::time::Duration::from_millis(1));
+/// }
+/// # Ok(())
+/// # });
+/// ```
+pub struct TcpIpcServer<P: Protocol> {
+    // this is None once the server stopped accepting (see 'shutdown_graceful')
+    listener: Option<TcpListener>,
+    config: TcpIpcConfig<P>,
+    connections: BTreeMap<ConnectionId, TcpIpc<P>>,
+    // the connection after which 'get_message' continues, so all connections are served in turn
+    last_served: Option<ConnectionId>,
+    // the messages taken from the connections while draining, which 'get_message' delivers first
+    #[allow(clippy::type_complexity)]
+    drained: VecDeque<(ConnectionId, Result<Message<P>, ReadThreadErrors<P>>)>,
+    sessions: SessionTable,
+    // the sessions started or resumed, until taken by 'next_session_event'
+    session_events: VecDeque<(ConnectionId, SessionEvent)>,
+}
+impl<P: Protocol> std::fmt::Debug for TcpIpcServer<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TcpIpcServer")
+            .field("local_addr", &self.local_addr().ok())
+            .field("connections", &self.connection_ids())
+            .field("drained", &self.drained.len())
+            .field("session_events", &self.session_events)
+            .finish()
+    }
+}
+impl<P: Protocol> TcpIpcServer<P> {
+    /// Binds the listener to the first of the given addresses which can be bound. No client is accepted yet (see 'accept_pending').
+    /// The config is checked (see 'ConnectErrors::InvalidProtocolDefinition') & used for all connections.
+    pub fn bind<T: ToSocketAddrs>(
+        socket_addresses: T,
+        config: TcpIpcConfig<P>,
+    ) -> Result<Self, ConnectErrors> {
+        config.check_protocol()?;
+        let mut error = ConnectErrors::SocketListIsEmpty;
+        for socket_address in super::tcp_ipc::resolve(socket_addresses)? {
+            match engine::bind(&socket_address) {
+                Ok(listener) => {
+                    info!("listening on {:?}", socket_address);
+                    return Ok(Self {
+                        listener: Some(listener),
+                        config,
+                        connections: BTreeMap::new(),
+                        last_served: None,
+                        drained: VecDeque::new(),
+                        sessions: SessionTable::new(),
+                        session_events: VecDeque::new(),
+                    });
+                }
+                Err(err) => error = ConnectErrors::BindError(err),
+            }
+        }
+        Err(error)
+    }
+    /// Returns the address the listener is bound to, for example to find out the port chosen for port 0.
+    /// After 'shutdown_graceful', there is no listener anymore, so 'NotConnected' is returned.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match &self.listener {
+            Some(listener) => listener.local_addr(),
+            None => Err(std::io::ErrorKind::NotConnected.into()),
+        }
+    }
+    /// Accepts all clients which are waiting to connect, without waiting for further ones, and returns the ids of their connections.
+    /// Each connection is set up like by 'TcpIpc::server', so this returns once all of them are ready (see 'TcpIpcConfig::ready_when').
+    ///
+    /// If accepting or setting up a connection fails, the error is returned. Clients accepted before stay connected (see 'connection_ids').
+    /// After 'shutdown_graceful', no client is accepted anymore.
+    pub fn accept_pending(&mut self) -> Result<Vec<ConnectionId>, ConnectErrors> {
+        let mut accepted = Vec::new();
+        let listener = match &self.listener {
+            Some(listener) => listener,
+            None => return Ok(accepted),
+        };
+        loop {
+            let stream = match engine::accept(listener) {
+                Ok((stream, socket_address)) => {
+                    info!("connected to {:?}", socket_address);
+                    stream
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return Ok(accepted),
+                Err(err) => return Err(ConnectErrors::ConnectionError(err)),
+            };
+            let connection = TcpIpc::start_read_thread(stream, self.config.clone())?;
+            let id = connection.id();
+            self.connections.insert(id, connection);
+            accepted.push(id);
+        }
+    }
+    /// Returns the ids of all connections, in the order they were accepted.
+    pub fn connection_ids(&self) -> Vec<ConnectionId> {
+        self.connections.keys().copied().collect()
+    }
+    /// Returns the connection with the given id, for example to await a message of this client only.
+    /// Returns None if there is no such connection (anymore).
+    pub fn connection(&mut self, id: ConnectionId) -> Option<&mut TcpIpc<P>> {
+        self.connections.get_mut(&id)
+    }
+    /// Writes a message to the given connection (see 'TcpIpc::write_message').
+    /// If there is no such connection (anymore), 'ConnectionClosed' is returned.
+    pub fn write_message(
+        &mut self,
+        id: ConnectionId,
+        command: P::Commands,
+        message: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        match self.connections.get_mut(&id) {
+            Some(connection) => connection.write_message(command, message),
+            None => Err(WriteMessageErrors::ConnectionClosed),
+        }
+    }
+    /// Writes a message to all connections & returns the connections it could not be written to, together with the error.
+    pub fn broadcast(
+        &mut self,
+        command: P::Commands,
+        message: &[u8],
+    ) -> Vec<(ConnectionId, WriteMessageErrors)> {
+        self.connections
+            .iter_mut()
+            .filter_map(|(id, connection)| {
+                connection
+                    .write_message(command, message)
+                    .err()
+                    .map(|err| (*id, err))
+            })
+            .collect()
+    }
+    /// Takes the next message of any connection, together with the id of the connection. The connections are served in turn.
+    /// Returns None if no connection has a message (or an error) to report.
+    ///
+    /// Errors are reported like by 'TcpIpc::get_message'. Once a connection reports 'ConnectionClosed' (or 'Disconnected'),
+    /// it is removed, since it delivered everything it received; this error is reported once.
+    /// Messages received while draining (see 'shutdown_graceful') are delivered first.
+    /// Session requests are answered instead of being delivered (see 'next_session_event').
+    #[allow(clippy::type_complexity)]
+    pub fn get_message(
+        &mut self,
+    ) -> Option<(ConnectionId, Result<Message<P>, ReadThreadErrors<P>>)> {
+        if let Some(drained) = self.drained.pop_front() {
+            return Some(drained);
+        }
+        let ids: Vec<_> = match self.last_served {
+            Some(last_served) => {
+                let (before, after): (Vec<_>, Vec<_>) =
+                    self.connections.keys().partition(|id| **id <= last_served);
+                after.into_iter().chain(before).collect()
+            }
+            None => self.connections.keys().copied().collect(),
+        };
+        for id in ids {
+            let received = loop {
+                let received = match self.connections.get_mut(&id) {
+                    Some(connection) => connection.get_message(),
+                    None => break Ok(None),
+                };
+                match received {
+                    Ok(Some((command, token))) if P::session_command() == Some(command) => {
+                        self.open_session(id, command, &token)
+                    }
+                    received => break received,
+                }
+            };
+            let received = match received {
+                Ok(Some(message)) => Ok(message),
+                Ok(None) => continue,
+                Err(err) => {
+                    if let ReadThreadErrors::ConnectionClosed | ReadThreadErrors::Disconnected = err
+                    {
+                        debug!("{}: Connection removed from the server", id);
+                        self.connections.remove(&id);
+                        self.sessions.released(id);
+                    }
+                    Err(err)
+                }
+            };
+            self.last_served = Some(id);
+            return Some((id, received));
+        }
+        None
+    }
+    // answers a session request with the token of the session, see 'Protocol::session_command'
+    fn open_session(&mut self, id: ConnectionId, command: P::Commands, presented: &[u8]) {
+        let event = self.sessions.open(id, presented);
+        debug!("{}: {:?}", id, event);
+        if let Err(err) = self.write_message(id, command, &event.token().0) {
+            warn!("{}: Session token could not be sent: {:?}", id, err);
+        }
+        self.session_events.push_back((id, event));
+    }
+    /// Returns the next session a client started or resumed, together with the id of its connection (see 'Protocol::session_command').
+    /// Sessions are handled by 'get_message', which answers the session requests of the clients, so call it regularly.
+    /// Returns None if there is none, which is always the case for a protocol without sessions.
+    ///
+    /// A client presenting a token which is unknown (or expired, see 'set_session_expiry') gets a new session.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn restore<I, T>(_: I, _: T) {}
+    /// # fn start<I, T>(_: I, _: T) {}
+    /// # run(|| {
+    /// # let mut server = TcpIpcServer::<ProtocolExample>::bind("127.0.0.1:6666", config())?;
+    /// while let Some((id, event)) = server.next_session_event() {
+    ///     match event {
+    ///         SessionEvent::ResumedSession(token) => restore(id, token),
+    ///         SessionEvent::NewSession(token) => start(id, token),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn next_session_event(&mut self) -> Option<(ConnectionId, SessionEvent)> {
+        self.session_events.pop_front()
+    }
+    /// Sets the time a session is kept once its connection was removed (see 'DEFAULT_SESSION_EXPIRY').
+    /// A client presenting the token afterwards gets a new session.
+    pub fn set_session_expiry(&mut self, expiry: std::time::Duration) {
+        self.sessions.set_expiry(expiry);
+    }
+    /// Shuts the given connection down (see 'TcpIpc::shutdown') & removes it. The other connections are not affected.
+    /// Returns None if there is no such connection (anymore).
+    pub fn disconnect(
+        &mut self,
+        id: ConnectionId,
+    ) -> Option<Result<ShutdownReport, ShutdownReport>> {
+        self.sessions.released(id);
+        self.connections
+            .remove(&id)
+            .map(|connection| connection.shutdown())
+    }
+    /// Shuts all connections down in an orderly way & returns the outcome per connection (see 'TcpIpc::shutdown').
+    ///
+    /// First, the listener is closed, so no further client can connect. Then the notify frame (if given) is written to all connections,
+    /// telling the clients that the server is closing. For up to the drain time, the connections keep running, so the clients can finish their exchanges:
+    /// immediate responses are still written and the messages received are kept, to be taken by 'get_message' afterwards.
+    /// The drain ends early once every client disconnected. Finally, each connection is shut down & its read thread joined.
+    ///
+    /// A client which disconnected on its own during the drain counts as clean, so its report is returned as Ok.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # use log::warn;
+    /// # use std::time::Duration;
+    /// # fn handle_final<I, M>(_: I, _: M) {}
+    /// # run(|| {
+    /// # let mut server = TcpIpcServer::<ProtocolExample>::bind("127.0.0.1:6666", config())?;
+    /// let outcomes = server.shutdown_graceful(Some((CommandsExample::Closing, Vec::new())), Duration::from_secs(1));
+    /// for (id, outcome) in outcomes {
+    ///     if let Err(report) = outcome {
+    ///         warn!("{} was not shut down cleanly: {:?}", id, report);
+    ///     }
+    /// }
+    /// while let Some((id, message)) = server.get_message() {
+    ///     handle_final(id, message);
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn shutdown_graceful(
+        &mut self,
+        notify: Option<(P::Commands, Vec<u8>)>,
+        drain: std::time::Duration,
+    ) -> Vec<(ConnectionId, Result<ShutdownReport, ShutdownReport>)> {
+        self.listener = None;
+        if let Some((command, message)) = notify {
+            for (id, err) in self.broadcast(command, &message) {
+                warn!("{}: Closing could not be announced: {:?}", id, err);
+            }
+        }
+        // the connections which delivered everything, since they are closed (for example since the client disconnected)
+        let mut finished = BTreeSet::new();
+        let start = std::time::Instant::now();
+        while finished.len() < self.connections.len() && start.elapsed() < drain {
+            for (id, connection) in &mut self.connections {
+                if finished.contains(id) {
+                    continue;
+                }
+                loop {
+                    match connection.get_message() {
+                        Ok(Some(message)) => self.drained.push_back((*id, Ok(message))),
+                        Ok(None) => break,
+                        Err(err) => {
+                            let done = matches!(
+                                err,
+                                ReadThreadErrors::ConnectionClosed | ReadThreadErrors::Disconnected
+                            );
+                            self.drained.push_back((*id, Err(err)));
+                            if done {
+                                finished.insert(*id);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            std::thread::sleep(DRAIN_CHECK_INTERVAL);
+        }
+        std::mem::take(&mut self.connections)
+            .into_iter()
+            .map(|(id, connection)| {
+                self.sessions.released(id);
+                let disconnected = connection.connection_state() == ConnectionState::PeerClosed;
+                let outcome = connection.shutdown();
+                debug!("{}: Connection shut down by the server: {:?}", id, outcome);
+                let outcome = match outcome {
+                    Err(report) if disconnected => Ok(report),
+                    outcome => outcome,
+                };
+                (id, outcome)
+            })
+            .collect()
+    }
+}
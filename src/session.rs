@@ -0,0 +1,133 @@
+use super::registry::ConnectionId;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The length of a session token in bytes.
+pub const SESSION_TOKEN_LENGTH: usize = 16;
+/// The time a session is kept once its connection is gone, unless set otherwise (see 'TcpIpcServer::set_session_expiry').
+pub const DEFAULT_SESSION_EXPIRY: Duration = Duration::from_secs(60);
+
+/// An opaque token identifying a session, issued by a 'TcpIpcServer' (see 'Protocol::session_command').
+/// A client presents it when it re-establishes a lost connection, so the server recognizes the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken(pub [u8; SESSION_TOKEN_LENGTH]);
+impl SessionToken {
+    // random, so tokens of different servers (& runs) do not collide
+    fn generate() -> Self {
+        static GENERATED: AtomicU64 = AtomicU64::new(0);
+        let count = GENERATED.fetch_add(1, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos());
+        let mut token = [0; SESSION_TOKEN_LENGTH];
+        for (half, bytes) in token.chunks_mut(8).enumerate() {
+            // each 'RandomState' is seeded with fresh random keys
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(count);
+            hasher.write_u128(nanos);
+            hasher.write_usize(half);
+            bytes.copy_from_slice(&hasher.finish().to_be_bytes());
+        }
+        Self(token)
+    }
+    /// Reads the token from a payload, which consists of the token only. Returns None for any other payload.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        payload.try_into().ok().map(Self)
+    }
+}
+impl std::fmt::Display for SessionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// The session a client started or resumed on a connection, as reported by 'TcpIpcServer::next_session_event'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionEvent {
+    /// The client started a new session: it presented no token, or one which is unknown (like one of another server) or expired.
+    NewSession(SessionToken),
+    /// The client presented the token of a known session, which is continued on this connection.
+    ResumedSession(SessionToken),
+}
+impl SessionEvent {
+    /// Returns the token of the session.
+    pub fn token(&self) -> SessionToken {
+        match self {
+            SessionEvent::NewSession(token) | SessionEvent::ResumedSession(token) => *token,
+        }
+    }
+}
+
+/// The session token of a client, stored by the read thread once the server answered.
+pub type SharedSessionToken = Arc<Mutex<Option<SessionToken>>>;
+pub fn new_session_token() -> SharedSessionToken {
+    Arc::new(Mutex::new(None))
+}
+
+struct Session {
+    connection: ConnectionId,
+    // the time the connection was removed, from which on the session expires
+    released: Option<Instant>,
+}
+
+/// The sessions of a server, each bound to the connection which started or resumed it last.
+pub struct SessionTable {
+    expiry: Duration,
+    sessions: HashMap<SessionToken, Session>,
+}
+impl SessionTable {
+    pub fn new() -> Self {
+        Self {
+            expiry: DEFAULT_SESSION_EXPIRY,
+            sessions: HashMap::new(),
+        }
+    }
+    pub fn set_expiry(&mut self, expiry: Duration) {
+        self.expiry = expiry;
+    }
+    /// Binds the connection to the session of the presented token if it is known, otherwise to a new session.
+    pub fn open(&mut self, connection: ConnectionId, presented: &[u8]) -> SessionEvent {
+        let expiry = self.expiry;
+        self.sessions.retain(|_, session| {
+            session
+                .released
+                .is_none_or(|released| released.elapsed() < expiry)
+        });
+        let session = Session {
+            connection,
+            released: None,
+        };
+        match SessionToken::from_payload(presented) {
+            Some(token) if self.sessions.contains_key(&token) => {
+                self.sessions.insert(token, session);
+                SessionEvent::ResumedSession(token)
+            }
+            _ => {
+                let mut token = SessionToken::generate();
+                while self.sessions.contains_key(&token) {
+                    token = SessionToken::generate();
+                }
+                self.sessions.insert(token, session);
+                SessionEvent::NewSession(token)
+            }
+        }
+    }
+    /// Starts the expiry of the session bound to the connection, which was removed.
+    /// A session resumed on another connection meanwhile is not affected.
+    pub fn released(&mut self, connection: ConnectionId) {
+        let now = Instant::now();
+        for session in self.sessions.values_mut() {
+            if session.connection == connection && session.released.is_none() {
+                session.released = Some(now);
+            }
+        }
+    }
+}
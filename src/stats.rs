@@ -0,0 +1,362 @@
+use super::memory_budget::{MemoryUsage, SharedMemoryBudget};
+use super::protocol_buffer::Protocol;
+use super::registry::ConnectionId;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of buckets of the frame size histograms (see 'ConnectionStats').
+///
+/// Bucket 0 counts empty frames, bucket i (for 1 <= i < 31) counts frames of at least 2^(i-1) and less than 2^i bytes,
+/// and the last bucket counts all frames of at least 2^30 bytes.
+pub const FRAME_SIZE_BUCKETS: usize = 32;
+
+/// The histogram bucket of a frame of the given size, see 'FRAME_SIZE_BUCKETS'.
+pub fn frame_size_bucket(size: usize) -> usize {
+    let bits = (usize::BITS - size.leading_zeros()) as usize;
+    bits.min(FRAME_SIZE_BUCKETS - 1)
+}
+
+/// The number of buckets of the exchange latency histograms (see 'ConnectionStats::exchange_latencies').
+///
+/// Bucket 0 counts latencies below 1 microsecond, bucket i (for 1 <= i < 23) counts latencies of at least 2^(i-1) and less than 2^i microseconds,
+/// and the last bucket counts all latencies of at least 2^22 microseconds (about 4.2 seconds).
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// The histogram bucket of the given latency, see 'LATENCY_BUCKETS'.
+pub fn latency_bucket(latency: std::time::Duration) -> usize {
+    let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+    let bits = (u64::BITS - micros.leading_zeros()) as usize;
+    bits.min(LATENCY_BUCKETS - 1)
+}
+/// The upper bound of the bucket containing the given fraction (between 0 & 1) of the latencies, capped by the maximal latency.
+fn latency_quantile(
+    latencies: &[u64; LATENCY_BUCKETS],
+    max: std::time::Duration,
+    fraction: f64,
+) -> Option<std::time::Duration> {
+    let count: u64 = latencies.iter().sum();
+    if count == 0 {
+        return None;
+    }
+    let rank = ((count as f64 * fraction).ceil() as u64).max(1);
+    let mut seen = 0;
+    for (bucket, &latencies) in latencies.iter().enumerate() {
+        seen += latencies;
+        if seen >= rank {
+            let upper = std::time::Duration::from_micros(1 << bucket);
+            return Some(upper.min(max));
+        }
+    }
+    Some(max)
+}
+
+/// The counters of a connection, shared between the read thread and the main thread.
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    connection_id: ConnectionId,
+    messages_received: AtomicU64,
+    messages_sent: AtomicU64,
+    immediate_responses_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    read_iterations: AtomicU64,
+    duplicates_dropped: AtomicU64,
+    max_received_frame: AtomicU64,
+    max_sent_frame: AtomicU64,
+    received_frame_sizes: [AtomicU64; FRAME_SIZE_BUCKETS],
+    sent_frame_sizes: [AtomicU64; FRAME_SIZE_BUCKETS],
+    exchange_latencies: [AtomicU64; LATENCY_BUCKETS],
+    // in nanoseconds
+    max_exchange_latency: AtomicU64,
+    exchange_timeouts: AtomicU64,
+    immediate_route_checks: AtomicU64,
+    // in nanoseconds
+    immediate_route_time: AtomicU64,
+    max_immediate_route_time: AtomicU64,
+    immediate_route_over_budget: AtomicU64,
+    frames_dropped_by_budget: AtomicU64,
+    memory_budget: Option<SharedMemoryBudget>,
+}
+impl StatsCounters {
+    pub fn new(connection_id: ConnectionId, memory_budget: Option<SharedMemoryBudget>) -> Self {
+        Self {
+            connection_id,
+            memory_budget,
+            ..Self::default()
+        }
+    }
+    /// The memory budget of the connection, which is shared by all its buffers & queues.
+    pub fn memory_budget(&self) -> &Option<SharedMemoryBudget> {
+        &self.memory_budget
+    }
+    pub fn message_received(&self, frame_size: usize) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.max_received_frame
+            .fetch_max(frame_size as u64, Ordering::Relaxed);
+        self.received_frame_sizes[frame_size_bucket(frame_size)].fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn message_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.frame_sent(bytes);
+    }
+    pub fn immediate_response_sent(&self, bytes: usize) {
+        self.immediate_responses_sent
+            .fetch_add(1, Ordering::Relaxed);
+        self.frame_sent(bytes);
+    }
+    // pings & acknowledgments only count as bytes (and frame sizes)
+    pub fn control_frame_sent(&self, bytes: usize) {
+        self.frame_sent(bytes);
+    }
+    fn frame_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.max_sent_frame
+            .fetch_max(bytes as u64, Ordering::Relaxed);
+        self.sent_frame_sizes[frame_size_bucket(bytes)].fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn duplicate_dropped(&self) {
+        self.duplicates_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Counts a frame of the read thread which was not queued, since the memory budget was used up.
+    pub fn frame_dropped_by_budget(&self) {
+        self.frames_dropped_by_budget
+            .fetch_add(1, Ordering::Relaxed);
+    }
+    /// Counts a request/response exchange, with its latency or as timed out (None).
+    pub fn exchange_finished(&self, latency: Option<std::time::Duration>) {
+        match latency {
+            Some(latency) => {
+                self.exchange_latencies[latency_bucket(latency)].fetch_add(1, Ordering::Relaxed);
+                self.max_exchange_latency.fetch_max(
+                    u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX),
+                    Ordering::Relaxed,
+                );
+            }
+            None => {
+                self.exchange_timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    /// Counts the time the read thread spent on the immediate route of a frame, see 'ImmediateResponseBudget'.
+    pub fn immediate_route_timed(&self, elapsed: std::time::Duration, over_budget: bool) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.immediate_route_checks.fetch_add(1, Ordering::Relaxed);
+        self.immediate_route_time
+            .fetch_add(nanos, Ordering::Relaxed);
+        self.max_immediate_route_time
+            .fetch_max(nanos, Ordering::Relaxed);
+        if over_budget {
+            self.immediate_route_over_budget
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+    pub fn bytes_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+    pub fn read_iteration(&self) {
+        self.read_iterations.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn snapshot(&self) -> ConnectionStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut received_frame_sizes = [0; FRAME_SIZE_BUCKETS];
+        let mut sent_frame_sizes = [0; FRAME_SIZE_BUCKETS];
+        for bucket in 0..FRAME_SIZE_BUCKETS {
+            received_frame_sizes[bucket] = load(&self.received_frame_sizes[bucket]);
+            sent_frame_sizes[bucket] = load(&self.sent_frame_sizes[bucket]);
+        }
+        let mut exchange_latencies = [0; LATENCY_BUCKETS];
+        for (bucket, latencies) in exchange_latencies.iter_mut().enumerate() {
+            *latencies = load(&self.exchange_latencies[bucket]);
+        }
+        ConnectionStats {
+            connection_id: self.connection_id,
+            messages_received: load(&self.messages_received),
+            messages_sent: load(&self.messages_sent),
+            immediate_responses_sent: load(&self.immediate_responses_sent),
+            bytes_received: load(&self.bytes_received),
+            bytes_sent: load(&self.bytes_sent),
+            read_iterations: load(&self.read_iterations),
+            duplicates_dropped: load(&self.duplicates_dropped),
+            max_received_frame: load(&self.max_received_frame),
+            max_sent_frame: load(&self.max_sent_frame),
+            received_frame_sizes,
+            sent_frame_sizes,
+            exchange_latencies,
+            max_exchange_latency: std::time::Duration::from_nanos(load(&self.max_exchange_latency)),
+            exchange_timeouts: load(&self.exchange_timeouts),
+            immediate_route_checks: load(&self.immediate_route_checks),
+            immediate_route_time: std::time::Duration::from_nanos(load(&self.immediate_route_time)),
+            max_immediate_route_time: std::time::Duration::from_nanos(load(
+                &self.max_immediate_route_time,
+            )),
+            immediate_route_over_budget: load(&self.immediate_route_over_budget),
+            frames_dropped_by_budget: load(&self.frames_dropped_by_budget),
+            memory: self.memory_budget.as_ref().map(|budget| budget.usage()),
+        }
+    }
+}
+
+/// A snapshot of the counters of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConnectionStats {
+    /// The id of the connection.
+    pub connection_id: ConnectionId,
+    /// The number of messages parsed by the read thread (including messages answered via the immediate route).
+    pub messages_received: u64,
+    /// The number of messages written via 'write_message'.
+    pub messages_sent: u64,
+    /// The number of immediate responses queued for sending by the read thread.
+    pub immediate_responses_sent: u64,
+    /// The number of bytes read from the TCP-stream.
+    pub bytes_received: u64,
+    /// The number of bytes written to the TCP-stream (headers included).
+    pub bytes_sent: u64,
+    /// The number of reads of the TCP-stream by the read thread, including reads without data.
+    /// This stops growing once the read thread finished, for example after the peer closed the connection.
+    pub read_iterations: u64,
+    /// The number of received frames dropped as duplicates, see 'TcpIpcConfig::dedup_window'.
+    pub duplicates_dropped: u64,
+    /// The size of the largest frame (header & payload) received.
+    pub max_received_frame: u64,
+    /// The size of the largest frame (header & payload) sent, including immediate responses, pings & acknowledgments.
+    pub max_sent_frame: u64,
+    /// The histogram of the sizes of the received frames, see 'FRAME_SIZE_BUCKETS'.
+    pub received_frame_sizes: [u64; FRAME_SIZE_BUCKETS],
+    /// The histogram of the sizes of the sent frames, see 'FRAME_SIZE_BUCKETS'.
+    pub sent_frame_sizes: [u64; FRAME_SIZE_BUCKETS],
+    /// The histogram of the latencies of the completed request/response exchanges (see 'TransactionGuard::send_and_wait'), see 'LATENCY_BUCKETS'.
+    pub exchange_latencies: [u64; LATENCY_BUCKETS],
+    /// The largest latency of a completed exchange.
+    pub max_exchange_latency: std::time::Duration,
+    /// The number of exchanges which timed out. They are not counted as latencies.
+    pub exchange_timeouts: u64,
+    /// The number of received frames whose immediate route was timed, see 'TcpIpcConfig::immediate_response_budget'.
+    /// Frames are only timed if a budget is configured.
+    pub immediate_route_checks: u64,
+    /// The total time the read thread spent on the immediate route (decision, construction & queueing of the response) of the timed frames.
+    pub immediate_route_time: std::time::Duration,
+    /// The largest time the read thread spent on the immediate route of a single frame.
+    pub max_immediate_route_time: std::time::Duration,
+    /// The number of frames whose immediate route exceeded the budget.
+    pub immediate_route_over_budget: u64,
+    /// The number of frames of the read thread (immediate responses, acknowledgments, pings, scheduled messages & fault frames) which were dropped, since the memory budget was used up.
+    /// The peer never receives these frames, so a growing count means that the budget is too small for the traffic.
+    pub frames_dropped_by_budget: u64,
+    /// The memory held by the buffers & queues of the connection. This is None if no budget is configured (see 'TcpIpcConfig::memory_budget').
+    pub memory: Option<MemoryUsage>,
+}
+impl ConnectionStats {
+    /// The median latency of the completed exchanges, as the upper bound of its histogram bucket. None if no exchange completed.
+    pub fn exchange_latency_p50(&self) -> Option<std::time::Duration> {
+        latency_quantile(&self.exchange_latencies, self.max_exchange_latency, 0.5)
+    }
+    /// The 95th percentile of the latencies of the completed exchanges, as the upper bound of its histogram bucket. None if no exchange completed.
+    pub fn exchange_latency_p95(&self) -> Option<std::time::Duration> {
+        latency_quantile(&self.exchange_latencies, self.max_exchange_latency, 0.95)
+    }
+    /// The mean time the read thread spent on the immediate route of a frame. None if no frame was timed.
+    pub fn mean_immediate_route_time(&self) -> Option<std::time::Duration> {
+        if self.immediate_route_checks == 0 {
+            return None;
+        }
+        let mean = self.immediate_route_time.as_nanos() / u128::from(self.immediate_route_checks);
+        Some(std::time::Duration::from_nanos(
+            u64::try_from(mean).unwrap_or(u64::MAX),
+        ))
+    }
+}
+
+/// The counters of a single command, see 'TcpIpc::command_stats'.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandStats {
+    /// The number of frames with this command sent (including immediate responses, scheduled messages, pings & acknowledgments).
+    pub sent: u64,
+    /// The number of frames with this command parsed by the read thread (including messages answered via the immediate route).
+    pub received: u64,
+    /// The time the last frame with this command was sent.
+    pub last_sent_at: Option<std::time::SystemTime>,
+    /// The time the last frame with this command was received.
+    pub last_received_at: Option<std::time::SystemTime>,
+    /// The histogram of the latencies of the completed exchanges with this command as request, see 'ConnectionStats::exchange_latencies'.
+    pub exchange_latencies: [u64; LATENCY_BUCKETS],
+    /// The largest latency of a completed exchange with this command as request.
+    pub max_exchange_latency: std::time::Duration,
+    /// The number of exchanges with this command as request which timed out.
+    pub exchange_timeouts: u64,
+}
+impl CommandStats {
+    /// The median latency of the completed exchanges with this command as request, see 'ConnectionStats::exchange_latency_p50'.
+    pub fn exchange_latency_p50(&self) -> Option<std::time::Duration> {
+        latency_quantile(&self.exchange_latencies, self.max_exchange_latency, 0.5)
+    }
+    /// The 95th percentile of the latencies of the completed exchanges with this command as request, see 'ConnectionStats::exchange_latency_p95'.
+    pub fn exchange_latency_p95(&self) -> Option<std::time::Duration> {
+        latency_quantile(&self.exchange_latencies, self.max_exchange_latency, 0.95)
+    }
+}
+
+/// The per-command counters of a connection, shared between the read thread and the main thread.
+/// A list is used (instead of a map), since the number of distinct commands is small and commands are only required to be comparable.
+pub type SharedCommandStats<P> = Arc<Mutex<Vec<(<P as Protocol>::Commands, CommandStats)>>>;
+
+fn with_command_stats<P: Protocol>(
+    command_stats: &Option<SharedCommandStats<P>>,
+    command: P::Commands,
+    update: impl FnOnce(&mut CommandStats),
+) {
+    if let Some(command_stats) = command_stats {
+        let mut command_stats = command_stats.lock().unwrap_or_else(|e| e.into_inner());
+        match command_stats
+            .iter_mut()
+            .find(|(known, _)| *known == command)
+        {
+            Some((_, stats)) => update(stats),
+            None => {
+                let mut stats = CommandStats::default();
+                update(&mut stats);
+                command_stats.push((command, stats));
+            }
+        }
+    }
+}
+/// Counts a sent frame, if per-command statistics are enabled.
+pub fn command_sent<P: Protocol>(
+    command_stats: &Option<SharedCommandStats<P>>,
+    command: P::Commands,
+) {
+    with_command_stats::<P>(command_stats, command, |stats| {
+        stats.sent += 1;
+        stats.last_sent_at = Some(std::time::SystemTime::now());
+    })
+}
+/// Counts a received frame, if per-command statistics are enabled.
+pub fn command_received<P: Protocol>(
+    command_stats: &Option<SharedCommandStats<P>>,
+    command: P::Commands,
+) {
+    with_command_stats::<P>(command_stats, command, |stats| {
+        stats.received += 1;
+        stats.last_received_at = Some(std::time::SystemTime::now());
+    })
+}
+/// Counts a request/response exchange (with its latency, or as timed out), if per-command statistics are enabled.
+pub fn command_exchanged<P: Protocol>(
+    command_stats: &Option<SharedCommandStats<P>>,
+    command: P::Commands,
+    latency: Option<std::time::Duration>,
+) {
+    with_command_stats::<P>(command_stats, command, |stats| match latency {
+        Some(latency) => {
+            stats.exchange_latencies[latency_bucket(latency)] += 1;
+            stats.max_exchange_latency = stats.max_exchange_latency.max(latency);
+        }
+        None => stats.exchange_timeouts += 1,
+    })
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+/// A transformation of the bytes persisted to disk (like an encryption at rest), see 'JournalConfig::codec' & 'replay::save_capture'.
+///
+/// Every persisted frame or chunk is encoded on its own, so 'encode' may change its length (for example, to add a nonce & a tag).
+/// A file has to be read with the codec it was written with.
+pub trait StorageCodec: Send + Sync {
+    /// Transforms the bytes before they are written to disk.
+    fn encode(&self, bytes: &[u8]) -> Vec<u8>;
+    /// Restores the bytes read from disk. An error (like a failed authentication) is reported by the reader, nothing is guessed.
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError>;
+}
+
+/// A shared codec, as configured for a journal.
+pub type SharedStorageCodec = Arc<dyn StorageCodec>;
+
+/// The error of 'StorageCodec::decode'.
+/// The readers return it as the inner error of an 'std::io::Error' of kind 'InvalidData' (see 'std::io::Error::get_ref').
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodecError {
+    /// A description of the failure.
+    pub reason: String,
+}
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "stored bytes could not be decoded: {}", self.reason)
+    }
+}
+impl std::error::Error for CodecError {}
+impl CodecError {
+    // wraps the error for the readers, which report 'std::io::Error'
+    pub(crate) fn into_io_error(self) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, self)
+    }
+}
+
+/// The codec which stores the bytes unchanged. This is used if no codec is configured.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PassThrough;
+impl StorageCodec for PassThrough {
+    fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        bytes.to_vec()
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A codec which XORs the bytes with a repeated key & prepends a marker byte, to check the plumbing of a real codec.
+/// This is no encryption: anybody can restore the bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XorCodec {
+    key: Vec<u8>,
+}
+impl XorCodec {
+    // marks encoded bytes, so bytes which were not encoded by this codec fail to decode
+    const MARKER: u8 = 0xA5;
+    /// Creates the codec. An empty key is replaced by a single zero byte (leaving the bytes unchanged except for the marker).
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            key: if key.is_empty() {
+                vec![0]
+            } else {
+                key.to_vec()
+            },
+        }
+    }
+    fn apply(&self, bytes: &[u8]) -> Vec<u8> {
+        let key = self.key.iter().cycle();
+        bytes
+            .iter()
+            .zip(key)
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
+}
+impl StorageCodec for XorCodec {
+    fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(bytes.len() + 1);
+        encoded.push(Self::MARKER);
+        encoded.extend_from_slice(&self.apply(bytes));
+        encoded
+    }
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        match bytes.split_first() {
+            Some((&Self::MARKER, encoded)) => Ok(self.apply(encoded)),
+            _ => Err(CodecError {
+                reason: "missing marker of the XOR codec".to_string(),
+            }),
+        }
+    }
+}
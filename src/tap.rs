@@ -0,0 +1,33 @@
+use super::protocol_buffer::Protocol;
+use log::*;
+use std::sync::Arc;
+
+/// The direction of a frame passed to the frame tap (see 'TcpIpcConfig::frame_tap').
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FrameDirection {
+    /// The frame was received from the peer.
+    Received,
+    /// The frame is sent to the peer (by 'write_message' or by the read thread).
+    Sent,
+}
+
+/// A callback which sees every frame with its borrowed payload, see 'TcpIpcConfig::frame_tap'.
+pub type FrameTap<P> = Arc<dyn Fn(FrameDirection, &<P as Protocol>::Commands, &[u8]) + Send + Sync>;
+
+/// Passes a frame to the tap, if any. A panic of the tap is logged & otherwise ignored.
+pub fn tap<P: Protocol>(
+    frame_tap: &Option<FrameTap<P>>,
+    direction: FrameDirection,
+    command: &P::Commands,
+    payload: &[u8],
+) {
+    if let Some(frame_tap) = frame_tap {
+        let tapped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            frame_tap(direction, command, payload)
+        }));
+        if tapped.is_err() {
+            error!("Frame tap panicked on {:?} frame {:?}", direction, command);
+        }
+    }
+}
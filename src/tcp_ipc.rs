@@ -1,59 +1,670 @@
-use super::protocol_buffer::*;
+use super::journal::Journal;
+use super::lanes::{new_lane_router, split, SharedLaneRouter};
+use super::memory_budget::{self, MemoryBudget};
+use super::outgoing_queue::*;
+use super::read_thread::*;
+use super::registry::Registration;
+use super::reliability::*;
+use super::schedule::ScheduledSend;
+use super::stats::{command_exchanged, command_sent, SharedCommandStats, StatsCounters};
+use super::tap::tap;
+use super::trace::{new_trace, trace_entries, trace_sent, SharedTrace};
+use super::unacked::{deliver, in_flight_count, new_in_flight, redeliver, SharedInFlight};
 
-pub use super::protocol_buffer::{ParseHeaderError, Protocol};
+#[cfg(feature = "tokio")]
+pub use super::async_tcp_ipc::AsyncTcpIpc;
+pub use super::bridge::{
+    bridge, BridgeAction, BridgeEnd, BridgeHandle, BridgeShutdown, BridgeStats, Direction,
+    DirectionStats,
+};
+pub use super::build_info::{
+    build_info, BuildInfo, CapabilityReport, ProtocolCapabilities, ResolvedConfig,
+};
+pub use super::connection_group::ConnectionGroup;
+pub use super::cooperative::{TcpIpcCooperative, TickReport};
+pub use super::deadline::{AwaitOutcome, DeadlineBudget};
+pub use super::dedup::DEDUP_CAPACITY;
+pub use super::delivery::{DeliveryReport, MessageMetadata, MessageOrGap, MessageWithContext};
+pub use super::diagnostics::*;
+use super::engine::{self, TcpStream};
+pub use super::file_format::UnsupportedFormatVersion;
+pub use super::inline::TcpIpcInline;
+pub use super::journal::{JournalConfig, JournalId};
+pub use super::lanes::{Lane, LaneConfig, LaneOverflow, RpcHandle, StreamHandle};
+pub use super::listener::TcpIpcListener;
+pub use super::memory_budget::MemoryUsage;
+pub use super::pair::close_both;
+use super::probe::{new_first_bytes, preview, SharedFirstBytes};
+pub use super::probe::{ProbeConfig, PROBE_CAPTURE_LENGTH};
+use super::protocol::check_definition;
+pub use super::protocol_buffer::{
+    Message, ParseHeaderError, ParserState, PeerInfo, Protocol, ProtocolBuffer, ProtocolViolation,
+    Reservation, TruncatedFrame, DEFAULT_MAX_HEADER_SIZE,
+};
+use super::rate_limit::{new_token_bucket, SharedTokenBucket};
+pub use super::rate_limit::{RateLimit, RateLimitPolicy};
+pub use super::reconnect::{ConnectionEvent, ReconnectPolicy};
+use super::reconnect::{ReconnectState, SharedReconnectState};
+#[cfg(feature = "registry")]
+pub use super::registry::registry;
+pub use super::registry::{ConnectionDescriptor, ConnectionId};
+pub use super::reliability::{DeliveryHandle, ReliabilityConfig};
+pub use super::response_budget::{BudgetExceededHook, ImmediateResponseBudget};
+pub use super::response_table::{ImmediateResponseTable, Matcher, ResponseTemplate};
+pub use super::schedule::PeriodicHandle;
+pub use super::server::TcpIpcServer;
+use super::session::{new_session_token, SharedSessionToken};
+pub use super::session::{
+    SessionEvent, SessionToken, DEFAULT_SESSION_EXPIRY, SESSION_TOKEN_LENGTH,
+};
+pub use super::stats::{
+    frame_size_bucket, latency_bucket, CommandStats, ConnectionStats, FRAME_SIZE_BUCKETS,
+    LATENCY_BUCKETS,
+};
+pub use super::storage_codec::{
+    CodecError, PassThrough, SharedStorageCodec, StorageCodec, XorCodec,
+};
+pub use super::tap::{FrameDirection, FrameTap};
+pub use super::trace::{FrameDisposition, IncomingTraceEntry, TraceConfig, TraceEntry};
+pub use super::transaction::{TransactionErrors, TransactionGuard};
+pub use super::unacked::UnackedMessage;
+pub use super::waker::AwaitWaker;
+use super::waker::SharedWakeSignal;
+use super::write_pressure::WatermarkTracker;
+pub use super::write_pressure::{WritePressure, WritePressureLevel, WritePressureWatermarks};
 use log::*;
-use mio::net::{TcpListener, TcpStream};
-use std::io::{Read, Write};
-use std::net::ToSocketAddrs;
-use std::sync::mpsc::TryRecvError;
+use std::collections::{BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::io::Write;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
 
-const BUFFER_SIZE: usize = 128;
-
-#[derive(Debug, Clone, Copy, PartialEq)]
 /// This bundles the time-settings for the protocol
 /// A 'None' value means that there will no time spend waiting.
+///
+/// Since version 0.4, the config is generic over the protocol & not 'Copy' (it holds hooks & names), see the crate documentation for migrating.
 /// # Example
 /// ```
-/// let config = TcpIpcConfig {
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// let config = TcpIpcConfig::<ProtocolExample> {
 ///     after_connect_wait_time: Some(std::time::Duration::from_micros(5_000)),
 ///     read_iteration_wait_time: Some(std::time::Duration::from_micros(1)),
 ///     shutdown_wait_time: Some(std::time::Duration::from_micros(5_000_000)),
-///     check_count: 1,
+///     name: Some("camera".to_string()),
+///     ..TcpIpcConfig::default()
 /// };
 /// ```
-pub struct TcpIpcConfig {
+pub struct TcpIpcConfig<P: Protocol> {
     /// This is the time the program waits for the server after it accepted the initial TCP connection.
     /// For example, this can be used to wait for the server doing some initialization.
+    /// It is only used if 'ready_when' is None, which waits for readiness instead of a fixed time.
     /// Moreover, the message read queue thread needs some time to start.
+    /// Immediate responses are already answered by the read thread during this time.
     pub after_connect_wait_time: Option<std::time::Duration>,
     /// This is the time the client sleeps between checking for new messages from the server.
     /// Very small values can yield high CPU-usage.
     pub read_iteration_wait_time: Option<std::time::Duration>,
     /// This is the time the client waits for the server to accept a shutdown request.
+    /// During this time, immediate responses which are not yet completely written are drained by the read thread.
+    /// 'shutdown' returns as soon as the read thread confirmed that it finished, so this is only waited in full if it does not.
+    /// If None, 'shutdown' waits for the confirmation without bound.
     pub shutdown_wait_time: Option<std::time::Duration>,
-    /// This is the number of iterations inside the read thread after which the busy_update_state will be checked
-    /// A good default value is 1 (check after each iteration)
+    /// Deprecated, use 'control_check_interval' instead.
+    /// This is only used if 'control_check_interval' is None, in which case the interval is approximated by 'check_count' times 'read_iteration_wait_time'.
     pub check_count: u32,
+    /// This is the time after which the read thread handles control requests (shutdown, busy state updates & queries), independent of the traffic.
+    /// Control requests are also handled whenever no data was available, so an idle connection reacts immediately.
+    pub control_check_interval: Option<std::time::Duration>,
+    /// This determines how the read thread reacts if an immediate response cannot be constructed.
+    pub on_immediate_construct_failure: ImmediateFailurePolicy<P>,
+    /// This is the TCP_NODELAY option set after connecting. 'Some(true)' disables Nagle's algorithm, which is recommended for low latency.
+    /// A 'None' value leaves the default of the operating system untouched (for example for transports which reject this option).
+    pub nodelay: Option<bool>,
+    /// This is the size of the send buffer of the operating system (SO_SNDBUF), set after connecting.
+    /// A 'None' value leaves the default of the operating system untouched, which is recommended unless the memory per connection has to be bounded.
+    pub send_buffer_size: Option<usize>,
+    /// This is the size of the receive buffer of the operating system (SO_RCVBUF), like 'send_buffer_size'.
+    pub recv_buffer_size: Option<usize>,
+    /// This is a name for the connection. The read thread is named "tcp-ipc/{name}/read" (or "tcp-ipc/read" if None), which shows up in panic messages & profilers.
+    pub name: Option<String>,
+    /// This is run first thing on the read thread, for example to apply platform-specific thread priority calls.
+    /// Since a config can be used for several connections, the hook is shared and may be called several times.
+    pub thread_priority: Option<Arc<dyn Fn() + Send + Sync>>,
+    /// If nothing was written for the given time, the read thread sends the given command with an empty payload.
+    /// Writing forces the operating system to detect a vanished peer, which is then reported as a write error.
+    /// The peer has to ignore (or answer) this command.
+    pub write_idle_ping: Option<(std::time::Duration, P::Commands)>,
+    /// This enables the reliability layer (see 'TcpIpc::write_message_reliable'). It requires a protocol which defines 'ack_command' & 'is_reliable_command'.
+    pub reliability: Option<ReliabilityConfig>,
+    /// If enabled, 'write_message' parses each constructed frame back and rejects it if command or payload length do not round-trip.
+    /// This catches flawed protocol implementations before the stream is desynchronized.
+    /// A 'None' value enables this for debug builds only.
+    pub verify_frames: Option<bool>,
+    /// This enables per-command counters (see 'TcpIpc::command_stats'). Their memory is bounded by the number of distinct commands.
+    pub per_command_stats: bool,
+    /// This is the busy state the read thread starts with, so it already applies to the very first incoming message.
+    /// A 'None' value means the protocol's 'idle' state.
+    pub initial_busy_state: Option<P::BusyStates>,
+    /// This determines how the read thread reacts to a protocol violation of the peer, i.e. a header which cannot be parsed.
+    pub strictness: Strictness,
+    /// If given, the callback is called when the bytes waiting to be written cross the high or low watermark, so producers can pause & resume.
+    /// See also 'TcpIpc::write_pressure'.
+    pub write_pressure_watermarks: Option<WritePressureWatermarks>,
+    /// This is the maximal number of payload bytes kept in an error (like 'ImmediateMessageConstructError'), so a failure on a large frame does not hold its memory.
+    /// The length of the original payload is kept as well. A sensible value is 'DEFAULT_ERROR_PAYLOAD_RETENTION'.
+    pub error_payload_retention: usize,
+    /// If given, received frames whose key (see 'Protocol::dedup_key') matches a frame received within this time are dropped before delivery.
+    /// They are counted as 'duplicates_dropped' (see 'stats'). At most 'DEDUP_CAPACITY' recent keys are remembered.
+    pub dedup_window: Option<std::time::Duration>,
+    /// If given, the most recent outgoing frames are kept for post-mortem debugging (see 'TcpIpc::outgoing_trace').
+    pub outgoing_trace: Option<TraceConfig>,
+    /// If given, the most recent incoming frames are kept for post-mortem debugging (see 'TcpIpc::incoming_trace').
+    pub incoming_trace: Option<TraceConfig>,
+    /// This is the time a client waits for a banner (see 'Protocol::is_banner'), counted from starting the read thread.
+    /// The client returns as soon as a banner arrived, but not before 'after_connect_wait_time' passed.
+    /// A 'None' value means that only frames received within 'after_connect_wait_time' are checked.
+    pub banner_wait_time: Option<std::time::Duration>,
+    /// If given, 'write_message' retries while the stream would block (or the write was interrupted), so it returns once the frame is written.
+    /// Partially written frames are continued, so no byte is sent twice. Once the time is exhausted, 'MessageSendFailed' is returned & the connection is closed.
+    /// A 'None' value queues the unwritten rest of the frame instead, which the read thread writes (see 'TcpIpc::write_pressure'). See also 'TcpIpc::write_message_within'.
+    pub write_retry: Option<RetrySpec>,
+    /// If given, this sees every frame with its command & borrowed payload, without copying: received frames right after parsing
+    /// (before they are answered, dropped or delivered) and sent frames right after construction (before writing, including immediate responses, pings & acknowledgments).
+    /// The payload is passed as on the wire, for example including the frame id of reliable messages.
+    /// It is called synchronously on the read thread & within 'write_message', so it is latency-critical and has to return quickly.
+    /// A panic of the tap is caught & logged, so it cannot take down the connection.
+    pub frame_tap: Option<FrameTap<P>>,
+    /// If given, the time the read thread spends on the immediate route of each received frame is measured (see 'ConnectionStats::max_immediate_route_time'),
+    /// and exceeding the budget is reported, since a slow immediate route delays every following frame.
+    pub immediate_response_budget: Option<ImmediateResponseBudget<P>>,
+    /// If given, frames written via 'TcpIpc::write_message_journaled' are journaled in this file, so they can be recovered after a crash (see 'journal').
+    pub journal: Option<JournalConfig>,
+    /// If given, connecting returns as soon as this condition holds, instead of sleeping for 'after_connect_wait_time'.
+    pub ready_when: Option<ReadyCondition>,
+    /// This is the maximal time connecting waits for 'ready_when' to hold, counted from starting the read thread.
+    /// If it is exceeded, connecting fails with 'WaitTimeExceeded'. A 'None' value means that there is no bound.
+    pub ready_wait_time: Option<std::time::Duration>,
+    /// If given, outgoing frames are limited to this rate, smoothing bursts for peers with small buffers (see 'RateLimit').
+    pub outgoing_rate_limit: Option<RateLimit>,
+    /// If given, a client sends 'Protocol::probe_frame' after connecting & fails with 'PeerNotSpeakingProtocol',
+    /// unless the peer answers with a valid frame in time. This detects a wrong port (like an HTTP server) while connecting.
+    pub probe: Option<ProbeConfig<P>>,
+    /// If given, the bytes held by all buffers & queues of the connection (parse buffer, received messages, lanes, outgoing queue,
+    /// retransmit buffer & traces) are limited to this number, so a peer (or a slow consumer) cannot make it grow without bound.
+    /// Reading pauses while the budget is used up (until messages are taken), a received message larger than the whole budget is refused like an oversized one ('LengthTooLarge'),
+    /// frames of the read thread (like immediate responses) are dropped, and writes which would have to be queued fail with 'MemoryBudgetExceeded'.
+    /// The budget should leave room for the largest message plus a read buffer (128 bytes). The usage is reported by 'ConnectionStats::memory'.
+    pub memory_budget: Option<usize>,
+    /// If given, a message taken via 'TcpIpc::get_message_unacked' is delivered again once it was not acknowledged within this time,
+    /// even if its guard is still alive. If None, it is only delivered again once its guard is dropped.
+    pub ack_visibility_timeout: Option<std::time::Duration>,
+    /// This determines what 'TcpIpc::restart_read_thread' keeps of the previous read thread.
+    pub restart_policy: RestartPolicy,
+    /// If given, the read thread stops & the connection is closed once this many reads failed in a row (without data or an idle read in between).
+    /// Identical consecutive read errors are reported once in any case, see 'ReadThreadErrors::RepeatedReadError'.
+    /// If None, a non-fatal read error is retried forever.
+    pub read_error_limit: Option<usize>,
+    /// If given, received messages are answered via the immediate route according to this table (see 'ImmediateResponseTable').
+    /// If no rule of the table matches, 'Protocol::message_is_answered_via_immediate_route' decides.
+    pub immediate_responses: Option<Arc<ImmediateResponseTable<P>>>,
+    /// The largest header (in bytes) the protocol may declare. It is checked before a connection is set up (see 'ConnectErrors::InvalidProtocolDefinition').
+    /// If None, 'DEFAULT_MAX_HEADER_SIZE' applies.
+    pub max_header_size: Option<usize>,
+    /// This determines whether a message which was only partially received when the peer closed the connection is reported or dropped.
+    pub on_truncated_frame: TruncatedFramePolicy,
+    /// If given, a client ('TcpIpc::client') re-establishes a lost connection instead of closing it: once the peer closed it or it failed,
+    /// the read thread connects again to the addresses resolved when connecting, waiting in between as the policy determines.
+    /// The loss is reported by 'get_message' as usual, the progress by 'TcpIpc::next_event'. The new connection starts with a fresh parser,
+    /// the busy state, queued frames & the identity of the connection are kept. Banner, probe & readiness are not awaited again.
+    /// Unacknowledged reliable messages (see 'TcpIpc::write_message_reliable') are sent again ahead of the queued frames.
+    /// Meanwhile, 'write_message' queues (see 'ReconnectPolicy::queue_limit') & 'connection_state' is 'Reconnecting'.
+    pub reconnect: Option<ReconnectPolicy>,
+}
+impl<P: Protocol> Clone for TcpIpcConfig<P> {
+    fn clone(&self) -> Self {
+        Self {
+            after_connect_wait_time: self.after_connect_wait_time,
+            read_iteration_wait_time: self.read_iteration_wait_time,
+            shutdown_wait_time: self.shutdown_wait_time,
+            check_count: self.check_count,
+            control_check_interval: self.control_check_interval,
+            on_immediate_construct_failure: self.on_immediate_construct_failure.clone(),
+            nodelay: self.nodelay,
+            send_buffer_size: self.send_buffer_size,
+            recv_buffer_size: self.recv_buffer_size,
+            name: self.name.clone(),
+            thread_priority: self.thread_priority.clone(),
+            write_idle_ping: self.write_idle_ping,
+            reliability: self.reliability,
+            verify_frames: self.verify_frames,
+            per_command_stats: self.per_command_stats,
+            initial_busy_state: self.initial_busy_state,
+            strictness: self.strictness,
+            write_pressure_watermarks: self.write_pressure_watermarks.clone(),
+            error_payload_retention: self.error_payload_retention,
+            dedup_window: self.dedup_window,
+            outgoing_trace: self.outgoing_trace,
+            incoming_trace: self.incoming_trace,
+            banner_wait_time: self.banner_wait_time,
+            write_retry: self.write_retry,
+            frame_tap: self.frame_tap.clone(),
+            immediate_response_budget: self.immediate_response_budget.clone(),
+            journal: self.journal.clone(),
+            ready_when: self.ready_when,
+            ready_wait_time: self.ready_wait_time,
+            outgoing_rate_limit: self.outgoing_rate_limit,
+            probe: self.probe.clone(),
+            memory_budget: self.memory_budget,
+            ack_visibility_timeout: self.ack_visibility_timeout,
+            restart_policy: self.restart_policy,
+            read_error_limit: self.read_error_limit,
+            immediate_responses: self.immediate_responses.clone(),
+            max_header_size: self.max_header_size,
+            on_truncated_frame: self.on_truncated_frame,
+            reconnect: self.reconnect,
+        }
+    }
+}
+impl<P: Protocol> std::fmt::Debug for TcpIpcConfig<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TcpIpcConfig")
+            .field("after_connect_wait_time", &self.after_connect_wait_time)
+            .field("read_iteration_wait_time", &self.read_iteration_wait_time)
+            .field("shutdown_wait_time", &self.shutdown_wait_time)
+            .field("check_count", &self.check_count)
+            .field("control_check_interval", &self.control_check_interval)
+            .field(
+                "on_immediate_construct_failure",
+                &self.on_immediate_construct_failure,
+            )
+            .field("nodelay", &self.nodelay)
+            .field("send_buffer_size", &self.send_buffer_size)
+            .field("recv_buffer_size", &self.recv_buffer_size)
+            .field("name", &self.name)
+            .field(
+                "thread_priority",
+                &self.thread_priority.as_ref().map(|_| "<hook>"),
+            )
+            .field("write_idle_ping", &self.write_idle_ping)
+            .field("reliability", &self.reliability)
+            .field("verify_frames", &self.verify_frames)
+            .field("per_command_stats", &self.per_command_stats)
+            .field("initial_busy_state", &self.initial_busy_state)
+            .field("strictness", &self.strictness)
+            .field("write_pressure_watermarks", &self.write_pressure_watermarks)
+            .field("error_payload_retention", &self.error_payload_retention)
+            .field("dedup_window", &self.dedup_window)
+            .field("outgoing_trace", &self.outgoing_trace)
+            .field("incoming_trace", &self.incoming_trace)
+            .field("banner_wait_time", &self.banner_wait_time)
+            .field("write_retry", &self.write_retry)
+            .field("frame_tap", &self.frame_tap.as_ref().map(|_| "<tap>"))
+            .field("immediate_response_budget", &self.immediate_response_budget)
+            .field("journal", &self.journal)
+            .field("ready_when", &self.ready_when)
+            .field("ready_wait_time", &self.ready_wait_time)
+            .field("outgoing_rate_limit", &self.outgoing_rate_limit)
+            .field("probe", &self.probe)
+            .field("memory_budget", &self.memory_budget)
+            .field("ack_visibility_timeout", &self.ack_visibility_timeout)
+            .field("restart_policy", &self.restart_policy)
+            .field("read_error_limit", &self.read_error_limit)
+            .field("immediate_responses", &self.immediate_responses)
+            .field("max_header_size", &self.max_header_size)
+            .field("on_truncated_frame", &self.on_truncated_frame)
+            .field("reconnect", &self.reconnect)
+            .finish()
+    }
+}
+impl<P: Protocol> TcpIpcConfig<P> {
+    /// The interval after which the read thread handles control requests, see 'control_check_interval'.
+    pub(crate) fn effective_control_check_interval(&self) -> std::time::Duration {
+        match self.control_check_interval {
+            Some(control_check_interval) => control_check_interval,
+            None => self.read_iteration_wait_time.unwrap_or_default() * self.check_count,
+        }
+    }
+    /// Checks the sizes the protocol declares, see 'ConnectErrors::InvalidProtocolDefinition'.
+    pub(crate) fn check_protocol(&self) -> Result<(), ConnectErrors> {
+        check_definition::<P>(self.max_header_size.unwrap_or(DEFAULT_MAX_HEADER_SIZE))
+            .map_err(ConnectErrors::InvalidProtocolDefinition)
+    }
+}
+impl<P: Protocol> PartialEq for TcpIpcConfig<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.after_connect_wait_time == other.after_connect_wait_time
+            && self.read_iteration_wait_time == other.read_iteration_wait_time
+            && self.shutdown_wait_time == other.shutdown_wait_time
+            && self.check_count == other.check_count
+            && self.control_check_interval == other.control_check_interval
+            && self.on_immediate_construct_failure == other.on_immediate_construct_failure
+            && self.nodelay == other.nodelay
+            && self.send_buffer_size == other.send_buffer_size
+            && self.recv_buffer_size == other.recv_buffer_size
+            && self.name == other.name
+            && match (&self.thread_priority, &other.thread_priority) {
+                (Some(hook), Some(other_hook)) => Arc::ptr_eq(hook, other_hook),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.write_idle_ping == other.write_idle_ping
+            && self.reliability == other.reliability
+            && self.verify_frames == other.verify_frames
+            && self.per_command_stats == other.per_command_stats
+            && self.initial_busy_state == other.initial_busy_state
+            && self.strictness == other.strictness
+            && self.write_pressure_watermarks == other.write_pressure_watermarks
+            && self.error_payload_retention == other.error_payload_retention
+            && self.dedup_window == other.dedup_window
+            && self.outgoing_trace == other.outgoing_trace
+            && self.incoming_trace == other.incoming_trace
+            && self.banner_wait_time == other.banner_wait_time
+            && self.write_retry == other.write_retry
+            && match (&self.frame_tap, &other.frame_tap) {
+                (Some(tap), Some(other_tap)) => Arc::ptr_eq(tap, other_tap),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.immediate_response_budget == other.immediate_response_budget
+            && self.journal == other.journal
+            && self.ready_when == other.ready_when
+            && self.ready_wait_time == other.ready_wait_time
+            && self.outgoing_rate_limit == other.outgoing_rate_limit
+            && self.probe == other.probe
+            && self.memory_budget == other.memory_budget
+            && self.ack_visibility_timeout == other.ack_visibility_timeout
+            && self.restart_policy == other.restart_policy
+            && self.read_error_limit == other.read_error_limit
+            && match (&self.immediate_responses, &other.immediate_responses) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.max_header_size == other.max_header_size
+            && self.on_truncated_frame == other.on_truncated_frame
+            && self.reconnect == other.reconnect
+    }
+}
+/// The default config waits 100 microseconds between checks for new messages & up to one second for a shutdown.
+/// It sets TCP_NODELAY (as this crate always did), limits consecutive read errors to 100 and leaves every optional feature disabled.
+/// So a config which only differs in a few settings is written as '..TcpIpcConfig::default()'.
+impl<P: Protocol> Default for TcpIpcConfig<P> {
+    fn default() -> Self {
+        Self {
+            after_connect_wait_time: None,
+            read_iteration_wait_time: Some(std::time::Duration::from_micros(100)),
+            shutdown_wait_time: Some(std::time::Duration::from_secs(1)),
+            check_count: 1,
+            control_check_interval: None,
+            on_immediate_construct_failure: ImmediateFailurePolicy::ReportOnly,
+            nodelay: Some(true),
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            name: None,
+            thread_priority: None,
+            write_idle_ping: None,
+            reliability: None,
+            verify_frames: None,
+            per_command_stats: false,
+            initial_busy_state: None,
+            strictness: Strictness::default(),
+            write_pressure_watermarks: None,
+            error_payload_retention: DEFAULT_ERROR_PAYLOAD_RETENTION,
+            dedup_window: None,
+            outgoing_trace: None,
+            incoming_trace: None,
+            banner_wait_time: None,
+            write_retry: None,
+            frame_tap: None,
+            immediate_response_budget: None,
+            journal: None,
+            ready_when: None,
+            ready_wait_time: None,
+            outgoing_rate_limit: None,
+            probe: None,
+            memory_budget: None,
+            ack_visibility_timeout: None,
+            restart_policy: RestartPolicy::default(),
+            read_error_limit: Some(100),
+            immediate_responses: None,
+            max_header_size: None,
+            on_truncated_frame: TruncatedFramePolicy::default(),
+            reconnect: None,
+        }
+    }
 }
 
-#[derive(Debug)]
-enum ReadThreadErrorsInternal<P: Protocol> {
-    WriteError(std::io::Error),
-    ReadError(std::io::Error),
-    ImmediateMessageConstructError((P::Commands, Vec<u8>)),
+/// This determines how the read thread reacts if 'construct_message' fails for an immediate response.
+/// In any case, the failure is reported as 'ImmediateMessageConstructError'.
+pub enum ImmediateFailurePolicy<P: Protocol> {
+    /// The failure is only reported. The peer does not receive any response.
+    ReportOnly,
+    /// The given frame (for example a generic Error command) is sent instead, so the peer does not wait forever.
+    /// If this frame cannot be constructed either, the connection is closed.
+    SendFallbackFrame(P::Commands, Vec<u8>),
+    /// The connection is closed.
+    CloseConnection,
+}
+impl<P: Protocol> Clone for ImmediateFailurePolicy<P> {
+    fn clone(&self) -> Self {
+        match self {
+            ImmediateFailurePolicy::ReportOnly => ImmediateFailurePolicy::ReportOnly,
+            ImmediateFailurePolicy::SendFallbackFrame(command, message) => {
+                ImmediateFailurePolicy::SendFallbackFrame(*command, message.clone())
+            }
+            ImmediateFailurePolicy::CloseConnection => ImmediateFailurePolicy::CloseConnection,
+        }
+    }
+}
+impl<P: Protocol> std::fmt::Debug for ImmediateFailurePolicy<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImmediateFailurePolicy::ReportOnly => write!(f, "ReportOnly"),
+            ImmediateFailurePolicy::SendFallbackFrame(command, message) => f
+                .debug_tuple("SendFallbackFrame")
+                .field(command)
+                .field(message)
+                .finish(),
+            ImmediateFailurePolicy::CloseConnection => write!(f, "CloseConnection"),
+        }
+    }
+}
+impl<P: Protocol> PartialEq for ImmediateFailurePolicy<P> {
+    fn eq(&self, other: &Self) -> bool {
+        use self::ImmediateFailurePolicy::*;
+        match (self, other) {
+            (ReportOnly, ReportOnly) | (CloseConnection, CloseConnection) => true,
+            (
+                SendFallbackFrame(command, message),
+                SendFallbackFrame(other_command, other_message),
+            ) => command == other_command && message == other_message,
+            _ => false,
+        }
+    }
+}
+
+/// This determines how the read thread reacts to a protocol violation of the peer (see 'TcpIpcConfig::strictness').
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strictness {
+    /// The per-feature policies apply. A header which cannot be parsed is reported as 'ReadThreadErrors::ProtocolViolation' & discarded,
+    /// then parsing continues with the bytes following it (see 'ProtocolBuffer::try_process_new_buffer').
+    /// The connection stays open, so the application decides whether to shut it down or, for example, to request a retransmission.
+    #[default]
+    Lenient,
+    /// The connection is terminated on the first protocol violation:
+    /// the protocol's 'fault_frame' is sent (if any), a single 'ProtocolViolation' error is reported & the socket is shut down.
+    /// Afterwards, all operations fail with 'ConnectionClosed'.
+    Strict,
+}
+
+/// This determines what happens to a partially received message once the peer closed the connection (see 'TcpIpcConfig::on_truncated_frame').
+/// In any case, the message is discarded & the closing is reported as 'ReadThreadErrors::PeerClosed'.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TruncatedFramePolicy {
+    /// The partially received message is described by 'mid_frame' of 'ReadThreadErrors::PeerClosed' (& logged as warning).
+    #[default]
+    Report,
+    /// The partially received message is dropped silently, so 'mid_frame' of 'ReadThreadErrors::PeerClosed' is None.
+    /// This suits peers which are known to abort writes when they exit.
+    Drop,
+}
+
+/// This determines what a restart of the read thread keeps, see 'TcpIpc::restart_read_thread' & 'TcpIpcConfig::restart_policy'.
+/// By default, both are discarded, so the new read thread starts from a clean state.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RestartPolicy {
+    /// If true, the new read thread continues the partially received message (or header) of the previous one.
+    /// Otherwise, these bytes are discarded & parsing starts with the next bytes read from the stream.
+    pub keep_partial_frame: bool,
+    /// If true, received messages which were not yet taken stay available.
+    /// Otherwise, they are discarded (& reported as a gap by 'get_message_or_gap'). Messages routed to lanes are always kept.
+    pub keep_messages: bool,
+}
+
+/// This determines how long writing a message is retried while the stream would block (see 'TcpIpcConfig::write_retry').
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetrySpec {
+    /// The time after which writing is given up. The connection is closed then, since the peer may have received a partial frame.
+    pub max_duration: std::time::Duration,
+    /// The time slept before each retry.
+    pub backoff: std::time::Duration,
+}
+
+/// The condition for a new connection to be ready, see 'TcpIpcConfig::ready_when'.
+/// Until it holds, connecting does not return, so no message can be written before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadyCondition {
+    /// The connection is ready right after the read thread started.
+    Immediately,
+    /// The connection is ready after the given time, counted from starting the read thread.
+    AfterDuration(std::time::Duration),
+    /// The connection is ready once a message was received. The message stays queued (see 'TcpIpc::get_message').
+    AfterFirstMessage,
+    /// The connection is ready once a banner was received (see 'Protocol::is_banner'). It is kept apart from the other messages (see 'TcpIpc::banner').
+    AfterBanner,
+    /// The connection is ready once a message completing the handshake was received (see 'Protocol::is_handshake_complete'). The message stays queued.
+    AfterHandshake,
+}
+
+/// The default of 'TcpIpcConfig::error_payload_retention'.
+pub const DEFAULT_ERROR_PAYLOAD_RETENTION: usize = 1024;
+
+/// The start of a payload kept in an error, together with the length of the original payload (see 'TcpIpcConfig::error_payload_retention').
+#[derive(Clone, PartialEq)]
+pub struct RetainedPayload {
+    /// The first bytes of the payload.
+    pub bytes: Vec<u8>,
+    /// The length of the original payload.
+    pub original_length: usize,
+}
+impl RetainedPayload {
+    /// Keeps (at most) the first 'retention' bytes of the payload & releases the memory of the rest.
+    pub fn new(mut payload: Vec<u8>, retention: usize) -> Self {
+        let original_length = payload.len();
+        if original_length > retention {
+            payload.truncate(retention);
+            payload.shrink_to_fit();
+        }
+        Self {
+            bytes: payload,
+            original_length,
+        }
+    }
+    /// Checks if bytes of the payload were dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.bytes.len() < self.original_length
+    }
+}
+impl std::fmt::Debug for RetainedPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.is_truncated() {
+            write!(
+                f,
+                "{:?}.. (truncated, {} bytes)",
+                self.bytes, self.original_length
+            )
+        } else {
+            write!(f, "{:?}", self.bytes)
+        }
+    }
+}
+
+/// The state of a connection, see 'TcpIpc::connection_state'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ConnectionState {
+    /// The connection is open.
+    Open,
+    /// The peer announced to close the connection (see 'Protocol::shutdown_command'), but did not close it yet.
+    /// Messages can still arrive (and be written) in this state.
+    PeerClosing,
+    /// The peer closed the connection, i.e. the end of the stream was reached, or the peer reset the connection.
+    PeerClosed,
+    /// The connection was lost & is being re-established (see 'TcpIpcConfig::reconnect').
+    Reconnecting,
+    /// The connection was closed otherwise: it was shut down, or reading or writing failed fatally.
+    Closed,
 }
+
 #[derive(Debug)]
 /// The error type for operations in the asynchronous read thread
 pub enum ReadThreadErrors<P: Protocol> {
     /// This indicates that a immediate respond in the read-thread failed
     WriteError(std::io::Error),
-    /// This indicates that the read-thread failed to receive a message
+    /// This indicates that the read-thread failed to receive a message.
+    /// Platform-specific error codes are normalized, so the error kind is the same on all platforms (like 'ConnectionReset' for a reset by the peer).
     ReadError(std::io::Error),
+    /// The same read error occurred several times in a row, so it is reported once (see 'TcpIpcConfig::read_error_limit').
+    RepeatedReadError(RepeatedReadError),
     /// This indicates that the read-thread failed to construct a message.
     /// This typically happens if the protocol implementation has a flaw.
-    ImmediateMessageConstructError((P::Commands, Vec<u8>)),
+    /// Only the start of the payload is kept, see 'TcpIpcConfig::error_payload_retention'.
+    ImmediateMessageConstructError((P::Commands, RetainedPayload)),
+    /// This indicates that the peer violated the protocol, i.e. a header could not be parsed.
+    /// With 'Strictness::Strict', the connection was terminated. Otherwise, the header was discarded & the connection stays open (see 'Strictness::Lenient').
+    ProtocolViolation(ProtocolViolation),
+    /// The peer closed the connection (the end of the stream was reached). This is reported once, after all messages received before.
+    /// If the peer closed it in the middle of a message, 'mid_frame' describes the partially received message, which is discarded
+    /// (unless it is dropped silently, see 'TcpIpcConfig::on_truncated_frame').
+    PeerClosed {
+        /// The partially received message, if any.
+        mid_frame: Option<TruncatedFrame<P>>,
+    },
     /// This happens if the read-thread is disconnected from the server.
     Disconnected,
+    /// The connection is known to be closed (shut down, closed by the peer or failed fatally).
+    /// Messages received before are still delivered. Once they are and the read thread finished, this error is returned immediately.
+    ConnectionClosed,
+    /// An await was interrupted by 'AwaitWaker::wake' before a message was received. The connection is not affected.
+    Interrupted,
+    /// An await for any of several commands was given no command (see 'TcpIpc::await_any_command'). The connection is not affected.
+    NoCommandGiven,
+}
+/// A read error which occurred several times in a row, see 'ReadThreadErrors::RepeatedReadError'.
+/// Consecutive read errors of the same kind are collapsed, so a socket stuck in an error state does not flood the messages.
+#[derive(Debug)]
+pub struct RepeatedReadError {
+    /// The last of the collapsed errors.
+    pub error: std::io::Error,
+    /// The number of collapsed errors.
+    pub occurrences: usize,
+    /// The time of the first collapsed error.
+    pub first: std::time::Instant,
+    /// The time of the last collapsed error.
+    pub last: std::time::Instant,
+}
+impl RepeatedReadError {
+    pub(crate) fn new(error: std::io::Error, now: std::time::Instant) -> Self {
+        Self {
+            error,
+            occurrences: 1,
+            first: now,
+            last: now,
+        }
+    }
 }
 /// The error type for the connect-function.
 #[derive(Debug)]
@@ -61,8 +672,18 @@ pub enum ConnectErrors {
     /// This happens if the input socket list is not a valid address.
     /// For example, the port may be missing.
     SocketListParseError(std::io::Error),
-    /// The parsed socket list is empty
+    /// The input socket list yields no address at all (for example an empty slice).
     SocketListIsEmpty,
+    /// The host name could not be resolved, for example since the name server is not reachable.
+    /// This may be temporary, so retrying makes sense (see 'is_retryable').
+    /// Unknown host names are reported this way as well, since the standard library does not tell them apart from temporary failures.
+    ResolutionFailed(std::io::Error),
+    /// The resolver reported that the input does not resolve to any address (by an error of kind 'NotFound'),
+    /// for example a custom resolver looking up an empty set of services. Retrying does not help.
+    NoAddressesResolved {
+        /// The input, as described by the resolver's error.
+        input: String,
+    },
     /// This occurs if the server is not available during connecting.
     ConnectionError(std::io::Error),
     /// This happens if a connection was established succesfully,
@@ -70,29 +691,183 @@ pub enum ConnectErrors {
     TryCloneError(std::io::Error),
     /// This happens if a server tries to bind a socket address and fails.
     BindError(std::io::Error),
-    /// Internally the tcp-stream is set to NoDelay (as default).
+    /// The tcp-stream is set to NoDelay as configured (see 'TcpIpcConfig::nodelay').
     /// This error indicates that this operation failed.
     SetNodelayError(std::io::Error),
-    /// Internally the tcp-stream receive buffer size is set to header length.
+    /// The tcp-stream receive buffer size is set as configured (see 'TcpIpcConfig::recv_buffer_size').
     /// This error indicates that this operation failed.
     SetReceiveBufferSizeError(std::io::Error),
-    /// Internally the tcp-stream send buffer size is set to header length.
+    /// The tcp-stream send buffer size is set as configured (see 'TcpIpcConfig::send_buffer_size').
     /// This error indicates that this operation failed.
     SetSendBufferSizeError(std::io::Error),
     /// This error indicates that the given wait time was exceeded
     WaitTimeExceeded,
+    /// This happens if the read thread could not be spawned.
+    ThreadSpawnError(std::io::Error),
+    /// This happens if a connection is added to a 'ConnectionGroup' whose thread is not running anymore (because it panicked).
+    GroupThreadStopped,
+    /// The peer did not answer the probe with a valid frame in time (see 'TcpIpcConfig::probe'), so it likely speaks another protocol.
+    /// The connection was closed.
+    PeerNotSpeakingProtocol {
+        /// The first bytes received (at most 'PROBE_CAPTURE_LENGTH'), with all bytes except printable ASCII replaced by '.'.
+        /// This is empty if the peer sent nothing.
+        received: String,
+        /// The reason, like a timeout, a protocol violation or the peer closing the connection (debug formatted).
+        reason: String,
+    },
+    /// The sizes the protocol declares are unusable, like an empty header or one larger than 'TcpIpcConfig::max_header_size'.
+    /// This is checked before any socket is touched. The string describes the flaw.
+    InvalidProtocolDefinition(String),
+}
+impl ConnectErrors {
+    /// Checks if connecting again may succeed, since the error can be temporary:
+    /// a failed resolution, a refused or failed connection, or an exceeded wait time.
+    /// Errors in the input (like 'NoAddressesResolved') or in configuring the connection are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ConnectErrors::ResolutionFailed(_)
+            | ConnectErrors::ConnectionError(_)
+            | ConnectErrors::WaitTimeExceeded => true,
+            ConnectErrors::SocketListParseError(_)
+            | ConnectErrors::SocketListIsEmpty
+            | ConnectErrors::NoAddressesResolved { .. }
+            | ConnectErrors::TryCloneError(_)
+            | ConnectErrors::BindError(_)
+            | ConnectErrors::SetNodelayError(_)
+            | ConnectErrors::SetReceiveBufferSizeError(_)
+            | ConnectErrors::SetSendBufferSizeError(_)
+            | ConnectErrors::ThreadSpawnError(_)
+            | ConnectErrors::GroupThreadStopped
+            | ConnectErrors::PeerNotSpeakingProtocol { .. }
+            | ConnectErrors::InvalidProtocolDefinition(_) => false,
+        }
+    }
+}
+/// Sets the options of a new stream: no_delay (if configured) & the buffer sizes.
+pub(crate) fn configure_stream<P: Protocol>(
+    tcp_stream: &TcpStream,
+    config: &TcpIpcConfig<P>,
+) -> Result<(), ConnectErrors> {
+    if let Some(nodelay) = config.nodelay {
+        tcp_stream
+            .set_nodelay(nodelay)
+            .map_err(self::ConnectErrors::SetNodelayError)?;
+    }
+    if let Some(size) = config.send_buffer_size {
+        engine::set_send_buffer_size(tcp_stream, size)
+            .map_err(self::ConnectErrors::SetSendBufferSizeError)?;
+    }
+    if let Some(size) = config.recv_buffer_size {
+        engine::set_recv_buffer_size(tcp_stream, size)
+            .map_err(self::ConnectErrors::SetReceiveBufferSizeError)?;
+    }
+    Ok(())
+}
+/// Resolves the input socket list, distinguishing an invalid input, a failed resolution & a resolution to no address.
+pub(crate) fn resolve<T: ToSocketAddrs>(
+    socket_addresses: T,
+) -> Result<std::iter::Peekable<T::Iter>, ConnectErrors> {
+    let mut resolved = match socket_addresses.to_socket_addrs() {
+        Ok(resolved) => resolved.peekable(),
+        // the standard library reports malformed input (like a missing port) as invalid input, before any lookup
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => {
+            return Err(ConnectErrors::SocketListParseError(err))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ConnectErrors::NoAddressesResolved {
+                input: err.to_string(),
+            })
+        }
+        Err(err) => return Err(ConnectErrors::ResolutionFailed(err)),
+    };
+    if resolved.peek().is_none() {
+        return Err(ConnectErrors::SocketListIsEmpty);
+    }
+    Ok(resolved)
+}
+/// Runs the read thread on a thread of its own, named after the connection.
+fn spawn_read_thread<P: Protocol>(
+    config: &TcpIpcConfig<P>,
+    read_thread: ReadThread<P>,
+) -> std::io::Result<std::thread::JoinHandle<()>> {
+    let thread_name = match &config.name {
+        Some(name) => format!("tcp-ipc/{}/read", name),
+        None => "tcp-ipc/read".to_string(),
+    };
+    let thread_priority = config.thread_priority.clone();
+    std::thread::Builder::new()
+        .name(thread_name)
+        .spawn(move || {
+            if let Some(thread_priority) = thread_priority {
+                thread_priority();
+            }
+            read_thread.run()
+        })
 }
 /// This is the main type of the library.
 /// Here all the logic is bundle.
 /// It can be used to easily send and receive messages via TCP, allowing for many different protcols to be used.
 pub struct TcpIpc<P: Protocol> {
     busy_state_sender: std::sync::mpsc::Sender<P::BusyStates>,
-    message_receiver: std::sync::mpsc::Receiver<Result<Message<P>, ReadThreadErrorsInternal<P>>>,
+    message_receiver: std::sync::mpsc::Receiver<Incoming<P>>,
+    incoming: VecDeque<Incoming<P>>,
     stream: TcpStream,
     shutdown_sender: std::sync::mpsc::Sender<()>,
-    shutdown_wait_time: Option<std::time::Duration>,
+    shutdown_ack_receiver: std::sync::mpsc::Receiver<usize>,
+    config: TcpIpcConfig<P>,
+    pending_outgoing: Arc<AtomicUsize>,
+    outgoing: SharedOutgoingQueue,
     busy_state_query_sender: std::sync::mpsc::Sender<()>,
     busy_state_queried_receiver: std::sync::mpsc::Receiver<P::BusyStates>,
+    parser_state_query_sender: std::sync::mpsc::Sender<()>,
+    parser_state_queried_receiver: std::sync::mpsc::Receiver<ParserState<P>>,
+    connection_closed: Arc<AtomicBool>,
+    peer_shutdown: Arc<PeerShutdown>,
+    busy_state_timeline: BusyStateTimeline<P>,
+    registration: Registration,
+    stats: Arc<StatsCounters>,
+    command_stats: Option<SharedCommandStats<P>>,
+    outgoing_trace: Option<SharedTrace<TraceEntry<P>>>,
+    incoming_trace: Option<SharedTrace<IncomingTraceEntry<P>>>,
+    banner: Option<Message<P>>,
+    peer_info: SharedPeerInfo,
+    lanes: SharedLaneRouter<P>,
+    last_error: Option<String>,
+    delivery: DeliveryReport,
+    retransmit_buffer: SharedRetransmitBuffer<P>,
+    schedule_sender: std::sync::mpsc::Sender<ScheduledSend<P>>,
+    restart_sender: std::sync::mpsc::Sender<std::sync::mpsc::Sender<RestartHandover<P>>>,
+    // opened by the first journaled write
+    journal: Option<Journal>,
+    // journaled frames which wait behind a partially written frame, so they are not yet completed
+    journal_in_flight: Vec<JournalId>,
+    // None if the outgoing frames are not rate-limited
+    rate_limiter: Option<SharedTokenBucket>,
+    // the first bytes received, only captured if a probe is configured
+    first_bytes: Option<SharedFirstBytes>,
+    // an error which was received by 'drain_messages' after some messages, to be returned by the next call
+    deferred_error: Option<ReadThreadErrors<P>>,
+    // the sequence number of the next message to be delivered
+    expected_sequence: u64,
+    // the sequence number of the next message to be received from the read thread
+    received_sequence: u64,
+    // messages which were delivered out of order (by transactions), so their sequence numbers are no gaps
+    delivered_out_of_order: BTreeSet<u64>,
+    // the sequence numbers of the queued high-priority messages (see 'Protocol::is_high_priority'), in order
+    // entries of messages which were taken otherwise (like by a transaction) are skipped
+    high_priority: VecDeque<u64>,
+    // messages taken via 'get_message_unacked', which are not yet acknowledged
+    in_flight: SharedInFlight<P>,
+    // interrupts the awaits, see 'waker'
+    wake_signal: SharedWakeSignal,
+    // set for a client which re-establishes a lost connection (see 'TcpIpcConfig::reconnect')
+    reconnect: Option<SharedReconnectState>,
+    // set for a client whose protocol has sessions (see 'Protocol::session_command')
+    session: Option<SharedSessionToken>,
+    // the thread running the read thread, if it has one of its own (unlike a connection of a 'ConnectionGroup')
+    read_thread_handle: Option<std::thread::JoinHandle<()>>,
+    // the address of the peer, kept so it is known once the connection is closed (None while a connect is still in progress)
+    peer_address: Option<SocketAddr>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -102,12 +877,16 @@ pub enum BusyStateUpdateResult {
     Success,
     /// The only posibility for fail is that the connection is already (disgracefully) closed.
     Disconnected,
+    /// The connection is known to be closed, so the update was not attempted.
+    ConnectionClosed,
 }
 #[derive(Debug, Clone, Copy, PartialEq)]
 /// The error type for a BusyState query
 pub enum BusyStateQueryResult {
     /// The only posibility for fail is that the connection is already (disgracefully) closed.
     Disconnected,
+    /// The connection is known to be closed, so the query was not attempted.
+    ConnectionClosed,
 }
 #[derive(Debug)]
 /// The error type for a message writing
@@ -116,40 +895,544 @@ pub enum WriteMessageErrors {
     MessageConstructionFailed,
     /// Failed to send message.
     /// This indicates typically a run-time problem.
+    /// Since a partially written message corrupts the stream, the connection is closed afterwards.
     MessageSendFailed(std::io::Error),
+    /// The connection is known to be closed, so nothing was sent.
+    ConnectionClosed,
+    /// A reliable message was requested, but the reliability layer is not enabled or the command is not reliable.
+    ReliabilityUnavailable,
+    /// A reliable message was requested, but the maximal number of unacknowledged messages is reached.
+    RetransmitBufferFull,
+    /// The constructed frame does not parse back into its command & payload length, so it was not sent (see 'TcpIpcConfig::verify_frames').
+    /// This indicates that the protocol implementation has a flaw.
+    ProtocolInconsistency {
+        /// The payload length declared in the header. This is None if the header could not be parsed back into the command.
+        declared: Option<usize>,
+        /// The actual payload length.
+        actual: usize,
+    },
+    /// A journaled message was requested, but no journal is configured (see 'TcpIpcConfig::journal').
+    JournalUnavailable,
+    /// The journal could not be opened or written, so the message was not sent.
+    JournalFailed(std::io::Error),
+    /// The outgoing rate limit is reached, so nothing was sent (see 'TcpIpcConfig::outgoing_rate_limit').
+    RateLimited {
+        /// The time until the frame could be sent.
+        retry_after: std::time::Duration,
+    },
+    /// The frame would have to be queued (or kept for retransmission), but the memory budget is used up, so nothing was sent (see 'TcpIpcConfig::memory_budget').
+    /// The connection stays usable, the frame can be written again once queued frames are written.
+    MemoryBudgetExceeded,
+    /// The connection is lost & being re-established, and the frame does not fit into 'ReconnectPolicy::queue_limit', so nothing was sent.
+    NotConnected,
+}
+/// The error type for writing several messages at once, see 'TcpIpc::write_messages'.
+#[derive(Debug)]
+pub enum BatchWriteErrors {
+    /// The message at the given index could not be constructed (or verified), so nothing was sent.
+    ConstructionFailed {
+        /// The index of the first message which could not be constructed.
+        index: usize,
+        /// The reason, see 'write_message'.
+        error: WriteMessageErrors,
+    },
+    /// Writing failed after the given number of complete messages was written.
+    /// Since a partially written message corrupts the stream, the connection is closed afterwards.
+    WriteFailed {
+        /// The number of messages which were written completely.
+        written: usize,
+        /// The error of the failed write.
+        error: std::io::Error,
+    },
+    /// The connection is known to be closed, so nothing was sent.
+    ConnectionClosed,
+    /// The outgoing rate limit does not allow all messages at once, so nothing was sent (see 'TcpIpcConfig::outgoing_rate_limit').
+    /// A batch larger than the burst is always rejected with 'RateLimitPolicy::Reject'.
+    RateLimited {
+        /// The time until the messages could be sent.
+        retry_after: std::time::Duration,
+    },
+    /// The stream would block & the unwritten messages would have to be queued, but the memory budget does not allow it (see 'TcpIpcConfig::memory_budget').
+    /// The connection stays usable, the unwritten messages can be written again once queued frames are written.
+    MemoryBudgetExceeded {
+        /// The number of messages which were written completely, the others were not sent at all.
+        written: usize,
+    },
+    /// The connection is lost & being re-established, and the messages do not fit into 'ReconnectPolicy::queue_limit', so nothing was sent.
+    NotConnected,
+}
+/// The priority of an outgoing message, see 'TcpIpc::write_message_with_priority'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// For urgent messages, like an emergency stop.
+    High,
+    /// The priority of messages written via 'write_message' & of immediate responses.
+    #[default]
+    Normal,
+    /// For bulk messages, like telemetry.
+    Low,
+}
+fn describe_read_thread_error<P: Protocol>(error: &ReadThreadErrorsInternal<P>) -> String {
+    match error {
+        ReadThreadErrorsInternal::WriteError(x) => format!("WriteError({:?})", x),
+        ReadThreadErrorsInternal::ReadError(x) => format!("ReadError({:?})", x),
+        ReadThreadErrorsInternal::RepeatedReadError(x) => {
+            format!("RepeatedReadError({:?}, {} times)", x.error, x.occurrences)
+        }
+        ReadThreadErrorsInternal::ImmediateMessageConstructError((command, message)) => format!(
+            "ImmediateMessageConstructError(({:?}, {} bytes))",
+            command, message.original_length
+        ),
+        ReadThreadErrorsInternal::ProtocolViolation(x) => {
+            format!("ProtocolViolation({:?}, header {:?})", x.error, x.header)
+        }
+        ReadThreadErrorsInternal::PeerClosed(mid_frame) => format!("PeerClosed({:?})", mid_frame),
+    }
+}
+/// Checks that the start of a constructed frame parses back into the given command & payload length.
+/// If the frame is only the header, it has to be consumed completely by the header.
+fn verify_frame<P: Protocol>(
+    command: P::Commands,
+    frame: &[u8],
+    header_only: bool,
+    payload_length: usize,
+) -> Result<(), WriteMessageErrors> {
+    let declared = P::message_slice_to_header_array(frame)
+        .filter(|(_, rest)| !header_only || rest.is_empty())
+        .and_then(|(header, _)| P::parse_header(header).ok())
+        .and_then(|(parsed_command, declared)| {
+            if parsed_command == command {
+                Some(declared)
+            } else {
+                None
+            }
+        });
+    if declared == Some(payload_length) {
+        Ok(())
+    } else {
+        Err(WriteMessageErrors::ProtocolInconsistency {
+            declared,
+            actual: payload_length,
+        })
+    }
+}
+// the unwritten part of a frame, given the number of its bytes already written
+fn unwritten<'a>(header: &'a [u8], payload: &'a [u8], written: usize) -> [&'a [u8]; 2] {
+    if written < header.len() {
+        [&header[written..], payload]
+    } else {
+        [&[], &payload[written - header.len()..]]
+    }
+}
+/// The time 'write_message_within' sleeps before each retry, unless 'TcpIpcConfig::write_retry' gives another backoff.
+const DEFAULT_WRITE_BACKOFF: std::time::Duration = std::time::Duration::from_micros(50);
+/// The longest time 'await_message' blocks on the channel of the read thread, before checking for a wake (see 'TcpIpc::waker').
+const WAKE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+/// The maximal number of frames passed to a single vectored write.
+const MAX_FRAMES_PER_WRITE: usize = 64;
+/// Constructs the frame of a message as header & payload, verifying it if requested (see 'TcpIpcConfig::verify_frames').
+/// If the protocol's payload does not follow the header, the header is the whole frame & the payload is empty.
+fn construct_frame<P: Protocol>(
+    command: P::Commands,
+    message_: &[u8],
+    verify: bool,
+) -> Result<(Vec<u8>, &[u8]), WriteMessageErrors> {
+    let (header, payload) = if P::payload_follows_header() {
+        (P::construct_message_header(command, message_), message_)
+    } else {
+        (P::construct_message(command, message_), &[] as &[u8])
+    };
+    let header = header.ok_or(WriteMessageErrors::MessageConstructionFailed)?;
+    if verify {
+        verify_frame::<P>(
+            command,
+            &header,
+            P::payload_follows_header(),
+            message_.len(),
+        )?;
+    }
+    Ok((header, payload))
+}
+/// The ways writing constructed frames fails, see 'TcpIpc::write_constructed'.
+enum WriteFailure {
+    /// Writing failed, so the connection is closed (unless it is re-established).
+    SendFailed(std::io::Error),
+    /// The frames would have to be queued, but the memory budget does not allow it.
+    MemoryBudgetExceeded,
+    /// The connection is being re-established & the frames do not fit into the reconnect queue.
+    NotConnected,
+}
+impl From<WriteFailure> for WriteMessageErrors {
+    fn from(failure: WriteFailure) -> Self {
+        match failure {
+            WriteFailure::SendFailed(err) => WriteMessageErrors::MessageSendFailed(err),
+            WriteFailure::MemoryBudgetExceeded => WriteMessageErrors::MemoryBudgetExceeded,
+            WriteFailure::NotConnected => WriteMessageErrors::NotConnected,
+        }
+    }
+}
+/// Writes several frames (each given as header & payload) in order, using vectored writes so the payloads are not copied.
+/// If the stream would block, writing is retried as given by the retry spec. Bytes already written are never written again.
+/// Without a retry spec, the unwritten rest is queued with the given priority & written by the read thread, so the queue has to be empty.
+/// On failure, the number of completely written frames is returned together with the reason.
+fn write_frames<W: Write>(
+    writer: &mut W,
+    frames: &[(Vec<u8>, &[u8])],
+    outgoing: &mut OutgoingQueue,
+    retry: Option<RetrySpec>,
+    priority: Priority,
+) -> Result<(), (usize, WriteFailure)> {
+    let start = std::time::Instant::now();
+    let mut index = 0;
+    let mut written = 0;
+    while index < frames.len() {
+        let mut slices = Vec::with_capacity(2 * MAX_FRAMES_PER_WRITE);
+        for (position, (header, payload)) in frames[index..]
+            .iter()
+            .take(MAX_FRAMES_PER_WRITE)
+            .enumerate()
+        {
+            let start = if position == 0 { written } else { 0 };
+            for part in &unwritten(header, payload, start) {
+                if !part.is_empty() {
+                    slices.push(std::io::IoSlice::new(part));
+                }
+            }
+        }
+        match writer.write_vectored(&slices) {
+            Ok(0) if !slices.is_empty() => {
+                return Err((
+                    index,
+                    WriteFailure::SendFailed(std::io::ErrorKind::WriteZero.into()),
+                ));
+            }
+            Ok(mut n) => {
+                // advance over the completely written frames
+                while index < frames.len() {
+                    let (header, payload) = &frames[index];
+                    let remaining = header.len() + payload.len() - written;
+                    if n < remaining {
+                        written += n;
+                        break;
+                    }
+                    n -= remaining;
+                    index += 1;
+                    written = 0;
+                }
+            }
+            Err(ref err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => match retry {
+                Some(retry) if start.elapsed() < retry.max_duration => {
+                    std::thread::sleep(retry.backoff)
+                }
+                Some(_) => {
+                    return Err((
+                        index,
+                        WriteFailure::SendFailed(std::io::ErrorKind::WouldBlock.into()),
+                    ))
+                }
+                // the rest is written by the read thread, in order
+                None => {
+                    return queue_unwritten(&frames[index..], written, outgoing, priority)
+                        .map_err(|failure| (index, failure))
+                }
+            },
+            Err(err) => return Err((index, WriteFailure::SendFailed(err))),
+        }
+    }
+    Ok(())
+}
+/// Queues the unwritten rest of the frames (each given as header & payload), of which the given number of bytes of the first one was written.
+/// The frames are queued as a whole, or not at all if the memory budget does not allow it.
+fn queue_unwritten(
+    frames: &[(Vec<u8>, &[u8])],
+    written: usize,
+    outgoing: &mut OutgoingQueue,
+    priority: Priority,
+) -> Result<(), WriteFailure> {
+    let length: usize = frames
+        .iter()
+        .map(|(header, payload)| header.len() + payload.len())
+        .sum();
+    if !outgoing.reserve(length - written) {
+        return Err(match written {
+            0 => WriteFailure::MemoryBudgetExceeded,
+            // the peer received a partial frame, which cannot be completed
+            _ => WriteFailure::SendFailed(std::io::ErrorKind::WouldBlock.into()),
+        });
+    }
+    for (position, (header, payload)) in frames.iter().enumerate() {
+        if position == 0 && written > 0 {
+            outgoing.push_reserved_tail(unwritten(header, payload, written).concat());
+        } else {
+            outgoing.push_reserved([&header[..], payload].concat(), priority);
+        }
+    }
+    Ok(())
+}
+/// This shows which connection this is & its state, but no payloads.
+/// Messages still in transit from the read thread are not counted as received.
+impl<P: Protocol> std::fmt::Debug for TcpIpc<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TcpIpc")
+            .field("name", &self.config.name)
+            .field("id", &self.id())
+            .field("peer_addr", &self.peer_addr().ok())
+            .field("local_addr", &self.stream.local_addr().ok())
+            .field("connection_closed", &self.is_connection_closed())
+            .field("peer_info", &self.peer_info())
+            .field("received_messages", &self.incoming.len())
+            .field(
+                "pending_outgoing",
+                &self.pending_outgoing.load(Ordering::SeqCst),
+            )
+            .finish()
+    }
 }
 impl<P: Protocol> TcpIpc<P> {
     /// This connects a client to a server, allowing to send and receive commands.
     /// The input variable 'connect_wait_time' is the time the client waits for the Server to accept a TCP-connection.
     /// A 'None' value yields an infinite waiting period.
+    /// This returns only once the connection is ready (see 'TcpIpcConfig::ready_when', by default after 'after_connect_wait_time' passed),
+    /// so no message can be written before (all operations need the returned value).
+    /// A banner sent by the server meanwhile is kept apart from the other messages (see 'banner').
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
     /// let config = TcpIpcConfig {
-    ///     connect_wait_time_ms: 5_000,
-    ///     read_iteration_wait_time_ns: 1_000,
-    ///     shutdown_wait_time_in_ns: 1_000_000,
+    ///     read_iteration_wait_time: Some(std::time::Duration::from_micros(1)),
+    ///     shutdown_wait_time: Some(std::time::Duration::from_millis(1)),
+    ///     ..TcpIpcConfig::default()
     /// };
-    /// let mut client =
-    ///     TcpIpc::<ProtocolExample>::client("127.0.0.1:6666", config, None).expect("connecting failed");
+    /// let connect_wait_time = Some(std::time::Duration::from_secs(5));
+    /// let mut client = TcpIpc::<ProtocolExample>::client("127.0.0.1:6666", config, connect_wait_time)
+    ///     .expect("connecting failed");
     /// ```
     pub fn client<T: ToSocketAddrs>(
         socket_addresses: T,
-        config: TcpIpcConfig,
+        config: TcpIpcConfig<P>,
         connect_wait_time: Option<std::time::Duration>,
     ) -> Result<TcpIpc<P>, ConnectErrors> {
-        // connect
+        config.check_protocol()?;
+        // the addresses are kept, so a lost connection can be re-established
+        let socket_addresses: Vec<_> = resolve(socket_addresses)?.collect();
+        let client = Self::connect(&socket_addresses[..], connect_wait_time)?;
+        let (mut client, mut read_thread) = Self::prepare_connection(client, config)?;
+        client.reconnect = client
+            .config
+            .reconnect
+            .map(|policy| Arc::new(ReconnectState::new(policy, socket_addresses)));
+        read_thread.enable_reconnect(client.reconnect.clone());
+        client.session = P::session_command().map(|_| new_session_token());
+        read_thread.enable_session(client.session.clone());
+        let started = std::time::Instant::now();
+        client.read_thread_handle = Some(
+            spawn_read_thread(&client.config, read_thread)
+                .map_err(ConnectErrors::ThreadSpawnError)?,
+        );
+        client.settle(started)?;
+        if client.banner.is_none() {
+            client.capture_banner(started);
+        }
+        client.request_session();
+        if let Some(probe) = client.config.probe.clone() {
+            client.probe(&probe)?;
+        }
+        Ok(client)
+    }
+    // asks the server for a new session, see 'Protocol::session_command'
+    // the answer is taken by the read thread, so this does not wait for it
+    fn request_session(&mut self) {
+        let command = match (&self.session, P::session_command()) {
+            (Some(_), Some(command)) => command,
+            _ => return,
+        };
+        if let Err(err) =
+            self.write_message_unlimited(command, &[], self.config.write_retry, Priority::Normal)
+        {
+            warn!("{}: Session could not be requested: {:?}", self.id(), err);
+        }
+    }
+    /// Sends the probe frame & waits for the answer, see 'TcpIpcConfig::probe'.
+    /// If the peer does not answer with a valid frame in time, the read thread is stopped & 'PeerNotSpeakingProtocol' is returned.
+    fn probe(&mut self, probe: &ProbeConfig<P>) -> Result<(), ConnectErrors> {
+        let (command, payload) = match P::probe_frame() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        let started = std::time::Instant::now();
+        let result = match self.write_message_unlimited(
+            command,
+            &payload,
+            self.config.write_retry,
+            Priority::Normal,
+        ) {
+            Ok(()) => loop {
+                let matched = self.take_first_matching(|_, received| {
+                    probe.response.is_some_and(|response| *received == response)
+                });
+                // without a specific response, any valid frame proves the protocol (it stays queued)
+                let any_frame = probe.response.is_none()
+                    && (self.banner.is_some() || self.incoming.iter().any(Result::is_ok));
+                match matched {
+                    Ok(Some(_)) => break Ok(()),
+                    Ok(None) if any_frame => break Ok(()),
+                    Err(err) if any_frame => {
+                        self.defer_error(err);
+                        break Ok(());
+                    }
+                    // the error was recorded, unless the read thread finished without one
+                    Err(_) => {
+                        break Err(self
+                            .last_error
+                            .clone()
+                            .unwrap_or_else(|| "the read thread finished".to_string()))
+                    }
+                    Ok(None) => {}
+                }
+                if started.elapsed() >= probe.timeout {
+                    break Err(format!("no answer within {:?}", probe.timeout));
+                }
+                if let Some(iteration_wait_time) = self.config.read_iteration_wait_time {
+                    std::thread::sleep(iteration_wait_time);
+                }
+            },
+            Err(err) => Err(format!("probe frame not sent: {:?}", err)),
+        };
+        let reason = match result {
+            Ok(()) => {
+                debug!(
+                    "{}: Probe answered after {:?}",
+                    self.id(),
+                    started.elapsed()
+                );
+                self.first_bytes = None;
+                return Ok(());
+            }
+            Err(reason) => reason,
+        };
+        let received = self.first_bytes.as_ref().map(preview).unwrap_or_default();
+        warn!(
+            "{}: Peer does not speak the protocol ({}), received {:?}. Connection will be closed.",
+            self.id(),
+            reason,
+            received
+        );
+        self.connection_closed.store(true, Ordering::SeqCst);
+        self.stop_read_thread();
+        Err(ConnectErrors::PeerNotSpeakingProtocol { received, reason })
+    }
+    /// Takes the first banner received so far out of the queue, waiting up to 'banner_wait_time' (counted from the given start) for it.
+    fn capture_banner(&mut self, started: std::time::Instant) {
+        while self.try_capture_banner() {
+            match self.config.banner_wait_time {
+                Some(banner_wait_time) if started.elapsed() < banner_wait_time => {}
+                _ => return,
+            }
+            if let Some(iteration_wait_time) = self.config.read_iteration_wait_time {
+                std::thread::sleep(iteration_wait_time);
+            }
+        }
+    }
+    /// Takes the first banner received so far out of the queue.
+    /// Returns false if waiting for a banner is pointless, since it was captured or an error occurred.
+    fn try_capture_banner(&mut self) -> bool {
+        // only frames which arrived by now are candidates, a banner parsed later is a normal message
+        let received = self.synchronize_with_read_thread();
+        // an error ends the wait, but stays queued behind the messages received before it (like the closing of a peer which sent no banner)
+        let error_first = self.incoming.iter().find_map(|queued| match queued {
+            Ok((sequence, (command, _))) if *sequence < received && P::is_banner(command) => {
+                Some(false)
+            }
+            Ok(_) => None,
+            Err(_) => Some(true),
+        });
+        if error_first == Some(true) {
+            return false;
+        }
+        match self
+            .take_first_matching(|sequence, command| sequence < received && P::is_banner(command))
+        {
+            Ok(Some(banner)) => {
+                debug!("{}: Banner received: {:?}", self.id(), banner.0);
+                self.banner = Some(banner);
+                false
+            }
+            Ok(None) => true,
+            Err(err) => {
+                self.defer_error(err);
+                false
+            }
+        }
+    }
+    /// Waits until the connection is ready (see 'TcpIpcConfig::ready_when'), counted from the given start.
+    /// If the connection fails before, waiting stops, so the error is returned by the next call of 'get_message'.
+    pub(crate) fn settle(&mut self, started: std::time::Instant) -> Result<(), ConnectErrors> {
+        let condition = match self.config.ready_when {
+            None => {
+                if let Some(after_connect_wait_time) = self.config.after_connect_wait_time {
+                    std::thread::sleep(after_connect_wait_time);
+                }
+                return Ok(());
+            }
+            Some(ReadyCondition::Immediately) => return Ok(()),
+            Some(ReadyCondition::AfterDuration(duration)) => {
+                std::thread::sleep(duration.saturating_sub(started.elapsed()));
+                return Ok(());
+            }
+            Some(condition) => condition,
+        };
+        loop {
+            let ready = match condition {
+                ReadyCondition::AfterBanner => !self.try_capture_banner(),
+                _ => {
+                    self.synchronize_with_read_thread();
+                    self.deferred_error.is_some()
+                        || self.incoming.iter().any(|received| match received {
+                            Ok((_, (command, _))) => {
+                                condition == ReadyCondition::AfterFirstMessage
+                                    || P::is_handshake_complete(command)
+                            }
+                            Err(_) => true,
+                        })
+                }
+            };
+            if ready || self.is_connection_closed() {
+                debug!("{}: Ready after {:?}", self.id(), started.elapsed());
+                return Ok(());
+            }
+            if let Some(ready_wait_time) = self.config.ready_wait_time {
+                if started.elapsed() >= ready_wait_time {
+                    info!(
+                        "{}: Not ready after {:?}, connection will be closed",
+                        self.id(),
+                        ready_wait_time
+                    );
+                    self.stop_read_thread();
+                    return Err(ConnectErrors::WaitTimeExceeded);
+                }
+            }
+            if let Some(iteration_wait_time) = self.config.read_iteration_wait_time {
+                std::thread::sleep(iteration_wait_time);
+            }
+        }
+    }
+    /// Connects to a server, see 'client'.
+    pub(crate) fn connect<T: ToSocketAddrs>(
+        socket_addresses: T,
+        connect_wait_time: Option<std::time::Duration>,
+    ) -> Result<TcpStream, ConnectErrors> {
         let client = {
             let mut error = self::ConnectErrors::SocketListIsEmpty;
-            let mut socket_addresses = socket_addresses
-                .to_socket_addrs()
-                .map_err(ConnectErrors::SocketListParseError)?;
+            let mut socket_addresses = resolve(socket_addresses)?;
             loop {
                 if let Some(socket_address) = socket_addresses.next() {
                     debug!("trying to connect to {:?}", socket_address);
                     match if let Some(connect_wait_time) = connect_wait_time {
                         let now = std::time::Instant::now();
                         loop {
-                            match TcpStream::connect(&socket_address) {
+                            match engine::connect(&socket_address) {
                                 Ok(stream) => break Ok(stream),
                                 Err(error) => match error.kind() {
                                     std::io::ErrorKind::WouldBlock => {}
@@ -161,7 +1444,7 @@ impl<P: Protocol> TcpIpc<P> {
                             }
                         }
                     } else {
-                        TcpStream::connect(&socket_address)
+                        engine::connect(&socket_address)
                     } {
                         Ok(stream) => {
                             info!("connected to {:?}", socket_address);
@@ -177,219 +1460,218 @@ impl<P: Protocol> TcpIpc<P> {
                 }
             }
         };
-        Self::start_read_thread(client, config)
+        Ok(client)
     }
     /// This sets up a server waiting for a client to connect to it.
     /// Afterwards it can be used to send and receive commands.
+    /// This returns only once the connection is ready (see 'TcpIpcConfig::ready_when', by default after 'after_connect_wait_time' passed),
+    /// so no message can be written before (all operations need the returned value).
+    ///
+    /// This is 'listen' followed by 'TcpIpcListener::accept'. To learn the bound address before a client connects
+    /// (like the port chosen for port 0), use these two instead.
     /// # Example
-    /// ```
-    /// let config = TcpIpcConfig {
-    ///     read_iteration_wait_time_ns: 1_000,
-    ///     shutdown_wait_time_in_ns: 1_000_000,
-    /// };
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let config = config();
     /// let mut server =
     ///     TcpIpc::<ProtocolExample>::server("127.0.0.1:6666", config).expect("connecting failed");
     /// ```
     pub fn server<T: ToSocketAddrs>(
         socket_addresses: T,
-        config: TcpIpcConfig,
+        config: TcpIpcConfig<P>,
     ) -> Result<TcpIpc<P>, ConnectErrors> {
-        // connect
-        let server = {
-            let mut error = self::ConnectErrors::SocketListIsEmpty;
-            let mut socket_addresses = socket_addresses
-                .to_socket_addrs()
-                .map_err(ConnectErrors::SocketListParseError)?;
-            loop {
-                if let Some(socket_address) = socket_addresses.next() {
-                    debug!("trying to connect to {:?}", socket_address);
-                    let listener =
-                        TcpListener::bind(&socket_address).map_err(ConnectErrors::BindError)?;
-                    match loop {
-                        match listener.accept() {
-                            Ok(stream) => break Ok(stream),
-                            Err(error) => match error.kind() {
-                                std::io::ErrorKind::WouldBlock => continue,
-                                _ => break Err(error),
-                            },
-                        }
-                    } {
-                        Ok((stream, socket_address)) => {
-                            info!("connected to {:?}", socket_address);
-                            break stream;
-                        }
-                        Err(err) => {
-                            info!("Received error: {:?}", err);
-                            error = ConnectErrors::ConnectionError(err);
-                        }
-                    }
-                } else {
-                    return Err(error);
-                }
-            }
-        };
-        Self::start_read_thread(server, config)
+        config.check_protocol()?;
+        Self::listen(socket_addresses)?.accept(config)
+    }
+    /// This binds a listener to the first of the given addresses which can be bound, without waiting for a client yet.
+    /// The bound address is known right away (see 'TcpIpcListener::local_addr'), so a server can bind to port 0 & tell its clients the port,
+    /// and a test can bind before it spawns the client. The client is then accepted via 'TcpIpcListener::accept'.
+    /// # Example
+    /// ```
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let config = config();
+    /// let listener = TcpIpc::<ProtocolExample>::listen("127.0.0.1:0")?;
+    /// let address = listener.local_addr()?;
+    /// let client_config = config.clone();
+    /// let client = std::thread::spawn(move || {
+    ///     TcpIpc::<ProtocolExample>::client(address, client_config, None)
+    /// });
+    /// let mut server = listener.accept(config)?;
+    /// let mut client = client.join().unwrap()?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn listen<T: ToSocketAddrs>(
+        socket_addresses: T,
+    ) -> Result<TcpIpcListener<P>, ConnectErrors> {
+        TcpIpcListener::bind(socket_addresses)
     }
-    fn start_read_thread(
+    /// Waits for a client to connect, see 'server'.
+    pub(crate) fn accept<T: ToSocketAddrs>(
+        socket_addresses: T,
+    ) -> Result<TcpStream, ConnectErrors> {
+        Self::listen(socket_addresses)?.accept_stream()
+    }
+    pub(crate) fn start_read_thread(
         tcp_stream: TcpStream,
-        config: TcpIpcConfig,
+        config: TcpIpcConfig<P>,
     ) -> Result<TcpIpc<P>, ConnectErrors> {
-        // set no_delay (as default), adjust buffer sizes
-        tcp_stream
-            .set_nodelay(true)
-            .map_err(self::ConnectErrors::SetNodelayError)?;
-        tcp_stream
-            .set_send_buffer_size(
-                std::mem::size_of::<<P as Protocol>::HeaderAsArray>() / std::mem::size_of::<u8>(),
-            )
-            .map_err(self::ConnectErrors::SetSendBufferSizeError)?;
-        tcp_stream
-            .set_recv_buffer_size(
-                std::mem::size_of::<<P as Protocol>::HeaderAsArray>() / std::mem::size_of::<u8>(),
-            )
-            .map_err(self::ConnectErrors::SetReceiveBufferSizeError)?;
-
-        // start read thread
-        let mut tcp_stream_read = tcp_stream
+        let (mut tcp_ipc, read_thread) = Self::prepare_connection(tcp_stream, config)?;
+        let started = std::time::Instant::now();
+        tcp_ipc.read_thread_handle = Some(
+            spawn_read_thread(&tcp_ipc.config, read_thread)
+                .map_err(ConnectErrors::ThreadSpawnError)?,
+        );
+        tcp_ipc.settle(started)?;
+        Ok(tcp_ipc)
+    }
+    /// Configures the stream and sets up both sides of a connection, without starting to read.
+    pub(crate) fn prepare_connection(
+        tcp_stream: TcpStream,
+        config: TcpIpcConfig<P>,
+    ) -> Result<(TcpIpc<P>, ReadThread<P>), ConnectErrors> {
+        config.check_protocol()?;
+        configure_stream(&tcp_stream, &config)?;
+        let peer_address = tcp_stream.peer_addr().ok();
+        let tcp_stream_read = tcp_stream
             .try_clone()
             .map_err(ConnectErrors::TryCloneError)?;
-        let (message_sender, message_receiver) = std::sync::mpsc::channel();
-        let (busy_state_sender, busy_state_receiver) = std::sync::mpsc::channel();
-        let (busy_state_query_sender, busy_state_query_receiver) = std::sync::mpsc::channel();
-        let (busy_state_queried_sender, busy_state_queried_receiver) = std::sync::mpsc::channel();
-        let (shutdown_sender, shutdown_receiver) = std::sync::mpsc::channel();
-        std::thread::spawn(move || {
-            let mut protocol = ProtocolBuffer::<P>::new();
-            let mut incoming_buffer = [0; BUFFER_SIZE];
-            info!("Read thread started");
-            let mut counter = 0;
-            'read_loop: loop {
-                if counter == config.check_count {
-                    counter = 0;
-                    match shutdown_receiver.try_recv() {
-                        Ok(()) => break 'read_loop,
-                        Err(std::sync::mpsc::TryRecvError::Empty) => {
-                            // nothing to do
-                        }
-                        Err(std::sync::mpsc::TryRecvError::Disconnected) => {
-                            debug!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                            break 'read_loop;
-                        }
-                    }
-                    match busy_state_query_receiver.try_recv() {
-                        Ok(()) => match busy_state_queried_sender.send(protocol.get_busy_state()) {
-                            Ok(()) => {}
-                            Err(std::sync::mpsc::SendError(_)) => {
-                                debug!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                                break 'read_loop;
-                            }
-                        },
-                        Err(std::sync::mpsc::TryRecvError::Empty) => {
-                            // nothing to do
-                        }
-                        Err(std::sync::mpsc::TryRecvError::Disconnected) => {
-                            debug!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                            break 'read_loop;
-                        }
-                    }
-
-                    loop {
-                        match busy_state_receiver.try_recv() {
-                            Ok(busy_state) => protocol.update_busy_state(busy_state),
-                            Err(std::sync::mpsc::TryRecvError::Empty) => break,
-                            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
-                                debug!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                                break 'read_loop;
-                            }
-                        }
-                    }
-                } else {
-                    counter += 1;
-                }
-                match tcp_stream_read.read(&mut incoming_buffer) {
-                    Ok(message_length) => {
-                        if message_length == 0 {
-                            // nothing to do
-                        } else {
-                            let mut buffer = &incoming_buffer[0..message_length];
-                            debug!("New incoming buffer: {:?}", buffer);
-                            while let Some((command, message)) = protocol.process_new_buffer(buffer)
-                            {
-                                buffer = &[];
-                                if let Some((command, message)) =
-                                    P::message_is_answered_via_immediate_route(
-                                        &command,
-                                        &message,
-                                        &protocol.get_busy_state(),
-                                    ) {
-                                    if let Some(message) = P::construct_message(command, &message) {
-                                        if let Err(err) = tcp_stream_read.write(&message) {
-                                            if message_sender
-                                                .send(Err(ReadThreadErrorsInternal::WriteError(
-                                                    err,
-                                                )))
-                                                .is_err()
-                                            {
-                                                info!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                                                break 'read_loop; //disconnected
-                                            }
-                                        }
-                                    } else if message_sender
-                                        .send(Err(
-                                            ReadThreadErrorsInternal::ImmediateMessageConstructError((
-                                                command, message,
-                                            )),
-                                        ))
-                                        .is_err()
-                                    {
-                                        debug!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                                        break 'read_loop; //disconnected
-                                    }
-                                } else if message_sender.send(Ok((command, message))).is_err() {
-                                    debug!("Read thread seems to be disconnected from main thread. Will be shut down.");
-                                    break 'read_loop; //disconnected
-                                }
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        if err.kind() == std::io::ErrorKind::WouldBlock {
-                            // nothing to do, this is interpreted as "no message available"
-                        } else if message_sender
-                            .send(Err(ReadThreadErrorsInternal::ReadError(err)))
-                            .is_err()
-                        {
-                            break 'read_loop; //disconnected
-                        }
-                    }
-                }
-                // wait between loops
-                if let Some(read_iteration_wait_time) = config.read_iteration_wait_time {
-                    std::thread::sleep(read_iteration_wait_time);
-                }
-            }
-            info!("Read thread finished");
-        });
-        if let Some(after_connect_wait_time) = config.after_connect_wait_time {
-            std::thread::sleep(after_connect_wait_time);
-        }
-        Ok(TcpIpc {
+        let (read_thread_channels, main_thread_channels) = channels::<P>();
+        let MainThreadChannels {
+            message_receiver,
+            busy_state_sender,
+            busy_state_query_sender,
+            busy_state_queried_receiver,
+            parser_state_query_sender,
+            parser_state_queried_receiver,
             shutdown_sender,
+            shutdown_ack_receiver,
+            schedule_sender,
+            restart_sender,
+        } = main_thread_channels;
+        let memory_budget = config
+            .memory_budget
+            .map(|budget| Arc::new(MemoryBudget::new(budget)));
+        let pending_outgoing = Arc::new(AtomicUsize::new(0));
+        let outgoing = Arc::new(std::sync::Mutex::new(OutgoingQueue::new(
+            pending_outgoing.clone(),
+            config
+                .write_pressure_watermarks
+                .clone()
+                .map(WatermarkTracker::new),
+            memory_budget.clone(),
+        )));
+        let connection_closed = Arc::new(AtomicBool::new(false));
+        let registration = Registration::new(
+            config.name.clone(),
+            tcp_stream.peer_addr().ok(),
+            connection_closed.clone(),
+        );
+        let peer_shutdown = Arc::new(PeerShutdown::default());
+        let busy_state_timeline =
+            new_busy_state_timeline::<P>(config.initial_busy_state.unwrap_or_else(P::idle));
+        let stats = Arc::new(StatsCounters::new(registration.id(), memory_budget.clone()));
+        let command_stats = if config.per_command_stats {
+            Some(Arc::new(std::sync::Mutex::new(Vec::new())))
+        } else {
+            None
+        };
+        let outgoing_trace = config
+            .outgoing_trace
+            .map(|trace| new_trace(trace, memory_budget.clone()));
+        let incoming_trace = config
+            .incoming_trace
+            .map(|trace| new_trace(trace, memory_budget.clone()));
+        let lanes = new_lane_router();
+        let peer_info = SharedPeerInfo::default();
+        let in_flight = new_in_flight(memory_budget.clone());
+        let retransmit_buffer = new_retransmit_buffer(memory_budget);
+        let rate_limiter = config.outgoing_rate_limit.as_ref().map(new_token_bucket);
+        let first_bytes = config.probe.as_ref().map(|_| new_first_bytes());
+        let read_thread = ReadThread::new(
+            registration.id(),
+            tcp_stream_read,
+            config.clone(),
+            read_thread_channels,
+            outgoing.clone(),
+            connection_closed.clone(),
+            peer_shutdown.clone(),
+            busy_state_timeline.clone(),
+            stats.clone(),
+            command_stats.clone(),
+            outgoing_trace.clone(),
+            incoming_trace.clone(),
+            lanes.clone(),
+            peer_info.clone(),
+            retransmit_buffer.clone(),
+            rate_limiter.clone(),
+            first_bytes.clone(),
+        );
+        let tcp_ipc = TcpIpc {
+            shutdown_sender,
+            shutdown_ack_receiver,
             busy_state_sender,
             message_receiver,
+            incoming: VecDeque::new(),
             stream: tcp_stream,
-            shutdown_wait_time: config.shutdown_wait_time,
+            config,
+            pending_outgoing,
+            outgoing,
             busy_state_query_sender,
             busy_state_queried_receiver,
-        })
+            parser_state_query_sender,
+            parser_state_queried_receiver,
+            connection_closed,
+            peer_shutdown,
+            busy_state_timeline,
+            registration,
+            stats,
+            command_stats,
+            outgoing_trace,
+            incoming_trace,
+            banner: None,
+            peer_info,
+            lanes,
+            last_error: None,
+            delivery: DeliveryReport::default(),
+            retransmit_buffer,
+            schedule_sender,
+            restart_sender,
+            journal: None,
+            journal_in_flight: Vec::new(),
+            rate_limiter,
+            first_bytes,
+            deferred_error: None,
+            expected_sequence: 0,
+            received_sequence: 0,
+            delivered_out_of_order: BTreeSet::new(),
+            high_priority: VecDeque::new(),
+            in_flight,
+            wake_signal: SharedWakeSignal::default(),
+            reconnect: None,
+            session: None,
+            read_thread_handle: None,
+            peer_address,
+        };
+        Ok((tcp_ipc, read_thread))
     }
 
     /// This updates the busy_state.
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
     /// client.update_busy_state(BusyStatesExample::Working);
     /// ```
     pub fn update_busy_state(&mut self, new_busy_state: P::BusyStates) -> BusyStateUpdateResult {
+        if self.is_connection_closed() {
+            return BusyStateUpdateResult::ConnectionClosed;
+        }
+        self.prune_busy_state_timeline();
         match self.busy_state_sender.send(new_busy_state) {
             Ok(()) => BusyStateUpdateResult::Success,
             Err(_) => BusyStateUpdateResult::Disconnected,
@@ -397,10 +1679,16 @@ impl<P: Protocol> TcpIpc<P> {
     }
     /// This queries the current busy_state.
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
     /// let current_busy_state = client.get_busy_state();
     /// ```
     pub fn get_busy_state(&mut self) -> Result<P::BusyStates, BusyStateQueryResult> {
+        if self.is_connection_closed() {
+            return Err(BusyStateQueryResult::ConnectionClosed);
+        }
         match self.busy_state_query_sender.send(()) {
             Ok(()) => match self.busy_state_queried_receiver.recv() {
                 Ok(busy_state) => Ok(busy_state),
@@ -411,30 +1699,556 @@ impl<P: Protocol> TcpIpc<P> {
     }
     /// This function check if a message was received and returns it, if so.
     /// If no message is available (or if a message is only partial available and more data is neceesary), Ok(None) is return.
+    /// Once the connection is closed, all messages received before are returned, afterwards ConnectionClosed is returned.
+    ///
+    /// Messages are returned in the order they were received, except for high-priority messages (see 'Protocol::is_high_priority'):
+    /// these are returned ahead of all other queued messages, as soon as the read thread forwarded them.
+    /// Among themselves, high-priority messages (as well as the other messages) keep their order.
     /// # Example
-    /// ```
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
     /// let message = client.get_message();
     /// ```
     pub fn get_message(&mut self) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
-        match self.message_receiver.try_recv() {
-            Ok(Ok(x)) => Ok(Some(x)),
-            Ok(Err(x)) => Err(match x {
-                ReadThreadErrorsInternal::WriteError(x) => ReadThreadErrors::WriteError(x),
-                ReadThreadErrorsInternal::ReadError(x) => ReadThreadErrors::ReadError(x),
-                ReadThreadErrorsInternal::ImmediateMessageConstructError(x) => {
-                    ReadThreadErrors::ImmediateMessageConstructError(x)
-                }
-            }),
-            Err(TryRecvError::Disconnected) => Err(ReadThreadErrors::Disconnected),
+        loop {
+            match self.get_message_or_gap()? {
+                Some(MessageOrGap::Message { message, .. }) => return Ok(Some(message)),
+                Some(MessageOrGap::Gap { .. }) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+    /// This function awaits the next message, like 'await_message', and returns it together with its context:
+    /// its sequence number, the busy state it was parsed with & the number of messages queued behind it.
+    /// Between checks, the read iteration wait time of the config is spent waiting.
+    ///
+    /// The context is consistent with the message without asking the read thread:
+    /// the busy state is exactly the one the read thread applied to the message (and checked the immediate route with), even if it was changed since.
+    /// The queue depth counts the messages the read thread forwarded before the message was delivered, excluding it.
+    /// Messages which are still in the socket or only partially parsed are not counted.
+    /// Dropped messages (see 'get_message_or_gap') are skipped.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn shed_load() {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// if let Some(next) = client.next_with_context(std::time::Duration::from_millis(10))? {
+    ///     if next.queue_depth > 100 && next.busy_state == BusyStatesExample::Working {
+    ///         shed_load();
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn next_with_context(
+        &mut self,
+        timeout: std::time::Duration,
+    ) -> Result<Option<MessageWithContext<P>>, ReadThreadErrors<P>> {
+        let wakes = self.wakes();
+        let start = std::time::Instant::now();
+        let (message, sequence) = loop {
+            match self.get_message_or_gap()? {
+                Some(MessageOrGap::Message { sequence, message }) => break (message, sequence),
+                Some(MessageOrGap::Gap { .. }) => continue,
+                None => {}
+            }
+            if start.elapsed() >= timeout {
+                return Ok(None);
+            }
+            self.pause(wakes, self.config.read_iteration_wait_time)?;
+        };
+        while let Ok(received) = self.receive_from_read_thread() {
+            self.incoming.push_back(received);
+        }
+        let queue_depth = self.incoming.iter().filter(|x| x.is_ok()).count();
+        let busy_state = {
+            let timeline = self
+                .busy_state_timeline
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            // the timeline is never empty, since its first entry is only removed in favour of a later one
+            timeline
+                .iter()
+                .rev()
+                .find(|(first, _)| *first <= sequence)
+                .or_else(|| timeline.front())
+                .map(|(_, busy_state)| *busy_state)
+                .unwrap_or_else(P::idle)
+        };
+        self.prune_busy_state_timeline();
+        Ok(Some(MessageWithContext {
+            message,
+            sequence,
+            busy_state,
+            queue_depth,
+        }))
+    }
+    // removes the busy states which only applied to messages delivered already
+    fn prune_busy_state_timeline(&self) {
+        let mut timeline = self
+            .busy_state_timeline
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        while timeline.len() > 1 && timeline[1].0 <= self.expected_sequence {
+            timeline.pop_front();
+        }
+    }
+    /// This function checks if a message was received, like 'get_message', but additionally reports its metadata.
+    /// Dropped messages (see 'get_message_or_gap') are skipped.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn handle_final_flush<M>(_: M) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// if let Some((message, metadata)) = client.get_message_with_metadata()? {
+    ///     if metadata.received_during_peer_shutdown {
+    ///         handle_final_flush(message);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn get_message_with_metadata(
+        &mut self,
+    ) -> Result<Option<(Message<P>, MessageMetadata)>, ReadThreadErrors<P>> {
+        loop {
+            match self.get_message_or_gap()? {
+                Some(MessageOrGap::Message { sequence, message }) => {
+                    let metadata = MessageMetadata {
+                        sequence,
+                        received_during_peer_shutdown: self
+                            .peer_shutdown
+                            .received_during_shutdown(sequence),
+                        redeliveries: 0,
+                    };
+                    return Ok(Some((message, metadata)));
+                }
+                Some(MessageOrGap::Gap { .. }) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+    /// This function checks if a message was received, like 'get_message', but the message stays in flight until it is acknowledged (see 'UnackedMessage').
+    /// Messages whose guard was dropped without acknowledging (or whose visibility timeout passed, see 'TcpIpcConfig::ack_visibility_timeout')
+    /// are delivered again first, with their 'MessageMetadata::redeliveries' incremented.
+    /// In-flight messages stay reserved in the memory budget (see 'TcpIpcConfig::memory_budget') until they are acknowledged.
+    /// Dropped messages (see 'get_message_or_gap') are skipped. This is purely local: the peer does not notice acknowledgments.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn apply_side_effect(_: &[u8]) -> std::io::Result<()> { Ok(()) }
+    /// # run(|| {
+    /// # let mut client = client();
+    /// while let Some(message) = client.get_message_unacked()? {
+    ///     apply_side_effect(message.payload())?; // if this fails, the guard is dropped & the message is redelivered
+    ///     message.ack();
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn get_message_unacked(
+        &mut self,
+    ) -> Result<Option<UnackedMessage<P>>, ReadThreadErrors<P>> {
+        let visibility_timeout = self.config.ack_visibility_timeout;
+        if let Some(message) = redeliver(&self.in_flight, visibility_timeout) {
+            return Ok(Some(message));
+        }
+        loop {
+            match self.take_message_or_gap()? {
+                Some(MessageOrGap::Message { sequence, message }) => {
+                    let metadata = MessageMetadata {
+                        sequence,
+                        received_during_peer_shutdown: self
+                            .peer_shutdown
+                            .received_during_shutdown(sequence),
+                        redeliveries: 0,
+                    };
+                    return Ok(Some(deliver(
+                        &self.in_flight,
+                        message,
+                        metadata,
+                        visibility_timeout,
+                    )));
+                }
+                Some(MessageOrGap::Gap { .. }) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+    /// This returns the number of messages taken via 'get_message_unacked', which are not yet acknowledged.
+    pub fn in_flight_count(&self) -> usize {
+        in_flight_count(&self.in_flight)
+    }
+    /// This function checks if a message was received, like 'get_message', but additionally reports its sequence number.
+    /// If messages were dropped before the next message, a gap is returned first (and the message by the next call).
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # use log::warn;
+    /// # fn handle<C, P>(_: C, _: P) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// match client.get_message_or_gap()? {
+    ///     Some(MessageOrGap::Message { sequence, message }) => handle(sequence, message),
+    ///     Some(MessageOrGap::Gap { first_missing, count }) => warn!("{} messages lost", count),
+    ///     None => {}
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn get_message_or_gap(&mut self) -> Result<Option<MessageOrGap<P>>, ReadThreadErrors<P>> {
+        let next = self.take_message_or_gap()?;
+        if let Some(MessageOrGap::Message { message, .. }) = &next {
+            memory_budget::release(self.stats.memory_budget(), message.1.len());
+        }
+        Ok(next)
+    }
+    // like 'get_message_or_gap', but the payload of a message stays reserved in the memory budget
+    fn take_message_or_gap(&mut self) -> Result<Option<MessageOrGap<P>>, ReadThreadErrors<P>> {
+        if self.deferred_error.is_none() {
+            if let Some((sequence, message)) = self.take_high_priority() {
+                // delivered ahead of the messages before it, which are no gap
+                self.delivered_out_of_order.insert(sequence);
+                self.skip_delivered_out_of_order();
+                self.delivery.delivered += 1;
+                self.delivery.last_sequence = Some(sequence);
+                return Ok(Some(MessageOrGap::Message { sequence, message }));
+            }
+        }
+        let (sequence, message) = match self.next_received()? {
+            Some(received) => received,
+            None => return Ok(None),
+        };
+        self.skip_delivered_out_of_order();
+        if sequence > self.expected_sequence {
+            let first_missing = self.expected_sequence;
+            let count = sequence - first_missing;
+            self.delivery.dropped_by_policy += count;
+            self.expected_sequence = sequence;
+            self.incoming.push_front(Ok((sequence, message)));
+            return Ok(Some(MessageOrGap::Gap {
+                first_missing,
+                count,
+            }));
+        }
+        self.expected_sequence = sequence + 1;
+        self.delivery.delivered += 1;
+        self.delivery.last_sequence = Some(sequence);
+        Ok(Some(MessageOrGap::Message { sequence, message }))
+    }
+    /// Returns the delivery report since the last call (or since connecting) and starts a new one.
+    /// The sequence number of the last delivered message is kept.
+    pub fn take_delivery_report(&mut self) -> DeliveryReport {
+        let report = self.delivery;
+        self.delivery = DeliveryReport {
+            last_sequence: report.last_sequence,
+            ..DeliveryReport::default()
+        };
+        report
+    }
+    fn next_received(&mut self) -> Result<Option<(u64, Message<P>)>, ReadThreadErrors<P>> {
+        if let Some(err) = self.deferred_error.take() {
+            return Err(err);
+        }
+        let received = match self.incoming.pop_front() {
+            Some(received) => Ok(received),
+            None => self.receive_from_read_thread(),
+        };
+        match received {
+            Ok(Ok(x)) => Ok(Some(x)),
+            Ok(Err(x)) => Err(self.read_thread_error(x)),
+            Err(TryRecvError::Disconnected) => Err(self.disconnected_error()),
             Err(TryRecvError::Empty) => Ok(None),
         }
     }
+    fn receive_from_read_thread(&mut self) -> Result<Incoming<P>, TryRecvError> {
+        let received = self.message_receiver.try_recv()?;
+        self.note_received(&received);
+        Ok(received)
+    }
+    // keeps track of the sequence & the high-priority messages, for everything taken from the channel
+    fn note_received(&mut self, received: &Incoming<P>) {
+        if let Ok((sequence, (command, _))) = received {
+            self.received_sequence = sequence + 1;
+            if P::is_high_priority(command) {
+                self.high_priority.push_back(*sequence);
+            }
+        }
+    }
+    // takes the oldest queued high-priority message out of the queue, after queueing all messages forwarded by the read thread
+    fn take_high_priority(&mut self) -> Option<(u64, Message<P>)> {
+        while let Ok(received) = self.receive_from_read_thread() {
+            self.incoming.push_back(received);
+        }
+        while let Some(sequence) = self.high_priority.pop_front() {
+            let position = self.incoming.iter().position(|received| match received {
+                Ok((queued, _)) => *queued == sequence,
+                Err(_) => false,
+            });
+            if let Some(Ok(received)) = position.and_then(|position| self.incoming.remove(position))
+            {
+                return Some(received);
+            }
+        }
+        None
+    }
+    fn read_thread_error(&mut self, error: ReadThreadErrorsInternal<P>) -> ReadThreadErrors<P> {
+        self.last_error = Some(describe_read_thread_error(&error));
+        match error {
+            ReadThreadErrorsInternal::WriteError(x) => ReadThreadErrors::WriteError(x),
+            ReadThreadErrorsInternal::ReadError(x) => ReadThreadErrors::ReadError(x),
+            ReadThreadErrorsInternal::RepeatedReadError(x) => {
+                ReadThreadErrors::RepeatedReadError(x)
+            }
+            ReadThreadErrorsInternal::ImmediateMessageConstructError(x) => {
+                ReadThreadErrors::ImmediateMessageConstructError(x)
+            }
+            ReadThreadErrorsInternal::ProtocolViolation(x) => {
+                ReadThreadErrors::ProtocolViolation(x)
+            }
+            ReadThreadErrorsInternal::PeerClosed(mid_frame) => {
+                ReadThreadErrors::PeerClosed { mid_frame }
+            }
+        }
+    }
+    fn disconnected_error(&self) -> ReadThreadErrors<P> {
+        // the closing is only reported once the read thread finished, so all messages received before are delivered first
+        if self.is_connection_closed() {
+            ReadThreadErrors::ConnectionClosed
+        } else {
+            ReadThreadErrors::Disconnected
+        }
+    }
+    /// Keeps the error, so it is returned by the next call retrieving a message.
+    pub(crate) fn defer_error(&mut self, error: ReadThreadErrors<P>) {
+        self.deferred_error = Some(error);
+    }
+    #[cfg(feature = "engine-mio")]
+    pub(crate) fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+    fn skip_delivered_out_of_order(&mut self) {
+        while self.delivered_out_of_order.remove(&self.expected_sequence) {
+            self.expected_sequence += 1;
+        }
+    }
+    /// This splits the received messages into two lanes: responses to requests & messages the peer pushes unsolicited (like telemetry).
+    /// The classifier decides the lane of each message by its command. Each lane is a bounded queue with its own overflow policy,
+    /// so a burst on one lane never delays or drops messages of the other lane.
+    /// The lanes are taken from the returned handles, which can be used on other threads. Requests are still written via this connection.
+    ///
+    /// The split applies to messages forwarded by the read thread afterwards. Messages received before stay queued (see 'get_message'),
+    /// and messages answered via the immediate route or dropped as duplicates are not routed.
+    /// To change the classification, split again: the previous handles are closed (see 'RpcHandle::is_closed'), but keep their queued messages.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn record<T>(_: T) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let (rpc, telemetry) = client.split_by(
+    ///     |command| match command {
+    ///         CommandsExample::Telemetry => Lane::Stream,
+    ///         _ => Lane::Rpc,
+    ///     },
+    ///     LaneConfig { capacity: 16, overflow: LaneOverflow::DropNewest },
+    ///     LaneConfig { capacity: 10_000, overflow: LaneOverflow::DropOldest },
+    /// );
+    /// std::thread::spawn(move || loop {
+    ///     for (_, sample) in telemetry.messages() {
+    ///         record(sample);
+    ///     }
+    /// });
+    /// client.write_message(CommandsExample::GetStatus, &[])?;
+    /// let status = rpc.await_command(CommandsExample::Status, std::time::Duration::from_millis(100));
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn split_by<F: Fn(&P::Commands) -> Lane + Send + 'static>(
+        &mut self,
+        classifier: F,
+        rpc: LaneConfig,
+        stream: LaneConfig,
+    ) -> (RpcHandle<P>, StreamHandle<P>) {
+        split(
+            &self.lanes,
+            Box::new(classifier),
+            rpc,
+            stream,
+            self.stats.memory_budget().clone(),
+        )
+    }
+    /// This starts a transaction, to send requests & wait for their responses without mistaking stale frames for responses (see 'TransactionGuard').
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let mut transaction = client.transaction();
+    /// let response = transaction.send_and_wait(
+    ///     CommandsExample::Start,
+    ///     &[],
+    ///     CommandsExample::Started,
+    ///     std::time::Duration::from_millis(100),
+    /// )?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn transaction(&mut self) -> TransactionGuard<'_, P> {
+        TransactionGuard::new(self)
+    }
+    pub(crate) fn read_iteration_wait_time(&self) -> Option<std::time::Duration> {
+        self.config.read_iteration_wait_time
+    }
+    /// Waits until the read thread forwarded all messages it parsed so far, and queues them.
+    /// Returns the sequence number of the next message, which is parsed afterwards.
+    pub(crate) fn synchronize_with_read_thread(&mut self) -> u64 {
+        // the read thread answers queries after forwarding the messages parsed before
+        if self.parser_state_query_sender.send(()).is_ok() {
+            let _ = self.parser_state_queried_receiver.recv();
+        }
+        while let Ok(received) = self.receive_from_read_thread() {
+            self.incoming.push_back(received);
+        }
+        self.received_sequence
+    }
+    /// Takes the first message matching the given predicate (of sequence number & command) out of the queue.
+    /// Other messages stay queued in order. An error which was received before is returned instead.
+    pub(crate) fn take_first_matching<F: Fn(u64, &P::Commands) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+        self.take_first_matching_skipping(predicate)
+            .map(|(message, _)| message)
+    }
+    // like 'take_first_matching', additionally returns the number of queued messages in front of the taken one (or all, if none matched)
+    fn take_first_matching_skipping<F: Fn(u64, &P::Commands) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Result<(Option<Message<P>>, usize), ReadThreadErrors<P>> {
+        if let Some(err) = self.deferred_error.take() {
+            return Err(err);
+        }
+        let disconnected = loop {
+            match self.receive_from_read_thread() {
+                Ok(received) => self.incoming.push_back(received),
+                Err(err) => break err == TryRecvError::Disconnected,
+            }
+        };
+        let position = self.incoming.iter().position(|received| match received {
+            Ok((sequence, (command, _))) => predicate(*sequence, command),
+            Err(_) => true,
+        });
+        let skipped = self
+            .incoming
+            .iter()
+            .take(position.unwrap_or(self.incoming.len()))
+            .filter(|received| received.is_ok())
+            .count();
+        match position.and_then(|position| self.incoming.remove(position)) {
+            Some(Ok((sequence, message))) => {
+                memory_budget::release(self.stats.memory_budget(), message.1.len());
+                self.delivery.delivered += 1;
+                self.delivered_out_of_order.insert(sequence);
+                self.skip_delivered_out_of_order();
+                Ok((Some(message), skipped))
+            }
+            Some(Err(x)) => Err(self.read_thread_error(x)),
+            None if disconnected => Err(self.disconnected_error()),
+            None => Ok((None, skipped)),
+        }
+    }
+    /// This function checks if a message was received, like 'get_message', but delivers the payload into the given buffer.
+    /// If some message is received, the buffer is cleared and filled with the payload, and Ok(Some(command)) is returned.
+    /// Otherwise, the buffer is left untouched.
+    /// The capacity of the buffer is reused across calls (it only grows), so a consumer can reuse a single buffer for all messages.
+    /// The queued payload is taken over as buffer if it is at least as large, otherwise it is copied into the buffer. So no allocation happens here.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn handle<C, P>(_: C, _: P) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let mut payload = Vec::with_capacity(1024);
+    /// while let Some(command) = client.read_message_into(&mut payload)? {
+    ///     handle(command, &payload);
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn read_message_into(
+        &mut self,
+        buf: &mut Vec<u8>,
+    ) -> Result<Option<P::Commands>, ReadThreadErrors<P>> {
+        loop {
+            match self.get_message_or_gap()? {
+                Some(MessageOrGap::Message {
+                    message: (command, mut payload),
+                    ..
+                }) => {
+                    if payload.capacity() >= buf.capacity() {
+                        std::mem::swap(buf, &mut payload);
+                    } else {
+                        buf.clear();
+                        buf.extend_from_slice(&payload);
+                    }
+                    return Ok(Some(command));
+                }
+                Some(MessageOrGap::Gap { .. }) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+    /// This function returns all messages which are currently available.
+    /// If an error occurs after some messages, these messages are returned and the error is returned by the next call (of this or any other receiving function).
+    /// So all messages received before the connection was closed are returned before 'ConnectionClosed' (or 'Disconnected').
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn handle<C, P>(_: C, _: P) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// for (command, payload) in client.drain_messages()? {
+    ///     handle(command, payload);
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn drain_messages(&mut self) -> Result<Vec<Message<P>>, ReadThreadErrors<P>> {
+        let mut messages = Vec::new();
+        loop {
+            match self.get_message() {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => return Ok(messages),
+                Err(err) if messages.is_empty() => return Err(err),
+                Err(err) => {
+                    self.deferred_error = Some(err);
+                    return Ok(messages);
+                }
+            }
+        }
+    }
     /// This function attemps to clear the message queue.
     /// To do this, it waits a given duration.
     /// Then it calls get_message until no message is received, or an error is received (which is returned in turn).
     /// # Example
-    /// ```
-    /// let result = client.clear_message_queue(std::time::Duration::from_micros(10_000));
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
+    /// let result = client.clear_message_queue(Some(std::time::Duration::from_micros(10_000)));
     /// ```
     pub fn clear_message_queue(
         &mut self,
@@ -455,101 +2269,1449 @@ impl<P: Protocol> TcpIpc<P> {
     /// If no message is received during the wait time, Ok(None) is returned.
     /// If some message is received, Ok(Some((command, payload))) is returned.
     /// If an error happens, Err(x) is returned.
+    ///
+    /// The message is returned as soon as the read thread forwarded it (see 'get_message_blocking'), without polling.
+    /// The iteration wait time only bounds how late a wake (see 'waker') is noticed, which is at most 1 ms.
     /// # Example
-    /// ```
-    /// let message = client.await_message(std::time::Duration::from_micros(10_000), std::time::Duration::from_nanos(2_000));
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
+    /// let message = client.await_message(std::time::Duration::from_micros(10_000), Some(std::time::Duration::from_nanos(2_000)));
     /// ```
     pub fn await_message(
         &mut self,
         maximal_wait_time: std::time::Duration,
         iteration_wait_time: Option<std::time::Duration>,
     ) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+        let wakes = self.wake_signal.wakes();
         let instant = std::time::Instant::now();
+        let slice = iteration_wait_time.map_or(WAKE_CHECK_INTERVAL, |iteration_wait_time| {
+            iteration_wait_time.min(WAKE_CHECK_INTERVAL)
+        });
         while instant.elapsed() < maximal_wait_time {
-            match self.get_message() {
-                Ok(Some(x)) => return Ok(Some(x)),
-                Ok(None) => {
-                    if let Some(iteration_wait_time) = iteration_wait_time {
-                        std::thread::sleep(iteration_wait_time)
-                    }
-                }
-                Err(x) => return Err(x),
+            let remaining = maximal_wait_time.saturating_sub(instant.elapsed());
+            if let Some(message) = self.get_message_blocking(Some(slice.min(remaining)))? {
+                return Ok(Some(message));
             }
+            self.pause(wakes, None)?;
         }
         Ok(None)
     }
+    /// This function waits until a message is received (at most the given time, or without bound if None) & returns it like 'get_message'.
+    /// If no message is received in time, Ok(None) is returned.
+    ///
+    /// Unlike 'await_message', this blocks on the channel of the read thread, so a message is returned as soon as it was forwarded & no CPU is spent meanwhile.
+    /// Errors are returned like by 'get_message': an error forwarded before (or while waiting) is returned right away, in order with the messages.
+    /// The wait is not interrupted by a wake (see 'waker'). Messages routed to a lane (see 'split_by') do not end the wait.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn handle<C, P>(_: C, _: P) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// while let Some((command, payload)) = client.get_message_blocking(Some(std::time::Duration::from_secs(1)))? {
+    ///     handle(command, payload);
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn get_message_blocking(
+        &mut self,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+        let deadline = timeout.map(|timeout| std::time::Instant::now() + timeout);
+        loop {
+            // queued messages & errors come first
+            if let Some(message) = self.get_message()? {
+                return Ok(Some(message));
+            }
+            let received = match deadline {
+                Some(deadline) => self
+                    .message_receiver
+                    .recv_timeout(deadline.saturating_duration_since(std::time::Instant::now())),
+                None => self
+                    .message_receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match received {
+                Ok(received) => {
+                    self.note_received(&received);
+                    self.incoming.push_back(received);
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                // the next check reports the closing
+                Err(RecvTimeoutError::Disconnected) => {}
+            }
+        }
+    }
+    /// Returns a waker, which interrupts the awaits of this connection from another thread (for example, once an operator pressed stop).
+    /// An interrupted await returns 'ReadThreadErrors::Interrupted', see 'AwaitWaker'.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn handle<T>(_: T) {}
+    /// # struct StopButton;
+    /// # impl StopButton {
+    /// #     fn wait_pressed(&self) {}
+    /// # }
+    /// # let stop_button = StopButton;
+    /// # let mut client = client();
+    /// let waker = client.waker();
+    /// std::thread::spawn(move || {
+    ///     stop_button.wait_pressed();
+    ///     waker.wake();
+    /// });
+    /// match client.await_message(std::time::Duration::from_secs(30), Some(std::time::Duration::from_millis(10))) {
+    ///     Err(ReadThreadErrors::Interrupted) => println!("stopped by the operator"),
+    ///     other => handle(other),
+    /// }
+    /// ```
+    pub fn waker(&self) -> AwaitWaker {
+        AwaitWaker::new(self.wake_signal.clone())
+    }
+    // the number of wakes so far, taken at the start of an await
+    pub(crate) fn wakes(&self) -> u64 {
+        self.wake_signal.wakes()
+    }
+    // waits between the checks of an await, returning early if a wake happened since the await started
+    pub(crate) fn pause(
+        &self,
+        wakes: u64,
+        duration: Option<std::time::Duration>,
+    ) -> Result<(), ReadThreadErrors<P>> {
+        if self.wake_signal.sleep(wakes, duration) {
+            Err(ReadThreadErrors::Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+    /// This function awaits the first message with any of the given commands, for example either Ack or Nack.
+    /// Messages with other commands stay queued in order and are retrieved by the next calls of 'get_message'.
+    /// If no such message is received during the wait time, Ok(None) is returned.
+    /// Between checks, the read iteration wait time of the config is spent waiting.
+    /// Listing a command twice is fine, but if no command is given, 'ReadThreadErrors::NoCommandGiven' is returned.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let reply = client.await_any_command(
+    ///     &[CommandsExample::Ack, CommandsExample::Nack],
+    ///     std::time::Duration::from_millis(100),
+    /// )?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn await_any_command(
+        &mut self,
+        commands: &[P::Commands],
+        maximal_wait_time: std::time::Duration,
+    ) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+        self.await_any_command_skipping(commands, maximal_wait_time)
+            .0
+    }
+    /// This function awaits the first message with any of the given commands, like 'await_any_command', but waits at most the time left of the given budget.
+    /// The time waited is drawn from the budget, so successive awaits can share an overall deadline.
+    /// The outcome reports the time waited & the number of messages with other commands which were passed over.
+    /// If no command is given, the result is 'ReadThreadErrors::NoCommandGiven' (without waiting).
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # use log::debug;
+    /// # #[derive(Debug)]
+    /// # struct StepTimedOut(CommandsExample);
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let mut budget = DeadlineBudget::new(std::time::Duration::from_millis(500));
+    /// for step in &[CommandsExample::Prepared, CommandsExample::Started, CommandsExample::Done] {
+    ///     let outcome = client.await_command_budgeted(&[*step], &mut budget);
+    ///     debug!("{:?} after {:?}, {} messages skipped", step, outcome.waited, outcome.messages_skipped);
+    ///     if outcome.result?.is_none() {
+    ///         return Err(StepTimedOut(*step).into());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn await_command_budgeted(
+        &mut self,
+        commands: &[P::Commands],
+        budget: &mut DeadlineBudget,
+    ) -> AwaitOutcome<P> {
+        let instant = std::time::Instant::now();
+        let (result, messages_skipped) =
+            self.await_any_command_skipping(commands, budget.remaining());
+        let waited = instant.elapsed();
+        budget.draw(waited);
+        AwaitOutcome {
+            result,
+            waited,
+            messages_skipped,
+        }
+    }
+    // like 'await_any_command', additionally returns the number of queued messages which did not match
+    #[allow(clippy::type_complexity)]
+    fn await_any_command_skipping(
+        &mut self,
+        commands: &[P::Commands],
+        maximal_wait_time: std::time::Duration,
+    ) -> (Result<Option<Message<P>>, ReadThreadErrors<P>>, usize) {
+        if commands.is_empty() {
+            return (Err(ReadThreadErrors::NoCommandGiven), 0);
+        }
+        let wakes = self.wakes();
+        let instant = std::time::Instant::now();
+        let mut skipped = 0;
+        let result = loop {
+            match self.take_first_matching_skipping(|_, command| commands.contains(command)) {
+                Ok((Some(message), skipped_before)) => {
+                    skipped = skipped_before;
+                    break Ok(Some(message));
+                }
+                Ok((None, queued)) => skipped = queued,
+                Err(err) => break Err(err),
+            }
+            if instant.elapsed() >= maximal_wait_time {
+                break Ok(None);
+            }
+            if let Err(err) = self.pause(wakes, self.config.read_iteration_wait_time) {
+                break Err(err);
+            }
+        };
+        (result, skipped)
+    }
+    /// This function writes several messages at once, using as few system calls as possible (see 'write_message').
+    /// All messages are constructed first, so if any fails, nothing is sent.
+    /// The messages are written like those of 'write_message': if the stream would block, this retries as configured (see 'TcpIpcConfig::write_retry'),
+    /// or queues the unwritten rest for the read thread, and while the connection is re-established, the messages are queued (see 'ReconnectPolicy::queue_limit').
+    /// Returns the number of messages, which were written (or queued) completely.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// # let (first, second) = (vec![1, 2, 3], vec![4, 5, 6]);
+    /// let written = client.write_messages(&[
+    ///     (CommandsExample::Data, &first[..]),
+    ///     (CommandsExample::Data, &second[..]),
+    /// ])?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn write_messages(
+        &mut self,
+        messages: &[(P::Commands, &[u8])],
+    ) -> Result<usize, BatchWriteErrors> {
+        if self.is_connection_closed() {
+            return Err(BatchWriteErrors::ConnectionClosed);
+        }
+        let verify_frames = self.config.verify_frames.unwrap_or(cfg!(debug_assertions));
+        let frames = messages
+            .iter()
+            .enumerate()
+            .map(|(index, (command, message_))| {
+                construct_frame::<P>(*command, message_, verify_frames)
+                    .map_err(|error| BatchWriteErrors::ConstructionFailed { index, error })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.take_rate_limit_tokens(frames.len(), self.waits_when_rate_limited())
+            .map_err(|retry_after| BatchWriteErrors::RateLimited { retry_after })?;
+        match self.write_constructed(messages, &frames, self.config.write_retry, Priority::Normal) {
+            Ok(()) => Ok(frames.len()),
+            Err((written, WriteFailure::SendFailed(error))) => {
+                Err(BatchWriteErrors::WriteFailed { written, error })
+            }
+            Err((written, WriteFailure::MemoryBudgetExceeded)) => {
+                Err(BatchWriteErrors::MemoryBudgetExceeded { written })
+            }
+            Err((_, WriteFailure::NotConnected)) => Err(BatchWriteErrors::NotConnected),
+        }
+    }
     /// This function writes/sends a message. The message is given as command (as enum-variant) & a payload/message.
     /// Then the message header is added and send via TCP, including the message.
     /// If an error occurs, Err(x) is returned.
     /// If the message is writen successfully, Ok(()) is returned.
+    ///
+    /// Messages & immediate responses (written by the read thread) are serialized by a lock, which is only held while writing.
+    /// If an immediate response is only partially written, the message is queued behind it and written by the read thread.
+    /// If the stream would block, this retries as configured (see 'TcpIpcConfig::write_retry'), or queues the unwritten rest for the read thread.
+    ///
+    /// If the outgoing rate limit is reached (see 'TcpIpcConfig::outgoing_rate_limit'), this waits or fails according to its policy.
     /// # Example
-    /// ```
-    /// let message = client.write_message(ProtocolExampleCommands::Start, "ok".as_bytes());
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
+    /// let message = client.write_message(CommandsExample::Start, "ok".as_bytes());
     /// ```
     pub fn write_message(
         &mut self,
         command: P::Commands,
         message_: &[u8],
     ) -> Result<(), WriteMessageErrors> {
-        let message = P::construct_message(command, message_)
-            .ok_or(WriteMessageErrors::MessageConstructionFailed)?;
-        let result = self
-            .stream
-            .write_all(&message)
-            .map_err(WriteMessageErrors::MessageSendFailed);
-        info!("Message send succesfully:{:?}", (command, message_));
+        self.write_message_limited(
+            command,
+            message_,
+            self.waits_when_rate_limited(),
+            self.config.write_retry,
+            Priority::Normal,
+        )
+    }
+    /// This function writes a message like 'write_message', but never waits for the outgoing rate limit:
+    /// if it is reached, 'RateLimited' is returned (whatever the policy of 'TcpIpcConfig::outgoing_rate_limit' is).
+    /// Without a rate limit, this is the same as 'write_message'.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # fn keep_for_later<T, D>(_: T, _: D) {}
+    /// # run(|| {
+    /// # let mut client = client();
+    /// # let sample = vec![0; 16];
+    /// match client.try_write_message(CommandsExample::Data, &sample) {
+    ///     Err(WriteMessageErrors::RateLimited { retry_after }) => keep_for_later(sample, retry_after),
+    ///     result => result?,
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn try_write_message(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+    ) -> Result<(), WriteMessageErrors> {
+        self.write_message_limited(
+            command,
+            message_,
+            false,
+            self.config.write_retry,
+            Priority::Normal,
+        )
+    }
+    /// This function writes a message like 'write_message', but retries at most the given time while the stream would block,
+    /// instead of as configured by 'TcpIpcConfig::write_retry' (whose backoff is used, if given).
+    /// Once the time is exhausted, 'MessageSendFailed' is returned & the connection is closed, since the peer may have received a partial frame.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # use std::time::Duration;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// # let image = vec![0; 1 << 20];
+    /// client.write_message_within(CommandsExample::Image, &image, Duration::from_millis(50))?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn write_message_within(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+        max_duration: std::time::Duration,
+    ) -> Result<(), WriteMessageErrors> {
+        let retry = RetrySpec {
+            max_duration,
+            backoff: self
+                .config
+                .write_retry
+                .map_or(DEFAULT_WRITE_BACKOFF, |retry| retry.backoff),
+        };
+        self.write_message_limited(
+            command,
+            message_,
+            self.waits_when_rate_limited(),
+            Some(retry),
+            Priority::Normal,
+        )
+    }
+    // checks if 'write_message' waits for the rate limit, instead of failing
+    fn waits_when_rate_limited(&self) -> bool {
+        self.config
+            .outgoing_rate_limit
+            .is_some_and(|limit| limit.when_limited == RateLimitPolicy::Wait)
+    }
+    // takes tokens of the rate limit (if any) for the given number of frames, or returns the time until they are available
+    fn take_rate_limit_tokens(&self, count: usize, wait: bool) -> Result<(), std::time::Duration> {
+        match &self.rate_limiter {
+            Some(rate_limiter) => super::rate_limit::take(
+                rate_limiter,
+                u32::try_from(count).unwrap_or(u32::MAX),
+                wait,
+            ),
+            None => Ok(()),
+        }
+    }
+    fn write_message_limited(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+        wait: bool,
+        retry: Option<RetrySpec>,
+        priority: Priority,
+    ) -> Result<(), WriteMessageErrors> {
+        if self.is_connection_closed() {
+            return Err(WriteMessageErrors::ConnectionClosed);
+        }
+        self.take_rate_limit_tokens(1, wait)
+            .map_err(|retry_after| WriteMessageErrors::RateLimited { retry_after })?;
+        self.write_message_unlimited(command, message_, retry, priority)
+    }
+    // writes a message without taking a token of the rate limit
+    fn write_message_unlimited(
+        &mut self,
+        command: P::Commands,
+        message_: &[u8],
+        retry: Option<RetrySpec>,
+        priority: Priority,
+    ) -> Result<(), WriteMessageErrors> {
+        if self.is_connection_closed() {
+            return Err(WriteMessageErrors::ConnectionClosed);
+        }
+        let verify_frames = self.config.verify_frames.unwrap_or(cfg!(debug_assertions));
+        let frame = construct_frame::<P>(command, message_, verify_frames)?;
+        self.write_constructed(&[(command, message_)], &[frame], retry, priority)
+            .map_err(|(_, failure)| failure.into())
+    }
+    // writes the constructed frames of the messages in order, which is the path shared by all writes of messages
+    // while the connection is re-established, the frames are queued instead (as far as 'ReconnectPolicy::queue_limit' allows)
+    // on failure, the number of completely written messages is returned together with the reason
+    fn write_constructed(
+        &mut self,
+        messages: &[(P::Commands, &[u8])],
+        frames: &[(Vec<u8>, &[u8])],
+        retry: Option<RetrySpec>,
+        priority: Priority,
+    ) -> Result<(), (usize, WriteFailure)> {
+        let connected = self.check_reconnected();
+        for (command, message_) in messages {
+            tap::<P>(
+                &self.config.frame_tap,
+                FrameDirection::Sent,
+                command,
+                message_,
+            );
+        }
+        if !connected {
+            return self
+                .queue_while_reconnecting(frames, priority)
+                .map_err(|failure| (0, failure));
+        }
+        // the stream is only written while the outgoing queue is locked, so these frames cannot interleave with immediate responses
+        let stream = &mut self.stream;
+        let mut outgoing = lock_outgoing(&self.outgoing);
+        let result = match outgoing.flush(stream) {
+            Err(err) => Err((0, WriteFailure::SendFailed(err))),
+            Ok(()) if outgoing.is_empty() => {
+                write_frames(stream, frames, &mut outgoing, retry, priority)
+            }
+            // a queued frame is not yet completely written, so these frames have to wait (at least) behind it
+            Ok(()) => {
+                queue_unwritten(frames, 0, &mut outgoing, priority).map_err(|failure| (0, failure))
+            }
+        };
+        drop(outgoing);
+        let written = match &result {
+            Ok(()) => frames.len(),
+            Err((written, _)) => *written,
+        };
+        for ((header, payload), (command, message_)) in frames.iter().zip(messages).take(written) {
+            self.stats.message_sent(header.len() + payload.len());
+            command_sent::<P>(&self.command_stats, *command);
+            trace_sent::<P>(&self.outgoing_trace, *command, message_);
+            // payloads are only formatted if they are logged at all
+            if log_enabled!(Level::Trace) {
+                trace!(
+                    "{}: Message send succesfully:{:?}",
+                    self.id(),
+                    (command, message_)
+                );
+            }
+        }
+        if let Err((_, WriteFailure::SendFailed(err))) = &result {
+            self.last_error = Some(format!("MessageSendFailed({:?})", err));
+            // a lost connection is re-established by the read thread, so it is not closed
+            if self.reconnect.is_none() {
+                self.connection_closed.store(true, Ordering::SeqCst);
+                self.stop_read_thread();
+            }
+        }
         result
     }
+    // queues frames while the connection is re-established, as far as 'ReconnectPolicy::queue_limit' allows
+    fn queue_while_reconnecting(
+        &mut self,
+        frames: &[(Vec<u8>, &[u8])],
+        priority: Priority,
+    ) -> Result<(), WriteFailure> {
+        let queue_limit = match &self.reconnect {
+            Some(reconnect) => reconnect.policy().queue_limit,
+            None => 0,
+        };
+        let length: usize = frames
+            .iter()
+            .map(|(header, payload)| header.len() + payload.len())
+            .sum();
+        let mut outgoing = lock_outgoing(&self.outgoing);
+        if outgoing.queued_bytes() + length > queue_limit {
+            return Err(WriteFailure::NotConnected);
+        }
+        queue_unwritten(frames, 0, &mut outgoing, priority)
+    }
+    // returns false while the connection is being re-established, otherwise the stream of the current connection is used for writing
+    fn check_reconnected(&mut self) -> bool {
+        let reconnect = match &self.reconnect {
+            Some(reconnect) => reconnect,
+            None => return true,
+        };
+        // the stream is published before the connection counts as re-established, see 'ReconnectState::reconnected'
+        let connected = reconnect.is_connected();
+        if let Some(stream) = reconnect.take_stream() {
+            self.peer_address = stream.peer_addr().ok();
+            self.stream = stream;
+        }
+        connected
+    }
+    /// Returns the next change of the connection itself, like a lost & re-established connection (see 'TcpIpcConfig::reconnect').
+    /// Returns None if there is none, which is always the case without reconnecting.
+    pub fn next_event(&mut self) -> Option<ConnectionEvent> {
+        self.reconnect
+            .as_ref()
+            .and_then(|reconnect| reconnect.next_event())
+    }
+    /// This function writes/sends a message like 'write_message', with the given priority.
+    /// There is no write thread, so if nothing is queued, the message is written immediately & the priority does not matter.
+    /// Once frames are queued (since the stream would block), a message overtakes the queued frames of lower priority which were not started yet.
+    /// Within a priority, the order of the calls is kept. Messages of 'write_message' & immediate responses have normal priority.
+    /// Note that frames of low priority starve, as long as frames of higher priority keep being queued.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// client.write_message_with_priority(CommandsExample::Stop, &[], Priority::High)?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn write_message_with_priority(
+        &mut self,
+        command: P::Commands,
+        message: &[u8],
+        priority: Priority,
+    ) -> Result<(), WriteMessageErrors> {
+        self.write_message_limited(
+            command,
+            message,
+            self.waits_when_rate_limited(),
+            self.config.write_retry,
+            priority,
+        )
+    }
+    /// This function lets the read thread send a message periodically, with a payload created by the given function.
+    /// The first message is sent after one interval.
+    /// The messages are written like immediate responses, so a failure is reported via 'get_message' (as WriteError or ImmediateMessageConstructError).
+    /// The timing precision is bounded by 'read_iteration_wait_time' & 'control_check_interval'.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let status = client.send_periodic(CommandsExample::Status, || vec![0], std::time::Duration::from_millis(500))?;
+    /// // ...
+    /// status.cancel();
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn send_periodic<F: FnMut() -> Vec<u8> + Send + 'static>(
+        &mut self,
+        command: P::Commands,
+        payload_fn: F,
+        interval: std::time::Duration,
+    ) -> Result<PeriodicHandle, WriteMessageErrors> {
+        self.schedule(command, Box::new(payload_fn), interval, Some(interval))
+    }
+    /// This function lets the read thread send a message once, after the given delay (see 'send_periodic').
+    pub fn send_after(
+        &mut self,
+        delay: std::time::Duration,
+        command: P::Commands,
+        message: Vec<u8>,
+    ) -> Result<PeriodicHandle, WriteMessageErrors> {
+        self.schedule(command, Box::new(move || message.clone()), delay, None)
+    }
+    fn schedule(
+        &mut self,
+        command: P::Commands,
+        payload_fn: Box<dyn FnMut() -> Vec<u8> + Send>,
+        delay: std::time::Duration,
+        interval: Option<std::time::Duration>,
+    ) -> Result<PeriodicHandle, WriteMessageErrors> {
+        if self.is_connection_closed() {
+            return Err(WriteMessageErrors::ConnectionClosed);
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.schedule_sender
+            .send(ScheduledSend {
+                command,
+                payload_fn,
+                due: std::time::Instant::now() + delay,
+                interval,
+                cancelled: cancelled.clone(),
+            })
+            .map_err(|_| WriteMessageErrors::ConnectionClosed)?;
+        Ok(PeriodicHandle::new(cancelled))
+    }
+    /// This function writes a reliable message: a frame id is put in front of the payload and the message is kept until the peer acknowledges it.
+    /// The peer removes the frame id, acknowledges the message and suppresses duplicates (within its 'dedup_window').
+    /// The returned handle is used to check for (or wait for) the acknowledgment.
+    /// If writing fails, the message is kept as well, so it can be sent again via 'resend_unacknowledged'.
+    /// A client which re-establishes a lost connection (see 'TcpIpcConfig::reconnect') sends all unacknowledged messages again by itself.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let delivery = client.write_message_reliable(CommandsExample::Start, "ok".as_bytes())?;
+    /// if !delivery.wait(std::time::Duration::from_secs(1)) {
+    ///     client.resend_unacknowledged()?;
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn write_message_reliable(
+        &mut self,
+        command: P::Commands,
+        message: &[u8],
+    ) -> Result<DeliveryHandle<P>, WriteMessageErrors> {
+        let reliability = match self.config.reliability {
+            Some(reliability) if P::ack_command().is_some() && P::is_reliable_command(&command) => {
+                reliability
+            }
+            _ => return Err(WriteMessageErrors::ReliabilityUnavailable),
+        };
+        let id = {
+            let (buffer, _) = &*self.retransmit_buffer;
+            let mut buffer = buffer
+                .lock()
+                .map_err(|_| WriteMessageErrors::ReliabilityUnavailable)?;
+            if buffer.frames.len() >= reliability.max_unacknowledged {
+                return Err(WriteMessageErrors::RetransmitBufferFull);
+            }
+            let id = buffer.next_id;
+            if !buffer.insert(id, command, message) {
+                return Err(WriteMessageErrors::MemoryBudgetExceeded);
+            }
+            buffer.next_id += 1;
+            id
+        };
+        if let Err(err) = self.write_message(command, &frame_with_id(id, message)) {
+            if let WriteMessageErrors::RateLimited { .. }
+            | WriteMessageErrors::MemoryBudgetExceeded = err
+            {
+                // nothing was sent, so there is nothing to acknowledge
+                let (buffer, _) = &*self.retransmit_buffer;
+                buffer
+                    .lock()
+                    .map_err(|_| WriteMessageErrors::ReliabilityUnavailable)?
+                    .remove(id);
+            }
+            return Err(err);
+        }
+        Ok(DeliveryHandle::new(id, self.retransmit_buffer.clone()))
+    }
+    /// This function writes all reliable messages which are not yet acknowledged again (in order), for example after the connection was interrupted.
+    /// The number of written messages is returned.
+    pub fn resend_unacknowledged(&mut self) -> Result<usize, WriteMessageErrors> {
+        let frames: Vec<_> = {
+            let (buffer, _) = &*self.retransmit_buffer;
+            let buffer = buffer
+                .lock()
+                .map_err(|_| WriteMessageErrors::ReliabilityUnavailable)?;
+            buffer
+                .frames
+                .iter()
+                .map(|(id, (command, message))| (*command, frame_with_id(*id, message)))
+                .collect()
+        };
+        for (command, frame) in &frames {
+            self.write_message(*command, frame)?;
+        }
+        Ok(frames.len())
+    }
+    /// This function writes a message like 'write_message', but appends the frame to the journal first (see 'TcpIpcConfig::journal').
+    /// Once the socket accepted all bytes of the frame, it is marked as completed in the journal.
+    /// So if the process crashes in between, 'journal::recover' reports the frame on the next start.
+    ///
+    /// If the frame has to wait behind a partially written immediate response, it is marked as completed by a later journaled write
+    /// which finds all queued frames written. A completion which cannot be written is logged, so the frame is reported as pending (although it was sent).
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let id = client.write_message_journaled(CommandsExample::OpenValve, &[])?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn write_message_journaled(
+        &mut self,
+        command: P::Commands,
+        message: &[u8],
+    ) -> Result<JournalId, WriteMessageErrors> {
+        if self.is_connection_closed() {
+            return Err(WriteMessageErrors::ConnectionClosed);
+        }
+        // the frame is built once, so the journal holds exactly the bytes which are written
+        let frame = P::construct_message(command, message)
+            .ok_or(WriteMessageErrors::MessageConstructionFailed)?;
+        if self.config.verify_frames.unwrap_or(cfg!(debug_assertions)) {
+            verify_frame::<P>(command, &frame, false, message.len())?;
+        }
+        if self.journal.is_none() {
+            let config = self
+                .config
+                .journal
+                .as_ref()
+                .ok_or(WriteMessageErrors::JournalUnavailable)?;
+            self.journal = Some(Journal::open(config).map_err(WriteMessageErrors::JournalFailed)?);
+        }
+        // the token is taken before appending, so a rejected frame is not journaled
+        self.take_rate_limit_tokens(1, self.waits_when_rate_limited())
+            .map_err(|retry_after| WriteMessageErrors::RateLimited { retry_after })?;
+        let id = match &mut self.journal {
+            Some(journal) => journal
+                .append(&frame)
+                .map_err(WriteMessageErrors::JournalFailed)?,
+            None => return Err(WriteMessageErrors::JournalUnavailable),
+        };
+        self.write_constructed(
+            &[(command, message)],
+            &[(frame, &[])],
+            self.config.write_retry,
+            Priority::Normal,
+        )
+        .map_err(|(_, failure)| WriteMessageErrors::from(failure))?;
+        self.journal_in_flight.push(id);
+        if lock_outgoing(&self.outgoing).is_empty() {
+            if let Some(journal) = &mut self.journal {
+                for id in self.journal_in_flight.drain(..) {
+                    if let Err(err) = journal.complete(id) {
+                        warn!(
+                            "{}: Journaled frame {:?} could not be marked as completed: {:?}",
+                            self.registration.id(),
+                            id,
+                            err
+                        );
+                    }
+                }
+            }
+        }
+        Ok(id)
+    }
+    /// This returns the number of reliable messages which are not yet acknowledged.
+    pub fn unacknowledged_count(&self) -> usize {
+        let (buffer, _) = &*self.retransmit_buffer;
+        buffer.lock().map(|buffer| buffer.frames.len()).unwrap_or(0)
+    }
     /// Attemps to close the TCP-connection
     /// Since the receiving side might not implement any shutdown functionality, this is optionally (and not included in Drop).
-    pub fn shutdown(self) -> Result<(), ShutdownError> {
-        let shutdown_requested_succesfully = match self.shutdown_sender.send(()) {
+    ///
+    /// First, the read thread is asked to stop reading (so no new immediate responses are generated).
+    /// Then, bounded by 'shutdown_wait_time', the read thread drains all immediate responses which are not yet completely written.
+    /// Afterwards the TCP-stream is shut down.
+    /// Frames which could not be written in time are reported as abandoned.
+    ///
+    /// The returned report lists the outcome of every phase, see 'ShutdownReport'.
+    /// It is returned as error if any phase failed (see 'ShutdownReport::is_clean'), so the reasons can be inspected in both cases.
+    pub fn shutdown(self) -> Result<ShutdownReport, ShutdownReport> {
+        let started = self.begin_shutdown();
+        let read_thread = self.await_read_thread(self.config.shutdown_wait_time);
+        self.finish_shutdown(started, read_thread)
+    }
+    // marks the connection as closed & asks the read thread to stop, which is the first phase of a shutdown
+    pub(crate) fn begin_shutdown(&self) -> ShutdownStarted {
+        let already_closed = self.connection_closed.swap(true, Ordering::SeqCst);
+        let pending_outgoing = self.pending_outgoing.load(Ordering::SeqCst);
+        let request = match self.shutdown_sender.send(()) {
             Ok(()) => {
-                debug!("Shutdown send successfully.");
-                true
+                debug!("{}: Shutdown send successfully.", self.id());
+                Ok(())
+            }
+            // the read thread dropped its channels, so it stopped before
+            Err(_) if already_closed => {
+                warn!(
+                    "{}: Send of shutdown failed, since the connection was already closed.",
+                    self.id()
+                );
+                Err(ShutdownRequestFailure::ConnectionClosed)
             }
             Err(_) => {
-                warn!("Send of shutdown failed.");
-                false
+                warn!(
+                    "{}: Send of shutdown failed, since the read thread is gone.",
+                    self.id()
+                );
+                Err(ShutdownRequestFailure::ReadThreadGone)
             }
         };
-
-        if let Some(shutdown_wait_time) = self.shutdown_wait_time {
-            std::thread::sleep(shutdown_wait_time);
+        ShutdownStarted {
+            request,
+            pending_outgoing,
+        }
+    }
+    // waits (at most the given time, without bound if None) until the read thread confirmed the shutdown, returning how it ended & the number of abandoned frames
+    pub(crate) fn await_read_thread(
+        &self,
+        wait_time: Option<std::time::Duration>,
+    ) -> (JoinOutcome, usize) {
+        let ack = if let Some(wait_time) = wait_time {
+            self.shutdown_ack_receiver.recv_timeout(wait_time)
+        } else {
+            self.shutdown_ack_receiver
+                .recv()
+                .map_err(|_| std::sync::mpsc::RecvTimeoutError::Disconnected)
+        };
+        match ack {
+            // a read thread which stopped earlier (for example, since the peer closed the connection) left its confirmation behind
+            Ok(abandoned_frames) => (JoinOutcome::finished(), abandoned_frames),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                warn!(
+                    "{}: Read thread did not confirm shutdown in time.",
+                    self.id()
+                );
+                (
+                    JoinOutcome {
+                        finished: false,
+                        panicked: false,
+                        timed_out: true,
+                    },
+                    self.pending_outgoing.load(Ordering::SeqCst),
+                )
+            }
+            // the read thread is gone without confirmation, whether it panicked is decided by joining it (see 'finish_shutdown')
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                debug!(
+                    "{}: Read thread ended without confirming shutdown.",
+                    self.id()
+                );
+                (
+                    JoinOutcome::finished(),
+                    self.pending_outgoing.load(Ordering::SeqCst),
+                )
+            }
         }
-        let shutdown_succesfully = match self.stream.shutdown(std::net::Shutdown::Both) {
+    }
+    // shuts down the TCP-stream, which is the last phase of a shutdown
+    pub(crate) fn finish_shutdown(
+        mut self,
+        started: ShutdownStarted,
+        (mut read_thread, abandoned_frames): (JoinOutcome, usize),
+    ) -> Result<ShutdownReport, ShutdownReport> {
+        // a finished read thread is joined, so the payload of its panic is reported (a running one would block)
+        let read_thread_panic = match self.read_thread_handle.take() {
+            Some(handle) if read_thread.finished => match handle.join() {
+                Ok(()) => None,
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    warn!("{}: Read thread panicked: {}", self.id(), message);
+                    read_thread.panicked = true;
+                    Some(panic)
+                }
+            },
+            _ => None,
+        };
+        self.check_reconnected();
+        let socket = match self.stream.shutdown(std::net::Shutdown::Both) {
             Ok(()) => {
-                debug!("Shutdown successfully.");
-                true
+                debug!("{}: Shutdown successfully.", self.id());
+                Ok(())
             }
-            Err(_) => {
-                warn!("Shutdown failed.");
-                false
+            Err(err) => {
+                warn!("{}: Shutdown failed: {:?}", self.id(), err);
+                Err(err)
             }
         };
-        if !shutdown_requested_succesfully || !shutdown_succesfully {
-            Err(ShutdownError {
-                shutdown_succesfully,
-                shutdown_requested_succesfully,
-            })
+        let report = ShutdownReport {
+            request: started.request,
+            socket,
+            read_thread,
+            read_thread_panic,
+            drained_outgoing: started.pending_outgoing.saturating_sub(abandoned_frames),
+            abandoned_frames,
+        };
+        if report.is_clean() {
+            Ok(report)
         } else {
-            Ok(())
+            Err(report)
         }
     }
+    /// Replaces the read thread by a new one on the same TCP connection, for example after the parser got out of sync
+    /// (with 'Strictness::Lenient', headers which cannot be parsed are reported one after the other) or a bug left it stuck.
+    ///
+    /// The read thread is asked to stop & hand its state over, waiting up to 'shutdown_wait_time' (without bound, if None).
+    /// If it does not stop in time, it keeps running unchanged & 'Timeout' is returned.
+    /// If it is gone already (for example, since it panicked), the new read thread continues with the last busy state.
+    /// The partially received frame & the received messages which were not yet taken are discarded or kept, see 'TcpIpcConfig::restart_policy'.
+    /// Queued frames, scheduled messages, statistics, traces, lanes & the identity of the connection are kept.
+    /// A connection of a 'ConnectionGroup' continues with a read thread of its own.
+    ///
+    /// Note that after a panic, the bytes held by the previous read thread stay reserved in the memory budget (if any),
+    /// and the next bytes read may belong to the middle of a frame, which the protocol has to resynchronize on.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// if let Err(ReadThreadErrors::Disconnected) = client.get_message() {
+    ///     client.restart_read_thread()?;
+    /// }
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn restart_read_thread(&mut self) -> Result<(), RestartError> {
+        if self.is_connection_closed() {
+            return Err(RestartError::ConnectionClosed);
+        }
+        self.check_reconnected();
+        // the stream is cloned first, so a failure leaves the current read thread running
+        let tcp_stream_read = self
+            .stream
+            .try_clone()
+            .map_err(RestartError::TryCloneError)?;
+        let (handover_sender, handover_receiver) = std::sync::mpsc::channel();
+        let handover = match self.restart_sender.send(handover_sender) {
+            Ok(()) => match if let Some(shutdown_wait_time) = self.config.shutdown_wait_time {
+                handover_receiver.recv_timeout(shutdown_wait_time)
+            } else {
+                handover_receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected)
+            } {
+                Ok(handover) => Some(handover),
+                Err(RecvTimeoutError::Timeout) => {
+                    warn!(
+                        "{}: Read thread did not stop for the restart in time.",
+                        self.id()
+                    );
+                    return Err(RestartError::Timeout);
+                }
+                // the read thread finished without handing over
+                Err(RecvTimeoutError::Disconnected) => None,
+            },
+            // the read thread is gone
+            Err(_) => None,
+        };
+        if self.is_connection_closed() {
+            return Err(RestartError::ConnectionClosed);
+        }
+        // everything forwarded by the previous read thread is kept or discarded together with the messages received before
+        while let Ok(received) = self.receive_from_read_thread() {
+            self.incoming.push_back(received);
+        }
+        if !self.config.restart_policy.keep_messages {
+            let memory_budget = self.stats.memory_budget();
+            self.incoming.retain(|received| match received {
+                Ok((_, message)) => {
+                    memory_budget::release(memory_budget, message.1.len());
+                    false
+                }
+                Err(_) => true,
+            });
+        }
+        let (read_thread_channels, main_thread_channels) = channels::<P>();
+        let mut read_thread = ReadThread::new(
+            self.id(),
+            tcp_stream_read,
+            self.config.clone(),
+            read_thread_channels,
+            self.outgoing.clone(),
+            self.connection_closed.clone(),
+            self.peer_shutdown.clone(),
+            self.busy_state_timeline.clone(),
+            self.stats.clone(),
+            self.command_stats.clone(),
+            self.outgoing_trace.clone(),
+            self.incoming_trace.clone(),
+            self.lanes.clone(),
+            self.peer_info.clone(),
+            self.retransmit_buffer.clone(),
+            self.rate_limiter.clone(),
+            None,
+        );
+        read_thread.enable_reconnect(self.reconnect.clone());
+        match handover {
+            Some(handover) => {
+                read_thread.take_over(handover, self.config.restart_policy.keep_partial_frame)
+            }
+            None => {
+                let busy_state = self
+                    .busy_state_timeline
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .back()
+                    .map(|(_, busy_state)| *busy_state)
+                    .unwrap_or_else(P::idle);
+                read_thread.continue_after(self.received_sequence, busy_state);
+            }
+        }
+        let MainThreadChannels {
+            message_receiver,
+            busy_state_sender,
+            busy_state_query_sender,
+            busy_state_queried_receiver,
+            parser_state_query_sender,
+            parser_state_queried_receiver,
+            shutdown_sender,
+            shutdown_ack_receiver,
+            schedule_sender,
+            restart_sender,
+        } = main_thread_channels;
+        self.message_receiver = message_receiver;
+        self.busy_state_sender = busy_state_sender;
+        self.busy_state_query_sender = busy_state_query_sender;
+        self.busy_state_queried_receiver = busy_state_queried_receiver;
+        self.parser_state_query_sender = parser_state_query_sender;
+        self.parser_state_queried_receiver = parser_state_queried_receiver;
+        self.shutdown_sender = shutdown_sender;
+        self.shutdown_ack_receiver = shutdown_ack_receiver;
+        self.schedule_sender = schedule_sender;
+        self.restart_sender = restart_sender;
+        // the previous read thread handed over (or is gone), so its handle is dropped
+        self.read_thread_handle = Some(
+            spawn_read_thread(&self.config, read_thread).map_err(RestartError::ThreadSpawnError)?,
+        );
+        info!("{}: Read thread restarted", self.id());
+        Ok(())
+    }
     /// Attemps to change the Tcp-Stream "NoDelay"-Option
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
     pub fn set_nodelay(&mut self, no_delay: bool) -> Result<(), std::io::Error> {
+        self.check_connection_open()?;
         self.stream.set_nodelay(no_delay)
     }
     /// Attemps to get the Tcp-Stream "NoDelay"-Option
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
     pub fn get_nodelay(&self) -> Result<bool, std::io::Error> {
+        self.check_connection_open()?;
         self.stream.nodelay()
     }
+    /// Returns the address of the peer, like the address a client of a server connected from.
+    /// It is kept once the connection is set up, so it is still known after the connection was closed.
+    /// If the connection is re-established (see 'TcpIpcConfig::reconnect'), the address of the new connection is returned.
+    pub fn peer_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        match self.peer_address {
+            Some(peer_address) => Ok(peer_address),
+            // a non-blocking connect may not have completed while the connection was set up
+            None => self.stream.peer_addr(),
+        }
+    }
+    /// Returns the local address of the connection, like the port the operating system chose for a client.
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.stream.local_addr()
+    }
+    /// Attemps to change the time-to-live (IP_TTL) of the packets sent via the Tcp-Stream.
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
+    pub fn set_ttl(&mut self, ttl: u32) -> Result<(), std::io::Error> {
+        self.check_connection_open()?;
+        self.stream.set_ttl(ttl)
+    }
+    /// Attemps to get the time-to-live (IP_TTL) of the packets sent via the Tcp-Stream.
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
+    pub fn ttl(&self) -> Result<u32, std::io::Error> {
+        self.check_connection_open()?;
+        self.stream.ttl()
+    }
+    /// Gets the size of the send buffer of the operating system (SO_SNDBUF), see 'TcpIpcConfig::send_buffer_size'.
+    /// The operating system may adjust the configured size, for example Linux doubles it.
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
+    pub fn send_buffer_size(&self) -> Result<usize, std::io::Error> {
+        self.check_connection_open()?;
+        engine::send_buffer_size(&self.stream)
+    }
+    /// Gets the size of the receive buffer of the operating system (SO_RCVBUF), see 'TcpIpcConfig::recv_buffer_size'.
+    /// The operating system may adjust the configured size, for example Linux doubles it.
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
+    pub fn recv_buffer_size(&self) -> Result<usize, std::io::Error> {
+        self.check_connection_open()?;
+        engine::recv_buffer_size(&self.stream)
+    }
+    /// Checks, without sending or consuming anything, if the connection can still be written to.
+    /// If the connection is known to be closed, an error of kind NotConnected is returned.
+    /// Otherwise, a pending socket error (like a reset by the peer) or a closing of the connection by the peer is reported as error, and the connection is marked as closed.
+    ///
+    /// Note that a vanished peer (for example a pulled cable) can only be detected after something was written and the operating system gave up retransmitting it.
+    /// This takes from seconds up to many minutes, depending on the platform; 'TcpIpcConfig::write_idle_ping' ensures that something is written regularly.
+    /// On Windows, a reset by the peer may only be reported by the next read or write instead of the pending socket error.
+    pub fn check_writable(&mut self) -> Result<(), std::io::Error> {
+        self.check_connection_open()?;
+        let result = match self.stream.take_error() {
+            Ok(Some(err)) | Err(err) => Err(err),
+            Ok(None) => match self.stream.peek(&mut [0]) {
+                Ok(0) => Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "connection closed by peer",
+                )),
+                Ok(_) => Ok(()),
+                Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+                Err(err) => Err(err),
+            },
+        };
+        if let Err(err) = &result {
+            self.connection_closed.store(true, Ordering::SeqCst);
+            self.last_error = Some(format!("CheckWritableFailed({:?})", err));
+            self.stop_read_thread();
+        }
+        result
+    }
+    /// Takes the pending error of the socket (SO_ERROR), if any, which clears it.
+    /// Asynchronous failures (like an unreachable host or an aborted connection) are often only observable this way, and otherwise surface on some later call.
+    /// A taken error is kept as the last error of the connection (see 'diagnostics'), but the connection is not marked as closed.
+    pub fn take_socket_error(&mut self) -> Result<Option<std::io::Error>, std::io::Error> {
+        let error = self.stream.take_error()?;
+        if let Some(err) = &error {
+            self.last_error = Some(format!("SocketError({:?})", err));
+        }
+        Ok(error)
+    }
+    /// Returns the process-unique id of this connection, which is also part of its log lines, stats & diagnostics.
+    pub fn id(&self) -> ConnectionId {
+        self.registration.id()
+    }
+    /// Returns how many frames & bytes wait to be written, since the peer does not read fast enough.
+    /// Frames are only queued if the socket did not accept them, and the read thread retries writing them each iteration.
+    /// So 'would_block_now' indicates that a message written now would be queued as well.
+    /// Producers can use this (or 'TcpIpcConfig::write_pressure_watermarks') to throttle.
+    pub fn write_pressure(&self) -> WritePressure {
+        lock_outgoing(&self.outgoing).pressure()
+    }
+    /// Checks if the connection is known to be closed.
+    /// This happens if the peer closed the connection, if reading or writing failed fatally, or after a shutdown.
+    pub fn is_connection_closed(&self) -> bool {
+        self.connection_closed.load(Ordering::SeqCst)
+    }
+    /// Returns the state of the connection.
+    /// The state moves from 'Open' to 'PeerClosing' once the peer's goodbye (see 'Protocol::shutdown_command') was received,
+    /// and to 'PeerClosed' once the peer closed the connection. Other closings yield 'Closed'.
+    pub fn connection_state(&self) -> ConnectionState {
+        if self.peer_shutdown.is_closed() {
+            ConnectionState::PeerClosed
+        } else if self.is_connection_closed() {
+            ConnectionState::Closed
+        } else if matches!(&self.reconnect, Some(reconnect) if !reconnect.is_connected()) {
+            ConnectionState::Reconnecting
+        } else if self.peer_shutdown.is_closing() {
+            ConnectionState::PeerClosing
+        } else {
+            ConnectionState::Open
+        }
+    }
+    /// Returns a snapshot of the counters of this connection.
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.snapshot()
+    }
+    /// Counts a request/response exchange with the given request command, with its latency or as timed out (None).
+    pub(crate) fn exchange_finished(
+        &self,
+        command: P::Commands,
+        latency: Option<std::time::Duration>,
+    ) {
+        self.stats.exchange_finished(latency);
+        command_exchanged::<P>(&self.command_stats, command, latency);
+    }
+    /// Returns a snapshot of the per-command counters of this connection, in the order the commands were first seen.
+    /// This is empty unless 'TcpIpcConfig::per_command_stats' is enabled.
+    pub fn command_stats(&self) -> Vec<(P::Commands, CommandStats)> {
+        match &self.command_stats {
+            Some(command_stats) => command_stats
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+            None => Vec::new(),
+        }
+    }
+    /// Returns the most recent frames written on this connection (oldest first), including immediate responses, scheduled messages, pings & acknowledgments.
+    /// This is empty unless 'TcpIpcConfig::outgoing_trace' is enabled.
+    pub fn outgoing_trace(&self) -> Vec<TraceEntry<P>> {
+        trace_entries(&self.outgoing_trace)
+    }
+    /// Returns the most recent frames received on this connection (oldest first), with what happened to them.
+    /// Unlike 'get_message', this includes frames answered via the immediate route or dropped.
+    /// This is empty unless 'TcpIpcConfig::incoming_trace' is enabled.
+    pub fn incoming_trace(&self) -> Vec<IncomingTraceEntry<P>> {
+        trace_entries(&self.incoming_trace)
+    }
+    /// Returns the banner the server sent while this client connected (see 'Protocol::is_banner' & 'TcpIpcConfig::banner_wait_time').
+    /// This is None if no banner arrived in time, or if this is no client. Banners arriving later are returned by 'get_message' as usual.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # use log::info;
+    /// # let mut client = client();
+    /// if let Some((_, firmware)) = client.banner() {
+    ///     info!("connected to firmware {:?}", firmware);
+    /// }
+    /// ```
+    pub fn banner(&self) -> Option<Message<P>> {
+        self.banner.clone()
+    }
+    /// Returns what the peer declared about itself (see 'Protocol::peer_info'), like its protocol version.
+    /// This is determined by the first received message declaring it (typically the peer's hello), and does not change afterwards.
+    /// It is None until such a message was received. To have it available right after connecting,
+    /// let the connection wait for the handshake (see 'ReadyCondition::AfterHandshake').
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
+    /// let version = client.peer_info().and_then(|peer| peer.protocol_version);
+    /// ```
+    pub fn peer_info(&self) -> Option<PeerInfo> {
+        self.peer_info
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+    /// Returns the token of the session the server issued to this client (see 'Protocol::session_command').
+    /// It is presented whenever a lost connection is re-established (see 'TcpIpcConfig::reconnect'), so the server recognizes the client.
+    /// It is None until the server answered, and always for a server or a protocol without sessions.
+    /// A server which does not know the presented token anymore issues a new one, which replaces it.
+    pub fn session_token(&self) -> Option<SessionToken> {
+        self.session
+            .as_ref()
+            .and_then(|session| *session.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+    /// Collects a snapshot of the internal state of this connection, for example to attach it to a bug report.
+    /// This does not consume any messages: all queued messages can still be retrieved afterwards.
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # let mut client = client();
+    /// let diagnostics = client.diagnostics();
+    /// if let Some(parser_state) = &diagnostics.parser_state {
+    ///     println!("stalled mid-frame: {}", parser_state.is_mid_frame());
+    /// }
+    /// ```
+    pub fn diagnostics(&mut self) -> Diagnostics<P> {
+        // the read thread answers queries after forwarding the messages parsed before, so the parser state matches the queued messages
+        let parser_state = match self.parser_state_query_sender.send(()) {
+            Ok(()) => self.parser_state_queried_receiver.recv().ok(),
+            Err(_) => None,
+        };
+        while let Ok(received) = self.receive_from_read_thread() {
+            self.incoming.push_back(received);
+        }
+        let pending_errors = self.incoming.iter().filter(|x| x.is_err()).count();
+        if let Some(Err(x)) = self.incoming.iter().rev().find(|x| x.is_err()) {
+            self.last_error = Some(describe_read_thread_error(x));
+        }
+        let pending_commands = self
+            .incoming
+            .iter()
+            .filter_map(|x| x.as_ref().ok().map(|(_, (command, _))| *command))
+            .take(DIAGNOSTICS_PENDING_COMMANDS)
+            .collect();
+        let busy_state = match self.busy_state_query_sender.send(()) {
+            Ok(()) => self.busy_state_queried_receiver.recv().ok(),
+            Err(_) => None,
+        };
+        Diagnostics {
+            connection_id: self.id(),
+            name: self.config.name.clone(),
+            connection_closed: self.is_connection_closed(),
+            stats: self.stats(),
+            pending_messages: self.incoming.len() - pending_errors,
+            pending_errors,
+            pending_commands,
+            parser_state,
+            busy_state,
+            limits: self.configured_limits(),
+            capabilities: self.capability_report(),
+            last_error: self.last_error.clone(),
+            peer_info: self.peer_info(),
+            outgoing_trace: self.outgoing_trace(),
+            incoming_trace: self.incoming_trace(),
+        }
+    }
+    /// Returns how this connection was built: the crate (see 'build_info'), what the protocol declares about its frames & the effective config.
+    /// This is intended to be attached to bug reports, it is part of 'diagnostics' as well.
+    pub fn capability_report(&self) -> CapabilityReport {
+        let config = &self.config;
+        CapabilityReport {
+            build: build_info(),
+            protocol: ProtocolCapabilities::of::<P>(),
+            limits: self.configured_limits(),
+            config: ResolvedConfig {
+                name: config.name.clone(),
+                nodelay: config.nodelay,
+                send_buffer_size: config.send_buffer_size,
+                recv_buffer_size: config.recv_buffer_size,
+                verify_frames: config.verify_frames.unwrap_or(cfg!(debug_assertions)),
+                strict: config.strictness == Strictness::Strict,
+                error_payload_retention: config.error_payload_retention,
+                max_unacknowledged: config
+                    .reliability
+                    .map(|reliability| reliability.max_unacknowledged),
+                dedup_window: config.dedup_window,
+                outgoing_frames_per_second: config
+                    .outgoing_rate_limit
+                    .map(|limit| limit.frames_per_second.max(1)),
+                write_retry: config.write_retry.map(|retry| retry.max_duration),
+                read_error_limit: config.read_error_limit,
+                journal: config.journal.is_some(),
+            },
+        }
+    }
+    fn configured_limits(&self) -> ConfiguredLimits {
+        ConfiguredLimits {
+            read_buffer_size: BUFFER_SIZE,
+            check_count: self.config.check_count,
+            control_check_interval: self.config.effective_control_check_interval(),
+            read_iteration_wait_time: self.config.read_iteration_wait_time,
+            shutdown_wait_time: self.config.shutdown_wait_time,
+            memory_budget: self.config.memory_budget,
+        }
+    }
+    // after the connection is found to be closed by the main thread, the read thread stops at its next control check
+    pub(crate) fn stop_read_thread(&self) {
+        let _ = self.shutdown_sender.send(());
+    }
+    fn check_connection_open(&self) -> Result<(), std::io::Error> {
+        if self.is_connection_closed() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "connection is closed",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+/// The error type for 'TcpIpc::restart_read_thread'.
+#[derive(Debug)]
+pub enum RestartError {
+    /// The connection is known to be closed, so no read thread is started.
+    ConnectionClosed,
+    /// The read thread did not stop within 'shutdown_wait_time', so it keeps running unchanged.
+    Timeout,
+    /// The stream could not be cloned for the new read thread, so the current one keeps running.
+    TryCloneError(std::io::Error),
+    /// The new read thread could not be spawned. Nothing is read from the connection anymore.
+    ThreadSpawnError(std::io::Error),
+}
+/// The reason the shutdown request could not be passed to the read thread, see 'ShutdownReport::request'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShutdownRequestFailure {
+    /// The read thread had already stopped, since the connection was closed before (for example, by the peer).
+    ConnectionClosed,
+    /// The read thread had already stopped although the connection was not known to be closed, for example since it panicked.
+    ReadThreadGone,
+}
+impl std::fmt::Display for ShutdownRequestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShutdownRequestFailure::ConnectionClosed => write!(f, "connection was already closed"),
+            ShutdownRequestFailure::ReadThreadGone => write!(f, "read thread was already gone"),
+        }
+    }
+}
+impl std::error::Error for ShutdownRequestFailure {}
+
+/// How the read thread ended during a shutdown, see 'ShutdownReport::read_thread'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JoinOutcome {
+    /// The read thread is not running anymore.
+    pub finished: bool,
+    /// The read thread ended by a panic (for example, of the protocol implementation).
+    pub panicked: bool,
+    /// The read thread did not confirm the shutdown within 'shutdown_wait_time', so it might still be running.
+    pub timed_out: bool,
+}
+impl JoinOutcome {
+    fn finished() -> Self {
+        Self {
+            finished: true,
+            panicked: false,
+            timed_out: false,
+        }
+    }
+    /// Checks that the read thread finished regularly.
+    pub fn is_clean(&self) -> bool {
+        self.finished && !self.panicked && !self.timed_out
+    }
+}
+
+// the outcome of the first phase of a shutdown
+pub(crate) struct ShutdownStarted {
+    request: Result<(), ShutdownRequestFailure>,
+    // the outgoing frames queued when the shutdown started
+    pending_outgoing: usize,
+}
+
+/// The outcome of every phase of 'TcpIpc::shutdown'.
+#[derive(Debug)]
+pub struct ShutdownReport {
+    /// Passing the shutdown request to the read thread.
+    pub request: Result<(), ShutdownRequestFailure>,
+    /// Shutting down the TCP-stream.
+    pub socket: Result<(), std::io::Error>,
+    /// How the read thread ended.
+    pub read_thread: JoinOutcome,
+    /// The payload of the panic which ended the read thread, as returned by joining it (for example, to re-raise it via 'std::panic::resume_unwind').
+    /// This is None if the read thread did not panic, did not finish in time or has no thread of its own (like a connection of a 'ConnectionGroup').
+    pub read_thread_panic: Option<Box<dyn std::any::Any + Send + 'static>>,
+    /// The number of outgoing frames which were still queued when the shutdown started & were written before it completed.
+    pub drained_outgoing: usize,
+    /// The number of outgoing frames which could not be written before the shutdown completed.
+    pub abandoned_frames: usize,
 }
+impl ShutdownReport {
+    /// Checks that every phase succeeded & no frame was abandoned.
+    pub fn is_clean(&self) -> bool {
+        self.request.is_ok()
+            && self.socket.is_ok()
+            && self.read_thread.is_clean()
+            && self.abandoned_frames == 0
+    }
+}
+
 /// The error type for a shutdown attemp.
+/// This only tells which phase failed, see 'ShutdownReport' for the reasons.
+#[deprecated(note = "use the 'ShutdownReport' returned by 'TcpIpc::shutdown'")]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ShutdownError {
     /// Indicates if the request was successfully transmitted.
     pub shutdown_requested_succesfully: bool,
     /// Indicates if the shutdown was successful.
     pub shutdown_succesfully: bool,
+    /// Indicates if the read thread confirmed that it finished (see 'JoinOutcome::is_clean').
+    pub ack_received: bool,
+    /// The number of outgoing frames which could not be written before the shutdown completed.
+    pub abandoned_frames: usize,
+}
+#[allow(deprecated)]
+impl From<ShutdownReport> for ShutdownError {
+    fn from(report: ShutdownReport) -> Self {
+        Self {
+            shutdown_requested_succesfully: report.request.is_ok(),
+            shutdown_succesfully: report.socket.is_ok(),
+            ack_received: report.read_thread.is_clean(),
+            abandoned_frames: report.abandoned_frames,
+        }
+    }
 }
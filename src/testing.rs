@@ -0,0 +1,331 @@
+//! Assertion helpers for tests of code using this crate.
+//!
+//! This module is only available with the `test-util` feature.
+//! All helpers panic with a descriptive message if the expectation is not met, so they can be used directly in tests.
+//! # Example
+//! ```
+//! # use rust_tcp_ipc::doc_example::*;
+//! # use std::time::Duration;
+//! # let (mut server, mut client) = rust_tcp_ipc::testing::loopback::<ProtocolExample>(config(), config())
+//! #     .unwrap_or_else(|_| panic!("connecting failed"));
+//! use rust_tcp_ipc::testing::*;
+//! client.write_message(CommandsExample::Start, &[1, 2, 3]).unwrap();
+//! expect_payload(&mut server, CommandsExample::Start, &[1, 2, 3], Duration::from_secs(1));
+//! expect_silence(&mut server, Duration::from_millis(100));
+//! ```
+use super::engine;
+use super::tcp_ipc::*;
+use std::time::Duration;
+
+/// The time the helpers sleep between checking for new messages.
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+/// The time 'loopback' waits for the connection to be accepted.
+const LOOPBACK_ACCEPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connects a server & a client on an ephemeral port of 127.0.0.1 and returns both ready endpoints (server first).
+/// The port is chosen by the operating system, so several pairs can coexist (for example in parallel tests).
+/// Both read threads are started concurrently, so the waiting for readiness (see 'TcpIpcConfig::ready_when') of both configs overlaps.
+/// If anything fails, everything set up so far is dropped (and thus closed).
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// # use rust_tcp_ipc::testing::*;
+/// # use std::time::Duration;
+/// # run(|| {
+/// # let config = config();
+/// let (mut server, mut client) = loopback::<ProtocolExample>(config.clone(), config)?;
+/// client.write_message(CommandsExample::Start, &[]).unwrap();
+/// expect_payload(&mut server, CommandsExample::Start, &[], Duration::from_secs(1));
+/// # Ok(())
+/// # });
+/// ```
+pub fn loopback<P: Protocol>(
+    server_config: TcpIpcConfig<P>,
+    client_config: TcpIpcConfig<P>,
+) -> Result<(TcpIpc<P>, TcpIpc<P>), ConnectErrors> {
+    server_config.check_protocol()?;
+    client_config.check_protocol()?;
+    let listener = engine::bind(&([127, 0, 0, 1], 0).into()).map_err(ConnectErrors::BindError)?;
+    let address = listener.local_addr().map_err(ConnectErrors::BindError)?;
+    // the listener is bound, so the connection is completed by the operating system even before it is accepted
+    let client = TcpIpc::<P>::connect(address, Some(LOOPBACK_ACCEPT_TIMEOUT))?;
+    let client_address = client
+        .local_addr()
+        .map_err(ConnectErrors::ConnectionError)?;
+    let start = std::time::Instant::now();
+    let server = loop {
+        match engine::accept(&listener) {
+            // a connection of somebody else (which raced for the port) is dropped
+            Ok((server, peer_address)) if peer_address == client_address => break server,
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                if start.elapsed() > LOOPBACK_ACCEPT_TIMEOUT {
+                    return Err(ConnectErrors::WaitTimeExceeded);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            Err(err) => return Err(ConnectErrors::ConnectionError(err)),
+        }
+    };
+    let server_thread = std::thread::Builder::new()
+        .name("tcp-ipc/loopback".to_string())
+        .spawn(move || TcpIpc::start_read_thread(server, server_config))
+        .map_err(ConnectErrors::ThreadSpawnError)?;
+    let client = TcpIpc::start_read_thread(client, client_config);
+    let server = server_thread
+        .join()
+        .unwrap_or_else(|err| std::panic::resume_unwind(err))?;
+    Ok((server, client?))
+}
+
+/// Waits for the next message and asserts that it has the given command. The payload is returned.
+/// Panics if no message arrives within the timeout, if a message with another command arrives or if the read thread reports an error.
+pub fn expect_message<P: Protocol>(
+    client: &mut TcpIpc<P>,
+    command: P::Commands,
+    timeout: Duration,
+) -> Vec<u8> {
+    match client.await_message(timeout, Some(POLL_INTERVAL)) {
+        Ok(Some((received_command, payload))) => {
+            if received_command != command {
+                panic!(
+                    "unexpected command\n  expected: {:?}\n     found: {:?}\n   payload: {}",
+                    command,
+                    received_command,
+                    format_bytes(&payload)
+                );
+            }
+            payload
+        }
+        Ok(None) => panic!(
+            "expected command {:?}, but no message arrived within {:?}",
+            command, timeout
+        ),
+        Err(err) => panic!(
+            "expected command {:?}, but the read thread reported an error: {}",
+            command,
+            describe_error(&err)
+        ),
+    }
+}
+
+/// Waits for the next message and asserts that it has the given command and payload.
+/// Panics like 'expect_message', and additionally if the payload differs (showing the first differing byte).
+pub fn expect_payload<P: Protocol>(
+    client: &mut TcpIpc<P>,
+    command: P::Commands,
+    expected: &[u8],
+    timeout: Duration,
+) {
+    let payload = expect_message(client, command, timeout);
+    if payload != expected {
+        let first_difference = payload
+            .iter()
+            .zip(expected.iter())
+            .position(|(found, expected)| found != expected)
+            .unwrap_or_else(|| payload.len().min(expected.len()));
+        panic!(
+            "unexpected payload for command {:?} (first difference at byte {})\n  expected: {} ({} bytes)\n     found: {} ({} bytes)",
+            command,
+            first_difference,
+            format_bytes(expected),
+            expected.len(),
+            format_bytes(&payload),
+            payload.len()
+        );
+    }
+}
+
+/// Asserts that no message arrives during the given duration.
+/// Panics if a message arrives or if the read thread reports an error.
+pub fn expect_silence<P: Protocol>(client: &mut TcpIpc<P>, duration: Duration) {
+    match client.await_message(duration, Some(POLL_INTERVAL)) {
+        Ok(None) => {}
+        Ok(Some((command, payload))) => panic!(
+            "expected no message within {:?}, but received command {:?} with payload {}",
+            duration,
+            command,
+            format_bytes(&payload)
+        ),
+        Err(err) => panic!(
+            "expected no message within {:?}, but the read thread reported an error: {}",
+            duration,
+            describe_error(&err)
+        ),
+    }
+}
+
+/// Returns the bytes the given exchanges produce on the wire: their frames (see 'Protocol::construct_message') concatenated in order.
+/// Panics if a frame cannot be constructed.
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::testing::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// let bytes = wire_snapshot::<ProtocolExample>(&[(CommandsExample::Start, vec![1, 2, 3])]);
+/// ```
+pub fn wire_snapshot<P: Protocol>(exchanges: &[(P::Commands, Vec<u8>)]) -> Vec<u8> {
+    wire_frames::<P>(exchanges).concat()
+}
+
+/// Asserts that the given exchanges produce exactly the bytes of a checked-in snapshot file, for example to pin a wire format during a refactoring.
+///
+/// The file stores every frame as one line of hex bytes, so changes are readable in a diff of the repository. Lines starting with `#` are ignored.
+/// On a mismatch, this panics with the lines of 16 bytes which differ (expected & found, with their offsets).
+/// If 'update' is true, a missing or differing file is written instead (typically passed from an environment variable, see the example).
+/// # Example
+/// ```
+/// # use rust_tcp_ipc::testing::*;
+/// # use rust_tcp_ipc::doc_example::*;
+/// fn wire_format_is_unchanged() {
+///     assert_wire_matches_file::<ProtocolExample, _>(
+///         "tests/snapshots/example.wire",
+///         &[(CommandsExample::Start, vec![]), (CommandsExample::Data, vec![1, 2, 3])],
+///         std::env::var_os("UPDATE_WIRE_SNAPSHOTS").is_some(),
+///     );
+/// }
+/// ```
+pub fn assert_wire_matches_file<P: Protocol, T: AsRef<std::path::Path>>(
+    path: T,
+    exchanges: &[(P::Commands, Vec<u8>)],
+    update: bool,
+) {
+    let path = path.as_ref();
+    let frames = wire_frames::<P>(exchanges);
+    let found = frames.concat();
+    let expected = match std::fs::read_to_string(path) {
+        Ok(content) => Some(parse_snapshot(path, &content)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => panic!("wire snapshot {:?} could not be read: {}", path, err),
+    };
+    if expected.as_ref() == Some(&found) {
+        return;
+    }
+    if update {
+        let mut content = String::from("# wire snapshot: one frame per line, as hex bytes\n");
+        for frame in &frames {
+            content.push_str(&hex(frame));
+            content.push('\n');
+        }
+        if let Err(err) = std::fs::write(path, content) {
+            panic!("wire snapshot {:?} could not be written: {}", path, err);
+        }
+        return;
+    }
+    match expected {
+        None => panic!(
+            "wire snapshot {:?} does not exist (pass 'update' to create it)",
+            path
+        ),
+        Some(expected) => panic!(
+            "wire bytes differ from snapshot {:?} ({} bytes expected, {} bytes found)\n{}",
+            path,
+            expected.len(),
+            found.len(),
+            hex_diff(&expected, &found)
+        ),
+    }
+}
+
+fn wire_frames<P: Protocol>(exchanges: &[(P::Commands, Vec<u8>)]) -> Vec<Vec<u8>> {
+    exchanges
+        .iter()
+        .map(|(command, payload)| {
+            P::construct_message(*command, payload).unwrap_or_else(|| {
+                panic!(
+                    "frame could not be constructed for command {:?} with payload {}",
+                    command,
+                    format_bytes(payload)
+                )
+            })
+        })
+        .collect()
+}
+
+fn parse_snapshot(path: &std::path::Path, content: &str) -> Vec<u8> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(|byte| {
+            u8::from_str_radix(byte, 16).unwrap_or_else(|_| {
+                panic!(
+                    "wire snapshot {:?} contains an invalid byte: {:?}",
+                    path, byte
+                )
+            })
+        })
+        .collect()
+}
+
+/// Formats the lines of 16 bytes which differ, as expected (`-`) & found (`+`).
+fn hex_diff(expected: &[u8], found: &[u8]) -> String {
+    const ROW: usize = 16;
+    let rows = expected.len().max(found.len()).div_ceil(ROW);
+    let row = |bytes: &[u8], index: usize| {
+        let start = (index * ROW).min(bytes.len());
+        let end = ((index + 1) * ROW).min(bytes.len());
+        bytes[start..end].to_vec()
+    };
+    let mut diff = String::new();
+    for index in 0..rows {
+        let (expected_row, found_row) = (row(expected, index), row(found, index));
+        if expected_row != found_row {
+            diff.push_str(&format!("- {:08x}: {}\n", index * ROW, hex(&expected_row)));
+            diff.push_str(&format!("+ {:08x}: {}\n", index * ROW, hex(&found_row)));
+        }
+    }
+    diff
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats bytes as hex, eliding the middle of long payloads.
+fn format_bytes(bytes: &[u8]) -> String {
+    const SHOWN: usize = 32;
+    if bytes.len() <= 2 * SHOWN {
+        format!("[{}]", hex(bytes))
+    } else {
+        format!(
+            "[{} .. {}]",
+            hex(&bytes[..SHOWN]),
+            hex(&bytes[bytes.len() - SHOWN..])
+        )
+    }
+}
+
+fn describe_error<P: Protocol>(error: &ReadThreadErrors<P>) -> String {
+    match error {
+        ReadThreadErrors::WriteError(x) => format!("WriteError({:?})", x),
+        ReadThreadErrors::ReadError(x) => format!("ReadError({:?})", x),
+        ReadThreadErrors::RepeatedReadError(x) => {
+            format!("RepeatedReadError({:?}, {} times)", x.error, x.occurrences)
+        }
+        ReadThreadErrors::ImmediateMessageConstructError((command, payload)) => format!(
+            "ImmediateMessageConstructError(({:?}, {}{}))",
+            command,
+            format_bytes(&payload.bytes),
+            if payload.is_truncated() {
+                format!(" (truncated, {} bytes)", payload.original_length)
+            } else {
+                String::new()
+            }
+        ),
+        ReadThreadErrors::ProtocolViolation(x) => format!(
+            "ProtocolViolation({:?}, {})",
+            x.error,
+            format_bytes(&x.header)
+        ),
+        ReadThreadErrors::PeerClosed { mid_frame } => format!("PeerClosed({:?})", mid_frame),
+        ReadThreadErrors::Disconnected => "Disconnected".to_string(),
+        ReadThreadErrors::ConnectionClosed => "ConnectionClosed".to_string(),
+        ReadThreadErrors::Interrupted => "Interrupted".to_string(),
+        ReadThreadErrors::NoCommandGiven => "NoCommandGiven".to_string(),
+    }
+}
@@ -0,0 +1,227 @@
+use super::memory_budget::{self, SharedMemoryBudget};
+use super::protocol_buffer::Protocol;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// This configures a frame trace (see 'TcpIpcConfig::outgoing_trace' & 'TcpIpcConfig::incoming_trace').
+/// The memory of a trace is bounded by 'frames' times 'bytes_per_frame' (plus a fixed size per entry).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceConfig {
+    /// The number of most recent frames which are kept.
+    pub frames: usize,
+    /// The number of payload bytes kept per frame (from the start of the payload).
+    pub bytes_per_frame: usize,
+}
+
+/// A frame recorded by the outgoing trace, see 'TcpIpc::outgoing_trace'.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(bound = "P::Commands: serde::Serialize")
+)]
+pub struct TraceEntry<P: Protocol> {
+    /// The command of the frame.
+    pub command: P::Commands,
+    /// The length of the payload.
+    pub length: usize,
+    /// The time the frame was written (or queued for writing).
+    pub timestamp: std::time::SystemTime,
+    /// The first bytes of the payload, see 'TraceConfig::bytes_per_frame'.
+    pub bytes: Vec<u8>,
+}
+impl<P: Protocol> Clone for TraceEntry<P> {
+    fn clone(&self) -> Self {
+        Self {
+            command: self.command,
+            length: self.length,
+            timestamp: self.timestamp,
+            bytes: self.bytes.clone(),
+        }
+    }
+}
+impl<P: Protocol> PartialEq for TraceEntry<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.length == other.length
+            && self.timestamp == other.timestamp
+            && self.bytes == other.bytes
+    }
+}
+
+/// The payload bytes kept by a trace entry.
+pub trait TracedBytes {
+    fn bytes_mut(&mut self) -> &mut Vec<u8>;
+}
+impl<P: Protocol> TracedBytes for TraceEntry<P> {
+    fn bytes_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.bytes
+    }
+}
+
+/// A ring buffer of the most recent frames, shared between the read thread and the main thread.
+/// The kept payload bytes are reserved in the memory budget of the connection (if any).
+pub struct FrameTrace<E> {
+    config: TraceConfig,
+    entries: VecDeque<E>,
+    memory_budget: Option<SharedMemoryBudget>,
+}
+pub type SharedTrace<E> = Arc<Mutex<FrameTrace<E>>>;
+pub fn new_trace<E>(
+    config: TraceConfig,
+    memory_budget: Option<SharedMemoryBudget>,
+) -> SharedTrace<E> {
+    Arc::new(Mutex::new(FrameTrace {
+        config,
+        entries: VecDeque::with_capacity(config.frames),
+        memory_budget,
+    }))
+}
+impl<E: TracedBytes> FrameTrace<E> {
+    fn record(&mut self, mut entry: E) {
+        if self.config.frames == 0 {
+            return;
+        }
+        if self.entries.len() == self.config.frames {
+            if let Some(mut evicted) = self.entries.pop_front() {
+                memory_budget::release(&self.memory_budget, evicted.bytes_mut().len());
+            }
+        }
+        // if the memory budget is used up, the frame is still recorded, but without its bytes
+        let bytes = entry.bytes_mut();
+        if !memory_budget::try_reserve(&self.memory_budget, bytes.len()) {
+            *bytes = Vec::new();
+        }
+        self.entries.push_back(entry);
+    }
+}
+impl<E> FrameTrace<E> {
+    /// The start of a payload, as kept in the trace.
+    fn truncate(&self, payload: &[u8]) -> Vec<u8> {
+        payload[..payload.len().min(self.config.bytes_per_frame)].to_vec()
+    }
+}
+/// Returns the recorded frames, oldest first. This is empty if the trace is disabled.
+pub fn trace_entries<E: Clone>(trace: &Option<SharedTrace<E>>) -> Vec<E> {
+    match trace {
+        Some(trace) => trace
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entries
+            .iter()
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    }
+}
+/// Records a sent frame, if the outgoing trace is enabled.
+pub fn trace_sent<P: Protocol>(
+    trace: &Option<SharedTrace<TraceEntry<P>>>,
+    command: P::Commands,
+    payload: &[u8],
+) {
+    if let Some(trace) = trace {
+        let mut trace = trace.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = TraceEntry {
+            command,
+            length: payload.len(),
+            timestamp: std::time::SystemTime::now(),
+            bytes: trace.truncate(payload),
+        };
+        trace.record(entry);
+    }
+}
+
+/// What happened to a received frame, as recorded by the incoming trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FrameDisposition {
+    /// The frame was forwarded to the main thread (see 'TcpIpc::get_message').
+    Delivered,
+    /// The frame was answered via the immediate route.
+    AnsweredImmediately,
+    /// The frame was to be answered via the immediate route, but the response could not be constructed.
+    Failed,
+    /// The frame was dropped as a duplicate (see 'TcpIpcConfig::dedup_window' & 'ReliabilityConfig::dedup_window'), or since the main thread is gone.
+    Dropped,
+    /// The frame acknowledged a reliable message (see 'TcpIpc::write_message_reliable').
+    Acknowledgment,
+    /// The frame carried the session token of a client (see 'TcpIpc::session_token').
+    SessionToken,
+}
+
+/// A frame recorded by the incoming trace, see 'TcpIpc::incoming_trace'.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(bound = "P::Commands: serde::Serialize")
+)]
+pub struct IncomingTraceEntry<P: Protocol> {
+    /// The command of the frame.
+    pub command: P::Commands,
+    /// The length of the payload, as declared by the header.
+    pub length: usize,
+    /// The time the frame was parsed.
+    pub timestamp: std::time::SystemTime,
+    /// What happened to the frame.
+    pub disposition: FrameDisposition,
+    /// The first bytes of the payload, see 'TraceConfig::bytes_per_frame'.
+    pub bytes: Vec<u8>,
+}
+impl<P: Protocol> Clone for IncomingTraceEntry<P> {
+    fn clone(&self) -> Self {
+        Self {
+            command: self.command,
+            length: self.length,
+            timestamp: self.timestamp,
+            disposition: self.disposition,
+            bytes: self.bytes.clone(),
+        }
+    }
+}
+impl<P: Protocol> TracedBytes for IncomingTraceEntry<P> {
+    fn bytes_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.bytes
+    }
+}
+impl<P: Protocol> PartialEq for IncomingTraceEntry<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.length == other.length
+            && self.timestamp == other.timestamp
+            && self.disposition == other.disposition
+            && self.bytes == other.bytes
+    }
+}
+/// The start of a payload as kept by the trace, taken before the payload is handed on. This is empty if the trace is disabled.
+pub fn trace_start<E>(trace: &Option<SharedTrace<E>>, payload: &[u8]) -> Vec<u8> {
+    match trace {
+        Some(trace) => trace
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .truncate(payload),
+        None => Vec::new(),
+    }
+}
+/// Records a received frame, if the incoming trace is enabled.
+pub fn trace_received<P: Protocol>(
+    trace: &Option<SharedTrace<IncomingTraceEntry<P>>>,
+    command: P::Commands,
+    length: usize,
+    bytes: Vec<u8>,
+    disposition: FrameDisposition,
+) {
+    if let Some(trace) = trace {
+        trace
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(IncomingTraceEntry {
+                command,
+                length,
+                timestamp: std::time::SystemTime::now(),
+                disposition,
+                bytes,
+            });
+    }
+}
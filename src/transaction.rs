@@ -0,0 +1,89 @@
+use super::protocol_buffer::Protocol;
+use super::tcp_ipc::{ReadThreadErrors, TcpIpc, WriteMessageErrors};
+
+/// This is returned by 'TcpIpc::transaction', to exchange requests & responses without mistaking stale frames for responses.
+///
+/// A response is only accepted if it was parsed after its request was sent (according to the sequence numbers of the messages).
+/// Older frames of the response command (for example a late response to a request which timed out before) are not consumed:
+/// together with all other messages received meanwhile, they stay queued in order & are delivered by 'get_message' after the transaction.
+pub struct TransactionGuard<'a, P: Protocol> {
+    tcp_ipc: &'a mut TcpIpc<P>,
+}
+
+/// The error type for a request sent within a transaction.
+#[derive(Debug)]
+pub enum TransactionErrors<P: Protocol> {
+    /// The request could not be written.
+    WriteError(WriteMessageErrors),
+    /// An error was received while waiting for the response (or the wait was interrupted, see 'AwaitWaker').
+    ReadError(ReadThreadErrors<P>),
+}
+
+impl<'a, P: Protocol> std::fmt::Debug for TransactionGuard<'a, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TransactionGuard")
+            .field("tcp_ipc", &self.tcp_ipc)
+            .finish()
+    }
+}
+impl<'a, P: Protocol> TransactionGuard<'a, P> {
+    pub(crate) fn new(tcp_ipc: &'a mut TcpIpc<P>) -> Self {
+        Self { tcp_ipc }
+    }
+    /// This sends a request & waits for the first response with the 'expected' command, which was parsed after the request was sent.
+    /// If no response is received during the given time, Ok(None) is returned. Otherwise, the payload of the response is returned.
+    /// Between checks for the response, the read iteration wait time of the connection's config is spent waiting.
+    /// The latency of the exchange (or its timeout) is counted in the connection's stats (see 'ConnectionStats::exchange_latencies').
+    /// # Example
+    /// ```no_run
+    /// # use rust_tcp_ipc::*;
+    /// # use rust_tcp_ipc::doc_example::*;
+    /// # run(|| {
+    /// # let mut client = client();
+    /// let mut transaction = client.transaction();
+    /// let status = transaction.send_and_wait(
+    ///     CommandsExample::GetStatus,
+    ///     &[],
+    ///     CommandsExample::Status,
+    ///     std::time::Duration::from_millis(100),
+    /// )?;
+    /// # Ok(())
+    /// # });
+    /// ```
+    pub fn send_and_wait(
+        &mut self,
+        command: P::Commands,
+        payload: &[u8],
+        expected: P::Commands,
+        timeout: std::time::Duration,
+    ) -> Result<Option<Vec<u8>>, TransactionErrors<P>> {
+        let first_sequence = self.tcp_ipc.synchronize_with_read_thread();
+        let wakes = self.tcp_ipc.wakes();
+        let instant = std::time::Instant::now();
+        self.tcp_ipc
+            .write_message(command, payload)
+            .map_err(TransactionErrors::WriteError)?;
+        let iteration_wait_time = self.tcp_ipc.read_iteration_wait_time();
+        loop {
+            match self.tcp_ipc.take_first_matching(|sequence, command| {
+                sequence >= first_sequence && *command == expected
+            }) {
+                Ok(Some((_, payload))) => {
+                    self.tcp_ipc
+                        .exchange_finished(command, Some(instant.elapsed()));
+                    return Ok(Some(payload));
+                }
+                Ok(None) => {}
+                Err(err) => return Err(TransactionErrors::ReadError(err)),
+            }
+            if instant.elapsed() >= timeout {
+                self.tcp_ipc.exchange_finished(command, None);
+                return Ok(None);
+            }
+            if let Err(err) = self.tcp_ipc.pause(wakes, iteration_wait_time) {
+                self.tcp_ipc.exchange_finished(command, None);
+                return Err(TransactionErrors::ReadError(err));
+            }
+        }
+    }
+}
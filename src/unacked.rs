@@ -0,0 +1,168 @@
+use super::delivery::MessageMetadata;
+use super::memory_budget::{self, SharedMemoryBudget};
+use super::protocol_buffer::{Message, Protocol};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+struct InFlightEntry<P: Protocol> {
+    message: Arc<Message<P>>,
+    metadata: MessageMetadata,
+    // the time after which the message is redelivered, even if its guard is still alive
+    visible_at: Option<std::time::Instant>,
+    // set once the guard was dropped without acknowledging
+    released: bool,
+}
+
+/// The messages taken via 'TcpIpc::get_message_unacked', which are not yet acknowledged.
+/// This is shared by the connection & the guards of the messages.
+/// The payloads stay reserved in the memory budget of the connection (if any) until they are acknowledged.
+pub struct InFlight<P: Protocol> {
+    // keyed by the delivery token, so the oldest delivery comes first
+    entries: BTreeMap<u64, InFlightEntry<P>>,
+    next_token: u64,
+    memory_budget: Option<SharedMemoryBudget>,
+}
+pub type SharedInFlight<P> = Arc<Mutex<InFlight<P>>>;
+pub fn new_in_flight<P: Protocol>(memory_budget: Option<SharedMemoryBudget>) -> SharedInFlight<P> {
+    Arc::new(Mutex::new(InFlight {
+        entries: BTreeMap::new(),
+        next_token: 0,
+        memory_budget,
+    }))
+}
+fn lock<P: Protocol>(in_flight: &SharedInFlight<P>) -> MutexGuard<'_, InFlight<P>> {
+    in_flight.lock().unwrap_or_else(|e| e.into_inner())
+}
+impl<P: Protocol> InFlight<P> {
+    fn insert(
+        &mut self,
+        message: Arc<Message<P>>,
+        metadata: MessageMetadata,
+        visibility_timeout: Option<std::time::Duration>,
+    ) -> u64 {
+        let token = self.next_token;
+        self.next_token += 1;
+        self.entries.insert(
+            token,
+            InFlightEntry {
+                message,
+                metadata,
+                visible_at: visibility_timeout.map(|timeout| std::time::Instant::now() + timeout),
+                released: false,
+            },
+        );
+        token
+    }
+}
+
+/// Delivers a new message, which stays in flight until it is acknowledged.
+pub fn deliver<P: Protocol>(
+    in_flight: &SharedInFlight<P>,
+    message: Message<P>,
+    metadata: MessageMetadata,
+    visibility_timeout: Option<std::time::Duration>,
+) -> UnackedMessage<P> {
+    let message = Arc::new(message);
+    let token = lock(in_flight).insert(message.clone(), metadata, visibility_timeout);
+    UnackedMessage {
+        message,
+        metadata,
+        token,
+        in_flight: in_flight.clone(),
+        acked: false,
+    }
+}
+/// Delivers the oldest message whose guard was dropped without acknowledging (or whose visibility timeout passed) again, if any.
+pub fn redeliver<P: Protocol>(
+    in_flight: &SharedInFlight<P>,
+    visibility_timeout: Option<std::time::Duration>,
+) -> Option<UnackedMessage<P>> {
+    let now = std::time::Instant::now();
+    let mut locked = lock(in_flight);
+    let token = locked
+        .entries
+        .iter()
+        .find(|(_, entry)| entry.released || entry.visible_at.is_some_and(|at| at <= now))
+        .map(|(token, _)| *token)?;
+    let entry = locked.entries.remove(&token)?;
+    let metadata = MessageMetadata {
+        redeliveries: entry.metadata.redeliveries + 1,
+        ..entry.metadata
+    };
+    // the new token invalidates the guard of the previous delivery
+    let token = locked.insert(entry.message.clone(), metadata, visibility_timeout);
+    drop(locked);
+    Some(UnackedMessage {
+        message: entry.message,
+        metadata,
+        token,
+        in_flight: in_flight.clone(),
+        acked: false,
+    })
+}
+/// Returns the number of messages which are taken, but not yet acknowledged.
+pub fn in_flight_count<P: Protocol>(in_flight: &SharedInFlight<P>) -> usize {
+    lock(in_flight).entries.len()
+}
+
+/// A message taken via 'TcpIpc::get_message_unacked', which is delivered again unless it is acknowledged via 'ack'.
+///
+/// If the guard is dropped without acknowledging (for example, since processing panicked), the message is redelivered by the next call of 'get_message_unacked'.
+/// The same holds once the visibility timeout passed (see 'TcpIpcConfig::ack_visibility_timeout'), even if the guard is still alive.
+pub struct UnackedMessage<P: Protocol> {
+    message: Arc<Message<P>>,
+    metadata: MessageMetadata,
+    token: u64,
+    in_flight: SharedInFlight<P>,
+    acked: bool,
+}
+impl<P: Protocol> std::fmt::Debug for UnackedMessage<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("UnackedMessage")
+            .field("command", &self.message.0)
+            .field("length", &self.message.1.len())
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+impl<P: Protocol> UnackedMessage<P> {
+    /// The message itself.
+    pub fn message(&self) -> &Message<P> {
+        &self.message
+    }
+    /// The command of the message.
+    pub fn command(&self) -> P::Commands {
+        self.message.0
+    }
+    /// The payload of the message.
+    pub fn payload(&self) -> &[u8] {
+        &self.message.1
+    }
+    /// The metadata of the message, including the number of previous deliveries ('MessageMetadata::redeliveries').
+    pub fn metadata(&self) -> MessageMetadata {
+        self.metadata
+    }
+    /// Acknowledges the message, so it is never delivered again.
+    /// Returns false if this delivery was outdated, since the visibility timeout passed & the message was delivered again meanwhile.
+    /// In this case, the newer delivery has to be acknowledged.
+    pub fn ack(mut self) -> bool {
+        self.acked = true;
+        let mut in_flight = lock(&self.in_flight);
+        match in_flight.entries.remove(&self.token) {
+            Some(entry) => {
+                memory_budget::release(&in_flight.memory_budget, entry.message.1.len());
+                true
+            }
+            None => false,
+        }
+    }
+}
+impl<P: Protocol> Drop for UnackedMessage<P> {
+    fn drop(&mut self) {
+        if !self.acked {
+            if let Some(entry) = lock(&self.in_flight).entries.get_mut(&self.token) {
+                entry.released = true;
+            }
+        }
+    }
+}
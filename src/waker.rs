@@ -0,0 +1,51 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// The signal shared by a connection & its wakers, counting the wakes so far.
+#[derive(Debug, Default)]
+pub struct WakeSignal {
+    wakes: Mutex<u64>,
+    condvar: Condvar,
+}
+pub type SharedWakeSignal = Arc<WakeSignal>;
+impl WakeSignal {
+    /// Returns the number of wakes so far, which an await compares against to detect a wake.
+    pub fn wakes(&self) -> u64 {
+        *self.wakes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Sleeps for the given duration, unless a wake happened since 'wakes' returned the given count.
+    /// Returns true if the sleep was interrupted by a wake.
+    pub fn sleep(&self, since: u64, duration: Option<Duration>) -> bool {
+        let wakes = self.wakes.lock().unwrap_or_else(|e| e.into_inner());
+        match duration {
+            Some(duration) => {
+                let (wakes, _) = self
+                    .condvar
+                    .wait_timeout_while(wakes, duration, |wakes| *wakes == since)
+                    .unwrap_or_else(|e| e.into_inner());
+                *wakes != since
+            }
+            None => *wakes != since,
+        }
+    }
+}
+
+/// This interrupts the awaits of a connection from another thread, see 'TcpIpc::waker'.
+///
+/// A wake interrupts the awaits which are in progress when it happens ('TcpIpc::await_message', 'TcpIpc::await_any_command',
+/// 'TcpIpc::next_with_context' & 'TransactionGuard::send_and_wait'), which return 'ReadThreadErrors::Interrupted'.
+/// A wake while no await is in progress has no effect, so it does not interrupt a later await.
+#[derive(Debug, Clone)]
+pub struct AwaitWaker {
+    signal: SharedWakeSignal,
+}
+impl AwaitWaker {
+    pub(crate) fn new(signal: SharedWakeSignal) -> Self {
+        Self { signal }
+    }
+    /// Interrupts the awaits in progress on the connection.
+    pub fn wake(&self) {
+        *self.signal.wakes.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.signal.condvar.notify_all();
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+/// The state of the outgoing path of a connection, see 'TcpIpc::write_pressure'.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WritePressure {
+    /// The number of frames waiting to be written, since the socket did not accept them yet.
+    pub queued_frames: usize,
+    /// The number of unwritten bytes of these frames.
+    pub queued_bytes: usize,
+    /// Indicates that a message written now would have to wait (i.e. the socket's send buffer is full).
+    pub would_block_now: bool,
+}
+
+/// The level reported to the watermark callback, see 'WritePressureWatermarks'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WritePressureLevel {
+    /// The queued bytes reached the high watermark. Producers should pause.
+    High,
+    /// The queued bytes dropped to the low watermark (after the high watermark was reached). Producers can resume.
+    Low,
+}
+
+/// This configures a callback, which is called when the queued outgoing bytes cross the given thresholds (see 'TcpIpcConfig::write_pressure_watermarks').
+///
+/// The callback is called by whichever thread changed the queue (the main thread or the read thread), while the queue is locked.
+/// So it has to return quickly and must not write to the connection; typically it sets a flag or notifies the producer.
+pub struct WritePressureWatermarks {
+    /// The number of queued bytes at which 'WritePressureLevel::High' is reported.
+    pub high: usize,
+    /// The number of queued bytes at which 'WritePressureLevel::Low' is reported. This has to be below 'high'.
+    pub low: usize,
+    /// The callback.
+    pub on_write_pressure: Arc<dyn Fn(WritePressureLevel) + Send + Sync>,
+}
+impl Clone for WritePressureWatermarks {
+    fn clone(&self) -> Self {
+        Self {
+            high: self.high,
+            low: self.low,
+            on_write_pressure: self.on_write_pressure.clone(),
+        }
+    }
+}
+impl std::fmt::Debug for WritePressureWatermarks {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("WritePressureWatermarks")
+            .field("high", &self.high)
+            .field("low", &self.low)
+            .field("on_write_pressure", &"<hook>")
+            .finish()
+    }
+}
+impl PartialEq for WritePressureWatermarks {
+    fn eq(&self, other: &Self) -> bool {
+        self.high == other.high
+            && self.low == other.low
+            && Arc::ptr_eq(&self.on_write_pressure, &other.on_write_pressure)
+    }
+}
+
+/// Tracks the queued bytes against the watermarks, reporting each crossing once.
+#[derive(Debug)]
+pub struct WatermarkTracker {
+    watermarks: WritePressureWatermarks,
+    high: bool,
+}
+impl WatermarkTracker {
+    pub fn new(watermarks: WritePressureWatermarks) -> Self {
+        Self {
+            watermarks,
+            high: false,
+        }
+    }
+    pub fn update(&mut self, queued_bytes: usize) {
+        if !self.high && queued_bytes >= self.watermarks.high {
+            self.high = true;
+            (self.watermarks.on_write_pressure)(WritePressureLevel::High);
+        } else if self.high && queued_bytes <= self.watermarks.low {
+            self.high = false;
+            (self.watermarks.on_write_pressure)(WritePressureLevel::Low);
+        }
+    }
+}
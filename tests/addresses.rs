@@ -0,0 +1,50 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+#[test]
+fn both_endpoints_know_the_addresses_of_the_connection() {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = std::thread::spawn(move || {
+        TcpIpc::<TestProtocol>::client(address, config(), Some(TIMEOUT)).unwrap()
+    });
+    let server = listener.accept(config()).unwrap();
+    let client = client.join().unwrap();
+
+    assert_eq!(client.peer_addr().unwrap(), address);
+    assert_eq!(server.local_addr().unwrap(), address);
+    // the client got an ephemeral port, which the server sees as peer
+    let client_address = client.local_addr().unwrap();
+    assert_ne!(client_address.port(), 0);
+    assert_eq!(server.peer_addr().unwrap(), client_address);
+}
+
+#[test]
+fn the_peer_address_is_kept_after_closing() {
+    let (mut server, client) = pair();
+    let client_address = client.local_addr().unwrap();
+    drop(client);
+    expect_closed(&mut server);
+    assert_eq!(server.peer_addr().unwrap(), client_address);
+}
+
+#[test]
+fn the_ttl_can_be_changed() {
+    let (mut server, client) = pair();
+    server.set_ttl(17).unwrap();
+    assert_eq!(server.ttl().unwrap(), 17);
+    assert_ne!(client.ttl().unwrap(), 0);
+
+    let (mut server, client) = pair();
+    drop(client);
+    expect_closed(&mut server);
+    assert_eq!(
+        server.ttl().unwrap_err().kind(),
+        std::io::ErrorKind::NotConnected
+    );
+    assert_eq!(
+        server.set_ttl(17).unwrap_err().kind(),
+        std::io::ErrorKind::NotConnected
+    );
+}
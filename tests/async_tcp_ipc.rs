@@ -0,0 +1,117 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::future::Future;
+
+// runs the future on a runtime of its own
+fn run<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+// connects an async client to a threaded server (the server is returned first)
+async fn async_pair() -> (TcpIpc<TestProtocol>, AsyncTcpIpc<TestProtocol>) {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || listener.accept(config()).unwrap());
+    let client = AsyncTcpIpc::<TestProtocol>::connect(address, config())
+        .await
+        .unwrap();
+    (server.join().unwrap(), client)
+}
+
+#[test]
+fn messages_flow_both_ways() {
+    run(async {
+        let (mut server, mut client) = async_pair().await;
+        client.write_message(DATA, b"from async").await.unwrap();
+        expect_payload(&mut server, DATA, b"from async", TIMEOUT);
+        assert_eq!(client.try_next_message().unwrap(), None);
+
+        for i in 0..100u8 {
+            server.write_message(DATA, &[i]).unwrap();
+        }
+        for i in 0..100u8 {
+            assert_eq!(client.next_message().await.unwrap(), (DATA, vec![i]));
+        }
+    });
+}
+
+// awaits the reply of the client on a blocking thread, so the tasks of the client keep running
+async fn expect_reply(
+    mut server: TcpIpc<TestProtocol>,
+    payload: &'static [u8],
+) -> TcpIpc<TestProtocol> {
+    tokio::task::spawn_blocking(move || {
+        expect_payload(&mut server, REPLY, payload, TIMEOUT);
+        server
+    })
+    .await
+    .unwrap()
+}
+
+#[test]
+fn queries_are_answered_by_the_read_task() {
+    run(async {
+        let (mut server, mut client) = async_pair().await;
+        server.write_message(QUERY, b"ping").unwrap();
+        let mut server = expect_reply(server, b"ping").await;
+        // the answered query is not delivered
+        assert_eq!(client.try_next_message().unwrap(), None);
+
+        client.update_busy_state(3);
+        assert_eq!(client.busy_state(), 3);
+        server.write_message(QUERY, b"busy").unwrap();
+        let mut server = expect_reply(server, b"busy").await;
+
+        // a query does not overtake the messages written before
+        server.write_message(DATA, b"first").unwrap();
+        server.write_message(QUERY, b"second").unwrap();
+        assert_eq!(
+            client.next_message().await.unwrap(),
+            (DATA, b"first".to_vec())
+        );
+        expect_reply(server, b"second").await;
+    });
+}
+
+#[test]
+fn a_shutdown_says_goodbye_and_closes_the_stream() {
+    run(async {
+        let (mut server, client) = async_pair().await;
+        client.write_message(DATA, b"last").await.unwrap();
+        client.shutdown().await.unwrap();
+        expect_payload(&mut server, DATA, b"last", TIMEOUT);
+        expect_closed(&mut server);
+    });
+}
+
+#[test]
+fn a_closing_peer_ends_the_messages() {
+    run(async {
+        let (mut server, mut client) = async_pair().await;
+        server.write_message(DATA, b"before").unwrap();
+        server.shutdown().expect("shutdown was not clean");
+        assert_eq!(
+            client.next_message().await.unwrap(),
+            (DATA, b"before".to_vec())
+        );
+        // the peer closing is reported once, then the connection is closed for good
+        let closing = tokio::time::timeout(TIMEOUT, client.next_message())
+            .await
+            .unwrap();
+        assert!(
+            matches!(closing, Err(ReadThreadErrors::PeerClosed { .. })),
+            "{:?}",
+            closing
+        );
+        assert!(matches!(
+            client.next_message().await,
+            Err(ReadThreadErrors::ConnectionClosed)
+        ));
+    });
+}
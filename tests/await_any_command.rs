@@ -0,0 +1,78 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+/// The positive reply of the tests.
+const ACCEPTED: u8 = 0x10;
+/// The negative reply of the tests.
+const REJECTED: u8 = 0x11;
+
+#[test]
+fn the_first_of_the_awaited_commands_is_returned() {
+    for reply in &[ACCEPTED, REJECTED] {
+        let (mut server, mut client) = pair();
+        server.write_message(*reply, b"reply").unwrap();
+        let (command, payload) = client
+            .await_any_command(&[ACCEPTED, REJECTED], TIMEOUT)
+            .unwrap()
+            .expect("no reply");
+        assert_eq!((command, &payload[..]), (*reply, &b"reply"[..]));
+    }
+}
+
+#[test]
+fn other_messages_stay_queued_in_order() {
+    let (mut server, mut client) = pair();
+    server
+        .write_messages(&[
+            (DATA, b"first"),
+            (DATA, b"second"),
+            (REJECTED, b"reply"),
+            (ACCEPTED, b"later"),
+        ])
+        .unwrap();
+    // a command listed twice is fine
+    let (command, _) = client
+        .await_any_command(&[ACCEPTED, REJECTED, ACCEPTED], TIMEOUT)
+        .unwrap()
+        .expect("no reply");
+    assert_eq!(command, REJECTED);
+    expect_payload(&mut client, DATA, b"first", TIMEOUT);
+    expect_payload(&mut client, DATA, b"second", TIMEOUT);
+    expect_payload(&mut client, ACCEPTED, b"later", TIMEOUT);
+}
+
+#[test]
+fn nothing_is_returned_after_the_wait_time() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"unrelated").unwrap();
+    let wait = Duration::from_millis(50);
+    let start = Instant::now();
+    assert!(client
+        .await_any_command(&[ACCEPTED, REJECTED], wait)
+        .unwrap()
+        .is_none());
+    assert!(start.elapsed() >= wait);
+    expect_payload(&mut client, DATA, b"unrelated", TIMEOUT);
+}
+
+#[test]
+fn awaiting_no_command_is_an_error() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"unrelated").unwrap();
+    let start = Instant::now();
+    assert!(matches!(
+        client.await_any_command(&[], TIMEOUT),
+        Err(ReadThreadErrors::NoCommandGiven)
+    ));
+    let mut budget = DeadlineBudget::new(TIMEOUT);
+    assert!(matches!(
+        client.await_command_budgeted(&[], &mut budget).result,
+        Err(ReadThreadErrors::NoCommandGiven)
+    ));
+    assert!(start.elapsed() < TIMEOUT);
+    // the connection is not affected
+    expect_payload(&mut client, DATA, b"unrelated", TIMEOUT);
+}
@@ -0,0 +1,112 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// The identification a server sends right after accepting.
+const BANNER: u8 = 0xBA;
+
+/// The test protocol, with a banner.
+#[derive(Debug)]
+enum BannerProtocol {}
+impl Protocol for BannerProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn is_banner(command: &u8) -> bool {
+        *command == BANNER
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+// connects a client to a plain TCP server, which writes the given bytes right after accepting
+fn connect(
+    banner_wait_time: Option<Duration>,
+    greeting: Vec<u8>,
+) -> (TcpIpc<BannerProtocol>, std::net::TcpStream) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&greeting).unwrap();
+        stream
+    });
+    let client_config = TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        banner_wait_time,
+        ..TcpIpcConfig::default()
+    };
+    let client = TcpIpc::<BannerProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    (client, server.join().unwrap())
+}
+
+#[test]
+fn the_banner_is_kept_apart_from_the_messages() {
+    let mut greeting = frame(BANNER, b"fw 1.0");
+    greeting.extend(frame(DATA, b"first message"));
+    let start = Instant::now();
+    let (mut client, mut server) = connect(Some(TIMEOUT), greeting);
+    // the client returns as soon as the banner arrived
+    assert!(start.elapsed() < TIMEOUT);
+    assert_eq!(client.banner(), Some((BANNER, b"fw 1.0".to_vec())));
+    expect_payload(&mut client, DATA, b"first message", TIMEOUT);
+
+    // a later banner is a normal message
+    server.write_all(&frame(BANNER, b"fw 2.0")).unwrap();
+    expect_payload(&mut client, BANNER, b"fw 2.0", TIMEOUT);
+    assert_eq!(client.banner(), Some((BANNER, b"fw 1.0".to_vec())));
+}
+
+#[test]
+fn without_a_banner_the_client_returns_after_the_wait() {
+    let wait = Duration::from_millis(100);
+    let start = Instant::now();
+    let (mut client, _server) = connect(Some(wait), frame(DATA, b"no banner"));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= wait, "returned after {:?}", elapsed);
+    assert!(elapsed < TIMEOUT, "returned after {:?}", elapsed);
+    assert_eq!(client.banner(), None);
+    expect_payload(&mut client, DATA, b"no banner", TIMEOUT);
+}
+
+#[test]
+fn without_a_banner_wait_time_nothing_blocks() {
+    let start = Instant::now();
+    let (client, _server) = connect(None, Vec::new());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(client.banner(), None);
+}
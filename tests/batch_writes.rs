@@ -0,0 +1,95 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::Read;
+use std::time::Duration;
+
+#[test]
+fn batched_frames_arrive_intact() {
+    let (mut server, mut client) = pair();
+    let payloads: Vec<Vec<u8>> = (0..1000u32)
+        .map(|i| i.to_be_bytes().repeat(i as usize % 7))
+        .collect();
+    let messages: Vec<(u8, &[u8])> = payloads
+        .iter()
+        .map(|payload| (DATA, &payload[..]))
+        .collect();
+    assert_eq!(client.write_messages(&messages).unwrap(), 1000);
+    for payload in &payloads {
+        expect_payload(&mut server, DATA, payload, TIMEOUT);
+    }
+    assert_eq!(client.stats().messages_sent, 1000);
+}
+
+#[test]
+fn batches_are_retried_as_configured() {
+    let server_config = TcpIpcConfig {
+        write_retry: Some(RetrySpec {
+            max_duration: TIMEOUT,
+            backoff: Duration::from_millis(1),
+        }),
+        ..config()
+    };
+    let (mut server, mut peer) = raw_peer_with(server_config);
+    // more than the socket buffers take, so the batch only completes once the peer reads
+    let payload = vec![7; 1 << 14];
+    let messages = vec![(DATA, &payload[..]); 256];
+    let expected = frame(DATA, &payload).repeat(messages.len());
+    let reader = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        let mut received = vec![0; expected.len()];
+        peer.read_exact(&mut received).unwrap();
+        assert!(received == expected);
+    });
+    assert_eq!(server.write_messages(&messages).unwrap(), messages.len());
+    // the batch was written completely by the call itself, nothing was left for the read thread
+    assert_eq!(server.write_pressure().queued_frames, 0);
+    reader.join().unwrap();
+}
+
+// returns a client whose connection was lost, & the listener it reconnects to
+fn lost_client(queue_limit: usize) -> (TcpIpc<TestProtocol>, std::net::TcpListener) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client_config = TcpIpcConfig {
+        reconnect: Some(ReconnectPolicy {
+            initial_delay: Duration::from_millis(200),
+            queue_limit,
+            ..ReconnectPolicy::default()
+        }),
+        ..config()
+    };
+    let mut client = TcpIpc::<TestProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    let (lost, _) = listener.accept().unwrap();
+    drop(lost);
+    await_condition(|| client.next_event() == Some(ConnectionEvent::Lost));
+    (client, listener)
+}
+
+#[test]
+fn batches_are_queued_while_reconnecting() {
+    let (mut client, listener) = lost_client(1 << 10);
+    assert_eq!(
+        client
+            .write_messages(&[(DATA, b"first"), (DATA, b"second")])
+            .unwrap(),
+        2
+    );
+    let (mut peer, _) = listener.accept().unwrap();
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let expected = [frame(DATA, b"first"), frame(DATA, b"second")].concat();
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn batches_beyond_the_reconnect_queue_are_not_sent() {
+    let (mut client, _listener) = lost_client(16);
+    assert!(matches!(
+        client.write_messages(&[(DATA, b"first"), (DATA, b"second")]),
+        Err(BatchWriteErrors::NotConnected)
+    ));
+    assert!(client.write_pressure().queued_frames == 0);
+}
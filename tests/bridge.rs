@@ -0,0 +1,84 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::Duration;
+
+/// A command which is forwarded unchanged.
+const PLAIN: u8 = 7;
+
+// device <-> (a) bridge (b) <-> controller, the device data is rewritten towards the controller & errors are dropped
+fn bridged(
+    propagate_disconnect: bool,
+) -> (
+    TcpIpc<TestProtocol>,
+    BridgeHandle<TestProtocol>,
+    TcpIpc<TestProtocol>,
+) {
+    let (a, device) = pair();
+    let (b, controller) = pair();
+    let handle = bridge(
+        a,
+        b,
+        propagate_disconnect,
+        |direction, command, payload| match (direction, *command) {
+            (Direction::AToB, DATA) => BridgeAction::Rewrite(URGENT, payload.to_ascii_uppercase()),
+            (_, ERROR) => BridgeAction::Drop,
+            _ => BridgeAction::Forward,
+        },
+    )
+    .unwrap();
+    (device, handle, controller)
+}
+
+#[test]
+fn messages_are_forwarded_rewritten_and_dropped() {
+    let (mut device, handle, mut controller) = bridged(true);
+    device.write_message(DATA, b"measurement").unwrap();
+    device.write_message(ERROR, b"internal").unwrap();
+    device.write_message(PLAIN, b"plain").unwrap();
+    expect_payload(&mut controller, URGENT, b"MEASUREMENT", TIMEOUT);
+    expect_payload(&mut controller, PLAIN, b"plain", TIMEOUT);
+
+    controller.write_message(DATA, b"command").unwrap();
+    expect_payload(&mut device, DATA, b"command", TIMEOUT);
+    expect_silence(&mut controller, Duration::from_millis(50));
+
+    let stats = handle.stats();
+    assert_eq!(
+        stats.a_to_b,
+        DirectionStats {
+            forwarded: 1,
+            dropped: 1,
+            rewritten: 1,
+            failed: 0,
+        }
+    );
+    assert_eq!(stats.b_to_a.forwarded, 1);
+
+    assert!(handle.shutdown().is_ok());
+    expect_closed(&mut device);
+    expect_closed(&mut controller);
+}
+
+#[test]
+fn a_disconnect_is_propagated_to_the_other_side() {
+    let (mut device, handle, controller) = bridged(true);
+    controller.shutdown().expect("shutdown was not clean");
+    expect_closed(&mut device);
+    assert!(matches!(handle.join(), BridgeEnd::ShutDown(_)));
+}
+
+#[test]
+fn without_propagation_both_connections_are_handed_back() {
+    let (mut device, handle, controller) = bridged(false);
+    controller.shutdown().expect("shutdown was not clean");
+    let (mut a, b) = match handle.join() {
+        BridgeEnd::Closed(connections) => *connections,
+        _ => panic!("the bridge shut down the connections"),
+    };
+    assert!(b.is_connection_closed());
+    // the device side stays usable
+    a.write_message(DATA, b"still open").unwrap();
+    expect_payload(&mut device, DATA, b"still open", TIMEOUT);
+}
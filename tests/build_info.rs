@@ -0,0 +1,64 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+// the features of the test run, as seen by the tests (the crate is built with the same features)
+fn enabled_features() -> Vec<&'static str> {
+    let features = [
+        ("bench", cfg!(feature = "bench")),
+        ("engine-mio", cfg!(feature = "engine-mio")),
+        ("engine-std", cfg!(feature = "engine-std")),
+        ("registry", cfg!(feature = "registry")),
+        ("serde", cfg!(feature = "serde")),
+        ("std", cfg!(feature = "std")),
+        ("test-util", cfg!(feature = "test-util")),
+        ("tokio", cfg!(feature = "tokio")),
+    ];
+    features
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| *feature)
+        .collect()
+}
+
+// run with the default features & with `--no-default-features --features engine-std,test-util`
+#[test]
+fn the_enabled_features_are_reported() {
+    let info = build_info();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.features, enabled_features().as_slice());
+    assert!(info.features.contains(&"std") && info.features.contains(&"test-util"));
+    if cfg!(feature = "engine-mio") {
+        assert_eq!(info.engine, "mio");
+        assert!(info.features.contains(&"engine-mio"));
+    } else {
+        assert_eq!(info.engine, "std");
+        assert!(!info.features.contains(&"engine-mio"));
+    }
+    assert_eq!(info.debug_assertions, cfg!(debug_assertions));
+}
+
+#[test]
+fn the_capability_report_resolves_the_config() {
+    let (mut server, _client) = pair_with(
+        TcpIpcConfig {
+            name: Some("plc".to_string()),
+            read_error_limit: None,
+            ..config()
+        },
+        config(),
+    );
+    let report = server.capability_report();
+    assert_eq!(report.build, build_info());
+    assert_eq!(report.protocol.header_size, 9);
+    assert_eq!(report.protocol.command_size, 1);
+    assert_eq!(report.protocol.length_size, 8);
+    assert!(report.protocol.ack_command);
+    assert!(report.protocol.protocol.ends_with("TestProtocol"));
+    assert_eq!(report.config.name.as_deref(), Some("plc"));
+    assert_eq!(report.config.read_error_limit, None);
+    // the default is resolved
+    assert_eq!(report.config.verify_frames, cfg!(debug_assertions));
+    assert!(!report.config.journal);
+    assert_eq!(server.diagnostics().capabilities, report);
+}
@@ -0,0 +1,136 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::sync::{Mutex, Once};
+
+// keeps the messages of all records, since a logger can be installed only once per process
+struct CaptureLogger {
+    records: Mutex<Vec<(log::Level, String)>>,
+}
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        self.records
+            .lock()
+            .unwrap()
+            .push((record.level(), format!("{}", record.args())));
+    }
+    fn flush(&self) {}
+}
+static LOGGER: CaptureLogger = CaptureLogger {
+    records: Mutex::new(Vec::new()),
+};
+static INSTALL: Once = Once::new();
+
+fn capture_logs() {
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+    });
+}
+
+// the records which report the other end closing or a failed read, of the given connections
+fn teardown_noise(ids: &[ConnectionId]) -> Vec<(log::Level, String)> {
+    let prefixes: Vec<_> = ids.iter().map(|id| format!("{}: ", id)).collect();
+    LOGGER
+        .records
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, message)| prefixes.iter().any(|prefix| message.starts_with(prefix)))
+        .filter(|(level, message)| {
+            *level <= log::Level::Warn || message.contains("by peer") || message.contains("error")
+        })
+        .cloned()
+        .collect()
+}
+
+/// The test protocol, without a goodbye message.
+#[derive(Debug)]
+enum SilentProtocol {}
+impl Protocol for SilentProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn config_for<P: Protocol>() -> TcpIpcConfig<P> {
+    TcpIpcConfig {
+        read_iteration_wait_time: Some(std::time::Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    }
+}
+
+#[test]
+fn both_ends_close_cleanly_after_exchanging_goodbyes() {
+    capture_logs();
+    let (server, mut client) = pair();
+    client.write_message(QUERY, b"last").unwrap();
+    expect_payload(&mut client, REPLY, b"last", TIMEOUT);
+    let ids = [server.id(), client.id()];
+
+    let (server, client) = close_both(server, client, TIMEOUT);
+    let (server, client) = (server.unwrap(), client.unwrap());
+    assert!(server.is_clean(), "{:?}", server);
+    assert!(client.is_clean(), "{:?}", client);
+    assert_eq!(teardown_noise(&ids), Vec::new());
+}
+
+#[test]
+fn both_ends_close_cleanly_without_goodbyes() {
+    capture_logs();
+    let (server, client) = loopback::<SilentProtocol>(config_for(), config_for()).unwrap();
+    let ids = [server.id(), client.id()];
+
+    let (server, client) = close_both(server, client, TIMEOUT);
+    assert!(server.unwrap().is_clean());
+    assert!(client.unwrap().is_clean());
+    assert_eq!(teardown_noise(&ids), Vec::new());
+}
+
+#[test]
+fn closing_one_end_after_the_other_is_noticed() {
+    // the race which 'close_both' avoids
+    capture_logs();
+    let (server, mut client) = loopback::<SilentProtocol>(config_for(), config_for()).unwrap();
+    let ids = [server.id(), client.id()];
+    server.shutdown().unwrap();
+    expect_closed(&mut client);
+    assert!(!teardown_noise(&ids).is_empty());
+}
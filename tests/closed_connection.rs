@@ -0,0 +1,92 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+/// An operation on a closed connection has to fail without waiting (for example for 'TIMEOUT').
+const FAST: Duration = Duration::from_millis(500);
+
+// returns the client, once it noticed that the server shut down
+fn closed_client() -> TcpIpc<TestProtocol> {
+    let (server, mut client) = pair();
+    server.shutdown().expect("shutdown was not clean");
+    expect_closed(&mut client);
+    assert!(client.is_connection_closed());
+    client
+}
+
+fn assert_fast<T, F: FnOnce() -> T>(operation: F) -> T {
+    let start = Instant::now();
+    let result = operation();
+    assert!(start.elapsed() < FAST, "took {:?}", start.elapsed());
+    result
+}
+
+#[test]
+fn writes_fail_with_connection_closed() {
+    let mut client = closed_client();
+    assert!(matches!(
+        assert_fast(|| client.write_message(DATA, b"late")),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+    assert!(matches!(
+        assert_fast(|| client.write_message_within(DATA, b"late", TIMEOUT)),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+    assert!(matches!(
+        assert_fast(|| client.write_message_with_priority(DATA, b"late", Priority::High)),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+    assert!(matches!(
+        assert_fast(|| client.write_messages(&[(DATA, b"late")])),
+        Err(BatchWriteErrors::ConnectionClosed)
+    ));
+    assert!(matches!(
+        assert_fast(|| client.send_after(Duration::from_millis(1), DATA, b"late".to_vec())),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+}
+
+#[test]
+fn reads_fail_with_connection_closed() {
+    let mut client = closed_client();
+    assert!(matches!(
+        assert_fast(|| client.get_message()),
+        Err(ReadThreadErrors::ConnectionClosed)
+    ));
+    assert!(matches!(
+        assert_fast(|| client.await_message(TIMEOUT, None)),
+        Err(ReadThreadErrors::ConnectionClosed)
+    ));
+}
+
+#[test]
+fn control_operations_fail_with_connection_closed() {
+    let mut client = closed_client();
+    assert_eq!(
+        assert_fast(|| client.update_busy_state(1)),
+        BusyStateUpdateResult::ConnectionClosed
+    );
+    assert_eq!(
+        assert_fast(|| client.get_busy_state()),
+        Err(BusyStateQueryResult::ConnectionClosed)
+    );
+    assert!(matches!(
+        assert_fast(|| client.restart_read_thread()),
+        Err(RestartError::ConnectionClosed)
+    ));
+    let err = assert_fast(|| client.set_nodelay(true)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+    let err = assert_fast(|| client.set_ttl(64)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+}
+
+#[test]
+fn messages_received_before_closing_are_delivered_first() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"last words").unwrap();
+    server.shutdown().expect("shutdown was not clean");
+    expect_payload(&mut client, DATA, b"last words", TIMEOUT);
+    expect_closed(&mut client);
+}
@@ -0,0 +1,74 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+
+fn counted() -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        per_command_stats: true,
+        ..config()
+    }
+}
+
+// returns (sent, received) of the command, or None if it was not seen
+fn tally(stats: &[(u8, CommandStats)], command: u8) -> Option<(u64, u64)> {
+    stats
+        .iter()
+        .find(|(seen, _)| *seen == command)
+        .map(|(_, stats)| (stats.sent, stats.received))
+}
+
+#[test]
+fn mixed_commands_are_tallied_per_command() {
+    let (mut server, mut client) = pair_with(counted(), counted());
+    for _ in 0..3 {
+        client.write_message(DATA, b"data").unwrap();
+    }
+    client.write_message(URGENT, b"urgent").unwrap();
+    for _ in 0..2 {
+        client.write_message(QUERY, b"query").unwrap();
+        expect_payload(&mut client, REPLY, b"query", TIMEOUT);
+    }
+    server.write_message(ERROR, b"error").unwrap();
+    expect_payload(&mut client, ERROR, b"error", TIMEOUT);
+    for _ in 0..3 {
+        expect_payload(&mut server, DATA, b"data", TIMEOUT);
+    }
+    expect_payload(&mut server, URGENT, b"urgent", TIMEOUT);
+
+    let client_stats = client.command_stats();
+    let commands: Vec<u8> = client_stats.iter().map(|(command, _)| *command).collect();
+    assert_eq!(commands, vec![DATA, URGENT, QUERY, REPLY, ERROR]);
+    assert_eq!(tally(&client_stats, DATA), Some((3, 0)));
+    assert_eq!(tally(&client_stats, URGENT), Some((1, 0)));
+    assert_eq!(tally(&client_stats, QUERY), Some((2, 0)));
+    assert_eq!(tally(&client_stats, REPLY), Some((0, 2)));
+    assert_eq!(tally(&client_stats, ERROR), Some((0, 1)));
+
+    let server_stats = server.command_stats();
+    assert_eq!(tally(&server_stats, DATA), Some((0, 3)));
+    assert_eq!(tally(&server_stats, URGENT), Some((0, 1)));
+    // answered via the immediate route, but received nevertheless
+    assert_eq!(tally(&server_stats, QUERY), Some((0, 2)));
+    assert_eq!(tally(&server_stats, REPLY), Some((2, 0)));
+    assert_eq!(tally(&server_stats, ERROR), Some((1, 0)));
+
+    for (command, stats) in &server_stats {
+        assert_eq!(stats.last_sent_at.is_some(), stats.sent > 0, "{}", command);
+        assert_eq!(
+            stats.last_received_at.is_some(),
+            stats.received > 0,
+            "{}",
+            command
+        );
+    }
+}
+
+#[test]
+fn nothing_is_tallied_unless_enabled() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"data").unwrap();
+    expect_payload(&mut server, DATA, b"data", TIMEOUT);
+    assert!(client.command_stats().is_empty());
+    assert!(server.command_stats().is_empty());
+}
@@ -0,0 +1,202 @@
+// shared by the integration tests, each of which uses only some of the helpers
+#![allow(dead_code)]
+use rust_tcp_ipc::*;
+use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
+
+/// The time the tests wait for something which is expected to happen.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// This is answered via the immediate route with 'REPLY' & the same payload.
+pub const QUERY: u8 = 1;
+/// The immediate response to 'QUERY'.
+pub const REPLY: u8 = 2;
+/// This is answered via the immediate route with 'UNCONSTRUCTIBLE', which fails.
+pub const FAULTY_QUERY: u8 = 3;
+/// A command which is delivered to the consumer.
+pub const DATA: u8 = 4;
+/// A command whose frames are written with high priority by the protocol.
+pub const URGENT: u8 = 5;
+/// A command whose messages are reliable messages, if the reliability layer is enabled.
+pub const RELIABLE: u8 = 6;
+/// The acknowledgment of reliable messages.
+pub const ACK: u8 = 0xA0;
+/// The peer announces to close the connection with this command.
+pub const GOODBYE: u8 = 0xB0;
+/// A generic error command, for example a fallback frame.
+pub const ERROR: u8 = 0xE0;
+/// No frame of this command can be constructed.
+pub const UNCONSTRUCTIBLE: u8 = 0xEE;
+/// A command which the protocol does not know, so headers with it cannot be parsed.
+pub const UNKNOWN: u8 = 0xFF;
+/// Longer payloads are invalid, so headers declaring them cannot be parsed.
+pub const MAX_LENGTH: u64 = 1 << 32;
+
+/// The protocol of the tests: a 1-byte command followed by an 8-byte big-endian length.
+#[derive(Debug)]
+pub enum TestProtocol {}
+impl Protocol for TestProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        0
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        _busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        match *command {
+            QUERY => Some((REPLY, message.to_vec())),
+            FAULTY_QUERY => Some((UNCONSTRUCTIBLE, message.to_vec())),
+            _ => None,
+        }
+    }
+    fn ack_command() -> Option<u8> {
+        Some(ACK)
+    }
+    fn is_reliable_command(command: &u8) -> bool {
+        *command == RELIABLE
+    }
+    fn shutdown_command() -> Option<u8> {
+        Some(GOODBYE)
+    }
+    fn fault_frame(violation: &ProtocolViolation) -> Option<(u8, Vec<u8>)> {
+        Some((ERROR, violation.header.clone()))
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        match command[0] {
+            UNKNOWN => None,
+            command => Some(command),
+        }
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        match u64::from_be_bytes(*length) {
+            length if length > MAX_LENGTH => None,
+            length => usize::try_from(length).ok(),
+        }
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        if input.len() >= 9 {
+            Some((input[..9].try_into().unwrap(), &input[9..]))
+        } else {
+            None
+        }
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        (
+            header[..1].try_into().unwrap(),
+            header[1..].try_into().unwrap(),
+        )
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        [command]
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        if command == UNCONSTRUCTIBLE {
+            None
+        } else {
+            Some((message.len() as u64).to_be_bytes())
+        }
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        let mut header = command.to_vec();
+        header.extend_from_slice(&length);
+        header
+    }
+}
+
+/// The config of the tests: short waits, everything else default.
+pub fn config() -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    }
+}
+
+/// Connects a server & a client with the given configs (server first).
+pub fn pair_with(
+    server_config: TcpIpcConfig<TestProtocol>,
+    client_config: TcpIpcConfig<TestProtocol>,
+) -> (TcpIpc<TestProtocol>, TcpIpc<TestProtocol>) {
+    testing::loopback(server_config, client_config).expect("loopback pair could not be connected")
+}
+
+/// Connects a server & a client with the test config (server first).
+pub fn pair() -> (TcpIpc<TestProtocol>, TcpIpc<TestProtocol>) {
+    pair_with(config(), config())
+}
+
+/// Waits for the read thread to report an error and returns it. Messages received before are skipped.
+pub fn expect_error<P: Protocol>(ipc: &mut TcpIpc<P>) -> ReadThreadErrors<P> {
+    let start = std::time::Instant::now();
+    loop {
+        match ipc.get_message() {
+            Err(err) => return err,
+            Ok(_) if start.elapsed() > TIMEOUT => panic!("no error within {:?}", TIMEOUT),
+            Ok(Some(_)) => {}
+            Ok(None) => std::thread::sleep(Duration::from_millis(1)),
+        }
+    }
+}
+
+/// Waits until the connection is closed, i.e. the read thread reports a closed or disconnected connection.
+pub fn expect_closed<P: Protocol>(ipc: &mut TcpIpc<P>) {
+    let start = std::time::Instant::now();
+    loop {
+        match expect_error(ipc) {
+            ReadThreadErrors::ConnectionClosed
+            | ReadThreadErrors::Disconnected
+            | ReadThreadErrors::PeerClosed { .. } => return,
+            _ if start.elapsed() > TIMEOUT => panic!("connection not closed within {:?}", TIMEOUT),
+            _ => {}
+        }
+    }
+}
+
+/// Accepts a connection from a plain TCP stream (instead of a 'TcpIpc'), so the test can write arbitrary bytes.
+/// The server is returned first.
+pub fn raw_peer_with<P: Protocol>(
+    server_config: TcpIpcConfig<P>,
+) -> (TcpIpc<P>, std::net::TcpStream) {
+    let listener = TcpIpc::<P>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || std::net::TcpStream::connect(address).unwrap());
+    let server = listener.accept(server_config).unwrap();
+    (server, peer.join().unwrap())
+}
+
+/// Like 'raw_peer_with', using the test config.
+pub fn raw_peer() -> (TcpIpc<TestProtocol>, std::net::TcpStream) {
+    raw_peer_with(config())
+}
+
+/// Returns the bytes of the given frame on the wire.
+pub fn frame(command: u8, payload: &[u8]) -> Vec<u8> {
+    TestProtocol::construct_message(command, payload).unwrap()
+}
+
+/// Waits until the read thread of the connection received the given number of bytes.
+pub fn await_bytes_received<P: Protocol>(ipc: &TcpIpc<P>, bytes: u64) {
+    let start = std::time::Instant::now();
+    while ipc.stats().bytes_received < bytes {
+        assert!(start.elapsed() < TIMEOUT, "{} bytes not received", bytes);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Waits until the condition holds, for example until a hook ran on the read thread.
+pub fn await_condition<F: FnMut() -> bool>(mut condition: F) {
+    let start = std::time::Instant::now();
+    while !condition() {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "condition not met within {:?}",
+            TIMEOUT
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,120 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::conformance::*;
+use rust_tcp_ipc::*;
+
+/// The declared length is one byte too long.
+const OFF_BY_ONE: u8 = 1;
+/// Parsing panics for some lengths, instead of returning None.
+const PANICKING: u8 = 2;
+/// Frames with payloads longer than 1000 bytes cannot be constructed.
+const REFUSING_LONG: u8 = 3;
+
+/// The test protocol, with the given flaw.
+#[derive(Debug)]
+enum FlawedProtocol<const FLAW: u8> {}
+impl<const FLAW: u8> Protocol for FlawedProtocol<FLAW> {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        if FLAW == PANICKING && length[0] == 0x42 {
+            panic!("unexpected length {:?}", length);
+        }
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        match FLAW {
+            OFF_BY_ONE => Some((message.len() as u64 + 1).to_be_bytes()),
+            REFUSING_LONG if message.len() > 1000 => None,
+            _ => TestProtocol::get_length_as_array(command, message),
+        }
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn samples() -> Vec<Vec<u8>> {
+    vec![vec![1, 2, 3], vec![0xFF; 1000]]
+}
+
+fn checks(failures: &[ConformanceFailure]) -> Vec<ConformanceCheck> {
+    let mut checks: Vec<_> = failures.iter().map(|failure| failure.check).collect();
+    checks.dedup();
+    checks
+}
+
+#[test]
+fn the_test_protocol_conforms() {
+    let failures = check_protocol::<TestProtocol>(&[QUERY, DATA, ACK], &samples());
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+#[test]
+fn an_inconsistent_header_is_reported() {
+    let failures = check_protocol::<FlawedProtocol<OFF_BY_ONE>>(&[DATA], &samples());
+    assert!(
+        checks(&failures).contains(&ConformanceCheck::HeaderConsistency),
+        "{:#?}",
+        failures
+    );
+    // the failure names the command & the payload, so it can be reproduced
+    let failure = failures
+        .iter()
+        .find(|failure| failure.check == ConformanceCheck::HeaderConsistency)
+        .unwrap();
+    assert!(
+        failure.description.contains("for 4 with a payload of"),
+        "{}",
+        failure
+    );
+    assert!(failure.to_string().starts_with("HeaderConsistency: "));
+}
+
+#[test]
+fn a_panicking_header_parser_is_reported() {
+    let failures = check_protocol::<FlawedProtocol<PANICKING>>(&[DATA], &samples());
+    assert!(!failures.is_empty());
+    assert_eq!(checks(&failures), vec![ConformanceCheck::RandomHeader]);
+}
+
+#[test]
+fn refusing_boundary_lengths_is_fine() {
+    let failures = check_protocol::<FlawedProtocol<REFUSING_LONG>>(&[DATA], &samples());
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+#[test]
+fn an_unconstructible_sample_is_reported() {
+    let failures = check_protocol::<TestProtocol>(&[UNCONSTRUCTIBLE], &samples());
+    assert!(
+        checks(&failures).contains(&ConformanceCheck::Construct),
+        "{:#?}",
+        failures
+    );
+}
@@ -0,0 +1,82 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+
+const CONNECTIONS: usize = 200;
+
+fn read_frame(peer: &mut std::net::TcpStream, payload_length: usize) -> Vec<u8> {
+    let mut bytes = vec![0; 9 + payload_length];
+    peer.read_exact(&mut bytes).unwrap();
+    bytes
+}
+
+// counts the threads of this crate, which are named "tcp-ipc/..." (see 'TcpIpcConfig::name')
+#[cfg(target_os = "linux")]
+fn crate_thread_count() -> usize {
+    std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter_map(|task| std::fs::read_to_string(task.ok()?.path().join("comm")).ok())
+        .filter(|name| name.starts_with("tcp-ipc/"))
+        .count()
+}
+
+#[test]
+fn one_thread_services_many_connections() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let group =
+        ConnectionGroup::<TestProtocol>::new(Some(std::time::Duration::from_micros(10))).unwrap();
+    let mut connections = Vec::new();
+    for _ in 0..CONNECTIONS {
+        let client = group.add_client(address, config(), Some(TIMEOUT)).unwrap();
+        let (peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+        connections.push((client, peer));
+    }
+    await_condition(|| group.connection_count() == CONNECTIONS);
+    // the group threads of this & the other test, which runs in parallel
+    #[cfg(target_os = "linux")]
+    assert!(
+        (1..=2).contains(&crate_thread_count()),
+        "{} threads",
+        crate_thread_count()
+    );
+
+    for (i, (client, peer)) in connections.iter_mut().enumerate() {
+        let payload = (i as u32).to_be_bytes();
+        // the consumer writes & receives
+        client.write_message(DATA, &payload).unwrap();
+        assert_eq!(read_frame(peer, 4), frame(DATA, &payload));
+        peer.write_all(&frame(ERROR, &payload)).unwrap();
+        expect_payload(client, ERROR, &payload, TIMEOUT);
+        // the group thread answers immediately
+        peer.write_all(&frame(QUERY, &payload)).unwrap();
+        assert_eq!(read_frame(peer, 4), frame(REPLY, &payload));
+    }
+}
+
+#[test]
+fn removing_a_connection_does_not_disturb_the_others() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let group = ConnectionGroup::<TestProtocol>::new(None).unwrap();
+    let mut connections = Vec::new();
+    for _ in 0..3 {
+        let client = group.add_client(address, config(), Some(TIMEOUT)).unwrap();
+        let (peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+        connections.push((client, peer));
+    }
+    let (removed, _removed_peer) = connections.remove(1);
+    removed.shutdown().expect("shutdown was not clean");
+    drop(connections.remove(0));
+    await_condition(|| group.connection_count() == 1);
+
+    let (client, peer) = &mut connections[0];
+    client.write_message(DATA, b"alive").unwrap();
+    assert_eq!(read_frame(peer, 5), frame(DATA, b"alive"));
+    peer.write_all(&frame(QUERY, b"?")).unwrap();
+    assert_eq!(read_frame(peer, 1), frame(REPLY, b"?"));
+}
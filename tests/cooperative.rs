@@ -0,0 +1,86 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+// connects a cooperative client to a threaded server (server first)
+fn cooperative_pair() -> (TcpIpc<TestProtocol>, TcpIpcCooperative<TestProtocol>) {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || listener.accept(config()).unwrap());
+    let client = TcpIpcCooperative::client(address, config(), Some(TIMEOUT)).unwrap();
+    (server.join().unwrap(), client)
+}
+
+// ticks until a message arrives, returning it with the reports of the ticks
+fn tick_until_message(
+    client: &mut TcpIpcCooperative<TestProtocol>,
+    budget: Duration,
+) -> (Message<TestProtocol>, Vec<TickReport>) {
+    let start = Instant::now();
+    let mut reports = Vec::new();
+    loop {
+        assert!(start.elapsed() < TIMEOUT, "no message within {:?}", TIMEOUT);
+        reports.push(client.tick(budget));
+        if let Some(message) = client.get_message().unwrap() {
+            return (message, reports);
+        }
+    }
+}
+
+#[test]
+fn a_large_transfer_completes_across_many_ticks() {
+    let (mut server, mut client) = cooperative_pair();
+    let payload: Vec<u8> = (0..1 << 20).map(|i: u32| (i % 251) as u8).collect();
+    server.write_message(DATA, &payload).unwrap();
+
+    let (message, reports) = tick_until_message(&mut client, Duration::from_micros(10));
+    assert!(message == (DATA, payload), "the payload was corrupted");
+    assert!(reports.len() > 1, "{} ticks", reports.len());
+    assert!(reports.iter().any(|report| report.more_pending));
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| report.frames_parsed)
+            .sum::<u64>(),
+        1
+    );
+    assert!(reports.iter().all(|report| !report.finished));
+}
+
+#[test]
+fn immediate_responses_are_written_by_ticks() {
+    let (mut server, mut client) = cooperative_pair();
+    server.write_message(QUERY, b"ping").unwrap();
+    // nothing is read before a tick
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(client.stats().messages_received, 0);
+
+    let start = Instant::now();
+    while client.stats().messages_received == 0 {
+        assert!(start.elapsed() < TIMEOUT);
+        client.tick(Duration::from_millis(1));
+    }
+    expect_payload(&mut server, REPLY, b"ping", TIMEOUT);
+
+    // the other calls work between ticks
+    client.write_message(DATA, b"between ticks").unwrap();
+    expect_payload(&mut server, DATA, b"between ticks", TIMEOUT);
+    assert_eq!(client.update_busy_state(1), BusyStateUpdateResult::Success);
+    server.write_message(DATA, b"after").unwrap();
+    let (message, _) = tick_until_message(&mut client, Duration::from_millis(1));
+    assert_eq!(message, (DATA, b"after".to_vec()));
+}
+
+#[test]
+fn a_closed_connection_finishes_the_ticks() {
+    let (server, mut client) = cooperative_pair();
+    drop(server);
+    let start = Instant::now();
+    while !client.tick(Duration::from_millis(1)).finished {
+        assert!(start.elapsed() < TIMEOUT, "the close was not noticed");
+    }
+    assert!(client.tick(Duration::from_millis(1)).finished);
+    assert!(client.is_connection_closed());
+}
@@ -0,0 +1,86 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::convert::TryInto;
+use std::sync::{Arc, Barrier};
+use std::time::Duration;
+
+const QUERIES: u32 = 1000;
+/// 1000 queries of this size are far more than the (small) socket buffers take, so both sides block in their writes.
+const QUERY_SIZE: usize = 1 << 10;
+/// The answers of a side are written once the peer's writes let them through, which might take a while.
+const ANSWER_WAIT: Duration = Duration::from_secs(30);
+
+fn retrying() -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        write_retry: Some(RetrySpec {
+            max_duration: ANSWER_WAIT,
+            backoff: Duration::from_micros(100),
+        }),
+        send_buffer_size: Some(16 << 10),
+        recv_buffer_size: Some(16 << 10),
+        // otherwise, small buffers & delayed acknowledgments stall the answers for 200 ms at a time
+        nodelay: Some(true),
+        ..config()
+    }
+}
+
+// a query of the given side, which the peer answers via the immediate route with the same payload
+fn query(side: u8, id: u32) -> Vec<u8> {
+    let mut payload = vec![side; QUERY_SIZE];
+    payload[..4].copy_from_slice(&id.to_be_bytes());
+    payload
+}
+
+// fires all queries of this side, then collects the answers (which the read thread queued meanwhile)
+fn fire_and_collect(
+    mut ipc: TcpIpc<TestProtocol>,
+    side: u8,
+    start: Arc<Barrier>,
+) -> (TcpIpc<TestProtocol>, Vec<u32>) {
+    start.wait();
+    for id in 0..QUERIES {
+        ipc.write_message(QUERY, &query(side, id)).unwrap();
+    }
+    let mut answered = Vec::new();
+    while answered.len() < QUERIES as usize {
+        let (command, payload) = ipc
+            .await_message(ANSWER_WAIT, Some(Duration::from_micros(10)))
+            .unwrap()
+            .unwrap_or_else(|| panic!("side {}: {} answers", side, answered.len()));
+        assert_eq!(command, REPLY);
+        let id = u32::from_be_bytes(payload[..4].try_into().unwrap());
+        assert!(
+            payload == query(side, id),
+            "side {}: answer {} corrupted",
+            side,
+            id
+        );
+        answered.push(id);
+    }
+    (ipc, answered)
+}
+
+#[test]
+fn crossing_queries_are_answered_exactly_once() {
+    let (server, client) = pair_with(retrying(), retrying());
+    let start = Arc::new(Barrier::new(2));
+    let server = {
+        let start = start.clone();
+        std::thread::spawn(move || fire_and_collect(server, b'S', start))
+    };
+    let (mut client, client_answers) = fire_and_collect(client, b'C', start);
+    let (mut server, server_answers) = server.join().unwrap();
+
+    let expected: Vec<u32> = (0..QUERIES).collect();
+    assert_eq!(server_answers, expected);
+    assert_eq!(client_answers, expected);
+    // neither side got a query, another frame or an error (like a parser desync)
+    for ipc in [&mut server, &mut client] {
+        assert!(matches!(ipc.get_message(), Ok(None)));
+        assert_eq!(ipc.stats().messages_received, u64::from(2 * QUERIES));
+        assert_eq!(ipc.stats().immediate_responses_sent, u64::from(QUERIES));
+        let parser_state = ipc.diagnostics().parser_state;
+        assert_eq!(parser_state.map(|state| state.is_mid_frame()), Some(false));
+    }
+}
@@ -0,0 +1,96 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+const STEP_1: u8 = 0x31;
+const STEP_2: u8 = 0x32;
+const STEP_3: u8 = 0x33;
+
+// the peer sends each step after the given delay, preceded by two telemetry frames
+// the peer is returned, so the connection stays open until the test joins the thread
+fn script(
+    mut peer: TcpIpc<TestProtocol>,
+    steps: Vec<(Duration, u8)>,
+) -> std::thread::JoinHandle<TcpIpc<TestProtocol>> {
+    std::thread::spawn(move || {
+        for (delay, step) in steps {
+            std::thread::sleep(delay);
+            peer.write_message(DATA, b"telemetry").unwrap();
+            peer.write_message(DATA, b"telemetry").unwrap();
+            peer.write_message(step, &[]).unwrap();
+        }
+        peer
+    })
+}
+
+#[test]
+fn chained_awaits_draw_from_one_budget() {
+    let (mut server, client) = pair();
+    let total = Duration::from_millis(500);
+    let mut budget = DeadlineBudget::new(total);
+    let peer = script(
+        client,
+        vec![
+            (Duration::from_millis(50), STEP_1),
+            (Duration::from_millis(50), STEP_2),
+        ],
+    );
+    let start = Instant::now();
+
+    let first = server.await_command_budgeted(&[STEP_1], &mut budget);
+    assert_eq!(first.result.unwrap(), Some((STEP_1, vec![])));
+    assert_eq!(first.messages_skipped, 2);
+    assert!(
+        first.waited >= Duration::from_millis(50),
+        "{:?}",
+        first.waited
+    );
+    assert_eq!(budget.remaining(), total - first.waited);
+
+    let second = server.await_command_budgeted(&[STEP_2], &mut budget);
+    assert_eq!(second.result.unwrap(), Some((STEP_2, vec![])));
+    // the skipped telemetry of the first await is passed over again
+    assert_eq!(second.messages_skipped, 4);
+    assert_eq!(budget.remaining(), total - first.waited - second.waited);
+
+    // the third step never arrives, so the await takes what is left
+    let left = budget.remaining();
+    let third = server.await_command_budgeted(&[STEP_3], &mut budget);
+    assert_eq!(third.result.unwrap(), None);
+    assert_eq!(third.messages_skipped, 4);
+    assert!(third.waited >= left, "{:?} < {:?}", third.waited, left);
+    assert!(budget.is_exhausted());
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= total && elapsed < total + Duration::from_millis(200),
+        "{:?}",
+        elapsed
+    );
+
+    // the skipped messages stay queued
+    for _ in 0..4 {
+        expect_payload(&mut server, DATA, b"telemetry", TIMEOUT);
+    }
+    peer.join().unwrap();
+}
+
+#[test]
+fn an_exhausted_budget_only_checks_the_queue() {
+    let (mut server, mut client) = pair();
+    client.write_message(STEP_1, &[]).unwrap();
+    await_bytes_received(&server, frame(STEP_1, &[]).len() as u64);
+    let mut budget = DeadlineBudget::new(Duration::from_secs(0));
+
+    let queued = server.await_command_budgeted(&[STEP_1], &mut budget);
+    assert_eq!(queued.result.unwrap(), Some((STEP_1, vec![])));
+    let missing = server.await_command_budgeted(&[STEP_2], &mut budget);
+    assert_eq!(missing.result.unwrap(), None);
+    assert!(
+        missing.waited < Duration::from_millis(50),
+        "{:?}",
+        missing.waited
+    );
+    assert!(budget.is_exhausted());
+}
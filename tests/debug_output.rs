@@ -0,0 +1,61 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+// user structs can derive Debug, if they hold a connection, its config & its handles
+#[derive(Debug)]
+#[allow(dead_code)] // only formatted
+struct Device {
+    connection: TcpIpc<TestProtocol>,
+    config: TcpIpcConfig<TestProtocol>,
+    status: PeriodicHandle,
+}
+
+#[test]
+fn debug_shows_the_connection_but_no_payloads() {
+    let named = TcpIpcConfig {
+        name: Some("camera".to_string()),
+        ..config()
+    };
+    let (mut server, mut client) = pair_with(named.clone(), config());
+    client.write_message(DATA, b"SECRET").unwrap();
+    await_bytes_received(&server, frame(DATA, b"SECRET").len() as u64);
+
+    let formatted = format!("{:?}", server);
+    assert!(formatted.starts_with("TcpIpc {"), "{}", formatted);
+    assert!(formatted.contains("\"camera\""), "{}", formatted);
+    assert!(
+        formatted.contains(&server.peer_addr().unwrap().to_string()),
+        "{}",
+        formatted
+    );
+    assert!(
+        formatted.contains(&client.peer_addr().unwrap().to_string()),
+        "{}",
+        formatted
+    );
+    assert!(
+        formatted.contains("connection_closed: false"),
+        "{}",
+        formatted
+    );
+    assert!(formatted.contains("pending_outgoing: 0"), "{}", formatted);
+    assert!(!formatted.contains("SECRET"), "{}", formatted);
+    assert!(!formatted.contains("83, 69, 67"), "{}", formatted);
+
+    let status = server
+        .send_periodic(DATA, Vec::new, std::time::Duration::from_secs(60))
+        .unwrap();
+    let device = Device {
+        connection: server,
+        config: named,
+        status,
+    };
+    let formatted = format!("{:?}", device);
+    assert!(formatted.contains("connection: TcpIpc {"), "{}", formatted);
+    assert!(
+        formatted.contains("status: PeriodicHandle"),
+        "{}",
+        formatted
+    );
+}
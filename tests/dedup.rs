@@ -0,0 +1,120 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::time::Duration;
+
+/// The test protocol, except that frames are keyed by their command & payload.
+#[derive(Debug)]
+enum DedupProtocol {}
+impl Protocol for DedupProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn dedup_key(command: &u8, payload: &[u8]) -> Option<u64> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (command, payload).hash(&mut hasher);
+        Some(hasher.finish())
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn dedup_peer(window: Duration) -> (TcpIpc<DedupProtocol>, std::net::TcpStream) {
+    let server_config = TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        dedup_window: Some(window),
+        ..TcpIpcConfig::default()
+    };
+    let listener = TcpIpc::<DedupProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || std::net::TcpStream::connect(address).unwrap());
+    let server = listener.accept(server_config).unwrap();
+    (server, peer.join().unwrap())
+}
+
+#[test]
+fn a_repeated_frame_within_the_window_is_delivered_once() {
+    let (mut server, mut peer) = dedup_peer(TIMEOUT);
+    peer.write_all(&frame(DATA, b"twice")).unwrap();
+    peer.write_all(&frame(DATA, b"twice")).unwrap();
+    peer.write_all(&frame(DATA, b"marker")).unwrap();
+
+    expect_payload(&mut server, DATA, b"twice", TIMEOUT);
+    // the duplicate would be delivered before the marker
+    expect_payload(&mut server, DATA, b"marker", TIMEOUT);
+    assert_eq!(server.stats().duplicates_dropped, 1);
+}
+
+#[test]
+fn a_repeated_frame_outside_the_window_is_delivered_again() {
+    let window = Duration::from_millis(50);
+    let (mut server, mut peer) = dedup_peer(window);
+    peer.write_all(&frame(DATA, b"twice")).unwrap();
+    expect_payload(&mut server, DATA, b"twice", TIMEOUT);
+    std::thread::sleep(3 * window);
+    peer.write_all(&frame(DATA, b"twice")).unwrap();
+    expect_payload(&mut server, DATA, b"twice", TIMEOUT);
+    assert_eq!(server.stats().duplicates_dropped, 0);
+}
+
+#[test]
+fn frames_with_other_keys_are_no_duplicates() {
+    let (mut server, mut peer) = dedup_peer(TIMEOUT);
+    for frame in [frame(DATA, b"a"), frame(DATA, b"b"), frame(URGENT, b"a")] {
+        peer.write_all(&frame).unwrap();
+    }
+    expect_payload(&mut server, DATA, b"a", TIMEOUT);
+    expect_payload(&mut server, DATA, b"b", TIMEOUT);
+    expect_payload(&mut server, URGENT, b"a", TIMEOUT);
+    assert_eq!(server.stats().duplicates_dropped, 0);
+}
+
+#[test]
+fn the_remembered_keys_are_bounded() {
+    let (mut server, mut peer) = dedup_peer(TIMEOUT);
+    // once more keys arrived, the first one is forgotten within the window
+    let payloads: Vec<Vec<u8>> = (0..=DEDUP_CAPACITY as u32)
+        .map(|i| i.to_be_bytes().to_vec())
+        .collect();
+    for payload in payloads.iter().chain(std::iter::once(&payloads[0])) {
+        peer.write_all(&frame(DATA, payload)).unwrap();
+    }
+    for payload in payloads.iter().chain(std::iter::once(&payloads[0])) {
+        expect_payload(&mut server, DATA, payload, TIMEOUT);
+    }
+    assert_eq!(server.stats().duplicates_dropped, 0);
+}
@@ -0,0 +1,102 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+// waits for the next message or gap
+fn next(server: &mut TcpIpc<TestProtocol>) -> MessageOrGap<TestProtocol> {
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(next) = server.get_message_or_gap().unwrap() {
+            return next;
+        }
+        assert!(start.elapsed() < TIMEOUT, "nothing arrived");
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn messages_are_numbered_in_order() {
+    let (mut server, mut client) = pair();
+    for i in 0..3u8 {
+        client.write_message(DATA, &[i]).unwrap();
+    }
+    for i in 0..3u8 {
+        assert_eq!(
+            next(&mut server),
+            MessageOrGap::Message {
+                sequence: u64::from(i),
+                message: (DATA, vec![i]),
+            }
+        );
+    }
+    assert_eq!(
+        server.take_delivery_report(),
+        DeliveryReport {
+            delivered: 3,
+            dropped_by_policy: 0,
+            last_sequence: Some(2),
+        }
+    );
+}
+
+#[test]
+fn dropped_messages_are_reported_as_gap() {
+    let (mut server, mut client) = pair_with(
+        TcpIpcConfig {
+            restart_policy: RestartPolicy {
+                keep_partial_frame: false,
+                keep_messages: false,
+            },
+            ..config()
+        },
+        config(),
+    );
+    client.write_message(DATA, &[0]).unwrap();
+    assert!(matches!(
+        next(&mut server),
+        MessageOrGap::Message { sequence: 0, .. }
+    ));
+    // these are received, but dropped by restarting the read thread before they are taken
+    for i in 1..6u8 {
+        client.write_message(DATA, &[i]).unwrap();
+    }
+    await_bytes_received(&server, 6 * 10);
+    server.restart_read_thread().unwrap();
+    for i in 6..8u8 {
+        client.write_message(DATA, &[i]).unwrap();
+    }
+
+    assert_eq!(
+        next(&mut server),
+        MessageOrGap::Gap {
+            first_missing: 1,
+            count: 5,
+        }
+    );
+    for i in 6..8u8 {
+        assert_eq!(
+            next(&mut server),
+            MessageOrGap::Message {
+                sequence: u64::from(i),
+                message: (DATA, vec![i]),
+            }
+        );
+    }
+    assert_eq!(
+        server.take_delivery_report(),
+        DeliveryReport {
+            delivered: 3,
+            dropped_by_policy: 5,
+            last_sequence: Some(7),
+        }
+    );
+    // a new report starts, keeping the last sequence number
+    assert_eq!(
+        server.take_delivery_report(),
+        DeliveryReport {
+            delivered: 0,
+            dropped_by_policy: 0,
+            last_sequence: Some(7),
+        }
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+use common::*;
+use std::io::Write;
+
+#[test]
+fn mid_frame_stall_is_visible() {
+    let (mut server, mut peer) = raw_peer();
+    // the header declares 10 bytes, but only 3 arrive
+    let frame = frame(DATA, &[1; 10]);
+    peer.write_all(&frame[..12]).unwrap();
+    await_bytes_received(&server, 12);
+
+    let diagnostics = server.diagnostics();
+    let parser_state = diagnostics
+        .parser_state
+        .expect("read thread is not running");
+    assert!(parser_state.is_mid_frame());
+    assert_eq!(parser_state.command, Some(DATA));
+    assert_eq!(parser_state.received, 3);
+    assert_eq!(parser_state.declared, 10);
+    assert!(!diagnostics.connection_closed);
+}
+
+#[test]
+fn diagnostics_keep_the_messages_queued() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"first").unwrap();
+    client.write_message(DATA + 1, b"second").unwrap();
+    await_bytes_received(&server, 2 * 9 + 11);
+
+    let diagnostics = server.diagnostics();
+    assert_eq!(diagnostics.pending_messages, 2);
+    assert_eq!(diagnostics.pending_commands, vec![DATA, DATA + 1]);
+    assert_eq!(
+        diagnostics.parser_state.map(|state| state.is_mid_frame()),
+        Some(false)
+    );
+
+    rust_tcp_ipc::testing::expect_payload(&mut server, DATA, b"first", TIMEOUT);
+    rust_tcp_ipc::testing::expect_payload(&mut server, DATA + 1, b"second", TIMEOUT);
+}
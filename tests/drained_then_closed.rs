@@ -0,0 +1,66 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Write;
+
+// returns the server once its peer sent three messages & closed the connection
+fn closed_after_three_messages() -> TcpIpc<TestProtocol> {
+    let (server, mut peer) = raw_peer();
+    for index in 0..3u8 {
+        peer.write_all(&frame(DATA, &[index])).unwrap();
+    }
+    drop(peer);
+    await_condition(|| server.is_connection_closed());
+    server
+}
+
+fn assert_closed(result: Result<Option<Message<TestProtocol>>, ReadThreadErrors<TestProtocol>>) {
+    match result {
+        Err(ReadThreadErrors::ConnectionClosed)
+        | Err(ReadThreadErrors::Disconnected)
+        | Err(ReadThreadErrors::PeerClosed { .. }) => {}
+        result => panic!("expected a closed connection, found {:?}", result),
+    }
+}
+
+#[test]
+fn get_message_delivers_all_messages_before_the_close() {
+    let mut server = closed_after_three_messages();
+    for index in 0..3u8 {
+        assert_eq!(server.get_message().unwrap(), Some((DATA, vec![index])));
+    }
+    assert_closed(server.get_message());
+}
+
+#[test]
+fn await_message_delivers_all_messages_before_the_close() {
+    let mut server = closed_after_three_messages();
+    for index in 0..3u8 {
+        assert_eq!(
+            server.await_message(TIMEOUT, None).unwrap(),
+            Some((DATA, vec![index]))
+        );
+    }
+    assert_closed(server.await_message(TIMEOUT, None));
+}
+
+#[test]
+fn drain_messages_defers_the_close() {
+    let mut server = closed_after_three_messages();
+    assert_eq!(
+        server.drain_messages().unwrap(),
+        vec![(DATA, vec![0]), (DATA, vec![1]), (DATA, vec![2])]
+    );
+    assert_closed(server.get_message());
+}
+
+#[test]
+fn clear_message_queue_skips_the_messages_and_reports_the_close() {
+    let mut server = closed_after_three_messages();
+    match server.clear_message_queue(None) {
+        Err(ReadThreadErrors::ConnectionClosed)
+        | Err(ReadThreadErrors::Disconnected)
+        | Err(ReadThreadErrors::PeerClosed { .. }) => {}
+        result => panic!("expected a closed connection, found {:?}", result),
+    }
+}
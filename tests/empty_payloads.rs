@@ -0,0 +1,31 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+
+#[test]
+fn empty_and_non_empty_frames_interleave() {
+    let (mut server, mut client) = pair();
+    let frames: Vec<(u8, Vec<u8>)> = (0..100u8)
+        .map(|i| match i % 3 {
+            0 => (DATA, vec![]),
+            1 => (ERROR, vec![i; i as usize]),
+            _ => (DATA + 1, vec![]),
+        })
+        .collect();
+    for (command, payload) in &frames {
+        client.write_message(*command, payload).unwrap();
+    }
+    for (command, payload) in &frames {
+        expect_payload(&mut server, *command, payload, TIMEOUT);
+    }
+    expect_silence(&mut server, std::time::Duration::from_millis(20));
+}
+
+#[test]
+fn empty_frame_is_only_the_header() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, &[]).unwrap();
+    expect_payload(&mut server, DATA, &[], TIMEOUT);
+    assert_eq!(client.stats().bytes_sent, frame(DATA, &[]).len() as u64);
+    assert_eq!(server.stats().bytes_received, 9);
+}
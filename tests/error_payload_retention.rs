@@ -0,0 +1,56 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+// sends a query whose answer cannot be constructed & returns the payload kept in the error
+fn retained(server_config: TcpIpcConfig<TestProtocol>, payload: &[u8]) -> RetainedPayload {
+    let (mut server, mut client) = pair_with(server_config, config());
+    client.write_message(FAULTY_QUERY, payload).unwrap();
+    match expect_error(&mut server) {
+        ReadThreadErrors::ImmediateMessageConstructError((command, retained)) => {
+            assert_eq!(command, UNCONSTRUCTIBLE);
+            retained
+        }
+        err => panic!("unexpected {:?}", err),
+    }
+}
+
+#[test]
+fn large_payloads_are_truncated_to_the_default_retention() {
+    let payload: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+    let retained = retained(config(), &payload);
+    assert!(retained.is_truncated());
+    assert_eq!(retained.original_length, payload.len());
+    assert_eq!(retained.bytes, payload[..DEFAULT_ERROR_PAYLOAD_RETENTION]);
+    // the memory of the rest is released
+    assert!(retained.bytes.capacity() <= DEFAULT_ERROR_PAYLOAD_RETENTION);
+    let debug = format!("{:?}", retained);
+    assert!(debug.ends_with("(truncated, 1048576 bytes)"), "{}", debug);
+}
+
+#[test]
+fn the_retention_is_configurable() {
+    let server_config = TcpIpcConfig {
+        error_payload_retention: 4,
+        ..config()
+    };
+    let retained = retained(server_config, b"too long");
+    assert_eq!(retained.bytes, b"too ");
+    assert_eq!(retained.original_length, 8);
+    assert_eq!(
+        format!("{:?}", retained),
+        "[116, 111, 111, 32].. (truncated, 8 bytes)"
+    );
+}
+
+#[test]
+fn short_payloads_are_kept_completely() {
+    let retained = retained(config(), b"short");
+    assert!(!retained.is_truncated());
+    assert_eq!(retained.bytes, b"short");
+    assert_eq!(retained.original_length, 5);
+    assert_eq!(
+        format!("{:?}", retained),
+        format!("{:?}", b"short".to_vec())
+    );
+}
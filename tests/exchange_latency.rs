@@ -0,0 +1,124 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// The delay of the scripted peer. Its bucket spans 16.4 to 32.8 ms, which leaves room for scheduling delays.
+const DELAY: Duration = Duration::from_millis(20);
+
+fn counted() -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        per_command_stats: true,
+        ..config()
+    }
+}
+
+// answers each request with 'REPLY' after the next of the given delays
+fn answer_after(
+    mut peer: std::net::TcpStream,
+    delays: Vec<Duration>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        for delay in delays {
+            let mut request = vec![0; frame(DATA, b"request").len()];
+            peer.read_exact(&mut request).unwrap();
+            std::thread::sleep(delay);
+            peer.write_all(&frame(REPLY, b"response")).unwrap();
+        }
+        // keep the connection open until the last response was taken
+        let _ = peer.read(&mut [0]);
+    })
+}
+
+#[test]
+fn latencies_are_placed_into_buckets() {
+    assert_eq!(latency_bucket(Duration::from_nanos(999)), 0);
+    assert_eq!(latency_bucket(Duration::from_micros(1)), 1);
+    assert_eq!(latency_bucket(Duration::from_micros(3)), 2);
+    assert_eq!(latency_bucket(Duration::from_micros(1024)), 11);
+    assert_eq!(latency_bucket(DELAY), 15);
+    assert_eq!(latency_bucket(Duration::from_secs(60)), LATENCY_BUCKETS - 1);
+}
+
+#[test]
+fn a_completed_exchange_records_its_latency() {
+    let (mut server, peer) = raw_peer_with(counted());
+    let peer = answer_after(peer, vec![DELAY]);
+    let response = server
+        .transaction()
+        .send_and_wait(DATA, b"request", REPLY, TIMEOUT)
+        .unwrap();
+    assert_eq!(response, Some(b"response".to_vec()));
+
+    let stats = server.stats();
+    let mut expected = [0; LATENCY_BUCKETS];
+    expected[latency_bucket(DELAY)] = 1;
+    assert_eq!(stats.exchange_latencies, expected);
+    assert!(stats.max_exchange_latency >= DELAY);
+    assert_eq!(stats.exchange_timeouts, 0);
+    // the quantiles are capped by the maximum, which lies within the bucket
+    assert_eq!(
+        stats.exchange_latency_p50(),
+        Some(stats.max_exchange_latency)
+    );
+    assert_eq!(
+        stats.exchange_latency_p95(),
+        Some(stats.max_exchange_latency)
+    );
+
+    // the request command has the same breakdown
+    let command_stats = server.command_stats();
+    let (_, data) = command_stats
+        .iter()
+        .find(|(command, _)| *command == DATA)
+        .unwrap();
+    assert_eq!(data.exchange_latencies, expected);
+    assert_eq!(data.max_exchange_latency, stats.max_exchange_latency);
+    drop(server);
+    peer.join().unwrap();
+}
+
+#[test]
+fn a_timed_out_exchange_is_no_latency() {
+    let (mut server, _peer) = raw_peer_with(counted());
+    let response = server
+        .transaction()
+        .send_and_wait(DATA, b"request", REPLY, Duration::from_millis(20))
+        .unwrap();
+    assert_eq!(response, None);
+
+    let stats = server.stats();
+    assert_eq!(stats.exchange_timeouts, 1);
+    assert_eq!(stats.exchange_latencies, [0; LATENCY_BUCKETS]);
+    assert_eq!(stats.exchange_latency_p50(), None);
+    let command_stats = server.command_stats();
+    let (_, data) = command_stats
+        .iter()
+        .find(|(command, _)| *command == DATA)
+        .unwrap();
+    assert_eq!(data.exchange_timeouts, 1);
+}
+
+#[test]
+fn the_quantiles_follow_the_distribution() {
+    let (mut server, peer) = raw_peer_with(config());
+    // 19 fast exchanges (answered right away) & one slow one
+    let mut delays = vec![Duration::from_millis(0); 19];
+    delays.push(DELAY);
+    let peer = answer_after(peer, delays);
+    for _ in 0..20 {
+        let response = server
+            .transaction()
+            .send_and_wait(DATA, b"request", REPLY, TIMEOUT)
+            .unwrap();
+        assert!(response.is_some());
+    }
+    let stats = server.stats();
+    assert_eq!(stats.exchange_latencies.iter().sum::<u64>(), 20);
+    assert!(stats.exchange_latency_p50().unwrap() < DELAY);
+    assert!(stats.exchange_latency_p95().unwrap() < DELAY);
+    assert!(stats.max_exchange_latency >= DELAY);
+    drop(server);
+    peer.join().unwrap();
+}
@@ -0,0 +1,71 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::Write;
+use std::net::TcpListener;
+
+// a plain server which sends the given bytes to its first client & exits, and a client connected to it
+fn server_sending(bytes: Vec<u8>, config: TcpIpcConfig<TestProtocol>) -> TcpIpc<TestProtocol> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&bytes).unwrap();
+    });
+    let client = TcpIpc::client(address, config, Some(TIMEOUT)).unwrap();
+    server.join().unwrap();
+    client
+}
+
+// the close is reported once, then the connection stays closed
+fn expect_peer_closed_once(
+    client: &mut TcpIpc<TestProtocol>,
+) -> Option<TruncatedFrame<TestProtocol>> {
+    let mid_frame = match expect_error(client) {
+        ReadThreadErrors::PeerClosed { mid_frame } => mid_frame,
+        error => panic!("expected PeerClosed, found {:?}", error),
+    };
+    for _ in 0..10 {
+        assert!(matches!(
+            client.get_message(),
+            Err(ReadThreadErrors::ConnectionClosed)
+        ));
+    }
+    assert_eq!(client.connection_state(), ConnectionState::PeerClosed);
+    mid_frame
+}
+
+#[test]
+fn the_message_is_delivered_before_the_close() {
+    let mut client = server_sending(frame(DATA, b"only"), config());
+    expect_payload(&mut client, DATA, b"only", TIMEOUT);
+    assert_eq!(expect_peer_closed_once(&mut client), None);
+}
+
+#[test]
+fn a_partial_message_is_reported_by_default() {
+    let mut bytes = frame(DATA, b"only");
+    bytes.extend_from_slice(&frame(DATA, b"cut off")[..12]);
+    let mut client = server_sending(bytes, config());
+    expect_payload(&mut client, DATA, b"only", TIMEOUT);
+    let mid_frame = expect_peer_closed_once(&mut client).expect("no truncated frame");
+    assert_eq!(mid_frame.command, Some(DATA));
+    assert_eq!(mid_frame.received, 3);
+    assert_eq!(mid_frame.declared, Some(7));
+}
+
+#[test]
+fn a_partial_message_can_be_dropped() {
+    let mut bytes = frame(DATA, b"only");
+    bytes.extend_from_slice(&frame(DATA, b"cut off")[..12]);
+    let mut client = server_sending(
+        bytes,
+        TcpIpcConfig {
+            on_truncated_frame: TruncatedFramePolicy::Drop,
+            ..config()
+        },
+    );
+    expect_payload(&mut client, DATA, b"only", TIMEOUT);
+    assert_eq!(expect_peer_closed_once(&mut client), None);
+}
@@ -0,0 +1,108 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::journal::*;
+use rust_tcp_ipc::replay::*;
+use rust_tcp_ipc::*;
+use std::path::PathBuf;
+
+/// A journal of version 1 (no flags byte): frame 0 was completed, frame 1 is pending.
+const JOURNAL_V1: &str = "tests/fixtures/journal_v1.bin";
+/// A capture of version 1 (no header line).
+const CAPTURE_V1: &str = "tests/fixtures/capture_v1.txt";
+
+// a copy of a fixture (or new content) in the temporary directory
+fn temp_file(test: &str, content: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "rust_tcp_ipc-{}-{}.version",
+        std::process::id(),
+        test
+    ));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn unsupported_version(err: std::io::Error) -> UnsupportedFormatVersion {
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<UnsupportedFormatVersion>())
+        .unwrap_or_else(|| panic!("unexpected error {:?}", err))
+        .clone()
+}
+
+#[test]
+fn a_version_1_journal_is_recovered() {
+    let pending = recover(JOURNAL_V1).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, JournalId(1));
+    assert_eq!(
+        pending[0].message::<TestProtocol>(),
+        Some((DATA, b"second".to_vec()))
+    );
+}
+
+#[test]
+fn a_version_1_journal_is_migrated_when_opened() {
+    let path = temp_file("migrated", &std::fs::read(JOURNAL_V1).unwrap());
+    let (mut server, _peer) = raw_peer_with(TcpIpcConfig {
+        journal: Some(JournalConfig {
+            path: path.clone(),
+            fsync: false,
+            codec: None,
+        }),
+        ..config()
+    });
+    // the journal is opened by the first journaled write, whose id continues the ids of the file
+    assert!(server.write_message_journaled(DATA, b"next").unwrap() > JournalId(1));
+    drop(server);
+    // the pending frame is kept in the current version, with its flags byte
+    assert!(std::fs::read(&path).unwrap().starts_with(b"TIJ2\0"));
+    assert_eq!(recover(&path).unwrap(), recover(JOURNAL_V1).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_newer_journal_is_rejected() {
+    let mut content = std::fs::read(JOURNAL_V1).unwrap();
+    content[3] = b'3';
+    let path = temp_file("journal-v3", &content);
+    assert_eq!(
+        unsupported_version(recover(&path).unwrap_err()),
+        UnsupportedFormatVersion {
+            found: 3,
+            supported: &[1, 2],
+        }
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_version_1_capture_is_loaded() {
+    let records = load_capture(CAPTURE_V1).unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[2].direction, FrameDirection::Sent);
+    let report = parse_capture::<TestProtocol>(&records);
+    assert!(report.is_clean());
+    assert_eq!(report.messages[0].message, (DATA, b"hi".to_vec()));
+
+    // saving writes the current version, which reads back the same
+    let path = temp_file("capture-v2", b"");
+    save_capture(&path, &records, None).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.starts_with(&format!("{} {} plain\n", CAPTURE_HEADER, CAPTURE_VERSION)));
+    assert_eq!(load_capture(&path).unwrap(), records);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_newer_capture_is_rejected() {
+    let content = format!("{} 3 plain\n< 04\n", CAPTURE_HEADER);
+    let path = temp_file("capture-v3", content.as_bytes());
+    assert_eq!(
+        unsupported_version(load_capture(&path).unwrap_err()),
+        UnsupportedFormatVersion {
+            found: 3,
+            supported: &[1, 2],
+        }
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,48 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+
+#[test]
+fn buckets_are_powers_of_two() {
+    assert_eq!(frame_size_bucket(0), 0);
+    assert_eq!(frame_size_bucket(1), 1);
+    assert_eq!(frame_size_bucket(2), 2);
+    assert_eq!(frame_size_bucket(3), 2);
+    assert_eq!(frame_size_bucket(4), 3);
+    assert_eq!(frame_size_bucket((1 << 30) - 1), 30);
+    assert_eq!(frame_size_bucket(1 << 30), FRAME_SIZE_BUCKETS - 1);
+    assert_eq!(frame_size_bucket(usize::MAX), FRAME_SIZE_BUCKETS - 1);
+}
+
+#[test]
+fn frame_sizes_are_counted_on_both_sides() {
+    let (mut server, mut client) = pair();
+    // the header takes 9 bytes, so the frames take 9, 16, 64 & 1009 bytes
+    for payload in &[&[][..], &[1; 7][..], &[2; 55][..], &[3; 1000][..]] {
+        client.write_message(DATA, payload).unwrap();
+        expect_payload(&mut server, DATA, payload, TIMEOUT);
+    }
+    // answered by a reply of 32 bytes
+    client.write_message(QUERY, &[4; 23]).unwrap();
+    expect_payload(&mut client, REPLY, &[4; 23], TIMEOUT);
+
+    let mut expected = [0; FRAME_SIZE_BUCKETS];
+    for &bucket in &[4, 5, 6, 7, 10] {
+        expected[bucket] = 1;
+    }
+    let mut reply = [0; FRAME_SIZE_BUCKETS];
+    reply[6] = 1;
+
+    let client_stats = client.stats();
+    assert_eq!(client_stats.sent_frame_sizes, expected);
+    assert_eq!(client_stats.max_sent_frame, 1009);
+    assert_eq!(client_stats.received_frame_sizes, reply);
+    assert_eq!(client_stats.max_received_frame, 32);
+
+    let server_stats = server.diagnostics().stats;
+    assert_eq!(server_stats.received_frame_sizes, expected);
+    assert_eq!(server_stats.max_received_frame, 1009);
+    assert_eq!(server_stats.sent_frame_sizes, reply);
+    assert_eq!(server_stats.max_sent_frame, 32);
+}
@@ -0,0 +1,89 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+type Tapped = Arc<Mutex<Vec<(FrameDirection, u8, Vec<u8>)>>>;
+
+// a config whose tap records every frame
+fn tapped() -> (TcpIpcConfig<TestProtocol>, Tapped) {
+    let tapped = Tapped::default();
+    let recorded = tapped.clone();
+    let config = TcpIpcConfig {
+        frame_tap: Some(Arc::new(
+            move |direction: FrameDirection, command: &u8, payload: &[u8]| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((direction, *command, payload.to_vec()))
+            },
+        )),
+        ..config()
+    };
+    (config, tapped)
+}
+
+// reads the next frame from the peer & checks it
+fn read_frame(peer: &mut std::net::TcpStream, expected: Vec<u8>) {
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn the_tap_sees_the_frames_the_peer_sees() {
+    let (tapped_config, tapped) = tapped();
+    let (mut server, mut peer) = raw_peer_with(tapped_config);
+    // the immediate response is tapped like any other frame
+    peer.write_all(&frame(QUERY, b"q")).unwrap();
+    read_frame(&mut peer, frame(REPLY, b"q"));
+    peer.write_all(&frame(DATA, b"in")).unwrap();
+    expect_payload(&mut server, DATA, b"in", TIMEOUT);
+    server.write_message(DATA, b"out").unwrap();
+    read_frame(&mut peer, frame(DATA, b"out"));
+
+    assert_eq!(
+        *tapped.lock().unwrap(),
+        vec![
+            (FrameDirection::Received, QUERY, b"q".to_vec()),
+            (FrameDirection::Sent, REPLY, b"q".to_vec()),
+            (FrameDirection::Received, DATA, b"in".to_vec()),
+            (FrameDirection::Sent, DATA, b"out".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn batches_are_tapped_frame_by_frame() {
+    let (tapped_config, tapped) = tapped();
+    let (_server, mut client) = pair_with(config(), tapped_config);
+    client
+        .write_messages(&[(DATA, &b"first"[..]), (DATA, &b"second"[..])])
+        .unwrap();
+    assert_eq!(
+        *tapped.lock().unwrap(),
+        vec![
+            (FrameDirection::Sent, DATA, b"first".to_vec()),
+            (FrameDirection::Sent, DATA, b"second".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn a_panicking_tap_does_not_break_the_connection() {
+    let server_config = TcpIpcConfig {
+        frame_tap: Some(Arc::new(|_: FrameDirection, _: &u8, _: &[u8]| {
+            panic!("the tap failed")
+        })),
+        ..config()
+    };
+    let (mut server, mut client) = pair_with(server_config, config());
+    client.write_message(QUERY, b"q").unwrap();
+    client.write_message(DATA, b"in").unwrap();
+    expect_payload(&mut client, REPLY, b"q", TIMEOUT);
+    expect_payload(&mut server, DATA, b"in", TIMEOUT);
+    server.write_message(DATA, b"out").unwrap();
+    expect_payload(&mut client, DATA, b"out", TIMEOUT);
+}
@@ -0,0 +1,118 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::Write;
+
+fn traced() -> Option<TraceConfig> {
+    Some(TraceConfig {
+        frames: 10,
+        bytes_per_frame: 4,
+    })
+}
+
+#[test]
+fn the_outgoing_trace_keeps_the_latest_frames() {
+    let client_config = TcpIpcConfig {
+        outgoing_trace: traced(),
+        ..config()
+    };
+    let (mut server, mut client) = pair_with(config(), client_config);
+    for i in 0..20u8 {
+        client.write_message(DATA, &[i; 6]).unwrap();
+    }
+    for i in 0..20u8 {
+        expect_payload(&mut server, DATA, &[i; 6], TIMEOUT);
+    }
+
+    let trace = client.outgoing_trace();
+    assert_eq!(trace.len(), 10);
+    for (entry, i) in trace.iter().zip(10..20u8) {
+        assert_eq!(entry.command, DATA);
+        assert_eq!(entry.length, 6);
+        // the payload is truncated to 'bytes_per_frame'
+        assert_eq!(entry.bytes, vec![i; 4]);
+    }
+    assert!(trace.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    assert_eq!(client.diagnostics().outgoing_trace, trace);
+}
+
+#[test]
+fn the_outgoing_trace_includes_immediate_responses() {
+    let server_config = TcpIpcConfig {
+        outgoing_trace: traced(),
+        ..config()
+    };
+    let (server, mut client) = pair_with(server_config, config());
+    client.write_message(QUERY, b"ab").unwrap();
+    expect_payload(&mut client, REPLY, b"ab", TIMEOUT);
+
+    let trace = server.outgoing_trace();
+    assert_eq!(trace.len(), 1);
+    assert_eq!((trace[0].command, trace[0].length), (REPLY, 2));
+    assert_eq!(trace[0].bytes, b"ab");
+}
+
+#[test]
+fn the_outgoing_trace_is_empty_unless_enabled() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"untraced").unwrap();
+    expect_payload(&mut server, DATA, b"untraced", TIMEOUT);
+    assert!(client.outgoing_trace().is_empty());
+    assert!(client.diagnostics().outgoing_trace.is_empty());
+}
+
+#[test]
+fn the_incoming_trace_records_what_happened_to_each_frame() {
+    let server_config = TcpIpcConfig {
+        incoming_trace: traced(),
+        reliability: Some(ReliabilityConfig {
+            max_unacknowledged: 4,
+            dedup_window: 16,
+        }),
+        ..config()
+    };
+    let (mut server, mut peer) = raw_peer_with(server_config);
+    let mut reliable = 1u64.to_be_bytes().to_vec();
+    reliable.extend_from_slice(b"r");
+    let frames = [
+        frame(DATA, b"delivered"),
+        frame(QUERY, b"answered"),
+        frame(FAULTY_QUERY, b"failed"),
+        frame(RELIABLE, &reliable),
+        // the repeated reliable message is a duplicate
+        frame(RELIABLE, &reliable),
+        frame(ACK, &7u64.to_be_bytes()),
+    ];
+    for frame in &frames {
+        peer.write_all(frame).unwrap();
+    }
+    await_condition(|| server.incoming_trace().len() == frames.len());
+
+    let recorded: Vec<_> = server
+        .incoming_trace()
+        .iter()
+        .map(|entry| (entry.command, entry.length, entry.disposition))
+        .collect();
+    assert_eq!(
+        recorded,
+        vec![
+            (DATA, 9, FrameDisposition::Delivered),
+            (QUERY, 8, FrameDisposition::AnsweredImmediately),
+            (FAULTY_QUERY, 6, FrameDisposition::Failed),
+            (RELIABLE, 9, FrameDisposition::Delivered),
+            (RELIABLE, 9, FrameDisposition::Dropped),
+            (ACK, 8, FrameDisposition::Acknowledgment),
+        ]
+    );
+    assert_eq!(server.incoming_trace()[0].bytes, b"deli");
+    assert_eq!(server.diagnostics().incoming_trace, server.incoming_trace());
+
+    // only the delivered frames reach the consumer, besides the error of the failed one
+    expect_payload(&mut server, DATA, b"delivered", TIMEOUT);
+    assert!(matches!(
+        expect_error(&mut server),
+        ReadThreadErrors::ImmediateMessageConstructError(_)
+    ));
+    expect_payload(&mut server, RELIABLE, b"r", TIMEOUT);
+}
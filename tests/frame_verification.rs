@@ -0,0 +1,104 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Read;
+
+/// The test protocol, except that the declared length is one byte too long.
+#[derive(Debug)]
+enum OffByOneProtocol {}
+impl Protocol for OffByOneProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(_command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        Some((message.len() as u64 + 1).to_be_bytes())
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+// returns a connection with the flawed protocol & the plain TCP stream of its peer
+fn flawed_connection(
+    verify_frames: Option<bool>,
+) -> (TcpIpc<OffByOneProtocol>, std::net::TcpStream) {
+    let listener = TcpIpc::<OffByOneProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || std::net::TcpStream::connect(address).unwrap());
+    let config = TcpIpcConfig {
+        verify_frames,
+        ..TcpIpcConfig::default()
+    };
+    let ipc = listener.accept(config).unwrap();
+    let peer = peer.join().unwrap();
+    peer.set_read_timeout(Some(std::time::Duration::from_millis(100)))
+        .unwrap();
+    (ipc, peer)
+}
+
+#[test]
+fn inconsistent_frames_are_rejected_before_writing() {
+    let (mut ipc, mut peer) = flawed_connection(Some(true));
+    assert!(matches!(
+        ipc.write_message(DATA, b"poisoned"),
+        Err(WriteMessageErrors::ProtocolInconsistency {
+            declared: Some(9),
+            actual: 8
+        })
+    ));
+    assert!(matches!(
+        ipc.write_messages(&[(DATA, b"poisoned")]),
+        Err(BatchWriteErrors::ConstructionFailed {
+            index: 0,
+            error: WriteMessageErrors::ProtocolInconsistency { .. }
+        })
+    ));
+    let mut received = [0; 1];
+    assert!(peer.read(&mut received).is_err(), "bytes hit the wire");
+    assert_eq!(ipc.stats().bytes_sent, 0);
+}
+
+#[test]
+fn verification_can_be_skipped() {
+    let (mut ipc, mut peer) = flawed_connection(Some(false));
+    ipc.write_message(DATA, b"poisoned").unwrap();
+    let mut received = [0; 17];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received[1..9], 9u64.to_be_bytes());
+}
+
+#[test]
+fn verification_is_on_in_debug_builds_by_default() {
+    let (mut ipc, _peer) = flawed_connection(None);
+    assert_eq!(
+        ipc.write_message(DATA, b"poisoned").is_err(),
+        cfg!(debug_assertions)
+    );
+}
@@ -0,0 +1,105 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+const DELAY: Duration = Duration::from_millis(50);
+
+#[test]
+fn nothing_is_returned_once_the_timeout_passed() {
+    let (_server, mut client) = pair();
+    let start = Instant::now();
+    assert_eq!(client.get_message_blocking(Some(DELAY)).unwrap(), None);
+    assert!(start.elapsed() >= DELAY);
+    assert!(start.elapsed() < TIMEOUT);
+}
+
+#[test]
+fn a_message_ends_the_wait_right_away() {
+    let (mut server, mut client) = pair();
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(DELAY);
+        server.write_message(DATA, b"late").unwrap();
+        server
+    });
+    let start = Instant::now();
+    assert_eq!(
+        client.get_message_blocking(Some(TIMEOUT)).unwrap(),
+        Some((DATA, b"late".to_vec()))
+    );
+    assert!(start.elapsed() >= DELAY);
+    assert!(start.elapsed() < TIMEOUT / 2, "took {:?}", start.elapsed());
+    let mut server = writer.join().unwrap();
+
+    // without a timeout, the wait ends with the message as well
+    server.write_message(DATA, b"unbounded").unwrap();
+    assert_eq!(
+        client.get_message_blocking(None).unwrap(),
+        Some((DATA, b"unbounded".to_vec()))
+    );
+}
+
+#[test]
+fn queued_messages_come_before_an_error() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"first").unwrap();
+    server.write_message(DATA, b"second").unwrap();
+    drop(server);
+    await_condition(|| client.is_connection_closed());
+    assert_eq!(
+        client.get_message_blocking(Some(TIMEOUT)).unwrap(),
+        Some((DATA, b"first".to_vec()))
+    );
+    assert_eq!(
+        client.get_message_blocking(Some(TIMEOUT)).unwrap(),
+        Some((DATA, b"second".to_vec()))
+    );
+    // the error was queued before the wait, so it is returned without waiting for the timeout
+    let start = Instant::now();
+    assert!(matches!(
+        client.get_message_blocking(Some(TIMEOUT)),
+        Err(ReadThreadErrors::PeerClosed { .. })
+    ));
+    assert!(matches!(
+        client.get_message_blocking(None),
+        Err(ReadThreadErrors::ConnectionClosed)
+    ));
+    assert!(start.elapsed() < TIMEOUT / 2, "took {:?}", start.elapsed());
+}
+
+#[test]
+fn an_error_while_waiting_ends_the_wait() {
+    let (server, mut client) = pair();
+    let closer = std::thread::spawn(move || {
+        std::thread::sleep(DELAY);
+        drop(server);
+    });
+    let start = Instant::now();
+    assert!(matches!(
+        client.get_message_blocking(Some(TIMEOUT)),
+        Err(ReadThreadErrors::PeerClosed { .. })
+    ));
+    assert!(start.elapsed() < TIMEOUT / 2, "took {:?}", start.elapsed());
+    closer.join().unwrap();
+}
+
+#[test]
+fn an_await_is_not_delayed_by_its_iteration_wait_time() {
+    let (mut server, mut client) = pair();
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(DELAY);
+        server.write_message(DATA, b"late").unwrap();
+        server
+    });
+    let start = Instant::now();
+    let message = client
+        .await_message(TIMEOUT, Some(Duration::from_secs(2)))
+        .unwrap();
+    assert_eq!(message, Some((DATA, b"late".to_vec())));
+    assert!(
+        start.elapsed() < Duration::from_secs(1),
+        "took {:?}",
+        start.elapsed()
+    );
+    let _server = writer.join().unwrap();
+}
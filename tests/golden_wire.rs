@@ -0,0 +1,92 @@
+#[path = "../benches/example_protocol.rs"]
+mod example_protocol;
+use example_protocol::*;
+use rust_tcp_ipc::testing::*;
+use std::path::PathBuf;
+
+const SNAPSHOT: &str = "tests/snapshots/example_protocol.wire";
+
+fn exchanges() -> Vec<(CommandsExample, Vec<u8>)> {
+    vec![
+        (CommandsExample::Start, vec![]),
+        (CommandsExample::Start, vec![b'a']),
+        (CommandsExample::Funny, vec![b'a', 0, 1, 2, 3, 4]),
+        // a length above 255, to pin the byte order of the length
+        (CommandsExample::Funny, (0..300).map(|i| i as u8).collect()),
+    ]
+}
+
+// a copy of the snapshot in the temporary directory, which a test may change
+fn snapshot_copy(test: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("rust_tcp_ipc-{}-{}.wire", std::process::id(), test));
+    std::fs::copy(SNAPSHOT, &path).unwrap();
+    path
+}
+
+// the panic message of the given closure
+fn panic_message(f: impl FnOnce() + std::panic::UnwindSafe) -> String {
+    let payload = std::panic::catch_unwind(f).expect_err("no panic");
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+    }
+}
+
+#[test]
+fn the_example_protocol_is_unchanged() {
+    assert_wire_matches_file::<ProtocolExample, _>(
+        SNAPSHOT,
+        &exchanges(),
+        std::env::var_os("UPDATE_WIRE_SNAPSHOTS").is_some(),
+    );
+}
+
+#[test]
+fn the_snapshot_holds_the_constructed_frames() {
+    let mut expected = vec![0, 0, 0, b'0', b'0'];
+    expected.extend([0, 0, 1, b'0', b'0', b'a']);
+    assert_eq!(
+        wire_snapshot::<ProtocolExample>(&exchanges()[..2]),
+        expected
+    );
+}
+
+#[test]
+fn a_changed_byte_is_reported_with_a_diff() {
+    let path = snapshot_copy("changed");
+    let mut changed = exchanges();
+    changed[2].1[3] = 0x99;
+    let message =
+        panic_message(|| assert_wire_matches_file::<ProtocolExample, _>(&path, &changed, false));
+    // only the row with the changed byte is shown
+    assert!(message.contains("- 00000010: "), "{}", message);
+    assert!(message.contains("+ 00000010: "), "{}", message);
+    assert!(message.contains(" 99"), "{}", message);
+    assert_eq!(message.matches("- 000").count(), 1, "{}", message);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn an_update_rewrites_the_snapshot() {
+    let path = snapshot_copy("updated");
+    let mut changed = exchanges();
+    changed.push((CommandsExample::Start, b"new".to_vec()));
+    assert_wire_matches_file::<ProtocolExample, _>(&path, &changed, true);
+    assert_wire_matches_file::<ProtocolExample, _>(&path, &changed, false);
+    let message = panic_message(|| {
+        assert_wire_matches_file::<ProtocolExample, _>(&path, &exchanges(), false)
+    });
+    assert!(message.contains("bytes expected"), "{}", message);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_missing_snapshot_fails_without_update() {
+    let path =
+        std::env::temp_dir().join(format!("rust_tcp_ipc-{}-missing.wire", std::process::id()));
+    let message = panic_message(|| {
+        assert_wire_matches_file::<ProtocolExample, _>(&path, &exchanges(), false)
+    });
+    assert!(message.contains("does not exist"), "{}", message);
+}
@@ -0,0 +1,114 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+
+/// The test protocol, whose error frames are delivered ahead of the queued messages.
+#[derive(Debug)]
+enum ErrorFirstProtocol {}
+impl Protocol for ErrorFirstProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn is_high_priority(command: &u8) -> bool {
+        *command == ERROR
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+const BACKLOG: u16 = 1000;
+
+type Pair = (TcpIpc<ErrorFirstProtocol>, TcpIpc<ErrorFirstProtocol>);
+
+// the client sends a backlog of regular frames, the given errors & a last regular frame, which the server has forwarded once this returns
+fn backlog_with_errors(errors: &[&[u8]]) -> Pair {
+    let config = || TcpIpcConfig {
+        read_iteration_wait_time: Some(std::time::Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    };
+    let (server, mut client) = loopback::<ErrorFirstProtocol>(config(), config()).unwrap();
+    for i in 0..BACKLOG {
+        client.write_message(DATA, &i.to_be_bytes()).unwrap();
+    }
+    for error in errors {
+        client.write_message(ERROR, error).unwrap();
+    }
+    client.write_message(DATA, b"last").unwrap();
+    // the errors were forwarded before the last frame was parsed
+    let frames = u64::from(BACKLOG) + errors.len() as u64 + 1;
+    await_condition(|| server.stats().messages_received == frames);
+    (server, client)
+}
+
+// checks that the regular messages follow in order
+fn expect_backlog(server: &mut TcpIpc<ErrorFirstProtocol>) {
+    for i in 0..BACKLOG {
+        expect_payload(server, DATA, &i.to_be_bytes(), TIMEOUT);
+    }
+    expect_payload(server, DATA, b"last", TIMEOUT);
+}
+
+#[test]
+fn an_error_is_returned_by_the_next_get_message() {
+    let (mut server, _client) = backlog_with_errors(&[b"overheated"]);
+    assert_eq!(
+        server.get_message().unwrap(),
+        Some((ERROR, b"overheated".to_vec()))
+    );
+    expect_backlog(&mut server);
+    assert_eq!(server.get_message().unwrap(), None);
+}
+
+#[test]
+fn an_error_is_returned_by_the_next_await() {
+    let (mut server, _client) = backlog_with_errors(&[b"overheated"]);
+    assert_eq!(
+        server.await_message(TIMEOUT, None).unwrap(),
+        Some((ERROR, b"overheated".to_vec()))
+    );
+    expect_backlog(&mut server);
+}
+
+#[test]
+fn errors_keep_their_order() {
+    let (mut server, _client) = backlog_with_errors(&[b"first", b"second", b"third"]);
+    for expected in &[&b"first"[..], b"second", b"third"] {
+        assert_eq!(
+            server.get_message().unwrap(),
+            Some((ERROR, expected.to_vec()))
+        );
+    }
+    expect_backlog(&mut server);
+}
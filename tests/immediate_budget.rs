@@ -0,0 +1,143 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A query whose immediate response takes 'SLOWNESS' to decide.
+const SLOW_QUERY: u8 = 0x51;
+const SLOWNESS: Duration = Duration::from_millis(5);
+const BUDGET: Duration = Duration::from_millis(1);
+
+/// The test protocol, with a slow responder.
+#[derive(Debug)]
+enum SlowProtocol {}
+impl Protocol for SlowProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        if *command == SLOW_QUERY {
+            std::thread::sleep(SLOWNESS);
+            return Some((REPLY, message.to_vec()));
+        }
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+type Exceeded = Arc<Mutex<Vec<(u8, Duration)>>>;
+
+// connects a client to a server with the given budget, whose hook records the exceeded budgets
+fn budgeted(demote: bool) -> (TcpIpc<SlowProtocol>, TcpIpc<SlowProtocol>, Exceeded) {
+    let exceeded = Exceeded::default();
+    let recorded = exceeded.clone();
+    let server_config = TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        immediate_response_budget: Some(ImmediateResponseBudget {
+            budget: BUDGET,
+            demote,
+            on_exceeded: Some(Arc::new(move |command: &u8, elapsed| {
+                recorded.lock().unwrap().push((*command, elapsed))
+            })),
+        }),
+        ..TcpIpcConfig::default()
+    };
+    let client_config = TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    };
+    let (server, client) = loopback(server_config, client_config).unwrap();
+    (server, client, exceeded)
+}
+
+#[test]
+fn a_slow_responder_is_reported() {
+    let (server, mut client, exceeded) = budgeted(false);
+    client.write_message(QUERY, b"fast").unwrap();
+    expect_payload(&mut client, REPLY, b"fast", TIMEOUT);
+    client.write_message(SLOW_QUERY, b"slow").unwrap();
+    expect_payload(&mut client, REPLY, b"slow", TIMEOUT);
+
+    let stats = server.stats();
+    assert_eq!(stats.immediate_route_checks, 2);
+    assert_eq!(stats.immediate_route_over_budget, 1);
+    assert!(stats.max_immediate_route_time >= SLOWNESS);
+    assert!(stats.immediate_route_time >= stats.max_immediate_route_time);
+    assert_eq!(
+        stats.mean_immediate_route_time(),
+        Some(stats.immediate_route_time / 2)
+    );
+    // a copy, since the hook locks the record on the read thread
+    let recorded = exceeded.lock().unwrap().clone();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].0, SLOW_QUERY);
+    assert!(recorded[0].1 >= SLOWNESS);
+
+    // the slow command is still answered immediately
+    client.write_message(SLOW_QUERY, b"again").unwrap();
+    expect_payload(&mut client, REPLY, b"again", TIMEOUT);
+}
+
+#[test]
+fn a_demoted_command_is_delivered_instead() {
+    let (mut server, mut client, exceeded) = budgeted(true);
+    client.write_message(SLOW_QUERY, b"first").unwrap();
+    expect_payload(&mut client, REPLY, b"first", TIMEOUT);
+    client.write_message(SLOW_QUERY, b"second").unwrap();
+    expect_payload(&mut server, SLOW_QUERY, b"second", TIMEOUT);
+    assert_eq!(exceeded.lock().unwrap().len(), 1);
+    // other commands keep their immediate route
+    client.write_message(QUERY, b"fast").unwrap();
+    expect_payload(&mut client, REPLY, b"fast", TIMEOUT);
+}
+
+#[test]
+fn without_a_budget_nothing_is_timed() {
+    let (server, mut client) = loopback::<SlowProtocol>(
+        TcpIpcConfig {
+            read_iteration_wait_time: Some(Duration::from_micros(10)),
+            ..TcpIpcConfig::default()
+        },
+        TcpIpcConfig {
+            read_iteration_wait_time: Some(Duration::from_micros(10)),
+            ..TcpIpcConfig::default()
+        },
+    )
+    .unwrap();
+    client.write_message(SLOW_QUERY, b"slow").unwrap();
+    expect_payload(&mut client, REPLY, b"slow", TIMEOUT);
+    let stats = server.stats();
+    assert_eq!(stats.immediate_route_checks, 0);
+    assert_eq!(stats.mean_immediate_route_time(), None);
+}
@@ -0,0 +1,70 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::Duration;
+
+fn server_with(
+    policy: ImmediateFailurePolicy<TestProtocol>,
+) -> (TcpIpc<TestProtocol>, TcpIpc<TestProtocol>) {
+    pair_with(
+        TcpIpcConfig {
+            on_immediate_construct_failure: policy,
+            ..config()
+        },
+        config(),
+    )
+}
+
+fn expect_construct_error(server: &mut TcpIpc<TestProtocol>) {
+    match expect_error(server) {
+        ReadThreadErrors::ImmediateMessageConstructError(_) => {}
+        err => panic!("expected ImmediateMessageConstructError, found {:?}", err),
+    }
+}
+
+#[test]
+fn report_only_reports_and_keeps_the_connection() {
+    let (mut server, mut client) = server_with(ImmediateFailurePolicy::ReportOnly);
+    client.write_message(FAULTY_QUERY, b"?").unwrap();
+    expect_construct_error(&mut server);
+    expect_silence(&mut client, Duration::from_millis(100));
+
+    client.write_message(DATA, b"still open").unwrap();
+    expect_payload(&mut server, DATA, b"still open", TIMEOUT);
+}
+
+#[test]
+fn send_fallback_frame_answers_with_the_fallback() {
+    let (mut server, mut client) = server_with(ImmediateFailurePolicy::SendFallbackFrame(
+        ERROR,
+        b"failed".to_vec(),
+    ));
+    client.write_message(FAULTY_QUERY, b"?").unwrap();
+    expect_payload(&mut client, ERROR, b"failed", TIMEOUT);
+    expect_construct_error(&mut server);
+
+    client.write_message(DATA, b"still open").unwrap();
+    expect_payload(&mut server, DATA, b"still open", TIMEOUT);
+}
+
+#[test]
+fn unconstructible_fallback_frame_closes_the_connection() {
+    let (mut server, mut client) = server_with(ImmediateFailurePolicy::SendFallbackFrame(
+        UNCONSTRUCTIBLE,
+        vec![],
+    ));
+    client.write_message(FAULTY_QUERY, b"?").unwrap();
+    expect_closed(&mut client);
+    expect_construct_error(&mut server);
+    assert!(server.is_connection_closed());
+}
+
+#[test]
+fn close_connection_closes_the_connection() {
+    let (mut server, mut client) = server_with(ImmediateFailurePolicy::CloseConnection);
+    client.write_message(FAULTY_QUERY, b"?").unwrap();
+    expect_closed(&mut client);
+    expect_construct_error(&mut server);
+    assert!(server.is_connection_closed());
+}
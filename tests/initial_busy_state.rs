@@ -0,0 +1,91 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+
+/// Asks for the busy state, which is answered via the immediate route.
+const BUSY_QUERY: u8 = 7;
+/// The answer to 'BUSY_QUERY', whose payload is the busy state.
+const BUSY_REPLY: u8 = 8;
+/// The busy state of a connection which is still initializing.
+const WORKING: u8 = 9;
+
+/// The test protocol, except that 'BUSY_QUERY' is answered with the busy state.
+#[derive(Debug)]
+enum BusyProtocol {}
+impl Protocol for BusyProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        _message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        if *command == BUSY_QUERY {
+            Some((BUSY_REPLY, vec![*busy_state]))
+        } else {
+            None
+        }
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+// accepts a peer which sends 'BUSY_QUERY' as soon as it is connected, & returns the answer it got
+fn first_answer(initial_busy_state: Option<u8>) -> (TcpIpc<BusyProtocol>, Vec<u8>) {
+    let listener = TcpIpc::<BusyProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || {
+        let mut peer = std::net::TcpStream::connect(address).unwrap();
+        peer.write_all(&frame(BUSY_QUERY, &[])).unwrap();
+        peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut answer = vec![0; frame(BUSY_REPLY, &[0]).len()];
+        peer.read_exact(&mut answer).unwrap();
+        answer
+    });
+    let config = TcpIpcConfig {
+        initial_busy_state,
+        ..TcpIpcConfig::default()
+    };
+    let server = listener.accept(config).unwrap();
+    (server, peer.join().unwrap())
+}
+
+#[test]
+fn the_first_message_sees_the_initial_busy_state() {
+    let (mut server, answer) = first_answer(Some(WORKING));
+    assert_eq!(answer, frame(BUSY_REPLY, &[WORKING]));
+    assert_eq!(server.get_busy_state().unwrap(), WORKING);
+}
+
+#[test]
+fn the_initial_busy_state_is_idle_by_default() {
+    let (mut server, answer) = first_answer(None);
+    assert_eq!(answer, frame(BUSY_REPLY, &[TestProtocol::idle()]));
+    assert_eq!(server.get_busy_state().unwrap(), TestProtocol::idle());
+}
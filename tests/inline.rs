@@ -0,0 +1,152 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+const CONNECTION: mio::Token = mio::Token(0);
+
+// an inline connection, registered at its own poll, & a plain TCP stream as its peer
+struct EventLoop {
+    poll: mio::Poll,
+    events: mio::Events,
+    connection: TcpIpcInline<TestProtocol>,
+}
+impl EventLoop {
+    fn new() -> (Self, std::net::TcpStream) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let stream = mio::net::TcpStream::from_stream(stream).unwrap();
+        let connection = TcpIpcInline::<TestProtocol>::from_transport(stream, config()).unwrap();
+        let poll = mio::Poll::new().unwrap();
+        connection.register(&poll, CONNECTION).unwrap();
+        let event_loop = Self {
+            poll,
+            events: mio::Events::with_capacity(16),
+            connection,
+        };
+        (event_loop, peer)
+    }
+    // runs the loop until the given number of messages was received, or an error
+    fn receive(
+        &mut self,
+        count: usize,
+    ) -> Result<Vec<Message<TestProtocol>>, ReadThreadErrors<TestProtocol>> {
+        let start = Instant::now();
+        let mut received = Vec::new();
+        while received.len() < count {
+            assert!(start.elapsed() < TIMEOUT, "received only {:?}", received);
+            self.poll
+                .poll(&mut self.events, Some(Duration::from_millis(10)))
+                .unwrap();
+            for event in &self.events {
+                assert_eq!(event.token(), CONNECTION);
+                if event.readiness().is_writable() {
+                    self.connection.handle_writable();
+                }
+                if event.readiness().is_readable() {
+                    received.extend(self.connection.handle_readable()?);
+                }
+            }
+        }
+        Ok(received)
+    }
+}
+
+fn read_frame(peer: &mut std::net::TcpStream, expected: &[u8]) {
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn messages_are_delivered_by_the_event_loop() {
+    let (mut event_loop, mut peer) = EventLoop::new();
+    peer.write_all(&frame(DATA, b"first")).unwrap();
+    peer.write_all(&frame(DATA, b"second")).unwrap();
+    assert_eq!(
+        event_loop.receive(2).unwrap(),
+        vec![(DATA, b"first".to_vec()), (DATA, b"second".to_vec())]
+    );
+    assert_eq!(event_loop.connection.stats().messages_received, 2);
+}
+
+#[test]
+fn queries_are_answered_via_the_immediate_route() {
+    let (mut event_loop, mut peer) = EventLoop::new();
+    peer.write_all(&frame(QUERY, b"ping")).unwrap();
+    peer.write_all(&frame(DATA, b"after")).unwrap();
+    // the query is answered, not delivered
+    assert_eq!(
+        event_loop.receive(1).unwrap(),
+        vec![(DATA, b"after".to_vec())]
+    );
+    read_frame(&mut peer, &frame(REPLY, b"ping"));
+}
+
+#[test]
+fn written_messages_reach_the_peer() {
+    let (mut event_loop, mut peer) = EventLoop::new();
+    event_loop.connection.write_message(DATA, b"hello").unwrap();
+    read_frame(&mut peer, &frame(DATA, b"hello"));
+
+    // a message which does not fit into the socket buffers is finished by 'handle_writable'
+    let payload = vec![7; 1 << 22];
+    event_loop.connection.write_message(DATA, &payload).unwrap();
+    let expected = frame(DATA, &payload);
+    let reader = std::thread::spawn(move || read_frame(&mut peer, &expected));
+    let start = Instant::now();
+    while !reader.is_finished() {
+        assert!(start.elapsed() < TIMEOUT, "message not written");
+        event_loop
+            .poll
+            .poll(&mut event_loop.events, Some(Duration::from_millis(10)))
+            .unwrap();
+        event_loop.connection.handle_writable();
+    }
+    reader.join().unwrap();
+}
+
+#[test]
+fn no_thread_is_spawned() {
+    let (mut event_loop, mut peer) = EventLoop::new();
+    peer.write_all(&frame(DATA, b"data")).unwrap();
+    // without calls of the event loop, nothing is read
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(event_loop.connection.stats().bytes_received, 0);
+    assert_eq!(
+        event_loop.receive(1).unwrap(),
+        vec![(DATA, b"data".to_vec())]
+    );
+}
+
+#[test]
+fn a_closed_peer_is_reported_after_its_messages() {
+    let (mut event_loop, mut peer) = EventLoop::new();
+    peer.write_all(&frame(DATA, b"last")).unwrap();
+    assert_eq!(
+        event_loop.receive(1).unwrap(),
+        vec![(DATA, b"last".to_vec())]
+    );
+    drop(peer);
+    assert!(matches!(
+        event_loop.receive(1).unwrap_err(),
+        ReadThreadErrors::PeerClosed { .. } | ReadThreadErrors::ConnectionClosed
+    ));
+}
+
+#[test]
+fn the_busy_state_is_updated_without_a_thread() {
+    let (mut event_loop, _peer) = EventLoop::new();
+    assert_eq!(
+        event_loop.connection.update_busy_state(3),
+        BusyStateUpdateResult::Success
+    );
+    assert!(!event_loop.connection.is_connection_closed());
+    let report = event_loop
+        .connection
+        .shutdown()
+        .expect("shutdown was not clean");
+    assert_eq!(report.abandoned_frames, 0);
+}
@@ -0,0 +1,126 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::journal::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// a fresh journal file for the given test
+fn journal_path(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "rust_tcp_ipc-{}-{}.journal",
+        std::process::id(),
+        test
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn journaled(path: &Path) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        journal: Some(JournalConfig {
+            path: path.to_path_buf(),
+            fsync: false,
+            codec: None,
+        }),
+        ..config()
+    }
+}
+
+#[test]
+fn a_written_frame_is_completed() {
+    let path = journal_path("completed");
+    let (mut server, mut peer) = raw_peer_with(journaled(&path));
+    let first = server.write_message_journaled(DATA, b"open valve").unwrap();
+    let second = server
+        .write_message_journaled(DATA, b"close valve")
+        .unwrap();
+    assert!(first < second);
+
+    // the journaled frames are written like any other
+    let mut expected = frame(DATA, b"open valve");
+    expected.extend(frame(DATA, b"close valve"));
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+    assert_eq!(recover(&path).unwrap(), Vec::new());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn an_unwritten_frame_is_recovered() {
+    let path = journal_path("pending");
+    let (mut server, mut peer) = raw_peer_with(journaled(&path));
+    // the peer does not read yet, so the frame does not fit into the socket buffers
+    let payload = vec![7; 1 << 24];
+    let id = server.write_message_journaled(DATA, &payload).unwrap();
+
+    let pending = recover(&path).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, id);
+    // the journal holds exactly the frame which is written
+    assert_eq!(pending[0].frame, frame(DATA, &payload));
+    assert_eq!(
+        pending[0].message::<TestProtocol>(),
+        Some((DATA, payload.clone()))
+    );
+
+    // once the queue was written, the next journaled write completes both
+    let mut received = vec![0; frame(DATA, &payload).len()];
+    peer.read_exact(&mut received).unwrap();
+    await_condition(|| server.write_pressure().queued_frames == 0);
+    server.write_message_journaled(DATA, b"next").unwrap();
+    assert_eq!(recover(&path).unwrap(), Vec::new());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn pending_frames_can_be_discarded() {
+    let path = journal_path("discarded");
+    let (mut server, _peer) = raw_peer_with(journaled(&path));
+    let id = server
+        .write_message_journaled(DATA, &vec![7; 1 << 24])
+        .unwrap();
+    drop(server);
+    assert_eq!(recover(&path).unwrap().len(), 1);
+    discard(&path, &[id]).unwrap();
+    assert_eq!(recover(&path).unwrap(), Vec::new());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_torn_record_is_ignored() {
+    let path = journal_path("torn");
+    let (mut server, _peer) = raw_peer_with(journaled(&path));
+    server
+        .write_message_journaled(DATA, &vec![7; 1 << 24])
+        .unwrap();
+    drop(server);
+    // a crash while appending leaves a partial record
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&[1, 0, 0, 0]).unwrap();
+    drop(file);
+    let pending = recover(&path).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].frame.len(), frame(DATA, &[7; 1 << 24]).len());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn without_a_journal_nothing_is_written() {
+    let (mut server, _peer) = raw_peer();
+    assert!(matches!(
+        server.write_message_journaled(DATA, b"critical"),
+        Err(WriteMessageErrors::JournalUnavailable)
+    ));
+    assert_eq!(server.stats().messages_sent, 0);
+}
+
+#[test]
+fn a_missing_journal_has_no_pending_frames() {
+    let path = journal_path("missing");
+    assert_eq!(recover(&path).unwrap(), Vec::new());
+}
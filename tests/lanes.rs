@@ -0,0 +1,140 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn lane(capacity: usize, overflow: LaneOverflow) -> LaneConfig {
+    LaneConfig { capacity, overflow }
+}
+
+// replies go to the rpc lane, everything else is telemetry
+fn classify(command: &u8) -> Lane {
+    match *command {
+        REPLY => Lane::Rpc,
+        _ => Lane::Stream,
+    }
+}
+
+#[test]
+fn a_telemetry_flood_does_not_delay_rpc_responses() {
+    let (mut server, mut client) = pair();
+    let (rpc, telemetry) = client.split_by(
+        classify,
+        lane(4, LaneOverflow::DropNewest),
+        lane(10, LaneOverflow::DropOldest),
+    );
+    let flooding = Arc::new(AtomicBool::new(true));
+    let flood = {
+        let flooding = flooding.clone();
+        std::thread::spawn(move || {
+            let mut sent = 0u64;
+            // bursts of 10 samples, about 2000 per second, which the read thread keeps up with
+            while flooding.load(Ordering::SeqCst) {
+                for _ in 0..10 {
+                    server.write_message(DATA, &[0; 256]).unwrap();
+                }
+                sent += 10;
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            sent
+        })
+    };
+    let mut slowest = Duration::from_secs(0);
+    let flood_start = Instant::now();
+    // rpc calls for a while, so the telemetry lane overflows meanwhile
+    for i in (0..=u8::MAX).cycle() {
+        if flood_start.elapsed() > Duration::from_millis(200) {
+            break;
+        }
+        let start = Instant::now();
+        client.write_message(QUERY, &[i]).unwrap();
+        assert_eq!(rpc.await_command(REPLY, TIMEOUT), Some(vec![i]));
+        slowest = slowest.max(start.elapsed());
+    }
+    flooding.store(false, Ordering::SeqCst);
+    let sent = flood.join().unwrap();
+
+    assert!(
+        slowest < Duration::from_secs(1),
+        "slowest rpc took {:?}",
+        slowest
+    );
+    assert_eq!(rpc.dropped(), 0);
+    // the telemetry lane never holds more than its capacity
+    assert!(telemetry.queued() <= 10);
+    assert!(telemetry.dropped() > 0);
+    await_condition(|| telemetry.queued() as u64 + telemetry.dropped() == sent);
+    assert!(telemetry
+        .messages()
+        .iter()
+        .all(|(command, payload)| *command == DATA && payload.len() == 256));
+}
+
+#[test]
+fn a_full_lane_follows_its_overflow_policy() {
+    let (mut server, mut client) = pair();
+    let (rpc, telemetry) = client.split_by(
+        classify,
+        lane(2, LaneOverflow::DropNewest),
+        lane(2, LaneOverflow::DropOldest),
+    );
+    for i in 0..4u8 {
+        server.write_message(REPLY, &[i]).unwrap();
+        server.write_message(DATA, &[i]).unwrap();
+    }
+    await_condition(|| rpc.dropped() == 2 && telemetry.dropped() == 2);
+    // the rpc lane kept the first replies, the stream lane the latest samples
+    assert_eq!(rpc.await_message(TIMEOUT), Some((REPLY, vec![0])));
+    assert_eq!(rpc.await_message(TIMEOUT), Some((REPLY, vec![1])));
+    assert_eq!(telemetry.messages(), vec![(DATA, vec![2]), (DATA, vec![3])]);
+}
+
+#[test]
+fn messages_received_before_the_split_stay_with_the_connection() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"before").unwrap();
+    await_bytes_received(&client, frame(DATA, b"before").len() as u64);
+    let (_rpc, telemetry) = client.split_by(
+        classify,
+        lane(4, LaneOverflow::DropNewest),
+        lane(4, LaneOverflow::DropNewest),
+    );
+    server.write_message(DATA, b"after").unwrap();
+    assert_eq!(
+        telemetry.await_message(TIMEOUT),
+        Some((DATA, b"after".to_vec()))
+    );
+    expect_payload(&mut client, DATA, b"before", TIMEOUT);
+}
+
+#[test]
+fn splitting_again_closes_the_previous_handles() {
+    let (mut server, mut client) = pair();
+    let (old_rpc, old_telemetry) = client.split_by(
+        classify,
+        lane(4, LaneOverflow::DropNewest),
+        lane(4, LaneOverflow::DropNewest),
+    );
+    server.write_message(DATA, b"old").unwrap();
+    await_condition(|| old_telemetry.queued() == 1);
+
+    // now everything is a response
+    let (rpc, telemetry) = client.split_by(
+        |_| Lane::Rpc,
+        lane(4, LaneOverflow::DropNewest),
+        lane(4, LaneOverflow::DropNewest),
+    );
+    assert!(old_rpc.is_closed() && old_telemetry.is_closed());
+    // the queued messages of a closed lane can still be taken
+    assert_eq!(old_telemetry.messages(), vec![(DATA, b"old".to_vec())]);
+    server.write_message(DATA, b"new").unwrap();
+    assert_eq!(rpc.await_command(DATA, TIMEOUT), Some(b"new".to_vec()));
+    assert_eq!(old_rpc.await_message(Duration::from_millis(10)), None);
+
+    // the lanes close with the connection
+    drop(client);
+    await_condition(|| rpc.is_closed() && telemetry.is_closed());
+}
@@ -0,0 +1,141 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::conformance::*;
+use rust_tcp_ipc::protocols::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::convert::TryFrom;
+
+/// The commands of the typed protocol, sent as 2-byte ids.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Commands {
+    Start = 1,
+    Stop = 0x0102,
+}
+impl From<Commands> for u32 {
+    fn from(command: Commands) -> u32 {
+        command as u32
+    }
+}
+impl TryFrom<u32> for Commands {
+    type Error = ();
+    fn try_from(id: u32) -> Result<Self, ()> {
+        match id {
+            1 => Ok(Commands::Start),
+            0x0102 => Ok(Commands::Stop),
+            _ => Err(()),
+        }
+    }
+}
+type TypedProtocol = LengthPrefixedProtocol<Commands, 3, 2>;
+
+// payloads which fit into every length field
+fn samples() -> Vec<Vec<u8>> {
+    vec![vec![1, 2, 3], vec![0xFF; 255]]
+}
+
+// constructs a frame & parses it back
+fn round_trip<P: Protocol>(command: P::Commands, payload: &[u8]) -> (P::Commands, Vec<u8>) {
+    let frame = P::construct_message(command, payload).expect("the frame was not constructed");
+    let (header, rest) = P::message_slice_to_header_array(&frame).unwrap();
+    let (command, length) = P::parse_header(header).unwrap();
+    assert_eq!(length, rest.len());
+    (command, rest.to_vec())
+}
+
+#[test]
+fn every_length_field_conforms() {
+    let checks = [
+        check_protocol::<LengthPrefixedProtocol<u8, 1, 1>>(&[0, 7, 0xFF], &samples()),
+        check_protocol::<LengthPrefixedProtocol<u8, 2, 1>>(&[0, 7, 0xFF], &samples()),
+        check_protocol::<LengthPrefixedProtocol<u16, 3, 2>>(&[0, 7, 0xFFFF], &samples()),
+        check_protocol::<LengthPrefixedProtocol<u32, 4, 4>>(&[0, 7, u32::MAX], &samples()),
+        check_protocol::<TypedProtocol>(&[Commands::Start, Commands::Stop], &samples()),
+    ];
+    for failures in &checks {
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
+}
+
+#[test]
+fn frames_round_trip_for_every_length_field() {
+    let payload: Vec<u8> = (0..255).collect();
+    assert_eq!(
+        round_trip::<LengthPrefixedProtocol<u8, 1, 1>>(9, &payload),
+        (9, payload.clone())
+    );
+    assert_eq!(
+        round_trip::<LengthPrefixedProtocol<u8, 2, 1>>(9, &payload),
+        (9, payload.clone())
+    );
+    assert_eq!(
+        round_trip::<LengthPrefixedProtocol<u8, 3, 1>>(9, &payload),
+        (9, payload.clone())
+    );
+    assert_eq!(
+        round_trip::<LengthPrefixedProtocol<u8, 4, 1>>(9, &payload),
+        (9, payload.clone())
+    );
+    assert_eq!(
+        round_trip::<TypedProtocol>(Commands::Stop, &payload),
+        (Commands::Stop, payload)
+    );
+}
+
+#[test]
+fn the_header_is_the_big_endian_length_followed_by_the_command() {
+    assert_eq!(
+        TypedProtocol::construct_message(Commands::Stop, b"ab").unwrap(),
+        vec![0, 0, 2, 0x01, 0x02, b'a', b'b']
+    );
+    assert_eq!(
+        LengthPrefixedProtocol::<u8, 1, 1>::construct_message(7, &[]).unwrap(),
+        vec![0, 7]
+    );
+}
+
+#[test]
+fn payloads_longer_than_the_length_field_are_refused() {
+    type OneByte = LengthPrefixedProtocol<u8, 1, 1>;
+    type TwoBytes = LengthPrefixedProtocol<u8, 2, 1>;
+    type ThreeBytes = LengthPrefixedProtocol<u8, 3, 1>;
+    assert!(OneByte::construct_message(1, &[0; 255]).is_some());
+    assert_eq!(OneByte::construct_message(1, &[0; 256]), None);
+    assert!(TwoBytes::construct_message(1, &vec![0; 0xFFFF]).is_some());
+    assert_eq!(TwoBytes::construct_message(1, &vec![0; 0x1_0000]), None);
+    assert!(ThreeBytes::construct_message(1, &vec![0; 0xFF_FFFF]).is_some());
+    assert_eq!(ThreeBytes::construct_message(1, &vec![0; 0x100_0000]), None);
+}
+
+#[test]
+fn command_ids_which_do_not_fit_or_convert_are_refused() {
+    assert_eq!(
+        LengthPrefixedProtocol::<u16, 1, 1>::construct_message(0x100, b"x"),
+        None
+    );
+    // an id the commands do not know is a parse failure
+    let frame = [0, 0, 0, 0x0F, 0x0F];
+    let (header, _) = TypedProtocol::message_slice_to_header_array(&frame).unwrap();
+    assert_eq!(
+        TypedProtocol::parse_header(header).map_err(|(error, _)| error),
+        Err(ParseHeaderError::CommandParseFailed)
+    );
+}
+
+#[test]
+fn a_connection_uses_the_protocol_end_to_end() {
+    let config = || TcpIpcConfig::<LengthPrefixedProtocol<u8, 2, 1>> {
+        read_iteration_wait_time: Some(std::time::Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    };
+    let (mut server, mut client) = loopback(config(), config()).unwrap();
+    client.write_message(DATA, b"hello").unwrap();
+    expect_payload(&mut server, DATA, b"hello", TIMEOUT);
+    // a payload too long for the length field is not written at all
+    assert!(matches!(
+        server.write_message(DATA, &vec![0; 0x1_0000]),
+        Err(WriteMessageErrors::MessageConstructionFailed)
+    ));
+    server.write_message(DATA, &vec![3; 0xFFFF]).unwrap();
+    expect_payload(&mut client, DATA, &vec![3; 0xFFFF], TIMEOUT);
+}
@@ -0,0 +1,55 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+
+#[test]
+fn port_zero_reports_the_chosen_port_before_accepting() {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    assert!(address.ip().is_loopback());
+    assert_ne!(address.port(), 0);
+    assert!(format!("{:?}", listener).contains(&address.to_string()));
+
+    // the client connects to the reported address, the server accepts afterwards
+    let client = std::thread::spawn(move || {
+        TcpIpc::<TestProtocol>::client(address, config(), Some(TIMEOUT)).unwrap()
+    });
+    let mut server = listener.accept(config()).unwrap();
+    let mut client = client.join().unwrap();
+    client.write_message(DATA, b"found you").unwrap();
+    expect_payload(&mut server, DATA, b"found you", TIMEOUT);
+    server.write_message(DATA, b"welcome").unwrap();
+    expect_payload(&mut client, DATA, b"welcome", TIMEOUT);
+}
+
+#[test]
+fn the_listener_accepts_further_clients() {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let mut connections = Vec::new();
+    for i in 0..3u8 {
+        let client = std::thread::spawn(move || {
+            TcpIpc::<TestProtocol>::client(address, config(), Some(TIMEOUT)).unwrap()
+        });
+        let mut server = listener.accept(config()).unwrap();
+        let mut client = client.join().unwrap();
+        client.write_message(DATA, &[i]).unwrap();
+        expect_payload(&mut server, DATA, &[i], TIMEOUT);
+        connections.push((server, client));
+    }
+    assert_eq!(listener.local_addr().unwrap(), address);
+}
+
+#[test]
+fn a_bound_address_cannot_be_bound_again() {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    assert!(matches!(
+        TcpIpc::<TestProtocol>::listen(address),
+        Err(ConnectErrors::BindError(_))
+    ));
+    // once the listener is dropped, the address is free again
+    drop(listener);
+    TcpIpc::<TestProtocol>::listen(address).unwrap();
+}
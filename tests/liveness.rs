@@ -0,0 +1,51 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Read;
+use std::time::Duration;
+
+#[test]
+fn check_writable_succeeds_on_a_live_connection() {
+    let (mut server, _peer) = raw_peer();
+    server.check_writable().unwrap();
+    server.write_message(DATA, b"still writable").unwrap();
+}
+
+#[test]
+fn peer_closing_is_detected_without_writing() {
+    let (mut server, peer) = raw_peer();
+    drop(peer);
+    await_condition(|| server.check_writable().is_err());
+    assert!(server.is_connection_closed());
+    assert!(matches!(
+        server.write_message(DATA, b"late"),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+}
+
+#[test]
+fn reset_by_peer_poisons_the_write_path() {
+    let (mut server, peer) = raw_peer();
+    // a socket closed with unread data resets the connection (instead of closing it orderly)
+    server.write_message(DATA, b"never read").unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    drop(peer);
+    await_condition(|| server.is_connection_closed());
+    assert!(matches!(
+        server.write_message(DATA, b"late"),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+    assert!(server.check_writable().is_err());
+}
+
+#[test]
+fn idle_connection_is_pinged() {
+    let (_server, mut peer) = raw_peer_with(TcpIpcConfig {
+        write_idle_ping: Some((Duration::from_millis(50), ERROR)),
+        ..config()
+    });
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut ping = [0; 9];
+    peer.read_exact(&mut ping).unwrap();
+    assert_eq!(ping.to_vec(), frame(ERROR, &[]));
+}
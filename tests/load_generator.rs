@@ -0,0 +1,77 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::bench::*;
+use rust_tcp_ipc::*;
+use std::time::Duration;
+
+fn spec() -> LoadSpec<TestProtocol> {
+    LoadSpec {
+        command_mix: vec![(DATA, 3), (URGENT, 1)],
+        payload_sizes: vec![(16, 3), (256, 1), (4096, 1)],
+        rate: Some(2000),
+        duration: Duration::from_secs(5),
+        max_messages: Some(200),
+        echo: Some(TIMEOUT),
+        seed: 42,
+    }
+}
+
+// waits for the next message
+fn await_message(ipc: &mut TcpIpc<TestProtocol>) -> (u8, Vec<u8>) {
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(message) = ipc.get_message().unwrap() {
+            return message;
+        }
+        assert!(start.elapsed() < TIMEOUT, "no message within {:?}", TIMEOUT);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn a_short_echo_profile_runs_without_errors() {
+    let (server, mut client) = pair();
+    let responder = EchoResponder::attach(server).unwrap();
+    let report = LoadGenerator::new(spec()).run(&mut client);
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.messages_sent, 200);
+    assert_eq!(report.echoes_received, 200);
+    assert_eq!((report.write_errors, report.read_errors), (0, 0));
+    let latency = report.latency.unwrap();
+    assert!(latency.p50 <= latency.p90 && latency.p90 <= latency.p99 && latency.p99 <= latency.max);
+    assert_eq!(responder.echoed(), 200);
+    assert_eq!(responder.errors(), 0);
+
+    let (server, client) = close_both(responder.detach(), client, TIMEOUT);
+    assert!(
+        server.is_ok() && client.is_ok(),
+        "{:?} {:?}",
+        server,
+        client
+    );
+}
+
+#[test]
+fn the_rate_paces_the_messages() {
+    let (mut server, mut client) = pair();
+    let report = LoadGenerator::new(LoadSpec {
+        rate: Some(1000),
+        duration: Duration::from_millis(200),
+        max_messages: None,
+        echo: None,
+        ..spec()
+    })
+    .run(&mut client);
+    assert!(report.is_clean(), "{:?}", report);
+    assert!(
+        (100..=250).contains(&report.messages_sent),
+        "{} messages sent",
+        report.messages_sent
+    );
+    assert_eq!(report.latency, None);
+    // every message arrives
+    for _ in 0..report.messages_sent {
+        let (command, _) = await_message(&mut server);
+        assert!(command == DATA || command == URGENT);
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use std::sync::Mutex;
+
+// keeps the messages of all records, since a logger can be installed only once per process
+struct CaptureLogger {
+    messages: Mutex<Vec<String>>,
+}
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push(format!("{}", record.args()));
+    }
+    fn flush(&self) {}
+}
+static LOGGER: CaptureLogger = CaptureLogger {
+    messages: Mutex::new(Vec::new()),
+};
+
+fn captured(pattern: &str) -> usize {
+    LOGGER
+        .messages
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|message| message.contains(pattern))
+        .count()
+}
+
+#[test]
+fn per_message_logs_appear_only_if_enabled() {
+    log::set_logger(&LOGGER).unwrap();
+    let (mut server, mut client) = pair();
+
+    log::set_max_level(log::LevelFilter::Debug);
+    client.write_message(DATA, &[0xAB]).unwrap();
+    expect_payload(&mut server, DATA, &[0xAB], TIMEOUT);
+    assert_eq!(captured("Message send succesfully"), 0);
+    assert_eq!(captured("Message received"), 0);
+
+    log::set_max_level(log::LevelFilter::Trace);
+    client.write_message(DATA, &[0xCD]).unwrap();
+    expect_payload(&mut server, DATA, &[0xCD], TIMEOUT);
+    assert_eq!(captured("Message send succesfully"), 1);
+    assert_eq!(captured("Message received: (4, [205])"), 1);
+    log::set_max_level(log::LevelFilter::Off);
+}
@@ -0,0 +1,207 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+const BUDGET: usize = 2000;
+/// A query whose immediate response does not fit into the budget.
+const BULKY_QUERY: u8 = 0x42;
+
+/// The test protocol, with a bulky immediate response.
+#[derive(Debug)]
+enum BulkyProtocol {}
+impl Protocol for BulkyProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        if *command == BULKY_QUERY {
+            return Some((REPLY, vec![0; 2 * BUDGET]));
+        }
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    // without the length limit of the test protocol, so a length can exceed what can be allocated
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        usize::try_from(u64::from_be_bytes(*length)).ok()
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn budgeted() -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        memory_budget: Some(BUDGET),
+        ..config()
+    }
+}
+
+fn memory(server: &TcpIpc<TestProtocol>) -> MemoryUsage {
+    let memory = server.stats().memory.unwrap();
+    assert!(memory.used <= BUDGET, "{:?}", memory);
+    assert!(memory.peak <= BUDGET, "{:?}", memory);
+    memory
+}
+
+// the payloads the peer sends: small messages, with a large one (which passes through the parse buffer) every 25 messages
+fn payloads() -> Vec<Vec<u8>> {
+    (0..100u8)
+        .map(|i| {
+            let length = if i % 25 == 24 { 1500 } else { 100 };
+            vec![i; length]
+        })
+        .collect()
+}
+
+#[test]
+fn the_budget_holds_under_pressure_on_the_parse_buffer_and_the_queue() {
+    let (mut server, mut peer) = raw_peer_with(budgeted());
+    let writer = std::thread::spawn(move || {
+        for payload in payloads() {
+            peer.write_all(&frame(DATA, &payload)).unwrap();
+        }
+        peer
+    });
+    // nothing is taken for a while, so the budget is used up
+    let start = std::time::Instant::now();
+    while start.elapsed() < Duration::from_millis(100) {
+        memory(&server);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(memory(&server).used > BUDGET / 2);
+
+    // all messages arrive in order once they are taken
+    for payload in payloads() {
+        let start = std::time::Instant::now();
+        loop {
+            memory(&server);
+            if let Some(message) = server.get_message().unwrap() {
+                assert!(
+                    message == (DATA, payload),
+                    "a message was corrupted or lost"
+                );
+                break;
+            }
+            assert!(start.elapsed() < TIMEOUT, "no message within {:?}", TIMEOUT);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+    let _peer = writer.join().unwrap();
+    let usage = memory(&server);
+    assert!(usage.used < 200, "{:?}", usage);
+    assert!(usage.peak > 1500, "{:?}", usage);
+    // pausing the reading is not a refusal
+    assert_eq!(usage.refused, 0);
+}
+
+#[test]
+fn writes_which_would_exceed_the_budget_fail() {
+    let (mut server, mut peer) = raw_peer_with(budgeted());
+    // the peer does not read, so the socket buffers fill up & frames are queued
+    let payload = vec![7; 500];
+    let mut written = 0;
+    loop {
+        match server.write_message(DATA, &payload) {
+            Ok(()) => written += 1,
+            Err(WriteMessageErrors::MemoryBudgetExceeded) => break,
+            Err(err) => panic!("unexpected error {:?}", err),
+        }
+        assert!(written < 1_000_000, "the budget was never reached");
+        memory(&server);
+    }
+    assert!(memory(&server).refused > 0);
+    assert!(!server.is_connection_closed());
+
+    // once the peer reads, the queue is written & writing works again
+    let mut received = vec![0; written * frame(DATA, &payload).len()];
+    peer.read_exact(&mut received).unwrap();
+    assert!(received == frame(DATA, &payload).repeat(written));
+    await_condition(|| server.write_pressure().queued_frames == 0);
+    server.write_message(DATA, b"after").unwrap();
+    let mut after = vec![0; frame(DATA, b"after").len()];
+    peer.read_exact(&mut after).unwrap();
+    assert_eq!(after, frame(DATA, b"after"));
+}
+
+#[test]
+fn dropped_immediate_responses_are_counted() {
+    let (server, mut client) = loopback::<BulkyProtocol>(
+        TcpIpcConfig {
+            read_iteration_wait_time: Some(Duration::from_micros(10)),
+            memory_budget: Some(BUDGET),
+            ..TcpIpcConfig::default()
+        },
+        TcpIpcConfig {
+            read_iteration_wait_time: Some(Duration::from_micros(10)),
+            ..TcpIpcConfig::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(server.stats().frames_dropped_by_budget, 0);
+    client.write_message(BULKY_QUERY, b"first").unwrap();
+    client.write_message(BULKY_QUERY, b"second").unwrap();
+    await_condition(|| server.stats().frames_dropped_by_budget == 2);
+    assert!(server.stats().memory.unwrap().refused >= 2);
+
+    // responses which fit are still sent
+    client.write_message(QUERY, b"small").unwrap();
+    expect_payload(&mut client, REPLY, b"small", TIMEOUT);
+    assert_eq!(server.stats().frames_dropped_by_budget, 2);
+    assert!(!server.is_connection_closed());
+}
+
+#[test]
+fn a_payload_which_cannot_be_allocated_is_not_held_in_the_budget() {
+    let (mut server, mut peer) = raw_peer_with(TcpIpcConfig::<BulkyProtocol> {
+        memory_budget: Some(usize::MAX),
+        ..TcpIpcConfig::default()
+    });
+    // the length fits into the budget, but exceeds the maximal size of an allocation
+    let length = isize::MAX as u64 + 1;
+    peer.write_all(&BulkyProtocol::construct_header(
+        [DATA],
+        length.to_be_bytes(),
+    ))
+    .unwrap();
+    match expect_error(&mut server) {
+        ReadThreadErrors::ProtocolViolation(violation) => {
+            assert_eq!(violation.error, ParseHeaderError::LengthTooLarge)
+        }
+        other => panic!("expected a protocol violation, got {:?}", other),
+    }
+    let usage = server.stats().memory.unwrap();
+    assert!(usage.used < 1 << 20, "{:?}", usage);
+}
+
+#[test]
+fn without_a_budget_no_usage_is_reported() {
+    let (server, _client) = pair();
+    assert_eq!(server.stats().memory, None);
+}
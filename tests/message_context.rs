@@ -0,0 +1,75 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+
+// writes a message & waits until the server parsed it
+fn send(client: &mut TcpIpc<TestProtocol>, server: &TcpIpc<TestProtocol>, payload: &[u8]) {
+    let received = server.stats().messages_received;
+    client.write_message(DATA, payload).unwrap();
+    await_condition(|| server.stats().messages_received > received);
+}
+
+// changes the busy state & waits until the read thread applies it
+fn switch(server: &mut TcpIpc<TestProtocol>, busy_state: u8) {
+    assert_eq!(
+        server.update_busy_state(busy_state),
+        BusyStateUpdateResult::Success
+    );
+    await_condition(|| server.get_busy_state() == Ok(busy_state));
+}
+
+#[test]
+fn each_message_comes_with_the_busy_state_it_was_parsed_with() {
+    let (mut server, mut client) = pair();
+    let idle = TestProtocol::idle();
+    // the timeline: a (idle), b & c (busy 1), d (busy 2), then back to idle
+    send(&mut client, &server, b"a");
+    switch(&mut server, 1);
+    send(&mut client, &server, b"b");
+    send(&mut client, &server, b"c");
+    switch(&mut server, 2);
+    send(&mut client, &server, b"d");
+    switch(&mut server, idle);
+
+    let expected = [(b"a", idle, 3), (b"b", 1, 2), (b"c", 1, 1), (b"d", 2, 0)];
+    for (sequence, (payload, busy_state, queue_depth)) in expected.iter().enumerate() {
+        let next = server.next_with_context(TIMEOUT).unwrap().unwrap();
+        assert_eq!(next.message, (DATA, payload.to_vec()));
+        assert_eq!(next.sequence, sequence as u64);
+        assert_eq!(next.busy_state, *busy_state, "busy state of {:?}", payload);
+        assert_eq!(
+            next.queue_depth, *queue_depth,
+            "queue depth of {:?}",
+            payload
+        );
+    }
+    assert!(server.next_with_context(TIMEOUT / 100).unwrap().is_none());
+}
+
+#[test]
+fn a_later_busy_state_change_does_not_affect_queued_messages() {
+    let (mut server, mut client) = pair();
+    switch(&mut server, 5);
+    send(&mut client, &server, b"parsed while busy");
+    // the change after parsing applies to later messages only
+    switch(&mut server, 6);
+    let next = server.next_with_context(TIMEOUT).unwrap().unwrap();
+    assert_eq!(next.busy_state, 5);
+    send(&mut client, &server, b"parsed later");
+    let next = server.next_with_context(TIMEOUT).unwrap().unwrap();
+    assert_eq!(next.busy_state, 6);
+    assert_eq!(next.sequence, 1);
+}
+
+#[test]
+fn immediately_answered_messages_are_not_counted() {
+    let (mut server, mut client) = pair();
+    send(&mut client, &server, b"first");
+    client.write_message(QUERY, b"answered").unwrap();
+    expect_payload(&mut client, REPLY, b"answered", TIMEOUT);
+    send(&mut client, &server, b"second");
+    let next = server.next_with_context(TIMEOUT).unwrap().unwrap();
+    assert_eq!(next.message, (DATA, b"first".to_vec()));
+    assert_eq!(next.queue_depth, 1);
+}
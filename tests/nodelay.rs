@@ -0,0 +1,42 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+fn connected_with(nodelay: Option<bool>) -> (TcpIpc<TestProtocol>, TcpIpc<TestProtocol>) {
+    let config = TcpIpcConfig {
+        nodelay,
+        ..config()
+    };
+    pair_with(config.clone(), config)
+}
+
+#[test]
+fn nodelay_is_set_if_requested() {
+    let (server, client) = connected_with(Some(true));
+    assert!(server.get_nodelay().unwrap());
+    assert!(client.get_nodelay().unwrap());
+}
+
+#[test]
+fn nagle_stays_enabled_if_requested() {
+    let (server, client) = connected_with(Some(false));
+    assert!(!server.get_nodelay().unwrap());
+    assert!(!client.get_nodelay().unwrap());
+}
+
+#[test]
+fn operating_system_default_is_left_untouched() {
+    // TCP_NODELAY is off by default on the supported operating systems
+    let (server, client) = connected_with(None);
+    assert!(!server.get_nodelay().unwrap());
+    assert!(!client.get_nodelay().unwrap());
+}
+
+#[test]
+fn nodelay_can_be_changed_at_runtime() {
+    let (mut server, _client) = connected_with(None);
+    server.set_nodelay(true).unwrap();
+    assert!(server.get_nodelay().unwrap());
+    server.set_nodelay(false).unwrap();
+    assert!(!server.get_nodelay().unwrap());
+}
@@ -0,0 +1,55 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+// checks that the read thread does not read anymore, i.e. it does not spin after the connection closed
+fn assert_no_spin<P: Protocol>(ipc: &TcpIpc<P>) {
+    let iterations = ipc.stats().read_iterations;
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(ipc.stats().read_iterations, iterations);
+}
+
+#[test]
+fn an_exited_peer_is_reported_once() {
+    let (mut server, peer) = raw_peer();
+    // the read thread reads while waiting for data
+    await_condition(|| server.stats().read_iterations > 1);
+    // the process of the peer exits, so its socket is closed
+    drop(peer);
+
+    let start = Instant::now();
+    let error = expect_error(&mut server);
+    assert!(
+        matches!(error, ReadThreadErrors::PeerClosed { .. }),
+        "{:?}",
+        error
+    );
+    assert!(
+        start.elapsed() < Duration::from_secs(1),
+        "{:?}",
+        start.elapsed()
+    );
+    assert_no_spin(&server);
+    // the close is not reported again
+    for _ in 0..10 {
+        assert!(!matches!(
+            server.get_message(),
+            Err(ReadThreadErrors::PeerClosed { .. })
+        ));
+    }
+    assert!(server.is_connection_closed());
+}
+
+#[test]
+fn a_reset_connection_is_not_read_anymore() {
+    let (mut server, peer) = raw_peer();
+    server.write_message(DATA, b"unread").unwrap();
+    let mut unread = [0; 1];
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    peer.peek(&mut unread).unwrap();
+    // closing a socket with unread data aborts the connection (the peer sends a reset)
+    drop(peer);
+    expect_closed(&mut server);
+    assert_no_spin(&server);
+}
@@ -0,0 +1,115 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::convert::TryInto;
+
+/// The client introduces itself with its protocol version.
+const HELLO: u8 = 0x48;
+/// The server answers a 'HELLO' (via the immediate route) with its own version.
+const WELCOME: u8 = 0x49;
+const SERVER_VERSION: u32 = 7;
+
+/// The test protocol, with a hello exchange declaring the versions.
+#[derive(Debug)]
+enum HelloProtocol {}
+impl Protocol for HelloProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        if *command == HELLO {
+            return Some((WELCOME, SERVER_VERSION.to_be_bytes().to_vec()));
+        }
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn peer_info(command: &u8, payload: &[u8]) -> Option<PeerInfo> {
+        if *command != HELLO && *command != WELCOME {
+            return None;
+        }
+        let version = u32::from_be_bytes(payload.get(..4)?.try_into().ok()?);
+        let mut info = PeerInfo {
+            protocol_version: Some(version),
+            ..PeerInfo::default()
+        };
+        info.fields
+            .insert("role".to_string(), format!("{:#x}", command));
+        Some(info)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn hello_pair() -> (TcpIpc<HelloProtocol>, TcpIpc<HelloProtocol>) {
+    let config = || TcpIpcConfig {
+        read_iteration_wait_time: Some(std::time::Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    };
+    loopback(config(), config()).unwrap()
+}
+
+#[test]
+fn both_endpoints_know_the_version_of_their_peer() {
+    let (mut server, mut client) = hello_pair();
+    assert_eq!(server.peer_info(), None);
+    assert_eq!(client.peer_info(), None);
+
+    client.write_message(HELLO, &5u32.to_be_bytes()).unwrap();
+    // the welcome is delivered as usual, the hello was answered via the immediate route
+    expect_payload(&mut client, WELCOME, &SERVER_VERSION.to_be_bytes(), TIMEOUT);
+    assert_eq!(server.get_message().unwrap(), None);
+
+    let server_view = server.peer_info().unwrap();
+    assert_eq!(server_view.protocol_version, Some(5));
+    assert_eq!(server_view.fields["role"], "0x48");
+    let client_view = client.peer_info().unwrap();
+    assert_eq!(client_view.protocol_version, Some(SERVER_VERSION));
+    assert_eq!(client_view.fields["role"], "0x49");
+}
+
+#[test]
+fn the_first_declaration_is_kept() {
+    let (server, mut client) = hello_pair();
+    client.write_message(HELLO, &5u32.to_be_bytes()).unwrap();
+    client.write_message(HELLO, &9u32.to_be_bytes()).unwrap();
+    expect_payload(&mut client, WELCOME, &SERVER_VERSION.to_be_bytes(), TIMEOUT);
+    expect_payload(&mut client, WELCOME, &SERVER_VERSION.to_be_bytes(), TIMEOUT);
+    assert_eq!(server.peer_info().unwrap().protocol_version, Some(5));
+}
+
+#[test]
+fn the_peer_info_is_part_of_the_diagnostics() {
+    let (mut server, mut client) = hello_pair();
+    client.write_message(HELLO, &5u32.to_be_bytes()).unwrap();
+    expect_payload(&mut client, WELCOME, &SERVER_VERSION.to_be_bytes(), TIMEOUT);
+    assert_eq!(server.diagnostics().peer_info, server.peer_info());
+    assert!(format!("{:?}", server).contains("protocol_version: Some(5)"));
+}
@@ -0,0 +1,51 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Write;
+
+// waits for the next message & returns it with the flag of its metadata
+fn next_flagged(ipc: &mut TcpIpc<TestProtocol>) -> (Message<TestProtocol>, bool) {
+    let mut next = None;
+    await_condition(|| {
+        next = ipc.get_message_with_metadata().unwrap();
+        next.is_some()
+    });
+    let (message, metadata) = next.unwrap();
+    (message, metadata.received_during_peer_shutdown)
+}
+
+#[test]
+fn messages_after_the_goodbye_are_flagged() {
+    let (mut server, mut peer) = raw_peer();
+    peer.write_all(&frame(DATA, b"before")).unwrap();
+    assert_eq!(
+        next_flagged(&mut server),
+        ((DATA, b"before".to_vec()), false)
+    );
+    assert_eq!(server.connection_state(), ConnectionState::Open);
+
+    peer.write_all(&frame(GOODBYE, &[])).unwrap();
+    // the goodbye itself is not part of the final flush
+    assert_eq!(next_flagged(&mut server), ((GOODBYE, Vec::new()), false));
+    assert_eq!(server.connection_state(), ConnectionState::PeerClosing);
+
+    peer.write_all(&[frame(DATA, b"one"), frame(DATA, b"two")].concat())
+        .unwrap();
+    assert_eq!(next_flagged(&mut server), ((DATA, b"one".to_vec()), true));
+    assert_eq!(next_flagged(&mut server), ((DATA, b"two".to_vec()), true));
+    assert_eq!(server.connection_state(), ConnectionState::PeerClosing);
+
+    peer.shutdown(std::net::Shutdown::Write).unwrap();
+    expect_closed(&mut server);
+    assert_eq!(server.connection_state(), ConnectionState::PeerClosed);
+}
+
+#[test]
+fn without_a_goodbye_nothing_is_flagged() {
+    let (mut server, mut peer) = raw_peer();
+    peer.write_all(&frame(DATA, b"only")).unwrap();
+    assert_eq!(next_flagged(&mut server), ((DATA, b"only".to_vec()), false));
+    drop(peer);
+    expect_closed(&mut server);
+    assert_eq!(server.connection_state(), ConnectionState::PeerClosed);
+}
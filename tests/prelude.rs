@@ -0,0 +1,101 @@
+// everything is imported through the prelude only
+use rust_tcp_ipc::prelude::*;
+use std::convert::TryInto;
+use std::time::Duration;
+
+#[derive(Debug)]
+enum PreludeProtocol {}
+impl Protocol for PreludeProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 1];
+    type HeaderAsArray = [u8; 2];
+    fn idle() -> u8 {
+        0
+    }
+    fn message_is_answered_via_immediate_route(
+        _command: &u8,
+        _message: &[u8],
+        _busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        None
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        Some(command[0])
+    }
+    fn parse_length(length: &[u8; 1]) -> Option<usize> {
+        Some(length[0] as usize)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 2], &[u8])> {
+        if input.len() >= 2 {
+            Some((input[..2].try_into().unwrap(), &input[2..]))
+        } else {
+            None
+        }
+    }
+    fn split_header_array(header: &[u8; 2]) -> (&[u8; 1], &[u8; 1]) {
+        (
+            header[..1].try_into().unwrap(),
+            header[1..].try_into().unwrap(),
+        )
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        [command]
+    }
+    fn get_length_as_array(_command: u8, message: &[u8]) -> Option<[u8; 1]> {
+        Some([message.len().try_into().ok()?])
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 1]) -> Vec<u8> {
+        vec![command[0], length[0]]
+    }
+}
+
+fn receive<P: Protocol>(
+    connection: &mut TcpIpc<P>,
+) -> Result<Option<Message<P>>, ReadThreadErrors<P>> {
+    connection.await_message(Duration::from_secs(5), None)
+}
+
+#[test]
+fn a_connection_is_usable_with_the_prelude_only() {
+    let config = TcpIpcConfig::<PreludeProtocol> {
+        strictness: Strictness::Strict,
+        on_immediate_construct_failure: ImmediateFailurePolicy::ReportOnly,
+        ..TcpIpcConfig::default()
+    };
+    let listener: TcpIpcListener<PreludeProtocol> = TcpIpc::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let connecting =
+        std::thread::spawn(move || -> Result<TcpIpc<PreludeProtocol>, ConnectErrors> {
+            TcpIpc::client(address, TcpIpcConfig::default(), None)
+        });
+    let mut server = listener.accept(config).unwrap();
+    let mut client = connecting.join().unwrap().unwrap();
+
+    let written: Result<(), WriteMessageErrors> = client.write_message(7, b"prelude");
+    written.unwrap();
+    assert_eq!(
+        receive(&mut server).unwrap(),
+        Some((7, b"prelude".to_vec()))
+    );
+    assert_eq!(server.update_busy_state(1), BusyStateUpdateResult::Success);
+    // the read thread takes the new busy state over asynchronously
+    let start = std::time::Instant::now();
+    loop {
+        let busy_state: Result<u8, BusyStateQueryResult> = server.get_busy_state();
+        match busy_state {
+            Ok(1) => break,
+            _ if start.elapsed() > Duration::from_secs(5) => panic!("busy state {:?}", busy_state),
+            _ => std::thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    assert!(matches!(
+        client.write_message(7, &[0; 256]),
+        Err(WriteMessageErrors::MessageConstructionFailed)
+    ));
+    let _: Option<ParseHeaderError> = None;
+
+    let report: Result<ShutdownReport, ShutdownReport> = client.shutdown();
+    report.unwrap();
+}
@@ -0,0 +1,66 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Read;
+
+fn read_frame(peer: &mut std::net::TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0; 9];
+    peer.read_exact(&mut header).unwrap();
+    let mut length = [0; 8];
+    length.copy_from_slice(&header[1..]);
+    let mut payload = vec![0; u64::from_be_bytes(length) as usize];
+    peer.read_exact(&mut payload).unwrap();
+    (header[0], payload)
+}
+
+#[test]
+fn queued_frames_are_written_by_priority() {
+    let (mut server, mut peer) = raw_peer();
+    // the peer does not read yet, so the rest of this frame is queued & everything behind it as well
+    let stalled = vec![0xAB; 1 << 24];
+    server.write_message(DATA, &stalled).unwrap();
+    assert!(server.write_pressure().would_block_now);
+
+    let messages = [
+        (Priority::Low, &b"low 1"[..]),
+        (Priority::Normal, b"norm 1"),
+        (Priority::High, b"high 1"),
+        (Priority::Low, b"low 2"),
+        (Priority::High, b"high 2"),
+        (Priority::Normal, b"norm 2"),
+    ];
+    for (priority, payload) in &messages {
+        server
+            .write_message_with_priority(URGENT, payload, *priority)
+            .unwrap();
+    }
+
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    // the frame which was started first is completed first
+    assert_eq!(read_frame(&mut peer), (DATA, stalled));
+    for expected in &[
+        &b"high 1"[..],
+        b"high 2",
+        b"norm 1",
+        b"norm 2",
+        b"low 1",
+        b"low 2",
+    ] {
+        assert_eq!(read_frame(&mut peer), (URGENT, expected.to_vec()));
+    }
+}
+
+#[test]
+fn without_queued_frames_messages_are_written_in_order() {
+    let (mut server, mut peer) = raw_peer();
+    server
+        .write_message_with_priority(URGENT, b"low", Priority::Low)
+        .unwrap();
+    server
+        .write_message_with_priority(URGENT, b"high", Priority::High)
+        .unwrap();
+
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(read_frame(&mut peer), (URGENT, b"low".to_vec()));
+    assert_eq!(read_frame(&mut peer), (URGENT, b"high".to_vec()));
+}
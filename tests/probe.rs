@@ -0,0 +1,160 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+/// The test protocol, which probes with a query (answered by a reply via the immediate route).
+#[derive(Debug)]
+enum ProbeProtocol {}
+impl Protocol for ProbeProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn probe_frame() -> Option<(u8, Vec<u8>)> {
+        Some((QUERY, b"probe".to_vec()))
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn probing(response: Option<u8>) -> TcpIpcConfig<ProbeProtocol> {
+    TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        probe: Some(ProbeConfig {
+            timeout: Duration::from_millis(200),
+            response,
+        }),
+        ..TcpIpcConfig::default()
+    }
+}
+
+// a plain TCP peer, which reads the probe frame & answers with the given bytes (then stays silent until the client is gone)
+fn fake_peer(answer: Vec<u8>) -> (SocketAddr, std::thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || {
+        let (mut stream, _): (TcpStream, _) = listener.accept().unwrap();
+        let mut probe = vec![0; frame(QUERY, b"probe").len()];
+        stream.read_exact(&mut probe).unwrap();
+        assert_eq!(probe, frame(QUERY, b"probe"));
+        stream.write_all(&answer).unwrap();
+        let _ = stream.read_to_end(&mut Vec::new());
+    });
+    (address, peer)
+}
+
+fn not_speaking_protocol(result: Result<TcpIpc<ProbeProtocol>, ConnectErrors>) -> (String, String) {
+    match result {
+        Err(ConnectErrors::PeerNotSpeakingProtocol { received, reason }) => (received, reason),
+        Err(err) => panic!("expected PeerNotSpeakingProtocol, found {:?}", err),
+        Ok(_) => panic!("the probe succeeded"),
+    }
+}
+
+#[test]
+fn an_http_server_does_not_speak_the_protocol() {
+    let (address, peer) =
+        fake_peer(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n".to_vec());
+    let (received, reason) =
+        not_speaking_protocol(TcpIpc::client(address, probing(None), Some(TIMEOUT)));
+    // printable bytes are kept, the line breaks are masked, & at most 'PROBE_CAPTURE_LENGTH' bytes are shown
+    assert!(
+        received.starts_with("HTTP/1.1 400 Bad Request..Conten"),
+        "{}",
+        received
+    );
+    assert_eq!(received.len(), PROBE_CAPTURE_LENGTH);
+    assert!(!reason.is_empty());
+    peer.join().unwrap();
+}
+
+#[test]
+fn a_silent_peer_times_out() {
+    let (address, peer) = fake_peer(Vec::new());
+    let (received, reason) =
+        not_speaking_protocol(TcpIpc::client(address, probing(None), Some(TIMEOUT)));
+    assert_eq!(received, "");
+    assert!(reason.contains("no answer within"), "{}", reason);
+    peer.join().unwrap();
+}
+
+#[test]
+fn a_correct_peer_answers_the_probe() {
+    let listener = TcpIpc::<ProbeProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        listener
+            .accept(TcpIpcConfig {
+                read_iteration_wait_time: Some(Duration::from_micros(10)),
+                ..TcpIpcConfig::default()
+            })
+            .unwrap()
+    });
+    let mut client = TcpIpc::client(address, probing(Some(REPLY)), Some(TIMEOUT)).unwrap();
+    let mut server = server.join().unwrap();
+    // the probe response is taken, the connection works as usual
+    server.write_message(DATA, b"after the probe").unwrap();
+    expect_payload(&mut client, DATA, b"after the probe", TIMEOUT);
+}
+
+#[test]
+fn other_frames_received_during_the_probe_are_delivered() {
+    let mut answer = frame(DATA, b"early");
+    answer.extend(frame(REPLY, b"probe"));
+    let (address, peer) = fake_peer(answer);
+    let mut client = TcpIpc::client(address, probing(Some(REPLY)), Some(TIMEOUT)).unwrap();
+    assert_eq!(
+        client.get_message().unwrap(),
+        Some((DATA, b"early".to_vec()))
+    );
+    assert_eq!(client.get_message().unwrap(), None);
+    drop(client);
+    peer.join().unwrap();
+}
+
+#[test]
+fn without_a_response_command_any_frame_answers_the_probe() {
+    let (address, peer) = fake_peer(frame(DATA, b"hello"));
+    let mut client = TcpIpc::client(address, probing(None), Some(TIMEOUT)).unwrap();
+    // the frame is delivered as usual
+    assert_eq!(
+        client.get_message().unwrap(),
+        Some((DATA, b"hello".to_vec()))
+    );
+    drop(client);
+    peer.join().unwrap();
+}
@@ -0,0 +1,131 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::time::Duration;
+
+/// A protocol with the given sizes of header, command & length, which is rejected before any of its functions is used.
+#[derive(Debug)]
+enum SizedProtocol<const HEADER: usize, const COMMAND: usize, const LENGTH: usize> {}
+impl<const HEADER: usize, const COMMAND: usize, const LENGTH: usize> Protocol
+    for SizedProtocol<HEADER, COMMAND, LENGTH>
+{
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; COMMAND];
+    type LengthAsArray = [u8; LENGTH];
+    type HeaderAsArray = [u8; HEADER];
+    fn idle() -> u8 {
+        0
+    }
+    fn message_is_answered_via_immediate_route(
+        _command: &u8,
+        _message: &[u8],
+        _busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        None
+    }
+    fn parse_command(_command: &[u8; COMMAND]) -> Option<u8> {
+        unreachable!("the protocol is rejected")
+    }
+    fn parse_length(_length: &[u8; LENGTH]) -> Option<usize> {
+        unreachable!("the protocol is rejected")
+    }
+    fn message_slice_to_header_array(_input: &[u8]) -> Option<(&[u8; HEADER], &[u8])> {
+        unreachable!("the protocol is rejected")
+    }
+    fn split_header_array(_header: &[u8; HEADER]) -> (&[u8; COMMAND], &[u8; LENGTH]) {
+        unreachable!("the protocol is rejected")
+    }
+    fn command_to_array(_command: u8) -> [u8; COMMAND] {
+        unreachable!("the protocol is rejected")
+    }
+    fn get_length_as_array(_command: u8, _message: &[u8]) -> Option<[u8; LENGTH]> {
+        unreachable!("the protocol is rejected")
+    }
+    fn construct_header(_command: [u8; COMMAND], _length: [u8; LENGTH]) -> Vec<u8> {
+        unreachable!("the protocol is rejected")
+    }
+}
+
+/// The parser would make no progress.
+type EmptyHeader = SizedProtocol<0, 0, 0>;
+/// The header is larger than 'DEFAULT_MAX_HEADER_SIZE'.
+type HugeHeader = SizedProtocol<8192, 1, 8>;
+
+// the description of the flaw, if the definition was rejected
+fn rejection<T: std::fmt::Debug>(result: Result<T, ConnectErrors>) -> String {
+    match result {
+        Err(ConnectErrors::InvalidProtocolDefinition(flaw)) => flaw,
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+fn no_config<P: Protocol>() -> TcpIpcConfig<P> {
+    TcpIpcConfig::default()
+}
+
+#[test]
+fn an_empty_header_is_rejected_before_connecting() {
+    // nobody listens there, so a connection attempt would fail differently
+    let flaw = rejection(TcpIpc::<EmptyHeader>::client(
+        "127.0.0.1:1",
+        no_config(),
+        Some(TIMEOUT),
+    ));
+    assert!(flaw.contains("empty"), "{}", flaw);
+    // the server does not wait for a client
+    let flaw = rejection(TcpIpc::<EmptyHeader>::server("127.0.0.1:0", no_config()));
+    assert!(flaw.contains("empty"), "{}", flaw);
+}
+
+#[test]
+fn a_huge_header_is_rejected_before_connecting() {
+    let flaw = rejection(TcpIpc::<HugeHeader>::client(
+        "127.0.0.1:1",
+        no_config(),
+        Some(TIMEOUT),
+    ));
+    assert!(flaw.contains("8192") && flaw.contains("4096"), "{}", flaw);
+    // an accepting listener does not wait for a client either
+    let listener = TcpIpc::<HugeHeader>::listen("127.0.0.1:0").unwrap();
+    assert!(rejection(listener.accept(no_config())).contains("8192"));
+    assert!(rejection(testing::loopback::<HugeHeader>(no_config(), no_config())).contains("8192"));
+}
+
+#[test]
+fn the_maximal_header_size_is_configurable() {
+    let config = TcpIpcConfig {
+        max_header_size: Some(10_000),
+        ..TcpIpcConfig::default()
+    };
+    // the definition is accepted, so the client tries to connect
+    let result =
+        TcpIpc::<HugeHeader>::client("127.0.0.1:1", config, Some(Duration::from_millis(10)));
+    assert!(
+        !matches!(result, Err(ConnectErrors::InvalidProtocolDefinition(_))),
+        "{:?}",
+        result.map(|_| ())
+    );
+}
+
+#[test]
+fn a_command_larger_than_the_header_is_rejected() {
+    let flaw = rejection(TcpIpc::<SizedProtocol<4, 8, 2>>::server(
+        "127.0.0.1:0",
+        no_config(),
+    ));
+    assert!(flaw.contains("do not fit"), "{}", flaw);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "empty")]
+fn a_parser_of_an_empty_header_panics_in_debug_builds() {
+    ProtocolBuffer::<EmptyHeader>::new();
+}
+
+#[test]
+fn a_parser_may_have_a_huge_header() {
+    // the size limit applies to connections only
+    ProtocolBuffer::<HugeHeader>::new();
+}
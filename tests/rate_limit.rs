@@ -0,0 +1,119 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+fn limited(
+    frames_per_second: u32,
+    burst: u32,
+    when_limited: RateLimitPolicy,
+    include_immediate_responses: bool,
+) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        outgoing_rate_limit: Some(RateLimit {
+            frames_per_second,
+            burst,
+            when_limited,
+            include_immediate_responses,
+        }),
+        ..config()
+    }
+}
+
+#[test]
+fn frames_beyond_the_burst_are_rejected() {
+    let (mut server, mut client) =
+        pair_with(limited(1, 3, RateLimitPolicy::Reject, false), config());
+    for i in 0..3u8 {
+        server.write_message(DATA, &[i]).unwrap();
+    }
+    match server.write_message(DATA, &[3]) {
+        Err(WriteMessageErrors::RateLimited { retry_after }) => {
+            assert!(retry_after > Duration::from_millis(0));
+            assert!(retry_after <= Duration::from_secs(1));
+        }
+        other => panic!("expected a rate limit, got {:?}", other),
+    }
+    for i in 0..3u8 {
+        expect_payload(&mut client, DATA, &[i], TIMEOUT);
+    }
+    // the rejected frame was not sent
+    assert_eq!(server.stats().messages_sent, 3);
+}
+
+#[test]
+fn try_write_message_never_waits() {
+    let (mut server, _client) = pair_with(limited(1, 1, RateLimitPolicy::Wait, false), config());
+    server.try_write_message(DATA, b"first").unwrap();
+    let start = Instant::now();
+    assert!(matches!(
+        server.try_write_message(DATA, b"second"),
+        Err(WriteMessageErrors::RateLimited { .. })
+    ));
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn waiting_writes_are_paced() {
+    let (mut server, mut client) =
+        pair_with(limited(10, 3, RateLimitPolicy::Wait, false), config());
+    let start = Instant::now();
+    for i in 0..8u8 {
+        server.write_message(DATA, &[i]).unwrap();
+    }
+    // the burst is sent at once, the remaining 5 frames one per 100 ms
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(450), "took {:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+    for i in 0..8u8 {
+        expect_payload(&mut client, DATA, &[i], TIMEOUT);
+    }
+}
+
+#[test]
+fn immediate_responses_are_not_limited_by_default() {
+    let (mut server, mut client) =
+        pair_with(limited(1, 1, RateLimitPolicy::Reject, false), config());
+    server.write_message(DATA, b"token").unwrap();
+    client.write_message(QUERY, b"ping").unwrap();
+    expect_payload(&mut client, DATA, b"token", TIMEOUT);
+    expect_payload(&mut client, REPLY, b"ping", TIMEOUT);
+    // the reply did not use a token, so the bucket is still empty
+    assert!(matches!(
+        server.try_write_message(DATA, b"more"),
+        Err(WriteMessageErrors::RateLimited { .. })
+    ));
+}
+
+#[test]
+fn included_immediate_responses_wait_for_a_token() {
+    let (mut server, mut client) =
+        pair_with(limited(5, 1, RateLimitPolicy::Reject, true), config());
+    server.write_message(DATA, b"token").unwrap();
+    let start = Instant::now();
+    client.write_message(QUERY, b"ping").unwrap();
+    expect_payload(&mut client, DATA, b"token", TIMEOUT);
+    expect_payload(&mut client, REPLY, b"ping", TIMEOUT);
+    // the next token is refilled 200 ms after the first write
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(150), "took {:?}", elapsed);
+}
+
+#[test]
+fn reading_continues_while_an_immediate_response_waits_for_a_token() {
+    let (mut server, mut client) =
+        pair_with(limited(1, 1, RateLimitPolicy::Reject, true), config());
+    server.write_message(DATA, b"token").unwrap();
+    client.write_message(QUERY, b"ping").unwrap();
+    client.write_message(DATA, b"behind the query").unwrap();
+    // the reply waits about a second for the next token, but the data behind the query is read meanwhile
+    expect_payload(
+        &mut server,
+        DATA,
+        b"behind the query",
+        Duration::from_millis(500),
+    );
+    expect_payload(&mut client, DATA, b"token", TIMEOUT);
+    expect_payload(&mut client, REPLY, b"ping", TIMEOUT);
+}
@@ -0,0 +1,58 @@
+mod common;
+use common::*;
+use std::time::Instant;
+
+// waits for the next message, delivered into the buffer
+fn read_into(server: &mut rust_tcp_ipc::TcpIpc<TestProtocol>, buf: &mut Vec<u8>) -> u8 {
+    let start = Instant::now();
+    loop {
+        if let Some(command) = server.read_message_into(buf).unwrap() {
+            return command;
+        }
+        assert!(start.elapsed() < TIMEOUT, "no message arrived");
+        std::thread::yield_now();
+    }
+}
+
+#[test]
+fn payloads_are_delivered_into_the_buffer() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"first").unwrap();
+    client.write_message(ERROR, b"").unwrap();
+    client.write_message(DATA, b"third one").unwrap();
+
+    let mut buf = b"stale".to_vec();
+    assert_eq!(read_into(&mut server, &mut buf), DATA);
+    assert_eq!(buf, b"first");
+    assert_eq!(read_into(&mut server, &mut buf), ERROR);
+    assert!(buf.is_empty());
+    assert_eq!(read_into(&mut server, &mut buf), DATA);
+    assert_eq!(buf, b"third one");
+}
+
+#[test]
+fn buffer_is_untouched_without_message() {
+    let (mut server, _client) = pair();
+    let mut buf = b"kept".to_vec();
+    assert_eq!(server.read_message_into(&mut buf).unwrap(), None);
+    assert_eq!(buf, b"kept");
+}
+
+#[test]
+fn capacity_does_not_shrink() {
+    let (mut server, mut client) = pair();
+    let mut buf = Vec::with_capacity(64);
+    for length in [1000, 3, 0, 200, 5000, 10] {
+        client
+            .write_message(DATA, &vec![length as u8; length])
+            .unwrap();
+    }
+    let mut capacity = buf.capacity();
+    for length in [1000, 3, 0, 200, 5000, 10] {
+        assert_eq!(read_into(&mut server, &mut buf), DATA);
+        assert_eq!(buf, vec![length as u8; length]);
+        assert!(buf.capacity() >= capacity);
+        capacity = buf.capacity();
+    }
+    assert!(capacity >= 5000);
+}
@@ -0,0 +1,49 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::sync::{Arc, Mutex};
+
+// connects with a hook recording the name of the thread it runs on, which is the read thread
+fn read_thread_name(name: Option<&str>) -> Option<String> {
+    let recorded = Arc::new(Mutex::new(None));
+    let recording = recorded.clone();
+    let server_config = TcpIpcConfig {
+        name: name.map(String::from),
+        thread_priority: Some(Arc::new(move || {
+            *recording.lock().unwrap() = std::thread::current().name().map(String::from);
+        })),
+        ..config()
+    };
+    let (_server, _client) = pair_with(server_config, config());
+    // the hook runs on the read thread, which may start after connecting returned
+    await_condition(|| recorded.lock().unwrap().is_some());
+    let name = recorded.lock().unwrap().clone();
+    name
+}
+
+#[test]
+fn read_thread_is_named_after_the_connection() {
+    assert_eq!(
+        read_thread_name(Some("camera")).as_deref(),
+        Some("tcp-ipc/camera/read")
+    );
+}
+
+#[test]
+fn unnamed_connection_has_a_generic_read_thread_name() {
+    assert_eq!(read_thread_name(None).as_deref(), Some("tcp-ipc/read"));
+}
+
+#[test]
+fn priority_hook_runs_once_per_connection() {
+    let calls = Arc::new(Mutex::new(0));
+    let counting = calls.clone();
+    let config = TcpIpcConfig {
+        thread_priority: Some(Arc::new(move || *counting.lock().unwrap() += 1)),
+        ..config()
+    };
+    let (_server, _client) = pair_with(config.clone(), config);
+    await_condition(|| *calls.lock().unwrap() == 2);
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert_eq!(*calls.lock().unwrap(), 2);
+}
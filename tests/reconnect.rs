@@ -0,0 +1,126 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+fn reconnecting(max_attempts: u32, queue_limit: usize) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        reconnect: Some(ReconnectPolicy {
+            max_attempts,
+            initial_delay: Duration::from_millis(10),
+            backoff_multiplier: 1.5,
+            max_delay: Duration::from_millis(50),
+            queue_limit,
+        }),
+        ..config()
+    }
+}
+
+// (re)starts the server at the given address & connects the client to it, if it is not connected already
+fn serve(
+    address: SocketAddr,
+    client: Option<TcpIpcConfig<TestProtocol>>,
+) -> (TcpIpc<TestProtocol>, Option<TcpIpc<TestProtocol>>) {
+    let listener = TcpIpc::<TestProtocol>::listen(address).unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = client.map(|config| {
+        std::thread::spawn(move || TcpIpc::<TestProtocol>::client(address, config, Some(TIMEOUT)))
+    });
+    let server = listener.accept(config()).unwrap();
+    (server, client.map(|client| client.join().unwrap().unwrap()))
+}
+
+// a free port of the loopback interface
+fn free_address() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+fn await_event(client: &mut TcpIpc<TestProtocol>) -> ConnectionEvent {
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(event) = client.next_event() {
+            return event;
+        }
+        assert!(start.elapsed() < TIMEOUT, "no event within {:?}", TIMEOUT);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn the_client_resumes_after_the_server_restarted() {
+    let address = free_address();
+    let (mut server, client) = serve(address, Some(reconnecting(100, 0)));
+    let mut client = client.unwrap();
+    server.write_message(DATA, b"before").unwrap();
+    expect_payload(&mut client, DATA, b"before", TIMEOUT);
+
+    // the server process is killed
+    drop(server);
+    assert!(matches!(
+        expect_error(&mut client),
+        ReadThreadErrors::PeerClosed { .. }
+    ));
+    assert_eq!(await_event(&mut client), ConnectionEvent::Lost);
+    assert_eq!(client.connection_state(), ConnectionState::Reconnecting);
+    assert!(matches!(
+        client.write_message(DATA, b"lost"),
+        Err(WriteMessageErrors::NotConnected)
+    ));
+
+    // & restarted
+    let (mut server, _) = serve(address, None);
+    assert!(matches!(
+        await_event(&mut client),
+        ConnectionEvent::Reconnected { attempts } if attempts >= 1
+    ));
+    assert_eq!(client.connection_state(), ConnectionState::Open);
+    server.write_message(DATA, b"after").unwrap();
+    expect_payload(&mut client, DATA, b"after", TIMEOUT);
+    client.write_message(DATA, b"back").unwrap();
+    expect_payload(&mut server, DATA, b"back", TIMEOUT);
+}
+
+#[test]
+fn writes_are_queued_up_to_the_limit_while_the_connection_is_lost() {
+    let address = free_address();
+    let (server, client) = serve(
+        address,
+        Some(reconnecting(100, 2 * frame(DATA, b"queued").len())),
+    );
+    let mut client = client.unwrap();
+    drop(server);
+    assert_eq!(await_event(&mut client), ConnectionEvent::Lost);
+
+    client.write_message(DATA, b"queued").unwrap();
+    client.write_message(DATA, b"queued").unwrap();
+    assert!(matches!(
+        client.write_message(DATA, b"queued"),
+        Err(WriteMessageErrors::NotConnected)
+    ));
+
+    let (mut server, _) = serve(address, None);
+    expect_payload(&mut server, DATA, b"queued", TIMEOUT);
+    expect_payload(&mut server, DATA, b"queued", TIMEOUT);
+    client.write_message(DATA, b"live").unwrap();
+    expect_payload(&mut server, DATA, b"live", TIMEOUT);
+}
+
+#[test]
+fn a_server_which_never_returns_closes_the_connection() {
+    let address = free_address();
+    let (server, client) = serve(address, Some(reconnecting(3, 0)));
+    let mut client = client.unwrap();
+    drop(server);
+    assert_eq!(await_event(&mut client), ConnectionEvent::Lost);
+    assert_eq!(
+        await_event(&mut client),
+        ConnectionEvent::ReconnectFailed { attempts: 3 }
+    );
+    await_condition(|| client.connection_state() == ConnectionState::Closed);
+    assert!(client.is_connection_closed());
+}
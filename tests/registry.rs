@@ -0,0 +1,76 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+fn named(name: &str) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        name: Some(name.to_string()),
+        ..config()
+    }
+}
+
+// returns the descriptors of the given connections, which are listed (other tests create connections as well)
+fn listed(ids: &[ConnectionId]) -> Vec<ConnectionDescriptor> {
+    registry()
+        .into_iter()
+        .filter(|descriptor| ids.contains(&descriptor.id))
+        .collect()
+}
+
+#[test]
+fn live_connections_are_listed_until_dropped() {
+    let (first_server, first_client) = pair_with(named("first server"), named("first client"));
+    let (second_server, second_client) = pair_with(named("second server"), config());
+    let ids = [
+        first_server.id(),
+        first_client.id(),
+        second_server.id(),
+        second_client.id(),
+    ];
+    assert!(ids.windows(2).all(|pair| pair[0] != pair[1]));
+
+    let descriptors = listed(&ids);
+    assert_eq!(descriptors.len(), 4);
+    // ordered by id
+    assert!(descriptors.windows(2).all(|pair| pair[0].id < pair[1].id));
+    let first_client_descriptor = descriptors
+        .iter()
+        .find(|descriptor| descriptor.id == first_client.id())
+        .unwrap();
+    assert_eq!(
+        first_client_descriptor.name.as_deref(),
+        Some("first client")
+    );
+    assert_eq!(
+        first_client_descriptor.peer_addr,
+        first_client.peer_addr().ok()
+    );
+    assert!(!first_client_descriptor.connection_closed);
+    let second_client_descriptor = descriptors
+        .iter()
+        .find(|descriptor| descriptor.id == second_client.id())
+        .unwrap();
+    assert_eq!(second_client_descriptor.name, None);
+
+    drop(first_client);
+    let remaining: Vec<ConnectionId> = listed(&ids)
+        .iter()
+        .map(|descriptor| descriptor.id)
+        .collect();
+    let mut expected = vec![ids[0], ids[2], ids[3]];
+    expected.sort();
+    assert_eq!(remaining, expected);
+
+    // a connection, whose read thread finished since the peer is gone, is listed as closed until dropped
+    let mut first_server = first_server;
+    expect_closed(&mut first_server);
+    let closed = listed(&[ids[0]]);
+    assert!(closed[0].connection_closed);
+    drop(first_server);
+    assert!(listed(&[ids[0]]).is_empty());
+
+    second_server.shutdown().expect("shutdown was not clean");
+    assert_eq!(listed(&ids).len(), 1);
+    drop(second_client);
+    assert!(listed(&ids).is_empty());
+}
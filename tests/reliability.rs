@@ -0,0 +1,108 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+fn reliable_config(max_unacknowledged: usize) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        reliability: Some(ReliabilityConfig {
+            max_unacknowledged,
+            dedup_window: 16,
+        }),
+        ..config()
+    }
+}
+
+// the bytes of a reliable message on the wire: the frame id in front of the payload
+fn reliable_frame(id: u64, payload: &[u8]) -> Vec<u8> {
+    let mut message = id.to_be_bytes().to_vec();
+    message.extend_from_slice(payload);
+    frame(RELIABLE, &message)
+}
+
+fn read_frame(peer: &mut std::net::TcpStream, expected: &[u8]) {
+    let mut received = vec![0; expected.len()];
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn reliable_messages_are_acknowledged() {
+    let (mut server, mut client) = pair_with(reliable_config(4), reliable_config(4));
+    let delivery = client
+        .write_message_reliable(RELIABLE, b"critical")
+        .unwrap();
+    // the frame id is removed before the message is delivered
+    expect_payload(&mut server, RELIABLE, b"critical", TIMEOUT);
+    assert!(delivery.wait(TIMEOUT));
+    assert!(delivery.is_acknowledged());
+
+    assert!(matches!(
+        client.write_message_reliable(DATA, b"not reliable"),
+        Err(WriteMessageErrors::ReliabilityUnavailable)
+    ));
+}
+
+#[test]
+fn duplicates_are_acknowledged_but_delivered_once() {
+    let (mut server, mut peer) = raw_peer_with(reliable_config(4));
+    let duplicated = reliable_frame(7, b"once");
+    peer.write_all(&duplicated).unwrap();
+    peer.write_all(&duplicated).unwrap();
+
+    expect_payload(&mut server, RELIABLE, b"once", TIMEOUT);
+    expect_silence(&mut server, Duration::from_millis(100));
+    let ack = frame(ACK, &7u64.to_be_bytes());
+    read_frame(&mut peer, &ack);
+    read_frame(&mut peer, &ack);
+}
+
+#[test]
+fn the_retransmit_buffer_is_limited() {
+    let (mut server, mut peer) = raw_peer_with(reliable_config(2));
+    let first = server.write_message_reliable(RELIABLE, b"0").unwrap();
+    server.write_message_reliable(RELIABLE, b"1").unwrap();
+    assert!(matches!(
+        server.write_message_reliable(RELIABLE, b"2"),
+        Err(WriteMessageErrors::RetransmitBufferFull)
+    ));
+
+    // an acknowledgment frees its place
+    peer.write_all(&frame(ACK, &first.id().to_be_bytes()))
+        .unwrap();
+    assert!(first.wait(TIMEOUT));
+    server.write_message_reliable(RELIABLE, b"2").unwrap();
+}
+
+#[test]
+fn unacknowledged_messages_are_resent_after_a_reconnect() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client_config = TcpIpcConfig {
+        reconnect: Some(ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        }),
+        ..reliable_config(4)
+    };
+    let mut client = TcpIpc::<TestProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    let (mut lost, _) = listener.accept().unwrap();
+
+    let delivery = client
+        .write_message_reliable(RELIABLE, b"critical")
+        .unwrap();
+    let expected = reliable_frame(delivery.id(), b"critical");
+    // the connection is dropped between sending & acknowledging
+    read_frame(&mut lost, &expected);
+    drop(lost);
+    assert!(!delivery.wait(Duration::from_millis(50)));
+
+    let (mut peer, _) = listener.accept().unwrap();
+    read_frame(&mut peer, &expected);
+    peer.write_all(&frame(ACK, &delivery.id().to_be_bytes()))
+        .unwrap();
+    assert!(delivery.wait(TIMEOUT));
+}
@@ -0,0 +1,149 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::replay::*;
+use rust_tcp_ipc::*;
+
+const CAPTURE: &str = "tests/captures/unknown_command.txt";
+/// The command of the newer peer, which the buggy protocol panics on.
+const NEWER: u8 = 0x07;
+
+/// The test protocol as deployed in the field, which panics on unknown commands, & its fix (if 'FIXED'), which knows the newer command.
+#[derive(Debug)]
+enum FieldProtocol<const FIXED: bool> {}
+impl<const FIXED: bool> Protocol for FieldProtocol<FIXED> {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        match command[0] {
+            QUERY..=RELIABLE => Some(command[0]),
+            NEWER if FIXED => Some(NEWER),
+            command => panic!("unknown command {}", command),
+        }
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+#[test]
+fn the_field_capture_reproduces_the_panic() {
+    let records = load_capture(CAPTURE).unwrap();
+    let report = parse_capture::<FieldProtocol<false>>(&records);
+    assert!(!report.is_clean());
+    assert_eq!(
+        report.messages,
+        vec![ReplayedMessage {
+            record: 0,
+            message: (DATA, b"hi".to_vec()),
+        }]
+    );
+    // the offending header starts right after the data frame, in the first record
+    assert_eq!(
+        report.failure,
+        Some(ReplayFailure {
+            kind: ReplayFailureKind::Panic("unknown command 7".to_string()),
+            record: 0,
+            offset: frame(DATA, b"hi").len(),
+            stream_offset: frame(DATA, b"hi").len(),
+        })
+    );
+    assert_eq!(report.replayed_records, 2);
+}
+
+#[test]
+fn the_fix_makes_the_report_clean() {
+    let records = load_capture(CAPTURE).unwrap();
+    let report = parse_capture::<FieldProtocol<true>>(&records);
+    assert!(report.is_clean(), "{:?}", report);
+    let messages: Vec<_> = report
+        .messages
+        .into_iter()
+        .map(|m| (m.record, m.message))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![(0, (DATA, b"hi".to_vec())), (2, (NEWER, vec![0x2a]))]
+    );
+}
+
+#[test]
+fn a_violation_is_located_in_its_record() {
+    let mut second = frame(DATA, b"ok");
+    second.extend_from_slice(&[UNKNOWN, 0, 0]);
+    let records = vec![
+        CaptureRecord::received(frame(DATA, b"first")[..5].to_vec()),
+        CaptureRecord::received(frame(DATA, b"first")[5..].to_vec()),
+        CaptureRecord::received(second),
+        CaptureRecord::received(vec![0; 6]),
+    ];
+    let report = parse_capture::<TestProtocol>(&records);
+    let failure = report.failure.unwrap();
+    match failure.kind {
+        ReplayFailureKind::ProtocolViolation(violation) => {
+            assert_eq!(violation.error, ParseHeaderError::CommandParseFailed)
+        }
+        kind => panic!("expected a protocol violation, found {:?}", kind),
+    }
+    assert_eq!(failure.record, 2);
+    assert_eq!(failure.offset, frame(DATA, b"ok").len());
+    assert_eq!(
+        failure.stream_offset,
+        frame(DATA, b"first").len() + frame(DATA, b"ok").len()
+    );
+    assert_eq!(report.messages.len(), 2);
+}
+
+#[test]
+fn a_capture_ending_mid_frame_is_not_clean() {
+    let truncated = frame(DATA, b"truncated");
+    let report =
+        parse_capture::<TestProtocol>(&[CaptureRecord::received(truncated[..12].to_vec())]);
+    assert_eq!(report.failure, None);
+    assert!(!report.is_clean());
+    assert_eq!(
+        report.parser_state.truncated_frame(),
+        Some(TruncatedFrame {
+            command: Some(DATA),
+            received: 3,
+            declared: Some(9),
+        })
+    );
+}
+
+#[test]
+fn a_saved_capture_is_loaded_unchanged() {
+    let records = load_capture(CAPTURE).unwrap();
+    let path =
+        std::env::temp_dir().join(format!("rust_tcp_ipc-{}-capture.txt", std::process::id()));
+    save_capture(&path, &records, None).unwrap();
+    assert_eq!(load_capture(&path).unwrap(), records);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,109 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// A resolver with a scripted outcome. It is deliberately not 'Debug', since the connecting functions must not require it.
+enum MockResolver {
+    Resolves(Vec<SocketAddr>),
+    Fails(io::ErrorKind),
+}
+impl ToSocketAddrs for MockResolver {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        match self {
+            MockResolver::Resolves(addresses) => Ok(addresses.clone().into_iter()),
+            MockResolver::Fails(kind) => Err(io::Error::new(*kind, "_srv._tcp.example")),
+        }
+    }
+}
+
+fn connect<T: ToSocketAddrs>(socket_addresses: T) -> ConnectErrors {
+    TcpIpc::<TestProtocol>::client(socket_addresses, config(), None)
+        .expect_err("connecting succeeded")
+}
+
+#[test]
+fn an_empty_input_is_an_empty_socket_list() {
+    let error = connect(MockResolver::Resolves(Vec::new()));
+    assert!(
+        matches!(error, ConnectErrors::SocketListIsEmpty),
+        "{:?}",
+        error
+    );
+    assert!(!error.is_retryable());
+    let error = connect(&Vec::<SocketAddr>::new()[..]);
+    assert!(
+        matches!(error, ConnectErrors::SocketListIsEmpty),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn a_failed_resolution_is_retryable() {
+    let error = connect(MockResolver::Fails(io::ErrorKind::TimedOut));
+    assert!(
+        matches!(&error, ConnectErrors::ResolutionFailed(err) if err.kind() == io::ErrorKind::TimedOut),
+        "{:?}",
+        error
+    );
+    assert!(error.is_retryable());
+}
+
+#[test]
+fn an_input_resolving_to_nothing_is_fatal() {
+    let error = connect(MockResolver::Fails(io::ErrorKind::NotFound));
+    match &error {
+        ConnectErrors::NoAddressesResolved { input } => assert_eq!(input, "_srv._tcp.example"),
+        error => panic!("unexpected error {:?}", error),
+    }
+    assert!(!error.is_retryable());
+}
+
+#[test]
+fn a_malformed_input_is_a_parse_error() {
+    // the port is missing
+    let error = connect("127.0.0.1");
+    assert!(
+        matches!(error, ConnectErrors::SocketListParseError(_)),
+        "{:?}",
+        error
+    );
+    assert!(!error.is_retryable());
+}
+
+#[test]
+fn the_resolved_addresses_are_used() {
+    let listener = TcpIpc::<TestProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || listener.accept(config()).map(|_| ()));
+    let client = TcpIpc::<TestProtocol>::client(
+        MockResolver::Resolves(vec![address]),
+        config(),
+        Some(TIMEOUT),
+    );
+    assert!(client.is_ok());
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn servers_resolve_the_same_way() {
+    let empty = TcpIpcServer::<TestProtocol>::bind(MockResolver::Resolves(Vec::new()), config());
+    assert!(matches!(
+        empty.err(),
+        Some(ConnectErrors::SocketListIsEmpty)
+    ));
+    let failed = TcpIpc::<TestProtocol>::listen(MockResolver::Fails(io::ErrorKind::TimedOut));
+    assert!(matches!(
+        failed.err(),
+        Some(ConnectErrors::ResolutionFailed(_))
+    ));
+    let unresolved =
+        TcpIpc::<TestProtocol>::server(MockResolver::Fails(io::ErrorKind::NotFound), config());
+    assert!(matches!(
+        unresolved.err(),
+        Some(ConnectErrors::NoAddressesResolved { .. })
+    ));
+}
@@ -0,0 +1,99 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::sync::Arc;
+
+/// Asks for the busy state of the peer, which answers with 'IS_BUSY' unless it is idle.
+const QUERY_IS_BUSY: u8 = 0x42;
+const IS_BUSY: u8 = 0x43;
+const PING: u8 = 0x50;
+const PONG: u8 = 0x51;
+
+// the busy state query of the example protocol, expressed as a table
+fn busy_table() -> ImmediateResponseTable<TestProtocol> {
+    ImmediateResponseTable::new()
+        .when(QUERY_IS_BUSY, 0, ResponseTemplate::Forward)
+        .when(
+            QUERY_IS_BUSY,
+            Matcher::Any,
+            ResponseTemplate::FromBusyState(|busy_state| (IS_BUSY, vec![busy_state])),
+        )
+        .when(PING, Matcher::Any, ResponseTemplate::EchoPayload(PONG))
+}
+
+#[test]
+fn the_first_matching_rule_wins() {
+    let table = ImmediateResponseTable::<TestProtocol>::new()
+        .when(DATA, 1, ResponseTemplate::Static(ERROR, b"busy".to_vec()))
+        .when(
+            Matcher::AnyOf(vec![DATA, URGENT]),
+            Matcher::Where(|busy_state: &u8| *busy_state < 5),
+            ResponseTemplate::Static(ACK, b"low".to_vec()),
+        )
+        .when(
+            DATA,
+            Matcher::Any,
+            ResponseTemplate::Static(ACK, b"any".to_vec()),
+        )
+        // never reached, since the rule above matches every busy state
+        .when(
+            DATA,
+            7,
+            ResponseTemplate::Static(ERROR, b"shadowed".to_vec()),
+        );
+    assert_eq!(
+        table.answer(&DATA, b"", &1),
+        Some((ERROR, b"busy".to_vec()))
+    );
+    assert_eq!(table.answer(&DATA, b"", &2), Some((ACK, b"low".to_vec())));
+    assert_eq!(table.answer(&URGENT, b"", &4), Some((ACK, b"low".to_vec())));
+    assert_eq!(table.answer(&DATA, b"", &7), Some((ACK, b"any".to_vec())));
+    assert_eq!(table.answer(&URGENT, b"", &7), None);
+}
+
+#[test]
+fn a_forwarding_rule_stops_the_search() {
+    let table = busy_table();
+    assert_eq!(table.answer(&QUERY_IS_BUSY, b"", &0), None);
+    assert_eq!(
+        table.answer(&QUERY_IS_BUSY, b"", &3),
+        Some((IS_BUSY, vec![3]))
+    );
+    assert_eq!(table.answer(&DATA, b"", &3), None);
+}
+
+#[test]
+fn the_echo_template_answers_with_the_payload() {
+    let table = busy_table();
+    assert_eq!(
+        table.answer(&PING, b"abc", &0),
+        Some((PONG, b"abc".to_vec()))
+    );
+    assert_eq!(table.answer(&PING, b"", &9), Some((PONG, Vec::new())));
+}
+
+#[test]
+fn an_installed_table_answers_with_the_current_busy_state() {
+    let (mut server, mut client) = pair_with(
+        TcpIpcConfig {
+            immediate_responses: Some(Arc::new(busy_table())),
+            ..config()
+        },
+        config(),
+    );
+    // an idle server forwards the query
+    client.write_message(QUERY_IS_BUSY, b"").unwrap();
+    expect_payload(&mut server, QUERY_IS_BUSY, b"", TIMEOUT);
+
+    assert_eq!(server.update_busy_state(4), BusyStateUpdateResult::Success);
+    await_condition(|| server.get_busy_state() == Ok(4));
+    client.write_message(QUERY_IS_BUSY, b"").unwrap();
+    expect_payload(&mut client, IS_BUSY, &[4], TIMEOUT);
+    client.write_message(PING, b"echo").unwrap();
+    expect_payload(&mut client, PONG, b"echo", TIMEOUT);
+
+    // without a matching rule, the protocol decides
+    client.write_message(QUERY, b"fallback").unwrap();
+    expect_payload(&mut client, REPLY, b"fallback", TIMEOUT);
+}
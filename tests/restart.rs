@@ -0,0 +1,118 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+
+fn restarting(restart_policy: RestartPolicy) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        restart_policy,
+        ..config()
+    }
+}
+
+// checks that the socket is still open, by a frame the peer receives
+fn expect_open(server: &mut TcpIpc<TestProtocol>, peer: &mut std::net::TcpStream) {
+    server.write_message(DATA, b"still open").unwrap();
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut received = vec![0; frame(DATA, b"still open").len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, frame(DATA, b"still open"));
+}
+
+// polls until the given function returns something
+fn await_some<T>(mut f: impl FnMut() -> Option<T>) -> T {
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        assert!(start.elapsed() < TIMEOUT, "nothing within {:?}", TIMEOUT);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn a_desynchronized_parser_recovers_after_a_restart() {
+    let (mut server, mut peer) = raw_peer_with(restarting(RestartPolicy::default()));
+    let id = server.id();
+    peer.write_all(&frame(DATA, b"before")).unwrap();
+    expect_payload(&mut server, DATA, b"before", TIMEOUT);
+
+    // a corrupted header declares a long payload, so the following frames are swallowed as its payload
+    let mut corrupted = vec![DATA];
+    corrupted.extend_from_slice(&1000u64.to_be_bytes());
+    peer.write_all(&corrupted).unwrap();
+    peer.write_all(&frame(DATA, b"swallowed")).unwrap();
+    let swallowed = (corrupted.len() + frame(DATA, b"swallowed").len()) as u64;
+    await_bytes_received(&server, frame(DATA, b"before").len() as u64 + swallowed);
+    assert_eq!(server.get_message().unwrap(), None);
+
+    server.restart_read_thread().unwrap();
+    peer.write_all(&frame(DATA, b"after")).unwrap();
+    expect_payload(&mut server, DATA, b"after", TIMEOUT);
+    expect_open(&mut server, &mut peer);
+    // statistics & identity persist across the restart
+    assert_eq!(server.id(), id);
+    assert_eq!(server.stats().messages_received, 2);
+    assert!(!server.is_connection_closed());
+}
+
+#[test]
+fn a_kept_partial_frame_is_continued() {
+    let (mut server, mut peer) = raw_peer_with(restarting(RestartPolicy {
+        keep_partial_frame: true,
+        keep_messages: false,
+    }));
+    let payload = vec![7; 200_000];
+    let complete = frame(DATA, &payload);
+    let (first, second) = complete.split_at(100_000);
+    peer.write_all(first).unwrap();
+    await_bytes_received(&server, first.len() as u64);
+
+    server.restart_read_thread().unwrap();
+    peer.write_all(second).unwrap();
+    expect_payload(&mut server, DATA, &payload, TIMEOUT);
+}
+
+#[test]
+fn untaken_messages_are_discarded_or_kept() {
+    for keep_messages in [false, true] {
+        let (mut server, mut client) = pair_with(
+            restarting(RestartPolicy {
+                keep_partial_frame: false,
+                keep_messages,
+            }),
+            config(),
+        );
+        client.write_message(DATA, b"untaken").unwrap();
+        await_bytes_received(&server, frame(DATA, b"untaken").len() as u64);
+        server.restart_read_thread().unwrap();
+        client.write_message(DATA, b"new").unwrap();
+        if keep_messages {
+            expect_payload(&mut server, DATA, b"untaken", TIMEOUT);
+        } else {
+            // the discarded message is reported as a gap
+            let gap = await_some(|| server.get_message_or_gap().unwrap());
+            assert_eq!(
+                gap,
+                MessageOrGap::Gap {
+                    first_missing: 0,
+                    count: 1
+                }
+            );
+        }
+        expect_payload(&mut server, DATA, b"new", TIMEOUT);
+    }
+}
+
+#[test]
+fn a_closed_connection_is_not_restarted() {
+    let (mut server, client) = pair();
+    drop(client);
+    expect_closed(&mut server);
+    assert!(matches!(
+        server.restart_read_thread(),
+        Err(RestartError::ConnectionClosed)
+    ));
+}
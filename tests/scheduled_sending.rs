@@ -0,0 +1,79 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_millis(20);
+
+#[test]
+fn periodic_messages_are_sent_until_cancelled() {
+    let (mut server, mut client) = pair();
+    let mut tick = 0u8;
+    let handle = client
+        .send_periodic(
+            DATA,
+            move || {
+                tick += 1;
+                vec![tick]
+            },
+            INTERVAL,
+        )
+        .unwrap();
+    for tick in 1..=5u8 {
+        expect_payload(&mut server, DATA, &[tick], TIMEOUT);
+    }
+    handle.cancel();
+    assert!(handle.is_finished());
+    // a tick may have been sent while cancelling
+    let _ = server.await_message(INTERVAL, None);
+    expect_silence(&mut server, 5 * INTERVAL);
+}
+
+#[test]
+fn periodic_messages_keep_their_interval() {
+    let (mut server, mut client) = pair();
+    let start = Instant::now();
+    let handle = client.send_periodic(DATA, Vec::new, INTERVAL).unwrap();
+    for _ in 0..5 {
+        expect_payload(&mut server, DATA, &[], TIMEOUT);
+    }
+    handle.cancel();
+    // the first message is sent after one interval
+    assert!(start.elapsed() >= 5 * INTERVAL, "{:?}", start.elapsed());
+}
+
+#[test]
+fn delayed_messages_are_sent_once() {
+    let (mut server, mut client) = pair();
+    let start = Instant::now();
+    let handle = client
+        .send_after(INTERVAL, DATA, b"later".to_vec())
+        .unwrap();
+    expect_payload(&mut server, DATA, b"later", TIMEOUT);
+    assert!(start.elapsed() >= INTERVAL);
+    expect_silence(&mut server, 5 * INTERVAL);
+    assert!(handle.is_finished());
+}
+
+#[test]
+fn cancelled_delayed_messages_are_not_sent() {
+    let (mut server, mut client) = pair();
+    let handle = client
+        .send_after(5 * INTERVAL, DATA, b"never".to_vec())
+        .unwrap();
+    handle.cancel();
+    expect_silence(&mut server, 10 * INTERVAL);
+}
+
+#[test]
+fn failures_are_reported_via_get_message() {
+    let (_server, mut client) = pair();
+    let handle = client
+        .send_after(INTERVAL, UNCONSTRUCTIBLE, b"?".to_vec())
+        .unwrap();
+    match expect_error(&mut client) {
+        rust_tcp_ipc::ReadThreadErrors::ImmediateMessageConstructError(_) => {}
+        err => panic!("expected ImmediateMessageConstructError, found {:?}", err),
+    }
+    assert!(handle.is_finished());
+}
@@ -0,0 +1,215 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+/// The frame the server announces its closing with.
+const CLOSING: u8 = 0xC0;
+
+fn client(server: &TcpIpcServer<TestProtocol>) -> TcpIpc<TestProtocol> {
+    let address = server.local_addr().unwrap();
+    TcpIpc::<TestProtocol>::client(address, config(), Some(TIMEOUT)).unwrap()
+}
+
+fn accept(server: &mut TcpIpcServer<TestProtocol>, clients: usize) -> Vec<ConnectionId> {
+    let mut accepted = Vec::new();
+    await_condition(|| {
+        accepted.extend(server.accept_pending().unwrap());
+        accepted.len() == clients
+    });
+    accepted
+}
+
+#[test]
+fn a_graceful_shutdown_announces_closing_and_drains() {
+    let mut server = TcpIpcServer::<TestProtocol>::bind("127.0.0.1:0", config()).unwrap();
+    let address = server.local_addr().unwrap();
+    let mut replying = client(&server);
+    let mut leaving = client(&server);
+    let ids = accept(&mut server, 2);
+
+    // one client sends a final frame, the other one disconnects
+    let replying = std::thread::spawn(move || {
+        expect_payload(&mut replying, CLOSING, b"bye", TIMEOUT);
+        replying.write_message(DATA, b"final").unwrap();
+        expect_closed(&mut replying);
+    });
+    let leaving = std::thread::spawn(move || {
+        expect_payload(&mut leaving, CLOSING, b"bye", TIMEOUT);
+        leaving.shutdown().expect("shutdown was not clean");
+    });
+
+    let drain = Duration::from_millis(300);
+    let start = Instant::now();
+    let outcomes = server.shutdown_graceful(Some((CLOSING, b"bye".to_vec())), drain);
+    assert!(start.elapsed() >= drain);
+    let outcome_ids: Vec<ConnectionId> = outcomes.iter().map(|(id, _)| *id).collect();
+    assert_eq!(outcome_ids, ids);
+    for (id, outcome) in &outcomes {
+        assert!(outcome.is_ok(), "{}: {:?}", id, outcome);
+    }
+    replying.join().unwrap();
+    leaving.join().unwrap();
+
+    // the final frame was received during the drain, so it is delivered afterwards
+    let mut finals = Vec::new();
+    while let Some((id, message)) = server.get_message() {
+        if let Ok(message) = message {
+            finals.push((id, message));
+        }
+    }
+    assert_eq!(finals, vec![(ids[0], (DATA, b"final".to_vec()))]);
+    assert!(server.connection_ids().is_empty());
+
+    // no client is accepted anymore
+    assert!(server.local_addr().is_err());
+    assert!(server.accept_pending().unwrap().is_empty());
+    assert!(std::net::TcpStream::connect(address).is_err());
+}
+
+#[test]
+fn the_drain_ends_once_every_client_disconnected() {
+    let mut server = TcpIpcServer::<TestProtocol>::bind("127.0.0.1:0", config()).unwrap();
+    let mut leaving = client(&server);
+    accept(&mut server, 1);
+    let leaving = std::thread::spawn(move || {
+        expect_payload(&mut leaving, CLOSING, &[], TIMEOUT);
+        leaving.shutdown().expect("shutdown was not clean");
+    });
+    let start = Instant::now();
+    let outcomes = server.shutdown_graceful(Some((CLOSING, Vec::new())), TIMEOUT);
+    assert!(start.elapsed() < TIMEOUT);
+    assert_eq!(outcomes.len(), 1);
+    assert!(outcomes[0].1.is_ok());
+    leaving.join().unwrap();
+}
+
+// takes messages of all connections until the given number arrived, skipping errors
+fn messages(
+    server: &mut TcpIpcServer<TestProtocol>,
+    count: usize,
+) -> Vec<(ConnectionId, Message<TestProtocol>)> {
+    let mut received = Vec::new();
+    await_condition(|| {
+        while let Some((id, message)) = server.get_message() {
+            if let Ok(message) = message {
+                received.push((id, message));
+            }
+        }
+        received.len() >= count
+    });
+    received
+}
+
+#[test]
+fn clients_are_served_via_their_own_connections() {
+    let mut server = TcpIpcServer::<TestProtocol>::bind("127.0.0.1:0", config()).unwrap();
+    assert!(server.accept_pending().unwrap().is_empty());
+    let mut clients: Vec<_> = (0..3).map(|_| client(&server)).collect();
+    let ids = accept(&mut server, 3);
+    assert_eq!(server.connection_ids(), ids);
+
+    // a message to one connection reaches this client only
+    server.write_message(ids[1], DATA, b"only you").unwrap();
+    expect_payload(&mut clients[1], DATA, b"only you", TIMEOUT);
+    expect_silence(&mut clients[0], Duration::from_millis(20));
+    expect_silence(&mut clients[2], Duration::from_millis(20));
+
+    assert!(server.broadcast(DATA, b"everyone").is_empty());
+    for client in &mut clients {
+        expect_payload(client, DATA, b"everyone", TIMEOUT);
+    }
+
+    // the messages are tagged with the connection they were received by
+    for (i, client) in clients.iter_mut().enumerate() {
+        client.write_message(DATA, &[i as u8]).unwrap();
+    }
+    let mut received = messages(&mut server, 3);
+    received.sort_by_key(|(id, _)| *id);
+    let expected: Vec<_> = (0..3).map(|i| (ids[i], (DATA, vec![i as u8]))).collect();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn disconnecting_a_client_does_not_affect_the_others() {
+    let mut server = TcpIpcServer::<TestProtocol>::bind("127.0.0.1:0", config()).unwrap();
+    let mut staying = client(&server);
+    let mut leaving = client(&server);
+    let ids = accept(&mut server, 2);
+
+    leaving.write_message(DATA, b"unread").unwrap();
+    staying.write_message(DATA, b"kept").unwrap();
+    await_condition(|| {
+        ids.iter()
+            .all(|id| server.connection(*id).unwrap().stats().messages_received == 1)
+    });
+    assert!(server.disconnect(ids[1]).is_some());
+    assert!(server.disconnect(ids[1]).is_none());
+    expect_closed(&mut leaving);
+    assert_eq!(server.connection_ids(), vec![ids[0]]);
+    assert!(matches!(
+        server.write_message(ids[1], DATA, b"gone"),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+
+    // the queue of the other connection is untouched
+    assert_eq!(
+        messages(&mut server, 1),
+        vec![(ids[0], (DATA, b"kept".to_vec()))]
+    );
+    server.write_message(ids[0], DATA, b"still here").unwrap();
+    expect_payload(&mut staying, DATA, b"still here", TIMEOUT);
+}
+
+#[test]
+fn clients_connecting_and_leaving_concurrently_keep_their_messages_apart() {
+    let mut server = TcpIpcServer::<TestProtocol>::bind("127.0.0.1:0", config()).unwrap();
+    let address = server.local_addr().unwrap();
+    let clients: Vec<_> = (0..8u8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let mut client =
+                    TcpIpc::<TestProtocol>::client(address, config(), Some(TIMEOUT)).unwrap();
+                for sequence in 0..10u8 {
+                    client.write_message(DATA, &[i, sequence]).unwrap();
+                }
+                // every other client leaves right away
+                if i % 2 == 0 {
+                    client.shutdown().expect("shutdown was not clean");
+                    None
+                } else {
+                    Some(client)
+                }
+            })
+        })
+        .collect();
+    let mut received = Vec::new();
+    let mut accepted = Vec::new();
+    await_condition(|| {
+        accepted.extend(server.accept_pending().unwrap());
+        while let Some((id, message)) = server.get_message() {
+            if let Ok(message) = message {
+                received.push((id, message));
+            }
+        }
+        received.len() == 80
+    });
+    let _remaining: Vec<_> = clients
+        .into_iter()
+        .map(|client| client.join().unwrap())
+        .collect();
+    assert_eq!(accepted.len(), 8);
+
+    // each connection delivered the messages of exactly one client, in order
+    for id in accepted {
+        let payloads: Vec<_> = received
+            .iter()
+            .filter(|(from, _)| *from == id)
+            .map(|(_, (_, payload))| payload.clone())
+            .collect();
+        let client = payloads[0][0];
+        let expected: Vec<_> = (0..10u8).map(|sequence| vec![client, sequence]).collect();
+        assert_eq!(payloads, expected);
+    }
+}
@@ -0,0 +1,176 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// Requests a session, which is answered with the session token.
+const SESSION: u8 = 0x5E;
+
+/// The test protocol, with sessions.
+#[derive(Debug)]
+enum SessionProtocol {}
+impl Protocol for SessionProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn session_command() -> Option<u8> {
+        Some(SESSION)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn session_config() -> TcpIpcConfig<SessionProtocol> {
+    TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        ..TcpIpcConfig::default()
+    }
+}
+
+fn bind() -> TcpIpcServer<SessionProtocol> {
+    TcpIpcServer::<SessionProtocol>::bind("127.0.0.1:0", session_config()).unwrap()
+}
+
+fn reconnecting_client(server: &TcpIpcServer<SessionProtocol>) -> TcpIpc<SessionProtocol> {
+    let client_config = TcpIpcConfig {
+        reconnect: Some(ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        }),
+        ..session_config()
+    };
+    let address = server.local_addr().unwrap();
+    TcpIpc::<SessionProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap()
+}
+
+// serves the server until a client started or resumed a session
+fn await_session(server: &mut TcpIpcServer<SessionProtocol>) -> (ConnectionId, SessionEvent) {
+    let mut event = None;
+    await_condition(|| {
+        server.accept_pending().unwrap();
+        while server.get_message().is_some() {}
+        event = server.next_session_event();
+        event.is_some()
+    });
+    event.unwrap()
+}
+
+fn await_token(client: &TcpIpc<SessionProtocol>, token: SessionToken) {
+    await_condition(|| client.session_token() == Some(token));
+}
+
+#[test]
+fn a_reconnecting_client_resumes_its_session() {
+    let mut server = bind();
+    let client = reconnecting_client(&server);
+    let (first, event) = await_session(&mut server);
+    let token = match event {
+        SessionEvent::NewSession(token) => token,
+        event => panic!("unexpected {:?}", event),
+    };
+    await_token(&client, token);
+
+    // the server drops the connection, so the client re-establishes it & presents its token
+    server.disconnect(first).unwrap().ok();
+    let (second, event) = await_session(&mut server);
+    assert_ne!(second, first);
+    assert_eq!(event, SessionEvent::ResumedSession(token));
+    assert_eq!(client.session_token(), Some(token));
+    assert_eq!(server.next_session_event(), None);
+}
+
+#[test]
+fn an_expired_session_is_not_resumed() {
+    let mut server = bind();
+    server.set_session_expiry(Duration::from_millis(0));
+    let client = reconnecting_client(&server);
+    let (first, event) = await_session(&mut server);
+    let expired = event.token();
+    await_token(&client, expired);
+
+    server.disconnect(first).unwrap().ok();
+    let (_, event) = await_session(&mut server);
+    let token = match event {
+        SessionEvent::NewSession(token) => token,
+        event => panic!("unexpected {:?}", event),
+    };
+    assert_ne!(token, expired);
+    // the new token replaces the expired one
+    await_token(&client, token);
+}
+
+#[test]
+fn an_unknown_token_starts_a_new_session() {
+    let mut server = bind();
+    let mut peer = std::net::TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    for presented in [vec![0xAB; SESSION_TOKEN_LENGTH], vec![1, 2, 3]] {
+        peer.write_all(&frame(SESSION, &presented)).unwrap();
+        let token = match await_session(&mut server).1 {
+            SessionEvent::NewSession(token) => token,
+            event => panic!("unexpected {:?}", event),
+        };
+        assert_ne!(&token.0[..], &presented[..]);
+
+        let expected = frame(SESSION, &token.0);
+        let mut answer = vec![0; expected.len()];
+        peer.read_exact(&mut answer).unwrap();
+        assert_eq!(answer, expected);
+    }
+
+    // the session requests are not delivered, other messages are
+    peer.write_all(&frame(DATA, b"data")).unwrap();
+    let mut received = None;
+    await_condition(|| {
+        received = server.get_message();
+        received.is_some()
+    });
+    assert_eq!(received.unwrap().1.unwrap(), (DATA, b"data".to_vec()));
+}
+
+#[test]
+fn a_session_token_is_shown_in_hex() {
+    let mut bytes = [0; SESSION_TOKEN_LENGTH];
+    bytes[0] = 0x0F;
+    bytes[SESSION_TOKEN_LENGTH - 1] = 0xA0;
+    assert_eq!(
+        SessionToken(bytes).to_string(),
+        format!("0f{}a0", "00".repeat(SESSION_TOKEN_LENGTH - 2))
+    );
+    assert_eq!(
+        SessionToken::from_payload(&bytes),
+        Some(SessionToken(bytes))
+    );
+    assert_eq!(SessionToken::from_payload(&bytes[1..]), None);
+}
@@ -0,0 +1,230 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+const SETTLING: Duration = Duration::from_millis(150);
+
+fn read_frame(peer: &mut std::net::TcpStream, expected: &[u8]) {
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+}
+
+// the peer queries the client while it is settling, then (optionally) sends the message the client waits for
+fn settling_peer(
+    listener: std::net::TcpListener,
+    ready: Option<Vec<u8>>,
+) -> std::thread::JoinHandle<std::net::TcpStream> {
+    std::thread::spawn(move || {
+        let (mut peer, _) = listener.accept().unwrap();
+        peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+        peer.write_all(&frame(QUERY, b"settling?")).unwrap();
+        read_frame(&mut peer, &frame(REPLY, b"settling?"));
+        if let Some(ready) = ready {
+            std::thread::sleep(SETTLING);
+            peer.write_all(&ready).unwrap();
+        }
+        peer
+    })
+}
+
+#[test]
+fn the_client_returns_after_the_settling_period_and_answers_meanwhile() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = settling_peer(listener, None);
+    let start = Instant::now();
+    let client_config = TcpIpcConfig {
+        after_connect_wait_time: Some(SETTLING),
+        ..config()
+    };
+    let mut client = TcpIpc::<TestProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    assert!(start.elapsed() >= SETTLING, "{:?}", start.elapsed());
+
+    let mut peer = peer.join().unwrap();
+    client.write_message(DATA, b"first").unwrap();
+    read_frame(&mut peer, &frame(DATA, b"first"));
+}
+
+#[test]
+fn the_client_returns_once_the_handshake_is_done() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = settling_peer(listener, Some(frame(DATA, b"ready")));
+    let start = Instant::now();
+    let client_config = TcpIpcConfig {
+        ready_when: Some(ReadyCondition::AfterFirstMessage),
+        ready_wait_time: Some(TIMEOUT),
+        ..config()
+    };
+    let mut client = TcpIpc::<TestProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    assert!(start.elapsed() >= SETTLING, "{:?}", start.elapsed());
+    // the message completing the handshake stays queued
+    expect_payload(&mut client, DATA, b"ready", TIMEOUT);
+
+    // nothing but the immediate response was written before
+    let mut peer = peer.join().unwrap();
+    client.write_message(DATA, b"first").unwrap();
+    read_frame(&mut peer, &frame(DATA, b"first"));
+}
+
+#[test]
+fn a_handshake_which_does_not_complete_fails_connecting() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = settling_peer(listener, None);
+    let client_config = TcpIpcConfig {
+        ready_when: Some(ReadyCondition::AfterFirstMessage),
+        ready_wait_time: Some(SETTLING),
+        ..config()
+    };
+    let result = TcpIpc::<TestProtocol>::client(address, client_config, Some(TIMEOUT));
+    assert!(
+        matches!(result, Err(ConnectErrors::WaitTimeExceeded)),
+        "{:?}",
+        result.map(|_| ())
+    );
+    peer.join().unwrap();
+}
+
+/// The peer announces itself with this command.
+const BANNER: u8 = 0xBA;
+/// The message completing the handshake.
+const HELLO: u8 = 0x48;
+
+/// The test protocol, with a banner & a handshake.
+#[derive(Debug)]
+enum HandshakeProtocol {}
+impl Protocol for HandshakeProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn is_banner(command: &u8) -> bool {
+        *command == BANNER
+    }
+    fn is_handshake_complete(command: &u8) -> bool {
+        *command == HELLO
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+// connects a client with the given readiness to a settling peer, returning it with the time connecting took
+fn connect_when(
+    ready_when: ReadyCondition,
+    ready: Option<Vec<u8>>,
+) -> (TcpIpc<HandshakeProtocol>, Duration, std::net::TcpStream) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = settling_peer(listener, ready);
+    let start = Instant::now();
+    let client_config = TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        // the fallback is ignored, since a condition is given
+        after_connect_wait_time: Some(TIMEOUT),
+        ready_when: Some(ready_when),
+        ready_wait_time: Some(TIMEOUT),
+        ..TcpIpcConfig::default()
+    };
+    let client =
+        TcpIpc::<HandshakeProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    let elapsed = start.elapsed();
+    (client, elapsed, peer.join().unwrap())
+}
+
+#[test]
+fn an_immediately_ready_client_does_not_wait() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let start = Instant::now();
+    let client_config = TcpIpcConfig {
+        after_connect_wait_time: Some(TIMEOUT),
+        ready_when: Some(ReadyCondition::Immediately),
+        ..config()
+    };
+    let _client = TcpIpc::<TestProtocol>::client(address, client_config, Some(TIMEOUT)).unwrap();
+    assert!(start.elapsed() < SETTLING, "{:?}", start.elapsed());
+    drop(listener);
+}
+
+#[test]
+fn a_client_ready_after_a_duration_waits_for_it() {
+    let (_client, elapsed, _peer) = connect_when(ReadyCondition::AfterDuration(SETTLING), None);
+    assert!(elapsed >= SETTLING, "{:?}", elapsed);
+    assert!(elapsed < TIMEOUT, "{:?}", elapsed);
+}
+
+#[test]
+fn a_client_ready_after_the_banner_waits_for_it() {
+    let (client, elapsed, _peer) =
+        connect_when(ReadyCondition::AfterBanner, Some(frame(BANNER, b"fw 1.0")));
+    assert!(elapsed >= SETTLING, "{:?}", elapsed);
+    assert!(elapsed < TIMEOUT, "{:?}", elapsed);
+    assert_eq!(client.banner(), Some((BANNER, b"fw 1.0".to_vec())));
+}
+
+#[test]
+fn a_client_ready_after_the_handshake_keeps_the_messages_queued() {
+    let mut ready = frame(DATA, b"not yet");
+    ready.extend(frame(HELLO, b"v2"));
+    let (mut client, elapsed, _peer) = connect_when(ReadyCondition::AfterHandshake, Some(ready));
+    assert!(elapsed >= SETTLING, "{:?}", elapsed);
+    assert!(elapsed < TIMEOUT, "{:?}", elapsed);
+    // both messages stay queued, in order
+    expect_payload(&mut client, DATA, b"not yet", TIMEOUT);
+    expect_payload(&mut client, HELLO, b"v2", TIMEOUT);
+}
+
+#[test]
+fn a_handshake_without_the_completing_message_fails_connecting() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = settling_peer(listener, Some(frame(DATA, b"not a hello")));
+    let client_config = TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        ready_when: Some(ReadyCondition::AfterHandshake),
+        ready_wait_time: Some(2 * SETTLING),
+        ..TcpIpcConfig::default()
+    };
+    let result = TcpIpc::<HandshakeProtocol>::client(address, client_config, Some(TIMEOUT));
+    assert!(
+        matches!(result, Err(ConnectErrors::WaitTimeExceeded)),
+        "{:?}",
+        result.map(|_| ())
+    );
+    peer.join().unwrap();
+}
@@ -0,0 +1,68 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+
+#[test]
+fn shutdown_drains_queued_frames() {
+    let (mut server, mut client) = pair();
+    // large frames do not fit into the socket buffers, so their rest is queued & written by the read thread
+    let payloads: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 1 << 18]).collect();
+    for payload in &payloads {
+        client.write_message(DATA, payload).unwrap();
+    }
+    let report = client.shutdown().expect("shutdown was not clean");
+    assert_eq!(report.abandoned_frames, 0);
+    assert!(report.drained_outgoing <= payloads.len());
+
+    for payload in &payloads {
+        expect_payload(&mut server, DATA, payload, TIMEOUT);
+    }
+}
+
+#[test]
+fn shutdown_finishes_immediate_responses() {
+    let (server, mut client) = pair();
+    let payload = vec![7; 1 << 18];
+    client.write_message(QUERY, &payload).unwrap();
+    // shut down as soon as the answer is queued, which is mostly before it is written completely
+    let start = std::time::Instant::now();
+    while server.stats().immediate_responses_sent == 0 {
+        assert!(start.elapsed() < TIMEOUT, "query was not answered");
+        std::thread::yield_now();
+    }
+    let report = server.shutdown().expect("shutdown was not clean");
+    assert_eq!(report.abandoned_frames, 0);
+
+    expect_payload(&mut client, REPLY, &payload, TIMEOUT);
+}
+
+#[test]
+fn shutdown_is_honored_within_the_control_check_interval_under_traffic() {
+    let interval = std::time::Duration::from_millis(100);
+    let server_config = rust_tcp_ipc::TcpIpcConfig {
+        control_check_interval: Some(interval),
+        // counted in iterations, this would postpone the control requests for a long time
+        check_count: u32::MAX,
+        read_iteration_wait_time: None,
+        ..config()
+    };
+    let (server, mut client) = pair_with(server_config, config());
+    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let flooding = stop.clone();
+    let flood = std::thread::spawn(move || {
+        while !flooding.load(std::sync::atomic::Ordering::SeqCst) {
+            if client.write_message(DATA, &[0; 64]).is_err() {
+                break;
+            }
+        }
+    });
+    std::thread::sleep(interval);
+    assert!(server.stats().messages_received > 0);
+
+    let start = std::time::Instant::now();
+    let _ = server.shutdown();
+    let elapsed = start.elapsed();
+    stop.store(true, std::sync::atomic::Ordering::SeqCst);
+    flood.join().unwrap();
+    assert!(elapsed < 2 * interval, "shutdown took {:?}", elapsed);
+}
@@ -0,0 +1,191 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+/// A query whose immediate response takes 'HANG_TIME', so the read thread hangs meanwhile.
+const HANG: u8 = 0x68;
+const HANG_TIME: Duration = Duration::from_millis(300);
+/// A query which makes the responder panic, which unwinds the read thread.
+const PANIC: u8 = 0x70;
+
+/// The test protocol, with a hanging responder.
+#[derive(Debug)]
+enum HangProtocol {}
+impl Protocol for HangProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        TestProtocol::idle()
+    }
+    fn message_is_answered_via_immediate_route(
+        command: &u8,
+        message: &[u8],
+        busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        if *command == HANG {
+            std::thread::sleep(HANG_TIME);
+            return Some((REPLY, message.to_vec()));
+        }
+        if *command == PANIC {
+            panic!("the responder gave up");
+        }
+        TestProtocol::message_is_answered_via_immediate_route(command, message, busy_state)
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+}
+
+fn hang_pair(shutdown_wait_time: Option<Duration>) -> (TcpIpc<HangProtocol>, TcpIpc<HangProtocol>) {
+    let config = |shutdown_wait_time| TcpIpcConfig {
+        read_iteration_wait_time: Some(Duration::from_micros(10)),
+        shutdown_wait_time,
+        ..TcpIpcConfig::default()
+    };
+    rust_tcp_ipc::testing::loopback(config(shutdown_wait_time), config(Some(TIMEOUT))).unwrap()
+}
+
+// makes the read thread of the server hang in its immediate route
+fn hang(server: &TcpIpc<HangProtocol>, client: &mut TcpIpc<HangProtocol>) {
+    client.write_message(HANG, b"hang").unwrap();
+    let start = Instant::now();
+    while server.stats().bytes_received < frame(HANG, b"hang").len() as u64 {
+        assert!(start.elapsed() < TIMEOUT, "the query was not received");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn a_clean_shutdown_reports_every_phase() {
+    let (_server, client) = pair();
+    let report = client.shutdown().expect("shutdown was not clean");
+    assert_eq!(report.request, Ok(()));
+    assert!(report.socket.is_ok());
+    assert_eq!(
+        report.read_thread,
+        JoinOutcome {
+            finished: true,
+            panicked: false,
+            timed_out: false,
+        }
+    );
+    assert_eq!((report.drained_outgoing, report.abandoned_frames), (0, 0));
+    assert!(report.read_thread_panic.is_none());
+}
+
+#[test]
+fn a_shutdown_after_the_peer_closed_reports_the_closed_connection() {
+    let (mut server, client) = pair();
+    drop(client);
+    expect_closed(&mut server);
+    let report = server
+        .shutdown()
+        .expect_err("the shutdown request cannot be passed on");
+    assert_eq!(
+        report.request,
+        Err(ShutdownRequestFailure::ConnectionClosed)
+    );
+    // the read thread stopped before & left its confirmation behind
+    assert!(report.read_thread.finished);
+    assert!(!report.read_thread.panicked && !report.read_thread.timed_out);
+}
+
+#[test]
+fn a_hung_read_thread_times_out() {
+    let (server, mut client) = hang_pair(Some(Duration::from_millis(50)));
+    hang(&server, &mut client);
+    let start = Instant::now();
+    let report = server.shutdown().expect_err("the read thread hung");
+    assert!(
+        start.elapsed() < HANG_TIME,
+        "shutdown took {:?}",
+        start.elapsed()
+    );
+    assert_eq!(report.request, Ok(()));
+    assert_eq!(
+        report.read_thread,
+        JoinOutcome {
+            finished: false,
+            panicked: false,
+            timed_out: true,
+        }
+    );
+}
+
+#[test]
+fn without_a_wait_time_the_confirmation_is_awaited() {
+    let (server, mut client) = hang_pair(None);
+    hang(&server, &mut client);
+    let start = Instant::now();
+    let report = server.shutdown().expect("shutdown was not clean");
+    assert!(
+        start.elapsed() >= HANG_TIME / 2,
+        "shutdown took {:?}",
+        start.elapsed()
+    );
+    assert!(report.read_thread.finished && !report.read_thread.timed_out);
+}
+
+#[test]
+fn a_panicked_read_thread_is_reported_with_its_payload() {
+    let (mut server, mut client) = hang_pair(Some(TIMEOUT));
+    client.write_message(PANIC, b"").unwrap();
+    expect_closed(&mut server);
+    let report = server.shutdown().expect_err("the read thread panicked");
+    assert_eq!(
+        report.read_thread,
+        JoinOutcome {
+            finished: true,
+            panicked: true,
+            timed_out: false,
+        }
+    );
+    let panic = report.read_thread_panic.expect("the payload was not kept");
+    assert_eq!(panic.downcast_ref::<&str>(), Some(&"the responder gave up"));
+}
+
+#[test]
+fn a_read_thread_which_ended_regularly_is_not_reported_as_panicked() {
+    let (server, client) = hang_pair(Some(TIMEOUT));
+    // the read thread of the client stops after a shutdown, the one of the server after the peer closed
+    client.shutdown().expect("shutdown was not clean");
+    await_condition(|| server.is_connection_closed());
+    let report = server.shutdown().unwrap_err();
+    assert!(report.read_thread.finished);
+    assert!(!report.read_thread.panicked);
+    assert!(report.read_thread_panic.is_none());
+}
+
+#[test]
+#[allow(deprecated)]
+fn the_report_converts_to_the_deprecated_error() {
+    let (mut server, client) = pair();
+    drop(client);
+    expect_closed(&mut server);
+    let report = server.shutdown().unwrap_err();
+    let error = ShutdownError::from(report);
+    assert!(!error.shutdown_requested_succesfully);
+    assert!(error.ack_received);
+}
@@ -0,0 +1,59 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+fn buffered(buffer_size: Option<usize>) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        send_buffer_size: buffer_size,
+        recv_buffer_size: buffer_size,
+        ..config()
+    }
+}
+
+// transfers a 1 MB payload from the server to the client
+fn transfer(buffer_size: Option<usize>) {
+    let (mut server, mut client) = pair_with(buffered(buffer_size), buffered(buffer_size));
+    let payload: Vec<u8> = (0..1 << 20).map(|i: u32| (i % 251) as u8).collect();
+    server.write_message(DATA, &payload).unwrap();
+    let message = client.await_message(TIMEOUT * 6, None).unwrap();
+    assert!(
+        message == Some((DATA, payload)),
+        "the payload was corrupted"
+    );
+}
+
+#[test]
+fn the_os_default_buffers_transfer_a_megabyte() {
+    transfer(None);
+}
+
+#[test]
+fn configured_buffers_transfer_a_megabyte() {
+    transfer(Some(1 << 14));
+}
+
+#[test]
+fn the_configured_sizes_are_set_on_the_socket() {
+    let (server, client) = pair_with(buffered(Some(1 << 14)), buffered(None));
+    // the operating system may round up the configured size (Linux doubles it)
+    let (send, recv) = (
+        server.send_buffer_size().unwrap(),
+        server.recv_buffer_size().unwrap(),
+    );
+    assert!(
+        (1 << 14..=1 << 16).contains(&send),
+        "send buffer of {}",
+        send
+    );
+    assert!(
+        (1 << 14..=1 << 16).contains(&recv),
+        "receive buffer of {}",
+        recv
+    );
+    // the defaults of the operating system are left untouched
+    assert!(client.send_buffer_size().unwrap() > 0);
+    assert!(client.recv_buffer_size().unwrap() > 0);
+    let report = server.capability_report();
+    assert_eq!(report.config.send_buffer_size, Some(1 << 14));
+    assert_eq!(report.config.recv_buffer_size, Some(1 << 14));
+}
@@ -0,0 +1,42 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+
+#[test]
+fn a_healthy_connection_has_no_socket_error() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"healthy").unwrap();
+    rust_tcp_ipc::testing::expect_payload(&mut server, DATA, b"healthy", TIMEOUT);
+    assert!(server.take_socket_error().unwrap().is_none());
+    assert!(client.take_socket_error().unwrap().is_none());
+}
+
+// the error of an aborted connection is reported once: either taken via 'take_socket_error' or by the read thread (which takes it as well)
+#[test]
+fn the_error_of_an_aborted_connection_is_reported() {
+    let (mut server, peer) = raw_peer();
+    server.write_message(DATA, b"unread").unwrap();
+    let mut unread = [0; 1];
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    peer.peek(&mut unread).unwrap();
+    // closing a socket with unread data aborts the connection (the peer sends a reset)
+    drop(peer);
+
+    let start = std::time::Instant::now();
+    let error = loop {
+        assert!(start.elapsed() < TIMEOUT, "the reset was not reported");
+        if let Some(err) = server.take_socket_error().unwrap() {
+            break err;
+        }
+        match server.get_message() {
+            Err(ReadThreadErrors::ReadError(err)) => break err,
+            Err(ReadThreadErrors::RepeatedReadError(repeated)) => break repeated.error,
+            Ok(None) => std::thread::sleep(std::time::Duration::from_millis(1)),
+            result => panic!("unexpected {:?}", result),
+        }
+    };
+    assert_eq!(error.kind(), std::io::ErrorKind::ConnectionReset);
+    assert!(server.take_socket_error().unwrap().is_none());
+    // a reset counts as closed by the peer, like a regular close
+    await_condition(|| server.connection_state() == ConnectionState::PeerClosed);
+}
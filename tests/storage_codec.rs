@@ -0,0 +1,107 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::journal::*;
+use rust_tcp_ipc::replay::*;
+use rust_tcp_ipc::*;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const SECRET: &[u8] = b"top secret";
+
+fn temp_path(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "rust_tcp_ipc-{}-{}.codec",
+        std::process::id(),
+        test
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn contains(content: &[u8], bytes: &[u8]) -> bool {
+    content.windows(bytes.len()).any(|window| window == bytes)
+}
+
+// the inner error of an error of kind 'InvalidData'
+fn inner_error<E: std::error::Error + Clone + 'static>(err: std::io::Error) -> E {
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<E>())
+        .unwrap_or_else(|| panic!("unexpected error {:?}", err))
+        .clone()
+}
+
+#[test]
+fn a_journal_is_recovered_through_the_codec() {
+    let path = temp_path("journal");
+    let codec: SharedStorageCodec = Arc::new(XorCodec::new(b"key"));
+    let (mut server, _peer) = raw_peer_with(TcpIpcConfig {
+        journal: Some(JournalConfig {
+            path: path.clone(),
+            fsync: false,
+            codec: Some(codec.clone()),
+        }),
+        ..config()
+    });
+    server.write_message_journaled(DATA, SECRET).unwrap();
+    // the peer does not read, so this frame stays pending
+    let payload: Vec<u8> = (0..1 << 24).map(|i: u32| (i % 251) as u8).collect();
+    let id = server.write_message_journaled(DATA, &payload).unwrap();
+    drop(server);
+
+    assert!(!contains(&std::fs::read(&path).unwrap(), SECRET));
+    let pending = recover_with(&path, &*codec).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, id);
+    assert!(pending[0].frame == frame(DATA, &payload));
+    // the plain reader refuses the encoded frames
+    assert_eq!(
+        recover(&path).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_capture_is_read_back_through_the_codec() {
+    let path = temp_path("capture");
+    let codec = XorCodec::new(b"key");
+    let records = vec![
+        CaptureRecord::received(frame(DATA, SECRET)[..4].to_vec()),
+        CaptureRecord::received(frame(DATA, SECRET)[4..].to_vec()),
+        CaptureRecord {
+            direction: FrameDirection::Sent,
+            bytes: frame(REPLY, SECRET),
+        },
+    ];
+    save_capture(&path, &records, Some(&codec)).unwrap();
+    let hex: String = SECRET.iter().map(|byte| format!(" {:02x}", byte)).collect();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains(&hex));
+
+    assert_eq!(load_capture_with(&path, &codec).unwrap(), records);
+    assert_eq!(
+        load_capture(&path).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_decode_failure_is_a_typed_error() {
+    let path = temp_path("undecodable");
+    // a capture without header is decoded if a codec is given, but these bytes lack the marker of the codec
+    std::fs::write(&path, "< 01 02 03\n").unwrap();
+    let error: CodecError =
+        inner_error(load_capture_with(&path, &XorCodec::new(b"key")).unwrap_err());
+    assert!(error.reason.contains("marker"), "{}", error);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn the_pass_through_codec_keeps_the_bytes() {
+    assert_eq!(PassThrough.encode(SECRET), SECRET);
+    assert_eq!(PassThrough.decode(SECRET), Ok(SECRET.to_vec()));
+    let codec = XorCodec::new(b"key");
+    assert_ne!(codec.encode(SECRET)[1..], *SECRET);
+    assert_eq!(codec.decode(&codec.encode(SECRET)), Ok(SECRET.to_vec()));
+}
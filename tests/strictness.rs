@@ -0,0 +1,90 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+
+/// The memory budget of the connections, a message larger than this is refused.
+const BUDGET: usize = 1 << 16;
+
+// a header which cannot be parsed, together with the reason
+fn violations() -> Vec<(Vec<u8>, ParseHeaderError)> {
+    let header = |command: u8, length: u64| {
+        let mut header = vec![command];
+        header.extend_from_slice(&length.to_be_bytes());
+        header
+    };
+    vec![
+        (header(UNKNOWN, 0), ParseHeaderError::CommandParseFailed),
+        (
+            header(DATA, MAX_LENGTH + 1),
+            ParseHeaderError::LengthParseFailed,
+        ),
+        (
+            header(DATA, 2 * BUDGET as u64),
+            ParseHeaderError::LengthTooLarge,
+        ),
+    ]
+}
+
+fn server_with(strictness: Strictness) -> (TcpIpc<TestProtocol>, std::net::TcpStream) {
+    let (server, peer) = raw_peer_with(TcpIpcConfig {
+        strictness,
+        memory_budget: Some(BUDGET),
+        ..config()
+    });
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    (server, peer)
+}
+
+fn expect_violation(server: &mut TcpIpc<TestProtocol>, header: &[u8], error: ParseHeaderError) {
+    match expect_error(server) {
+        ReadThreadErrors::ProtocolViolation(violation) => {
+            assert_eq!(violation.error, error);
+            assert_eq!(violation.header, header);
+        }
+        err => panic!("expected ProtocolViolation, found {:?}", err),
+    }
+}
+
+#[test]
+fn lenient_mode_reports_violations_and_keeps_the_connection() {
+    for (header, error) in violations() {
+        let (mut server, mut peer) = server_with(Strictness::Lenient);
+        peer.write_all(&header).unwrap();
+        peer.write_all(&frame(DATA, b"after")).unwrap();
+
+        expect_violation(&mut server, &header, error);
+        expect_payload(&mut server, DATA, b"after", TIMEOUT);
+        assert!(!server.is_connection_closed());
+        server.write_message(DATA, b"still open").unwrap();
+        let mut received = vec![0; frame(DATA, b"still open").len()];
+        peer.read_exact(&mut received).unwrap();
+        assert_eq!(received, frame(DATA, b"still open"));
+    }
+}
+
+#[test]
+fn strict_mode_terminates_the_connection_on_any_violation() {
+    for (header, error) in violations() {
+        let (mut server, mut peer) = server_with(Strictness::Strict);
+        peer.write_all(&header).unwrap();
+        peer.write_all(&frame(DATA, b"after")).unwrap();
+
+        expect_violation(&mut server, &header, error);
+        // the fault frame is the last thing the peer receives
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).unwrap();
+        assert_eq!(received, frame(ERROR, &header));
+
+        assert!(server.is_connection_closed());
+        assert!(matches!(
+            server.get_message(),
+            Err(ReadThreadErrors::ConnectionClosed)
+        ));
+        assert!(matches!(
+            server.write_message(DATA, b"late"),
+            Err(WriteMessageErrors::ConnectionClosed)
+        ));
+    }
+}
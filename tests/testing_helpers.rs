@@ -0,0 +1,74 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use std::time::Duration;
+
+#[test]
+fn expect_message_returns_the_payload() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"payload").unwrap();
+    assert_eq!(expect_message(&mut server, DATA, TIMEOUT), b"payload");
+}
+
+#[test]
+#[should_panic(expected = "unexpected command")]
+fn expect_message_panics_on_another_command() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"payload").unwrap();
+    expect_message(&mut server, ERROR, TIMEOUT);
+}
+
+#[test]
+#[should_panic(expected = "no message arrived within")]
+fn expect_message_panics_on_timeout() {
+    let (mut server, _client) = pair();
+    expect_message(&mut server, DATA, Duration::from_millis(50));
+}
+
+#[test]
+#[should_panic(expected = "first difference at byte 2")]
+fn expect_payload_shows_the_first_difference() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, &[1, 2, 3]).unwrap();
+    expect_payload(&mut server, DATA, &[1, 2, 4], TIMEOUT);
+}
+
+#[test]
+fn expect_silence_accepts_silence() {
+    let (mut server, _client) = pair();
+    expect_silence(&mut server, Duration::from_millis(50));
+}
+
+#[test]
+#[should_panic(expected = "expected no message")]
+fn expect_silence_panics_on_a_message() {
+    let (mut server, mut client) = pair();
+    client.write_message(DATA, b"noise").unwrap();
+    expect_silence(&mut server, TIMEOUT);
+}
+
+#[test]
+fn loopback_pairs_coexist() {
+    let (mut first_server, mut first_client) = pair();
+    let (mut second_server, mut second_client) = pair();
+    // each pair got its own ephemeral port & its endpoints are connected to each other
+    assert_ne!(
+        first_server.local_addr().unwrap(),
+        second_server.local_addr().unwrap()
+    );
+    assert_eq!(
+        first_server.peer_addr().unwrap(),
+        first_client.local_addr().unwrap()
+    );
+    assert_eq!(
+        second_server.peer_addr().unwrap(),
+        second_client.local_addr().unwrap()
+    );
+    first_client.write_message(DATA, b"first").unwrap();
+    second_client.write_message(DATA, b"second").unwrap();
+    expect_payload(&mut second_server, DATA, b"second", TIMEOUT);
+    expect_payload(&mut first_server, DATA, b"first", TIMEOUT);
+    first_server.write_message(DATA, b"back").unwrap();
+    expect_payload(&mut first_client, DATA, b"back", TIMEOUT);
+    assert!(second_client.get_message().unwrap().is_none());
+}
@@ -0,0 +1,56 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::sync::{Arc, Mutex};
+
+fn send<T: Send>() {}
+fn send_sync<T: Send + Sync>() {}
+
+#[test]
+fn the_documented_types_may_cross_threads() {
+    send::<TcpIpc<TestProtocol>>();
+    send::<TcpIpcServer<TestProtocol>>();
+    send::<ReadThreadErrors<TestProtocol>>();
+    send_sync::<TcpIpcConfig<TestProtocol>>();
+    send_sync::<DeliveryHandle<TestProtocol>>();
+    send_sync::<PeriodicHandle>();
+    send_sync::<ConnectionGroup<TestProtocol>>();
+}
+
+#[test]
+fn a_connection_can_be_moved_to_another_thread() {
+    let (mut server, client) = pair();
+    let client = std::thread::spawn(move || {
+        let mut client = client;
+        client.write_message(DATA, b"moved").unwrap();
+        client
+    })
+    .join()
+    .unwrap();
+    expect_payload(&mut server, DATA, b"moved", TIMEOUT);
+    drop(client);
+}
+
+#[test]
+fn a_connection_behind_a_mutex_is_shared_by_threads() {
+    let (mut server, client) = pair();
+    let client = Arc::new(Mutex::new(client));
+    let writers: Vec<_> = (0..4u8)
+        .map(|index| {
+            let client = client.clone();
+            std::thread::spawn(move || client.lock().unwrap().write_message(DATA, &[index]))
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap().unwrap();
+    }
+    let mut received: Vec<u8> = (0..4)
+        .map(|_| match server.await_message(TIMEOUT, None).unwrap() {
+            Some((DATA, payload)) => payload[0],
+            message => panic!("unexpected {:?}", message),
+        })
+        .collect();
+    received.sort_unstable();
+    assert_eq!(received, vec![0, 1, 2, 3]);
+}
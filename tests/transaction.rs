@@ -0,0 +1,65 @@
+mod common;
+use common::*;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+fn read_request(peer: &mut std::net::TcpStream, payload: &[u8]) {
+    let expected = frame(DATA, payload);
+    let mut received = vec![0; expected.len()];
+    peer.read_exact(&mut received).unwrap();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn a_late_response_is_not_mistaken_for_the_next_one() {
+    let (mut server, mut peer) = raw_peer();
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    // the first request times out, its response arrives late (after unrelated traffic)
+    let response = server
+        .transaction()
+        .send_and_wait(DATA, b"first", REPLY, Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(response, None);
+    read_request(&mut peer, b"first");
+    let mut late = frame(DATA, b"unsolicited");
+    late.extend(frame(REPLY, b"stale"));
+    peer.write_all(&late).unwrap();
+    await_bytes_received(&server, late.len() as u64);
+
+    let answering = std::thread::spawn(move || {
+        read_request(&mut peer, b"second");
+        peer.write_all(&frame(REPLY, b"fresh")).unwrap();
+        peer
+    });
+    let response = server
+        .transaction()
+        .send_and_wait(DATA, b"second", REPLY, TIMEOUT)
+        .unwrap();
+    assert_eq!(response, Some(b"fresh".to_vec()));
+    let _peer = answering.join().unwrap();
+
+    // the frames received meanwhile are delivered in order afterwards
+    assert_eq!(
+        server.get_message().unwrap(),
+        Some((DATA, b"unsolicited".to_vec()))
+    );
+    assert_eq!(
+        server.get_message().unwrap(),
+        Some((REPLY, b"stale".to_vec()))
+    );
+    assert_eq!(server.get_message().unwrap(), None);
+}
+
+#[test]
+fn a_response_without_stale_frames_is_returned() {
+    let (mut server, mut client) = pair();
+    // the server answers QUERY via the immediate route
+    let response = client
+        .transaction()
+        .send_and_wait(QUERY, b"ping", REPLY, TIMEOUT)
+        .unwrap();
+    assert_eq!(response, Some(b"ping".to_vec()));
+    assert_eq!(client.get_message().unwrap(), None);
+    assert_eq!(server.get_message().unwrap(), None);
+}
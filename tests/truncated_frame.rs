@@ -0,0 +1,84 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::io::Write;
+
+const DECLARED: usize = 1 << 20;
+
+// the peer sends the given bytes & closes its sending side, returning the terminal error
+fn close_after(
+    config: TcpIpcConfig<TestProtocol>,
+    bytes: &[u8],
+) -> (TcpIpc<TestProtocol>, ReadThreadErrors<TestProtocol>) {
+    let (mut server, mut peer) = raw_peer_with(config);
+    peer.write_all(bytes).unwrap();
+    peer.shutdown(std::net::Shutdown::Write).unwrap();
+    let error = expect_error(&mut server);
+    (server, error)
+}
+
+fn mid_frame(error: ReadThreadErrors<TestProtocol>) -> Option<TruncatedFrame<TestProtocol>> {
+    match error {
+        ReadThreadErrors::PeerClosed { mid_frame } => mid_frame,
+        error => panic!("expected PeerClosed, found {:?}", error),
+    }
+}
+
+#[test]
+fn a_payload_cut_in_half_is_reported() {
+    let mut bytes = frame(DATA, b"complete");
+    let truncated = frame(URGENT, &vec![7; DECLARED]);
+    let header_length = truncated.len() - DECLARED;
+    bytes.extend_from_slice(&truncated[..header_length + DECLARED / 2]);
+
+    let (mut server, mut peer) = raw_peer();
+    peer.write_all(&bytes).unwrap();
+    peer.shutdown(std::net::Shutdown::Write).unwrap();
+    // the complete message is delivered first, the partial one never
+    expect_payload(&mut server, DATA, b"complete", TIMEOUT);
+    assert_eq!(
+        mid_frame(expect_error(&mut server)),
+        Some(TruncatedFrame {
+            command: Some(URGENT),
+            received: DECLARED / 2,
+            declared: Some(DECLARED),
+        })
+    );
+    assert_eq!(server.connection_state(), ConnectionState::PeerClosed);
+    assert_eq!(server.stats().messages_received, 1);
+}
+
+#[test]
+fn a_header_cut_in_half_is_reported() {
+    let header = &frame(DATA, b"payload")[..4];
+    let (_server, error) = close_after(config(), header);
+    assert_eq!(
+        mid_frame(error),
+        Some(TruncatedFrame {
+            command: None,
+            received: 4,
+            declared: None,
+        })
+    );
+}
+
+#[test]
+fn a_close_between_frames_is_not_a_truncation() {
+    let (_server, error) = close_after(config(), &frame(DATA, b"complete"));
+    assert_eq!(mid_frame(error), None);
+}
+
+#[test]
+fn the_drop_policy_does_not_report_the_truncation() {
+    let truncated = frame(DATA, &[7; 20]);
+    let (server, error) = close_after(
+        TcpIpcConfig {
+            on_truncated_frame: TruncatedFramePolicy::Drop,
+            ..config()
+        },
+        &truncated[..truncated.len() - 10],
+    );
+    assert_eq!(mid_frame(error), None);
+    assert_eq!(server.stats().messages_received, 0);
+}
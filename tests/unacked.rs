@@ -0,0 +1,109 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::time::Duration;
+
+// waits for the next message taken via 'get_message_unacked'
+fn next_unacked(ipc: &mut TcpIpc<TestProtocol>) -> UnackedMessage<TestProtocol> {
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(message) = ipc.get_message_unacked().unwrap() {
+            return message;
+        }
+        assert!(start.elapsed() < TIMEOUT, "no message within {:?}", TIMEOUT);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn a_dropped_guard_is_redelivered() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"side effect").unwrap();
+    let message = next_unacked(&mut client);
+    assert_eq!(message.message(), &(DATA, b"side effect".to_vec()));
+    assert_eq!(message.metadata().redeliveries, 0);
+    let sequence = message.metadata().sequence;
+    // processing failed, so the message is not acknowledged
+    drop(message);
+    assert_eq!(client.in_flight_count(), 1);
+
+    let message = next_unacked(&mut client);
+    assert_eq!(message.payload(), b"side effect");
+    assert_eq!(message.metadata().redeliveries, 1);
+    assert_eq!(message.metadata().sequence, sequence);
+    drop(message);
+    assert_eq!(next_unacked(&mut client).metadata().redeliveries, 2);
+}
+
+#[test]
+fn acked_messages_are_never_seen_again() {
+    let (mut server, mut client) = pair();
+    for i in 0..3u8 {
+        server.write_message(DATA, &[i]).unwrap();
+    }
+    for i in 0..3u8 {
+        let message = next_unacked(&mut client);
+        assert_eq!(message.payload(), &[i]);
+        assert!(message.ack());
+    }
+    assert_eq!(client.in_flight_count(), 0);
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(client.get_message_unacked().unwrap().is_none());
+}
+
+#[test]
+fn redeliveries_come_before_new_messages() {
+    let (mut server, mut client) = pair();
+    server.write_message(DATA, b"first").unwrap();
+    server.write_message(DATA, b"second").unwrap();
+    drop(next_unacked(&mut client));
+    await_bytes_received(
+        &client,
+        (frame(DATA, b"first").len() + frame(DATA, b"second").len()) as u64,
+    );
+    let message = next_unacked(&mut client);
+    assert_eq!(message.payload(), b"first");
+    assert!(message.ack());
+    assert_eq!(next_unacked(&mut client).payload(), b"second");
+}
+
+#[test]
+fn a_held_message_is_redelivered_after_the_visibility_timeout() {
+    let (mut server, mut client) = pair_with(
+        config(),
+        TcpIpcConfig {
+            ack_visibility_timeout: Some(Duration::from_millis(50)),
+            ..config()
+        },
+    );
+    server.write_message(DATA, b"slow").unwrap();
+    let stale = next_unacked(&mut client);
+    // the guard is still alive, but the message is delivered again once the timeout passed
+    assert!(client.get_message_unacked().unwrap().is_none());
+    std::thread::sleep(Duration::from_millis(60));
+    let fresh = next_unacked(&mut client);
+    assert_eq!(fresh.payload(), b"slow");
+    assert_eq!(fresh.metadata().redeliveries, 1);
+    // acknowledging the outdated delivery does not count
+    assert!(!stale.ack());
+    assert_eq!(client.in_flight_count(), 1);
+    assert!(fresh.ack());
+    assert_eq!(client.in_flight_count(), 0);
+}
+
+#[test]
+fn in_flight_messages_stay_in_the_memory_budget() {
+    let (mut server, mut client) = pair_with(
+        config(),
+        TcpIpcConfig {
+            memory_budget: Some(1 << 16),
+            ..config()
+        },
+    );
+    let baseline = client.stats().memory.unwrap().used;
+    server.write_message(DATA, &[7; 1000]).unwrap();
+    let message = next_unacked(&mut client);
+    assert!(client.stats().memory.unwrap().used >= baseline + 1000);
+    assert!(message.ack());
+    assert_eq!(client.stats().memory.unwrap().used, baseline);
+}
@@ -0,0 +1,54 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::time::{Duration, Instant};
+
+const LONG_WAIT: Duration = Duration::from_secs(10);
+
+// wakes the given waker after a short while, from another thread
+fn wake_soon(waker: AwaitWaker) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        waker.wake();
+    })
+}
+
+#[test]
+fn a_wake_interrupts_a_long_await() {
+    let (_server, mut client) = pair();
+    let waker = wake_soon(client.waker());
+    let start = Instant::now();
+    let result = client.await_message(LONG_WAIT, Some(Duration::from_millis(1)));
+    let elapsed = start.elapsed();
+    waker.join().unwrap();
+    assert!(matches!(result, Err(ReadThreadErrors::Interrupted)));
+    // the await returns within 100 ms of the wake
+    assert!(elapsed < Duration::from_millis(150), "took {:?}", elapsed);
+    assert!(elapsed >= Duration::from_millis(50), "took {:?}", elapsed);
+}
+
+#[test]
+fn a_wake_interrupts_an_await_for_any_command() {
+    let (mut server, mut client) = pair();
+    let waker = wake_soon(client.waker());
+    let start = Instant::now();
+    let result = client.await_any_command(&[REPLY, ERROR], LONG_WAIT);
+    waker.join().unwrap();
+    assert!(matches!(result, Err(ReadThreadErrors::Interrupted)));
+    assert!(start.elapsed() < Duration::from_millis(150));
+
+    // the connection is not affected
+    server.write_message(DATA, b"after").unwrap();
+    expect_payload(&mut client, DATA, b"after", TIMEOUT);
+}
+
+#[test]
+fn an_earlier_wake_has_no_effect() {
+    let (mut server, mut client) = pair();
+    // a wake while no await is in progress
+    client.waker().wake();
+    server.write_message(DATA, b"later").unwrap();
+    let result = client.await_message(TIMEOUT, Some(Duration::from_millis(1)));
+    assert_eq!(result.unwrap(), Some((DATA, b"later".to_vec())));
+}
@@ -0,0 +1,70 @@
+mod common;
+use common::TIMEOUT;
+use rust_tcp_ipc::testing::*;
+use rust_tcp_ipc::*;
+use std::convert::{TryFrom, TryInto};
+
+/// A command id above u32::MAX.
+const WIDE: u64 = (1 << 40) + 5;
+
+/// A protocol with an 8-byte command field, followed by a 4-byte length (both big-endian).
+#[derive(Debug)]
+enum WideProtocol {}
+impl Protocol for WideProtocol {
+    type Commands = u64;
+    type BusyStates = ();
+    type CommandAsArray = [u8; 8];
+    type LengthAsArray = [u8; 4];
+    type HeaderAsArray = [u8; 12];
+    fn idle() {}
+    fn message_is_answered_via_immediate_route(
+        _command: &u64,
+        _message: &[u8],
+        _busy_state: &(),
+    ) -> Option<(u64, Vec<u8>)> {
+        None
+    }
+    fn parse_command(command: &[u8; 8]) -> Option<u64> {
+        Some(u64::from_be_bytes(*command))
+    }
+    fn parse_length(length: &[u8; 4]) -> Option<usize> {
+        usize::try_from(u32::from_be_bytes(*length)).ok()
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 12], &[u8])> {
+        if input.len() >= 12 {
+            Some((input[..12].try_into().unwrap(), &input[12..]))
+        } else {
+            None
+        }
+    }
+    fn split_header_array(header: &[u8; 12]) -> (&[u8; 8], &[u8; 4]) {
+        (
+            header[..8].try_into().unwrap(),
+            header[8..].try_into().unwrap(),
+        )
+    }
+    fn command_to_array(command: u64) -> [u8; 8] {
+        command.to_be_bytes()
+    }
+    fn get_length_as_array(_command: u64, message: &[u8]) -> Option<[u8; 4]> {
+        u32::try_from(message.len()).ok().map(u32::to_be_bytes)
+    }
+    fn construct_header(command: [u8; 8], length: [u8; 4]) -> Vec<u8> {
+        let mut header = command.to_vec();
+        header.extend_from_slice(&length);
+        header
+    }
+}
+
+#[test]
+fn commands_beyond_u32_round_trip_through_an_8_byte_field() {
+    let frame = WideProtocol::construct_message(WIDE, b"wide").unwrap();
+    assert_eq!(&frame[..8], &WIDE.to_be_bytes());
+
+    let config = TcpIpcConfig::<WideProtocol>::default();
+    let (mut server, mut client) = loopback(config.clone(), config).unwrap();
+    client.write_message(WIDE, b"wide").unwrap();
+    client.write_message(u64::MAX, b"max").unwrap();
+    expect_payload(&mut server, WIDE, b"wide", TIMEOUT);
+    expect_payload(&mut server, u64::MAX, b"max", TIMEOUT);
+}
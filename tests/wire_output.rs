@@ -0,0 +1,97 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Read;
+
+// reads exactly the given number of bytes from the raw peer
+fn read_exactly(peer: &mut std::net::TcpStream, length: usize) -> Vec<u8> {
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut bytes = vec![0; length];
+    peer.read_exact(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn header_and_payload_are_written_like_construct_message() {
+    let (mut server, mut peer) = raw_peer();
+    let large: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+    let frames = [(DATA, b"small".to_vec()), (ERROR, vec![]), (DATA, large)];
+    for (command, payload) in &frames {
+        server.write_message(*command, payload).unwrap();
+    }
+    for (command, payload) in &frames {
+        let expected = frame(*command, payload);
+        assert!(read_exactly(&mut peer, expected.len()) == expected);
+    }
+}
+
+/// The test protocol, with a checksum byte appended to each frame (which this test does not parse).
+#[derive(Debug)]
+enum ChecksumProtocol {}
+impl Protocol for ChecksumProtocol {
+    type Commands = u8;
+    type BusyStates = u8;
+    type CommandAsArray = [u8; 1];
+    type LengthAsArray = [u8; 8];
+    type HeaderAsArray = [u8; 9];
+    fn idle() -> u8 {
+        0
+    }
+    fn message_is_answered_via_immediate_route(
+        _command: &u8,
+        _message: &[u8],
+        _busy_state: &u8,
+    ) -> Option<(u8, Vec<u8>)> {
+        None
+    }
+    fn parse_command(command: &[u8; 1]) -> Option<u8> {
+        TestProtocol::parse_command(command)
+    }
+    fn parse_length(length: &[u8; 8]) -> Option<usize> {
+        TestProtocol::parse_length(length)
+    }
+    fn message_slice_to_header_array(input: &[u8]) -> Option<(&[u8; 9], &[u8])> {
+        TestProtocol::message_slice_to_header_array(input)
+    }
+    fn split_header_array(header: &[u8; 9]) -> (&[u8; 1], &[u8; 8]) {
+        TestProtocol::split_header_array(header)
+    }
+    fn command_to_array(command: u8) -> [u8; 1] {
+        TestProtocol::command_to_array(command)
+    }
+    fn get_length_as_array(command: u8, message: &[u8]) -> Option<[u8; 8]> {
+        TestProtocol::get_length_as_array(command, message)
+    }
+    fn construct_header(command: [u8; 1], length: [u8; 8]) -> Vec<u8> {
+        TestProtocol::construct_header(command, length)
+    }
+    fn payload_follows_header() -> bool {
+        false
+    }
+    fn construct_message(command: u8, message: &[u8]) -> Option<Vec<u8>> {
+        let mut frame = TestProtocol::construct_message(command, message)?;
+        let checksum = frame.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        frame.push(checksum);
+        Some(frame)
+    }
+}
+
+#[test]
+fn protocols_controlling_the_final_bytes_are_written_as_constructed() {
+    let listener = TcpIpc::<ChecksumProtocol>::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let peer = std::thread::spawn(move || std::net::TcpStream::connect(address).unwrap());
+    let mut server = listener
+        .accept(TcpIpcConfig {
+            verify_frames: Some(false),
+            ..TcpIpcConfig::default()
+        })
+        .unwrap();
+    let mut peer = peer.join().unwrap();
+
+    let payload = vec![3; 1000];
+    server.write_message(DATA, &payload).unwrap();
+    let expected = ChecksumProtocol::construct_message(DATA, &payload).unwrap();
+    assert_eq!(expected.len(), 9 + 1000 + 1);
+    assert!(read_exactly(&mut peer, expected.len()) == expected);
+}
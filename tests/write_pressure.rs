@@ -0,0 +1,48 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn watermarks_fire_when_the_reader_stalls_and_drains() {
+    let levels = Arc::new(Mutex::new(Vec::new()));
+    let reported = levels.clone();
+    let server_config = TcpIpcConfig {
+        send_buffer_size: Some(1 << 12),
+        write_pressure_watermarks: Some(WritePressureWatermarks {
+            high: 1 << 18,
+            low: 1 << 14,
+            on_write_pressure: Arc::new(move |level| reported.lock().unwrap().push(level)),
+        }),
+        ..config()
+    };
+    let (mut server, mut peer) = raw_peer_with(server_config);
+    assert_eq!(server.write_pressure().queued_frames, 0);
+    assert!(!server.write_pressure().would_block_now);
+
+    // the peer does not read, so frames are queued until the high watermark is reached
+    let payload = vec![1; 1 << 14];
+    let mut frames = 0;
+    while levels.lock().unwrap().is_empty() {
+        assert!(frames < 10_000, "the high watermark was not reached");
+        server.write_message(DATA, &payload).unwrap();
+        frames += 1;
+    }
+    assert_eq!(*levels.lock().unwrap(), vec![WritePressureLevel::High]);
+    let pressure = server.write_pressure();
+    assert!(pressure.would_block_now);
+    assert!(pressure.queued_frames > 0);
+    assert!(pressure.queued_bytes >= 1 << 18);
+
+    // the read thread writes the queued frames, while the peer reads them
+    let mut received = vec![0; frames * frame(DATA, &payload).len()];
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+    peer.read_exact(&mut received).unwrap();
+    await_condition(|| levels.lock().unwrap().len() == 2);
+    assert_eq!(
+        *levels.lock().unwrap(),
+        vec![WritePressureLevel::High, WritePressureLevel::Low]
+    );
+    assert_eq!(server.write_pressure().queued_frames, 0);
+}
@@ -0,0 +1,150 @@
+mod common;
+use common::*;
+use rust_tcp_ipc::*;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+fn retrying(max_duration: Duration) -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        write_retry: Some(RetrySpec {
+            max_duration,
+            backoff: Duration::from_millis(1),
+        }),
+        ..config()
+    }
+}
+
+#[test]
+fn a_message_which_would_block_is_retried_until_written() {
+    let (mut server, mut peer) = raw_peer_with(retrying(TIMEOUT));
+    // far more than the socket buffers take, so the write would block many times
+    let payload: Vec<u8> = (0..1u32 << 22).map(|i| i as u8).collect();
+    let mut expected = frame(DATA, &payload);
+    expected.extend(frame(DATA, b"next"));
+    let reader = std::thread::spawn(move || {
+        // a slow reader, so the writer keeps running into a full socket
+        let mut received = Vec::new();
+        let mut chunk = vec![0; 1 << 16];
+        while received.len() < expected.len() {
+            let read = peer.read(&mut chunk).unwrap();
+            assert!(read > 0, "connection closed after {} bytes", received.len());
+            received.extend_from_slice(&chunk[..read]);
+            std::thread::sleep(Duration::from_micros(200));
+        }
+        // no byte was sent twice
+        assert!(received == expected);
+    });
+    server.write_message(DATA, &payload).unwrap();
+    // the frame was written by the call itself, nothing was left for the read thread
+    assert_eq!(server.write_pressure().queued_frames, 0);
+    server.write_message(DATA, b"next").unwrap();
+    reader.join().unwrap();
+}
+
+#[test]
+fn an_exhausted_retry_budget_fails_and_closes_the_connection() {
+    let budget = Duration::from_millis(50);
+    let (mut server, _peer) = raw_peer_with(retrying(budget));
+    let payload = vec![7; 1 << 24];
+    let start = Instant::now();
+    // the peer never reads, so the frame cannot be written within the budget
+    let result = server.write_message(DATA, &payload);
+    assert!(
+        matches!(result, Err(WriteMessageErrors::MessageSendFailed(_))),
+        "{:?}",
+        result
+    );
+    assert!(start.elapsed() >= budget);
+    // a partial frame was sent, so the connection cannot be used anymore
+    assert!(matches!(
+        server.write_message(DATA, b"after"),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+}
+
+fn tiny_send_buffer() -> TcpIpcConfig<TestProtocol> {
+    TcpIpcConfig {
+        send_buffer_size: Some(1 << 12),
+        ..config()
+    }
+}
+
+// reads the given frames on a thread, slowly, so the writer keeps running into a full socket
+fn slow_reader(
+    mut peer: std::net::TcpStream,
+    expected: Vec<u8>,
+) -> std::thread::JoinHandle<std::net::TcpStream> {
+    std::thread::spawn(move || {
+        peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut received = Vec::new();
+        let mut chunk = vec![0; 1 << 12];
+        while received.len() < expected.len() {
+            let read = peer.read(&mut chunk).unwrap();
+            assert!(read > 0, "connection closed after {} bytes", received.len());
+            received.extend_from_slice(&chunk[..read]);
+            std::thread::sleep(Duration::from_micros(100));
+        }
+        assert!(received == expected, "a frame was corrupted");
+        peer
+    })
+}
+
+#[test]
+fn partially_written_messages_are_completed_without_a_retry_budget() {
+    let (mut server, peer) = raw_peer_with(tiny_send_buffer());
+    let payloads: Vec<Vec<u8>> = (0..200u32)
+        .map(|i| vec![i as u8; 10_000 + i as usize])
+        .collect();
+    let expected = payloads
+        .iter()
+        .flat_map(|payload| frame(DATA, payload))
+        .collect();
+    let reader = slow_reader(peer, expected);
+    // back to back, the unwritten rest of each frame is queued instead of failing
+    for payload in &payloads {
+        server.write_message(DATA, payload).unwrap();
+    }
+    let _peer = reader.join().unwrap();
+    assert!(!server.is_connection_closed());
+}
+
+#[test]
+fn partially_written_immediate_responses_are_completed() {
+    let (server, mut peer) = raw_peer_with(tiny_send_buffer());
+    let payloads: Vec<Vec<u8>> = (0..100u32)
+        .map(|i| vec![i as u8; 10_000 + i as usize])
+        .collect();
+    let expected = payloads
+        .iter()
+        .flat_map(|payload| frame(REPLY, payload))
+        .collect();
+    let queries: Vec<u8> = payloads
+        .iter()
+        .flat_map(|payload| frame(QUERY, payload))
+        .collect();
+    let reader = slow_reader(peer.try_clone().unwrap(), expected);
+    peer.write_all(&queries).unwrap();
+    reader.join().unwrap();
+    assert_eq!(server.stats().immediate_responses_sent, 100);
+    assert!(!server.is_connection_closed());
+}
+
+#[test]
+fn a_write_within_a_deadline_fails_once_it_is_exhausted() {
+    let deadline = Duration::from_millis(50);
+    let (mut server, _peer) = raw_peer_with(tiny_send_buffer());
+    let start = Instant::now();
+    // the peer never reads, so the frame cannot be written in time
+    let result = server.write_message_within(DATA, &vec![7; 1 << 24], deadline);
+    assert!(
+        matches!(result, Err(WriteMessageErrors::MessageSendFailed(_))),
+        "{:?}",
+        result
+    );
+    assert!(start.elapsed() >= deadline);
+    assert!(start.elapsed() < TIMEOUT);
+    assert!(matches!(
+        server.write_message(DATA, b"after"),
+        Err(WriteMessageErrors::ConnectionClosed)
+    ));
+}
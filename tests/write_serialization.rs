@@ -0,0 +1,50 @@
+mod common;
+use common::*;
+
+const FRAMES: usize = 200;
+const QUERIES: usize = 300;
+const FRAME_SIZE: usize = 8_000;
+
+fn query(index: usize) -> Vec<u8> {
+    (0..100 + index).map(|byte| (byte + index) as u8).collect()
+}
+
+// the frames of the main thread & the immediate responses of the read thread are written concurrently to the same stream
+#[test]
+fn messages_and_immediate_responses_do_not_interleave() {
+    let (mut server, mut client) = pair();
+    let writer = std::thread::spawn(move || {
+        for index in 0..FRAMES {
+            server
+                .write_message(DATA, &vec![index as u8; FRAME_SIZE])
+                .unwrap();
+        }
+        server
+    });
+
+    let mut data = 0;
+    let mut replies = 0;
+    let mut receive = |client: &mut rust_tcp_ipc::TcpIpc<TestProtocol>| {
+        while let Some((command, payload)) = client.get_message().expect("corrupted stream") {
+            match command {
+                DATA => {
+                    assert_eq!(payload, vec![data as u8; FRAME_SIZE]);
+                    data += 1;
+                }
+                REPLY => {
+                    assert_eq!(payload, query(replies));
+                    replies += 1;
+                }
+                command => panic!("unexpected command {}", command),
+            }
+        }
+        (data, replies)
+    };
+    for index in 0..QUERIES {
+        client.write_message(QUERY, &query(index)).unwrap();
+        receive(&mut client);
+    }
+    await_condition(|| receive(&mut client) == (FRAMES, QUERIES));
+    let server = writer.join().unwrap();
+    assert_eq!(server.stats().immediate_responses_sent, QUERIES as u64);
+}